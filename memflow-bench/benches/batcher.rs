@@ -31,6 +31,8 @@ impl PhysicalMemory for NullMem {
             real_size: 0,
             readonly: true,
             ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
         }
     }
 }
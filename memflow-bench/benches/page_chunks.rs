@@ -0,0 +1,86 @@
+use criterion::*;
+
+use memflow::architecture::x86::x64;
+use memflow::dummy::DummyMemory as Memory;
+use memflow::prelude::v1::*;
+
+use rand::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng as CurRng;
+
+const MEM_SIZE: usize = size::mb(16);
+
+fn scattered_addrs(count: usize) -> Vec<Address> {
+    let mut rng = CurRng::from_rng(thread_rng()).unwrap();
+    (0..count)
+        .map(|_| Address::from(rng.gen_range(0..(MEM_SIZE - 8) as umem)))
+        .collect()
+}
+
+fn page_chunks_small(bench: &mut Bencher, count: usize) {
+    let addrs = scattered_addrs(count);
+    let mut bufs = vec![[0u8; 8]; count];
+
+    bench.iter(|| {
+        let mut chunks = 0;
+        for (addr, buf) in addrs.iter().zip(bufs.iter_mut()) {
+            chunks += CSliceMut::from(&mut buf[..])
+                .page_chunks(*addr, size::kb(4))
+                .count();
+        }
+        black_box(chunks)
+    });
+}
+
+fn cached_read_small(bench: &mut Bencher, count: usize) {
+    let mut mem = CachedPhysicalMemory::builder(Memory::new(MEM_SIZE))
+        .arch(x64::ARCH)
+        .build()
+        .unwrap();
+
+    let addrs = scattered_addrs(count);
+    let mut bufs = vec![[0u8; 8]; count];
+
+    bench.iter(|| {
+        let iter = addrs.iter().zip(bufs.iter_mut()).map(|(addr, buf)| {
+            (
+                PhysicalAddress::with_page(*addr, PageType::READ_ONLY, size::kb(4) as umem),
+                CSliceMut::from(&mut buf[..]),
+            )
+        });
+        let _ = black_box(MemOps::with(iter, None, None, |data| {
+            mem.phys_read_raw_iter(data)
+        }));
+    });
+}
+
+fn small_reads(c: &mut Criterion) {
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+
+    let mut group = c.benchmark_group("small_scattered_reads");
+    group.plot_config(plot_config);
+
+    for &count in [16, 256, 4096, 65536].iter() {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(
+            BenchmarkId::new("page_chunks", count),
+            &count,
+            |b, &count| page_chunks_small(b, count),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("cached_read", count),
+            &count,
+            |b, &count| cached_read_small(b, count),
+        );
+    }
+}
+
+criterion_group! {
+    name = page_chunks;
+    config = Criterion::default()
+        .warm_up_time(std::time::Duration::from_millis(500))
+        .measurement_time(std::time::Duration::from_millis(5000));
+    targets = small_reads
+}
+
+criterion_main!(page_chunks);
@@ -534,6 +534,290 @@ pub fn byteswap_derive(input: TokenStream) -> TokenStream {
     gen.into()
 }
 
+/// Auto derive the `RemoteStruct` trait for structs with explicitly placed fields.
+///
+/// Instead of modelling a remote structure as a `Pod` type with manual padding, every field that
+/// should be read from the target is annotated with its offset from the structure base.
+/// All annotated fields are collected into a single scatter read.
+///
+/// The following field attributes are supported:
+///
+/// * `#[offset(0x18)]` - offset of the field relative to the structure base. The field type has to implement `Pod`.
+///
+/// * `#[ptr]` - the field is a pointer with the width of the memory view architecture. The field type has to implement `From<Address>`.
+///
+/// * `#[utf16(len_field)]` - the field is a `String` whose offset points to a pointer to UTF-16 data.
+///   `len_field` names another field of the struct containing the length of the data in bytes.
+///   Strings are read in a second scatter read after the main one.
+///
+/// Fields without an `#[offset]` attribute are initialized with `Default::default()`.
+///
+/// For every field with an `#[offset]` attribute an `OFFSET_<FIELD>` constant and a
+/// `read_<field>(mem, base)` function are generated, with the visibility of the field. They
+/// allow reading single fields of large structures without reading all other fields as well.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[derive(RemoteStruct)]
+/// struct ModuleEntry {
+///     #[offset(0x30)]
+///     #[ptr]
+///     base: Address,
+///     #[offset(0x40)]
+///     size: u32,
+///     #[offset(0x58)]
+///     name_len: u16,
+///     #[offset(0x60)]
+///     #[utf16(name_len)]
+///     name: String,
+/// }
+///
+/// // reads 4 bytes instead of the whole entry
+/// let size = ModuleEntry::read_size(&mut mem, entry)?;
+/// assert_eq!(ModuleEntry::OFFSET_SIZE, 0x40);
+/// ```
+#[proc_macro_derive(RemoteStruct, attributes(offset, ptr, utf16))]
+pub fn remote_struct_derive(input: TokenStream) -> TokenStream {
+    let crate_path = crate_path();
+
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match input.data {
+        Data::Struct(data) => match data.fields {
+            Fields::Named(named) => named.named,
+            _ => {
+                return syn::Error::new_spanned(name, "RemoteStruct requires named fields")
+                    .to_compile_error()
+                    .into()
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "RemoteStruct can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let mut gen_decl = quote!();
+    let mut gen_ops = quote!();
+    let mut ops_count = 0usize;
+    let mut gen_post = quote!();
+    let mut gen_str_ops = quote!();
+    let mut gen_str_post = quote!();
+    let mut gen_fields = quote!();
+    let mut gen_accessors = quote!();
+
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let vis = &field.vis;
+
+        let mut offset = None;
+        let mut is_ptr = false;
+        let mut utf16_len = None;
+
+        for attr in field.attrs.iter() {
+            if attr.path.is_ident("offset") {
+                match attr
+                    .parse_args::<syn::LitInt>()
+                    .and_then(|l| l.base10_parse::<u64>())
+                {
+                    Ok(o) => offset = Some(o),
+                    Err(e) => return e.to_compile_error().into(),
+                }
+            } else if attr.path.is_ident("ptr") {
+                is_ptr = true;
+            } else if attr.path.is_ident("utf16") {
+                match attr.parse_args::<syn::Ident>() {
+                    Ok(l) => utf16_len = Some(l),
+                    Err(e) => return e.to_compile_error().into(),
+                }
+            }
+        }
+
+        gen_fields.extend(quote!(#ident,));
+
+        let offset = match offset {
+            Some(o) => o,
+            None => {
+                if is_ptr || utf16_len.is_some() {
+                    return syn::Error::new_spanned(
+                        ident,
+                        "field is missing an #[offset] attribute",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+                gen_decl.extend(quote!(
+                    let #ident: #ty = ::core::default::Default::default();
+                ));
+                continue;
+            }
+        };
+
+        let raw = format_ident!("__raw_{}", ident);
+        let addr = quote!(__base + (#offset as #crate_path::types::umem));
+
+        let field_name = ident.to_string().trim_start_matches("r#").to_string();
+        let offset_const = format_ident!("OFFSET_{}", field_name.to_uppercase());
+        let read_fn = format_ident!("read_{}", field_name);
+        let field_addr = quote!(__base + Self::#offset_const);
+
+        let read_body = if let Some(len_field) = &utf16_len {
+            let read_len = format_ident!("read_{}", len_field.to_string().trim_start_matches("r#"));
+            quote!(
+                let __len = #crate_path::error::PartialResultExt::data_part(
+                    Self::#read_len(__mem, __base)
+                )?;
+                #crate_path::mem::memory_view::remote_struct::read_utf16_field(
+                    __mem,
+                    #field_addr,
+                    __len as usize,
+                )
+            )
+        } else if is_ptr {
+            quote!(
+                #crate_path::error::PartialResultExt::map_data(
+                    #crate_path::mem::memory_view::remote_struct::read_ptr_field(__mem, #field_addr),
+                    |__ptr| -> #ty { __ptr.into() },
+                )
+            )
+        } else {
+            quote!(#crate_path::mem::MemoryView::read::<#ty>(__mem, #field_addr))
+        };
+
+        let offset_doc = format!("Offset of the `{}` field.", field_name);
+        let read_doc = format!(
+            "Reads only the `{}` field of the structure at `base`.",
+            field_name
+        );
+        gen_accessors.extend(quote!(
+            #[doc = #offset_doc]
+            #vis const #offset_const: #crate_path::types::umem = #offset as #crate_path::types::umem;
+
+            #[doc = #read_doc]
+            #vis fn #read_fn<__M: #crate_path::mem::MemoryView>(
+                __mem: &mut __M,
+                __base: #crate_path::types::Address,
+            ) -> #crate_path::error::PartialResult<#ty> {
+                #read_body
+            }
+        ));
+
+        if let Some(len_field) = utf16_len {
+            let buf = format_ident!("__buf_{}", ident);
+            gen_decl.extend(quote!(
+                let mut #raw = [0u8; 8];
+            ));
+            gen_ops.extend(quote!(
+                #crate_path::cglue::CTup2(#addr, (&mut #raw[..__ptr_size]).into()),
+            ));
+            ops_count += 1;
+            gen_post.extend(quote!(
+                let #raw: #crate_path::types::Address =
+                    #crate_path::mem::memory_view::remote_struct::ptr_from_bytes(&#raw, &__meta);
+                let mut #buf = #crate_path::mem::memory_view::remote_struct::utf16_buf(
+                    if #raw.is_null() { 0 } else { #len_field as usize },
+                );
+            ));
+            gen_str_ops.extend(quote!(
+                if !#buf.is_empty() {
+                    __ops.push(#crate_path::cglue::CTup2(#raw, (&mut #buf[..]).into()));
+                }
+            ));
+            gen_str_post.extend(quote!(
+                let #ident: #ty = #crate_path::mem::memory_view::remote_struct::utf16_string(
+                    &#buf,
+                    __meta.little_endian,
+                );
+            ));
+        } else if is_ptr {
+            gen_decl.extend(quote!(
+                let mut #raw = [0u8; 8];
+            ));
+            gen_ops.extend(quote!(
+                #crate_path::cglue::CTup2(#addr, (&mut #raw[..__ptr_size]).into()),
+            ));
+            ops_count += 1;
+            gen_post.extend(quote!(
+                let #ident: #ty = #crate_path::mem::memory_view::remote_struct::ptr_from_bytes(
+                    &#raw,
+                    &__meta,
+                )
+                .into();
+            ));
+        } else {
+            gen_decl.extend(quote!(
+                let mut #ident: #ty = #crate_path::mem::memory_view::remote_struct::pod_zeroed();
+            ));
+            gen_ops.extend(quote!(
+                #crate_path::cglue::CTup2(
+                    #addr,
+                    #crate_path::dataview::Pod::as_bytes_mut(&mut #ident).into()
+                ),
+            ));
+            ops_count += 1;
+        }
+    }
+
+    let gen = quote!(
+        #[allow(dead_code, clippy::identity_op)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #gen_accessors
+        }
+
+        impl #impl_generics #crate_path::mem::RemoteStruct for #name #ty_generics #where_clause {
+            #[allow(unused_mut, unused_variables, clippy::identity_op)]
+            fn read_remote<__M: #crate_path::mem::MemoryView>(
+                __mem: &mut __M,
+                __base: #crate_path::types::Address,
+            ) -> #crate_path::error::PartialResult<Self> {
+                let __meta = #crate_path::mem::MemoryView::metadata(__mem);
+                let __ptr_size = #crate_path::mem::memory_view::remote_struct::ptr_size(&__meta);
+                let mut __partial = false;
+
+                #gen_decl
+
+                {
+                    let mut __ops: [#crate_path::mem::ReadData; #ops_count] = [#gen_ops];
+                    #crate_path::mem::memory_view::remote_struct::merge_partial(
+                        #crate_path::mem::MemoryView::read_raw_list(__mem, &mut __ops),
+                        &mut __partial,
+                    )?;
+                }
+
+                #gen_post
+
+                {
+                    let mut __ops = #crate_path::mem::memory_view::remote_struct::read_list();
+                    #gen_str_ops
+                    if !__ops.is_empty() {
+                        #crate_path::mem::memory_view::remote_struct::merge_partial(
+                            #crate_path::mem::MemoryView::read_raw_list(__mem, &mut __ops),
+                            &mut __partial,
+                        )?;
+                    }
+                }
+
+                #gen_str_post
+
+                let __out = Self { #gen_fields };
+
+                if __partial {
+                    Err(#crate_path::error::PartialError::PartialVirtualRead(__out))
+                } else {
+                    Ok(__out)
+                }
+            }
+        }
+    );
+
+    gen.into()
+}
+
 fn crate_path() -> proc_macro2::TokenStream {
     let (col, ident) = crate_path_ident();
     quote!(#col #ident)
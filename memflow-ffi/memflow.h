@@ -628,6 +628,18 @@ typedef struct PhysicalMemoryMetadata {
     umem real_size;
     bool readonly;
     uint32_t ideal_batch_size;
+    /**
+     * Maximum number of elements the connector accepts in a single batch.
+     *
+     * `u32::MAX` means there is no limit.
+     */
+    uint32_t max_batch_size;
+    /**
+     * Maximum number of bytes the connector accepts in a single batch.
+     *
+     * `umem::MAX` means there is no limit. Single elements are never split to honor this limit.
+     */
+    umem max_batch_bytes;
 } PhysicalMemoryMetadata;
 
 typedef struct PhysicalMemoryMapping {
@@ -663,6 +675,46 @@ typedef struct CSliceRef_PhysicalMemoryMapping {
     uintptr_t len;
 } CSliceRef_PhysicalMemoryMapping;
 
+/**
+ * FFI-safe 2 element tuple.
+ */
+typedef struct CTup2_PhysicalAddress__umem {
+    struct PhysicalAddress _0;
+    umem _1;
+} CTup2_PhysicalAddress__umem;
+
+/**
+ * Physical address range, used for prefetch hints.
+ */
+typedef struct CTup2_PhysicalAddress__umem PhysicalRange;
+
+/**
+ * Wrapper around const slices.
+ *
+ * This is meant as a safe type to pass across the FFI boundary with similar semantics as regular
+ * slice. However, not all functionality is present, use the slice conversion functions.
+ *
+ * # Examples
+ *
+ * Simple conversion:
+ *
+ * ```
+ * use cglue::slice::CSliceRef;
+ *
+ * let arr = [0, 5, 3, 2];
+ *
+ * let cslice = CSliceRef::from(&arr[..]);
+ *
+ * let slice = cslice.as_slice();
+ *
+ * assert_eq!(&arr, slice);
+ * ```
+ */
+typedef struct CSliceRef_PhysicalRange {
+    const PhysicalRange *data;
+    uintptr_t len;
+} CSliceRef_PhysicalRange;
+
 /**
  * FFI-safe 3 element tuple.
  */
@@ -1176,6 +1228,26 @@ typedef enum ArchitectureIdent_Tag {
      * Valid page sizes are 4kb, 16kb, 64kb. Only 4kb is supported at the moment
      */
     ArchitectureIdent_AArch64,
+    /**
+     * RISC-V 64-bit architecture with the specified virtual address width
+     *
+     * Valid widths are 39 (Sv39) and 48 (Sv48).
+     */
+    ArchitectureIdent_RiscV64,
+    /**
+     * PowerPC 64-bit architecture with the specified page size and byte order
+     *
+     * First argument - `page_size` is either 4kb or 64kb.
+     * Second argument - `little_endian` controls whether it's the ppc64le variant.
+     */
+    ArchitectureIdent_Ppc64,
+    /**
+     * MIPS with specified bitness and byte order
+     *
+     * First argument - `bitness` controls whether it's 32, or 64 bit variant.
+     * Second argument - `little_endian` controls whether it's the mipsel variant.
+     */
+    ArchitectureIdent_Mips,
 } ArchitectureIdent_Tag;
 
 typedef struct ArchitectureIdent_X86_Body {
@@ -1183,6 +1255,16 @@ typedef struct ArchitectureIdent_X86_Body {
     bool _1;
 } ArchitectureIdent_X86_Body;
 
+typedef struct ArchitectureIdent_Ppc64_Body {
+    uintptr_t _0;
+    bool _1;
+} ArchitectureIdent_Ppc64_Body;
+
+typedef struct ArchitectureIdent_Mips_Body {
+    uint8_t _0;
+    bool _1;
+} ArchitectureIdent_Mips_Body;
+
 typedef struct ArchitectureIdent {
     ArchitectureIdent_Tag tag;
     union {
@@ -1193,6 +1275,11 @@ typedef struct ArchitectureIdent {
         struct {
             uintptr_t a_arch64;
         };
+        struct {
+            uint8_t risc_v64;
+        };
+        ArchitectureIdent_Ppc64_Body ppc64;
+        ArchitectureIdent_Mips_Body mips;
     };
 } ArchitectureIdent;
 
@@ -1774,6 +1861,7 @@ typedef struct PhysicalMemoryVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_voi
     int32_t (*phys_write_raw_iter)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, PhysicalWriteMemOps data);
     struct PhysicalMemoryMetadata (*metadata)(const struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     void (*set_mem_map)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_PhysicalMemoryMapping _mem_map);
+    void (*prefetch_hint)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_PhysicalRange _ranges);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*into_phys_view)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void cont);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*phys_view)(struct OsInstanceContainer_CBox_c_void_____CArc_c_void *cont);
 } PhysicalMemoryVtbl_OsInstanceContainer_CBox_c_void_____CArc_c_void;
@@ -2083,6 +2171,7 @@ typedef struct PhysicalMemoryVtbl_ConnectorInstanceContainer_CBox_c_void_____CAr
     int32_t (*phys_write_raw_iter)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, PhysicalWriteMemOps data);
     struct PhysicalMemoryMetadata (*metadata)(const struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont);
     void (*set_mem_map)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_PhysicalMemoryMapping _mem_map);
+    void (*prefetch_hint)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont, struct CSliceRef_PhysicalRange _ranges);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*into_phys_view)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void cont);
     MemoryViewBase_CBox_c_void_____CArc_c_void (*phys_view)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont);
 } PhysicalMemoryVtbl_ConnectorInstanceContainer_CBox_c_void_____CArc_c_void;
@@ -2230,6 +2319,22 @@ int32_t inventory_create_connector(struct Inventory *inv,
                                    const char *args,
                                    MuConnectorInstanceArcBox *out);
 
+/**
+ * Create a connector with given arguments on the heap
+ *
+ * This behaves like `inventory_create_connector`, but allocates the instance, so bindings
+ * that do not know the size of `ConnectorInstance` (e.g. Python's ctypes) can use it.
+ *
+ * This instance needs to be freed using `connector_free`.
+ *
+ * # Safety
+ *
+ * Both `name`, and `args` must be valid null terminated strings. `args` may be null.
+ */
+ConnectorInstanceArcBox *inventory_create_connector_boxed(struct Inventory *inv,
+                                                          const char *name,
+                                                          const char *args);
+
 /**
  * Create a OS instance with given arguments
  *
@@ -2299,6 +2404,16 @@ void connector_clone(const ConnectorInstanceArcBox *conn, MuConnectorInstanceArc
  */
 void connector_drop(ConnectorInstanceArcBox *conn);
 
+/**
+ * Free a connector instance created by `inventory_create_connector_boxed`
+ *
+ * # Safety
+ *
+ * `conn` has to point to a valid [`ConnectorInstance`] created by
+ * `inventory_create_connector_boxed`.
+ */
+void connector_free(ConnectorInstanceArcBox *conn);
+
 /**
  * Free a connector inventory
  *
@@ -2309,6 +2424,41 @@ void connector_drop(ConnectorInstanceArcBox *conn);
  */
 void inventory_free(struct Inventory *inv);
 
+/**
+ * Read `len` bytes of physical memory at `addr` into `out`
+ *
+ * Returns 0 on success, and a negative error code if any part of the range can not be read.
+ *
+ * # Safety
+ *
+ * `out` has to point to a writeable buffer of at least `len` bytes.
+ */
+int32_t connector_phys_read(ConnectorInstanceArcBox *conn, uint64_t addr, void *out, uintptr_t len);
+
+/**
+ * Write `len` bytes from `data` to physical memory at `addr`
+ *
+ * Returns 0 on success, and a negative error code otherwise.
+ *
+ * # Safety
+ *
+ * `data` has to point to a readable buffer of at least `len` bytes.
+ */
+int32_t connector_phys_write(ConnectorInstanceArcBox *conn,
+                             uint64_t addr,
+                             const void *data,
+                             uintptr_t len);
+
+/**
+ * Returns the highest physical address of the connector
+ */
+uint64_t connector_max_address(const ConnectorInstanceArcBox *conn);
+
+/**
+ * Returns 1 if the connector does not allow writes, 0 otherwise
+ */
+int32_t connector_readonly(const ConnectorInstanceArcBox *conn);
+
 uint8_t arch_bits(const struct ArchitectureObj *arch);
 
 Endianess arch_endianess(const struct ArchitectureObj *arch);
@@ -2696,6 +2846,11 @@ static inline void mf_osinstance_set_mem_map(void *self, struct CSliceRef_Physic
 
 }
 
+static inline void mf_osinstance_prefetch_hint(void *self, struct CSliceRef_PhysicalRange _ranges)  {
+(((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->prefetch_hint(&((struct OsInstance_CBox_c_void_____CArc_c_void *)self)->container, _ranges);
+
+}
+
 static inline MemoryViewBase_CBox_c_void_____CArc_c_void mf_osinstance_into_phys_view(struct OsInstance_CBox_c_void_____CArc_c_void self)  {
     CArc_c_void ___ctx = ctx_arc_clone(&self.container.context);
     MemoryViewBase_CBox_c_void_____CArc_c_void __ret = (self.vtbl_physicalmemory)->into_phys_view(self.container);
@@ -3096,6 +3251,11 @@ static inline void mf_connectorinstance_set_mem_map(void *self, struct CSliceRef
 
 }
 
+static inline void mf_connectorinstance_prefetch_hint(void *self, struct CSliceRef_PhysicalRange _ranges)  {
+(((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->vtbl_physicalmemory)->prefetch_hint(&((struct ConnectorInstance_CBox_c_void_____CArc_c_void *)self)->container, _ranges);
+
+}
+
 static inline MemoryViewBase_CBox_c_void_____CArc_c_void mf_connectorinstance_into_phys_view(struct ConnectorInstance_CBox_c_void_____CArc_c_void self)  {
     CArc_c_void ___ctx = ctx_arc_clone(&self.container.context);
     MemoryViewBase_CBox_c_void_____CArc_c_void __ret = (self.vtbl_physicalmemory)->into_phys_view(self.container);
@@ -807,6 +807,18 @@ struct PhysicalMemoryMetadata {
     umem real_size;
     bool readonly;
     uint32_t ideal_batch_size;
+    /**
+     * Maximum number of elements the connector accepts in a single batch.
+     *
+     * `u32::MAX` means there is no limit.
+     */
+    uint32_t max_batch_size;
+    /**
+     * Maximum number of bytes the connector accepts in a single batch.
+     *
+     * `umem::MAX` means there is no limit. Single elements are never split to honor this limit.
+     */
+    umem max_batch_bytes;
 };
 
 struct PhysicalMemoryMapping {
@@ -815,6 +827,11 @@ struct PhysicalMemoryMapping {
     Address real_base;
 };
 
+/**
+ * Physical address range, used for prefetch hints.
+ */
+using PhysicalRange = CTup2<PhysicalAddress, umem>;
+
 /**
  * Simple CGlue trait object container.
  *
@@ -999,6 +1016,7 @@ struct PhysicalMemoryVtbl {
     int32_t (*phys_write_raw_iter)(CGlueC *cont, PhysicalWriteMemOps data);
     PhysicalMemoryMetadata (*metadata)(const CGlueC *cont);
     void (*set_mem_map)(CGlueC *cont, CSliceRef<PhysicalMemoryMapping> _mem_map);
+    void (*prefetch_hint)(CGlueC *cont, CSliceRef<PhysicalRange> _ranges);
     MemoryViewBase<CBox<void>, Context> (*into_phys_view)(CGlueC cont);
     MemoryViewBase<CBox<void>, Context> (*phys_view)(CGlueC *cont);
 };
@@ -1011,6 +1029,7 @@ constexpr PhysicalMemoryVtblImpl() :
         &Impl::phys_write_raw_iter,
         &Impl::metadata,
         &Impl::set_mem_map,
+        &Impl::prefetch_hint,
         &Impl::into_phys_view,
         &Impl::phys_view
     } {}
@@ -1215,6 +1234,11 @@ struct ConnectorInstance {
 
     }
 
+    inline void prefetch_hint(CSliceRef<PhysicalRange> _ranges) noexcept {
+    (this->vtbl_physicalmemory)->prefetch_hint(&this->container, _ranges);
+
+    }
+
     inline MemoryViewBase<CBox<void>, Context> into_phys_view() && noexcept {
         auto ___ctx = StoreAll()[this->container.clone_context(), StoreAll()];
         MemoryViewBase<CBox<void>, Context> __ret = (this->vtbl_physicalmemory)->into_phys_view(this->container);
@@ -1360,6 +1384,26 @@ struct ArchitectureIdent {
          * Valid page sizes are 4kb, 16kb, 64kb. Only 4kb is supported at the moment
          */
         ArchitectureIdent_AArch64,
+        /**
+         * RISC-V 64-bit architecture with the specified virtual address width
+         *
+         * Valid widths are 39 (Sv39) and 48 (Sv48).
+         */
+        ArchitectureIdent_RiscV64,
+        /**
+         * PowerPC 64-bit architecture with the specified page size and byte order
+         *
+         * First argument - `page_size` is either 4kb or 64kb.
+         * Second argument - `little_endian` controls whether it's the ppc64le variant.
+         */
+        ArchitectureIdent_Ppc64,
+        /**
+         * MIPS with specified bitness and byte order
+         *
+         * First argument - `bitness` controls whether it's 32, or 64 bit variant.
+         * Second argument - `little_endian` controls whether it's the mipsel variant.
+         */
+        ArchitectureIdent_Mips,
     };
 
     struct ArchitectureIdent_Unknown_Body {
@@ -1375,11 +1419,28 @@ struct ArchitectureIdent {
         uintptr_t _0;
     };
 
+    struct ArchitectureIdent_RiscV64_Body {
+        uint8_t _0;
+    };
+
+    struct ArchitectureIdent_Ppc64_Body {
+        uintptr_t _0;
+        bool _1;
+    };
+
+    struct ArchitectureIdent_Mips_Body {
+        uint8_t _0;
+        bool _1;
+    };
+
     Tag tag;
     union {
         ArchitectureIdent_Unknown_Body unknown;
         ArchitectureIdent_X86_Body x86;
         ArchitectureIdent_AArch64_Body a_arch64;
+        ArchitectureIdent_RiscV64_Body risc_v64;
+        ArchitectureIdent_Ppc64_Body ppc64;
+        ArchitectureIdent_Mips_Body mips;
     };
 };
 
@@ -2686,6 +2747,11 @@ struct OsInstance {
 
     }
 
+    inline void prefetch_hint(CSliceRef<PhysicalRange> _ranges) noexcept {
+    (this->vtbl_physicalmemory)->prefetch_hint(&this->container, _ranges);
+
+    }
+
     inline MemoryViewBase<CBox<void>, Context> into_phys_view() && noexcept {
         auto ___ctx = StoreAll()[this->container.clone_context(), StoreAll()];
         MemoryViewBase<CBox<void>, Context> __ret = (this->vtbl_physicalmemory)->into_phys_view(this->container);
@@ -2897,6 +2963,22 @@ int32_t inventory_create_connector(Inventory *inv,
                                    const char *args,
                                    MuConnectorInstanceArcBox *out);
 
+/**
+ * Create a connector with given arguments on the heap
+ *
+ * This behaves like `inventory_create_connector`, but allocates the instance, so bindings
+ * that do not know the size of `ConnectorInstance` (e.g. Python's ctypes) can use it.
+ *
+ * This instance needs to be freed using `connector_free`.
+ *
+ * # Safety
+ *
+ * Both `name`, and `args` must be valid null terminated strings. `args` may be null.
+ */
+ConnectorInstanceArcBox *inventory_create_connector_boxed(Inventory *inv,
+                                                          const char *name,
+                                                          const char *args);
+
 /**
  * Create a OS instance with given arguments
  *
@@ -2966,6 +3048,16 @@ void connector_clone(const ConnectorInstanceArcBox *conn, MuConnectorInstanceArc
  */
 void connector_drop(ConnectorInstanceArcBox *conn);
 
+/**
+ * Free a connector instance created by `inventory_create_connector_boxed`
+ *
+ * # Safety
+ *
+ * `conn` has to point to a valid [`ConnectorInstance`] created by
+ * `inventory_create_connector_boxed`.
+ */
+void connector_free(ConnectorInstanceArcBox *conn);
+
 /**
  * Free a connector inventory
  *
@@ -2976,6 +3068,41 @@ void connector_drop(ConnectorInstanceArcBox *conn);
  */
 void inventory_free(Inventory *inv);
 
+/**
+ * Read `len` bytes of physical memory at `addr` into `out`
+ *
+ * Returns 0 on success, and a negative error code if any part of the range can not be read.
+ *
+ * # Safety
+ *
+ * `out` has to point to a writeable buffer of at least `len` bytes.
+ */
+int32_t connector_phys_read(ConnectorInstanceArcBox *conn, uint64_t addr, void *out, uintptr_t len);
+
+/**
+ * Write `len` bytes from `data` to physical memory at `addr`
+ *
+ * Returns 0 on success, and a negative error code otherwise.
+ *
+ * # Safety
+ *
+ * `data` has to point to a readable buffer of at least `len` bytes.
+ */
+int32_t connector_phys_write(ConnectorInstanceArcBox *conn,
+                             uint64_t addr,
+                             const void *data,
+                             uintptr_t len);
+
+/**
+ * Returns the highest physical address of the connector
+ */
+uint64_t connector_max_address(const ConnectorInstanceArcBox *conn);
+
+/**
+ * Returns 1 if the connector does not allow writes, 0 otherwise
+ */
+int32_t connector_readonly(const ConnectorInstanceArcBox *conn);
+
 uint8_t arch_bits(const ArchitectureObj *arch);
 
 Endianess arch_endianess(const ArchitectureObj *arch);
@@ -3115,6 +3242,11 @@ struct CGlueTraitObj<T, PhysicalMemoryVtbl<CGlueObjContainer<T, C, R>>, C, R> {
 
     }
 
+    inline void prefetch_hint(CSliceRef<PhysicalRange> _ranges) noexcept {
+    (this->vtbl)->prefetch_hint(&this->container, _ranges);
+
+    }
+
     inline MemoryViewBase<CBox<void>, Context> into_phys_view() && noexcept {
         auto ___ctx = StoreAll()[this->container.clone_context(), StoreAll()];
         MemoryViewBase<CBox<void>, Context> __ret = (this->vtbl)->into_phys_view(this->container);
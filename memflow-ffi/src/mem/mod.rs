@@ -2,3 +2,5 @@
 pub use memflow::mem::phys_mem::*;
 #[allow(unused)]
 pub use memflow::mem::virt_mem::*;
+
+pub mod raw;
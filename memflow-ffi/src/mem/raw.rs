@@ -0,0 +1,69 @@
+//! Physical memory accessors taking plain integers and buffers.
+//!
+//! The generated headers access connectors through inline wrappers around the vtables, which
+//! bindings without a C compiler (e.g. Python's ctypes, used by the Volatility 3 layer in
+//! `volatility/`) can not call. These functions provide the same functionality with a flat
+//! signature.
+
+use std::os::raw::c_void;
+
+use memflow::cglue::result::IntResult;
+use memflow::mem::PhysicalMemory;
+use memflow::plugins::connector::ConnectorInstanceArcBox;
+use memflow::types::Address;
+
+use crate::util::*;
+
+/// Read `len` bytes of physical memory at `addr` into `out`
+///
+/// Returns 0 on success, and a negative error code if any part of the range can not be read.
+///
+/// # Safety
+///
+/// `out` has to point to a writeable buffer of at least `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn connector_phys_read(
+    conn: &mut ConnectorInstanceArcBox<'static>,
+    addr: u64,
+    out: *mut c_void,
+    len: usize,
+) -> i32 {
+    let out = std::slice::from_raw_parts_mut(out as *mut u8, len);
+
+    conn.phys_read_into(Address::from(addr).into(), out)
+        .map_err(inspect_err)
+        .into_int_result()
+}
+
+/// Write `len` bytes from `data` to physical memory at `addr`
+///
+/// Returns 0 on success, and a negative error code otherwise.
+///
+/// # Safety
+///
+/// `data` has to point to a readable buffer of at least `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn connector_phys_write(
+    conn: &mut ConnectorInstanceArcBox<'static>,
+    addr: u64,
+    data: *const c_void,
+    len: usize,
+) -> i32 {
+    let data = std::slice::from_raw_parts(data as *const u8, len);
+
+    conn.phys_write(Address::from(addr).into(), data)
+        .map_err(inspect_err)
+        .into_int_result()
+}
+
+/// Returns the highest physical address of the connector
+#[no_mangle]
+pub extern "C" fn connector_max_address(conn: &ConnectorInstanceArcBox<'static>) -> u64 {
+    u64::from(conn.metadata().max_address.to_umem())
+}
+
+/// Returns 1 if the connector does not allow writes, 0 otherwise
+#[no_mangle]
+pub extern "C" fn connector_readonly(conn: &ConnectorInstanceArcBox<'static>) -> i32 {
+    conn.metadata().readonly as i32
+}
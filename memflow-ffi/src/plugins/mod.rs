@@ -98,6 +98,31 @@ pub unsafe extern "C" fn inventory_create_connector(
     }
 }
 
+/// Create a connector with given arguments on the heap
+///
+/// This behaves like `inventory_create_connector`, but allocates the instance, so bindings
+/// that do not know the size of `ConnectorInstance` (e.g. Python's ctypes) can use it.
+///
+/// This instance needs to be freed using `connector_free`.
+///
+/// # Safety
+///
+/// Both `name`, and `args` must be valid null terminated strings. `args` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn inventory_create_connector_boxed(
+    inv: &mut Inventory,
+    name: *const c_char,
+    args: *const c_char,
+) -> Option<&'static mut ConnectorInstanceArcBox<'static>> {
+    let mut out = MuConnectorInstanceArcBox::uninit();
+
+    if inventory_create_connector(inv, name, args, &mut out) == 0 {
+        Some(to_heap(out.assume_init()))
+    } else {
+        None
+    }
+}
+
 /// Create a OS instance with given arguments
 ///
 /// This creates an instance of `KernelInstance`.
@@ -202,6 +227,18 @@ pub unsafe extern "C" fn connector_drop(conn: &mut ConnectorInstanceArcBox<'stat
     std::ptr::drop_in_place(conn)
 }
 
+/// Free a connector instance created by `inventory_create_connector_boxed`
+///
+/// # Safety
+///
+/// `conn` has to point to a valid [`ConnectorInstance`] created by
+/// `inventory_create_connector_boxed`.
+#[no_mangle]
+pub unsafe extern "C" fn connector_free(conn: &'static mut ConnectorInstanceArcBox<'static>) {
+    trace!("connector_free: {:?}", conn as *mut _);
+    let _ = Box::from_raw(conn);
+}
+
 /// Free a connector inventory
 ///
 /// # Safety
@@ -0,0 +1,113 @@
+"""Volatility 3 data layer backed by a memflow connector.
+
+Copy this file into a directory passed to volatility with `-p` (or into
+`volatility3/framework/layers/`) and reference `memflow_layer.MemflowLayer` as memory layer,
+for example in a config file generated with memflow's `VolatilityConfig`.
+
+The layer location is the connector name, optionally followed by its arguments:
+`qemu` or `kvm:1234`. The memflow-ffi library is loaded from the `MEMFLOW_FFI_LIB`
+environment variable, or `libmemflow_ffi.so` from the library search path.
+"""
+
+import ctypes
+import os
+from typing import List, Optional
+
+from volatility3.framework import exceptions, interfaces
+from volatility3.framework.configuration import requirements
+
+
+def _load_library() -> ctypes.CDLL:
+    lib = ctypes.CDLL(os.environ.get("MEMFLOW_FFI_LIB", "libmemflow_ffi.so"))
+
+    lib.inventory_scan.restype = ctypes.c_void_p
+    lib.inventory_scan.argtypes = []
+    lib.inventory_free.restype = None
+    lib.inventory_free.argtypes = [ctypes.c_void_p]
+
+    lib.inventory_create_connector_boxed.restype = ctypes.c_void_p
+    lib.inventory_create_connector_boxed.argtypes = [ctypes.c_void_p, ctypes.c_char_p, ctypes.c_char_p]
+    lib.connector_free.restype = None
+    lib.connector_free.argtypes = [ctypes.c_void_p]
+
+    lib.connector_phys_read.restype = ctypes.c_int32
+    lib.connector_phys_read.argtypes = [ctypes.c_void_p, ctypes.c_uint64, ctypes.c_void_p, ctypes.c_size_t]
+    lib.connector_phys_write.restype = ctypes.c_int32
+    lib.connector_phys_write.argtypes = [ctypes.c_void_p, ctypes.c_uint64, ctypes.c_void_p, ctypes.c_size_t]
+    lib.connector_max_address.restype = ctypes.c_uint64
+    lib.connector_max_address.argtypes = [ctypes.c_void_p]
+    lib.connector_readonly.restype = ctypes.c_int32
+    lib.connector_readonly.argtypes = [ctypes.c_void_p]
+
+    return lib
+
+
+class MemflowLayer(interfaces.layers.DataLayerInterface):
+    """Physical memory layer reading through a memflow connector."""
+
+    def __init__(self, context: interfaces.context.ContextInterface, config_path: str, name: str,
+                 metadata: Optional[dict] = None) -> None:
+        super().__init__(context, config_path, name, metadata)
+
+        location = self.config["location"]
+        connector, _, args = location.partition(":")
+
+        self._lib = _load_library()
+        self._inventory = self._lib.inventory_scan()
+        self._conn = self._lib.inventory_create_connector_boxed(self._inventory, connector.encode(),
+                                                                args.encode() if args else None)
+        if not self._conn:
+            self._lib.inventory_free(self._inventory)
+            raise exceptions.LayerException(name, "unable to create memflow connector {}".format(location))
+
+        self._maximum_address = self._lib.connector_max_address(self._conn)
+        self._readonly = bool(self._lib.connector_readonly(self._conn))
+
+    def __del__(self) -> None:
+        if getattr(self, "_conn", None):
+            self._lib.connector_free(self._conn)
+            self._conn = None
+        if getattr(self, "_inventory", None):
+            self._lib.inventory_free(self._inventory)
+            self._inventory = None
+
+    @property
+    def maximum_address(self) -> int:
+        return self._maximum_address
+
+    @property
+    def minimum_address(self) -> int:
+        return 0
+
+    def is_valid(self, offset: int, length: int = 1) -> bool:
+        return self.minimum_address <= offset and offset + length - 1 <= self.maximum_address
+
+    def read(self, offset: int, length: int, pad: bool = False) -> bytes:
+        if not self.is_valid(offset, length):
+            if not pad:
+                raise exceptions.InvalidAddressException(self.name, offset, "offset outside of the connector")
+            return b"\x00" * length
+
+        buf = ctypes.create_string_buffer(length)
+        if self._lib.connector_phys_read(self._conn, offset, buf, length) != 0 and not pad:
+            raise exceptions.InvalidAddressException(self.name, offset, "unable to read memory")
+
+        return buf.raw
+
+    def write(self, offset: int, data: bytes) -> None:
+        if self._readonly:
+            raise exceptions.LayerException(self.name, "connector is read only")
+        if not self.is_valid(offset, len(data)):
+            raise exceptions.InvalidAddressException(self.name, offset, "offset outside of the connector")
+
+        buf = ctypes.create_string_buffer(data, len(data))
+        if self._lib.connector_phys_write(self._conn, offset, buf, len(data)) != 0:
+            raise exceptions.InvalidAddressException(self.name, offset, "unable to write memory")
+
+    @classmethod
+    def get_requirements(cls) -> List[interfaces.configuration.RequirementInterface]:
+        return [
+            requirements.StringRequirement(name="location",
+                                           optional=False,
+                                           description="memflow connector name and arguments"),
+        ]
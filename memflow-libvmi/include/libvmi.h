@@ -0,0 +1,69 @@
+#ifndef MEMFLOW_LIBVMI_H
+#define MEMFLOW_LIBVMI_H
+
+/* Subset of the LibVMI API implemented by memflow-libvmi. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef uint32_t status_t;
+typedef uint64_t addr_t;
+typedef int32_t vmi_pid_t;
+typedef uint32_t vmi_mode_t;
+typedef uint32_t vmi_init_error_t;
+
+typedef struct VmiInstance *vmi_instance_t;
+
+#define VMI_SUCCESS 0
+#define VMI_FAILURE 1
+
+#define VMI_INIT_ERROR_NONE 0
+#define VMI_INIT_ERROR_DRIVER 2
+#define VMI_INIT_ERROR_VM_NOT_FOUND 3
+
+/* `domain` is a memflow chain: "connector[:args]/os[:args]" */
+status_t vmi_init(vmi_instance_t *vmi, vmi_mode_t mode, const void *domain, uint64_t init_flags,
+                  void *init_data, vmi_init_error_t *error);
+status_t vmi_init_complete(vmi_instance_t *vmi, const void *domain, uint64_t init_flags,
+                           void *init_data, uint32_t config_mode, void *config,
+                           vmi_init_error_t *error);
+status_t vmi_destroy(vmi_instance_t vmi);
+
+status_t vmi_read_pa(vmi_instance_t vmi, addr_t paddr, size_t count, void *buf, size_t *bytes_read);
+status_t vmi_write_pa(vmi_instance_t vmi, addr_t paddr, size_t count, const void *buf,
+                      size_t *bytes_written);
+status_t vmi_read_va(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, size_t count, void *buf,
+                     size_t *bytes_read);
+status_t vmi_write_va(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, size_t count,
+                      const void *buf, size_t *bytes_written);
+
+status_t vmi_read_8_pa(vmi_instance_t vmi, addr_t paddr, uint8_t *value);
+status_t vmi_read_16_pa(vmi_instance_t vmi, addr_t paddr, uint16_t *value);
+status_t vmi_read_32_pa(vmi_instance_t vmi, addr_t paddr, uint32_t *value);
+status_t vmi_read_64_pa(vmi_instance_t vmi, addr_t paddr, uint64_t *value);
+status_t vmi_read_8_va(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, uint8_t *value);
+status_t vmi_read_16_va(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, uint16_t *value);
+status_t vmi_read_32_va(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, uint32_t *value);
+status_t vmi_read_64_va(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, uint64_t *value);
+status_t vmi_read_addr_va(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, addr_t *value);
+
+status_t vmi_translate_uv2p(vmi_instance_t vmi, addr_t vaddr, vmi_pid_t pid, addr_t *paddr);
+status_t vmi_translate_kv2p(vmi_instance_t vmi, addr_t vaddr, addr_t *paddr);
+
+uint64_t vmi_get_memsize(vmi_instance_t vmi);
+addr_t vmi_get_max_physical_address(vmi_instance_t vmi);
+uint8_t vmi_get_address_width(vmi_instance_t vmi);
+unsigned int vmi_get_num_vcpus(vmi_instance_t vmi);
+
+status_t vmi_pause_vm(vmi_instance_t vmi);
+status_t vmi_resume_vm(vmi_instance_t vmi);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
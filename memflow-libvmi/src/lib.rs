@@ -0,0 +1,502 @@
+/*!
+LibVMI compatible C API on top of memflow.
+
+This crate builds `libvmi.so` (and a static library) exposing the commonly used subset of the
+LibVMI API, backed by memflow connectors and OS plugins. Existing VMI tooling can be linked
+against it, or have it preloaded, to run on top of any memflow connector without
+modification.
+
+The domain passed to `vmi_init` is a memflow chain of the form `connector[:args]/os[:args]`,
+for example `qemu:win10/win32`. Plugins are looked up through the default inventory paths.
+
+Differences to LibVMI:
+
+* pid `0` refers to the address space of the OS layer, all other pids open the process through
+  the OS plugin,
+* kernel virtual to physical translation is not available, because OS layers do not expose
+  their translation,
+* events, pausing and the configuration interface are no-ops.
+*/
+
+#![allow(non_camel_case_types)]
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+
+use log::error;
+
+use memflow::cglue::*;
+use memflow::error::PartialResultExt;
+use memflow::mem::{MemoryView, PhysicalMemory, VirtualTranslate};
+use memflow::os::{OsInner, Pid};
+use memflow::plugins::{Inventory, OsChain, OsInstanceArcBox};
+use memflow::types::Address;
+
+pub type status_t = u32;
+pub type addr_t = u64;
+pub type vmi_pid_t = i32;
+pub type vmi_mode_t = u32;
+pub type vmi_init_error_t = u32;
+
+pub const VMI_SUCCESS: status_t = 0;
+pub const VMI_FAILURE: status_t = 1;
+
+pub const VMI_INIT_ERROR_NONE: vmi_init_error_t = 0;
+pub const VMI_INIT_ERROR_DRIVER: vmi_init_error_t = 2;
+pub const VMI_INIT_ERROR_VM_NOT_FOUND: vmi_init_error_t = 3;
+
+type ProcessInstance = <OsInstanceArcBox<'static> as OsInner<'static>>::IntoProcessType;
+
+/// State behind a `vmi_instance_t`.
+pub struct VmiInstance {
+    os: OsInstanceArcBox<'static>,
+    processes: HashMap<vmi_pid_t, ProcessInstance>,
+}
+
+pub type vmi_instance_t = *mut VmiInstance;
+
+impl VmiInstance {
+    fn process(&mut self, pid: vmi_pid_t) -> Option<&mut ProcessInstance> {
+        if !self.processes.contains_key(&pid) {
+            let process = self
+                .os
+                .clone()
+                .into_process_by_pid(pid as Pid)
+                .map_err(|err| error!("unable to open process {}: {}", pid, err))
+                .ok()?;
+            self.processes.insert(pid, process);
+        }
+
+        self.processes.get_mut(&pid)
+    }
+
+    fn read_pa(&mut self, paddr: addr_t, out: &mut [u8]) -> bool {
+        match as_mut!(self.os impl PhysicalMemory) {
+            Some(phys) => phys
+                .phys_view()
+                .read_raw_into(Address::from(paddr), out)
+                .data()
+                .is_ok(),
+            None => false,
+        }
+    }
+
+    fn write_pa(&mut self, paddr: addr_t, data: &[u8]) -> bool {
+        match as_mut!(self.os impl PhysicalMemory) {
+            Some(phys) => phys
+                .phys_view()
+                .write_raw(Address::from(paddr), data)
+                .data()
+                .is_ok(),
+            None => false,
+        }
+    }
+
+    fn read_va(&mut self, vaddr: addr_t, pid: vmi_pid_t, out: &mut [u8]) -> bool {
+        let addr = Address::from(vaddr);
+
+        if pid == 0 {
+            match as_mut!(self.os impl MemoryView) {
+                Some(mem) => mem.read_raw_into(addr, out).data().is_ok(),
+                None => false,
+            }
+        } else {
+            match self.process(pid) {
+                Some(proc) => proc.read_raw_into(addr, out).data().is_ok(),
+                None => false,
+            }
+        }
+    }
+
+    fn write_va(&mut self, vaddr: addr_t, pid: vmi_pid_t, data: &[u8]) -> bool {
+        let addr = Address::from(vaddr);
+
+        if pid == 0 {
+            match as_mut!(self.os impl MemoryView) {
+                Some(mem) => mem.write_raw(addr, data).data().is_ok(),
+                None => false,
+            }
+        } else {
+            match self.process(pid) {
+                Some(proc) => proc.write_raw(addr, data).data().is_ok(),
+                None => false,
+            }
+        }
+    }
+
+    fn address_width(&mut self) -> u8 {
+        let bits = as_mut!(self.os impl MemoryView)
+            .map(|mem| mem.metadata().arch_bits)
+            .unwrap_or(64);
+        bits / 8
+    }
+}
+
+fn status(success: bool) -> status_t {
+    if success {
+        VMI_SUCCESS
+    } else {
+        VMI_FAILURE
+    }
+}
+
+/// Creates an instance for the memflow chain in `domain`.
+///
+/// `mode`, `init_flags` and `init_data` are accepted for compatibility and ignored.
+///
+/// # Safety
+///
+/// `vmi` has to be a valid pointer and `domain` a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_init(
+    vmi: *mut vmi_instance_t,
+    _mode: vmi_mode_t,
+    domain: *const c_void,
+    _init_flags: u64,
+    _init_data: *mut c_void,
+    error: *mut vmi_init_error_t,
+) -> status_t {
+    let (ret, err) = match init(domain as *const c_char) {
+        Ok(instance) => {
+            *vmi = Box::into_raw(Box::new(instance));
+            (VMI_SUCCESS, VMI_INIT_ERROR_NONE)
+        }
+        Err(err) => (VMI_FAILURE, err),
+    };
+
+    if !error.is_null() {
+        *error = err;
+    }
+
+    ret
+}
+
+/// Same as `vmi_init`, the configuration is ignored.
+///
+/// # Safety
+///
+/// See `vmi_init`.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_init_complete(
+    vmi: *mut vmi_instance_t,
+    domain: *const c_void,
+    init_flags: u64,
+    init_data: *mut c_void,
+    _config_mode: u32,
+    _config: *mut c_void,
+    error: *mut vmi_init_error_t,
+) -> status_t {
+    vmi_init(vmi, 0, domain, init_flags, init_data, error)
+}
+
+unsafe fn init(domain: *const c_char) -> std::result::Result<VmiInstance, vmi_init_error_t> {
+    if domain.is_null() {
+        return Err(VMI_INIT_ERROR_VM_NOT_FOUND);
+    }
+
+    let domain = CStr::from_ptr(domain)
+        .to_str()
+        .map_err(|_| VMI_INIT_ERROR_VM_NOT_FOUND)?;
+    let (connector, os) = domain.split_once('/').unwrap_or((domain, "win32"));
+
+    let chain =
+        OsChain::new(std::iter::once((0, connector)), std::iter::once((1, os))).map_err(|err| {
+            error!("invalid domain {}: {}", domain, err);
+            VMI_INIT_ERROR_VM_NOT_FOUND
+        })?;
+
+    let os = Inventory::scan()
+        .builder()
+        .os_chain(chain)
+        .build()
+        .map_err(|err| {
+            error!("unable to initialize {}: {}", domain, err);
+            VMI_INIT_ERROR_DRIVER
+        })?;
+
+    Ok(VmiInstance {
+        os,
+        processes: HashMap::new(),
+    })
+}
+
+/// Destroys an instance created by `vmi_init`.
+///
+/// # Safety
+///
+/// `vmi` has to be created by `vmi_init` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_destroy(vmi: vmi_instance_t) -> status_t {
+    if !vmi.is_null() {
+        std::mem::drop(Box::from_raw(vmi));
+    }
+    VMI_SUCCESS
+}
+
+/// # Safety
+///
+/// `buf` has to be null or point to at least `count` writeable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_read_pa(
+    vmi: vmi_instance_t,
+    paddr: addr_t,
+    count: usize,
+    buf: *mut c_void,
+    bytes_read: *mut usize,
+) -> status_t {
+    if let Some(res) = check_buf(buf, count, bytes_read) {
+        return res;
+    }
+    let out = std::slice::from_raw_parts_mut(buf as *mut u8, count);
+    let ok = (*vmi).read_pa(paddr, out);
+    set_count(bytes_read, ok, count);
+    status(ok)
+}
+
+/// # Safety
+///
+/// `buf` has to be null or point to at least `count` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_write_pa(
+    vmi: vmi_instance_t,
+    paddr: addr_t,
+    count: usize,
+    buf: *const c_void,
+    bytes_written: *mut usize,
+) -> status_t {
+    if let Some(res) = check_buf(buf, count, bytes_written) {
+        return res;
+    }
+    let data = std::slice::from_raw_parts(buf as *const u8, count);
+    let ok = (*vmi).write_pa(paddr, data);
+    set_count(bytes_written, ok, count);
+    status(ok)
+}
+
+/// # Safety
+///
+/// `buf` has to be null or point to at least `count` writeable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_read_va(
+    vmi: vmi_instance_t,
+    vaddr: addr_t,
+    pid: vmi_pid_t,
+    count: usize,
+    buf: *mut c_void,
+    bytes_read: *mut usize,
+) -> status_t {
+    if let Some(res) = check_buf(buf, count, bytes_read) {
+        return res;
+    }
+    let out = std::slice::from_raw_parts_mut(buf as *mut u8, count);
+    let ok = (*vmi).read_va(vaddr, pid, out);
+    set_count(bytes_read, ok, count);
+    status(ok)
+}
+
+/// # Safety
+///
+/// `buf` has to be null or point to at least `count` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_write_va(
+    vmi: vmi_instance_t,
+    vaddr: addr_t,
+    pid: vmi_pid_t,
+    count: usize,
+    buf: *const c_void,
+    bytes_written: *mut usize,
+) -> status_t {
+    if let Some(res) = check_buf(buf, count, bytes_written) {
+        return res;
+    }
+    let data = std::slice::from_raw_parts(buf as *const u8, count);
+    let ok = (*vmi).write_va(vaddr, pid, data);
+    set_count(bytes_written, ok, count);
+    status(ok)
+}
+
+/// Returns the status of accesses that must not touch `buf`.
+///
+/// Empty accesses succeed without touching any memory, null buffers fail.
+unsafe fn check_buf(buf: *const c_void, count: usize, bytes: *mut usize) -> Option<status_t> {
+    if count == 0 || buf.is_null() {
+        set_count(bytes, false, 0);
+        Some(status(count == 0))
+    } else {
+        None
+    }
+}
+
+unsafe fn set_count(out: *mut usize, ok: bool, count: usize) {
+    if !out.is_null() {
+        *out = if ok { count } else { 0 };
+    }
+}
+
+macro_rules! read_int {
+    ($pa:ident, $va:ident, $ty:ty) => {
+        /// # Safety
+        ///
+        /// `value` has to be a valid pointer.
+        #[no_mangle]
+        pub unsafe extern "C" fn $pa(
+            vmi: vmi_instance_t,
+            paddr: addr_t,
+            value: *mut $ty,
+        ) -> status_t {
+            let mut buf = [0u8; std::mem::size_of::<$ty>()];
+            let ok = (*vmi).read_pa(paddr, &mut buf);
+            if ok {
+                *value = <$ty>::from_le_bytes(buf);
+            }
+            status(ok)
+        }
+
+        /// # Safety
+        ///
+        /// `value` has to be a valid pointer.
+        #[no_mangle]
+        pub unsafe extern "C" fn $va(
+            vmi: vmi_instance_t,
+            vaddr: addr_t,
+            pid: vmi_pid_t,
+            value: *mut $ty,
+        ) -> status_t {
+            let mut buf = [0u8; std::mem::size_of::<$ty>()];
+            let ok = (*vmi).read_va(vaddr, pid, &mut buf);
+            if ok {
+                *value = <$ty>::from_le_bytes(buf);
+            }
+            status(ok)
+        }
+    };
+}
+
+read_int!(vmi_read_8_pa, vmi_read_8_va, u8);
+read_int!(vmi_read_16_pa, vmi_read_16_va, u16);
+read_int!(vmi_read_32_pa, vmi_read_32_va, u32);
+read_int!(vmi_read_64_pa, vmi_read_64_va, u64);
+
+/// Reads a pointer sized value, zero extended to 64 bits.
+///
+/// # Safety
+///
+/// `value` has to be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_read_addr_va(
+    vmi: vmi_instance_t,
+    vaddr: addr_t,
+    pid: vmi_pid_t,
+    value: *mut addr_t,
+) -> status_t {
+    let mut buf = [0u8; 8];
+    let width = (*vmi).address_width() as usize;
+    let ok = (*vmi).read_va(vaddr, pid, &mut buf[..width]);
+    if ok {
+        *value = u64::from_le_bytes(buf);
+    }
+    status(ok)
+}
+
+/// Translates a virtual address of a process to a physical address.
+///
+/// # Safety
+///
+/// `paddr` has to be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_translate_uv2p(
+    vmi: vmi_instance_t,
+    vaddr: addr_t,
+    pid: vmi_pid_t,
+    paddr: *mut addr_t,
+) -> status_t {
+    let phys = (*vmi)
+        .process(pid)
+        .and_then(|proc| as_mut!(proc impl VirtualTranslate))
+        .and_then(|vat| vat.virt_to_phys(Address::from(vaddr)).ok());
+
+    match phys {
+        Some(phys) => {
+            *paddr = phys.address().to_umem() as addr_t;
+            VMI_SUCCESS
+        }
+        None => VMI_FAILURE,
+    }
+}
+
+/// Kernel address translation is not exposed by OS layers and always fails.
+///
+/// # Safety
+///
+/// This function is always safe to call.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_translate_kv2p(
+    _vmi: vmi_instance_t,
+    _vaddr: addr_t,
+    _paddr: *mut addr_t,
+) -> status_t {
+    VMI_FAILURE
+}
+
+/// Returns the size of physical memory in bytes.
+///
+/// # Safety
+///
+/// `vmi` has to be created by `vmi_init`.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_get_memsize(vmi: vmi_instance_t) -> u64 {
+    as_mut!((*vmi).os impl PhysicalMemory)
+        .map(|phys| phys.metadata().real_size as u64)
+        .unwrap_or_default()
+}
+
+/// Returns the highest physical address.
+///
+/// # Safety
+///
+/// `vmi` has to be created by `vmi_init`.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_get_max_physical_address(vmi: vmi_instance_t) -> addr_t {
+    as_mut!((*vmi).os impl PhysicalMemory)
+        .map(|phys| phys.metadata().max_address.to_umem() as addr_t)
+        .unwrap_or_default()
+}
+
+/// Returns the size of a pointer of the OS in bytes.
+///
+/// # Safety
+///
+/// `vmi` has to be created by `vmi_init`.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_get_address_width(vmi: vmi_instance_t) -> u8 {
+    (*vmi).address_width()
+}
+
+/// # Safety
+///
+/// This function is always safe to call.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_get_num_vcpus(_vmi: vmi_instance_t) -> u32 {
+    1
+}
+
+/// Pausing is up to the connector, this is a no-op.
+///
+/// # Safety
+///
+/// This function is always safe to call.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_pause_vm(_vmi: vmi_instance_t) -> status_t {
+    VMI_SUCCESS
+}
+
+/// Resuming is up to the connector, this is a no-op.
+///
+/// # Safety
+///
+/// This function is always safe to call.
+#[no_mangle]
+pub unsafe extern "C" fn vmi_resume_vm(_vmi: vmi_instance_t) -> status_t {
+    VMI_SUCCESS
+}
@@ -0,0 +1,122 @@
+/*!
+On-board triage with the embedded profile.
+
+This example is structured like firmware running on a DMA device would be: no threads, no
+clock and a fixed size heap handed out by a bump allocator. The DMA engine is simulated by a
+buffer, on real hardware `DmaEngine` would drive the transfer registers of the device.
+
+The device scans the target for page aligned PE headers and only reports their addresses,
+instead of shipping the whole memory to the host.
+
+Run with `cargo run --example embedded_triage --no-default-features --features embedded`.
+*/
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use memflow::embedded::{RawConnector, RawPhysicalMemory};
+use memflow::error::{Error, ErrorKind, ErrorOrigin, Result};
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::scan::{MemoryScan, Pattern};
+use memflow::types::{size, umem, Address};
+
+const HEAP_SIZE: usize = size::mb(16);
+
+/// Allocator handing out memory of a static heap, memory is never freed.
+struct BumpAllocator {
+    heap: UnsafeCell<[u8; HEAP_SIZE]>,
+    next: AtomicUsize,
+}
+
+unsafe impl Sync for BumpAllocator {}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let base = self.heap.get() as usize;
+        let mut next = self.next.load(Ordering::Relaxed);
+
+        loop {
+            let start = (base + next + layout.align() - 1) & !(layout.align() - 1);
+            let end = start - base + layout.size();
+            if end > HEAP_SIZE {
+                return core::ptr::null_mut();
+            }
+
+            match self
+                .next
+                .compare_exchange_weak(next, end, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return start as *mut u8,
+                Err(current) => next = current,
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[global_allocator]
+static ALLOCATOR: BumpAllocator = BumpAllocator {
+    heap: UnsafeCell::new([0; HEAP_SIZE]),
+    next: AtomicUsize::new(0),
+};
+
+/// Stand-in for the DMA engine of the device.
+struct DmaEngine {
+    target: Vec<u8>,
+}
+
+impl RawPhysicalMemory for DmaEngine {
+    fn read_raw(&mut self, addr: Address, out: &mut [u8]) -> Result<()> {
+        let start = addr.to_umem() as usize;
+        let data = self
+            .target
+            .get(start..start + out.len())
+            .ok_or(Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds))?;
+        out.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn write_raw(&mut self, _addr: Address, _data: &[u8]) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly))
+    }
+
+    fn max_address(&self) -> Address {
+        Address::from((self.target.len() - 1) as umem)
+    }
+
+    fn readonly(&self) -> bool {
+        true
+    }
+}
+
+fn main() {
+    let mut target = vec![0u8; size::mb(4)];
+    for &page in [0x10000usize, 0x2a000, 0x31337].iter() {
+        target[page..page + 4].copy_from_slice(&[0x4d, 0x5a, 0x90, 0x00]);
+    }
+
+    let mut conn = RawConnector::new(DmaEngine { target });
+    let max_address = conn.metadata().max_address;
+
+    let pattern = Pattern::parse("4d 5a 90 00").unwrap();
+    let headers = conn
+        .phys_view()
+        .find_pattern(Address::null()..max_address, &pattern)
+        .into_iter()
+        .filter(|addr| addr.to_umem() % size::kb(4) as umem == 0)
+        .collect::<Vec<_>>();
+
+    // on the device this would be sent over the link to the host
+    for addr in headers.iter() {
+        let e_lfanew: u32 = conn.phys_view().read(*addr + 0x3cusize).unwrap_or_default();
+        println!("pe header at {:x} (e_lfanew {:x})", addr, e_lfanew);
+    }
+
+    println!(
+        "{} headers found, {} bytes of heap used",
+        headers.len(),
+        ALLOCATOR.next.load(Ordering::Relaxed)
+    );
+}
@@ -0,0 +1,91 @@
+/*!
+Reading virtual memory without touching the heap.
+
+All storage used during a read is handed to memflow up front: the translator works on a borrowed
+buffer (`DirectTranslate::with_buffer`) and `VirtualDma::with_scratch` keeps the translated
+addresses in a caller provided buffer. The allocator of this example counts every allocation,
+the read itself is expected to not perform any.
+
+The target is a flat buffer with a minimal x64 page table mapping the first 2 MiB of virtual
+memory through a single large page.
+
+Run with `cargo run --example no_alloc_read --no-default-features --features embedded`.
+*/
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memflow::architecture::x86::x64;
+use memflow::embedded::{RawConnector, SliceMemory};
+use memflow::mem::{DirectTranslate, MemoryView, VirtualDma};
+use memflow::types::{size, Address};
+
+/// Allocator forwarding to the system allocator and counting the allocations.
+struct CountingAllocator {
+    allocations: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator {
+    allocations: AtomicUsize::new(0),
+};
+
+const DTB: usize = 0x1000;
+const PAGE_BASE: usize = 0x20_0000;
+
+fn write_entry(target: &mut [u8], addr: usize, entry: u64) {
+    target[addr..addr + 8].copy_from_slice(&entry.to_le_bytes());
+}
+
+fn main() {
+    let mut target = vec![0u8; size::mb(4)];
+
+    // pml4 -> pdpt -> pd, the pd maps a present and writeable 2 MiB page
+    write_entry(&mut target, DTB, 0x2003);
+    write_entry(&mut target, 0x2000, 0x3003);
+    write_entry(&mut target, 0x3000, PAGE_BASE as u64 | 0x83);
+
+    let virt_addr = 0x1_0000;
+    let len = size::kb(16);
+    for (i, b) in target[PAGE_BASE + virt_addr..][..len]
+        .iter_mut()
+        .enumerate()
+    {
+        *b = i as u8;
+    }
+
+    let mut tmp_buf = [MaybeUninit::new(0); size::kb(32)];
+    let mut scratch = [MaybeUninit::new(0); size::kb(1)];
+    let mut out = [0u8; size::kb(16)];
+
+    let mut virt_mem = VirtualDma::with_scratch(
+        RawConnector::new(SliceMemory::new(&mut target)),
+        x64::ARCH,
+        x64::new_translator(Address::from(DTB as u64)),
+        DirectTranslate::with_buffer(&mut tmp_buf),
+        &mut scratch,
+    );
+
+    let before = ALLOCATOR.allocations.load(Ordering::Relaxed);
+    virt_mem
+        .read_raw_into(Address::from(virt_addr as u64), &mut out)
+        .unwrap();
+    let allocations = ALLOCATOR.allocations.load(Ordering::Relaxed) - before;
+
+    assert!(out.iter().enumerate().all(|(i, &b)| b == i as u8));
+    assert_eq!(allocations, 0, "the read performed allocations");
+
+    println!("read {:x} bytes without allocating", out.len());
+}
@@ -0,0 +1,729 @@
+//! Detection of IAT, EAT and inline hooks.
+//!
+//! Hooks are found by comparing a module loaded in a process against a baseline of the same
+//! module. The baseline is either the image on disk ([`ImageBaseline`]) or the same module
+//! loaded into another process ([`ProcessBaseline`]).
+//!
+//! * IAT hooks are import address table entries that point somewhere else than in the
+//!   baseline. If the baseline has no resolved imports (e.g. an image on disk) all entries
+//!   pointing outside of any module are reported.
+//! * EAT hooks are exports whose offset differs from the baseline.
+//! * Inline hooks are exported functions whose first [`PROLOGUE_SIZE`] bytes differ from the
+//!   baseline. Common jump sequences are decoded to find the target of the hook.
+//!
+//! Every hook is attributed to the module containing its target, so hooks installed by
+//! legitimate software can be told apart from hooks leading into unbacked memory.
+//!
+//! # Remarks
+//!
+//! Images on disk are not relocated, prologues containing absolute addresses will therefore
+//! show up as inline hooks when comparing against an [`ImageBaseline`] of a relocated module.
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{ExportInfo, ModuleInfo, Process};
+use crate::types::{imem, umem, Address};
+
+use cglue::prelude::v1::ReprCString;
+
+/// Amount of bytes compared at the start of every exported function.
+pub const PROLOGUE_SIZE: usize = 16;
+
+/// The kind of a [`Hook`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HookKind {
+    Iat,
+    Eat,
+    Inline,
+}
+
+/// A hook found by [`find_hooks`].
+#[derive(Clone, Debug)]
+pub struct Hook {
+    pub kind: HookKind,
+    /// Name of the hooked function, imports are named `dll!function`.
+    pub name: ReprCString,
+    /// Address of the hooked entry: the IAT slot, the original export or the patched code.
+    pub address: Address,
+    /// Where the hook leads to, if it could be determined.
+    pub target: Option<Address>,
+    /// The module containing the hook target.
+    pub target_module: Option<ModuleInfo>,
+}
+
+/// A single entry of the import address table of a module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportSlot {
+    /// Name of the module the function is imported from.
+    pub dll: String,
+    /// Name of the imported function, or `#ordinal` for imports by ordinal.
+    pub name: String,
+    /// Address of the import address table entry.
+    pub slot: Address,
+    /// The address stored in the entry.
+    pub target: Address,
+}
+
+/// The known good state of a module that hooks are compared against.
+pub trait HookBaseline {
+    /// Returns the exports of the baseline module.
+    fn exports(&mut self, module: &ModuleInfo) -> Result<Vec<ExportInfo>>;
+
+    /// Reads the baseline module at `offset` from its base.
+    fn read(&mut self, module: &ModuleInfo, offset: umem, out: &mut [u8]) -> Result<()>;
+
+    /// Returns the resolved import address table of the baseline module.
+    ///
+    /// Baselines without resolved imports return `None`.
+    fn resolved_imports(&mut self, _module: &ModuleInfo) -> Result<Option<Vec<ImportSlot>>> {
+        Ok(None)
+    }
+}
+
+/// Uses the module with the same name in another process as baseline.
+pub struct ProcessBaseline<'a, P> {
+    process: &'a mut P,
+    cached: Option<(Address, ModuleInfo)>,
+}
+
+impl<'a, P: Process + MemoryView> ProcessBaseline<'a, P> {
+    pub fn new(process: &'a mut P) -> Self {
+        Self {
+            process,
+            cached: None,
+        }
+    }
+
+    fn module(&mut self, module: &ModuleInfo) -> Result<ModuleInfo> {
+        if let Some((base, baseline)) = &self.cached {
+            if *base == module.base {
+                return Ok(baseline.clone());
+            }
+        }
+
+        let baseline = self.process.module_by_name(module.name.as_ref())?;
+        self.cached = Some((module.base, baseline.clone()));
+        Ok(baseline)
+    }
+}
+
+impl<'a, P: Process + MemoryView> HookBaseline for ProcessBaseline<'a, P> {
+    fn exports(&mut self, module: &ModuleInfo) -> Result<Vec<ExportInfo>> {
+        let baseline = self.module(module)?;
+        self.process.module_export_list(&baseline)
+    }
+
+    fn read(&mut self, module: &ModuleInfo, offset: umem, out: &mut [u8]) -> Result<()> {
+        let baseline = self.module(module)?;
+        self.process
+            .read_raw_into(baseline.base + offset, out)
+            .data()
+    }
+
+    fn resolved_imports(&mut self, module: &ModuleInfo) -> Result<Option<Vec<ImportSlot>>> {
+        let baseline = self.module(module)?;
+        import_slots(&mut *self.process, &baseline).map(Some)
+    }
+}
+
+/// Uses a PE image in its on-disk layout as baseline.
+pub struct ImageBaseline {
+    image: Vec<u64>,
+    len: usize,
+}
+
+impl ImageBaseline {
+    /// Creates a baseline from the raw bytes of a PE file.
+    pub fn new(image: &[u8]) -> Self {
+        // some of the parsers require aligned data
+        let mut aligned = vec![0u64; (image.len() + 7) / 8];
+        dataview::Pod::as_bytes_mut(aligned.as_mut_slice())[..image.len()].copy_from_slice(image);
+
+        Self {
+            image: aligned,
+            len: image.len(),
+        }
+    }
+
+    /// Reads the baseline from a PE file on disk.
+    #[cfg(feature = "std")]
+    pub fn open<Q: AsRef<::std::path::Path>>(path: Q) -> Result<Self> {
+        let image = ::std::fs::read(path).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to read the baseline image: {}", err))
+        })?;
+        Ok(Self::new(&image))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &dataview::Pod::as_bytes(self.image.as_slice())[..self.len]
+    }
+}
+
+impl HookBaseline for ImageBaseline {
+    fn exports(&mut self, _module: &ModuleInfo) -> Result<Vec<ExportInfo>> {
+        let mut out = vec![];
+        crate::os::util::image_export_list_callback(self.bytes(), (&mut out).into())?;
+        Ok(out)
+    }
+
+    fn read(&mut self, _module: &ModuleInfo, offset: umem, out: &mut [u8]) -> Result<()> {
+        let image = self.bytes();
+
+        let start = PeHeaders::parse(image)
+            .ok_or_else(|| Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile))?
+            .rva_to_offset(offset)
+            .ok_or_else(|| Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds))?;
+
+        let data = image
+            .get(start..start + out.len())
+            .ok_or_else(|| Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds))?;
+        out.copy_from_slice(data);
+
+        Ok(())
+    }
+}
+
+/// Finds all hooks of `module` in `process` by comparing it against `baseline`.
+pub fn find_hooks<P: Process + MemoryView>(
+    process: &mut P,
+    module: &ModuleInfo,
+    baseline: &mut impl HookBaseline,
+) -> Result<Vec<Hook>> {
+    let modules = process.module_list()?;
+    let exports = process.module_export_list(module)?;
+    let baseline_exports = baseline.exports(module)?;
+
+    let mut out = find_eat_hooks(module, &exports, &baseline_exports, &modules);
+    out.extend(find_inline_hooks(
+        process,
+        module,
+        &baseline_exports,
+        baseline,
+        &modules,
+    ));
+
+    // modules without a parseable import directory can not have iat hooks
+    if let Ok(slots) = import_slots(process, module) {
+        let baseline_slots = baseline.resolved_imports(module)?;
+        out.extend(find_iat_hooks(&slots, baseline_slots.as_deref(), &modules));
+    }
+
+    Ok(out)
+}
+
+/// Returns all import address table entries of a module loaded in memory.
+pub fn import_slots(mem: &mut impl MemoryView, module: &ModuleInfo) -> Result<Vec<ImportSlot>> {
+    let mut image = vec![0u8; module.size as usize];
+    mem.read_raw_into(module.base, &mut image).data_part()?;
+
+    let headers = PeHeaders::parse(&image)
+        .ok_or_else(|| Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile))?;
+
+    Ok(headers.import_slots(&image, module.base))
+}
+
+/// Compares the import address table against a baseline.
+pub fn find_iat_hooks(
+    slots: &[ImportSlot],
+    baseline: Option<&[ImportSlot]>,
+    modules: &[ModuleInfo],
+) -> Vec<Hook> {
+    slots
+        .iter()
+        .filter_map(|slot| {
+            let target_module = containing_module(modules, slot.target);
+
+            let expected = baseline
+                .and_then(|b| {
+                    b.iter()
+                        .find(|b| b.dll.eq_ignore_ascii_case(&slot.dll) && b.name == slot.name)
+                })
+                .map(|b| b.target);
+
+            let hooked = match expected {
+                Some(expected) => expected != slot.target,
+                None => target_module.is_none(),
+            };
+
+            if hooked {
+                Some(Hook {
+                    kind: HookKind::Iat,
+                    name: ReprCString::from(format!("{}!{}", slot.dll, slot.name).as_str()),
+                    address: slot.slot,
+                    target: Some(slot.target),
+                    target_module,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Compares the exports of a module against a baseline.
+pub fn find_eat_hooks(
+    module: &ModuleInfo,
+    exports: &[ExportInfo],
+    baseline: &[ExportInfo],
+    modules: &[ModuleInfo],
+) -> Vec<Hook> {
+    let baseline = baseline
+        .iter()
+        .map(|e| (e.name.as_ref(), e.offset))
+        .collect::<BTreeMap<&str, umem>>();
+
+    exports
+        .iter()
+        .filter_map(|e| {
+            let expected = *baseline.get(e.name.as_ref())?;
+            if expected == e.offset {
+                return None;
+            }
+
+            let target = module.base + e.offset;
+            Some(Hook {
+                kind: HookKind::Eat,
+                name: e.name.clone(),
+                address: module.base + expected,
+                target: Some(target),
+                target_module: containing_module(modules, target),
+            })
+        })
+        .collect()
+}
+
+/// Compares the prologues of all `exports` against a baseline.
+///
+/// Exports that can not be read in either the process or the baseline are skipped.
+pub fn find_inline_hooks(
+    mem: &mut impl MemoryView,
+    module: &ModuleInfo,
+    exports: &[ExportInfo],
+    baseline: &mut impl HookBaseline,
+    modules: &[ModuleInfo],
+) -> Vec<Hook> {
+    let arch_bits = mem.metadata().arch_bits;
+    let mut out = vec![];
+
+    for export in exports {
+        let address = module.base + export.offset;
+
+        let mut code = [0u8; PROLOGUE_SIZE];
+        let mut expected = [0u8; PROLOGUE_SIZE];
+
+        if mem.read_raw_into(address, &mut code).is_err()
+            || baseline.read(module, export.offset, &mut expected).is_err()
+            || code == expected
+        {
+            continue;
+        }
+
+        let target = decode_jump(mem, address, &code, arch_bits);
+
+        out.push(Hook {
+            kind: HookKind::Inline,
+            name: export.name.clone(),
+            address,
+            target,
+            target_module: target.and_then(|t| containing_module(modules, t)),
+        });
+    }
+
+    out
+}
+
+fn containing_module(modules: &[ModuleInfo], address: Address) -> Option<ModuleInfo> {
+    modules
+        .iter()
+        .find(|m| m.base <= address && address < m.base + m.size)
+        .cloned()
+}
+
+/// Decodes the most common jump sequences placed by hooking libraries.
+fn decode_jump(
+    mem: &mut impl MemoryView,
+    address: Address,
+    code: &[u8; PROLOGUE_SIZE],
+    arch_bits: u8,
+) -> Option<Address> {
+    let rel32 = |off: usize| i32::from_le_bytes(code[off..off + 4].try_into().unwrap()) as imem;
+    let abs32 =
+        |off: usize| Address::from(u32::from_le_bytes(code[off..off + 4].try_into().unwrap()));
+    let rel = |off: imem| Address::from((address.to_umem() as imem).wrapping_add(off) as umem);
+
+    match code {
+        // jmp rel32
+        [0xe9, ..] => Some(rel(5 + rel32(1))),
+        // jmp rel8
+        [0xeb, off, ..] => Some(rel(2 + *off as i8 as imem)),
+        // jmp [rip + disp32] / jmp [disp32]
+        [0xff, 0x25, ..] => {
+            if arch_bits == 64 {
+                let ptr = rel(6 + rel32(2));
+                mem.read::<u64>(ptr).data().ok().map(Address::from)
+            } else {
+                mem.read::<u32>(abs32(2)).data().ok().map(Address::from)
+            }
+        }
+        // mov rax, imm64; jmp rax
+        [0x48, 0xb8, ..] if code[10] == 0xff && code[11] == 0xe0 => Some(Address::from(
+            u64::from_le_bytes(code[2..10].try_into().unwrap()),
+        )),
+        // push imm32; ret
+        [0x68, ..] if code[5] == 0xc3 => Some(abs32(1)),
+        _ => None,
+    }
+}
+
+struct PeSection {
+    va: u32,
+    virtual_size: u32,
+    raw: u32,
+    raw_size: u32,
+}
+
+/// The parts of the PE headers needed to locate import tables and to map rvas to file offsets.
+struct PeHeaders {
+    is_64: bool,
+    data_dirs: Vec<(u32, u32)>,
+    sections: Vec<PeSection>,
+}
+
+impl PeHeaders {
+    fn parse(image: &[u8]) -> Option<Self> {
+        if image.get(..2)? != b"MZ" {
+            return None;
+        }
+
+        let nt = read_u32(image, 0x3c)? as usize;
+        if image.get(nt..nt + 4)? != b"PE\0\0" {
+            return None;
+        }
+
+        let num_sections = read_u16(image, nt + 6)? as usize;
+        let opt = nt + 24;
+        let sections_start = opt + read_u16(image, nt + 20)? as usize;
+
+        let (is_64, dirs) = match read_u16(image, opt)? {
+            0x10b => (false, opt + 96),
+            0x20b => (true, opt + 112),
+            _ => return None,
+        };
+
+        let num_dirs = std::cmp::min(read_u32(image, dirs - 4)? as usize, 16);
+        let data_dirs = (0..num_dirs)
+            .map(|i| {
+                Some((
+                    read_u32(image, dirs + i * 8)?,
+                    read_u32(image, dirs + i * 8 + 4)?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let sections = (0..num_sections)
+            .map(|i| {
+                let section = sections_start + i * 40;
+                Some(PeSection {
+                    virtual_size: read_u32(image, section + 8)?,
+                    va: read_u32(image, section + 12)?,
+                    raw_size: read_u32(image, section + 16)?,
+                    raw: read_u32(image, section + 20)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            is_64,
+            data_dirs,
+            sections,
+        })
+    }
+
+    /// Maps an rva to an offset in the on-disk layout of the image.
+    fn rva_to_offset(&self, rva: umem) -> Option<usize> {
+        let first_section = self.sections.iter().map(|s| s.va as umem).min();
+
+        // the headers are mapped as they are
+        if first_section.map(|va| rva < va).unwrap_or(true) {
+            return Some(rva as usize);
+        }
+
+        self.sections
+            .iter()
+            .find(|s| {
+                let size = std::cmp::max(s.virtual_size, s.raw_size) as umem;
+                s.va as umem <= rva && rva < s.va as umem + size
+            })
+            .map(|s| (rva - s.va as umem + s.raw as umem) as usize)
+    }
+
+    /// Walks the import directory of an image in its in-memory layout.
+    fn import_slots(&self, image: &[u8], base: Address) -> Vec<ImportSlot> {
+        let mut out = vec![];
+
+        let dir = match self.data_dirs.get(1) {
+            Some(&(rva, _)) if rva != 0 => rva as usize,
+            _ => return out,
+        };
+
+        let ptr_size = if self.is_64 { 8 } else { 4 };
+        let ordinal_flag = if self.is_64 { 1 << 63 } else { 1 << 31 };
+
+        for desc in (dir..).step_by(20) {
+            let (names, dll, iat) = match (
+                read_u32(image, desc),
+                read_u32(image, desc + 12),
+                read_u32(image, desc + 16),
+            ) {
+                (Some(0), Some(0), Some(0)) => break,
+                (Some(names), Some(dll), Some(iat)) => (names, dll, iat),
+                _ => break,
+            };
+
+            let dll = read_cstr(image, dll as usize).unwrap_or_default();
+            // the loader overwrites the iat, the names are only left in the original thunks
+            let names = if names != 0 { names } else { iat } as usize;
+
+            for i in 0.. {
+                let thunk = read_ptr(image, names + i * ptr_size, self.is_64);
+                let target = read_ptr(image, iat as usize + i * ptr_size, self.is_64);
+
+                let (thunk, target) = match (thunk, target) {
+                    (Some(thunk), Some(target)) if thunk != 0 => (thunk, target),
+                    _ => break,
+                };
+
+                let name = if thunk & ordinal_flag != 0 {
+                    format!("#{}", thunk & 0xffff)
+                } else {
+                    read_cstr(image, thunk as usize + 2).unwrap_or_default()
+                };
+
+                out.push(ImportSlot {
+                    dll: dll.clone(),
+                    name,
+                    slot: base + (iat as usize + i * ptr_size),
+                    target: Address::from(target),
+                });
+            }
+        }
+
+        out
+    }
+}
+
+fn read_u16(image: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        image.get(off..off + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(image: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        image.get(off..off + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_ptr(image: &[u8], off: usize, is_64: bool) -> Option<u64> {
+    if is_64 {
+        Some(u64::from_le_bytes(
+            image.get(off..off + 8)?.try_into().ok()?,
+        ))
+    } else {
+        read_u32(image, off).map(u64::from)
+    }
+}
+
+fn read_cstr(image: &[u8], off: usize) -> Option<String> {
+    let bytes = image.get(off..)?;
+    let len = bytes.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    fn module(base: Address, size: umem, name: &str) -> ModuleInfo {
+        ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base,
+            size,
+            name: name.into(),
+            path: name.into(),
+            arch: crate::architecture::ArchitectureIdent::X86(64, false),
+        }
+    }
+
+    fn export(name: &str, offset: umem) -> ExportInfo {
+        ExportInfo {
+            name: name.into(),
+            offset,
+        }
+    }
+
+    /// Builds a minimal 64-bit PE image with a single import and a single section.
+    fn test_image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x1000];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        image[0x40..0x44].copy_from_slice(b"PE\0\0");
+        image[0x46..0x48].copy_from_slice(&1u16.to_le_bytes());
+        image[0x54..0x56].copy_from_slice(&0xf0u16.to_le_bytes());
+        // optional header
+        image[0x58..0x5a].copy_from_slice(&0x20bu16.to_le_bytes());
+        image[0xc4..0xc8].copy_from_slice(&16u32.to_le_bytes());
+        image[0xd0..0xd4].copy_from_slice(&0x200u32.to_le_bytes());
+        // section table
+        image[0x150..0x154].copy_from_slice(&0x100u32.to_le_bytes());
+        image[0x154..0x158].copy_from_slice(&0x1000u32.to_le_bytes());
+        image[0x158..0x15c].copy_from_slice(&0x100u32.to_le_bytes());
+        image[0x15c..0x160].copy_from_slice(&0x800u32.to_le_bytes());
+        // import descriptor
+        image[0x200..0x204].copy_from_slice(&0x300u32.to_le_bytes());
+        image[0x20c..0x210].copy_from_slice(&0x280u32.to_le_bytes());
+        image[0x210..0x214].copy_from_slice(&0x400u32.to_le_bytes());
+        image[0x280..0x28c].copy_from_slice(b"kernel32.dll");
+        image[0x300..0x308].copy_from_slice(&0x340u64.to_le_bytes());
+        image[0x342..0x34d].copy_from_slice(b"ExitProcess");
+        image[0x400..0x408].copy_from_slice(&0x7ff0_0000_1000u64.to_le_bytes());
+        image[0x800..0x804].copy_from_slice(&[0x48, 0x89, 0x5c, 0x24]);
+        image
+    }
+
+    #[test]
+    fn parse_import_slots() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &test_image());
+        let base = proc.info().address;
+
+        let slots = import_slots(&mut proc, &module(base, 0x1000, "test.dll")).unwrap();
+
+        assert_eq!(
+            slots,
+            vec![ImportSlot {
+                dll: "kernel32.dll".into(),
+                name: "ExitProcess".into(),
+                slot: base + 0x400usize,
+                target: Address::from(0x7ff0_0000_1000u64),
+            }]
+        );
+    }
+
+    #[test]
+    fn image_baseline_maps_sections() {
+        let image = test_image();
+        let mut baseline = ImageBaseline::new(&image);
+        let info = module(Address::null(), 0x2000, "test.dll");
+
+        let mut out = [0u8; 4];
+        baseline.read(&info, 0x1000, &mut out).unwrap();
+        assert_eq!(out, [0x48, 0x89, 0x5c, 0x24]);
+
+        baseline.read(&info, 0x40, &mut out).unwrap();
+        assert_eq!(&out, b"PE\0\0");
+
+        assert!(baseline.read(&info, 0x3000, &mut out).is_err());
+    }
+
+    #[test]
+    fn iat_hooks() {
+        let kernel32 = module(Address::from(0x7ff0_0000_0000u64), 0x10000, "kernel32.dll");
+        let slot = |target: u64| ImportSlot {
+            dll: "kernel32.dll".into(),
+            name: "ExitProcess".into(),
+            slot: Address::from(0x1400u64),
+            target: Address::from(target),
+        };
+
+        let modules = [kernel32.clone()];
+
+        // without a baseline only unbacked targets are reported
+        assert!(find_iat_hooks(&[slot(0x7ff0_0000_1000)], None, &modules).is_empty());
+        let hooks = find_iat_hooks(&[slot(0x1234_0000)], None, &modules);
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(hooks[0].name.as_ref(), "kernel32.dll!ExitProcess");
+        assert!(hooks[0].target_module.is_none());
+
+        // with a baseline every difference is reported
+        let hooks = find_iat_hooks(
+            &[slot(0x7ff0_0000_2000)],
+            Some(&[slot(0x7ff0_0000_1000)]),
+            &modules,
+        );
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(
+            hooks[0].target_module.as_ref().map(|m| m.base),
+            Some(kernel32.base)
+        );
+    }
+
+    #[test]
+    fn eat_hooks() {
+        let info = module(Address::from(0x1000_0000u64), 0x10000, "test.dll");
+        let evil = module(Address::from(0x2000_0000u64), 0x10000, "evil.dll");
+
+        let hooks = find_eat_hooks(
+            &info,
+            &[export("a", 0x1000), export("b", 0x1000_2000)],
+            &[export("a", 0x1000), export("b", 0x2000)],
+            &[info.clone(), evil.clone()],
+        );
+
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(hooks[0].kind, HookKind::Eat);
+        assert_eq!(hooks[0].name.as_ref(), "b");
+        assert_eq!(hooks[0].address, Address::from(0x1000_2000u64));
+        assert_eq!(hooks[0].target, Some(Address::from(0x2000_2000u64)));
+        assert_eq!(
+            hooks[0].target_module.as_ref().map(|m| m.base),
+            Some(evil.base)
+        );
+    }
+
+    struct ZeroBaseline;
+
+    impl HookBaseline for ZeroBaseline {
+        fn exports(&mut self, _module: &ModuleInfo) -> Result<Vec<ExportInfo>> {
+            Ok(vec![])
+        }
+
+        fn read(&mut self, _module: &ModuleInfo, _offset: umem, out: &mut [u8]) -> Result<()> {
+            out.iter_mut().for_each(|b| *b = 0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn inline_hooks() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // jmp base + 0x1000
+        let jmp = [0xe9u8, 0xfb, 0x0e, 0x00, 0x00];
+        proc.write(base + 0x100usize, &jmp).unwrap();
+
+        let info = module(base, 0x10000, "test.dll");
+        let hooks = find_inline_hooks(
+            &mut proc,
+            &info,
+            &[export("hooked", 0x100), export("clean", 0x200)],
+            &mut ZeroBaseline,
+            &[info.clone()],
+        );
+
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(hooks[0].kind, HookKind::Inline);
+        assert_eq!(hooks[0].address, base + 0x100usize);
+        assert_eq!(hooks[0].target, Some(base + 0x1000usize));
+        assert!(hooks[0].target_module.is_some());
+    }
+}
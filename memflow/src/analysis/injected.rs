@@ -0,0 +1,183 @@
+//! Detection of injected and unbacked executable memory.
+//!
+//! Code injected into a process usually lives in memory that was allocated at runtime instead
+//! of being mapped from a module on disk. [`find_injected_memory`] walks the mapped regions of
+//! a process and flags:
+//!
+//! * regions that are writeable and executable at the same time,
+//! * executable regions that are not backed by any module of the process,
+//! * regions outside of modules that start with a PE header, which is typical for manually
+//!   mapped images.
+//!
+//! Regions with an unknown page type are only checked for PE headers.
+
+use std::prelude::v1::*;
+
+use crate::error::Result;
+use crate::mem::{MemoryRange, MemoryView};
+use crate::os::{ModuleInfo, Process};
+use crate::types::{umem, Address, PageType};
+
+use cglue::tuple::*;
+
+/// Amount of bytes read from the start of a region to look for a PE header.
+const HEADER_SIZE: usize = 0x400;
+
+bitflags! {
+    /// Reasons why a region was flagged by [`find_injected_memory`].
+    #[repr(transparent)]
+    pub struct InjectionFlags: u8 {
+        /// The region is writeable and executable.
+        const RWX = 0b0000_0001;
+        /// The region is executable, but not backed by a module.
+        const UNBACKED_EXEC = 0b0000_0010;
+        /// The region is not backed by a module, but starts with a PE header.
+        const PE_HEADER = 0b0000_0100;
+    }
+}
+
+/// A suspicious memory region found by [`find_injected_memory`].
+#[derive(Clone, Debug)]
+pub struct InjectedRegion {
+    pub address: Address,
+    pub size: umem,
+    pub page_type: PageType,
+    pub flags: InjectionFlags,
+    /// The module containing this region, if any.
+    pub module: Option<ModuleInfo>,
+}
+
+/// Walks all mapped regions of `process` and returns the ones that look injected.
+pub fn find_injected_memory<P: Process + MemoryView>(
+    process: &mut P,
+) -> Result<Vec<InjectedRegion>> {
+    let modules = process.module_list()?;
+    let regions = process.mapped_mem_vec(-1);
+    Ok(find_injected_regions(process, &regions, &modules))
+}
+
+/// Checks the given regions against the module list of the process.
+///
+/// This is the building block of [`find_injected_memory`], it can be used when regions or
+/// modules have been retrieved in another way.
+pub fn find_injected_regions(
+    mem: &mut impl MemoryView,
+    regions: &[MemoryRange],
+    modules: &[ModuleInfo],
+) -> Vec<InjectedRegion> {
+    let mut out = vec![];
+
+    for &CTup3(address, size, page_type) in regions {
+        let module = modules
+            .iter()
+            .find(|m| m.base <= address && address + size <= m.base + m.size);
+
+        let mut flags = InjectionFlags::empty();
+
+        if !page_type.contains(PageType::UNKNOWN) && !page_type.contains(PageType::NOEXEC) {
+            if page_type.contains(PageType::WRITEABLE) {
+                flags |= InjectionFlags::RWX;
+            }
+            if module.is_none() {
+                flags |= InjectionFlags::UNBACKED_EXEC;
+            }
+        }
+
+        if module.is_none() && has_pe_header(mem, address, size) {
+            flags |= InjectionFlags::PE_HEADER;
+        }
+
+        if !flags.is_empty() {
+            out.push(InjectedRegion {
+                address,
+                size,
+                page_type,
+                flags,
+                module: module.cloned(),
+            });
+        }
+    }
+
+    out
+}
+
+fn has_pe_header(mem: &mut impl MemoryView, address: Address, size: umem) -> bool {
+    let mut header = [0u8; HEADER_SIZE];
+    let header = &mut header[..std::cmp::min(size, HEADER_SIZE as umem) as usize];
+
+    if header.len() < 0x40 || mem.read_raw_into(address, header).is_err() {
+        return false;
+    }
+
+    if &header[..2] != b"MZ" {
+        return false;
+    }
+
+    let mut e_lfanew = [0u8; 4];
+    e_lfanew.copy_from_slice(&header[0x3c..0x40]);
+    let e_lfanew = u32::from_le_bytes(e_lfanew) as usize;
+
+    header
+        .get(e_lfanew..e_lfanew.saturating_add(4))
+        .map(|sig| sig == b"PE\0\0")
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    fn rx() -> PageType {
+        PageType::default().write(false).noexec(false)
+    }
+
+    #[test]
+    fn flags_regions() {
+        let mut header = vec![0u8; 0x3000];
+        header[..2].copy_from_slice(b"MZ");
+        header[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        header[0x80..0x84].copy_from_slice(b"PE\0\0");
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &header);
+        let base = proc.info().address;
+
+        let module = ModuleInfo {
+            address: Address::null(),
+            parent_process: base,
+            base: base + 0x10000usize,
+            size: 0x10000,
+            name: "test.dll".into(),
+            path: "C:\\test.dll".into(),
+            arch: proc.info().proc_arch,
+        };
+
+        let regions = [
+            // manually mapped image
+            CTup3(base, 0x1000, PageType::default().write(false).noexec(true)),
+            CTup3(base + 0x1000usize, 0x1000, rx()),
+            // rwx memory inside of a module
+            CTup3(base + 0x10000usize, 0x1000, rx().write(true)),
+            // regular code of a module
+            CTup3(base + 0x11000usize, 0x1000, rx()),
+            // regular data
+            CTup3(
+                base + 0x20000usize,
+                0x1000,
+                PageType::default().write(true).noexec(true),
+            ),
+        ];
+
+        let found = find_injected_regions(&mut proc, &regions, &[module]);
+
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].address, base);
+        assert_eq!(found[0].flags, InjectionFlags::PE_HEADER);
+        assert_eq!(found[1].address, base + 0x1000usize);
+        assert_eq!(found[1].flags, InjectionFlags::UNBACKED_EXEC);
+        assert_eq!(found[2].address, base + 0x10000usize);
+        assert_eq!(found[2].flags, InjectionFlags::RWX);
+        assert!(found[2].module.is_some());
+    }
+}
@@ -0,0 +1,536 @@
+//! Kernel integrity scan orchestration.
+//!
+//! Kernel rootkits redirect control flow by patching tables of code pointers: the system
+//! service descriptor table, the IRP major function tables of drivers, the interrupt
+//! descriptor table and the various notification callback arrays. Every check has the same
+//! shape: read a table, decode its entries and verify that each target lies in the module it
+//! is supposed to.
+//!
+//! [`run_integrity_checks`] runs a set of [`IntegrityCheck`]s in one pass and collects their
+//! findings into an [`IntegrityReport`]. All checks share a single [`IntegrityContext`], which
+//! caches the kernel pages that have been read, so tables that live on the same pages (or
+//! pointers that are followed by multiple checks) are only read once.
+//!
+//! The tables themselves are OS specific and have to be located by the OS plugin, which can
+//! then describe them through [`PointerTable`]. Checks that do not fit a flat table (e.g.
+//! callback arrays holding referenced pointers) implement [`IntegrityCheck`] directly on top
+//! of the cached reads of the context.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::analysis::{
+//!     run_integrity_checks, EntryFormat, IntegrityCheck, IntegrityContext, PointerTable,
+//! };
+//! use memflow::dummy::DummyOs;
+//! use memflow::mem::MemoryView;
+//! use memflow::os::{ModuleInfo, Process};
+//! use memflow::types::size;
+//!
+//! let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! let base = proc.info().address;
+//!
+//! let kernel = ModuleInfo {
+//!     address: base,
+//!     parent_process: base,
+//!     base,
+//!     size: size::mb(1) as _,
+//!     name: "ntoskrnl.exe".into(),
+//!     path: "ntoskrnl.exe".into(),
+//!     arch: proc.info().proc_arch,
+//! };
+//!
+//! // a table with a single entry pointing into the kernel
+//! proc.write(base, &(base + 0x1000usize).to_umem()).unwrap();
+//!
+//! let mut ctx = IntegrityContext::new(proc, vec![kernel]);
+//! let mut table = PointerTable::new("irp", base, 1, EntryFormat::Pointer)
+//!     .expected_module("ntoskrnl.exe");
+//!
+//! let report = run_integrity_checks(&mut ctx, &mut [&mut table as &mut dyn IntegrityCheck<_>]);
+//! assert!(report.is_clean());
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::ModuleInfo;
+use crate::types::{imem, size, umem, Address};
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+/// Granularity of the read cache of [`IntegrityContext`].
+const CACHE_PAGE_SIZE: usize = size::kb(4);
+
+/// A single integrity check run by [`run_integrity_checks`].
+pub trait IntegrityCheck<M: MemoryView> {
+    /// Returns the name of this check, as shown in the report.
+    fn name(&self) -> &str;
+
+    /// Runs the check and returns everything it considers suspicious.
+    fn run(&mut self, ctx: &mut IntegrityContext<M>) -> Result<Vec<IntegrityFinding>>;
+}
+
+/// State shared between all checks of a scan.
+///
+/// The context holds the kernel memory, the kernel module list and a cache of all pages read
+/// through it. Pages that could not be read are remembered as well, so failing reads are not
+/// retried by every check.
+pub struct IntegrityContext<M> {
+    mem: M,
+    modules: Vec<ModuleInfo>,
+    arch_bits: u8,
+    pages: BTreeMap<Address, Option<Box<[u8]>>>,
+}
+
+impl<M: MemoryView> IntegrityContext<M> {
+    /// Creates a new context over the kernel memory `mem` with the loaded kernel `modules`.
+    pub fn new(mem: M, modules: Vec<ModuleInfo>) -> Self {
+        let arch_bits = mem.metadata().arch_bits;
+
+        Self {
+            mem,
+            modules,
+            arch_bits,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Returns all kernel modules.
+    pub fn modules(&self) -> &[ModuleInfo] {
+        &self.modules
+    }
+
+    /// Returns the module containing `address`.
+    pub fn module_by_address(&self, address: Address) -> Option<&ModuleInfo> {
+        self.modules
+            .iter()
+            .find(|m| m.base <= address && address < m.base + m.size)
+    }
+
+    /// Returns the module with the given name, compared case insensitively.
+    pub fn module_by_name(&self, name: &str) -> Option<&ModuleInfo> {
+        self.modules
+            .iter()
+            .find(|m| m.name.as_ref().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the pointer width of the kernel in bits.
+    pub fn arch_bits(&self) -> u8 {
+        self.arch_bits
+    }
+
+    /// Reads `out.len()` bytes at `address` through the page cache.
+    ///
+    /// Fails if any of the touched pages could not be read.
+    pub fn read_raw_into(&mut self, address: Address, out: &mut [u8]) -> Result<()> {
+        let mut done = 0;
+
+        while done < out.len() {
+            let cur = address + done;
+            let page = cur.as_page_aligned(CACHE_PAGE_SIZE);
+            let offset = (cur - page) as usize;
+            let len = std::cmp::min(CACHE_PAGE_SIZE - offset, out.len() - done);
+
+            let mem = &mut self.mem;
+            let data = self.pages.entry(page).or_insert_with(|| {
+                let mut buf = vec![0u8; CACHE_PAGE_SIZE].into_boxed_slice();
+                mem.read_raw_into(page, &mut buf).data().ok().map(|_| buf)
+            });
+
+            let data = data.as_ref().ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+                    .log_debug(format!("integrity check could not read {}", page))
+            })?;
+
+            out[done..done + len].copy_from_slice(&data[offset..offset + len]);
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_u16(&mut self, address: Address) -> Result<u16> {
+        let mut buf = [0u8; 2];
+        self.read_raw_into(address, &mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn read_u32(&mut self, address: Address) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        self.read_raw_into(address, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn read_u64(&mut self, address: Address) -> Result<u64> {
+        let mut buf = [0u8; 8];
+        self.read_raw_into(address, &mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    /// Reads a pointer sized to the architecture of the kernel.
+    pub fn read_addr(&mut self, address: Address) -> Result<Address> {
+        if self.arch_bits == 64 {
+            self.read_u64(address).map(Address::from)
+        } else {
+            self.read_u32(address).map(Address::from)
+        }
+    }
+
+    /// Drops all cached pages.
+    pub fn invalidate(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Returns the amount of pages currently cached, including failed ones.
+    pub fn cached_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Consumes the context and returns the underlying memory.
+    pub fn into_inner(self) -> M {
+        self.mem
+    }
+}
+
+/// Why an entry was reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntegrityIssue {
+    /// The entry points outside of every loaded module.
+    UnbackedTarget,
+    /// The entry points into a different module than expected.
+    UnexpectedModule,
+    /// The entry could not be read.
+    Unreadable,
+}
+
+/// A suspicious entry found by an [`IntegrityCheck`].
+#[derive(Clone, Debug)]
+pub struct IntegrityFinding {
+    pub issue: IntegrityIssue,
+    /// Name of the entry, e.g. `ssdt[42]`.
+    pub name: String,
+    /// Address of the entry.
+    pub address: Address,
+    /// The decoded target of the entry, if it could be read.
+    pub target: Option<Address>,
+    /// The module containing the target.
+    pub target_module: Option<ModuleInfo>,
+}
+
+/// A finding together with the check that reported it.
+#[derive(Clone, Debug)]
+pub struct IntegrityReportEntry {
+    /// Index of the check that reported this finding.
+    pub check: usize,
+    pub finding: IntegrityFinding,
+}
+
+/// The result of [`run_integrity_checks`].
+#[derive(Clone, Debug)]
+pub struct IntegrityReport {
+    /// Names of all checks, in the order they were passed in.
+    pub checks: Vec<String>,
+    /// Checks that failed to run.
+    pub errors: Vec<(usize, Error)>,
+    /// Findings of all checks, in the order they were reported.
+    pub findings: Vec<IntegrityReportEntry>,
+}
+
+impl IntegrityReport {
+    /// Returns `true` if every check ran and none of them reported anything.
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.findings.is_empty()
+    }
+
+    /// Returns the findings of the check with the given index.
+    pub fn findings_of(&self, check: usize) -> impl Iterator<Item = &IntegrityFinding> {
+        self.findings
+            .iter()
+            .filter(move |e| e.check == check)
+            .map(|e| &e.finding)
+    }
+}
+
+/// Runs all `checks` on the shared context and collects their findings.
+///
+/// A failing check does not abort the scan, its error is recorded in the report instead.
+pub fn run_integrity_checks<M: MemoryView>(
+    ctx: &mut IntegrityContext<M>,
+    checks: &mut [&mut dyn IntegrityCheck<M>],
+) -> IntegrityReport {
+    let mut errors = vec![];
+    let mut findings = vec![];
+
+    for (check, c) in checks.iter_mut().enumerate() {
+        match c.run(ctx) {
+            Ok(found) => findings.extend(
+                found
+                    .into_iter()
+                    .map(|finding| IntegrityReportEntry { check, finding }),
+            ),
+            Err(err) => errors.push((check, err)),
+        }
+    }
+
+    IntegrityReport {
+        checks: checks.iter().map(|c| c.name().to_string()).collect(),
+        errors,
+        findings,
+    }
+}
+
+/// How the entries of a [`PointerTable`] are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntryFormat {
+    /// Plain pointers of the kernel width (IRP tables, callback arrays).
+    Pointer,
+    /// Service table entries.
+    ///
+    /// On 64-bit kernels every entry is a 32-bit offset relative to the table, shifted left
+    /// by 4 bits. On 32-bit kernels entries are plain pointers.
+    ServiceTable,
+    /// Interrupt or trap gate descriptors with the handler offset split across the entry.
+    InterruptGate,
+}
+
+impl EntryFormat {
+    /// Returns the size of a single entry in bytes.
+    pub fn entry_size(self, arch_bits: u8) -> usize {
+        match (self, arch_bits) {
+            (EntryFormat::Pointer, 64) => 8,
+            (EntryFormat::Pointer, _) => 4,
+            (EntryFormat::ServiceTable, _) => 4,
+            (EntryFormat::InterruptGate, 64) => 16,
+            (EntryFormat::InterruptGate, _) => 8,
+        }
+    }
+
+    /// Decodes the target of an entry of the table located at `table`.
+    pub fn decode(self, arch_bits: u8, table: Address, entry: &[u8]) -> Address {
+        let u16_at = |off: usize| u16::from_le_bytes(entry[off..off + 2].try_into().unwrap());
+        let u32_at = |off: usize| u32::from_le_bytes(entry[off..off + 4].try_into().unwrap());
+
+        match (self, arch_bits) {
+            (EntryFormat::Pointer, 64) => {
+                Address::from(u64::from_le_bytes(entry[..8].try_into().unwrap()))
+            }
+            (EntryFormat::Pointer, _) => Address::from(u32_at(0)),
+            (EntryFormat::ServiceTable, 64) => {
+                let off = (u32_at(0) as i32 >> 4) as imem;
+                Address::from((table.to_umem() as imem).wrapping_add(off) as umem)
+            }
+            (EntryFormat::ServiceTable, _) => Address::from(u32_at(0)),
+            (EntryFormat::InterruptGate, 64) => Address::from(
+                u16_at(0) as u64 | (u16_at(6) as u64) << 16 | (u32_at(8) as u64) << 32,
+            ),
+            (EntryFormat::InterruptGate, _) => {
+                Address::from(u16_at(0) as u32 | (u16_at(6) as u32) << 16)
+            }
+        }
+    }
+}
+
+/// Checks a flat table of code pointers.
+///
+/// Every entry has to point into a loaded module, or into `expected_module` if one is set.
+/// Null entries are skipped.
+#[derive(Clone, Debug)]
+pub struct PointerTable {
+    name: String,
+    address: Address,
+    count: usize,
+    format: EntryFormat,
+    expected_module: Option<String>,
+}
+
+impl PointerTable {
+    /// Creates a check for `count` entries of the table located at `address`.
+    pub fn new(
+        name: impl Into<String>,
+        address: Address,
+        count: usize,
+        format: EntryFormat,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            address,
+            count,
+            format,
+            expected_module: None,
+        }
+    }
+
+    /// Requires all entries to point into the module with the given name.
+    pub fn expected_module(mut self, name: impl Into<String>) -> Self {
+        self.expected_module = Some(name.into());
+        self
+    }
+}
+
+impl<M: MemoryView> IntegrityCheck<M> for PointerTable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&mut self, ctx: &mut IntegrityContext<M>) -> Result<Vec<IntegrityFinding>> {
+        let arch_bits = ctx.arch_bits();
+        let entry_size = self.format.entry_size(arch_bits);
+
+        let expected = match &self.expected_module {
+            Some(name) => Some(ctx.module_by_name(name).cloned().ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound)
+                    .log_info(format!("expected module {} is not loaded", name))
+            })?),
+            None => None,
+        };
+
+        let mut out = vec![];
+        let mut entry = vec![0u8; entry_size];
+
+        for idx in 0..self.count {
+            let address = self.address + idx * entry_size;
+            let name = format!("{}[{}]", self.name, idx);
+
+            if ctx.read_raw_into(address, &mut entry).is_err() {
+                out.push(IntegrityFinding {
+                    issue: IntegrityIssue::Unreadable,
+                    name,
+                    address,
+                    target: None,
+                    target_module: None,
+                });
+                continue;
+            }
+
+            if entry.iter().all(|&b| b == 0) {
+                continue;
+            }
+
+            let target = self.format.decode(arch_bits, self.address, &entry);
+            let target_module = ctx.module_by_address(target).cloned();
+
+            let issue = match (&target_module, &expected) {
+                (None, _) => IntegrityIssue::UnbackedTarget,
+                (Some(m), Some(e)) if m.base != e.base => IntegrityIssue::UnexpectedModule,
+                _ => continue,
+            };
+
+            out.push(IntegrityFinding {
+                issue,
+                name,
+                address,
+                target: Some(target),
+                target_module,
+            });
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    fn module(base: Address, name: &str) -> ModuleInfo {
+        ModuleInfo {
+            address: base,
+            parent_process: Address::null(),
+            base,
+            size: 0x10000,
+            name: name.into(),
+            path: name.into(),
+            arch: crate::architecture::ArchitectureIdent::X86(64, false),
+        }
+    }
+
+    fn context() -> (IntegrityContext<impl MemoryView>, Address) {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // pointer table at 0x100: kernel, driver, unbacked, null
+        let pointers = [
+            (base + 0x1000usize).to_umem() as u64,
+            (base + 0x20000usize).to_umem() as u64,
+            0xdead_0000,
+            0,
+        ];
+        proc.write(base + 0x100usize, &pointers).unwrap();
+
+        // service table at 0x200: kernel + 0x1000, unbacked
+        let services: [i32; 2] = [(0x1000 - 0x200) << 4, (0x80000 - 0x200) << 4];
+        proc.write(base + 0x200usize, &services).unwrap();
+
+        // interrupt gate at 0x300 pointing to kernel + 0x1234
+        let handler = (base + 0x1234usize).to_umem() as u64;
+        let mut gate = [0u8; 16];
+        gate[..2].copy_from_slice(&(handler as u16).to_le_bytes());
+        gate[6..8].copy_from_slice(&((handler >> 16) as u16).to_le_bytes());
+        gate[8..12].copy_from_slice(&((handler >> 32) as u32).to_le_bytes());
+        proc.write(base + 0x300usize, &gate).unwrap();
+
+        let modules = vec![
+            module(base, "ntoskrnl.exe"),
+            module(base + 0x20000usize, "driver.sys"),
+        ];
+
+        (IntegrityContext::new(proc, modules), base)
+    }
+
+    #[test]
+    fn pointer_tables() {
+        let (mut ctx, base) = context();
+
+        let mut irp = PointerTable::new("irp", base + 0x100usize, 4, EntryFormat::Pointer)
+            .expected_module("ntoskrnl.exe");
+        let mut ssdt = PointerTable::new("ssdt", base + 0x200usize, 2, EntryFormat::ServiceTable);
+        let mut idt = PointerTable::new("idt", base + 0x300usize, 1, EntryFormat::InterruptGate)
+            .expected_module("ntoskrnl.exe");
+
+        let report = run_integrity_checks(
+            &mut ctx,
+            &mut [&mut irp as &mut dyn IntegrityCheck<_>, &mut ssdt, &mut idt],
+        );
+
+        assert_eq!(report.checks, vec!["irp", "ssdt", "idt"]);
+        assert!(report.errors.is_empty());
+
+        let irp = report.findings_of(0).collect::<Vec<_>>();
+        assert_eq!(irp.len(), 2);
+        assert_eq!(irp[0].name, "irp[1]");
+        assert_eq!(irp[0].issue, IntegrityIssue::UnexpectedModule);
+        assert_eq!(irp[1].name, "irp[2]");
+        assert_eq!(irp[1].issue, IntegrityIssue::UnbackedTarget);
+        assert_eq!(irp[1].target, Some(Address::from(0xdead_0000u64)));
+
+        let ssdt = report.findings_of(1).collect::<Vec<_>>();
+        assert_eq!(ssdt.len(), 1);
+        assert_eq!(ssdt[0].name, "ssdt[1]");
+        assert_eq!(ssdt[0].target, Some(base + 0x80000usize));
+
+        assert_eq!(report.findings_of(2).count(), 0);
+
+        // all tables live on the same page
+        assert_eq!(ctx.cached_pages(), 1);
+    }
+
+    #[test]
+    fn missing_module_is_an_error() {
+        let (mut ctx, base) = context();
+
+        let mut table =
+            PointerTable::new("irp", base, 1, EntryFormat::Pointer).expected_module("missing.sys");
+
+        let report =
+            run_integrity_checks(&mut ctx, &mut [&mut table as &mut dyn IntegrityCheck<_>]);
+
+        assert!(!report.is_clean());
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.findings.is_empty());
+    }
+}
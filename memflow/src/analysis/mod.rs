@@ -0,0 +1,29 @@
+//! Analysis helpers built on top of the OS abstraction.
+//!
+//! The functions in this module do not depend on a specific OS plugin. Wherever an analysis
+//! requires OS specific knowledge (e.g. scanning kernel pools) it is split into small sources
+//! that OS plugins can provide themselves.
+
+pub mod hooks;
+pub mod injected;
+pub mod integrity;
+pub mod processes;
+pub mod timeline;
+
+pub use hooks::{
+    find_eat_hooks, find_hooks, find_iat_hooks, find_inline_hooks, import_slots, Hook,
+    HookBaseline, HookKind, ImageBaseline, ImportSlot, ProcessBaseline,
+};
+pub use injected::{find_injected_memory, find_injected_regions, InjectedRegion, InjectionFlags};
+pub use integrity::{
+    run_integrity_checks, EntryFormat, IntegrityCheck, IntegrityContext, IntegrityFinding,
+    IntegrityIssue, IntegrityReport, IntegrityReportEntry, PointerTable,
+};
+pub use processes::{
+    cross_view_all, cross_view_processes, CrossViewEntry, CrossViewReport, FnProcessSource,
+    ListWalk, ProcessSource, ProcessSources,
+};
+pub use timeline::{
+    build_timeline, build_timeline_all, FnTimelineSource, Timeline, TimelineEvent, TimelineKind,
+    TimelineSource, TimelineSources,
+};
@@ -0,0 +1,291 @@
+//! Cross-view detection of hidden processes.
+//!
+//! Rootkits commonly hide processes by unlinking them from the list the OS uses to enumerate
+//! processes. The process objects themselves are still alive and can be found through other,
+//! independent views of the system, for example by scanning kernel pools for process objects,
+//! by following the back-references of threads or by attributing physical pages to their
+//! owning processes.
+//!
+//! [`cross_view_processes`] collects process addresses from multiple [`ProcessSource`]s and
+//! reports every process that is not seen by all of them. The only source that works for
+//! every OS is [`ListWalk`], the regular enumeration of [`OsInner::process_address_list`].
+//! OS specific sources are provided by the OS plugins through [`ProcessSources`] and can be
+//! compared all at once with [`cross_view_all`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::analysis::{cross_view_processes, FnProcessSource, ListWalk, ProcessSource};
+//! use memflow::dummy::{DummyMemory, DummyOs};
+//! use memflow::os::OsInner;
+//! use memflow::types::size;
+//!
+//! let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+//! os.alloc_process(size::mb(1), &[]);
+//!
+//! // a stand-in for an OS specific scanner
+//! let mut scan = FnProcessSource::new("scan", |os: &mut DummyOs| os.process_address_list());
+//!
+//! let report = cross_view_processes(
+//!     &mut os,
+//!     &mut [&mut ListWalk as &mut dyn ProcessSource<_>, &mut scan],
+//! );
+//!
+//! assert_eq!(report.discrepancies().count(), 0);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, Result};
+use crate::os::{Os, OsInner, ProcessInfo};
+use crate::types::Address;
+
+use std::collections::BTreeMap;
+
+/// An independent view of the processes running on a system.
+pub trait ProcessSource<T: Os> {
+    /// Returns the name of this source, as shown in the report.
+    fn name(&self) -> &str;
+
+    /// Returns the addresses of all processes found by this source.
+    ///
+    /// The addresses have to be comparable to the ones returned by
+    /// [`OsInner::process_address_list`](crate::os::OsInner::process_address_list).
+    fn process_addresses(&mut self, os: &mut T) -> Result<Vec<Address>>;
+}
+
+/// Provides the OS specific process sources of an OS implementation.
+///
+/// OS plugins implement this trait to expose the scans they support (e.g. pool scanning).
+/// The default implementation only returns [`ListWalk`].
+pub trait ProcessSources: Os + Sized {
+    /// Returns all process sources supported by this OS.
+    fn process_sources(&self) -> Vec<Box<dyn ProcessSource<Self>>> {
+        vec![Box::new(ListWalk)]
+    }
+}
+
+/// The process list as enumerated by the OS itself.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ListWalk;
+
+impl<T: Os> ProcessSource<T> for ListWalk {
+    fn name(&self) -> &str {
+        "list"
+    }
+
+    fn process_addresses(&mut self, os: &mut T) -> Result<Vec<Address>> {
+        os.process_address_list()
+    }
+}
+
+/// A process source backed by a closure.
+pub struct FnProcessSource<F> {
+    name: String,
+    func: F,
+}
+
+impl<F> FnProcessSource<F> {
+    pub fn new(name: impl Into<String>, func: F) -> Self {
+        Self {
+            name: name.into(),
+            func,
+        }
+    }
+}
+
+impl<T: Os, F: FnMut(&mut T) -> Result<Vec<Address>>> ProcessSource<T> for FnProcessSource<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process_addresses(&mut self, os: &mut T) -> Result<Vec<Address>> {
+        (self.func)(os)
+    }
+}
+
+/// A single process found by at least one source.
+#[derive(Clone, Debug)]
+pub struct CrossViewEntry {
+    /// The address of the process.
+    pub address: Address,
+    /// Indices of all sources that found this process.
+    pub seen_by: Vec<usize>,
+    /// Process information, if the OS is able to parse the process.
+    pub info: Option<ProcessInfo>,
+}
+
+/// The result of [`cross_view_processes`].
+#[derive(Clone, Debug)]
+pub struct CrossViewReport {
+    /// Names of all sources, in the order they were passed in.
+    pub sources: Vec<String>,
+    /// Sources that failed to enumerate processes, they are ignored for the comparison.
+    pub errors: Vec<(usize, Error)>,
+    /// All processes found by any of the sources, sorted by address.
+    pub processes: Vec<CrossViewEntry>,
+}
+
+impl CrossViewReport {
+    /// Returns `true` if the source with the given index could enumerate processes.
+    pub fn source_succeeded(&self, source: usize) -> bool {
+        source < self.sources.len() && self.errors.iter().all(|(idx, _)| *idx != source)
+    }
+
+    /// Returns all processes that were not found by every successful source.
+    pub fn discrepancies(&self) -> impl Iterator<Item = &CrossViewEntry> {
+        let succeeded = (0..self.sources.len())
+            .filter(|&idx| self.source_succeeded(idx))
+            .count();
+
+        self.processes
+            .iter()
+            .filter(move |p| p.seen_by.len() < succeeded)
+    }
+
+    /// Returns all processes missing from the source with the given index, but found by others.
+    ///
+    /// With the [`ListWalk`] passed as first source, `hidden_from(0)` returns the processes
+    /// that are hidden from the regular process list.
+    pub fn hidden_from(&self, source: usize) -> impl Iterator<Item = &CrossViewEntry> {
+        let succeeded = self.source_succeeded(source);
+
+        self.processes
+            .iter()
+            .filter(move |p| succeeded && !p.seen_by.contains(&source))
+    }
+}
+
+/// Enumerates processes through all sources of the OS and compares the results.
+///
+/// See [`cross_view_processes`] for details.
+pub fn cross_view_all<T: ProcessSources>(os: &mut T) -> CrossViewReport {
+    let mut sources = os.process_sources();
+    let mut sources = sources
+        .iter_mut()
+        .map(|s| &mut **s as &mut dyn ProcessSource<T>)
+        .collect::<Vec<_>>();
+
+    cross_view_processes(os, &mut sources)
+}
+
+/// Enumerates processes through all `sources` and compares the results.
+///
+/// Process information is parsed for every found address through
+/// [`OsInner::process_info_by_address`](crate::os::OsInner::process_info_by_address).
+pub fn cross_view_processes<T: Os>(
+    os: &mut T,
+    sources: &mut [&mut dyn ProcessSource<T>],
+) -> CrossViewReport {
+    let mut errors = vec![];
+    let mut found = BTreeMap::<Address, Vec<usize>>::new();
+
+    for (idx, source) in sources.iter_mut().enumerate() {
+        match source.process_addresses(os) {
+            Ok(addresses) => {
+                for address in addresses {
+                    let seen_by = found.entry(address).or_default();
+                    if !seen_by.contains(&idx) {
+                        seen_by.push(idx);
+                    }
+                }
+            }
+            Err(err) => errors.push((idx, err)),
+        }
+    }
+
+    let processes = found
+        .into_iter()
+        .map(|(address, seen_by)| CrossViewEntry {
+            address,
+            seen_by,
+            info: os.process_info_by_address(address).ok(),
+        })
+        .collect();
+
+    CrossViewReport {
+        sources: sources.iter().map(|s| s.name().to_string()).collect(),
+        errors,
+        processes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::error::{ErrorKind, ErrorOrigin};
+    use crate::types::size;
+
+    fn dummy_os() -> (DummyOs, Vec<Address>) {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(32)));
+        for _ in 0..3 {
+            os.alloc_process(size::mb(1), &[]);
+        }
+        let list = os.process_address_list().unwrap();
+        (os, list)
+    }
+
+    #[test]
+    fn hidden_process() {
+        let (mut os, list) = dummy_os();
+        let hidden = Address::from(0xdead_0000u64);
+
+        let mut scan = FnProcessSource::new("scan", move |os: &mut DummyOs| {
+            let mut list = os.process_address_list()?;
+            list.push(hidden);
+            Ok(list)
+        });
+
+        let report = cross_view_processes(
+            &mut os,
+            &mut [&mut ListWalk as &mut dyn ProcessSource<_>, &mut scan],
+        );
+
+        assert_eq!(report.sources, vec!["list", "scan"]);
+        assert_eq!(report.processes.len(), list.len() + 1);
+
+        let hidden_from_list = report.hidden_from(0).collect::<Vec<_>>();
+        assert_eq!(hidden_from_list.len(), 1);
+        assert_eq!(hidden_from_list[0].address, hidden);
+        assert!(hidden_from_list[0].info.is_none());
+
+        assert_eq!(report.discrepancies().count(), 1);
+        assert!(report
+            .processes
+            .iter()
+            .filter(|p| p.address != hidden)
+            .all(|p| p.info.is_some()));
+    }
+
+    #[test]
+    fn all_sources() {
+        let (mut os, list) = dummy_os();
+
+        let report = cross_view_all(&mut os);
+
+        assert_eq!(report.sources, vec!["list"]);
+        assert_eq!(report.processes.len(), list.len());
+        assert_eq!(report.discrepancies().count(), 0);
+    }
+
+    #[test]
+    fn failed_source_is_ignored() {
+        let (mut os, list) = dummy_os();
+
+        let mut broken = FnProcessSource::new("broken", |_: &mut DummyOs| {
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+        });
+
+        let report = cross_view_processes(
+            &mut os,
+            &mut [&mut ListWalk as &mut dyn ProcessSource<_>, &mut broken],
+        );
+
+        assert_eq!(report.processes.len(), list.len());
+        assert_eq!(report.errors.len(), 1);
+        assert!(!report.source_succeeded(1));
+        assert_eq!(report.discrepancies().count(), 0);
+        assert_eq!(report.hidden_from(1).count(), 0);
+    }
+}
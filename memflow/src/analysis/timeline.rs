@@ -0,0 +1,362 @@
+//! Timeline extraction from timestamps kept in memory.
+//!
+//! Kernels record when processes and threads were created, when modules were loaded and when
+//! network connections were opened. [`build_timeline`] collects these events from multiple
+//! [`TimelineSource`]s and merges them into a single, sorted [`Timeline`], which can be
+//! exported as JSON or CSV.
+//!
+//! The OS traits do not expose timestamps, so all sources are OS specific and provided by the
+//! OS plugins through [`TimelineSources`]. All timestamps are converted to nanoseconds since
+//! the unix epoch by the sources.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::analysis::{
+//!     build_timeline, FnTimelineSource, TimelineEvent, TimelineKind, TimelineSource,
+//! };
+//! use memflow::dummy::{DummyMemory, DummyOs};
+//! use memflow::os::OsInner;
+//! use memflow::types::size;
+//!
+//! let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+//! os.alloc_process(size::mb(1), &[]);
+//!
+//! // a stand-in for an OS specific source
+//! let mut processes = FnTimelineSource::new("processes", |os: &mut DummyOs| {
+//!     Ok(os
+//!         .process_info_list()?
+//!         .into_iter()
+//!         .map(|p| TimelineEvent::new(0, TimelineKind::ProcessCreated, "process").pid(p.pid))
+//!         .collect())
+//! });
+//!
+//! let timeline = build_timeline(&mut os, &mut [&mut processes as &mut dyn TimelineSource<_>]);
+//! assert_eq!(timeline.events.len(), 1);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, Result};
+use crate::os::{Os, Pid};
+use crate::types::Address;
+#[cfg(feature = "std")]
+use crate::util::json;
+
+/// The kind of a [`TimelineEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TimelineKind {
+    ProcessCreated,
+    ProcessExited,
+    ThreadCreated,
+    ThreadExited,
+    ModuleLoaded,
+    ConnectionCreated,
+    /// Any other OS specific event.
+    Other,
+}
+
+impl TimelineKind {
+    /// Returns the name used for this kind in exports.
+    pub fn name(self) -> &'static str {
+        match self {
+            TimelineKind::ProcessCreated => "process_created",
+            TimelineKind::ProcessExited => "process_exited",
+            TimelineKind::ThreadCreated => "thread_created",
+            TimelineKind::ThreadExited => "thread_exited",
+            TimelineKind::ModuleLoaded => "module_loaded",
+            TimelineKind::ConnectionCreated => "connection_created",
+            TimelineKind::Other => "other",
+        }
+    }
+}
+
+/// A single event of a [`Timeline`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimelineEvent {
+    /// Nanoseconds since the unix epoch.
+    pub timestamp: u64,
+    pub kind: TimelineKind,
+    /// The process the event belongs to.
+    pub pid: Option<Pid>,
+    /// Address of the object the timestamp was read from.
+    pub address: Option<Address>,
+    /// Human readable description, e.g. the process name or the remote endpoint.
+    pub description: String,
+    /// Name of the source that found this event, set by [`build_timeline`].
+    pub source: String,
+}
+
+impl TimelineEvent {
+    pub fn new(timestamp: u64, kind: TimelineKind, description: impl Into<String>) -> Self {
+        Self {
+            timestamp,
+            kind,
+            pid: None,
+            address: None,
+            description: description.into(),
+            source: String::new(),
+        }
+    }
+
+    pub fn pid(mut self, pid: Pid) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    pub fn address(mut self, address: Address) -> Self {
+        self.address = Some(address);
+        self
+    }
+}
+
+/// A source of timestamped events.
+pub trait TimelineSource<T: Os> {
+    /// Returns the name of this source, as shown in the timeline.
+    fn name(&self) -> &str;
+
+    /// Returns all events found by this source, in any order.
+    fn events(&mut self, os: &mut T) -> Result<Vec<TimelineEvent>>;
+}
+
+/// Provides the timeline sources of an OS implementation.
+///
+/// The default implementation provides no sources at all.
+pub trait TimelineSources: Os + Sized {
+    /// Returns all timeline sources supported by this OS.
+    fn timeline_sources(&self) -> Vec<Box<dyn TimelineSource<Self>>> {
+        vec![]
+    }
+}
+
+/// A timeline source backed by a closure.
+pub struct FnTimelineSource<F> {
+    name: String,
+    func: F,
+}
+
+impl<F> FnTimelineSource<F> {
+    pub fn new(name: impl Into<String>, func: F) -> Self {
+        Self {
+            name: name.into(),
+            func,
+        }
+    }
+}
+
+impl<T: Os, F: FnMut(&mut T) -> Result<Vec<TimelineEvent>>> TimelineSource<T>
+    for FnTimelineSource<F>
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn events(&mut self, os: &mut T) -> Result<Vec<TimelineEvent>> {
+        (self.func)(os)
+    }
+}
+
+/// Events of all sources, sorted by timestamp.
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    pub events: Vec<TimelineEvent>,
+    /// Sources that failed, by name.
+    pub errors: Vec<(String, Error)>,
+}
+
+impl Timeline {
+    /// Returns all events in the given time range.
+    pub fn between(&self, start: u64, end: u64) -> impl Iterator<Item = &TimelineEvent> {
+        let first = self.events.partition_point(|e| e.timestamp < start);
+        self.events[first..]
+            .iter()
+            .take_while(move |e| e.timestamp < end)
+    }
+
+    /// Returns all events belonging to a process.
+    pub fn process(&self, pid: Pid) -> impl Iterator<Item = &TimelineEvent> {
+        self.events.iter().filter(move |e| e.pid == Some(pid))
+    }
+
+    /// Writes the timeline as a json array.
+    #[cfg(feature = "std")]
+    pub fn write_json(&self, out: &mut (impl std::io::Write + ?Sized)) -> Result<()> {
+        self.write_json_inner(out).map_err(export_error)
+    }
+
+    #[cfg(feature = "std")]
+    fn write_json_inner(&self, out: &mut (impl std::io::Write + ?Sized)) -> std::io::Result<()> {
+        writeln!(out, "[")?;
+        for (i, e) in self.events.iter().enumerate() {
+            write!(
+                out,
+                "  {{\"timestamp\": {}, \"kind\": \"{}\", \"pid\": {}, \"address\": {}, \"description\": \"{}\", \"source\": \"{}\"}}",
+                e.timestamp,
+                e.kind.name(),
+                e.pid.map(|p| p.to_string()).unwrap_or_else(|| "null".into()),
+                e.address
+                    .map(|a| format!("\"0x{:x}\"", a))
+                    .unwrap_or_else(|| "null".into()),
+                json::escape(&e.description),
+                json::escape(&e.source),
+            )?;
+            writeln!(out, "{}", if i + 1 < self.events.len() { "," } else { "" })?;
+        }
+        writeln!(out, "]")
+    }
+
+    /// Writes the timeline as csv with a header line.
+    #[cfg(feature = "std")]
+    pub fn write_csv(&self, out: &mut (impl std::io::Write + ?Sized)) -> Result<()> {
+        self.write_csv_inner(out).map_err(export_error)
+    }
+
+    #[cfg(feature = "std")]
+    fn write_csv_inner(&self, out: &mut (impl std::io::Write + ?Sized)) -> std::io::Result<()> {
+        writeln!(out, "timestamp,kind,pid,address,description,source")?;
+        for e in self.events.iter() {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                e.timestamp,
+                e.kind.name(),
+                e.pid.map(|p| p.to_string()).unwrap_or_default(),
+                e.address.map(|a| format!("0x{:x}", a)).unwrap_or_default(),
+                csv_escape(&e.description),
+                csv_escape(&e.source),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the events of all sources of the OS into a timeline.
+pub fn build_timeline_all<T: TimelineSources>(os: &mut T) -> Timeline {
+    let mut sources = os.timeline_sources();
+    let mut sources = sources
+        .iter_mut()
+        .map(|s| &mut **s as &mut dyn TimelineSource<T>)
+        .collect::<Vec<_>>();
+
+    build_timeline(os, &mut sources)
+}
+
+/// Collects the events of all `sources` into a timeline.
+///
+/// Events with equal timestamps keep the order of the sources they were found by.
+pub fn build_timeline<T: Os>(os: &mut T, sources: &mut [&mut dyn TimelineSource<T>]) -> Timeline {
+    let mut timeline = Timeline::default();
+
+    for source in sources.iter_mut() {
+        match source.events(os) {
+            Ok(events) => timeline
+                .events
+                .extend(events.into_iter().map(|e| TimelineEvent {
+                    source: source.name().to_string(),
+                    ..e
+                })),
+            Err(err) => timeline.errors.push((source.name().to_string(), err)),
+        }
+    }
+
+    timeline.events.sort_by_key(|e| e.timestamp);
+    timeline
+}
+
+#[cfg(feature = "std")]
+fn export_error(err: std::io::Error) -> Error {
+    use crate::error::{ErrorKind, ErrorOrigin};
+
+    Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile)
+        .log_error(format!("unable to write the timeline: {}", err))
+}
+
+#[cfg(feature = "std")]
+fn csv_escape(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::error::{ErrorKind, ErrorOrigin};
+    use crate::types::size;
+
+    fn timeline() -> Timeline {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+
+        let mut processes = FnTimelineSource::new("processes", |_: &mut DummyOs| {
+            Ok(vec![
+                TimelineEvent::new(30, TimelineKind::ProcessCreated, "b.exe").pid(2),
+                TimelineEvent::new(10, TimelineKind::ProcessCreated, "a.exe").pid(1),
+            ])
+        });
+        let mut network = FnTimelineSource::new("network", |_: &mut DummyOs| {
+            Ok(vec![TimelineEvent::new(
+                20,
+                TimelineKind::ConnectionCreated,
+                "10.0.0.1:443, \"tls\"",
+            )
+            .pid(1)
+            .address(Address::from(0x1000u64))])
+        });
+        let mut broken = FnTimelineSource::new("broken", |_: &mut DummyOs| {
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+        });
+
+        build_timeline(
+            &mut os,
+            &mut [
+                &mut processes as &mut dyn TimelineSource<_>,
+                &mut network,
+                &mut broken,
+            ],
+        )
+    }
+
+    #[test]
+    fn merges_sources() {
+        let timeline = timeline();
+
+        assert_eq!(
+            timeline
+                .events
+                .iter()
+                .map(|e| e.timestamp)
+                .collect::<Vec<_>>(),
+            vec![10, 20, 30]
+        );
+        assert_eq!(timeline.events[1].source, "network");
+        assert_eq!(timeline.errors.len(), 1);
+        assert_eq!(timeline.errors[0].0, "broken");
+
+        assert_eq!(timeline.process(1).count(), 2);
+        assert_eq!(timeline.between(15, 30).count(), 1);
+    }
+
+    #[test]
+    fn export() {
+        let timeline = timeline();
+
+        let mut csv = vec![];
+        timeline.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(2),
+            Some("20,connection_created,1,0x1000,\"10.0.0.1:443, \"\"tls\"\"\",network")
+        );
+
+        let mut json = vec![];
+        timeline.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(
+            "{\"timestamp\": 20, \"kind\": \"connection_created\", \"pid\": 1, \"address\": \"0x1000\", \"description\": \"10.0.0.1:443, \\\"tls\\\"\", \"source\": \"network\"},"
+        ));
+        assert!(json.contains("\"pid\": 2, \"address\": null"));
+    }
+}
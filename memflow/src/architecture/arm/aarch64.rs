@@ -20,6 +20,7 @@ pub(super) static ARCH_SPEC: ArmArchitecture = ArmArchitecture {
         writeable_bit: |a, _| a.bit_at(10),
         nx_bit: |a, _| a.bit_at(54),
         large_page_bit: |a| !a.bit_at(1),
+        pte_fixup: |a| a,
     }
     .into_spec(),
 };
@@ -71,7 +71,8 @@ pub struct ArmPageTableBase(Address, Address);
 impl MmuTranslationBase for ArmPageTableBase {
     fn get_pt_by_virt_addr(&self, addr: Address) -> Address {
         //TODO: handle for Arm 32
-        if (addr.to_umem().to_be() & 1) == 1 {
+        // bit 55 selects between the lower (TTBR0) and the upper (TTBR1) address range
+        if addr.bit_at(55) {
             self.1
         } else {
             self.0
@@ -165,3 +166,35 @@ pub fn new_translator_nonsplit(
 pub fn is_arm_arch(arch: ArchitectureObj) -> bool {
     underlying_arch(arch).is_some()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_table_by_address_range() {
+        let base = ArmPageTableBase(Address::from(0x1000u64), Address::from(0x2000u64));
+
+        // lower half, translated through TTBR0
+        assert_eq!(base.get_pt_by_virt_addr(Address::NULL), base.0);
+        assert_eq!(
+            base.get_pt_by_virt_addr(Address::from(0x0000_7fff_ffff_f000u64)),
+            base.0
+        );
+        // tagged addresses of the lower half keep bit 55 clear
+        assert_eq!(
+            base.get_pt_by_virt_addr(Address::from(0x0100_0000_0000_1000u64)),
+            base.0
+        );
+
+        // upper half, translated through TTBR1
+        assert_eq!(
+            base.get_pt_by_virt_addr(Address::from(0xffff_8000_0000_0000u64)),
+            base.1
+        );
+        assert_eq!(
+            base.get_pt_by_virt_addr(Address::from(0x0080_0000_0000_0000u64)),
+            base.1
+        );
+    }
+}
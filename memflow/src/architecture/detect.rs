@@ -0,0 +1,321 @@
+/*!
+Heuristic architecture detection.
+
+[`detect`] scans the physical memory of an unknown target for structures that give away the
+architecture:
+
+- x86 page tables that reference themselves (Windows), or that map the kernel half of the
+  address space (Linux). These are also reported as candidate DTBs.
+- x64 interrupt descriptor tables.
+- Headers of Linux kernel images on AArch64 and RISC-V.
+
+The results are guesses, callers should verify them (for example by translating a well known
+kernel address) before relying on them.
+*/
+
+use std::prelude::v1::*;
+
+use super::ArchitectureIdent;
+use crate::error::{PartialResultExt, Result};
+use crate::mem::memory_view::AccessReport;
+use crate::mem::{MemoryView, PhysicalMemory};
+use crate::types::{size, umem, Address};
+
+use std::convert::TryInto;
+
+const PAGE_SIZE: usize = size::kb(4);
+const CHUNK_SIZE: usize = size::mb(2);
+
+const SELF_REFERENCE_SCORE: u32 = 4;
+const PAGE_TABLE_SCORE: u32 = 1;
+const IDT_SCORE: u32 = 8;
+const KERNEL_IMAGE_SCORE: u32 = 8;
+
+/// A possible architecture of the target, along with the page tables found for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchCandidate {
+    pub arch: ArchitectureIdent,
+    /// Sum of the scores of all structures found for the architecture
+    pub score: u32,
+    /// Candidate page table roots, self-referencing tables first
+    pub dtbs: Vec<Address>,
+}
+
+/// Scans physical memory and returns the candidate architectures, the most likely first.
+///
+/// Pages that can not be read entirely are skipped, the remaining pages of a partially read chunk
+/// are still scanned. An empty list is returned if nothing was found.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::architecture;
+/// use memflow::dummy::DummyMemory;
+/// use memflow::types::size;
+///
+/// let mut mem = DummyMemory::new(size::mb(4));
+/// let candidates = architecture::detect(&mut mem).unwrap();
+/// assert!(candidates.is_empty());
+/// ```
+pub fn detect<T: PhysicalMemory>(mem: &mut T) -> Result<Vec<ArchCandidate>> {
+    let max_address = mem.metadata().max_address;
+    let mut votes: Vec<Votes> = vec![];
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut view = mem.phys_view();
+
+    let mut base = Address::null();
+    while base <= max_address {
+        let len = std::cmp::min(CHUNK_SIZE as umem, (max_address - base) as umem + 1) as usize;
+        let chunk = &mut buf[..len];
+
+        if let Ok(report) = view.read_raw_into_report(base, chunk).data_part() {
+            for (i, page) in chunk.chunks_exact(PAGE_SIZE).enumerate() {
+                if page_valid(&report, i * PAGE_SIZE) {
+                    scan_page(page, base + i * PAGE_SIZE, max_address, &mut votes);
+                }
+            }
+        }
+
+        base += CHUNK_SIZE;
+    }
+
+    let mut candidates = votes
+        .into_iter()
+        .map(|mut votes| {
+            votes.self_refs.append(&mut votes.tables);
+            ArchCandidate {
+                arch: votes.arch,
+                score: votes.score,
+                dtbs: votes.self_refs,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| b.score.cmp(&a.score));
+    Ok(candidates)
+}
+
+/// Checks whether the whole page at `offset` of a chunk was read.
+fn page_valid(report: &AccessReport, offset: usize) -> bool {
+    report
+        .valid_ranges()
+        .iter()
+        .any(|r| r.start <= offset && offset + PAGE_SIZE <= r.end)
+}
+
+/// Structures found for a single architecture.
+struct Votes {
+    arch: ArchitectureIdent,
+    score: u32,
+    self_refs: Vec<Address>,
+    tables: Vec<Address>,
+}
+
+fn votes_for(votes: &mut Vec<Votes>, arch: ArchitectureIdent) -> &mut Votes {
+    let idx = match votes.iter().position(|v| v.arch == arch) {
+        Some(idx) => idx,
+        None => {
+            votes.push(Votes {
+                arch,
+                score: 0,
+                self_refs: vec![],
+                tables: vec![],
+            });
+            votes.len() - 1
+        }
+    };
+    &mut votes[idx]
+}
+
+fn scan_page(page: &[u8], addr: Address, max_address: Address, votes: &mut Vec<Votes>) {
+    if let Some(self_ref) = x64_page_table(page, addr, max_address) {
+        let votes = votes_for(votes, ArchitectureIdent::X86(64, false));
+        if self_ref {
+            votes.score += SELF_REFERENCE_SCORE;
+            votes.self_refs.push(addr);
+        } else {
+            votes.score += PAGE_TABLE_SCORE;
+            votes.tables.push(addr);
+        }
+    }
+
+    if x86_page_directory(page, addr, max_address) {
+        let votes = votes_for(votes, ArchitectureIdent::X86(32, false));
+        votes.score += SELF_REFERENCE_SCORE;
+        votes.self_refs.push(addr);
+    }
+
+    if x64_idt(page) {
+        votes_for(votes, ArchitectureIdent::X86(64, false)).score += IDT_SCORE;
+    }
+
+    if let Some(arch) = kernel_image(page) {
+        votes_for(votes, arch).score += KERNEL_IMAGE_SCORE;
+    }
+}
+
+pub(crate) fn u64_at(page: &[u8], idx: usize) -> u64 {
+    u64::from_le_bytes(page[idx * 8..idx * 8 + 8].try_into().unwrap())
+}
+
+pub(crate) fn u32_at(page: &[u8], idx: usize) -> u32 {
+    u32::from_le_bytes(page[idx * 4..idx * 4 + 4].try_into().unwrap())
+}
+
+/// Checks whether the page is a x64 PML4, returns whether it references itself.
+///
+/// All non-zero entries have to be present and point into physical memory, and at least one
+/// entry of the kernel half has to be set.
+pub(crate) fn x64_page_table(page: &[u8], addr: Address, max_address: Address) -> Option<bool> {
+    const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+    let mut kernel_entries = 0;
+    let mut self_ref = false;
+
+    for idx in 0..512 {
+        let entry = u64_at(page, idx);
+        if entry == 0 {
+            continue;
+        }
+
+        // not present, or a large page bit which is reserved on this level
+        if entry & 1 == 0 || entry & (1 << 7) != 0 {
+            return None;
+        }
+
+        let target = Address::from(entry & ADDRESS_MASK);
+        if target > max_address {
+            return None;
+        }
+
+        self_ref |= target == addr;
+        if idx >= 256 {
+            kernel_entries += 1;
+        }
+    }
+
+    if kernel_entries > 0 {
+        Some(self_ref)
+    } else {
+        None
+    }
+}
+
+/// Checks whether the page is a non-PAE page directory mapping itself at `0xc030_0000`, as done by
+/// 32-bit Windows.
+pub(crate) fn x86_page_directory(page: &[u8], addr: Address, max_address: Address) -> bool {
+    let self_entry = u32_at(page, 0x300);
+    if self_entry & 1 == 0 || Address::from(self_entry & !0xfff) != addr {
+        return false;
+    }
+
+    (0..1024)
+        .map(|idx| u32_at(page, idx))
+        .all(|entry| entry == 0 || (entry & 1 != 0 && Address::from(entry & !0xfff) <= max_address))
+}
+
+/// Checks for a page aligned x64 IDT, with the exception vectors set up as present interrupt or
+/// trap gates into the kernel half of the address space.
+fn x64_idt(page: &[u8]) -> bool {
+    let gate = |idx: usize| (u64_at(page, idx * 2), u64_at(page, idx * 2 + 1));
+
+    let (first_lo, first_hi) = gate(0);
+    let selector = (first_lo >> 16) & 0xffff;
+
+    // the upper half of the second quadword is reserved, the lower half holds offset bits 32..64
+    selector != 0
+        && (0xffff_8000..=0xffff_ffff).contains(&first_hi)
+        && (0..32).all(|idx| {
+            let (lo, hi) = gate(idx);
+            let attributes = (lo >> 40) & 0xff;
+            attributes & 0x80 != 0
+                && matches!(attributes & 0xf, 0xe | 0xf)
+                && (lo >> 16) & 0xffff == selector
+                && hi == first_hi
+        })
+}
+
+/// Checks for the header of a Linux kernel image, which starts on a page boundary.
+fn kernel_image(page: &[u8]) -> Option<ArchitectureIdent> {
+    if &page[0x38..0x3c] == b"ARM\x64" {
+        Some(ArchitectureIdent::AArch64(size::kb(4)))
+    } else if &page[0x30..0x38] == b"RISCV\0\0\0" && &page[0x38..0x3c] == b"RSC\x05" {
+        // the paging mode is not part of the header, Sv39 is the common one
+        Some(ArchitectureIdent::RiscV64(39))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, Fault, FaultyMemory};
+
+    fn write_u64(mem: &mut DummyMemory, addr: u64, value: u64) {
+        mem.phys_write(Address::from(addr).into(), &value.to_le_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn finds_structures() {
+        let mut mem = DummyMemory::new(size::mb(4));
+
+        // a linux style pml4 at 0x1000, a windows style one at 0x2000
+        write_u64(&mut mem, 0x1000 + 256 * 8, 0x5003);
+        write_u64(&mut mem, 0x2000 + 256 * 8, 0x5003);
+        write_u64(&mut mem, 0x2000 + 0x1ed * 8, 0x2063);
+
+        // idt at 0x10_0000
+        for idx in 0..256 {
+            let lo = 0x1234 | (0x10 << 16) | (0x8e << 40) | (0x5678 << 48);
+            write_u64(&mut mem, 0x10_0000 + idx * 16, lo);
+            write_u64(&mut mem, 0x10_0000 + idx * 16 + 8, 0xffff_f801);
+        }
+
+        // aarch64 kernel image at 0x20_0000
+        mem.phys_write(Address::from(0x20_0038).into(), b"ARM\x64")
+            .unwrap();
+
+        let candidates = detect(&mut mem).unwrap();
+        assert_eq!(candidates.len(), 2);
+
+        let x64 = &candidates[0];
+        assert_eq!(x64.arch, ArchitectureIdent::X86(64, false));
+        assert_eq!(
+            x64.score,
+            SELF_REFERENCE_SCORE + PAGE_TABLE_SCORE + IDT_SCORE
+        );
+        assert_eq!(x64.dtbs, vec![Address::from(0x2000), Address::from(0x1000)]);
+
+        assert_eq!(candidates[1].arch, ArchitectureIdent::AArch64(size::kb(4)));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        // kernel half entry pointing outside of physical memory
+        write_u64(&mut mem, 0x1000 + 300 * 8, 0x1_0000_0003);
+        // not present entry next to a valid one
+        write_u64(&mut mem, 0x2000 + 256 * 8, 0x5003);
+        write_u64(&mut mem, 0x2000 + 8, 0x6000);
+
+        assert!(detect(&mut mem).unwrap().is_empty());
+    }
+
+    #[test]
+    fn partial_chunks() {
+        let mut mem = DummyMemory::new(size::mb(4));
+        write_u64(&mut mem, 0x1000 + 256 * 8, 0x5003);
+        write_u64(&mut mem, 0x1000 + 0x1ed * 8, 0x1063);
+
+        // only the first two pages of every chunk can be read
+        let mut mem =
+            FaultyMemory::with_seed(mem, 1).fault_everywhere(Fault::new().truncate_reads(0x2000));
+
+        let candidates = detect(&mut mem).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].dtbs, vec![Address::from(0x1000)]);
+        assert_eq!(mem.stats().truncated_reads, 2);
+    }
+}
@@ -0,0 +1,27 @@
+use super::{
+    super::{ArchitectureObj, Endianess},
+    MipsArchitecture, MipsVirtualTranslate, TlbLookup,
+};
+
+pub(super) static ARCH_SPEC: MipsArchitecture = MipsArchitecture {
+    bits: 64,
+    endianess: Endianess::BigEndian,
+    address_space_bits: 59,
+};
+
+pub(super) static ARCH_LE_SPEC: MipsArchitecture = MipsArchitecture {
+    bits: 64,
+    endianess: Endianess::LittleEndian,
+    address_space_bits: 59,
+};
+
+pub static ARCH: ArchitectureObj = &ARCH_SPEC;
+pub static ARCH_LE: ArchitectureObj = &ARCH_LE_SPEC;
+
+pub fn new_translator<F: TlbLookup>(asid: u16, tlb: F) -> MipsVirtualTranslate<F> {
+    MipsVirtualTranslate::new(&ARCH_SPEC, asid, tlb)
+}
+
+pub fn new_translator_le<F: TlbLookup>(asid: u16, tlb: F) -> MipsVirtualTranslate<F> {
+    MipsVirtualTranslate::new(&ARCH_LE_SPEC, asid, tlb)
+}
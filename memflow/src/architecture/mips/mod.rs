@@ -0,0 +1,338 @@
+/*!
+MIPS32 and MIPS64 address translation.
+
+MIPS has no hardware page table walker, mapped addresses are translated by a software managed TLB
+which the operating system refills on its own. The translators thus resolve mapped addresses
+through a user supplied [`TlbLookup`] callback, while the unmapped segments (`kseg0`, `kseg1`
+and `xkphys` on MIPS64) are translated directly:
+
+```
+use memflow::architecture::mips::{self, TlbEntry};
+use memflow::types::{mem, Address};
+
+// a single 4kb page, for example recovered from a TLB dump
+let entries = [TlbEntry::new(Address::from(0x40_0000), Address::from(0x1000), mem::kb(4))];
+
+let translator = mips::mips32::new_translator(1, |addr: Address| {
+    entries.iter().copied().find(|entry| entry.contains(addr))
+});
+```
+
+The callback has to be `Copy`, so it usually captures its mappings by reference.
+*/
+
+pub mod mips32;
+pub mod mips64;
+
+use super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
+
+use crate::mem::virt_translate::{VirtualTranslate3, VtopFailureCallback, VtopOutputCallback};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::PhysicalMemory;
+use crate::types::{mem, size, umem, Address, PageType, PhysicalAddress};
+use cglue::tuple::*;
+
+pub struct MipsArchitecture {
+    /// Defines how many bits does the native word size have
+    bits: u8,
+    /// Defines the byte order of the system
+    endianess: Endianess,
+    /// Defines the width of physical addresses
+    address_space_bits: u8,
+}
+
+impl Architecture for MipsArchitecture {
+    fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    fn endianess(&self) -> Endianess {
+        self.endianess
+    }
+
+    fn page_size(&self) -> usize {
+        size::kb(4)
+    }
+
+    fn size_addr(&self) -> usize {
+        self.bits as usize / 8
+    }
+
+    fn address_space_bits(&self) -> u8 {
+        self.address_space_bits
+    }
+
+    fn ident(&self) -> ArchitectureIdent {
+        ArchitectureIdent::Mips(self.bits, self.endianess == Endianess::LittleEndian)
+    }
+}
+
+impl MipsArchitecture {
+    /// Translates addresses of the unmapped segments, returns `None` for mapped addresses.
+    fn unmapped(&self, addr: umem) -> Option<umem> {
+        // the 32-bit segments are sign extended on MIPS64
+        let compat = if self.bits == 32 {
+            Some(addr)
+        } else if addr >= 0xffff_ffff_8000_0000 {
+            Some(addr & 0xffff_ffff)
+        } else {
+            None
+        };
+
+        match compat {
+            // kseg0 (cached) and kseg1 (uncached) both map the lowest 512mb
+            Some(addr) if (0x8000_0000..0xc000_0000).contains(&addr) => Some(addr & 0x1fff_ffff),
+            // xkphys, bits 59..62 select the cache attributes
+            None if (0x8000_0000_0000_0000..0xc000_0000_0000_0000).contains(&addr) => {
+                Some(addr & ((1 << self.address_space_bits) - 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A mapping of the TLB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TlbEntry {
+    pub virt: Address,
+    pub phys: Address,
+    /// Size of the page, both addresses are aligned to it
+    pub size: umem,
+    pub writeable: bool,
+    pub noexec: bool,
+}
+
+impl TlbEntry {
+    /// Creates a writeable and executable mapping.
+    pub fn new(virt: Address, phys: Address, size: umem) -> Self {
+        Self {
+            virt,
+            phys,
+            size,
+            writeable: true,
+            noexec: false,
+        }
+    }
+
+    pub fn writeable(mut self, writeable: bool) -> Self {
+        self.writeable = writeable;
+        self
+    }
+
+    pub fn noexec(mut self, noexec: bool) -> Self {
+        self.noexec = noexec;
+        self
+    }
+
+    pub fn contains(&self, addr: Address) -> bool {
+        addr.to_umem().wrapping_sub(self.virt.to_umem()) < self.size
+    }
+
+    fn translate(&self, addr: Address) -> PhysicalAddress {
+        PhysicalAddress::with_page(
+            self.phys + addr.to_umem().wrapping_sub(self.virt.to_umem()),
+            PageType::default()
+                .write(self.writeable)
+                .noexec(self.noexec),
+            self.size,
+        )
+    }
+}
+
+/// Resolves mapped addresses to the TLB entry mapping them.
+///
+/// The lookup is implemented for all `Copy` closures taking the virtual address, entries that do
+/// not contain the address are treated as a miss.
+pub trait TlbLookup: Fn(Address) -> Option<TlbEntry> + Clone + Copy + Send {}
+
+impl<F: Fn(Address) -> Option<TlbEntry> + Clone + Copy + Send> TlbLookup for F {}
+
+#[derive(Clone, Copy)]
+pub struct MipsVirtualTranslate<F> {
+    arch: &'static MipsArchitecture,
+    asid: u16,
+    tlb: F,
+}
+
+impl<F: TlbLookup> MipsVirtualTranslate<F> {
+    pub fn new(arch: &'static MipsArchitecture, asid: u16, tlb: F) -> Self {
+        Self { arch, asid, tlb }
+    }
+
+    fn translate(&self, addr: Address) -> Option<PhysicalAddress> {
+        if let Some(phys) = self.arch.unmapped(addr.to_umem()) {
+            // report the segments in large pages, their alignment matches in both address spaces
+            return Some(PhysicalAddress::with_page(
+                phys.into(),
+                PageType::default().write(true),
+                mem::mb(512),
+            ));
+        }
+
+        if self.arch.bits == 32 && addr.to_umem() > 0xffff_ffff {
+            return None;
+        }
+
+        (self.tlb)(addr)
+            .filter(|entry| entry.contains(addr))
+            .map(|entry| entry.translate(addr))
+    }
+}
+
+impl<F: TlbLookup> VirtualTranslate3 for MipsVirtualTranslate<F> {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        _mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        _tmp_buf: &mut [std::mem::MaybeUninit<u8>],
+    ) {
+        for CTup3(mut addr, mut meta_addr, buf) in addrs {
+            let mut rest = Some(buf);
+
+            while let Some(buf) = rest.take() {
+                let page = self.translate(addr);
+
+                // misses are reported per 4kb page, so that the next page can still be resolved
+                let page_size = page.map(|p| p.page_size()).unwrap_or(mem::kb(4));
+                let len = page_size - addr.to_umem() % page_size;
+                let (left, right) = buf.split_at(len);
+
+                if let Some(buf) = left {
+                    let cont = match page {
+                        Some(page) => out.call(CTup3(page, meta_addr, buf)),
+                        None => out_fail.call((
+                            Error(ErrorOrigin::Mmu, ErrorKind::OutOfMemoryRange),
+                            CTup3(addr, meta_addr, buf),
+                        )),
+                    };
+
+                    if !cont {
+                        return;
+                    }
+                }
+
+                rest = right;
+                addr += len;
+                meta_addr += len;
+            }
+        }
+    }
+
+    fn translation_table_id(&self, address: Address) -> umem {
+        // the unmapped segments are shared by all address spaces
+        if self.arch.unmapped(address.to_umem()).is_some() {
+            0
+        } else {
+            umem::from(self.asid) + 1
+        }
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        self.arch
+    }
+}
+
+// This lint doesn't make any sense in our usecase, since we nevel leak ARCH_SPECs, and ARCH is
+// a static trait object with a consistent address.
+fn underlying_arch(arch: ArchitectureObj) -> Option<&'static MipsArchitecture> {
+    if arch == mips32::ARCH {
+        Some(&mips32::ARCH_SPEC)
+    } else if arch == mips32::ARCH_LE {
+        Some(&mips32::ARCH_LE_SPEC)
+    } else if arch == mips64::ARCH {
+        Some(&mips64::ARCH_SPEC)
+    } else if arch == mips64::ARCH_LE {
+        Some(&mips64::ARCH_LE_SPEC)
+    } else {
+        None
+    }
+}
+
+pub fn new_translator<F: TlbLookup>(
+    arch: ArchitectureObj,
+    asid: u16,
+    tlb: F,
+) -> Result<MipsVirtualTranslate<F>> {
+    let arch =
+        underlying_arch(arch).ok_or(Error(ErrorOrigin::Mmu, ErrorKind::InvalidArchitecture))?;
+    Ok(MipsVirtualTranslate::new(arch, asid, tlb))
+}
+
+pub fn is_mips_arch(arch: ArchitectureObj) -> bool {
+    underlying_arch(arch).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{MemoryView, VirtualDma};
+
+    #[test]
+    fn mips32_segments() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(Address::from(0x1000).into(), b"mips")
+            .unwrap();
+
+        let entries = [
+            TlbEntry::new(Address::from(0x40_0000), Address::from(0x1000), mem::kb(4)),
+            TlbEntry::new(Address::from(0xc000_0000u64), 0x2000.into(), mem::kb(4))
+                .writeable(false),
+        ];
+        let translator = mips32::new_translator(3, |addr: Address| {
+            entries.iter().copied().find(|entry| entry.contains(addr))
+        });
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0xa000_1000u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x1000));
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0xc000_0010u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x2010));
+        assert_eq!(page.page_type(), PageType::READ_ONLY);
+
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0x1000))
+            .is_err());
+
+        let mut virt_mem = VirtualDma::new(mem, mips32::ARCH, translator);
+        assert_eq!(virt_mem.read_raw(0x40_0000.into(), 4).unwrap(), b"mips");
+        assert_eq!(
+            virt_mem.read_raw(0x8000_1000u64.into(), 4).unwrap(),
+            b"mips"
+        );
+    }
+
+    #[test]
+    fn mips64_segments() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let translator = new_translator(mips64::ARCH_LE, 0, |_: Address| None).unwrap();
+        assert_eq!(translator.arch().ident(), ArchitectureIdent::Mips(64, true));
+
+        // ckseg0 and xkphys
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0xffff_ffff_8000_1234u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x1234));
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x9800_0000_0001_2345u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x1_2345));
+
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0xc000_0000_0000_0000u64))
+            .is_err());
+        assert!(new_translator(crate::architecture::x86::x64::ARCH, 0, |_: Address| None).is_err());
+    }
+}
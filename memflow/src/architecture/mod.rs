@@ -14,8 +14,14 @@ that memflow know the proper byte order of the target system.
 */
 
 pub mod arm;
+pub mod mips;
+pub mod ppc64;
+pub mod riscv;
 pub mod x86;
 
+pub(crate) mod detect;
+pub use detect::{detect, ArchCandidate};
+
 use crate::types::size;
 
 /// Identifies the byte order of a architecture
@@ -158,6 +164,20 @@ pub enum ArchitectureIdent {
     ///
     /// Valid page sizes are 4kb, 16kb, 64kb. Only 4kb is supported at the moment
     AArch64(usize),
+    /// RISC-V 64-bit architecture with the specified virtual address width
+    ///
+    /// Valid widths are 39 (Sv39) and 48 (Sv48).
+    RiscV64(u8),
+    /// PowerPC 64-bit architecture with the specified page size and byte order
+    ///
+    /// First argument - `page_size` is either 4kb or 64kb.
+    /// Second argument - `little_endian` controls whether it's the ppc64le variant.
+    Ppc64(usize, bool),
+    /// MIPS with specified bitness and byte order
+    ///
+    /// First argument - `bitness` controls whether it's 32, or 64 bit variant.
+    /// Second argument - `little_endian` controls whether it's the mipsel variant.
+    Mips(u8, bool),
 }
 
 impl std::fmt::Display for ArchitectureIdent {
@@ -169,6 +189,16 @@ impl std::fmt::Display for ArchitectureIdent {
             ArchitectureIdent::X86(64, true) => f.pad("x86_64 LA57"),
             ArchitectureIdent::X86(_, _) => f.pad("x86"),
             ArchitectureIdent::AArch64(_) => f.pad("AArch64"),
+            ArchitectureIdent::RiscV64(39) => f.pad("riscv64 Sv39"),
+            ArchitectureIdent::RiscV64(48) => f.pad("riscv64 Sv48"),
+            ArchitectureIdent::RiscV64(_) => f.pad("riscv64"),
+            ArchitectureIdent::Ppc64(_, false) => f.pad("ppc64"),
+            ArchitectureIdent::Ppc64(_, true) => f.pad("ppc64le"),
+            ArchitectureIdent::Mips(32, false) => f.pad("mips"),
+            ArchitectureIdent::Mips(32, true) => f.pad("mipsel"),
+            ArchitectureIdent::Mips(64, false) => f.pad("mips64"),
+            ArchitectureIdent::Mips(64, true) => f.pad("mips64el"),
+            ArchitectureIdent::Mips(_, _) => f.pad("mips"),
             ArchitectureIdent::Unknown(id) => f.debug_tuple("Unknown").field(&id).finish(),
         }
     }
@@ -183,11 +213,22 @@ impl ArchitectureIdent {
 impl From<ArchitectureIdent> for ArchitectureObj {
     fn from(arch: ArchitectureIdent) -> ArchitectureObj {
         const KB4: usize = size::kb(4);
+        const KB64: usize = size::kb(64);
         match arch {
             ArchitectureIdent::X86(32, false) => x86::x32::ARCH,
             ArchitectureIdent::X86(32, true) => x86::x32_pae::ARCH,
             ArchitectureIdent::X86(64, false) => x86::x64::ARCH,
             ArchitectureIdent::AArch64(KB4) => arm::aarch64::ARCH,
+            ArchitectureIdent::RiscV64(39) => riscv::sv39::ARCH,
+            ArchitectureIdent::RiscV64(48) => riscv::sv48::ARCH,
+            ArchitectureIdent::Ppc64(KB4, false) => ppc64::radix4k::ARCH,
+            ArchitectureIdent::Ppc64(KB4, true) => ppc64::radix4k::ARCH_LE,
+            ArchitectureIdent::Ppc64(KB64, false) => ppc64::radix64k::ARCH,
+            ArchitectureIdent::Ppc64(KB64, true) => ppc64::radix64k::ARCH_LE,
+            ArchitectureIdent::Mips(32, false) => mips::mips32::ARCH,
+            ArchitectureIdent::Mips(32, true) => mips::mips32::ARCH_LE,
+            ArchitectureIdent::Mips(64, false) => mips::mips64::ARCH,
+            ArchitectureIdent::Mips(64, true) => mips::mips64::ARCH_LE,
             _ => panic!("unsupported architecture! {:?}", arch),
         }
     }
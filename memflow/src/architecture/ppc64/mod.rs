@@ -0,0 +1,310 @@
+/*!
+PowerPC 64-bit (Book3S) radix tree translation, as used by Linux on POWER9 and newer.
+
+Both page size configurations of Linux are supported: [`radix64k`] (the default on most
+distributions) and [`radix4k`]. The page tables are always stored big-endian, independently of
+the byte order of the system, so every configuration comes in a big-endian (`ARCH`) and a
+little-endian (`ARCH_LE`, ppc64le) flavour, which only differ in how data is read.
+
+Translators are created from the physical address of the root page directory, or straight from
+a process table entry:
+
+```
+use memflow::architecture::ppc64;
+use memflow::types::Address;
+
+// swapper_pg_dir, the kernel page table
+let kernel = ppc64::radix64k::new_translator_le(Address::from(0x1_0000));
+
+// a process table entry with a 52 bit tree and a 8192 entry root directory
+let prte: u64 = (1 << 62) | (5 << 5) | 0x2_0000 | 13;
+let process = ppc64::new_translator_from_prte(prte, ppc64::radix64k::ARCH_LE).unwrap();
+```
+
+The two top bits of an effective address select the quadrant. The translator walks the same
+tables for the user quadrant (`0x0000..`) and the kernel quadrant (`0xc000..`), since Linux keeps
+the kernel mappings in their own table (the one of PID 0). All other addresses are rejected.
+*/
+
+pub mod radix4k;
+pub mod radix64k;
+
+use super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
+
+use crate::mem::virt_translate::{
+    mmu::{
+        translate_data::{TranslateData, TranslateDataVec, TranslationChunk},
+        ArchMmuSpec, MmuTranslationBase,
+    },
+    VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address};
+use cglue::tuple::*;
+
+pub struct Ppc64Architecture {
+    /// Defines how many bits does the native word size have
+    bits: u8,
+    /// Defines the byte order of data, page tables are always big-endian
+    endianess: Endianess,
+    /// Defines the underlying MMU used for address translation
+    mmu: ArchMmuSpec,
+}
+
+impl Architecture for Ppc64Architecture {
+    fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    fn endianess(&self) -> Endianess {
+        self.endianess
+    }
+
+    fn page_size(&self) -> usize {
+        self.mmu.page_size_level(1) as usize
+    }
+
+    fn size_addr(&self) -> usize {
+        self.mmu.def.addr_size.into()
+    }
+
+    fn address_space_bits(&self) -> u8 {
+        self.mmu.def.address_space_bits
+    }
+
+    fn ident(&self) -> ArchitectureIdent {
+        ArchitectureIdent::Ppc64(self.page_size(), self.endianess == Endianess::LittleEndian)
+    }
+}
+
+/// Size of the effective address range of a quadrant, 52 bits on all Linux configurations.
+const QUADRANT_SIZE: umem = 1 << 52;
+const KERNEL_QUADRANT: umem = 0b11 << 62;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ppc64PageTableBase(Address);
+
+impl MmuTranslationBase for Ppc64PageTableBase {
+    fn get_pt_by_virt_addr(&self, _: Address) -> Address {
+        self.0
+    }
+
+    fn get_pt_by_index(&self, idx: usize) -> (Address, usize) {
+        (self.0, idx)
+    }
+
+    fn pt_count(&self) -> usize {
+        1
+    }
+
+    fn virt_addr_filter<B>(
+        &self,
+        _spec: &ArchMmuSpec,
+        CTup3(addr, meta_addr, buf): CTup3<Address, Address, B>,
+        (chunks, addrs_out): (&mut TranslationChunk<Self>, &mut TranslateDataVec<B>),
+        out_fail: &mut VtopFailureCallback<B>,
+    ) where
+        B: SplitAtIndex,
+    {
+        let mut reject = |data: TranslateData<B>| {
+            // TODO: handle condition
+            let _ = out_fail.call((
+                Error(ErrorOrigin::Mmu, ErrorKind::OutOfMemoryRange),
+                CTup3(data.addr, data.meta_addr, data.buf),
+            ));
+        };
+
+        let data = TranslateData {
+            addr,
+            meta_addr,
+            buf,
+        };
+
+        // the user quadrant, followed by the quadrants 1 and 2
+        let (user, higher) = data.split_at_address(QUADRANT_SIZE.into());
+
+        if let Some(user) = user {
+            chunks.push_data(user, addrs_out);
+        }
+
+        if let Some(data) = higher {
+            let (invalid, kernel) = data.split_at_address(KERNEL_QUADRANT.into());
+            if let Some(data) = invalid {
+                reject(data);
+            }
+
+            if let Some(data) = kernel {
+                let (kernel, invalid) =
+                    data.split_at_address((KERNEL_QUADRANT + QUADRANT_SIZE).into());
+                if let Some(data) = invalid {
+                    reject(data);
+                }
+
+                if let Some(kernel) = kernel {
+                    chunks.push_data(kernel, addrs_out);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Ppc64VirtualTranslate {
+    arch: &'static Ppc64Architecture,
+    root: Ppc64PageTableBase,
+}
+
+impl Ppc64VirtualTranslate {
+    pub fn new(arch: &'static Ppc64Architecture, root: Address) -> Self {
+        Self {
+            arch,
+            root: Ppc64PageTableBase(root),
+        }
+    }
+}
+
+impl VirtualTranslate3 for Ppc64VirtualTranslate {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        tmp_buf: &mut [std::mem::MaybeUninit<u8>],
+    ) {
+        self.arch
+            .mmu
+            .virt_to_phys_iter(mem, self.root, addrs, out, out_fail, tmp_buf)
+    }
+
+    fn translation_table_id(&self, _address: Address) -> umem {
+        self.root.0.to_umem().overflowing_shr(12).0
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        self.arch
+    }
+}
+
+// This lint doesn't make any sense in our usecase, since we nevel leak ARCH_SPECs, and ARCH is
+// a static trait object with a consistent address.
+fn underlying_arch(arch: ArchitectureObj) -> Option<&'static Ppc64Architecture> {
+    if arch == radix64k::ARCH {
+        Some(&radix64k::ARCH_SPEC)
+    } else if arch == radix64k::ARCH_LE {
+        Some(&radix64k::ARCH_LE_SPEC)
+    } else if arch == radix4k::ARCH {
+        Some(&radix4k::ARCH_SPEC)
+    } else if arch == radix4k::ARCH_LE {
+        Some(&radix4k::ARCH_LE_SPEC)
+    } else {
+        None
+    }
+}
+
+pub fn new_translator(root: Address, arch: ArchitectureObj) -> Result<Ppc64VirtualTranslate> {
+    let arch =
+        underlying_arch(arch).ok_or(Error(ErrorOrigin::Mmu, ErrorKind::InvalidArchitecture))?;
+    Ok(Ppc64VirtualTranslate::new(arch, root))
+}
+
+/// Creates a translator from the first doubleword of a process table entry.
+///
+/// Fails if the entry does not describe a 52 bit radix tree with a 8192 entry root directory,
+/// which is the only layout used by Linux.
+pub fn new_translator_from_prte(prte: u64, arch: ArchitectureObj) -> Result<Ppc64VirtualTranslate> {
+    let tree_size = ((prte >> 58) & 0b11000) | ((prte >> 5) & 0b111);
+    let root_size = prte & 0b11111;
+    if tree_size + 31 != 52 || root_size != 13 {
+        return Err(Error(ErrorOrigin::Mmu, ErrorKind::NotSupported));
+    }
+
+    new_translator(Address::from(prte & 0x0fff_ffff_ffff_ff00), arch)
+}
+
+pub fn is_ppc64_arch(arch: ArchitectureObj) -> bool {
+    underlying_arch(arch).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::{mem, size, PageType};
+
+    const V: u64 = 1 << 63;
+    const L: u64 = 1 << 62;
+    const X: u64 = 1 << 0;
+    const RW: u64 = 1 << 1;
+    const R: u64 = 1 << 2;
+    const PRIV: u64 = 1 << 3;
+
+    fn write_entry(mem: &mut DummyMemory, table: u64, idx: u64, entry: u64) {
+        mem.phys_write(Address::from(table + idx * 8).into(), &entry.to_be_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn radix64k_translation() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        // directory entries hold the size of the next level in the low bits
+        write_entry(&mut mem, 0x1_0000, 0, V | 0x2_0000 | 9);
+        write_entry(&mut mem, 0x2_0000, 4, V | 0x3_0000 | 9);
+        write_entry(&mut mem, 0x3_0000, 0, V | 0x4_0000 | 5);
+        // 0x1_0012_0000 -> read only 64k page at 0x50_0000
+        write_entry(&mut mem, 0x4_0000, 0x12, V | L | 0x50_0000 | R | X);
+        // linear map of the kernel, 1g leaf at 0x8000_0000
+        write_entry(&mut mem, 0x2_0000, 0, V | L | 0x8000_0000 | PRIV | R | RW);
+
+        let translator = radix64k::new_translator_le(Address::from(0x1_0000));
+        assert_eq!(
+            translator.arch().ident(),
+            ArchitectureIdent::Ppc64(size::kb(64), true)
+        );
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x1_0012_3456u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x50_3456));
+        assert_eq!(page.page_size(), mem::kb(64));
+        assert_eq!(page.page_type(), PageType::READ_ONLY);
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0xc000_0000_1234_5678u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x9234_5678u64));
+        assert_eq!(page.page_size(), mem::gb(1));
+        assert_eq!(page.page_type(), PageType::WRITEABLE | PageType::NOEXEC);
+
+        // outside of the quadrants used by Linux
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0x10_0000_0000_0000u64))
+            .is_err());
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0x4000_0000_0000_0000u64))
+            .is_err());
+    }
+
+    #[test]
+    fn process_table_entry() {
+        let rts = (0b10 << 61) | (0b101 << 5);
+        let translator = new_translator_from_prte(rts | 0x1_0000 | 13, radix4k::ARCH).unwrap();
+        assert_eq!(translator.root.0, Address::from(0x1_0000));
+        assert_eq!(translator.arch().endianess(), Endianess::BigEndian);
+        assert_eq!(translator.arch().page_size(), size::kb(4));
+
+        assert!(new_translator_from_prte(rts | 0x1_0000 | 12, radix4k::ARCH).is_err());
+        assert!(new_translator_from_prte(0x1_0000 | 13, radix4k::ARCH).is_err());
+        assert!(
+            new_translator_from_prte(rts | 0x1_0000 | 13, crate::architecture::x86::x64::ARCH)
+                .is_err()
+        );
+    }
+}
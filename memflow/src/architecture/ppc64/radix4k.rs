@@ -0,0 +1,45 @@
+use super::{
+    super::{ArchitectureObj, Endianess},
+    Ppc64Architecture, Ppc64VirtualTranslate,
+};
+
+use crate::mem::virt_translate::mmu::ArchMmuDef;
+
+use crate::types::Address;
+
+const MMU: ArchMmuDef = ArchMmuDef {
+    virtual_address_splits: &[13, 9, 9, 9, 12],
+    valid_final_page_steps: &[2, 3, 4],
+    address_space_bits: 56,
+    endianess: Endianess::BigEndian,
+    addr_size: 8,
+    pte_size: 8,
+    present_bit: |a| a.bit_at(63),
+    writeable_bit: |a, _| a.bit_at(1),
+    nx_bit: |a, _| !a.bit_at(0),
+    large_page_bit: |a| a.bit_at(62),
+    pte_fixup: |a| a,
+};
+
+pub(super) static ARCH_SPEC: Ppc64Architecture = Ppc64Architecture {
+    bits: 64,
+    endianess: Endianess::BigEndian,
+    mmu: MMU.into_spec(),
+};
+
+pub(super) static ARCH_LE_SPEC: Ppc64Architecture = Ppc64Architecture {
+    bits: 64,
+    endianess: Endianess::LittleEndian,
+    mmu: MMU.into_spec(),
+};
+
+pub static ARCH: ArchitectureObj = &ARCH_SPEC;
+pub static ARCH_LE: ArchitectureObj = &ARCH_LE_SPEC;
+
+pub fn new_translator(root: Address) -> Ppc64VirtualTranslate {
+    Ppc64VirtualTranslate::new(&ARCH_SPEC, root)
+}
+
+pub fn new_translator_le(root: Address) -> Ppc64VirtualTranslate {
+    Ppc64VirtualTranslate::new(&ARCH_LE_SPEC, root)
+}
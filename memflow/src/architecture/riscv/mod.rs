@@ -0,0 +1,229 @@
+/*!
+RISC-V Sv39 and Sv48 page table translation.
+
+Translators are created from the physical address of the root page table, which is the `PPN`
+field of the `satp` register shifted left by 12 bits:
+
+```
+use memflow::architecture::riscv;
+use memflow::types::Address;
+
+let satp: u64 = 0x8000_0000_0008_0400;
+let root = Address::from((satp & ((1 << 44) - 1)) << 12);
+
+let translator = riscv::new_translator(root, riscv::sv39::ARCH).unwrap();
+```
+
+Megapages and gigapages (and terapages on Sv48) are supported. The accessed bit is not checked,
+since the referenced page is backed by memory either way. Pages are only reported as writeable if
+both the `W` and the dirty bit are set, as a write to a clean page traps on hardware that does not
+update the bits on its own.
+*/
+
+pub mod sv39;
+pub mod sv48;
+
+use super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
+
+use crate::mem::virt_translate::{
+    mmu::ArchMmuSpec, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address};
+use cglue::tuple::*;
+
+pub struct RiscvArchitecture {
+    /// Defines how many bits does the native word size have
+    bits: u8,
+    /// Defines the underlying MMU used for address translation
+    mmu: ArchMmuSpec,
+}
+
+impl Architecture for RiscvArchitecture {
+    fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    fn endianess(&self) -> Endianess {
+        self.mmu.def.endianess
+    }
+
+    fn page_size(&self) -> usize {
+        self.mmu.page_size_level(1) as usize
+    }
+
+    fn size_addr(&self) -> usize {
+        self.mmu.def.addr_size.into()
+    }
+
+    fn address_space_bits(&self) -> u8 {
+        self.mmu.def.address_space_bits
+    }
+
+    fn ident(&self) -> ArchitectureIdent {
+        ArchitectureIdent::RiscV64(self.mmu.def.virtual_address_splits.iter().sum())
+    }
+}
+
+/// Moves the PPN of a PTE (bits 10..54) to the position of the physical address (bits 12..56).
+///
+/// The flags in bits 0..10 are kept in place.
+fn pte_fixup(pte: Address) -> Address {
+    let pte = pte.to_umem();
+    Address::from(((pte >> 10) << 12) | (pte & 0x3ff))
+}
+
+#[derive(Clone, Copy)]
+pub struct RiscvVirtualTranslate {
+    arch: &'static RiscvArchitecture,
+    root: Address,
+}
+
+impl RiscvVirtualTranslate {
+    pub fn new(arch: &'static RiscvArchitecture, root: Address) -> Self {
+        Self { arch, root }
+    }
+}
+
+impl VirtualTranslate3 for RiscvVirtualTranslate {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        tmp_buf: &mut [std::mem::MaybeUninit<u8>],
+    ) {
+        self.arch
+            .mmu
+            .virt_to_phys_iter(mem, self.root, addrs, out, out_fail, tmp_buf)
+    }
+
+    fn translation_table_id(&self, _address: Address) -> umem {
+        self.root.to_umem().overflowing_shr(12).0
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        self.arch
+    }
+}
+
+// This lint doesn't make any sense in our usecase, since we nevel leak ARCH_SPECs, and ARCH is
+// a static trait object with a consistent address.
+fn underlying_arch(arch: ArchitectureObj) -> Option<&'static RiscvArchitecture> {
+    if arch == sv39::ARCH {
+        Some(&sv39::ARCH_SPEC)
+    } else if arch == sv48::ARCH {
+        Some(&sv48::ARCH_SPEC)
+    } else {
+        None
+    }
+}
+
+pub fn new_translator(root: Address, arch: ArchitectureObj) -> Result<RiscvVirtualTranslate> {
+    let arch =
+        underlying_arch(arch).ok_or(Error(ErrorOrigin::Mmu, ErrorKind::InvalidArchitecture))?;
+    Ok(RiscvVirtualTranslate::new(arch, root))
+}
+
+pub fn is_riscv_arch(arch: ArchitectureObj) -> bool {
+    underlying_arch(arch).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::{mem, size, PageType};
+
+    const V: u64 = 1 << 0;
+    const R: u64 = 1 << 1;
+    const W: u64 = 1 << 2;
+    const X: u64 = 1 << 3;
+    const A: u64 = 1 << 6;
+    const D: u64 = 1 << 7;
+
+    fn write_pte(mem: &mut DummyMemory, table: u64, idx: u64, target: u64, flags: u64) {
+        let pte = ((target >> 12) << 10) | flags;
+        mem.phys_write(Address::from(table + idx * 8).into(), &pte.to_le_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn sv39_translation() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let translator = new_translator(Address::from(0x1000), sv39::ARCH).unwrap();
+
+        // 0x4000_0000 -> 4k page at 0x1_0000
+        write_pte(&mut mem, 0x1000, 1, 0x2000, V);
+        write_pte(&mut mem, 0x2000, 0, 0x3000, V);
+        write_pte(&mut mem, 0x3000, 0, 0x1_0000, V | R | W | A | D);
+        // 0x4020_0000 -> clean, executable 2m page at 0x20_0000
+        write_pte(&mut mem, 0x2000, 1, 0x20_0000, V | R | X | A);
+        // top of the address space -> 1g page at 0x8000_0000
+        write_pte(&mut mem, 0x1000, 511, 0x8000_0000, V | R | W | A | D);
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x4000_0123))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x1_0123));
+        assert_eq!(page.page_size(), mem::kb(4));
+        assert_eq!(page.page_type(), PageType::WRITEABLE | PageType::NOEXEC);
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x4021_2345))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x21_2345));
+        assert_eq!(page.page_size(), mem::mb(2));
+        assert_eq!(page.page_type(), PageType::READ_ONLY);
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0xffff_ffff_c000_1000u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x8000_1000));
+        assert_eq!(page.page_size(), mem::gb(1));
+
+        // unmapped and non canonical addresses
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0x8000_0000))
+            .is_err());
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0x40_0000_0000u64))
+            .is_err());
+    }
+
+    #[test]
+    fn sv48_translation() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let translator = new_translator(Address::from(0x1000), sv48::ARCH).unwrap();
+        assert_eq!(translator.arch().ident(), ArchitectureIdent::RiscV64(48));
+
+        // 0x80_0000_0000 -> 4k page at 0x1_0000
+        write_pte(&mut mem, 0x1000, 1, 0x2000, V);
+        write_pte(&mut mem, 0x2000, 0, 0x3000, V);
+        write_pte(&mut mem, 0x3000, 0, 0x4000, V);
+        write_pte(&mut mem, 0x4000, 0, 0x1_0000, V | R | A);
+        // 0x100_0000_0000 -> 512g page at 0
+        write_pte(&mut mem, 0x1000, 2, 0, V | R | W | X | A | D);
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x80_0000_0042u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x1_0042));
+        assert_eq!(page.page_size(), mem::kb(4));
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x100_1234_5678u64))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x1234_5678));
+        assert_eq!(page.page_size(), mem::gb(512));
+        assert_eq!(page.page_type(), PageType::WRITEABLE);
+    }
+}
@@ -0,0 +1,32 @@
+use super::{
+    super::{ArchitectureObj, Endianess},
+    RiscvArchitecture, RiscvVirtualTranslate,
+};
+
+use crate::mem::virt_translate::mmu::ArchMmuDef;
+
+use crate::types::Address;
+
+pub(super) static ARCH_SPEC: RiscvArchitecture = RiscvArchitecture {
+    bits: 64,
+    mmu: ArchMmuDef {
+        virtual_address_splits: &[9, 9, 9, 9, 12],
+        valid_final_page_steps: &[1, 2, 3, 4],
+        address_space_bits: 56,
+        endianess: Endianess::LittleEndian,
+        addr_size: 8,
+        pte_size: 8,
+        present_bit: |a| a.bit_at(0),
+        writeable_bit: |a, _| a.bit_at(2) && a.bit_at(7),
+        nx_bit: |a, _| !a.bit_at(3),
+        large_page_bit: |a| a.bit_at(1) || a.bit_at(3),
+        pte_fixup: super::pte_fixup,
+    }
+    .into_spec(),
+};
+
+pub static ARCH: ArchitectureObj = &ARCH_SPEC;
+
+pub fn new_translator(root: Address) -> RiscvVirtualTranslate {
+    RiscvVirtualTranslate::new(&ARCH_SPEC, root)
+}
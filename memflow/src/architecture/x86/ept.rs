@@ -0,0 +1,233 @@
+//! Intel extended page tables (EPT).
+//!
+//! EPT translates guest physical addresses of a virtual machine into host physical addresses. The
+//! translator can be used on its own to access guest physical memory from a host side connector,
+//! or be composed with a guest translator through
+//! [`NestedTranslate`](crate::mem::NestedTranslate) to access guest virtual memory.
+//!
+//! Only 4-level EPT is supported. 2MB and 1GB leaves are translated by the page walk, the
+//! memory type of a leaf can be inspected with [`leaf_entry`].
+
+use super::{
+    super::{ArchitectureObj, Endianess},
+    X86Architecture, X86VirtualTranslate,
+};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::virt_translate::mmu::ArchMmuDef;
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address};
+
+pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
+    bits: 64,
+    mmu: ArchMmuDef {
+        virtual_address_splits: &[9, 9, 9, 9, 12],
+        valid_final_page_steps: &[2, 3, 4],
+        address_space_bits: 52,
+        endianess: Endianess::LittleEndian,
+        addr_size: 8,
+        pte_size: 8,
+        present_bit: |a| a.to_umem() & 0b111 != 0,
+        writeable_bit: |a, _| a.bit_at(1),
+        nx_bit: |a, _| !a.bit_at(2),
+        large_page_bit: |a| a.bit_at(7),
+        pte_fixup: |a| a,
+    }
+    .into_spec(),
+};
+
+pub static ARCH: ArchitectureObj = &ARCH_SPEC;
+
+/// Creates a translator from the physical address of the EPT PML4 table.
+pub fn new_translator(pml4: Address) -> X86VirtualTranslate {
+    X86VirtualTranslate::new(&ARCH_SPEC, pml4)
+}
+
+/// Creates a translator from the raw EPT pointer (EPTP) of a VMCS.
+///
+/// Fails if the pointer does not describe a 4-level page walk.
+pub fn new_translator_from_eptp(eptp: u64) -> Result<X86VirtualTranslate> {
+    let walk_length = ((eptp >> 3) & 0b111) + 1;
+    if walk_length != 4 {
+        return Err(Error(ErrorOrigin::Mmu, ErrorKind::NotSupported));
+    }
+    Ok(new_translator(Address::from(eptp & EPT_ADDRESS_MASK)))
+}
+
+const EPT_ADDRESS_MASK: u64 = ((1 << 52) - 1) & !0xfff;
+
+/// Memory type of an EPT leaf entry.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EptMemoryType {
+    Uncacheable = 0,
+    WriteCombining = 1,
+    WriteThrough = 4,
+    WriteProtected = 5,
+    WriteBack = 6,
+}
+
+impl EptMemoryType {
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(EptMemoryType::Uncacheable),
+            1 => Some(EptMemoryType::WriteCombining),
+            4 => Some(EptMemoryType::WriteThrough),
+            5 => Some(EptMemoryType::WriteProtected),
+            6 => Some(EptMemoryType::WriteBack),
+            _ => None,
+        }
+    }
+}
+
+/// A raw EPT entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EptEntry(pub u64);
+
+impl EptEntry {
+    pub fn is_present(&self) -> bool {
+        self.0 & 0b111 != 0
+    }
+
+    pub fn readable(&self) -> bool {
+        self.0 & (1 << 0) != 0
+    }
+
+    pub fn writeable(&self) -> bool {
+        self.0 & (1 << 1) != 0
+    }
+
+    pub fn executable(&self) -> bool {
+        self.0 & (1 << 2) != 0
+    }
+
+    /// Returns the memory type of a leaf entry, `None` if the type is reserved.
+    pub fn memory_type(&self) -> Option<EptMemoryType> {
+        EptMemoryType::from_bits(((self.0 >> 3) & 0b111) as u8)
+    }
+
+    /// Returns `true` if the guest PAT is ignored for this leaf.
+    pub fn ignore_pat(&self) -> bool {
+        self.0 & (1 << 6) != 0
+    }
+
+    pub fn is_large(&self) -> bool {
+        self.0 & (1 << 7) != 0
+    }
+
+    pub fn accessed(&self) -> bool {
+        self.0 & (1 << 8) != 0
+    }
+
+    pub fn dirty(&self) -> bool {
+        self.0 & (1 << 9) != 0
+    }
+
+    /// Returns the physical address of the next table or of the page.
+    pub fn address(&self) -> Address {
+        Address::from(self.0 & EPT_ADDRESS_MASK)
+    }
+}
+
+/// Walks the EPT of `pml4` and returns the leaf entry mapping `gpa`, along with the size of the
+/// page it maps.
+pub fn leaf_entry<T: PhysicalMemory>(
+    mem: &mut T,
+    pml4: Address,
+    gpa: Address,
+) -> Result<(EptEntry, umem)> {
+    let mut table = pml4;
+    let mut level = 4;
+
+    loop {
+        let shift = 12 + 9 * (level - 1);
+        let idx = (gpa.to_umem() >> shift) & 0x1ff;
+
+        let mut buf = [0u8; 8];
+        mem.phys_read_into((table + idx * 8).into(), &mut buf)?;
+        let entry = EptEntry(u64::from_le_bytes(buf));
+
+        if !entry.is_present() {
+            return Err(Error(ErrorOrigin::Mmu, ErrorKind::OutOfMemoryRange));
+        }
+
+        if level == 1 || (level <= 3 && entry.is_large()) {
+            return Ok((entry, 1 << shift));
+        }
+
+        table = entry.address();
+        level -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::VirtualTranslate3;
+    use crate::types::{mem, size, PageType};
+
+    const RWX: u64 = 0b111;
+    const LARGE: u64 = 1 << 7;
+    const WB: u64 = 6 << 3;
+
+    fn write_entry(mem: &mut DummyMemory, table: u64, idx: u64, entry: u64) {
+        mem.phys_write(Address::from(table + idx * 8).into(), &entry.to_le_bytes())
+            .unwrap();
+    }
+
+    fn tables() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::mb(1));
+        write_entry(&mut mem, 0x1000, 0, 0x2000 | RWX);
+        write_entry(&mut mem, 0x2000, 0, 0x3000 | RWX);
+        // 2mb leaf at gpa 0x20_0000
+        write_entry(&mut mem, 0x3000, 1, 0x60_0000 | RWX | LARGE | WB);
+        // read only 1gb leaf at gpa 0x4000_0000
+        write_entry(&mut mem, 0x2000, 1, 0x8000_0000 | 0b001 | LARGE);
+        mem
+    }
+
+    #[test]
+    fn large_leaves() {
+        let mut mem = tables();
+        let translator = new_translator_from_eptp(0x1000 | (3 << 3) | 6).unwrap();
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x20_1234))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x60_1234));
+        assert_eq!(page.page_size(), mem::mb(2));
+        assert_eq!(page.page_type(), PageType::WRITEABLE);
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x4000_5000))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x8000_5000));
+        assert_eq!(page.page_size(), mem::gb(1));
+        assert_eq!(page.page_type(), PageType::READ_ONLY | PageType::NOEXEC);
+
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0x8000_0000))
+            .is_err());
+        assert!(new_translator_from_eptp(0x1000 | (4 << 3) | 6).is_err());
+    }
+
+    #[test]
+    fn memory_types() {
+        let mut mem = tables();
+
+        let (entry, size) =
+            leaf_entry(&mut mem, Address::from(0x1000), Address::from(0x20_1234)).unwrap();
+        assert_eq!(entry.memory_type(), Some(EptMemoryType::WriteBack));
+        assert_eq!(entry.address(), Address::from(0x60_0000));
+        assert_eq!(size, mem::mb(2));
+
+        let (entry, size) =
+            leaf_entry(&mut mem, Address::from(0x1000), Address::from(0x4000_0000)).unwrap();
+        assert_eq!(entry.memory_type(), Some(EptMemoryType::Uncacheable));
+        assert!(entry.readable() && !entry.writeable() && !entry.executable());
+        assert_eq!(size, mem::gb(1));
+
+        assert!(leaf_entry(&mut mem, Address::from(0x1000), Address::from(0x8000_0000)).is_err());
+    }
+}
@@ -1,11 +1,20 @@
+pub mod ept;
+pub mod npt;
 pub mod x32;
 pub mod x32_pae;
 pub mod x64;
 
+pub mod walk;
+pub use walk::PageTableEntry;
+
+#[cfg(feature = "pte_write")]
+pub mod pte_write;
+
 use super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
 
 use crate::mem::virt_translate::{
-    mmu::ArchMmuSpec, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    mmu::ArchMmuSpec, TranslationFailure, VirtualTranslate3, VtopFailureCallback,
+    VtopOutputCallback,
 };
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
@@ -82,6 +91,14 @@ impl VirtualTranslate3 for X86VirtualTranslate {
             .virt_to_phys_iter(mem, self.dtb, addrs, out, out_fail, tmp_buf)
     }
 
+    fn diagnose<T: PhysicalMemory>(
+        &self,
+        mem: &mut T,
+        address: Address,
+    ) -> Option<TranslationFailure> {
+        self.diagnose_access(mem, address, false)
+    }
+
     fn translation_table_id(&self, _address: Address) -> umem {
         self.dtb.to_umem().overflowing_shr(12).0
     }
@@ -100,6 +117,10 @@ fn underlying_arch(arch: ArchitectureObj) -> Option<&'static X86Architecture> {
         Some(&x32::ARCH_SPEC)
     } else if arch == x32_pae::ARCH {
         Some(&x32_pae::ARCH_SPEC)
+    } else if arch == ept::ARCH {
+        Some(&ept::ARCH_SPEC)
+    } else if arch == npt::ARCH {
+        Some(&npt::ARCH_SPEC)
     } else {
         None
     }
@@ -0,0 +1,87 @@
+//! AMD nested page tables (NPT, also known as RVI).
+//!
+//! NPT translates guest physical addresses of a SVM guest into host physical addresses. Unlike
+//! [`ept`](super::ept), nested page tables use the regular long mode page table format, with the
+//! no-execute bit at bit 63. All guest accesses are treated as user accesses by the hardware, so
+//! entries without the user bit are not considered present.
+//!
+//! Only nested page tables of long mode hosts are supported. The translator is created from the
+//! `nCR3` field of the VMCB.
+
+use super::{
+    super::{ArchitectureObj, Endianess},
+    X86Architecture, X86VirtualTranslate,
+};
+
+use crate::mem::virt_translate::mmu::ArchMmuDef;
+
+use crate::types::{size, Address};
+
+pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
+    bits: 64,
+    mmu: ArchMmuDef {
+        virtual_address_splits: &[9, 9, 9, 9, 12],
+        valid_final_page_steps: &[2, 3, 4],
+        address_space_bits: 52,
+        endianess: Endianess::LittleEndian,
+        addr_size: 8,
+        pte_size: 8,
+        present_bit: |a| a.bit_at(0) && a.bit_at(2),
+        writeable_bit: |a, pb| pb || a.bit_at(1),
+        nx_bit: |a, pb| pb || a.bit_at(63),
+        large_page_bit: |a| a.bit_at(7),
+        pte_fixup: |a| a,
+    }
+    .into_spec(),
+};
+
+pub static ARCH: ArchitectureObj = &ARCH_SPEC;
+
+/// Creates a translator from the `nCR3` field of a VMCB.
+pub fn new_translator(ncr3: Address) -> X86VirtualTranslate {
+    // bits 0..12 of nCR3 hold the cache control bits of the top level table
+    X86VirtualTranslate::new(&ARCH_SPEC, ncr3.as_page_aligned(size::kb(4)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{PhysicalMemory, VirtualTranslate3};
+    use crate::types::{mem, PageType};
+
+    const P: u64 = 1 << 0;
+    const W: u64 = 1 << 1;
+    const U: u64 = 1 << 2;
+    const LARGE: u64 = 1 << 7;
+    const NX: u64 = 1 << 63;
+
+    fn write_entry(mem: &mut DummyMemory, table: u64, idx: u64, entry: u64) {
+        mem.phys_write(Address::from(table + idx * 8).into(), &entry.to_le_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn nested_pages() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        write_entry(&mut mem, 0x1000, 0, 0x2000 | P | W | U);
+        write_entry(&mut mem, 0x2000, 0, 0x3000 | P | W | U);
+        // no-execute 2mb leaf at gpa 0x20_0000
+        write_entry(&mut mem, 0x3000, 1, 0x60_0000 | P | W | U | LARGE | NX);
+        // supervisor only pages are not accessible by the guest
+        write_entry(&mut mem, 0x3000, 2, 0x80_0000 | P | W | LARGE);
+
+        let translator = new_translator(Address::from(0x1000 | 0x18));
+
+        let page = translator
+            .virt_to_phys(&mut mem, Address::from(0x20_1234))
+            .unwrap();
+        assert_eq!(page.address(), Address::from(0x60_1234));
+        assert_eq!(page.page_size(), mem::mb(2));
+        assert_eq!(page.page_type(), PageType::WRITEABLE | PageType::NOEXEC);
+
+        assert!(translator
+            .virt_to_phys(&mut mem, Address::from(0x40_0000))
+            .is_err());
+    }
+}
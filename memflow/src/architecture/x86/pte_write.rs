@@ -0,0 +1,144 @@
+//! Modification of page table entries.
+//!
+//! This module is only available with the `pte_write` feature. Rewriting page tables of a live
+//! system changes the memory of the target in ways it can not anticipate, faulty entries will
+//! bring the system down.
+//!
+//! The entries are written through the physical memory passed to the functions, so physical
+//! page caches are kept coherent. The translations cached by the virtual translator passed
+//! along are dropped through [`invalidate_translations`] after every successful write. Note that
+//! the TLBs of the target itself are not flushed, the target may keep using the previous
+//! translations for a while.
+//!
+//! [`invalidate_translations`]: crate::mem::VirtualTranslate2::invalidate_translations
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::architecture::x86::x64;
+//! use memflow::dummy::{DummyMemory, DummyOs};
+//! use memflow::mem::DirectTranslate;
+//! use memflow::types::size;
+//!
+//! let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+//! let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+//! let translator = x64::new_translator(dtb);
+//! let mut vat = DirectTranslate::new();
+//!
+//! // make the first page of the mapping read only
+//! unsafe {
+//!     translator
+//!         .modify_leaf(os.as_mut(), &mut vat, virt_base, |entry| {
+//!             entry.with_writeable(false)
+//!         })
+//!         .unwrap();
+//! }
+//! ```
+
+use super::{PageTableEntry, X86VirtualTranslate};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{PhysicalMemory, VirtualTranslate2};
+use crate::types::Address;
+
+impl X86VirtualTranslate {
+    /// Replaces the raw value of `entry` with `raw` and returns the updated entry.
+    ///
+    /// The translations cached by `vat` are invalidated once the entry was written.
+    ///
+    /// # Safety
+    ///
+    /// The page tables of the target are modified. The caller has to ensure that `raw` is a valid
+    /// entry for its level and that the target can cope with the modification.
+    pub unsafe fn write_entry<T: PhysicalMemory, V: VirtualTranslate2>(
+        &self,
+        mem: &mut T,
+        vat: &mut V,
+        entry: &PageTableEntry,
+        raw: u64,
+    ) -> Result<PageTableEntry> {
+        let layout = self.layout();
+        mem.phys_write(entry.entry_addr.into(), layout.encode(raw).as_slice())?;
+        vat.invalidate_translations();
+
+        // re-read the entry, so that the present and leaf state reflect the new value
+        let path = self.entry_path(mem, entry.virt)?;
+        path.into_iter()
+            .find(|e| e.entry_addr == entry.entry_addr)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Mmu, ErrorKind::NotFound)
+                    .log_warn("modified entry is no longer part of the page walk")
+            })
+    }
+
+    /// Rewrites the leaf entry translating `virt` and returns the updated entry.
+    ///
+    /// `modify` receives the current leaf entry and returns its new raw value. Fails if `virt`
+    /// is not mapped.
+    ///
+    /// # Safety
+    ///
+    /// The page tables of the target are modified, see [`X86VirtualTranslate::write_entry`].
+    pub unsafe fn modify_leaf<T: PhysicalMemory, V: VirtualTranslate2>(
+        &self,
+        mem: &mut T,
+        vat: &mut V,
+        virt: Address,
+        modify: impl FnOnce(&PageTableEntry) -> u64,
+    ) -> Result<PageTableEntry> {
+        let leaf = self
+            .entry_path(mem, virt)?
+            .pop()
+            .filter(PageTableEntry::is_leaf)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Mmu, ErrorKind::OutOfMemoryRange)
+                    .log_warn(format!("{:x} is not mapped", virt))
+            })?;
+
+        let raw = modify(&leaf);
+        self.write_entry(mem, vat, &leaf, raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::x64;
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::VirtualTranslate3;
+    use crate::mem::{CachedVirtualTranslate, DirectTranslate, VirtualDma, VirtualTranslate};
+    use crate::types::{size, PageType};
+
+    #[test]
+    fn toggle_noexec() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let translator = x64::new_translator(dtb);
+
+        let vat = CachedVirtualTranslate::builder(DirectTranslate::new())
+            .arch(x64::ARCH)
+            .build()
+            .unwrap();
+        let mut virt_mem = VirtualDma::with_vat(os.into_inner(), x64::ARCH, translator, vat);
+
+        let page = VirtualTranslate::virt_to_phys(&mut virt_mem, virt_base).unwrap();
+        assert!(!page.page_type.contains(PageType::NOEXEC));
+
+        let leaf = unsafe {
+            let (mem, vat) = virt_mem.mem_vat_pair();
+            translator
+                .modify_leaf(mem, vat, virt_base, |e| e.with_noexec(true))
+                .unwrap()
+        };
+        assert!(leaf.noexec() && leaf.is_leaf());
+
+        let page = translator
+            .virt_to_phys(virt_mem.phys_mem(), virt_base)
+            .unwrap();
+        assert!(page.page_type.contains(PageType::NOEXEC));
+
+        // the cached translation was dropped by the modification
+        let page = VirtualTranslate::virt_to_phys(&mut virt_mem, virt_base).unwrap();
+        assert!(page.page_type.contains(PageType::NOEXEC));
+    }
+}
@@ -0,0 +1,480 @@
+//! Page table walks with access to the raw entries.
+//!
+//! The regular translation functions only return the final physical pages. For auditing page
+//! tables, e.g. looking for writeable and executable pages or for code that is mapped without
+//! belonging to any module, the raw entries of every level are needed instead.
+//!
+//! [`X86VirtualTranslate::walk_page_tables`] visits every entry of the page tables of a
+//! translator, from the PML4 (or page directory) down to the page table entries.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::architecture::x86::{x64, PageTableEntry};
+//! use memflow::dummy::{DummyMemory, DummyOs};
+//!
+//! let mut os = DummyOs::new(DummyMemory::new(memflow::types::size::mb(16)));
+//! let (dtb, _) = os.alloc_dtb(memflow::types::size::mb(2), &[]);
+//! let translator = x64::new_translator(dtb);
+//!
+//! // find all writeable and executable pages
+//! let mut wx = vec![];
+//! translator
+//!     .walk_page_tables(os.as_mut(), &mut |entry: PageTableEntry| {
+//!         if entry.is_leaf() && entry.writeable() && !entry.noexec() {
+//!             wx.push(entry.virt);
+//!         }
+//!         true
+//!     })
+//!     .unwrap();
+//! ```
+
+use super::{x32_pae, x64, X86VirtualTranslate};
+
+use crate::architecture::Endianess;
+use crate::error::Result;
+use crate::mem::virt_translate::mmu::ArchMmuSpec;
+use crate::mem::PhysicalMemory;
+use crate::mem::{TranslationFailReason, TranslationFailure};
+use crate::types::{umem, Address};
+
+use std::convert::TryInto;
+use std::prelude::v1::*;
+use std::ptr;
+
+/// A raw page table entry visited during a page table walk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageTableEntry {
+    /// Paging level of the entry, 1 being the page table entry and higher levels being the
+    /// page directory (2), the page directory pointer table (3) and the PML4 (4).
+    pub level: u8,
+    /// Index of the entry in its table.
+    pub index: usize,
+    /// Physical address of the entry.
+    pub entry_addr: Address,
+    /// Raw value of the entry.
+    pub raw: u64,
+    /// First virtual address translated through the entry.
+    pub virt: Address,
+    /// Size of the virtual memory translated through the entry.
+    pub size: umem,
+    present: bool,
+    leaf: bool,
+}
+
+impl PageTableEntry {
+    /// Returns `true` if the present bit of the entry is set.
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    /// Returns `true` if the entry is present and maps a page instead of a table.
+    pub fn is_leaf(&self) -> bool {
+        self.leaf
+    }
+
+    /// Returns `true` if the entry maps a page that is larger than the smallest page size.
+    pub fn is_large(&self) -> bool {
+        self.leaf && self.level > 1
+    }
+
+    /// Returns the state of the write bit of this entry alone.
+    pub fn writeable(&self) -> bool {
+        self.raw & (1 << 1) != 0
+    }
+
+    /// Returns the state of the user/supervisor bit of this entry alone.
+    pub fn user(&self) -> bool {
+        self.raw & (1 << 2) != 0
+    }
+
+    /// Returns the state of the no-execute bit of this entry alone.
+    ///
+    /// Always `false` for 32-bit tables without PAE.
+    pub fn noexec(&self) -> bool {
+        self.raw & (1 << 63) != 0
+    }
+
+    /// Returns the physical address of the page or table the entry points to.
+    pub fn target(&self) -> Address {
+        Address::from(self.raw & self.target_mask())
+    }
+
+    /// Returns the raw value of the entry with the write bit set to `writeable`.
+    pub fn with_writeable(&self, writeable: bool) -> u64 {
+        with_bit(self.raw, 1, writeable)
+    }
+
+    /// Returns the raw value of the entry with the no-execute bit set to `noexec`.
+    pub fn with_noexec(&self, noexec: bool) -> u64 {
+        with_bit(self.raw, 63, noexec)
+    }
+
+    /// Returns the raw value of the entry pointing to `target` instead, all flags are kept.
+    ///
+    /// `target` is truncated to the alignment of the page or table.
+    pub fn with_target(&self, target: Address) -> u64 {
+        (self.raw & !self.target_mask()) | (target.to_umem() as u64 & self.target_mask())
+    }
+
+    fn target_mask(&self) -> u64 {
+        let mask = if self.leaf { self.size - 1 } else { 0xfff };
+        ((1 << 52) - 1) & !(mask as u64)
+    }
+}
+
+fn with_bit(raw: u64, bit: u32, set: bool) -> u64 {
+    if set {
+        raw | (1 << bit)
+    } else {
+        raw & !(1 << bit)
+    }
+}
+
+impl X86VirtualTranslate {
+    /// Visits every entry of the page tables of this translator.
+    ///
+    /// Entries are visited in order, the entries of a table directly follow the entry pointing
+    /// to it. Entries that are not present are visited as well. The walk stops as soon as
+    /// `visitor` returns `false`.
+    ///
+    /// Tables that can not be read are skipped, only a failed read of the top level table
+    /// results in an error. Permission bits are not inherited from the upper levels, the final
+    /// permissions of a page are the combination of all entries on the way to it.
+    pub fn walk_page_tables<T: PhysicalMemory>(
+        &self,
+        mem: &mut T,
+        visitor: &mut impl FnMut(PageTableEntry) -> bool,
+    ) -> Result<()> {
+        let mut walker = Walker {
+            layout: self.layout(),
+            mem,
+            visitor,
+            stopped: false,
+        };
+        walker.table(self.dtb, 0, 0)
+    }
+
+    /// Returns the entries translating `virt`, from the top level table down to the leaf.
+    ///
+    /// The walk ends at the first entry that is not present, which is returned as the last
+    /// entry of the path in that case.
+    pub fn entry_path<T: PhysicalMemory>(
+        &self,
+        mem: &mut T,
+        virt: Address,
+    ) -> Result<Vec<PageTableEntry>> {
+        let mut path = vec![];
+        self.read_path(mem, virt, &mut path)?;
+        Ok(path)
+    }
+
+    /// Determines why `virt` can not be translated, `None` if it can.
+    ///
+    /// With `user` set, entries that do not allow accesses from user mode are reported as well.
+    /// Only reserved bits that are independent of the processor configuration are checked.
+    pub fn diagnose_access<T: PhysicalMemory>(
+        &self,
+        mem: &mut T,
+        virt: Address,
+        user: bool,
+    ) -> Option<TranslationFailure> {
+        let failure = |reason, level, entry| {
+            Some(TranslationFailure {
+                from: virt,
+                size: 1,
+                reason,
+                level,
+                entry,
+            })
+        };
+
+        let layout = self.layout();
+        if !layout.in_address_space(virt) {
+            return failure(TranslationFailReason::OutOfAddressSpace, 0, 0);
+        }
+
+        let mut path = vec![];
+        if self.read_path(mem, virt, &mut path).is_err() {
+            let level = path
+                .last()
+                .map(|e| e.level - 1)
+                .unwrap_or((layout.mmu.split_count() - 1) as u8);
+            return failure(TranslationFailReason::Unreadable, level, 0);
+        }
+
+        for entry in path.iter() {
+            if !entry.is_present() {
+                return failure(TranslationFailReason::NotPresent, entry.level, entry.raw);
+            }
+            if entry.raw & self.reserved_bits(entry.level) != 0 {
+                return failure(TranslationFailReason::ReservedBits, entry.level, entry.raw);
+            }
+            if user && !entry.user() {
+                return failure(
+                    TranslationFailReason::UserSupervisor,
+                    entry.level,
+                    entry.raw,
+                );
+            }
+        }
+
+        None
+    }
+
+    fn read_path<T: PhysicalMemory>(
+        &self,
+        mem: &mut T,
+        virt: Address,
+        path: &mut Vec<PageTableEntry>,
+    ) -> Result<()> {
+        let layout = self.layout();
+        let mmu = layout.mmu;
+        let virt = virt.to_umem() & layout.virt_mask();
+
+        let mut pt_addr = self.dtb;
+
+        for step in 0..mmu.split_count() - 1 {
+            let table = Address::from(mmu.pte_addr_mask(pt_addr, step));
+            let index = (mmu.virt_addr_to_pte_offset(Address::from(virt), step) as usize)
+                / mmu.def.pte_size;
+
+            let mut buf = [0u8; 8];
+            let buf = &mut buf[..mmu.def.pte_size];
+            mem.phys_read_into((table + index * mmu.def.pte_size).into(), buf)?;
+
+            let shift = mmu.virt_addr_bit_ranges[step].0;
+            let entry = layout.entry(
+                table,
+                step,
+                index,
+                layout.decode(buf),
+                virt >> shift << shift,
+            );
+            path.push(entry);
+
+            if !entry.is_present() || entry.is_leaf() {
+                break;
+            }
+            pt_addr = (mmu.def.pte_fixup)(Address::from(entry.raw));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the bits of an entry at `level` that have to be zero regardless of the
+    /// processor configuration.
+    fn reserved_bits(&self, level: u8) -> u64 {
+        if ptr::eq(self.arch, &x64::ARCH_SPEC) && level == 4 {
+            // the page size bit of a PML4E
+            1 << 7
+        } else if ptr::eq(self.arch, &x32_pae::ARCH_SPEC) && level == 3 {
+            // PAE PDPTEs have no access rights
+            0x1e6
+        } else {
+            0
+        }
+    }
+
+    pub(super) fn layout(&self) -> Layout {
+        Layout {
+            mmu: &self.arch.mmu,
+            // x64 addresses have to be sign extended to be canonical
+            canonical: ptr::eq(self.arch, &x64::ARCH_SPEC),
+        }
+    }
+}
+
+/// Decoding of the entries of a page table layout.
+#[derive(Clone, Copy)]
+pub(super) struct Layout {
+    pub(super) mmu: &'static ArchMmuSpec,
+    canonical: bool,
+}
+
+impl Layout {
+    fn entry(
+        &self,
+        table: Address,
+        step: usize,
+        index: usize,
+        raw: u64,
+        virt: umem,
+    ) -> PageTableEntry {
+        let pte = (self.mmu.def.pte_fixup)(Address::from(raw));
+        let present = self.mmu.check_entry(pte, step + 1);
+
+        PageTableEntry {
+            level: (self.mmu.split_count() - 1 - step) as u8,
+            index,
+            entry_addr: table + index * self.mmu.def.pte_size,
+            raw,
+            virt: self.virt_address(virt),
+            size: 1 << self.mmu.virt_addr_bit_ranges[step].0,
+            present,
+            leaf: present && self.mmu.is_final_mapping(pte, step + 1),
+        }
+    }
+
+    pub(super) fn encode(&self, raw: u64) -> Vec<u8> {
+        match (self.mmu.def.endianess, self.mmu.def.pte_size) {
+            (Endianess::LittleEndian, 8) => raw.to_le_bytes().to_vec(),
+            (Endianess::BigEndian, 8) => raw.to_be_bytes().to_vec(),
+            (Endianess::LittleEndian, _) => (raw as u32).to_le_bytes().to_vec(),
+            (Endianess::BigEndian, _) => (raw as u32).to_be_bytes().to_vec(),
+        }
+    }
+
+    pub(super) fn decode(&self, bytes: &[u8]) -> u64 {
+        match (self.mmu.def.endianess, bytes.len()) {
+            (Endianess::LittleEndian, 8) => u64::from_le_bytes(bytes.try_into().unwrap()),
+            (Endianess::BigEndian, 8) => u64::from_be_bytes(bytes.try_into().unwrap()),
+            (Endianess::LittleEndian, _) => u32::from_le_bytes(bytes.try_into().unwrap()) as u64,
+            (Endianess::BigEndian, _) => u32::from_be_bytes(bytes.try_into().unwrap()) as u64,
+        }
+    }
+
+    fn in_address_space(&self, virt: Address) -> bool {
+        let high = virt.to_umem() & !self.virt_mask();
+        let bits = self.mmu.virt_addr_bit_ranges[0].1;
+
+        if self.canonical && virt.bit_at(bits - 1) {
+            high == !self.virt_mask()
+        } else {
+            high == 0
+        }
+    }
+
+    fn virt_mask(&self) -> umem {
+        let bits = self.mmu.virt_addr_bit_ranges[0].1;
+        Address::bit_mask_u8(0..(bits - 1)).to_umem()
+    }
+
+    fn virt_address(&self, virt: umem) -> Address {
+        let bits = self.mmu.virt_addr_bit_ranges[0].1 as u32;
+        if self.canonical && (virt >> (bits - 1)) & 1 != 0 {
+            Address::from(virt | (!0 << bits))
+        } else {
+            Address::from(virt)
+        }
+    }
+}
+
+struct Walker<'a, T, V> {
+    layout: Layout,
+    mem: &'a mut T,
+    visitor: &'a mut V,
+    stopped: bool,
+}
+
+impl<'a, T: PhysicalMemory, V: FnMut(PageTableEntry) -> bool> Walker<'a, T, V> {
+    fn table(&mut self, pt_addr: Address, step: usize, virt_base: umem) -> Result<()> {
+        let mmu = self.layout.mmu;
+        let table = Address::from(mmu.pte_addr_mask(pt_addr, step));
+
+        let mut buf = vec![0u8; mmu.pt_leaf_size(step)];
+        self.mem.phys_read_into(table.into(), buf.as_mut_slice())?;
+
+        let shift = mmu.virt_addr_bit_ranges[step].0;
+
+        for (index, bytes) in buf.chunks_exact(mmu.def.pte_size).enumerate() {
+            let virt = virt_base | ((index as umem) << shift);
+            let entry = self
+                .layout
+                .entry(table, step, index, self.layout.decode(bytes), virt);
+
+            if !(self.visitor)(entry) {
+                self.stopped = true;
+            } else if entry.is_present() && !entry.is_leaf() {
+                // the contents of lower level tables are best effort
+                let pte = (mmu.def.pte_fixup)(Address::from(entry.raw));
+                let _ = self.table(pte, step + 1, virt);
+            }
+
+            if self.stopped {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::{VirtualDma, VirtualTranslate, VirtualTranslate3};
+    use crate::types::size;
+
+    #[test]
+    fn walk_leaves() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let translator = x64::new_translator(dtb);
+
+        let mut entries = vec![];
+        translator
+            .walk_page_tables(os.as_mut(), &mut |entry| {
+                entries.push(entry);
+                true
+            })
+            .unwrap();
+
+        let leaves = entries.iter().filter(|e| e.is_leaf()).collect::<Vec<_>>();
+        assert_eq!(
+            leaves.iter().map(|e| e.size).sum::<umem>(),
+            size::mb(2) as umem
+        );
+        assert_eq!(leaves.iter().map(|e| e.virt).min(), Some(virt_base));
+        assert_eq!(entries.iter().filter(|e| e.level == 4).count(), 512);
+
+        for leaf in leaves {
+            let page = translator.virt_to_phys(os.as_mut(), leaf.virt).unwrap();
+            assert_eq!(page.address(), leaf.target());
+            assert_eq!(page.page_size(), leaf.size);
+        }
+
+        let mut visited = 0;
+        translator
+            .walk_page_tables(os.as_mut(), &mut |_| {
+                visited += 1;
+                visited < 3
+            })
+            .unwrap();
+        assert_eq!(visited, 3);
+    }
+
+    #[test]
+    fn diagnose_failures() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let translator = x64::new_translator(dtb);
+
+        assert_eq!(translator.diagnose(os.as_mut(), virt_base), None);
+
+        let failure = translator
+            .diagnose(os.as_mut(), virt_base - 1usize)
+            .unwrap();
+        assert_eq!(failure.reason, TranslationFailReason::NotPresent);
+        assert!(failure.level >= 3);
+
+        let non_canonical = Address::from(0x0000_8000_0000_0000u64);
+        let failure = translator.diagnose(os.as_mut(), non_canonical).unwrap();
+        assert_eq!(failure.reason, TranslationFailReason::OutOfAddressSpace);
+
+        // the dummy maps everything as supervisor only
+        let failure = translator
+            .diagnose_access(os.as_mut(), virt_base, true)
+            .unwrap();
+        assert_eq!(failure.reason, TranslationFailReason::UserSupervisor);
+        assert_eq!(failure.level, 4);
+
+        let mut virt_mem = VirtualDma::new(os.into_inner(), x64::ARCH, translator);
+        let failure = virt_mem
+            .virt_translation_failure(virt_base + size::mb(2))
+            .unwrap();
+        assert_eq!(failure.from, virt_base + size::mb(2));
+        assert_eq!(failure.reason, TranslationFailReason::NotPresent);
+    }
+}
@@ -0,0 +1,472 @@
+/*!
+Connector for 64-bit Windows crash dumps.
+
+Windows writes crash dumps (`MEMORY.DMP`, or dumps created with tools like DumpIt) in one of
+two layouts, both starting with a `PAGEDU64` header of 0x2000 bytes:
+
+* Full dumps store all pages of the physical memory descriptor runs of the header back to
+  back, in the order of the runs.
+* Bitmap dumps (full bitmap dumps, kernel and automatic dumps) have a second header
+  following the first one, with a bitmap of all page frames stored in the file. The present
+  pages follow in the order of their page frame numbers.
+
+[`CrashDumpMemory`] maps the pages of the dump to their physical addresses. Pages missing from
+the dump, e.g. user space pages in a kernel dump, fail to be read. The kernel information
+stored in the header, like the directory table base and the address of `PsLoadedModuleList`,
+is available through [`CrashDumpMemory::header`].
+
+32-bit (`PAGEDUMP`) dumps, mini dumps and triage dumps are not supported.
+
+# Examples
+
+```no_run
+use memflow::connector::CrashDumpMemory;
+use memflow::mem::{MemoryView, PhysicalMemory};
+
+let mut mem = CrashDumpMemory::open("MEMORY.DMP").unwrap();
+let dtb = mem.header().dtb;
+println!("dtb: {:x}", dtb);
+
+let pml4e: u64 = mem.phys_view().read(dtb).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureIdent;
+use crate::connector::{CloneFile, FileIoMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{size, umem, Address};
+
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::cglue::*;
+
+/// Signature at the start of every 64-bit crash dump.
+pub const DUMP_SIGNATURE: [u8; 8] = *b"PAGEDU64";
+
+const HEADER_SIZE: u64 = 0x2000;
+const PAGE_SIZE: umem = size::kb(4) as umem;
+
+const PHYSICAL_MEMORY_BLOCK: usize = 0x88;
+const CONTEXT_RECORD: usize = 0x348;
+const DUMP_TYPE: usize = 0xf98;
+
+const MAX_RUNS: usize = (CONTEXT_RECORD - PHYSICAL_MEMORY_BLOCK - 0x10) / 0x10;
+
+const BITMAP_HEADER_SIZE: usize = 0x38;
+
+const DUMP_TYPE_FULL: u32 = 1;
+
+/// The layout of the pages in a crash dump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashDumpType {
+    /// All pages of the physical memory runs, back to back.
+    Full,
+    /// All pages of physical memory, stored as a bitmap dump.
+    BitmapFull,
+    /// Kernel pages only, stored as a bitmap dump.
+    BitmapKernel,
+}
+
+/// The header of a crash dump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrashDumpHeader {
+    pub major_version: u32,
+    pub minor_version: u32,
+    /// Directory table base of the process that was running at the time of the crash.
+    pub dtb: Address,
+    pub pfn_database: Address,
+    pub ps_loaded_module_list: Address,
+    pub ps_active_process_head: Address,
+    pub kd_debugger_data_block: Address,
+    /// `IMAGE_FILE_MACHINE_*` value of the target.
+    pub machine_type: u32,
+    pub processors: u32,
+    pub bugcheck_code: u32,
+    pub bugcheck_parameters: [u64; 4],
+    pub dump_type: CrashDumpType,
+    /// Physical memory runs of the target as (base, size) pairs.
+    ///
+    /// Bitmap dumps do not necessarily contain all pages of the runs.
+    pub runs: Vec<(Address, umem)>,
+}
+
+impl CrashDumpHeader {
+    /// Returns the architecture of the target, if it is known to memflow.
+    pub fn arch(&self) -> Option<ArchitectureIdent> {
+        match self.machine_type {
+            0x8664 => Some(ArchitectureIdent::X86(64, false)),
+            0xaa64 => Some(ArchitectureIdent::AArch64(size::kb(4))),
+            _ => None,
+        }
+    }
+
+    /// Reads the headers of the crash dump in `reader` and maps its pages.
+    ///
+    /// Returns the header and a map of physical addresses to file offsets.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<(Self, MemoryMap<(Address, umem)>)> {
+        let mut header = vec![0u8; HEADER_SIZE as usize];
+        let mut bitmap_header = [0u8; BITMAP_HEADER_SIZE];
+
+        let file_size = reader
+            .seek(SeekFrom::End(0))
+            .and_then(|size| {
+                reader.seek(SeekFrom::Start(0))?;
+                reader.read_exact(&mut header)?;
+                reader.read_exact(&mut bitmap_header)?;
+                Ok(size)
+            })
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                    .log_error(format!("unable to read the crash dump: {}", err))
+            })?;
+
+        if header[..8] != DUMP_SIGNATURE {
+            let err = if header[..8] == *b"PAGEDUMP" {
+                Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+                    .log_error("32-bit crash dumps are not supported")
+            } else {
+                Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                    .log_error("the file is not a 64-bit crash dump")
+            };
+            return Err(err);
+        }
+
+        let run_count = u32_at(&header, PHYSICAL_MEMORY_BLOCK) as usize;
+        if run_count > MAX_RUNS {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("invalid physical memory descriptor in the crash dump"));
+        }
+
+        let runs = (0..run_count)
+            .map(|i| {
+                let run = PHYSICAL_MEMORY_BLOCK + 0x10 + i * 0x10;
+                (
+                    Address::from((u64_at(&header, run) as umem).saturating_mul(PAGE_SIZE)),
+                    (u64_at(&header, run + 8) as umem).saturating_mul(PAGE_SIZE),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let (dump_type, mem_map) = match &bitmap_header[..8] {
+            b"FDMPDUMP" => (
+                CrashDumpType::BitmapFull,
+                bitmap_map(reader, &bitmap_header, file_size)?,
+            ),
+            b"SDMPDUMP" => (
+                CrashDumpType::BitmapKernel,
+                bitmap_map(reader, &bitmap_header, file_size)?,
+            ),
+            _ if u32_at(&header, DUMP_TYPE) == DUMP_TYPE_FULL => {
+                (CrashDumpType::Full, runs_map(&runs, file_size))
+            }
+            _ => {
+                return Err(
+                    Error(ErrorOrigin::Connector, ErrorKind::NotSupported).log_error(format!(
+                        "unsupported crash dump type {}",
+                        u32_at(&header, DUMP_TYPE)
+                    )),
+                )
+            }
+        };
+
+        let header = Self {
+            major_version: u32_at(&header, 0x8),
+            minor_version: u32_at(&header, 0xc),
+            dtb: u64_at(&header, 0x10).into(),
+            pfn_database: u64_at(&header, 0x18).into(),
+            ps_loaded_module_list: u64_at(&header, 0x20).into(),
+            ps_active_process_head: u64_at(&header, 0x28).into(),
+            kd_debugger_data_block: u64_at(&header, 0x80).into(),
+            machine_type: u32_at(&header, 0x30),
+            processors: u32_at(&header, 0x34),
+            bugcheck_code: u32_at(&header, 0x38),
+            bugcheck_parameters: [
+                u64_at(&header, 0x40),
+                u64_at(&header, 0x48),
+                u64_at(&header, 0x50),
+                u64_at(&header, 0x58),
+            ],
+            dump_type,
+            runs,
+        };
+
+        Ok((header, mem_map))
+    }
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// Maps the runs of a full dump, truncated dumps are mapped as far as they go.
+fn runs_map(runs: &[(Address, umem)], file_size: u64) -> MemoryMap<(Address, umem)> {
+    let mut mem_map = MemoryMap::new();
+    let mut file_off = HEADER_SIZE as umem;
+
+    for &(base, size) in runs {
+        let size = std::cmp::min(size, (file_size as umem).saturating_sub(file_off));
+        if size > 0 {
+            mem_map.push_remap(base, size, file_off.into());
+        }
+        file_off += size;
+    }
+
+    mem_map
+}
+
+/// Maps the present pages of a bitmap dump, contiguous page frames are merged into one range.
+fn bitmap_map<R: Read + Seek>(
+    reader: &mut R,
+    bitmap_header: &[u8],
+    file_size: u64,
+) -> Result<MemoryMap<(Address, umem)>> {
+    let first_page = u64_at(bitmap_header, 0x20);
+    let bits = u64_at(bitmap_header, 0x28);
+
+    let bitmap_len = bits
+        .checked_add(7)
+        .map(|bits| bits / 8)
+        .filter(|&len| first_page <= file_size && len <= file_size.saturating_sub(HEADER_SIZE))
+        .ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("invalid bitmap header in the crash dump")
+        })?;
+
+    let mut bitmap = vec![0u8; bitmap_len as usize];
+    reader.read_exact(&mut bitmap).map_err(|err| {
+        Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+            .log_error(format!("unable to read the crash dump bitmap: {}", err))
+    })?;
+
+    let mut mem_map = MemoryMap::new();
+    let pages_in_file = (file_size - first_page) / PAGE_SIZE as u64;
+    let mut stored = 0;
+    let mut run: Option<(u64, u64)> = None;
+
+    let is_present = |pfn: u64| {
+        bitmap
+            .get((pfn / 8) as usize)
+            .map_or(false, |byte| byte & (1 << (pfn % 8)) != 0)
+    };
+
+    for pfn in (0..bits).filter(|&pfn| is_present(pfn)) {
+        if stored == pages_in_file {
+            break;
+        }
+
+        run = match run {
+            Some((start, len)) if start + len == pfn => Some((start, len + 1)),
+            _ => {
+                push_pages(&mut mem_map, run, stored, first_page);
+                Some((pfn, 1))
+            }
+        };
+        stored += 1;
+    }
+    push_pages(&mut mem_map, run, stored, first_page);
+
+    Ok(mem_map)
+}
+
+/// Maps a run of `len` page frames starting at `start`, ending before the `stored`th page.
+fn push_pages(
+    mem_map: &mut MemoryMap<(Address, umem)>,
+    run: Option<(u64, u64)>,
+    stored: u64,
+    first_page: u64,
+) {
+    if let Some((start, len)) = run {
+        let file_off = first_page as umem + (stored - len) as umem * PAGE_SIZE;
+        mem_map.push_remap(
+            Address::from(start as umem * PAGE_SIZE),
+            len as umem * PAGE_SIZE,
+            file_off.into(),
+        );
+    }
+}
+
+/// Read-only connector over a 64-bit Windows crash dump.
+#[derive(Clone)]
+pub struct CrashDumpMemory<T> {
+    mem: FileIoMemory<T>,
+    header: CrashDumpHeader,
+}
+
+impl<T: Read + Seek + Write + Send> CrashDumpMemory<T> {
+    /// Opens the crash dump stored in `reader`.
+    pub fn new(mut reader: T) -> Result<Self> {
+        let (header, mem_map) = CrashDumpHeader::read(&mut reader)?;
+        let mem = FileIoMemory::try_with_reader(reader, mem_map)?;
+        Ok(Self { mem, header })
+    }
+
+    /// Returns the header of the crash dump.
+    pub fn header(&self) -> &CrashDumpHeader {
+        &self.header
+    }
+}
+
+impl CrashDumpMemory<CloneFile> {
+    /// Opens a crash dump file.
+    pub fn open<P: AsRef<::std::path::Path>>(path: P) -> Result<Self> {
+        let file = ::std::fs::File::open(path).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to open the crash dump: {}", err))
+        })?;
+        Self::new(file.into())
+    }
+}
+
+impl<T: Read + Seek + Write + Send> PhysicalMemory for CrashDumpMemory<T> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.mem.phys_read_raw_iter(data)
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_warn("crash dumps can not be written to"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            readonly: true,
+            ..self.mem.metadata()
+        }
+    }
+}
+
+cglue_impl_group!(
+    CrashDumpMemory<T: Read + Seek + Write + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+
+    use std::io::Cursor;
+
+    fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+        buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(buf: &mut [u8], offset: usize, value: u64) {
+        buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Creates a dump header with the given runs of (base page, page count).
+    fn header(runs: &[(u64, u64)], dump_type: u32) -> Vec<u8> {
+        let mut out = vec![0u8; HEADER_SIZE as usize];
+        out[..8].copy_from_slice(&DUMP_SIGNATURE);
+        put_u64(&mut out, 0x10, 0x1aa000);
+        put_u32(&mut out, 0x30, 0x8664);
+        put_u32(&mut out, PHYSICAL_MEMORY_BLOCK, runs.len() as u32);
+        for (i, &(base, count)) in runs.iter().enumerate() {
+            put_u64(&mut out, PHYSICAL_MEMORY_BLOCK + 0x10 + i * 0x10, base);
+            put_u64(&mut out, PHYSICAL_MEMORY_BLOCK + 0x18 + i * 0x10, count);
+        }
+        put_u32(&mut out, DUMP_TYPE, dump_type);
+        out
+    }
+
+    /// Appends a page filled with its page frame number.
+    fn page(out: &mut Vec<u8>, pfn: u8) {
+        out.extend(std::iter::repeat(pfn).take(PAGE_SIZE as usize));
+    }
+
+    fn read_page(mem: &mut impl PhysicalMemory, pfn: umem) -> Option<u8> {
+        let mut buf = [0u8; 2];
+        mem.phys_view()
+            .read_raw_into(Address::from(pfn * PAGE_SIZE + 0xfff), &mut buf[..1])
+            .ok()
+            .map(|_| buf[0])
+    }
+
+    #[test]
+    fn full_dump() {
+        let mut file = header(&[(0, 2), (0x10, 1)], DUMP_TYPE_FULL);
+        for &pfn in [0, 1, 0x10].iter() {
+            page(&mut file, pfn);
+        }
+
+        let mut mem = CrashDumpMemory::new(Cursor::new(file)).unwrap();
+        assert_eq!(mem.header().dump_type, CrashDumpType::Full);
+        assert_eq!(mem.header().dtb, Address::from(0x1aa000u64));
+        assert_eq!(mem.header().arch(), Some(ArchitectureIdent::X86(64, false)));
+        assert_eq!(
+            mem.header().runs,
+            vec![
+                (Address::null(), 2 * PAGE_SIZE),
+                (Address::from(0x10 * PAGE_SIZE), PAGE_SIZE)
+            ]
+        );
+
+        assert_eq!(read_page(&mut mem, 1), Some(1));
+        assert_eq!(read_page(&mut mem, 0x10), Some(0x10));
+        assert_eq!(read_page(&mut mem, 2), None);
+        assert!(mem.phys_write(Address::null().into(), &0u8).is_err());
+    }
+
+    #[test]
+    fn bitmap_dump() {
+        // kernel dump of pages 1, 3 and 4, stored at 0x3000
+        let mut file = header(&[(0, 8)], 6);
+        let mut bitmap_header = [0u8; BITMAP_HEADER_SIZE];
+        bitmap_header[..8].copy_from_slice(b"SDMPDUMP");
+        put_u64(&mut bitmap_header, 0x20, 0x3000);
+        put_u64(&mut bitmap_header, 0x28, 8);
+        put_u64(&mut bitmap_header, 0x30, 3);
+        file.extend_from_slice(&bitmap_header);
+        file.push(0b0001_1010);
+        file.resize(0x3000, 0);
+        for &pfn in [1, 3, 4].iter() {
+            page(&mut file, pfn);
+        }
+
+        let mut mem = CrashDumpMemory::new(Cursor::new(file.clone())).unwrap();
+        assert_eq!(mem.header().dump_type, CrashDumpType::BitmapKernel);
+        assert_eq!(mem.metadata().real_size, 3 * PAGE_SIZE);
+
+        for pfn in 0..8 {
+            let expected = if [1, 3, 4].contains(&pfn) {
+                Some(pfn as u8)
+            } else {
+                None
+            };
+            assert_eq!(read_page(&mut mem, pfn), expected);
+        }
+
+        file[..8].copy_from_slice(b"PAGEDUMP");
+        assert!(CrashDumpMemory::new(Cursor::new(file)).is_err());
+    }
+
+    #[test]
+    fn bitmap_oversized() {
+        let mut file = header(&[(0, 8)], 6);
+        let mut bitmap_header = [0u8; BITMAP_HEADER_SIZE];
+        bitmap_header[..8].copy_from_slice(b"SDMPDUMP");
+        put_u64(&mut bitmap_header, 0x20, 0x3000);
+        file.extend_from_slice(&bitmap_header);
+        file.resize(0x4000, 0);
+
+        for &bits in [u64::MAX, u64::MAX - 7, 0x10_0000].iter() {
+            put_u64(&mut file, HEADER_SIZE as usize + 0x28, bits);
+            match CrashDumpMemory::new(Cursor::new(file.clone())) {
+                Err(err) => assert_eq!(err, Error(ErrorOrigin::Connector, ErrorKind::Encoding)),
+                Ok(_) => panic!("expected the bitmap to be rejected"),
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+/*!
+Connector for raw physical memory dumps.
+
+Raw dumps, as written by tools like `dd` on `/dev/mem`, LiME in raw mode or the `pmemsave`
+command of QEMU, are plain copies of physical memory without any header. By default the whole
+file is mapped to physical memory starting at address 0. Dumps that skip memory holes (e.g.
+the PCI hole below 4 GB) need a physical memory layout mapping every physical range to its
+offset in the file, which can be passed in as a [`MemoryMap`].
+
+[`FileConnector`] only reads from the dump, writes are rejected.
+
+# Examples
+
+```
+use memflow::connector::FileConnector;
+use memflow::mem::{MemoryMap, MemoryView, PhysicalMemory};
+use memflow::types::{size, Address};
+
+// 1 MB of memory at 0, followed by 1 MB of memory at 4 GB
+let file = std::io::Cursor::new(vec![0u8; size::mb(2)]);
+
+let mut layout = MemoryMap::new();
+layout.push_remap(Address::null(), size::mb(1) as _, Address::null());
+layout.push_remap(
+    Address::from(size::gb(4) as u64),
+    size::mb(1) as _,
+    Address::from(size::mb(1)),
+);
+
+let mut mem = FileConnector::with_layout(file, layout).unwrap();
+
+let value: u64 = mem.phys_view().read(Address::from(size::gb(4) as u64)).unwrap();
+assert_eq!(value, 0);
+assert!(mem.phys_view().read::<u64>(Address::from(size::mb(1))).is_err());
+```
+*/
+
+use crate::connector::{CloneFile, FileIoMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::cglue::*;
+
+/// Read-only connector over a raw physical memory dump.
+#[derive(Clone)]
+pub struct FileConnector<T> {
+    mem: FileIoMemory<T>,
+}
+
+impl<T: Read + Seek + Write + Send> FileConnector<T> {
+    /// Maps the whole dump in `reader` to physical memory starting at address 0.
+    pub fn new(mut reader: T) -> Result<Self> {
+        let len = dump_len(&mut reader)?;
+
+        let mut layout = MemoryMap::new();
+        if len > 0 {
+            layout.push_remap(Address::null(), len, Address::null());
+        }
+
+        Self::with_layout(reader, layout)
+    }
+
+    /// Maps the dump in `reader` according to `layout`.
+    ///
+    /// The layout maps physical ranges to their offsets in the dump. Ranges that reach past the
+    /// end of the dump are rejected.
+    pub fn with_layout(mut reader: T, layout: MemoryMap<(Address, umem)>) -> Result<Self> {
+        let len = dump_len(&mut reader)?;
+
+        for mapping in layout.iter() {
+            let (file_off, size) = *mapping.output();
+            if file_off
+                .to_umem()
+                .checked_add(size)
+                .map_or(true, |end| end > len)
+            {
+                return Err(
+                    Error(ErrorOrigin::Connector, ErrorKind::MemoryMapOutOfRange).log_error(
+                        format!("physical range at {:x} exceeds the dump", mapping.base()),
+                    ),
+                );
+            }
+        }
+
+        Ok(Self {
+            mem: FileIoMemory::try_with_reader(reader, layout)?,
+        })
+    }
+}
+
+impl FileConnector<CloneFile> {
+    /// Opens a raw dump and maps it to physical memory starting at address 0.
+    pub fn open<P: AsRef<::std::path::Path>>(path: P) -> Result<Self> {
+        Self::new(open_dump(path)?)
+    }
+
+    /// Opens a raw dump and maps it according to `layout`.
+    pub fn open_with_layout<P: AsRef<::std::path::Path>>(
+        path: P,
+        layout: MemoryMap<(Address, umem)>,
+    ) -> Result<Self> {
+        Self::with_layout(open_dump(path)?, layout)
+    }
+
+    /// Opens a raw dump and maps it according to the memory map file at `map_path`.
+    ///
+    /// The `real_base` of the ranges in the file are the offsets in the dump, see
+    /// [`MemoryMap::open`] for the format.
+    #[cfg(feature = "memmapfiles")]
+    pub fn open_with_map_file<P: AsRef<::std::path::Path>, M: AsRef<::std::path::Path>>(
+        path: P,
+        map_path: M,
+    ) -> Result<Self> {
+        Self::open_with_layout(path, MemoryMap::open(map_path)?)
+    }
+}
+
+fn open_dump<P: AsRef<::std::path::Path>>(path: P) -> Result<CloneFile> {
+    ::std::fs::File::open(path)
+        .map(CloneFile::from)
+        .map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to open the memory dump: {}", err))
+        })
+}
+
+fn dump_len<T: Seek>(reader: &mut T) -> Result<umem> {
+    reader
+        .seek(SeekFrom::End(0))
+        .map(|len| len as umem)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err))
+}
+
+impl<T: Read + Seek + Write + Send> PhysicalMemory for FileConnector<T> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.mem.phys_read_raw_iter(data)
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_warn("memory dumps can not be written to"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            readonly: true,
+            ..self.mem.metadata()
+        }
+    }
+}
+
+cglue_impl_group!(
+    FileConnector<T: Read + Seek + Write + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    use std::io::Cursor;
+
+    fn dump() -> Vec<u8> {
+        (0..size::kb(64)).map(|i| (i / 0x100) as u8).collect()
+    }
+
+    #[test]
+    fn whole_dump() {
+        let path = std::env::temp_dir().join("memflow_dump_connector.raw");
+        std::fs::write(&path, dump()).unwrap();
+
+        let mut mem = FileConnector::open(&path).unwrap();
+        let meta = mem.metadata();
+        assert_eq!(meta.max_address, Address::from(size::kb(64) - 1));
+        assert_eq!(meta.real_size, size::kb(64) as umem);
+        assert!(meta.readonly);
+
+        let mut buf = [0u8; 4];
+        mem.phys_read_into(Address::from(0x1ffeu64).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, [0x1f, 0x1f, 0x20, 0x20]);
+        assert!(mem
+            .phys_view()
+            .read_raw_into(Address::from(size::kb(64)), &mut buf)
+            .is_err());
+        assert!(mem.phys_write(Address::null().into(), &buf).is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn custom_layout() {
+        let mut layout = MemoryMap::new();
+        layout.push_remap(
+            Address::from(0x10_0000u64),
+            0x1000,
+            Address::from(0x3000u64),
+        );
+
+        let mut mem = FileConnector::with_layout(Cursor::new(dump()), layout).unwrap();
+        let mut buf = [0u8; 2];
+        mem.phys_read_into(Address::from(0x10_0fffu64).into(), &mut buf[..1])
+            .unwrap();
+        assert_eq!(buf[0], 0x3f);
+        assert!(mem
+            .phys_view()
+            .read_raw_into(Address::from(0x3000u64), &mut buf)
+            .is_err());
+
+        let mut layout = MemoryMap::new();
+        layout.push_remap(Address::null(), 0x1000, Address::from(size::kb(64) - 0x800));
+        assert!(FileConnector::with_layout(Cursor::new(dump()), layout).is_err());
+    }
+}
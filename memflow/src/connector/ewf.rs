@@ -0,0 +1,618 @@
+/*!
+Connector for EnCase evidence files (EWF / E01).
+
+Evidence files store a disk or memory image as a sequence of chunks, usually 32 KB each, which
+are compressed with zlib unless compression would not shrink them. Images are split into
+segment files (`image.E01`, `image.E02`, ...), every segment contains `sectors` sections holding
+the chunk data and `table` sections pointing to the chunks. The last segment ends with a `done`
+section, all others with a `next` section.
+
+[`EwfConnector`] maps the image to physical memory starting at address 0, decompressing chunks
+as they are read. The most recently used chunk is kept decompressed, so sequential reads
+decompress every chunk only once. The connector is read-only.
+
+Only the EWF version 1 format (E01) is supported, EWF2 containers (Ex01) are rejected.
+
+# Examples
+
+```no_run
+use memflow::connector::EwfConnector;
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::types::Address;
+
+// opens image.E02, image.E03, ... alongside
+let mut mem = EwfConnector::open("image.E01").unwrap();
+println!("{} bytes of memory", mem.media_size());
+
+let value: u64 = mem.phys_view().read(Address::from(0x1000u64)).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::connector::CloneFile;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::MemOps;
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{size, umem, Address};
+
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::cglue::*;
+
+/// Signature at the start of every EWF segment file.
+pub const EWF_SIGNATURE: [u8; 8] = *b"EVF\x09\x0d\x0a\xff\x00";
+
+/// Signature of EWF2 (Ex01) segment files.
+const EWF2_SIGNATURE: [u8; 8] = *b"EVF2\x0d\x0a\x81\x00";
+
+const FILE_HEADER_SIZE: u64 = 13;
+const SECTION_SIZE: u64 = 76;
+const TABLE_HEADER_SIZE: u64 = 24;
+
+/// Set in table entries of compressed chunks.
+const COMPRESSED: u32 = 1 << 31;
+
+/// Maximum size of a chunk, larger chunk sizes indicate a corrupted volume section.
+const MAX_CHUNK_SIZE: usize = size::mb(16);
+
+/// Location of a single chunk in the segment files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EwfChunk {
+    /// Index of the segment file holding the chunk.
+    pub segment: usize,
+    /// Offset of the chunk data in the segment file.
+    pub offset: u64,
+    /// Size of the stored chunk data, including the checksum of uncompressed chunks.
+    pub size: u64,
+    pub compressed: bool,
+}
+
+/// Read-only connector over an EnCase evidence file.
+#[derive(Clone)]
+pub struct EwfConnector<T> {
+    segments: Vec<T>,
+    chunks: Vec<EwfChunk>,
+    chunk_size: usize,
+    media_size: umem,
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+/// State carried over while walking the sections of all segments.
+#[derive(Default)]
+struct Parser {
+    chunk_size: usize,
+    media_size: umem,
+    chunks: Vec<EwfChunk>,
+    sectors_end: u64,
+}
+
+impl<T: Read + Seek + Send> EwfConnector<T> {
+    /// Opens the image stored in `segments`, ordered by their segment number.
+    ///
+    /// Segments following the one holding the `done` section are ignored.
+    pub fn new(mut segments: Vec<T>) -> Result<Self> {
+        let mut parser = Parser::default();
+
+        let mut last = None;
+        for (i, segment) in segments.iter_mut().enumerate() {
+            if parser.read_segment(segment, i)? {
+                last = Some(i);
+                break;
+            }
+        }
+
+        let last = last.ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+                .log_error("the evidence file is incomplete, segment files are missing")
+        })?;
+        segments.truncate(last + 1);
+
+        if parser.chunk_size == 0 {
+            return Err(invalid_image("the evidence file has no volume section"));
+        }
+
+        let needed =
+            (parser.media_size + parser.chunk_size as umem - 1) / parser.chunk_size as umem;
+        if (parser.chunks.len() as umem) < needed {
+            return Err(invalid_image(
+                "the chunk tables do not cover the whole image",
+            ));
+        }
+
+        Ok(Self {
+            segments,
+            chunks: parser.chunks,
+            chunk_size: parser.chunk_size,
+            media_size: parser.media_size,
+            cached: None,
+        })
+    }
+
+    /// Returns the size of the stored image.
+    pub fn media_size(&self) -> umem {
+        self.media_size
+    }
+
+    /// Returns the size of a decompressed chunk.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Returns the locations of all chunks of the image.
+    pub fn chunks(&self) -> &[EwfChunk] {
+        &self.chunks
+    }
+
+    fn read_media(&mut self, mut addr: umem, buf: &mut [u8]) -> Result<()> {
+        if addr
+            .checked_add(buf.len() as umem)
+            .map_or(true, |end| end > self.media_size)
+        {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds));
+        }
+
+        let mut buf = buf;
+        while !buf.is_empty() {
+            let offset = (addr % self.chunk_size as umem) as usize;
+            let len = buf.len().min(self.chunk_size - offset);
+            let (head, tail) = std::mem::take(&mut buf).split_at_mut(len);
+
+            let chunk = self.chunk((addr / self.chunk_size as umem) as usize)?;
+            head.copy_from_slice(&chunk[offset..offset + len]);
+
+            buf = tail;
+            addr += len as umem;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the decompressed data of chunk `idx`.
+    fn chunk(&mut self, idx: usize) -> Result<&[u8]> {
+        if !matches!(&self.cached, Some((cached, _)) if *cached == idx) {
+            let data = self.load_chunk(idx)?;
+            self.cached = Some((idx, data));
+        }
+
+        Ok(&self.cached.as_ref().unwrap().1)
+    }
+
+    fn load_chunk(&mut self, idx: usize) -> Result<Vec<u8>> {
+        let chunk = self.chunks[idx];
+        let len = (self.media_size - idx as umem * self.chunk_size as umem)
+            .min(self.chunk_size as umem) as usize;
+
+        // compressed chunks may grow slightly, uncompressed ones carry a checksum
+        if chunk.size > (self.chunk_size + self.chunk_size / 8 + 64) as u64 {
+            return Err(invalid_image("chunk exceeds the chunk size"));
+        }
+
+        let mut raw = vec![0u8; chunk.size as usize];
+        read_exact_at(&mut self.segments[chunk.segment], chunk.offset, &mut raw)?;
+
+        let mut data = if chunk.compressed {
+            miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&raw, self.chunk_size).map_err(
+                |err| {
+                    Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                        .log_error(format!("unable to decompress chunk {}: {:?}", idx, err))
+                },
+            )?
+        } else {
+            raw
+        };
+
+        if data.len() < len {
+            return Err(invalid_image("chunk is shorter than the chunk size"));
+        }
+
+        data.truncate(len);
+        Ok(data)
+    }
+}
+
+impl Parser {
+    /// Walks the sections of a segment, returns whether it is the last one.
+    fn read_segment<T: Read + Seek>(&mut self, reader: &mut T, segment: usize) -> Result<bool> {
+        let mut header = [0u8; FILE_HEADER_SIZE as usize];
+        read_exact_at(reader, 0, &mut header)?;
+
+        if header[..8] == EWF2_SIGNATURE {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+                .log_error("EWF2 (Ex01) evidence files are not supported"));
+        } else if header[..8] != EWF_SIGNATURE {
+            return Err(invalid_image("the file is not an EWF segment"));
+        }
+
+        let number = u16::from_le_bytes([header[9], header[10]]) as usize;
+        if number != segment + 1 {
+            return Err(invalid_image(&format!(
+                "expected segment {}, found segment {}",
+                segment + 1,
+                number
+            )));
+        }
+
+        let mut offset = FILE_HEADER_SIZE;
+        loop {
+            let mut section = [0u8; SECTION_SIZE as usize];
+            read_exact_at(reader, offset, &mut section)?;
+
+            let name = section[..16].split(|&b| b == 0).next().unwrap_or_default();
+            let next = u64_at(&section, 16);
+            let size = u64_at(&section, 24);
+            let data = offset + SECTION_SIZE;
+
+            match name {
+                b"volume" | b"disk" => self.read_volume(reader, data)?,
+                b"sectors" => self.sectors_end = offset.saturating_add(size),
+                b"table" => self.read_table(reader, segment, offset, size)?,
+                b"next" => return Ok(false),
+                b"done" => return Ok(true),
+                _ => {}
+            }
+
+            if next <= offset {
+                return Err(invalid_image("section list of the segment is corrupted"));
+            }
+            offset = next;
+        }
+    }
+
+    fn read_volume<T: Read + Seek>(&mut self, reader: &mut T, offset: u64) -> Result<()> {
+        let mut volume = [0u8; 24];
+        read_exact_at(reader, offset, &mut volume)?;
+
+        let sectors_per_chunk = u32_at(&volume, 8) as usize;
+        let bytes_per_sector = u32_at(&volume, 12) as usize;
+        let sector_count = u64_at(&volume, 16) as umem;
+
+        self.chunk_size = sectors_per_chunk
+            .checked_mul(bytes_per_sector)
+            .filter(|&size| size > 0 && size <= MAX_CHUNK_SIZE)
+            .ok_or_else(|| invalid_image("invalid chunk size in the volume section"))?;
+        self.media_size = sector_count
+            .checked_mul(bytes_per_sector as umem)
+            .ok_or_else(|| invalid_image("invalid sector count in the volume section"))?;
+
+        Ok(())
+    }
+
+    fn read_table<T: Read + Seek>(
+        &mut self,
+        reader: &mut T,
+        segment: usize,
+        offset: u64,
+        size: u64,
+    ) -> Result<()> {
+        let mut header = [0u8; TABLE_HEADER_SIZE as usize];
+        read_exact_at(reader, offset + SECTION_SIZE, &mut header)?;
+
+        let count = u32_at(&header, 0) as u64;
+        let base = u64_at(&header, 8);
+
+        // the entry count is untrusted, it has to fit into the section
+        if count > size.saturating_sub(SECTION_SIZE + TABLE_HEADER_SIZE) / 4 {
+            return Err(invalid_image("chunk table is larger than its section"));
+        }
+
+        let mut entries = vec![0u8; count as usize * 4];
+        read_exact_at(
+            reader,
+            offset + SECTION_SIZE + TABLE_HEADER_SIZE,
+            &mut entries,
+        )?;
+
+        let offsets = entries
+            .chunks_exact(4)
+            .map(|e| u32::from_le_bytes(e.try_into().unwrap()))
+            .map(|e| (base + (e & !COMPRESSED) as u64, e & COMPRESSED != 0))
+            .collect::<Vec<_>>();
+
+        // the last chunk of a table reaches up to the end of its sectors section
+        let sectors_end = if self.sectors_end > 0 {
+            self.sectors_end
+        } else {
+            offset
+        };
+
+        for (i, &(start, compressed)) in offsets.iter().enumerate() {
+            let end = offsets.get(i + 1).map_or(sectors_end, |&(end, _)| end);
+            if end < start {
+                return Err(invalid_image("chunk table is not sorted"));
+            }
+
+            self.chunks.push(EwfChunk {
+                segment,
+                offset: start,
+                size: end - start,
+                compressed,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl EwfConnector<CloneFile> {
+    /// Opens an evidence file by the path of its first segment.
+    ///
+    /// The following segments are expected next to it, with the extension counting up from
+    /// `.E01` to `.E99` and then on from `.EAA`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut segments = vec![open_segment(path.as_ref())?];
+
+        let mut path = path.as_ref().to_path_buf();
+        while let Some(next) = next_segment_path(&path) {
+            if !next.is_file() {
+                break;
+            }
+            segments.push(open_segment(&next)?);
+            path = next;
+        }
+
+        Self::new(segments)
+    }
+}
+
+fn open_segment(path: &Path) -> Result<CloneFile> {
+    ::std::fs::File::open(path)
+        .map(CloneFile::from)
+        .map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(format!(
+                "unable to open the segment file {}: {}",
+                path.display(),
+                err
+            ))
+        })
+}
+
+/// Returns the path of the segment following `path`, e.g. `.E09` -> `.E10` and `.E99` -> `.EAA`.
+fn next_segment_path(path: &Path) -> Option<PathBuf> {
+    let ext = path.extension()?.to_str()?;
+    let mut ext = ext.as_bytes().to_vec();
+    if ext.len() != 3 {
+        return None;
+    }
+
+    let lower = ext[0].is_ascii_lowercase();
+    ext.make_ascii_uppercase();
+
+    match (ext[1], ext[2]) {
+        (b'9', b'9') => ext[1..].copy_from_slice(b"AA"),
+        (b'0'..=b'9', b'9') => {
+            ext[1] += 1;
+            ext[2] = b'0';
+        }
+        (b'0'..=b'9', b'0'..=b'8') => ext[2] += 1,
+        (b'A'..=b'Z', b'A'..=b'Y') => ext[2] += 1,
+        (b'A'..=b'Y', b'Z') => {
+            ext[1] += 1;
+            ext[2] = b'A';
+        }
+        (b'Z', b'Z') if ext[0] < b'Z' => {
+            ext[0] += 1;
+            ext[1..].copy_from_slice(b"AA");
+        }
+        _ => return None,
+    }
+
+    if lower {
+        ext.make_ascii_lowercase();
+    }
+
+    Some(path.with_extension(String::from_utf8(ext).ok()?))
+}
+
+fn invalid_image(msg: &str) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(msg)
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_exact_at<T: Read + Seek>(reader: &mut T, offset: u64, out: &mut [u8]) -> Result<()> {
+    reader
+        .seek(SeekFrom::Start(offset))
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err))?;
+    reader
+        .read_exact(out)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err))
+}
+
+impl<T: Read + Seek + Send> PhysicalMemory for EwfConnector<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            if self.read_media(addr.address().to_umem(), &mut *buf).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_warn("evidence files can not be written to"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: Address::from(self.media_size.saturating_sub(1)),
+            real_size: self.media_size,
+            readonly: true,
+            ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(
+    EwfConnector<T: Read + Seek + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+
+    use std::io::Cursor;
+
+    const SECTOR_SIZE: usize = 512;
+
+    fn section(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+        let offset = out.len() as u64;
+        let size = SECTION_SIZE + data.len() as u64;
+        let next = match name {
+            "next" | "done" => offset,
+            _ => offset + size,
+        };
+
+        let mut desc = [0u8; SECTION_SIZE as usize];
+        desc[..name.len()].copy_from_slice(name.as_bytes());
+        desc[16..24].copy_from_slice(&next.to_le_bytes());
+        desc[24..32].copy_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&desc);
+        out.extend_from_slice(data);
+    }
+
+    /// Builds a segment with one chunk per sector, the first segment holds the volume section.
+    fn segment(number: u16, sector_count: u64, chunks: &[(&[u8], bool)], last: bool) -> Vec<u8> {
+        let mut out = EWF_SIGNATURE.to_vec();
+        out.push(1);
+        out.extend_from_slice(&number.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+
+        if number == 1 {
+            let mut volume = vec![0u8; 1052];
+            volume[8..12].copy_from_slice(&1u32.to_le_bytes());
+            volume[12..16].copy_from_slice(&(SECTOR_SIZE as u32).to_le_bytes());
+            volume[16..24].copy_from_slice(&sector_count.to_le_bytes());
+            section(&mut out, "volume", &volume);
+        }
+
+        let mut sectors = vec![];
+        let mut table = vec![0u8; TABLE_HEADER_SIZE as usize];
+        table[..4].copy_from_slice(&(chunks.len() as u32).to_le_bytes());
+        let base = out.len() as u64 + SECTION_SIZE;
+        table[8..16].copy_from_slice(&base.to_le_bytes());
+
+        for &(chunk, compressed) in chunks {
+            let mut entry = sectors.len() as u32;
+            if compressed {
+                entry |= COMPRESSED;
+                sectors.extend(miniz_oxide::deflate::compress_to_vec_zlib(chunk, 6));
+            } else {
+                sectors.extend_from_slice(chunk);
+                sectors.extend_from_slice(&[0u8; 4]);
+            }
+            table.extend_from_slice(&entry.to_le_bytes());
+        }
+        table.extend_from_slice(&[0u8; 4]);
+
+        section(&mut out, "sectors", &sectors);
+        section(&mut out, "table", &table);
+        section(&mut out, "table2", &table);
+        section(&mut out, if last { "done" } else { "next" }, &[]);
+        out
+    }
+
+    fn sector(seed: usize) -> Vec<u8> {
+        (0..SECTOR_SIZE).map(|i| ((i * seed) % 251) as u8).collect()
+    }
+
+    #[test]
+    fn read_chunks() {
+        let (s0, s1, s2) = (sector(0), sector(7), sector(13));
+        let image = segment(1, 3, &[(&s0, true), (&s1, false), (&s2, true)], true);
+
+        let mut mem = EwfConnector::new(vec![Cursor::new(image)]).unwrap();
+        assert_eq!(mem.media_size(), 3 * SECTOR_SIZE as umem);
+        assert_eq!(mem.chunk_size(), SECTOR_SIZE);
+        assert_eq!(mem.chunks().len(), 3);
+        assert!(mem.metadata().readonly);
+
+        let mut buf = vec![0u8; SECTOR_SIZE + 4];
+        mem.phys_read_into(Address::from(SECTOR_SIZE as u64 - 2).into(), &mut buf[..])
+            .unwrap();
+        assert_eq!(&buf[..2], &s0[SECTOR_SIZE - 2..]);
+        assert_eq!(&buf[2..SECTOR_SIZE + 2], &s1[..]);
+        assert_eq!(&buf[SECTOR_SIZE + 2..], &s2[..2]);
+
+        assert!(mem
+            .phys_view()
+            .read_raw_into(Address::from(3 * SECTOR_SIZE as u64 - 2), &mut buf[..4])
+            .is_err());
+        assert!(mem.phys_write(Address::null().into(), &buf[..4]).is_err());
+    }
+
+    #[test]
+    fn oversized_table() {
+        let s0 = sector(0);
+        let mut image = segment(1, 1, &[(&s0, false)], true);
+
+        let table = image.windows(6).position(|w| w == b"table\0").unwrap();
+        let count = table + SECTION_SIZE as usize;
+        image[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        match EwfConnector::new(vec![Cursor::new(image)]) {
+            Err(err) => assert_eq!(err, Error(ErrorOrigin::Connector, ErrorKind::Encoding)),
+            Ok(_) => panic!("expected the table to be rejected"),
+        }
+    }
+
+    #[test]
+    fn multiple_segments() {
+        let (s0, s1) = (sector(3), sector(5));
+        let first = segment(1, 2, &[(&s0, true)], false);
+        let second = segment(2, 2, &[(&s1, true)], true);
+
+        assert!(EwfConnector::new(vec![Cursor::new(first.clone())]).is_err());
+        assert!(EwfConnector::new(vec![
+            Cursor::new(second.clone()),
+            Cursor::new(first.clone())
+        ])
+        .is_err());
+
+        let mut mem = EwfConnector::new(vec![Cursor::new(first), Cursor::new(second)]).unwrap();
+        let mut buf = [0u8; 8];
+        mem.phys_read_into(Address::from(SECTOR_SIZE as u64 + 8).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf[..], s1[8..16]);
+
+        assert_eq!(
+            next_segment_path(Path::new("image.E09")),
+            Some(PathBuf::from("image.E10"))
+        );
+        assert_eq!(
+            next_segment_path(Path::new("image.e99")),
+            Some(PathBuf::from("image.eaa"))
+        );
+        assert_eq!(
+            next_segment_path(Path::new("image.EAZ")),
+            Some(PathBuf::from("image.EBA"))
+        );
+    }
+}
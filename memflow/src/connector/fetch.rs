@@ -0,0 +1,410 @@
+/*!
+Connector fetching memory asynchronously from a capture server.
+
+In the browser memory can only be fetched asynchronously, while all of memflow is synchronous.
+[`FetchConnector`] bridges the two: memory is fetched in chunks by an asynchronous
+[`ChunkFetcher`] and kept in a local store, and the synchronous [`PhysicalMemory`] interface
+only ever serves the fetched chunks. Reads of chunks that have not been fetched yet fail and
+are recorded as misses.
+
+An analysis is run with [`FetchConnector::resolve`], which repeats it after fetching the
+missed chunks until it completes without misses. Since every round only fetches the chunks
+accessed by the previous one, a typical analysis converges after a few rounds.
+
+The transport is provided by the frontend through [`ChunkFetcher`], e.g. over a WebSocket. With
+the `web` feature [`HttpFetcher`] fetches chunks with http range requests.
+
+The connector does not require `std` and builds for `wasm32-unknown-unknown` with
+`--no-default-features --features web`.
+
+# Examples
+
+```
+use memflow::connector::fetch::{ChunkFetcher, FetchConnector, FetchFuture};
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::types::Address;
+
+struct Server(Vec<u8>);
+
+impl ChunkFetcher for Server {
+    fn fetch(&self, addr: Address, len: usize) -> FetchFuture<'_> {
+        let start = addr.to_umem() as usize;
+        let data = self.0[start..start + len].to_vec();
+        Box::pin(async move { Ok(data) })
+    }
+}
+
+async fn analyze() -> u64 {
+    let mut server = vec![0u8; 0x100000];
+    server[0x1000] = 0x2a;
+
+    let mut conn = FetchConnector::new(Server(server), Address::from(0xfffffu64));
+    conn.resolve(4, |conn| conn.phys_view().read::<u64>(Address::from(0x1000)).ok())
+        .await
+        .unwrap()
+        .unwrap()
+}
+```
+*/
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::MemOps;
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{size, umem, Address};
+
+use core::future::Future;
+use core::ops::Range;
+use core::pin::Pin;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::prelude::v1::*;
+
+/// Default size of the fetched chunks.
+pub const DEFAULT_CHUNK_SIZE: usize = size::kb(64);
+
+/// Future returned by [`ChunkFetcher::fetch`].
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>>> + 'a>>;
+
+/// Asynchronous transport of a [`FetchConnector`].
+pub trait ChunkFetcher {
+    /// Fetches `len` bytes at the physical address `addr`.
+    ///
+    /// Fewer bytes may be returned for chunks at the end of memory.
+    fn fetch(&self, addr: Address, len: usize) -> FetchFuture<'_>;
+}
+
+/// Connector serving memory fetched by a [`ChunkFetcher`].
+pub struct FetchConnector<F> {
+    fetcher: F,
+    max_address: Address,
+    chunk_size: usize,
+    chunks: BTreeMap<umem, Vec<u8>>,
+    misses: BTreeSet<umem>,
+}
+
+impl<F: ChunkFetcher> FetchConnector<F> {
+    /// Creates a connector for a target with memory up to `max_address`.
+    pub fn new(fetcher: F, max_address: Address) -> Self {
+        Self {
+            fetcher,
+            max_address,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunks: BTreeMap::new(),
+            misses: BTreeSet::new(),
+        }
+    }
+
+    /// Sets the size of the fetched chunks, this drops all fetched chunks.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = core::cmp::max(chunk_size, 1);
+        self.clear();
+        self
+    }
+
+    /// Drops all fetched chunks, e.g. after the target has been resumed.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.misses.clear();
+    }
+
+    /// Returns the ranges of all chunks reads have missed since the last fetch.
+    pub fn misses(&self) -> impl Iterator<Item = Range<Address>> + '_ {
+        self.misses.iter().map(move |&idx| self.chunk_range(idx))
+    }
+
+    /// Fetches all chunks overlapping with `range` that have not been fetched yet.
+    pub async fn fetch(&mut self, range: Range<Address>) -> Result<()> {
+        if range.start >= range.end {
+            return Ok(());
+        }
+
+        let first = range.start.to_umem() / self.chunk_size as umem;
+        let last = (range.end.to_umem() - 1) / self.chunk_size as umem;
+
+        for idx in first..=last {
+            self.fetch_chunk(idx).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetches all chunks reads have missed since the last fetch.
+    ///
+    /// Returns the number of fetched chunks.
+    pub async fn fetch_misses(&mut self) -> Result<usize> {
+        let misses = core::mem::take(&mut self.misses);
+
+        for &idx in misses.iter() {
+            self.fetch_chunk(idx).await?;
+        }
+
+        Ok(misses.len())
+    }
+
+    /// Runs `analysis` until it completes without missing any chunks.
+    ///
+    /// After every round the missed chunks are fetched and the analysis is run again, at most
+    /// `max_rounds` times. The result of the last round is returned, even if it still missed
+    /// chunks.
+    pub async fn resolve<T>(
+        &mut self,
+        max_rounds: usize,
+        mut analysis: impl FnMut(&mut Self) -> T,
+    ) -> Result<T> {
+        let mut round = 0;
+
+        loop {
+            self.misses.clear();
+            let result = analysis(self);
+            round += 1;
+
+            if self.misses.is_empty() || round >= max_rounds {
+                return Ok(result);
+            }
+
+            self.fetch_misses().await?;
+        }
+    }
+
+    async fn fetch_chunk(&mut self, idx: umem) -> Result<()> {
+        if self.chunks.contains_key(&idx) {
+            return Ok(());
+        }
+
+        let range = self.chunk_range(idx);
+        if range.start > self.max_address {
+            return Ok(());
+        }
+
+        let len = (range.end.to_umem() - range.start.to_umem()) as usize;
+        let data = self.fetcher.fetch(range.start, len).await?;
+        self.chunks.insert(idx, data);
+
+        Ok(())
+    }
+
+    fn chunk_range(&self, idx: umem) -> Range<Address> {
+        let start = idx * self.chunk_size as umem;
+        let end = core::cmp::min(
+            start + self.chunk_size as umem,
+            self.max_address.to_umem().saturating_add(1),
+        );
+        Address::from(start)..Address::from(end)
+    }
+
+    /// Copies fetched data into `out`, recording all chunks that are missing.
+    fn read_fetched(&mut self, addr: Address, out: &mut [u8]) -> bool {
+        let chunk_size = self.chunk_size as umem;
+        let mut complete = true;
+        let mut done = 0;
+
+        while done < out.len() {
+            let addr = addr.to_umem() + done as umem;
+            let idx = addr / chunk_size;
+            let start = (addr % chunk_size) as usize;
+            let len = core::cmp::min(out.len() - done, self.chunk_size - start);
+
+            match self.chunks.get(&idx) {
+                Some(chunk) if chunk.len() >= start + len => {
+                    out[done..done + len].copy_from_slice(&chunk[start..start + len])
+                }
+                Some(_) => complete = false,
+                None => {
+                    if Address::from(addr) <= self.max_address {
+                        self.misses.insert(idx);
+                    }
+                    complete = false;
+                }
+            }
+
+            done += len;
+        }
+
+        complete
+    }
+}
+
+impl<F: ChunkFetcher + Send> PhysicalMemory for FetchConnector<F> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            if self.read_fetched(addr.address(), &mut *buf) {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp, mut out_fail, ..
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        for CTup3(_, meta_addr, buf) in inp {
+            opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.max_address,
+            real_size: self.max_address.to_umem().saturating_add(1),
+            readonly: true,
+            ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
+        }
+    }
+}
+
+/// Fetcher using http range requests through the `fetch` api of the browser.
+///
+/// The server has to answer `GET` requests of `url` carrying a `Range: bytes=<start>-<end>`
+/// header with the requested bytes of physical memory.
+#[cfg(feature = "web")]
+pub struct HttpFetcher {
+    url: String,
+}
+
+#[cfg(feature = "web")]
+impl HttpFetcher {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    async fn fetch_range(&self, addr: Address, len: usize) -> Result<Vec<u8>> {
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+        use web_sys::{Request, RequestInit, Response};
+
+        let err = |_| {
+            Error(ErrorOrigin::Connector, ErrorKind::Http)
+                .log_error(format!("unable to fetch {:x}+{:x}", addr, len))
+        };
+
+        let mut init = RequestInit::new();
+        init.method("GET");
+
+        let request = Request::new_with_str_and_init(&self.url, &init).map_err(err)?;
+        let end = addr.to_umem() + len as umem - 1;
+        request
+            .headers()
+            .set("Range", &format!("bytes={}-{}", addr.to_umem(), end))
+            .map_err(err)?;
+
+        let window = web_sys::window().ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+                .log_error("http fetching requires a browser window")
+        })?;
+
+        let response: Response = JsFuture::from(window.fetch_with_request(&request))
+            .await
+            .and_then(|r| r.dyn_into())
+            .map_err(err)?;
+
+        if !response.ok() {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Http)
+                .log_error(format!("server responded with {}", response.status())));
+        }
+
+        let buf = JsFuture::from(response.array_buffer().map_err(err)?)
+            .await
+            .map_err(err)?;
+
+        Ok(js_sys::Uint8Array::new(&buf).to_vec())
+    }
+}
+
+#[cfg(feature = "web")]
+impl ChunkFetcher for HttpFetcher {
+    fn fetch(&self, addr: Address, len: usize) -> FetchFuture<'_> {
+        Box::pin(self.fetch_range(addr, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+
+    use core::cell::Cell;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    struct Server {
+        mem: Vec<u8>,
+        fetches: Cell<usize>,
+    }
+
+    impl ChunkFetcher for Server {
+        fn fetch(&self, addr: Address, len: usize) -> FetchFuture<'_> {
+            self.fetches.set(self.fetches.get() + 1);
+            let start = addr.to_umem() as usize;
+            let data = self.mem[start..start + len].to_vec();
+            Box::pin(async move { Ok(data) })
+        }
+    }
+
+    /// Polls a future that never has to wait.
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        fn raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { Waker::from_raw(raw_waker()) };
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("future is not ready"),
+        }
+    }
+
+    #[test]
+    fn resolve_misses() {
+        let mut mem = vec![0u8; 0x10000];
+        mem[0x1ffc..0x2004].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        mem[0x8000] = 0x10;
+
+        let server = Server {
+            mem,
+            fetches: Cell::new(0),
+        };
+        let mut conn =
+            FetchConnector::new(server, Address::from(0xffffu64)).chunk_size(size::kb(4));
+
+        // reads a value crossing a chunk boundary, then follows it to another chunk
+        let (value, next) = block_on(conn.resolve(4, |conn| {
+            let mut view = conn.phys_view();
+            let value = view.read::<u64>(Address::from(0x1ffc)).ok();
+            let next = view.read::<u8>(Address::from(0x8000)).ok();
+            (value, next)
+        }))
+        .unwrap();
+
+        assert_eq!(value, Some(u64::from_le_bytes([1, 2, 3, 4, 5, 6, 7, 8])));
+        assert_eq!(next, Some(0x10));
+        assert_eq!(conn.fetcher.fetches.get(), 3);
+        assert_eq!(conn.misses().count(), 0);
+    }
+}
@@ -194,6 +194,8 @@ impl<T: Seek + Read + Write + Send> PhysicalMemory for FileIoMemory<T> {
             real_size: self.mem_map.real_size(),
             readonly: false,
             ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
         }
     }
 }
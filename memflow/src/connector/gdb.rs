@@ -0,0 +1,273 @@
+/*!
+Connector for GDB remote stubs.
+
+[`GdbConnector`] attaches to an existing stub speaking the GDB remote serial protocol, like the
+stub of QEMU (`-s`), JTAG probes or kernel debugger stubs, and exposes its memory as a
+connector. This makes embedded and early boot targets, which are only reachable through such
+a stub, available to the rest of memflow.
+
+Most stubs access memory through the current virtual address space of the halted cpu. In that
+case the connector acts as a virtual memory source and the addresses passed to it are virtual.
+QEMU can be switched to physical addresses with [`GdbConnector::physical_mode`].
+
+# Examples
+
+```no_run
+use memflow::connector::gdb::GdbConnector;
+use memflow::mem::PhysicalMemory;
+
+let mut conn = GdbConnector::connect("127.0.0.1:1234").unwrap();
+conn.physical_mode(true).unwrap();
+
+let mut buf = [0u8; 16];
+conn.phys_read_into(0x1000.into(), &mut buf).unwrap();
+```
+*/
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::gdb::{hex_decode, hex_encode, Connection};
+use crate::mem::mem_data::MemOps;
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+/// Packet size assumed if the stub does not report one.
+pub const DEFAULT_PACKET_SIZE: usize = 0x400;
+
+/// Connector on top of a GDB remote stub.
+pub struct GdbConnector<S> {
+    conn: Arc<Mutex<Connection<S>>>,
+    packet_size: usize,
+    max_address: Address,
+}
+
+impl<S> Clone for GdbConnector<S> {
+    fn clone(&self) -> Self {
+        Self {
+            conn: self.conn.clone(),
+            packet_size: self.packet_size,
+            max_address: self.max_address,
+        }
+    }
+}
+
+impl GdbConnector<TcpStream> {
+    /// Connects to a stub listening on `addr`.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+                .log_error(format!("unable to connect to the gdb stub: {}", err))
+        })?;
+        stream.set_nodelay(true).ok();
+
+        Self::new(stream)
+    }
+}
+
+impl<S: Read + Write + Send> GdbConnector<S> {
+    /// Attaches to a stub connected through `stream`.
+    pub fn new(stream: S) -> Result<Self> {
+        let mut conn = Connection::new(stream);
+
+        let supported = conn.request(b"qSupported:swbreak+;hwbreak+")?;
+        let supported = String::from_utf8_lossy(&supported);
+
+        let packet_size = supported
+            .split(';')
+            .find_map(|f| f.strip_prefix("PacketSize="))
+            .and_then(|s| usize::from_str_radix(s, 16).ok())
+            .unwrap_or(DEFAULT_PACKET_SIZE);
+
+        if supported.split(';').any(|f| f == "QStartNoAckMode+")
+            && conn.request(b"QStartNoAckMode")? == b"OK"
+        {
+            conn.no_ack = true;
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            packet_size,
+            max_address: Address::invalid(),
+        })
+    }
+
+    /// Sets the highest address of the target, which is reported in the connector metadata.
+    ///
+    /// Stubs have no way of reporting the memory size, so by default the full address space is
+    /// reported.
+    pub fn max_address(mut self, max_address: Address) -> Self {
+        self.max_address = max_address;
+        self
+    }
+
+    /// Switches QEMU between physical and virtual memory accesses.
+    pub fn physical_mode(&mut self, physical: bool) -> Result<()> {
+        let request = format!("Qqemu.PhysMemMode:{}", physical as u8);
+        match self.request(request.as_bytes())?.as_slice() {
+            b"OK" => Ok(()),
+            _ => Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+                .log_error("the gdb stub does not support physical memory mode")),
+        }
+    }
+
+    /// Reads the raw register contents of the current thread.
+    ///
+    /// The layout depends on the architecture description of the stub, unavailable registers
+    /// are returned as zeroes.
+    pub fn read_registers(&mut self) -> Result<Vec<u8>> {
+        let response = self.request(b"g")?;
+        let response = String::from_utf8_lossy(&response).replace('x', "0");
+
+        hex_decode(&response).ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory)
+                .log_error("unable to read the registers of the gdb stub")
+        })
+    }
+
+    fn request(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.conn.lock().unwrap().request(data)
+    }
+
+    /// Number of bytes that fit into a single response.
+    fn chunk_size(&self) -> usize {
+        std::cmp::max(self.packet_size.saturating_sub(8) / 2, 1)
+    }
+
+    fn read_memory(&self, addr: Address, out: &mut [u8]) -> Result<()> {
+        for (i, chunk) in out.chunks_mut(self.chunk_size()).enumerate() {
+            let addr = addr + i * self.chunk_size();
+            let response = self.request(format!("m{:x},{:x}", addr, chunk.len()).as_bytes())?;
+
+            match std::str::from_utf8(&response).ok().and_then(hex_decode) {
+                Some(data) if data.len() == chunk.len() => chunk.copy_from_slice(&data),
+                _ => return Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_memory(&self, addr: Address, data: &[u8]) -> Result<()> {
+        // requests carry the address and length in addition to the data
+        let chunk_size = std::cmp::max(self.packet_size.saturating_sub(40) / 2, 1);
+
+        for (i, chunk) in data.chunks(chunk_size).enumerate() {
+            let addr = addr + i * chunk_size;
+            let request = format!("M{:x},{:x}:{}", addr, chunk.len(), hex_encode(chunk));
+
+            if self.request(request.as_bytes())? != b"OK" {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::AccessDenied));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: Read + Write + Send> PhysicalMemory for GdbConnector<S> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            if self.read_memory(addr.address(), &mut *buf).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, buf) in inp {
+            if self.write_memory(addr.address(), &*buf).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.max_address,
+            real_size: self.max_address.to_umem().saturating_add(1),
+            readonly: false,
+            ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
+        }
+    }
+}
+
+cglue_impl_group!(
+    GdbConnector<S: Read + Write + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::gdb::{GdbServer, MemoryTarget};
+    use crate::types::size;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn server_roundtrip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mem = DummyMemory::new(size::mb(1)).into_phys_view();
+            GdbServer::new(MemoryTarget::new(mem)).serve(stream)
+        });
+
+        let mut conn = GdbConnector::connect(addr)
+            .unwrap()
+            .max_address(Address::from(size::mb(1) as umem - 1));
+
+        let data = (0..0x1000).map(|i| i as u8).collect::<Vec<_>>();
+        conn.phys_write(Address::from(0x2000u64).into(), data.as_slice())
+            .unwrap();
+
+        let mut out = vec![0u8; data.len()];
+        conn.phys_read_into(Address::from(0x2000u64).into(), out.as_mut_slice())
+            .unwrap();
+        assert_eq!(out, data);
+
+        // failed reads are zeroed
+        let mut out = [0xffu8; 4];
+        conn.phys_read_into(Address::from(size::mb(2) as umem).into(), &mut out)
+            .unwrap();
+        assert_eq!(out, [0; 4]);
+
+        std::mem::drop(conn);
+        server.join().unwrap().unwrap();
+    }
+}
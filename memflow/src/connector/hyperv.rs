@@ -0,0 +1,306 @@
+/*!
+Connector for Hyper-V saved state files.
+
+Hyper-V writes the state of saved and checkpointed VMs to `.vmrs` files, older versions to a
+`.bin` file holding the guest memory and a `.vsv` file holding the partition state. The formats
+are not documented, they are read through the saved state dump provider shipped with Windows
+(`vmsavedstatedumpprovider.dll`, part of the Windows SDK), which is loaded at runtime.
+
+[`HyperVConnector`] reconstructs the guest physical memory from the memory chunks of the saved
+partition, ranges not backed by a chunk fail to read. The connector is read-only.
+
+# Examples
+
+```no_run
+use memflow::connector::HyperVConnector;
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::types::Address;
+
+let mut mem = HyperVConnector::open(r"C:\VMs\guest\Virtual Machines\guest.vmrs").unwrap();
+let value: u64 = mem.phys_view().read(Address::from(0x1000u64)).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::MemOps;
+use crate::mem::{
+    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use std::ffi::{c_void, OsStr};
+use std::iter::once;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use libloading::Library;
+
+use crate::cglue::*;
+
+/// Name of the saved state dump provider library.
+pub const DUMP_PROVIDER_LIBRARY: &str = "vmsavedstatedumpprovider.dll";
+
+type Handle = *mut c_void;
+type HResult = i32;
+
+/// `GPA_MEMORY_CHUNK` of `vmsavedstatedump.h`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct GpaMemoryChunk {
+    guest_physical_start_page_index: u64,
+    page_count: u64,
+}
+
+type LoadSavedStateFile = unsafe extern "system" fn(*const u16, *mut Handle) -> HResult;
+type LoadSavedStateFiles =
+    unsafe extern "system" fn(*const u16, *const u16, *mut Handle) -> HResult;
+type ApplyPendingSavedStateFileReplayLog = unsafe extern "system" fn(*const u16) -> HResult;
+type ReleaseSavedStateFiles = unsafe extern "system" fn(Handle) -> HResult;
+type GetGuestPhysicalMemoryChunks =
+    unsafe extern "system" fn(Handle, *mut u64, *mut GpaMemoryChunk, *mut u64) -> HResult;
+type ReadGuestPhysicalAddress =
+    unsafe extern "system" fn(Handle, u64, *mut c_void, u32, *mut u32) -> HResult;
+
+/// A loaded saved state, released once the last connector referencing it is dropped.
+struct SavedState {
+    handle: Handle,
+    release: ReleaseSavedStateFiles,
+    read: ReadGuestPhysicalAddress,
+    // keeps the function pointers above valid
+    _library: Library,
+}
+
+// the handle is only used behind the mutex of the connector
+unsafe impl Send for SavedState {}
+
+impl Drop for SavedState {
+    fn drop(&mut self) {
+        unsafe { (self.release)(self.handle) };
+    }
+}
+
+/// Read-only connector over the guest memory of a Hyper-V saved state.
+#[derive(Clone)]
+pub struct HyperVConnector {
+    state: Arc<Mutex<SavedState>>,
+    mem_map: MemoryMap<(Address, umem)>,
+}
+
+impl HyperVConnector {
+    /// Opens a saved state by the path of its `.vmrs` file, or of its `.bin` or `.vsv` file.
+    ///
+    /// The `.bin` and `.vsv` files of older Hyper-V versions are expected next to each other.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+
+        match ext.as_deref() {
+            Some("bin") | Some("vsv") => {
+                Self::open_legacy(path.with_extension("bin"), path.with_extension("vsv"))
+            }
+            _ => Self::open_vmrs(path),
+        }
+    }
+
+    /// Opens the `.vmrs` file of a saved state.
+    ///
+    /// Pending changes in the replay log of the file are applied to it first.
+    pub fn open_vmrs<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = wide(path.as_ref());
+
+        Self::load(|library| unsafe {
+            // only exported by newer versions of the library
+            if let Ok(apply) = library.get::<ApplyPendingSavedStateFileReplayLog>(
+                b"ApplyPendingSavedStateFileReplayLog\0",
+            ) {
+                check(apply(path.as_ptr()), "unable to apply the replay log")?;
+            }
+
+            let load = symbol::<LoadSavedStateFile>(library, b"LoadSavedStateFile\0")?;
+            let mut handle = ptr::null_mut();
+            check(
+                load(path.as_ptr(), &mut handle),
+                "unable to load the saved state",
+            )?;
+            Ok(handle)
+        })
+    }
+
+    /// Opens the `.bin` and `.vsv` files of a saved state of older Hyper-V versions.
+    pub fn open_legacy<B: AsRef<Path>, V: AsRef<Path>>(bin: B, vsv: V) -> Result<Self> {
+        let (bin, vsv) = (wide(bin.as_ref()), wide(vsv.as_ref()));
+
+        Self::load(|library| unsafe {
+            let load = symbol::<LoadSavedStateFiles>(library, b"LoadSavedStateFiles\0")?;
+            let mut handle = ptr::null_mut();
+            check(
+                load(bin.as_ptr(), vsv.as_ptr(), &mut handle),
+                "unable to load the saved state",
+            )?;
+            Ok(handle)
+        })
+    }
+
+    fn load(open: impl FnOnce(&Library) -> Result<Handle>) -> Result<Self> {
+        let library = unsafe { Library::new(DUMP_PROVIDER_LIBRARY) }.map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToLoadLibrary)
+                .log_error(format!("unable to load {}: {}", DUMP_PROVIDER_LIBRARY, err))
+        })?;
+
+        let (release, read, chunks) = unsafe {
+            (
+                symbol::<ReleaseSavedStateFiles>(&library, b"ReleaseSavedStateFiles\0")?,
+                symbol::<ReadGuestPhysicalAddress>(&library, b"ReadGuestPhysicalAddress\0")?,
+                symbol::<GetGuestPhysicalMemoryChunks>(
+                    &library,
+                    b"GetGuestPhysicalMemoryChunks\0",
+                )?,
+            )
+        };
+
+        let handle = open(&library)?;
+        let state = SavedState {
+            handle,
+            release,
+            read,
+            _library: library,
+        };
+
+        let mem_map = memory_chunks(&state, chunks)?;
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(state)),
+            mem_map,
+        })
+    }
+
+    fn read_memory(state: &SavedState, addr: Address, buf: &mut [u8]) -> Result<()> {
+        let mut read = 0;
+        let res = unsafe {
+            (state.read)(
+                state.handle,
+                addr.to_umem() as u64,
+                buf.as_mut_ptr().cast(),
+                buf.len() as u32,
+                &mut read,
+            )
+        };
+
+        if res >= 0 && read as usize == buf.len() {
+            Ok(())
+        } else {
+            Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory))
+        }
+    }
+}
+
+/// Builds the memory map of the guest from the memory chunks of the saved partition.
+fn memory_chunks(
+    state: &SavedState,
+    get: GetGuestPhysicalMemoryChunks,
+) -> Result<MemoryMap<(Address, umem)>> {
+    let mut page_size = 0;
+    let mut count = 0;
+
+    // the first call only returns the number of chunks
+    unsafe { get(state.handle, &mut page_size, ptr::null_mut(), &mut count) };
+
+    let mut chunks = vec![GpaMemoryChunk::default(); count as usize];
+    check(
+        unsafe {
+            get(
+                state.handle,
+                &mut page_size,
+                chunks.as_mut_ptr(),
+                &mut count,
+            )
+        },
+        "unable to retrieve the guest memory chunks",
+    )?;
+    chunks.truncate(count as usize);
+
+    let mut mem_map = MemoryMap::new();
+    for chunk in chunks.iter().filter(|c| c.page_count > 0) {
+        let base = chunk.guest_physical_start_page_index * page_size;
+        let size = (chunk.page_count * page_size) as umem;
+        mem_map.push_remap(base.into(), size, base.into());
+    }
+
+    Ok(mem_map)
+}
+
+fn wide(path: &Path) -> Vec<u16> {
+    path.as_os_str().encode_wide().chain(once(0)).collect()
+}
+
+unsafe fn symbol<T: Copy>(library: &Library, name: &[u8]) -> Result<T> {
+    library.get::<T>(name).map(|sym| *sym).map_err(|err| {
+        Error(ErrorOrigin::Connector, ErrorKind::NotFound).log_error(format!(
+            "{} does not export {}: {}",
+            DUMP_PROVIDER_LIBRARY,
+            String::from_utf8_lossy(&name[..name.len() - 1]),
+            err
+        ))
+    })
+}
+
+fn check(res: HResult, msg: &str) -> Result<()> {
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+            .log_error(format!("{} (HRESULT {:#x})", msg, res)))
+    }
+}
+
+impl PhysicalMemory for HyperVConnector {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+
+        // ranges outside of the memory chunks are failed by the iterator
+        let mut iter = self.mem_map.map_iter(inp, out_fail);
+        while let Some(CTup3((addr, _), meta_addr, mut buf)) = iter.next() {
+            if Self::read_memory(&state, addr, &mut *buf).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_warn("Hyper-V saved states can not be written to"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.mem_map.max_address(),
+            real_size: self.mem_map.real_size(),
+            readonly: true,
+            ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(HyperVConnector, crate::plugins::ConnectorInstance, {});
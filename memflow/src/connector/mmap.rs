@@ -4,8 +4,8 @@ Basic connector which works on mapped memory.
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::mem::{
-    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
-    PhysicalWriteMemOps,
+    opt_call, MappablePhysicalMemory, MemoryMap, PhysicalMemory, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps,
 };
 use crate::types::{umem, Address};
 
@@ -134,6 +134,8 @@ impl<'a, F: AsRef<MemoryMap<&'a mut [u8]>> + Send> PhysicalMemory
             real_size,
             readonly: false,
             ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
         }
     }
 }
@@ -176,10 +178,23 @@ impl<'a, F: AsRef<MemoryMap<&'a [u8]>> + Send> PhysicalMemory
             real_size,
             readonly: true,
             ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
         }
     }
 }
 
+impl<'a, F: AsRef<MemoryMap<&'a [u8]>> + Send> MappablePhysicalMemory
+    for MappedPhysicalMemory<&'a [u8], F>
+{
+    #[inline]
+    fn phys_map(&self, addr: Address) -> Option<&[u8]> {
+        let mapping = self.info.as_ref().lookup(addr)?;
+        let data: &'a [u8] = *mapping.output();
+        data.get((addr - mapping.base()) as usize..)
+    }
+}
+
 #[cfg(feature = "plugins")]
 cglue_impl_group!(
     MappedPhysicalMemory<T = &'cglue_a mut [u8], F: AsRef<MemoryMap<&'cglue_a mut [u8]>>>,
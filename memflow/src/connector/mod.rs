@@ -16,6 +16,60 @@ pub mod fileio;
 #[cfg(feature = "std")]
 pub use fileio::{CloneFile, FileIoMemory};
 
+#[cfg(feature = "std")]
+pub mod crashdump;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use crashdump::{CrashDumpHeader, CrashDumpMemory, CrashDumpType};
+
+#[cfg(feature = "std")]
+pub mod dump;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use dump::FileConnector;
+
+#[cfg(feature = "ewf")]
+pub mod ewf;
+#[doc(hidden)]
+#[cfg(feature = "ewf")]
+pub use ewf::EwfConnector;
+
+#[cfg(feature = "std")]
+pub mod gdb;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use gdb::GdbConnector;
+
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use snapshot::{SnapshotMemory, SnapshotWriter};
+
+#[cfg(all(windows, feature = "hyperv"))]
+pub mod hyperv;
+#[doc(hidden)]
+#[cfg(all(windows, feature = "hyperv"))]
+pub use hyperv::HyperVConnector;
+
+#[cfg(feature = "std")]
+pub mod qmp;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use qmp::QmpConnector;
+
+#[cfg(feature = "std")]
+pub mod replay;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use replay::{RecordingMemory, ReplayMemory};
+
+#[cfg(feature = "std")]
+pub mod vmware;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use vmware::{VmwareConnector, VmwareState, VmwareTag};
+
 #[cfg(feature = "filemap")]
 pub mod filemap;
 #[cfg(feature = "filemap")]
@@ -23,6 +77,10 @@ pub use filemap::{
     MmapInfo, MmapInfoMut, ReadMappedFilePhysicalMemory, WriteMappedFilePhysicalMemory,
 };
 
+pub mod fetch;
+#[doc(hidden)]
+pub use fetch::FetchConnector;
+
 pub mod mmap;
 #[doc(hidden)]
 pub use mmap::MappedPhysicalMemory;
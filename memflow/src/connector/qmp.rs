@@ -0,0 +1,354 @@
+/*!
+Connector for live QEMU guests controlled through QMP.
+
+[`QmpConnector`] attaches to the QEMU Machine Protocol socket of a running VM (`-qmp
+tcp:127.0.0.1:4444,server,nowait` or `-qmp unix:/tmp/qmp.sock,server,nowait`) and reads guest
+physical memory through the `xp` command of the human monitor. This works with any QEMU
+instance without shared memory mappings on the host and without stopping the guest.
+
+The monitor is not made for bulk transfers and has no command to write guest memory. For
+writes, and faster reads, [`QmpConnector::start_gdbserver`] starts the gdb stub of QEMU through
+the monitor and routes all accesses through a [`GdbConnector`] in physical memory mode. Note
+that QEMU stops the guest while a debugger is attached to it.
+
+# Examples
+
+```no_run
+use memflow::connector::QmpConnector;
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::types::Address;
+
+let mut mem = QmpConnector::connect("127.0.0.1:4444").unwrap();
+let value: u64 = mem.phys_view().read(Address::from(0x1000u64)).unwrap();
+
+// enables writes
+mem.start_gdbserver("127.0.0.1:1234").unwrap();
+mem.phys_view().write(Address::from(0x1000u64), &value).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::connector::GdbConnector;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::MemOps;
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+use crate::util::json::{self, Json};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+/// Maximum number of bytes read by a single `xp` command.
+pub const MAX_MONITOR_READ: usize = 0x200;
+
+/// Connector for a running QEMU instance.
+pub struct QmpConnector<S> {
+    qmp: Arc<Mutex<Qmp<S>>>,
+    gdb: Option<GdbConnector<TcpStream>>,
+    real_size: umem,
+}
+
+impl<S> Clone for QmpConnector<S> {
+    fn clone(&self) -> Self {
+        Self {
+            qmp: self.qmp.clone(),
+            gdb: self.gdb.clone(),
+            real_size: self.real_size,
+        }
+    }
+}
+
+impl QmpConnector<TcpStream> {
+    /// Connects to a QMP socket listening on `addr`.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).map_err(connect_error)?;
+        stream.set_nodelay(true).ok();
+
+        Self::new(stream)
+    }
+}
+
+#[cfg(unix)]
+impl QmpConnector<std::os::unix::net::UnixStream> {
+    /// Connects to a QMP unix socket at `path`.
+    pub fn connect_unix<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Self::new(std::os::unix::net::UnixStream::connect(path).map_err(connect_error)?)
+    }
+}
+
+fn connect_error(err: std::io::Error) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+        .log_error(format!("unable to connect to the qmp socket: {}", err))
+}
+
+impl<S: Read + Write + Send> QmpConnector<S> {
+    /// Attaches to a QMP server connected through `stream`.
+    pub fn new(stream: S) -> Result<Self> {
+        let mut qmp = Qmp::new(stream)?;
+
+        let summary = qmp.execute("query-memory-size-summary", None)?;
+        let real_size = ["base-memory", "plugged-memory"]
+            .iter()
+            .filter_map(|key| summary.get(key).and_then(Json::as_u64))
+            .sum::<u64>() as umem;
+
+        Ok(Self {
+            qmp: Arc::new(Mutex::new(qmp)),
+            gdb: None,
+            real_size,
+        })
+    }
+
+    /// Runs a command of the human monitor and returns its output.
+    pub fn hmp(&self, command: &str) -> Result<String> {
+        self.qmp.lock().unwrap().hmp(command)
+    }
+
+    /// Starts the gdb stub of QEMU on `addr` and routes all memory accesses through it.
+    ///
+    /// `addr` has to be reachable from the host running the connector, e.g. `127.0.0.1:1234`.
+    /// The guest is stopped as long as the stub is attached.
+    pub fn start_gdbserver(&mut self, addr: &str) -> Result<()> {
+        self.hmp(&format!("gdbserver tcp:{}", addr))?;
+
+        let mut gdb = GdbConnector::connect(addr)?;
+        gdb.physical_mode(true)?;
+        self.gdb = Some(gdb);
+
+        Ok(())
+    }
+
+    fn read_memory(&self, addr: Address, out: &mut [u8]) -> Result<()> {
+        let mut qmp = self.qmp.lock().unwrap();
+
+        for (i, chunk) in out.chunks_mut(MAX_MONITOR_READ).enumerate() {
+            let addr = addr + i * MAX_MONITOR_READ;
+            let output = qmp.hmp(&format!("xp /{}xb 0x{:x}", chunk.len(), addr))?;
+
+            // every line looks like `0000000000001000: 0x55 0x48 0x89 0xe5 ...`
+            let data = output
+                .lines()
+                .filter_map(|line| line.find(": ").map(|i| &line[i + 2..]))
+                .flat_map(str::split_whitespace)
+                .map(|byte| u8::from_str_radix(byte.trim_start_matches("0x"), 16).ok())
+                .collect::<Option<Vec<_>>>();
+
+            match data {
+                Some(data) if data.len() == chunk.len() => chunk.copy_from_slice(&data),
+                _ => {
+                    return Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory)
+                        .log_error(format!("unexpected monitor output: {}", output.trim())))
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: Read + Write + Send> PhysicalMemory for QmpConnector<S> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        if let Some(gdb) = &mut self.gdb {
+            return gdb.phys_read_raw_iter(data);
+        }
+
+        let MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        } = data;
+
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            if self.read_memory(addr.address(), &mut *buf).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        match &mut self.gdb {
+            Some(gdb) => gdb.phys_write_raw_iter(data),
+            None => Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+                .log_error("the qemu monitor can not write memory, start the gdbserver first")),
+        }
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            // memory above the pci hole is not reflected in the memory size
+            max_address: Address::invalid(),
+            real_size: self.real_size,
+            readonly: self.gdb.is_none(),
+            ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
+        }
+    }
+}
+
+cglue_impl_group!(
+    QmpConnector<S: Read + Write + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+/// A QMP session in command mode.
+struct Qmp<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: Read + Write> Qmp<S> {
+    fn new(stream: S) -> Result<Self> {
+        let mut qmp = Self {
+            stream: BufReader::new(stream),
+        };
+
+        if qmp.receive()?.get("QMP").is_none() {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+                .log_error("the socket does not speak qmp"));
+        }
+
+        qmp.execute("qmp_capabilities", None)?;
+        Ok(qmp)
+    }
+
+    /// Executes `command`, `arguments` is a json object.
+    fn execute(&mut self, command: &str, arguments: Option<&str>) -> Result<Json> {
+        let request = match arguments {
+            Some(arguments) => format!(
+                "{{\"execute\": \"{}\", \"arguments\": {}}}\r\n",
+                json::escape(command),
+                arguments
+            ),
+            None => format!("{{\"execute\": \"{}\"}}\r\n", json::escape(command)),
+        };
+
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(request.as_bytes())
+            .and_then(|_| stream.flush())
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToWriteFile)
+                    .log_error(format!("unable to send the qmp command: {}", err))
+            })?;
+
+        loop {
+            let mut response = self.receive()?;
+
+            if let Some(error) = response.get("error") {
+                let desc = error.get("desc").and_then(Json::as_str).unwrap_or("");
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::Unknown)
+                    .log_error(format!("qmp command {} failed: {}", command, desc)));
+            }
+
+            // asynchronous events can arrive before the response
+            if let Some(ret) = response.take("return") {
+                return Ok(ret);
+            }
+        }
+    }
+
+    fn hmp(&mut self, command: &str) -> Result<String> {
+        let arguments = format!("{{\"command-line\": \"{}\"}}", json::escape(command));
+
+        match self.execute("human-monitor-command", Some(&arguments))? {
+            Json::String(output) => Ok(output),
+            _ => Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("unexpected response to the monitor command")),
+        }
+    }
+
+    fn receive(&mut self) -> Result<Json> {
+        let mut line = String::new();
+        match self.stream.read_line(&mut line) {
+            Ok(0) => Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                .log_error("the qmp socket was closed")),
+            Ok(_) => Json::parse(&line).ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                    .log_error(format!("invalid qmp message: {}", line.trim()))
+            }),
+            Err(err) => Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to read from the qmp socket: {}", err))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answers the commands of the connector like QEMU with 1 MB of memory would.
+    fn serve(stream: TcpStream) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+
+        let mut send = |msg: &str| stream.write_all(format!("{}\r\n", msg).as_bytes()).unwrap();
+        send(
+            r#"{"QMP": {"version": {"qemu": {"micro": 0, "minor": 2, "major": 6}}, "capabilities": []}}"#,
+        );
+
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            let request = Json::parse(&line).unwrap();
+            let command = request.get("execute").and_then(Json::as_str).unwrap();
+            let command_line = request
+                .get("arguments")
+                .and_then(|a| a.get("command-line"))
+                .and_then(Json::as_str);
+
+            match (command, command_line) {
+                ("qmp_capabilities", _) => send(r#"{"return": {}}"#),
+                ("query-memory-size-summary", _) => {
+                    send(r#"{"timestamp": {"seconds": 1, "microseconds": 2}, "event": "RESUME"}"#);
+                    send(r#"{"return": {"base-memory": 1048576, "plugged-memory": 0}}"#);
+                }
+                (_, Some("xp /10xb 0x1000")) => send(
+                    r#"{"return": "0000000000001000: 0x00 0x01 0x02 0x03 0x04 0x05 0x06 0x07\r\n0000000000001008: 0x08 0xff\r\n"}"#,
+                ),
+                _ => send(r#"{"error": {"class": "GenericError", "desc": "invalid command"}}"#),
+            }
+
+            line.clear();
+        }
+    }
+
+    #[test]
+    fn monitor_reads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || serve(listener.accept().unwrap().0));
+
+        let mut mem = QmpConnector::connect(addr).unwrap();
+        assert_eq!(mem.metadata().real_size, 0x10_0000);
+        assert!(mem.metadata().readonly);
+
+        let mut buf = [0u8; 10];
+        mem.phys_view()
+            .read_raw_into(Address::from(0x1000u64), &mut buf)
+            .unwrap();
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5, 6, 7, 8, 0xff]);
+
+        assert!(mem
+            .phys_view()
+            .read_raw_into(Address::from(0x2000u64), &mut buf)
+            .is_err());
+        assert!(mem.phys_write(Address::null().into(), &buf).is_err());
+
+        std::mem::drop(mem);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,512 @@
+/*!
+Recording and replay of physical memory accesses.
+
+[`RecordingMemory`] wraps a physical memory object and appends every read and write that passes
+through it to a trace. [`ReplayMemory`] is a connector that serves the recorded data again,
+without access to the original target. Since a trace only contains the memory an OS layer
+actually touched, it is usually a small fraction of a full memory dump, which makes it suitable
+for attaching to bug reports.
+
+Traces can either store the full data of every access, or only a hash of it. Hashed traces are
+much smaller and can be used to compare two runs with each other, but they can not be replayed.
+
+The replay does not reproduce changes of the target over time: every address serves the data of
+the last recorded access to it.
+
+# Format
+
+All integers are little endian.
+
+```text
+header:  magic "MFTRACE\0" | version: u32 | flags: u32 (bit 0: full data)
+records: kind: u8 | address: u64 | length: u64 | data or hash: u64
+```
+
+Failed reads (kind 2) are stored without data or hash.
+
+# Examples
+
+```
+use memflow::connector::replay::{RecordingMemory, ReplayMemory};
+use memflow::dummy::DummyMemory;
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::types::size;
+
+let mut mem = DummyMemory::new(size::mb(1));
+mem.phys_write(0x1000.into(), &0x1234u32).unwrap();
+
+let mut recording = RecordingMemory::new(mem, vec![]).unwrap();
+let value: u32 = recording.phys_view().read(0x1000.into()).unwrap();
+let (_, trace) = recording.finish().unwrap();
+
+let mut replay = ReplayMemory::new(trace.as_slice()).unwrap();
+assert_eq!(replay.phys_view().read::<u32>(0x1000.into()).unwrap(), value);
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::snapshot::{put_addr, put_u32, put_u64, Decoder};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::*;
+use crate::mem::phys_mem::cache::PendingWrites;
+use crate::mem::{MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::types::{umem, Address};
+
+use core::cell::RefCell;
+use std::io::{Read, Write};
+
+use crate::cglue::*;
+
+/// Magic bytes at the start of every trace.
+pub const TRACE_MAGIC: [u8; 8] = *b"MFTRACE\0";
+
+/// The current version of the trace format.
+pub const TRACE_VERSION: u32 = 1;
+
+const FLAG_FULL_DATA: u32 = 1;
+
+/// The kind of a recorded memory access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TraceKind {
+    Read = 0,
+    Write = 1,
+    ReadFailed = 2,
+}
+
+/// The recorded contents of a memory access.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceContents {
+    /// The access failed, there is no data.
+    Empty,
+    /// Hash of the data, see [`hash_data`].
+    Hash(u64),
+    Data(Vec<u8>),
+}
+
+/// A single recorded memory access.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub kind: TraceKind,
+    /// Physical address of the access.
+    pub addr: Address,
+    /// Length of the access in bytes.
+    pub len: umem,
+    pub contents: TraceContents,
+}
+
+/// A decoded trace.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    /// Whether the records hold the full data, or only a hash of it.
+    pub full_data: bool,
+    /// All recorded accesses, in the order they happened.
+    pub records: Vec<TraceRecord>,
+}
+
+impl Trace {
+    /// Reads and decodes a whole trace.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to read the trace: {}", err))
+        })?;
+
+        let mut decoder = Decoder(&buf);
+        if decoder.take(TRACE_MAGIC.len()) != Some(&TRACE_MAGIC[..]) {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("the file is not a memflow trace"));
+        }
+
+        let (version, flags) = (decoder.u32(), decoder.u32());
+        if version != Some(TRACE_VERSION) {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::VersionMismatch)
+                .log_error(format!("unsupported trace version {:?}", version)));
+        }
+
+        let full_data = flags.unwrap_or_default() & FLAG_FULL_DATA != 0;
+
+        let mut records = vec![];
+        while !decoder.0.is_empty() {
+            let record = Self::decode_record(&mut decoder, full_data).ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                    .log_error(format!("trace record {} is corrupted", records.len()))
+            })?;
+            records.push(record);
+        }
+
+        Ok(Self { full_data, records })
+    }
+
+    fn decode_record(decoder: &mut Decoder, full_data: bool) -> Option<TraceRecord> {
+        let kind = match decoder.u8()? {
+            0 => TraceKind::Read,
+            1 => TraceKind::Write,
+            2 => TraceKind::ReadFailed,
+            _ => return None,
+        };
+        let addr = decoder.addr()?;
+        let len = decoder.u64()? as umem;
+
+        let contents = match kind {
+            TraceKind::ReadFailed => TraceContents::Empty,
+            _ if full_data => TraceContents::Data(decoder.take(len as usize)?.to_vec()),
+            _ => TraceContents::Hash(decoder.u64()?),
+        };
+
+        Some(TraceRecord {
+            kind,
+            addr,
+            len,
+            contents,
+        })
+    }
+}
+
+/// Hashes the data of an access as stored in hashed traces (64-bit FNV-1a).
+pub fn hash_data(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn encode_record(out: &mut Vec<u8>, kind: TraceKind, addr: Address, data: &[u8], full_data: bool) {
+    out.push(kind as u8);
+    put_addr(out, addr);
+    put_u64(out, data.len() as u64);
+
+    match kind {
+        TraceKind::ReadFailed => {}
+        _ if full_data => out.extend_from_slice(data),
+        _ => put_u64(out, hash_data(data)),
+    }
+}
+
+fn write_error(err: std::io::Error) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::UnableToWriteFile)
+        .log_error(format!("unable to write the trace: {}", err))
+}
+
+/// Physical memory wrapper recording all accesses into a trace.
+///
+/// The records of every batch are written to the trace once the batch completed. Writes are
+/// recorded as they were requested, regardless of whether they succeeded.
+pub struct RecordingMemory<T, W> {
+    mem: T,
+    trace: W,
+    full_data: bool,
+    records: Vec<u8>,
+}
+
+impl<T: PhysicalMemory, W: Write + Send> RecordingMemory<T, W> {
+    /// Records the full data of all accesses to `mem` into `trace`.
+    pub fn new(mem: T, trace: W) -> Result<Self> {
+        Self::with_full_data(mem, trace, true)
+    }
+
+    /// Records only a hash of the accessed data into `trace`.
+    pub fn hashed(mem: T, trace: W) -> Result<Self> {
+        Self::with_full_data(mem, trace, false)
+    }
+
+    fn with_full_data(mem: T, mut trace: W, full_data: bool) -> Result<Self> {
+        let mut header = TRACE_MAGIC.to_vec();
+        put_u32(&mut header, TRACE_VERSION);
+        put_u32(&mut header, if full_data { FLAG_FULL_DATA } else { 0 });
+
+        trace.write_all(&header).map_err(write_error)?;
+
+        Ok(Self {
+            mem,
+            trace,
+            full_data,
+            records: vec![],
+        })
+    }
+
+    /// Flushes the trace and returns the memory object and the trace writer.
+    pub fn finish(mut self) -> Result<(T, W)> {
+        self.trace.flush().map_err(write_error)?;
+        Ok((self.mem, self.trace))
+    }
+
+    fn write_records(&mut self) -> Result<()> {
+        let ret = self.trace.write_all(&self.records).map_err(write_error);
+        self.records.clear();
+        ret
+    }
+}
+
+impl<T: PhysicalMemory, W: Write + Send> PhysicalMemory for RecordingMemory<T, W> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let full_data = self.full_data;
+
+        // replace the meta addresses with offsets, so that partial results can be mapped back
+        // onto the physical address: (physical address, meta address, offset)
+        let mut elems = vec![];
+        let mut offset: umem = 0;
+        let inputs = inp
+            .map(|CTup3(addr, meta_addr, buf)| {
+                elems.push((addr.address(), meta_addr, offset));
+                let data = CTup3(addr, Address::from(offset), buf);
+                offset += data.2.len() as umem;
+                data
+            })
+            .collect::<Vec<_>>();
+
+        let locate = |offset: Address| {
+            let offset = offset.to_umem();
+            let idx = elems.partition_point(|&(_, _, start)| start <= offset) - 1;
+            let (addr, meta_addr, start) = elems[idx];
+            (addr + (offset - start), meta_addr + (offset - start))
+        };
+
+        let ret = {
+            let records = RefCell::new(&mut self.records);
+
+            let succeeded = &mut |CTup2(offset, buf): ReadData| {
+                let (addr, meta_addr) = locate(offset);
+                let kind = TraceKind::Read;
+                encode_record(&mut records.borrow_mut(), kind, addr, &buf, full_data);
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf))
+            };
+            let failed = &mut |CTup2(offset, buf): ReadData| {
+                let (addr, meta_addr) = locate(offset);
+                let kind = TraceKind::ReadFailed;
+                encode_record(&mut records.borrow_mut(), kind, addr, &buf, full_data);
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+            };
+
+            let mem = &mut self.mem;
+            MemOps::with_raw(
+                inputs.into_iter(),
+                Some(&mut succeeded.into()),
+                Some(&mut failed.into()),
+                |data| mem.phys_read_raw_iter(data),
+            )
+        };
+
+        self.write_records()?;
+        ret
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let Self {
+            mem,
+            full_data,
+            records,
+            ..
+        } = self;
+
+        let inp = inp.map(|CTup3(addr, meta_addr, data): PhysicalWriteData| {
+            encode_record(records, TraceKind::Write, addr.address(), &data, *full_data);
+            CTup3(addr, meta_addr, data)
+        });
+
+        let ret = MemOps::with_raw(inp, out, out_fail, |data| mem.phys_write_raw_iter(data));
+
+        self.write_records()?;
+        ret
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
+}
+
+/// Connector serving the data of a recorded trace.
+///
+/// Reads of memory that was not recorded fail. Writes are kept in memory and are visible to
+/// subsequent reads.
+///
+/// # Examples
+/// ```no_run
+/// use memflow::connector::replay::ReplayMemory;
+///
+/// let replay = ReplayMemory::open("bug-report.mftrace").unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ReplayMemory {
+    image: PendingWrites,
+    max_address: Address,
+}
+
+impl ReplayMemory {
+    /// Decodes the trace in `reader` and replays it.
+    pub fn new<R: Read>(mut reader: R) -> Result<Self> {
+        Self::from_trace(&Trace::read(&mut reader)?)
+    }
+
+    /// Opens a trace file.
+    pub fn open<P: AsRef<::std::path::Path>>(path: P) -> Result<Self> {
+        let file = ::std::fs::File::open(path).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to open the trace: {}", err))
+        })?;
+        Self::new(std::io::BufReader::new(file))
+    }
+
+    /// Replays an already decoded trace, which has to contain the full data.
+    pub fn from_trace(trace: &Trace) -> Result<Self> {
+        if !trace.full_data {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+                .log_error("hashed traces can not be replayed"));
+        }
+
+        let mut image = PendingWrites::default();
+        let mut max_address = Address::null();
+
+        for record in trace.records.iter() {
+            if let TraceContents::Data(data) = &record.contents {
+                if !data.is_empty() {
+                    image.insert(record.addr, data);
+                    max_address = std::cmp::max(max_address, record.addr + (data.len() - 1));
+                }
+            }
+        }
+
+        Ok(Self { image, max_address })
+    }
+}
+
+impl PhysicalMemory for ReplayMemory {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            let cont = if self.image.patch(addr.address(), &mut buf) == buf.len() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf))
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+            };
+            if !cont {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, mut out, .. }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, data) in inp {
+            self.image.insert(addr.address(), data.into());
+            if !opt_call(out.as_deref_mut(), CTup2(meta_addr, data)) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.max_address,
+            real_size: self.image.bytes() as umem,
+            readonly: false,
+            ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
+        }
+    }
+}
+
+cglue_impl_group!(ReplayMemory, crate::plugins::ConnectorInstance, {});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cglue::ForwardMut;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn record_and_replay() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(Address::from(0x1000).into(), &[1u8; 16])
+            .unwrap();
+
+        let mut recording = RecordingMemory::new(mem.forward_mut(), vec![]).unwrap();
+        let mut buf = [0u8; 16];
+        recording
+            .phys_read_into(Address::from(0x1000).into(), &mut buf)
+            .unwrap();
+        recording
+            .phys_write(Address::from(0x2000).into(), &[2u8; 4])
+            .unwrap();
+        recording
+            .phys_view()
+            .read_raw_list(&mut [CTup2(Address::from(size::mb(2)), buf.as_mut().into())])
+            .unwrap_err();
+        let (_, trace) = recording.finish().unwrap();
+
+        let decoded = Trace::read(&mut trace.as_slice()).unwrap();
+        let kinds = decoded.records.iter().map(|r| r.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [TraceKind::Read, TraceKind::Write, TraceKind::ReadFailed]
+        );
+
+        let mut replay = ReplayMemory::new(trace.as_slice()).unwrap();
+        replay
+            .phys_read_into(Address::from(0x1000).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, [1; 16]);
+        let mut data = [0u8; 4];
+        replay
+            .phys_read_into(Address::from(0x2000).into(), &mut data)
+            .unwrap();
+        assert_eq!(data, [2; 4]);
+
+        // never recorded
+        replay
+            .phys_view()
+            .read_raw_list(&mut [CTup2(Address::from(0x3000), data.as_mut().into())])
+            .unwrap_err();
+    }
+
+    #[test]
+    fn hashed_trace() {
+        let mut recording = RecordingMemory::hashed(DummyMemory::new(size::mb(1)), vec![]).unwrap();
+        recording.phys_view().read::<u64>(0x1000.into()).unwrap();
+        let (_, trace) = recording.finish().unwrap();
+
+        let decoded = Trace::read(&mut trace.as_slice()).unwrap();
+        assert!(!decoded.full_data);
+        assert_eq!(
+            decoded.records[0].contents,
+            TraceContents::Hash(hash_data(&[0; 8]))
+        );
+
+        assert!(ReplayMemory::from_trace(&decoded).is_err());
+    }
+}
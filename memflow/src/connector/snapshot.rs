@@ -0,0 +1,710 @@
+/*!
+Native memflow snapshot container.
+
+A snapshot is a single file that holds everything needed to re-analyze a target offline:
+
+* the captured physical memory, stored as chunks,
+* the memory map of the target at the time of capture,
+* OS metadata (name, kernel base and size, architecture, directory table base and arbitrary
+  properties),
+* optionally a set of cached virtual to physical translations.
+
+Snapshots are written sequentially with [`SnapshotWriter`] and reopened with
+[`SnapshotMemory`], a read-only connector over the captured chunks. Since the connector never
+touches the live target, every analysis of a snapshot is deterministic.
+
+# Format
+
+All integers are little endian.
+
+```text
+header:  magic "MFSNAPSH" | version: u32 | reserved: u32
+chunks:  raw physical memory, in the order it was written
+toc:     chunk table | metadata | translation table
+footer:  toc offset: u64 | magic "MFSNAPSH"
+```
+
+# Examples
+
+```
+use memflow::connector::snapshot::{SnapshotMemory, SnapshotWriter};
+use memflow::dummy::DummyMemory;
+use memflow::mem::PhysicalMemory;
+use memflow::types::{size, Address};
+
+let mut mem = DummyMemory::new(size::mb(1));
+
+let mut writer = SnapshotWriter::new(std::io::Cursor::new(vec![])).unwrap();
+writer.metadata_mut().os_name = Some("dummy".into());
+writer
+    .capture(&mut mem, Address::null()..Address::from(size::mb(1)))
+    .unwrap();
+let file = writer.finish().unwrap();
+
+let snapshot = SnapshotMemory::new(file).unwrap();
+assert_eq!(snapshot.snapshot().metadata.os_name.as_deref(), Some("dummy"));
+assert_eq!(snapshot.metadata().real_size, size::mb(1) as _);
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureIdent;
+use crate::connector::{CloneFile, FileIoMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::acquire::{read_chunk, DEFAULT_CHUNK_SIZE};
+use crate::mem::{
+    MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::os::OsInfo;
+use crate::types::{umem, Address};
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use crate::cglue::*;
+
+/// Magic bytes at the start and the end of every snapshot.
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"MFSNAPSH";
+
+/// The current version of the snapshot format.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const HEADER_SIZE: u64 = 16;
+const FOOTER_SIZE: u64 = 16;
+
+/// A contiguous part of physical memory stored in a snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotChunk {
+    /// Physical address of the chunk.
+    pub base: Address,
+    /// Size of the chunk in bytes.
+    pub size: umem,
+    /// Offset of the chunk data in the snapshot file.
+    pub offset: u64,
+}
+
+/// A virtual to physical translation recorded at capture time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CachedTranslation {
+    /// Directory table base the translation belongs to.
+    pub dtb: Address,
+    /// Virtual base address of the page.
+    pub virt: Address,
+    /// Physical base address of the page.
+    pub phys: Address,
+    /// Size of the page.
+    pub size: umem,
+}
+
+/// Metadata stored next to the memory of a snapshot.
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotMetadata {
+    /// Name of the OS plugin the snapshot was taken with.
+    pub os_name: Option<String>,
+    /// Information about the OS kernel.
+    pub os_info: Option<OsInfo>,
+    /// Directory table base of the kernel.
+    pub dtb: Option<Address>,
+    /// Physical memory map of the target. Parts of it may be missing from the chunks if they
+    /// could not be read.
+    pub mem_map: Vec<Range<Address>>,
+    /// Arbitrary properties, e.g. the kernel version or the connector that was used.
+    pub properties: BTreeMap<String, String>,
+}
+
+/// The table of contents of a snapshot.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    pub metadata: SnapshotMetadata,
+    /// All stored chunks, sorted by physical address.
+    pub chunks: Vec<SnapshotChunk>,
+    pub translations: Vec<CachedTranslation>,
+}
+
+impl Snapshot {
+    /// Reads the table of contents of a snapshot.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut header = [0u8; HEADER_SIZE as usize];
+        let mut footer = [0u8; FOOTER_SIZE as usize];
+
+        let file_size = reader
+            .seek(SeekFrom::End(0))
+            .and_then(|size| {
+                reader.seek(SeekFrom::Start(0))?;
+                reader.read_exact(&mut header)?;
+                reader.seek(SeekFrom::End(-(FOOTER_SIZE as i64)))?;
+                reader.read_exact(&mut footer)?;
+                Ok(size)
+            })
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                    .log_error(format!("unable to read the snapshot: {}", err))
+            })?;
+
+        if header[..8] != SNAPSHOT_MAGIC || footer[8..] != SNAPSHOT_MAGIC {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("the file is not a memflow snapshot"));
+        }
+
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != SNAPSHOT_VERSION {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::VersionMismatch)
+                .log_error(format!("unsupported snapshot version {}", version)));
+        }
+
+        let toc_offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+        if toc_offset < HEADER_SIZE || toc_offset > file_size - FOOTER_SIZE {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("invalid snapshot table of contents offset"));
+        }
+
+        let mut toc = vec![0u8; (file_size - FOOTER_SIZE - toc_offset) as usize];
+        reader
+            .seek(SeekFrom::Start(toc_offset))
+            .and_then(|_| reader.read_exact(&mut toc))
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                    .log_error(format!("unable to read the snapshot: {}", err))
+            })?;
+
+        Self::decode(&mut Decoder(&toc)).ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("the snapshot table of contents is corrupted")
+        })
+    }
+
+    /// Returns the memory map translating physical addresses to offsets in the snapshot file.
+    pub fn mem_map(&self) -> MemoryMap<(Address, umem)> {
+        let mut mem_map = MemoryMap::new();
+        for chunk in self.chunks.iter() {
+            mem_map.push_remap(chunk.base, chunk.size, Address::from(chunk.offset));
+        }
+        mem_map
+    }
+
+    /// Looks up a virtual address in the cached translations.
+    pub fn translate(&self, dtb: Address, virt: Address) -> Option<Address> {
+        self.translations
+            .iter()
+            .find(|t| t.dtb == dtb && t.virt <= virt && virt < t.virt + t.size)
+            .map(|t| t.phys + (virt - t.virt) as umem)
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        put_u32(out, self.chunks.len() as u32);
+        for chunk in self.chunks.iter() {
+            put_addr(out, chunk.base);
+            put_u64(out, chunk.size as u64);
+            put_u64(out, chunk.offset);
+        }
+
+        let metadata = &self.metadata;
+
+        put_opt(out, metadata.os_name.as_ref(), |out, name| {
+            put_str(out, name)
+        });
+        put_opt(out, metadata.os_info.as_ref(), |out, info| {
+            put_addr(out, info.base);
+            put_u64(out, info.size as u64);
+            put_arch(out, info.arch);
+        });
+        put_opt(out, metadata.dtb.as_ref(), |out, dtb| put_addr(out, *dtb));
+
+        put_u32(out, metadata.mem_map.len() as u32);
+        for range in metadata.mem_map.iter() {
+            put_addr(out, range.start);
+            put_addr(out, range.end);
+        }
+
+        put_u32(out, metadata.properties.len() as u32);
+        for (key, value) in metadata.properties.iter() {
+            put_str(out, key);
+            put_str(out, value);
+        }
+
+        put_u32(out, self.translations.len() as u32);
+        for t in self.translations.iter() {
+            put_addr(out, t.dtb);
+            put_addr(out, t.virt);
+            put_addr(out, t.phys);
+            put_u64(out, t.size as u64);
+        }
+    }
+
+    fn decode(d: &mut Decoder) -> Option<Self> {
+        let chunks = (0..d.u32()?)
+            .map(|_| {
+                Some(SnapshotChunk {
+                    base: d.addr()?,
+                    size: d.u64()? as umem,
+                    offset: d.u64()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let os_name = d.opt(|d| d.string())?;
+        let os_info = d.opt(|d| {
+            Some(OsInfo {
+                base: d.addr()?,
+                size: d.u64()? as umem,
+                arch: d.arch()?,
+            })
+        })?;
+        let dtb = d.opt(|d| d.addr())?;
+
+        let mem_map = (0..d.u32()?)
+            .map(|_| Some(d.addr()?..d.addr()?))
+            .collect::<Option<Vec<_>>>()?;
+
+        let properties = (0..d.u32()?)
+            .map(|_| Some((d.string()?, d.string()?)))
+            .collect::<Option<BTreeMap<_, _>>>()?;
+
+        let translations = (0..d.u32()?)
+            .map(|_| {
+                Some(CachedTranslation {
+                    dtb: d.addr()?,
+                    virt: d.addr()?,
+                    phys: d.addr()?,
+                    size: d.u64()? as umem,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            metadata: SnapshotMetadata {
+                os_name,
+                os_info,
+                dtb,
+                mem_map,
+                properties,
+            },
+            chunks,
+            translations,
+        })
+    }
+}
+
+/// Writes a snapshot sequentially.
+///
+/// The table of contents is only written by [`SnapshotWriter::finish`], dropping the writer
+/// before leaves an incomplete snapshot behind.
+pub struct SnapshotWriter<W> {
+    out: W,
+    offset: u64,
+    snapshot: Snapshot,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    /// Creates a new snapshot and writes its header to `out`.
+    pub fn new(mut out: W) -> Result<Self> {
+        let mut header = SNAPSHOT_MAGIC.to_vec();
+        put_u32(&mut header, SNAPSHOT_VERSION);
+        put_u32(&mut header, 0);
+
+        out.write_all(&header).map_err(write_error)?;
+
+        Ok(Self {
+            out,
+            offset: HEADER_SIZE,
+            snapshot: Snapshot::default(),
+        })
+    }
+
+    /// Returns the metadata that will be stored in the snapshot.
+    pub fn metadata_mut(&mut self) -> &mut SnapshotMetadata {
+        &mut self.snapshot.metadata
+    }
+
+    /// Records a translation in the snapshot.
+    pub fn push_translation(&mut self, translation: CachedTranslation) {
+        self.snapshot.translations.push(translation);
+    }
+
+    /// Stores `data` as the physical memory at `base`.
+    ///
+    /// Chunks must not overlap. Adjacent chunks are merged.
+    pub fn write_chunk(&mut self, base: Address, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let end = base + data.len();
+        if self
+            .snapshot
+            .chunks
+            .iter()
+            .any(|c| c.base < end && base < c.base + c.size)
+        {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument).log_error(format!(
+                    "snapshot chunk at {} overlaps an existing chunk",
+                    base
+                )),
+            );
+        }
+
+        self.out.write_all(data).map_err(write_error)?;
+
+        match self.snapshot.chunks.last_mut() {
+            Some(last)
+                if last.base + last.size == base
+                    && last.offset + last.size as u64 == self.offset =>
+            {
+                last.size += data.len() as umem
+            }
+            _ => self.snapshot.chunks.push(SnapshotChunk {
+                base,
+                size: data.len() as umem,
+                offset: self.offset,
+            }),
+        }
+
+        self.offset += data.len() as u64;
+
+        Ok(())
+    }
+
+    /// Captures the physical memory `range` of `mem` and adds it to the memory map.
+    ///
+    /// Pages that can not be read are left out of the snapshot and returned.
+    pub fn capture<T: PhysicalMemory>(
+        &mut self,
+        mem: &mut T,
+        range: Range<Address>,
+    ) -> Result<Vec<Range<Address>>> {
+        let mut failed = vec![];
+        let mut buf = vec![0u8; DEFAULT_CHUNK_SIZE];
+
+        let mut addr = range.start;
+        while addr < range.end {
+            let len = std::cmp::min((range.end - addr) as usize, buf.len());
+            let chunk = &mut buf[..len];
+
+            let mut chunk_failed = vec![];
+            read_chunk(mem, addr.to_umem(), chunk, &mut chunk_failed)?;
+
+            let mut start = addr;
+            for bad in chunk_failed
+                .iter()
+                .chain(std::iter::once(&(addr + len..addr + len)))
+            {
+                let from = (start - addr) as usize;
+                let to = (bad.start - addr) as usize;
+                self.write_chunk(start, &chunk[from..to])?;
+                start = bad.end;
+            }
+
+            failed.extend(chunk_failed);
+            addr += len;
+        }
+
+        self.snapshot.metadata.mem_map.push(range);
+
+        Ok(failed)
+    }
+
+    /// Writes the table of contents and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.snapshot.chunks.sort_by_key(|c| c.base);
+
+        let mut toc = vec![];
+        self.snapshot.encode(&mut toc);
+        put_u64(&mut toc, self.offset);
+        toc.extend_from_slice(&SNAPSHOT_MAGIC);
+
+        self.out
+            .write_all(&toc)
+            .and_then(|_| self.out.flush())
+            .map_err(write_error)?;
+
+        Ok(self.out)
+    }
+}
+
+fn write_error(err: std::io::Error) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::UnableToWriteFile)
+        .log_error(format!("unable to write the snapshot: {}", err))
+}
+
+/// Read-only connector over a snapshot.
+///
+/// # Examples
+/// ```no_run
+/// use memflow::connector::snapshot::SnapshotMemory;
+///
+/// let snapshot = SnapshotMemory::open("target.mfsnap").unwrap();
+/// println!("{:?}", snapshot.snapshot().metadata.os_info);
+/// ```
+#[derive(Clone)]
+pub struct SnapshotMemory<T> {
+    mem: FileIoMemory<T>,
+    snapshot: Snapshot,
+}
+
+impl<T: Read + Seek + Write + Send> SnapshotMemory<T> {
+    /// Reopens the snapshot stored in `reader`.
+    pub fn new(mut reader: T) -> Result<Self> {
+        let snapshot = Snapshot::read(&mut reader)?;
+        let mem = FileIoMemory::try_with_reader(reader, snapshot.mem_map())?;
+        Ok(Self { mem, snapshot })
+    }
+
+    /// Returns the table of contents of the snapshot.
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+}
+
+impl SnapshotMemory<CloneFile> {
+    /// Opens a snapshot file.
+    pub fn open<P: AsRef<::std::path::Path>>(path: P) -> Result<Self> {
+        let file = ::std::fs::File::open(path).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to open the snapshot: {}", err))
+        })?;
+        Self::new(file.into())
+    }
+}
+
+impl<T: Read + Seek + Write + Send> PhysicalMemory for SnapshotMemory<T> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.mem.phys_read_raw_iter(data)
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_warn("snapshots can not be written to"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            readonly: true,
+            ..self.mem.metadata()
+        }
+    }
+}
+
+cglue_impl_group!(
+    SnapshotMemory<T: Read + Seek + Write + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+pub(super) fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(super) fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(super) fn put_addr(out: &mut Vec<u8>, addr: Address) {
+    put_u64(out, addr.to_umem() as u64);
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn put_arch(out: &mut Vec<u8>, arch: ArchitectureIdent) {
+    match arch {
+        ArchitectureIdent::Unknown(id) => {
+            out.push(0);
+            put_u64(out, id as u64);
+        }
+        ArchitectureIdent::X86(bits, ext) => out.extend_from_slice(&[1, bits, ext as u8]),
+        ArchitectureIdent::AArch64(page_size) => {
+            out.push(2);
+            put_u64(out, page_size as u64);
+        }
+        ArchitectureIdent::RiscV64(va_bits) => out.extend_from_slice(&[3, va_bits]),
+        ArchitectureIdent::Ppc64(page_size, le) => {
+            out.push(4);
+            put_u64(out, page_size as u64);
+            out.push(le as u8);
+        }
+        ArchitectureIdent::Mips(bits, le) => out.extend_from_slice(&[5, bits, le as u8]),
+    }
+}
+
+fn put_opt<T>(out: &mut Vec<u8>, value: Option<T>, put: impl FnOnce(&mut Vec<u8>, T)) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            put(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+pub(super) struct Decoder<'a>(pub(super) &'a [u8]);
+
+impl<'a> Decoder<'a> {
+    pub(super) fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (data, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(data)
+    }
+
+    pub(super) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    pub(super) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    pub(super) fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    pub(super) fn addr(&mut self) -> Option<Address> {
+        self.u64().map(Address::from)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn arch(&mut self) -> Option<ArchitectureIdent> {
+        match self.u8()? {
+            0 => Some(ArchitectureIdent::Unknown(self.u64()? as usize)),
+            1 => Some(ArchitectureIdent::X86(self.u8()?, self.u8()? != 0)),
+            2 => Some(ArchitectureIdent::AArch64(self.u64()? as usize)),
+            3 => Some(ArchitectureIdent::RiscV64(self.u8()?)),
+            4 => Some(ArchitectureIdent::Ppc64(
+                self.u64()? as usize,
+                self.u8()? != 0,
+            )),
+            5 => Some(ArchitectureIdent::Mips(self.u8()?, self.u8()? != 0)),
+            _ => None,
+        }
+    }
+
+    /// Decodes an optional value, the outer `None` signals a decoding error.
+    fn opt<T>(&mut self, get: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => get(self).map(Some),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+    use std::io::Cursor;
+
+    fn dummy_mem() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let data = (0..size::mb(1))
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        mem.phys_write(Address::null().into(), data.as_slice())
+            .unwrap();
+        mem
+    }
+
+    fn write_snapshot(mem: &mut DummyMemory) -> Cursor<Vec<u8>> {
+        let mut writer = SnapshotWriter::new(Cursor::new(vec![])).unwrap();
+
+        let metadata = writer.metadata_mut();
+        metadata.os_name = Some("dummy".into());
+        metadata.os_info = Some(OsInfo {
+            base: Address::from(0xffff_8000_0000_0000u64),
+            size: 0x10_0000,
+            arch: ArchitectureIdent::X86(64, false),
+        });
+        metadata.dtb = Some(Address::from(0x1000u64));
+        metadata
+            .properties
+            .insert("kernel_version".into(), "5.15.0".into());
+
+        writer
+            .capture(mem, Address::null()..Address::from(0x10000u64))
+            .unwrap();
+        writer
+            .capture(mem, Address::from(0x20000u64)..Address::from(0x30000u64))
+            .unwrap();
+
+        writer.push_translation(CachedTranslation {
+            dtb: Address::from(0x1000u64),
+            virt: Address::from(0x7fff_0000u64),
+            phys: Address::from(0x20000u64),
+            size: 0x1000,
+        });
+
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut mem = dummy_mem();
+        let mut snapshot = SnapshotMemory::new(write_snapshot(&mut mem)).unwrap();
+
+        let toc = snapshot.snapshot().clone();
+        assert_eq!(toc.chunks.len(), 2);
+        assert_eq!(toc.metadata.os_name.as_deref(), Some("dummy"));
+        assert_eq!(
+            toc.metadata.os_info.as_ref().map(|i| i.arch),
+            Some(ArchitectureIdent::X86(64, false))
+        );
+        assert_eq!(toc.metadata.dtb, Some(Address::from(0x1000u64)));
+        assert_eq!(toc.metadata.mem_map.len(), 2);
+        assert_eq!(
+            toc.metadata
+                .properties
+                .get("kernel_version")
+                .map(String::as_str),
+            Some("5.15.0")
+        );
+        assert_eq!(
+            toc.translate(Address::from(0x1000u64), Address::from(0x7fff_0123u64)),
+            Some(Address::from(0x20123u64))
+        );
+
+        let mut expected = [0u8; 0x100];
+        let mut actual = [0u8; 0x100];
+        for &addr in [0x1000u64, 0x2ff00].iter() {
+            mem.phys_read_into(addr.into(), &mut expected).unwrap();
+            snapshot.phys_read_into(addr.into(), &mut actual).unwrap();
+            assert_eq!(expected, actual);
+        }
+
+        // memory outside of the captured ranges is not available
+        assert!(snapshot
+            .phys_view()
+            .read_raw_into(Address::from(0x18000u64), &mut actual)
+            .is_err());
+
+        assert!(snapshot.metadata().readonly);
+        assert!(snapshot
+            .phys_write(Address::null().into(), &actual)
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let mut mem = dummy_mem();
+        let mut file = write_snapshot(&mut mem).into_inner();
+
+        let last = file.len() - 1;
+        file[last] ^= 0xff;
+        assert!(SnapshotMemory::new(Cursor::new(file)).is_err());
+
+        assert!(SnapshotMemory::new(Cursor::new(vec![0u8; 64])).is_err());
+    }
+}
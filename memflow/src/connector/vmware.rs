@@ -0,0 +1,529 @@
+/*!
+Connector for the memory of suspended or snapshotted VMware guests.
+
+VMware stores the state of a suspended VM in a `.vmss` file and the state of a snapshot in a
+`.vmsn` file. Both consist of groups of tagged values describing the devices of the VM. The guest
+memory is either embedded in the state file, as the data of the `Memory` tag of the `memory`
+group, or stored in a `.vmem` file next to it.
+
+Guests with more memory than fits below the PCI hole have their memory split into regions, the
+`regionPPN`, `regionPageNum` and `regionSize` tags map every region of physical memory to its
+pages in the memory data. [`VmwareConnector`] parses these tags to build the physical memory
+layout. A `.vmem` file opened without its state file is mapped linearly starting at address 0.
+
+The connector only reads from the files, writes are rejected.
+
+# Examples
+
+```no_run
+use memflow::connector::VmwareConnector;
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::types::Address;
+
+// uses the layout stored in vm.vmss, next to the memory file
+let mut mem = VmwareConnector::open("vm.vmem").unwrap();
+let value: u64 = mem.phys_view().read(Address::from(0x1000u64)).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::connector::{CloneFile, FileIoMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{size, umem, Address};
+
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::cglue::*;
+
+/// Magic values of the supported state file versions.
+const MAGICS: [u32; 4] = [0xbed2_bed0, 0xbad1_bad1, 0xbed2_bed2, 0xbed3_bed3];
+/// Versions storing the sizes of large tags as 64-bit values.
+const MAGICS_64: [u32; 2] = [0xbed2_bed2, 0xbed3_bed3];
+
+const GROUP_SIZE: usize = 80;
+const GROUP_NAME_SIZE: usize = 64;
+
+/// Tags with this data size store their real size after the indices.
+const LARGE_TAG_SIZES: [u8; 2] = [62, 63];
+
+/// Maximum number of groups in a state file.
+const MAX_GROUPS: u32 = 0x1000;
+
+const PAGE_SIZE: umem = size::kb(4) as umem;
+
+/// A single tagged value of a VMware state file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmwareTag {
+    pub group: String,
+    pub name: String,
+    pub indices: Vec<u32>,
+    /// Offset of the data in the state file.
+    pub data_offset: u64,
+    /// Size of the data in the state file.
+    pub data_size: u64,
+    /// Size of the data once loaded, differs from `data_size` for compressed data.
+    pub mem_size: u64,
+    /// Value of tags holding at most 8 bytes of data.
+    pub value: Option<u64>,
+}
+
+/// The groups and tags of a VMware state file.
+#[derive(Clone, Debug, Default)]
+pub struct VmwareState {
+    tags: Vec<VmwareTag>,
+}
+
+impl VmwareState {
+    /// Parses the groups and tags of a `.vmss` or `.vmsn` file.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut header = [0u8; 12];
+        read_exact_at(reader, 0, &mut header)?;
+
+        let magic = u32::from_le_bytes(header[..4].try_into().unwrap());
+        if !MAGICS.contains(&magic) {
+            return Err(invalid_state("the file is not a VMware state file"));
+        }
+        let wide_sizes = MAGICS_64.contains(&magic);
+
+        let group_count = u32::from_le_bytes(header[8..].try_into().unwrap());
+        if group_count > MAX_GROUPS {
+            return Err(invalid_state("too many groups in the state file"));
+        }
+
+        let mut groups = vec![0u8; group_count as usize * GROUP_SIZE];
+        read_exact_at(reader, header.len() as u64, &mut groups)?;
+
+        let mut tags = vec![];
+        for group in groups.chunks_exact(GROUP_SIZE) {
+            let name = group[..GROUP_NAME_SIZE]
+                .split(|&b| b == 0)
+                .next()
+                .unwrap_or_default();
+            let name = String::from_utf8_lossy(name).into_owned();
+            let offset = u64::from_le_bytes(
+                group[GROUP_NAME_SIZE..GROUP_NAME_SIZE + 8]
+                    .try_into()
+                    .unwrap(),
+            );
+            read_tags(reader, &name, offset, wide_sizes, &mut tags)?;
+        }
+
+        Ok(Self { tags })
+    }
+
+    /// Returns all tags of the state file.
+    pub fn tags(&self) -> &[VmwareTag] {
+        &self.tags
+    }
+
+    /// Returns the tag `name` of `group` with the given indices.
+    pub fn tag(&self, group: &str, name: &str, indices: &[u32]) -> Option<&VmwareTag> {
+        self.tags
+            .iter()
+            .find(|t| t.group == group && t.name == name && t.indices == indices)
+    }
+
+    /// Returns the value of the tag `name` of `group` with the given indices.
+    pub fn value(&self, group: &str, name: &str, indices: &[u32]) -> Option<u64> {
+        self.tag(group, name, indices).and_then(|t| t.value)
+    }
+
+    /// Returns the offset and size of the guest memory, if it is embedded in the state file.
+    pub fn embedded_memory(&self) -> Option<(u64, u64)> {
+        self.tag("memory", "Memory", &[0, 0])
+            .filter(|t| t.data_size > 0)
+            .map(|t| (t.data_offset, t.data_size))
+    }
+
+    /// Builds the physical memory layout of the guest.
+    ///
+    /// `base` is the offset of the memory data in its file and `size` its size. Guests without
+    /// memory regions have their memory mapped linearly starting at address 0.
+    pub fn memory_map(&self, base: u64, size: u64) -> Result<MemoryMap<(Address, umem)>> {
+        let mut map = MemoryMap::new();
+
+        let regions = self.value("memory", "regionsCount", &[]).unwrap_or(0);
+        if regions == 0 {
+            if size > 0 {
+                map.push_remap(Address::null(), size as umem, Address::from(base));
+            }
+            return Ok(map);
+        }
+
+        let mut ranges = (0..regions as u32)
+            .map(|i| {
+                let region = |name: &str| {
+                    self.value("memory", name, &[i])
+                        .map(|pages| pages as umem * PAGE_SIZE)
+                        .ok_or_else(|| invalid_state("incomplete memory region in the state file"))
+                };
+                Ok((
+                    region("regionPPN")?,
+                    region("regionPageNum")?,
+                    region("regionSize")?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ranges.sort_unstable();
+
+        let mut end = 0;
+        for (phys, page, len) in ranges {
+            if phys < end
+                || page
+                    .checked_add(len)
+                    .map_or(true, |page_end| page_end > size as umem)
+            {
+                return Err(
+                    Error(ErrorOrigin::Connector, ErrorKind::MemoryMapOutOfRange).log_error(
+                        format!("memory region at {:x} exceeds the memory data", phys),
+                    ),
+                );
+            }
+
+            if len > 0 {
+                map.push_remap(phys.into(), len, Address::from(base + page as u64));
+            }
+            end = phys + len;
+        }
+
+        Ok(map)
+    }
+}
+
+fn read_tags<R: Read + Seek>(
+    reader: &mut R,
+    group: &str,
+    mut offset: u64,
+    wide_sizes: bool,
+    out: &mut Vec<VmwareTag>,
+) -> Result<()> {
+    loop {
+        let mut head = [0u8; 2];
+        read_exact_at(reader, offset, &mut head)?;
+
+        let [flags, name_len] = head;
+        if flags == 0 && name_len == 0 {
+            return Ok(());
+        }
+
+        let index_count = (flags >> 6) as usize;
+        let mut name = vec![0u8; name_len as usize + index_count * 4];
+        reader.read_exact(&mut name).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err)
+        })?;
+        let indices = name
+            .split_off(name_len as usize)
+            .chunks_exact(4)
+            .map(|i| u32::from_le_bytes(i.try_into().unwrap()))
+            .collect();
+
+        let mut data_offset = offset + 2 + name.len() as u64 + index_count as u64 * 4;
+        let small_size = flags & 0x3f;
+
+        let (data_size, mem_size, value) = if LARGE_TAG_SIZES.contains(&small_size) {
+            let width = if wide_sizes { 8 } else { 4 };
+            let mut sizes = [0u8; 16];
+            read_exact_at(reader, data_offset, &mut sizes[..width * 2])?;
+
+            let size_at = |i: usize| {
+                let mut value = [0u8; 8];
+                value[..width].copy_from_slice(&sizes[i * width..(i + 1) * width]);
+                u64::from_le_bytes(value)
+            };
+
+            // the sizes are followed by 2 bytes of padding
+            data_offset += width as u64 * 2 + 2;
+            (size_at(0), size_at(1), None)
+        } else {
+            let mut value = [0u8; 8];
+            let len = (small_size as usize).min(value.len());
+            read_exact_at(reader, data_offset, &mut value[..len])?;
+            let value = if small_size as usize <= 8 {
+                Some(u64::from_le_bytes(value))
+            } else {
+                None
+            };
+            (small_size as u64, small_size as u64, value)
+        };
+
+        out.push(VmwareTag {
+            group: group.to_string(),
+            name: String::from_utf8_lossy(&name).into_owned(),
+            indices,
+            data_offset,
+            data_size,
+            mem_size,
+            value,
+        });
+
+        offset = data_offset
+            .checked_add(data_size)
+            .ok_or_else(|| invalid_state("tag exceeds the state file"))?;
+    }
+}
+
+/// Read-only connector over the memory of a VMware guest.
+#[derive(Clone)]
+pub struct VmwareConnector<T> {
+    mem: FileIoMemory<T>,
+}
+
+impl<T: Read + Seek + Write + Send> VmwareConnector<T> {
+    /// Opens a `.vmss` or `.vmsn` file holding the guest memory.
+    pub fn new(mut reader: T) -> Result<Self> {
+        let state = VmwareState::read(&mut reader)?;
+
+        let memory = state.tag("memory", "Memory", &[0, 0]).ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+                .log_error("the state file does not contain the guest memory")
+        })?;
+        if memory.data_size != memory.mem_size {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+                .log_error("compressed guest memory is not supported"));
+        }
+
+        let (base, size) = state.embedded_memory().ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+                .log_error("the guest memory is stored in a separate .vmem file")
+        })?;
+
+        Self::with_layout(reader, state.memory_map(base, size)?)
+    }
+
+    /// Opens a `.vmem` file, using the memory layout stored in `state`.
+    pub fn with_state(mut memory: T, state: &VmwareState) -> Result<Self> {
+        let size = file_len(&mut memory)?;
+        Self::with_layout(memory, state.memory_map(0, size)?)
+    }
+
+    /// Opens a `.vmem` file without its state file, mapping it linearly to address 0.
+    pub fn linear(mut memory: T) -> Result<Self> {
+        let size = file_len(&mut memory)?;
+        Self::with_layout(memory, VmwareState::default().memory_map(0, size)?)
+    }
+
+    fn with_layout(reader: T, layout: MemoryMap<(Address, umem)>) -> Result<Self> {
+        Ok(Self {
+            mem: FileIoMemory::try_with_reader(reader, layout)?,
+        })
+    }
+}
+
+impl VmwareConnector<CloneFile> {
+    /// Opens the memory of a guest by the path of its `.vmem`, `.vmss` or `.vmsn` file.
+    ///
+    /// State files without embedded memory are combined with the `.vmem` file next to them.
+    /// A `.vmem` file is combined with the `.vmss` or `.vmsn` file next to it, if there is one.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let is_vmem = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("vmem"));
+
+        if is_vmem {
+            let state = ["vmss", "vmsn"]
+                .iter()
+                .map(|ext| path.with_extension(ext))
+                .find(|state| state.is_file());
+
+            let memory = open_file(path)?;
+            return match state {
+                Some(state) => {
+                    Self::with_state(memory, &VmwareState::read(&mut open_file(&state)?)?)
+                }
+                None => Self::linear(memory),
+            };
+        }
+
+        let mut file = open_file(path)?;
+        let state = VmwareState::read(&mut file)?;
+        if state.embedded_memory().is_some() {
+            Self::new(file)
+        } else {
+            Self::with_state(open_file(&path.with_extension("vmem"))?, &state)
+        }
+    }
+}
+
+fn open_file(path: &Path) -> Result<CloneFile> {
+    ::std::fs::File::open(path)
+        .map(CloneFile::from)
+        .map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(format!(
+                "unable to open {}: {}",
+                path.display(),
+                err
+            ))
+        })
+}
+
+fn file_len<T: Seek>(reader: &mut T) -> Result<u64> {
+    reader
+        .seek(SeekFrom::End(0))
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err))
+}
+
+fn read_exact_at<R: Read + Seek>(reader: &mut R, offset: u64, out: &mut [u8]) -> Result<()> {
+    reader
+        .seek(SeekFrom::Start(offset))
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err))?;
+    reader
+        .read_exact(out)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err))
+}
+
+fn invalid_state(msg: &str) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(msg)
+}
+
+impl<T: Read + Seek + Write + Send> PhysicalMemory for VmwareConnector<T> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.mem.phys_read_raw_iter(data)
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_warn("VMware memory files can not be written to"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            readonly: true,
+            ..self.mem.metadata()
+        }
+    }
+}
+
+cglue_impl_group!(
+    VmwareConnector<T: Read + Seek + Write + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+
+    use std::io::Cursor;
+
+    fn small_tag(out: &mut Vec<u8>, name: &str, indices: &[u32], value: u32) {
+        out.push(((indices.len() as u8) << 6) | 4);
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+        indices
+            .iter()
+            .for_each(|i| out.extend_from_slice(&i.to_le_bytes()));
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn large_tag(out: &mut Vec<u8>, name: &str, indices: &[u32], data: &[u8]) {
+        out.push(((indices.len() as u8) << 6) | 62);
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+        indices
+            .iter()
+            .for_each(|i| out.extend_from_slice(&i.to_le_bytes()));
+        out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        out.extend_from_slice(&[0u8; 2]);
+        out.extend_from_slice(data);
+    }
+
+    /// Builds a state file with 2 pages at 0 and 1 page at 1 MB, `memory` is embedded if given.
+    fn state(memory: Option<&[u8]>) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend_from_slice(&0xbed2_bed2u32.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&2u32.to_le_bytes());
+
+        let groups = out.len();
+        out.resize(groups + 2 * GROUP_SIZE, 0);
+
+        let group = |out: &mut Vec<u8>, i: usize, name: &str| {
+            let entry = groups + i * GROUP_SIZE;
+            out[entry..entry + name.len()].copy_from_slice(name.as_bytes());
+            let offset = (out.len() as u64).to_le_bytes();
+            out[entry + GROUP_NAME_SIZE..entry + GROUP_NAME_SIZE + 8].copy_from_slice(&offset);
+        };
+
+        group(&mut out, 0, "cpu");
+        small_tag(&mut out, "cpuid", &[0], 0x1234);
+        out.extend_from_slice(&[0u8; 2]);
+
+        group(&mut out, 1, "memory");
+        small_tag(&mut out, "regionsCount", &[], 2);
+        for (i, &(ppn, page, size)) in [(0, 0, 2), (0x100, 2, 1)].iter().enumerate() {
+            small_tag(&mut out, "regionPPN", &[i as u32], ppn);
+            small_tag(&mut out, "regionPageNum", &[i as u32], page);
+            small_tag(&mut out, "regionSize", &[i as u32], size);
+        }
+        if let Some(memory) = memory {
+            large_tag(&mut out, "Memory", &[0, 0], memory);
+        }
+        out.extend_from_slice(&[0u8; 2]);
+
+        out
+    }
+
+    fn memory() -> Vec<u8> {
+        (0..3 * PAGE_SIZE)
+            .map(|i| (i / PAGE_SIZE) as u8 + 1)
+            .collect()
+    }
+
+    #[test]
+    fn embedded_memory() {
+        let file = state(Some(&memory()));
+
+        let parsed = VmwareState::read(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(parsed.value("cpu", "cpuid", &[0]), Some(0x1234));
+        assert_eq!(parsed.value("memory", "regionPageNum", &[1]), Some(2));
+
+        let mut mem = VmwareConnector::new(Cursor::new(file)).unwrap();
+        assert_eq!(mem.metadata().real_size, 3 * PAGE_SIZE);
+        assert!(mem.metadata().readonly);
+
+        let mut buf = [0u8; 2];
+        mem.phys_read_into(Address::from(0x1fffu64).into(), &mut buf[..1])
+            .unwrap();
+        assert_eq!(buf[0], 2);
+        mem.phys_read_into(Address::from(0x10_0000u64).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, [3, 3]);
+        assert!(mem
+            .phys_view()
+            .read_raw_into(Address::from(0x2000u64), &mut buf)
+            .is_err());
+        assert!(mem.phys_write(Address::null().into(), &buf).is_err());
+    }
+
+    #[test]
+    fn separate_memory() {
+        let parsed = VmwareState::read(&mut Cursor::new(state(None))).unwrap();
+        assert_eq!(parsed.embedded_memory(), None);
+        assert!(VmwareConnector::new(Cursor::new(state(None))).is_err());
+
+        let mut mem = VmwareConnector::with_state(Cursor::new(memory()), &parsed).unwrap();
+        let mut buf = [0u8; 1];
+        mem.phys_read_into(Address::from(0x10_0fffu64).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf[0], 3);
+
+        // the regions reach past the end of a truncated memory file
+        let truncated = memory()[..2 * PAGE_SIZE as usize].to_vec();
+        assert!(VmwareConnector::with_state(Cursor::new(truncated), &parsed).is_err());
+
+        let mut mem = VmwareConnector::linear(Cursor::new(memory())).unwrap();
+        mem.phys_read_into(Address::from(0x2000u64).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf[0], 3);
+    }
+}
@@ -0,0 +1,399 @@
+//! Memory wrapper simulating a misbehaving transport.
+//!
+//! Real connectors fail in ways that [`DummyMemory`](super::DummyMemory) never does: reads of
+//! some regions fail randomly, large reads only partially succeed, every request takes time and
+//! writes may be torn, i.e. only partially applied even though they were acknowledged.
+//! [`FaultyMemory`] wraps any [`PhysicalMemory`] object and injects these faults, so caches,
+//! middleware and os plugins can be tested against them.
+//!
+//! ```
+//! use memflow::dummy::{DummyMemory, Fault, FaultyMemory};
+//! use memflow::prelude::v1::*;
+//!
+//! let mem = DummyMemory::new(size::mb(4));
+//! let mut mem = FaultyMemory::with_seed(mem, 1)
+//!     .fault(
+//!         Address::from(size::mb(1))..Address::from(size::mb(2)),
+//!         Fault::new().read_failure(1.0),
+//!     );
+//!
+//! let mut buf = [0u8; 16];
+//! let result = mem.phys_view().read_raw_into(Address::from(size::mb(1)), &mut buf);
+//! assert!(result.is_err());
+//! assert_eq!(mem.stats().failed_reads, 1);
+//! ```
+
+use crate::cglue::*;
+use crate::error::Result;
+use crate::iter::SplitAtIndex;
+use crate::mem::mem_data::*;
+use crate::mem::{PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::plugins::*;
+use crate::types::{umem, Address};
+
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use std::ops::Range;
+use std::time::Duration;
+
+cglue_impl_group!(FaultyMemory<T: PhysicalMemory + Clone>, ConnectorInstance, {});
+
+/// Fault probabilities of a memory range.
+///
+/// All probabilities are in the range `0.0..=1.0` and are rolled once per accessed element.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Fault {
+    read_failure: f64,
+    partial_read: f64,
+    max_read_len: Option<usize>,
+    write_failure: f64,
+    torn_write: f64,
+}
+
+impl Fault {
+    /// Creates a fault configuration that never fails.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Probability of a read failing completely.
+    pub fn read_failure(mut self, probability: f64) -> Self {
+        self.read_failure = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Probability of only a random prefix of a read succeeding, the rest fails.
+    pub fn partial_read(mut self, probability: f64) -> Self {
+        self.partial_read = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Truncates all reads to at most `len` bytes, the rest of every longer read fails.
+    ///
+    /// Unlike [`partial_read`](Self::partial_read) this is deterministic, which mimics
+    /// transports with a fixed maximum transfer size.
+    pub fn truncate_reads(mut self, len: usize) -> Self {
+        self.max_read_len = Some(len);
+        self
+    }
+
+    /// Probability of a write failing completely.
+    pub fn write_failure(mut self, probability: f64) -> Self {
+        self.write_failure = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Probability of a write only applying a random prefix of the data, while still being
+    /// reported as successful.
+    pub fn torn_write(mut self, probability: f64) -> Self {
+        self.torn_write = probability.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// Number of faults injected by a [`FaultyMemory`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    pub failed_reads: usize,
+    pub partial_reads: usize,
+    pub truncated_reads: usize,
+    pub failed_writes: usize,
+    pub torn_writes: usize,
+}
+
+/// Wrapper injecting faults and latency into the accesses of a memory object.
+#[derive(Clone)]
+pub struct FaultyMemory<T> {
+    mem: T,
+    rng: XorShiftRng,
+    faults: Vec<(Range<Address>, Fault)>,
+    latency: Duration,
+    jitter: Duration,
+    stats: FaultStats,
+}
+
+impl<T: PhysicalMemory> FaultyMemory<T> {
+    pub fn new(mem: T) -> Self {
+        Self::with_rng(mem, SeedableRng::from_rng(thread_rng()).unwrap())
+    }
+
+    pub fn with_seed(mem: T, seed: u64) -> Self {
+        Self::with_rng(mem, SeedableRng::seed_from_u64(seed))
+    }
+
+    pub fn with_rng(mem: T, rng: XorShiftRng) -> Self {
+        Self {
+            mem,
+            rng,
+            faults: vec![],
+            latency: Duration::default(),
+            jitter: Duration::default(),
+            stats: FaultStats::default(),
+        }
+    }
+
+    /// Injects `fault` into all accesses starting in `range`.
+    ///
+    /// Ranges are matched in the order they were added, the first match wins.
+    pub fn fault(mut self, range: Range<Address>, fault: Fault) -> Self {
+        self.faults.push((range, fault));
+        self
+    }
+
+    /// Injects `fault` into all accesses not matched by a previously added range.
+    pub fn fault_everywhere(self, fault: Fault) -> Self {
+        self.fault(Address::null()..Address::from(umem::MAX), fault)
+    }
+
+    /// Delays every batch of accesses by `latency`.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Adds a random delay of up to `jitter` on top of the latency.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the number of faults injected so far.
+    pub fn stats(&self) -> FaultStats {
+        self.stats
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.mem
+    }
+
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    fn delay(&mut self) {
+        let mut delay = self.latency;
+        if self.jitter > Duration::default() {
+            delay += self.jitter.mul_f64(self.rng.gen());
+        }
+        if delay > Duration::default() {
+            std::thread::sleep(delay);
+        }
+    }
+
+    fn fault_at(&self, addr: Address) -> Fault {
+        self.faults
+            .iter()
+            .find(|(range, _)| range.contains(&addr))
+            .map(|(_, fault)| *fault)
+            .unwrap_or_default()
+    }
+
+    fn roll(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.gen_bool(probability)
+    }
+
+    /// Returns the length of the prefix a partial access applies to.
+    fn split_point(&mut self, len: usize) -> umem {
+        if len > 1 {
+            self.rng.gen_range(1..len) as umem
+        } else {
+            0
+        }
+    }
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for FaultyMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        self.delay();
+
+        let mut ops = vec![];
+
+        for CTup3(addr, meta_addr, buf) in inp {
+            let fault = self.fault_at(addr.address());
+
+            if self.roll(fault.read_failure) {
+                self.stats.failed_reads += 1;
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+                continue;
+            }
+
+            let split = if self.roll(fault.partial_read) {
+                self.stats.partial_reads += 1;
+                Some(self.split_point(buf.len()))
+            } else {
+                match fault.max_read_len {
+                    Some(max_len) if buf.len() > max_len => {
+                        self.stats.truncated_reads += 1;
+                        Some(max_len as umem)
+                    }
+                    _ => None,
+                }
+            };
+
+            if let Some(split) = split {
+                let (left, right) = buf.split_at(split);
+                if let Some(left) = left {
+                    ops.push(CTup3(addr, meta_addr, left));
+                }
+                if let Some(right) = right {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta_addr + split, right));
+                }
+            } else {
+                ops.push(CTup3(addr, meta_addr, buf));
+            }
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(ops.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        self.delay();
+
+        let mut ops = vec![];
+
+        for CTup3(addr, meta_addr, buf) in inp {
+            let fault = self.fault_at(addr.address());
+
+            if self.roll(fault.write_failure) {
+                self.stats.failed_writes += 1;
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            } else if self.roll(fault.torn_write) {
+                self.stats.torn_writes += 1;
+                let split = self.split_point(buf.len()) as usize;
+                let mem = &mut self.mem;
+                MemOps::with_raw(
+                    std::iter::once(CTup3(addr, meta_addr, (&buf[..split]).into())),
+                    None,
+                    None,
+                    |data| mem.phys_write_raw_iter(data),
+                )?;
+                // the whole write is acknowledged
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                ops.push(CTup3(addr, meta_addr, buf));
+            }
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(ops.into_iter(), out, out_fail, |data| {
+            mem.phys_write_raw_iter(data)
+        })
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::{size, PhysicalAddress};
+
+    #[test]
+    fn partial_reads() {
+        let mem = DummyMemory::new(size::mb(1));
+        let mut mem =
+            FaultyMemory::with_seed(mem, 7).fault_everywhere(Fault::new().partial_read(1.0));
+
+        let mut buf = [0u8; 0x100];
+        let mut read = 0;
+        let mut failed = 0;
+        let mut out = |CTup2(_, data): ReadData| {
+            read += data.len();
+            true
+        };
+        let mut out_fail = |CTup2(_, data): ReadData| {
+            failed += data.len();
+            true
+        };
+
+        MemOps::with(
+            std::iter::once((
+                PhysicalAddress::from(Address::from(0x1000)),
+                CSliceMut::from(&mut buf[..]),
+            )),
+            Some(&mut (&mut out).into()),
+            Some(&mut (&mut out_fail).into()),
+            |data| mem.phys_read_raw_iter(data),
+        )
+        .unwrap();
+
+        assert!(read > 0 && failed > 0);
+        assert_eq!(read + failed, 0x100);
+        assert_eq!(mem.stats().partial_reads, 1);
+    }
+
+    #[test]
+    fn truncated_reads() {
+        let mem = DummyMemory::new(size::mb(1));
+        let mut mem = FaultyMemory::with_seed(mem, 1).fault(
+            Address::from(0x1000)..Address::from(0x2000),
+            Fault::new().truncate_reads(0x10),
+        );
+
+        let mut short = [0u8; 0x10];
+        let mut long = [0u8; 0x20];
+        mem.phys_view()
+            .read_raw_into(Address::from(0x1000), &mut short)
+            .unwrap();
+        mem.phys_view()
+            .read_raw_into(Address::from(0x1000), &mut long)
+            .unwrap_err();
+        // outside of the range
+        mem.phys_view()
+            .read_raw_into(Address::from(0x2000), &mut long)
+            .unwrap();
+
+        assert_eq!(mem.stats().truncated_reads, 1);
+    }
+
+    #[test]
+    fn torn_writes() {
+        let mem = DummyMemory::new(size::mb(1));
+        let mut mem = FaultyMemory::with_seed(mem.clone(), 3).fault(
+            Address::from(0x2000)..Address::from(0x3000),
+            Fault::new().torn_write(1.0),
+        );
+
+        let data = [0xffu8; 0x10];
+        mem.phys_write(Address::from(0x1000).into(), &data).unwrap();
+        mem.phys_write(Address::from(0x2000).into(), &data).unwrap();
+
+        let mut view = mem.into_inner().into_phys_view();
+        let intact: [u8; 0x10] = view.read(Address::from(0x1000)).unwrap();
+        let torn: [u8; 0x10] = view.read(Address::from(0x2000)).unwrap();
+
+        assert_eq!(intact, data);
+        assert_ne!(torn, data);
+        assert_eq!(torn[0], 0xff);
+    }
+}
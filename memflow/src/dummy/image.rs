@@ -0,0 +1,133 @@
+//! Fake executable headers for simulated modules.
+//!
+//! The headers only contain what is needed to identify and size an image: a PE header without
+//! sections and data directories, or an ELF header without program and section headers.
+
+use crate::architecture::ArchitectureIdent;
+use crate::types::{umem, Address};
+
+/// Executable format of a simulated module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DummyModuleFormat {
+    /// Windows style image with a PE header.
+    Pe,
+    /// Unix style shared object with an ELF header.
+    Elf,
+}
+
+/// Size of the header page written at the base of a module.
+pub const HEADER_SIZE: usize = 0x400;
+
+const PE_OFFSET: usize = 0x80;
+
+fn put(buf: &mut [u8], offset: usize, data: &[u8]) {
+    buf[offset..offset + data.len()].copy_from_slice(data);
+}
+
+/// Builds the header of a module loaded at `base`.
+pub fn build_header(
+    format: DummyModuleFormat,
+    arch: ArchitectureIdent,
+    base: Address,
+    size: umem,
+) -> Vec<u8> {
+    let is_64 = !matches!(arch, ArchitectureIdent::X86(32, _));
+    match format {
+        DummyModuleFormat::Pe => build_pe(is_64, base, size),
+        DummyModuleFormat::Elf => build_elf(is_64),
+    }
+}
+
+fn build_pe(is_64: bool, base: Address, size: umem) -> Vec<u8> {
+    let mut buf = vec![0u8; HEADER_SIZE];
+
+    // dos header
+    put(&mut buf, 0, b"MZ");
+    put(&mut buf, 0x3c, &(PE_OFFSET as u32).to_le_bytes());
+
+    // coff header
+    let (machine, opt_size, characteristics): (u16, u16, u16) = if is_64 {
+        (0x8664, 240, 0x2022)
+    } else {
+        (0x14c, 224, 0x2102)
+    };
+    let coff = PE_OFFSET + 4;
+    put(&mut buf, PE_OFFSET, b"PE\0\0");
+    put(&mut buf, coff, &machine.to_le_bytes());
+    put(&mut buf, coff + 16, &opt_size.to_le_bytes());
+    put(&mut buf, coff + 18, &characteristics.to_le_bytes());
+
+    // optional header
+    let opt = coff + 20;
+    let size = size as u32;
+    if is_64 {
+        put(&mut buf, opt, &0x20bu16.to_le_bytes());
+        put(&mut buf, opt + 24, &(base.to_umem() as u64).to_le_bytes());
+    } else {
+        put(&mut buf, opt, &0x10bu16.to_le_bytes());
+        put(&mut buf, opt + 28, &(base.to_umem() as u32).to_le_bytes());
+    }
+    put(&mut buf, opt + 32, &0x1000u32.to_le_bytes());
+    put(&mut buf, opt + 36, &0x200u32.to_le_bytes());
+    put(&mut buf, opt + 40, &6u16.to_le_bytes());
+    put(&mut buf, opt + 48, &6u16.to_le_bytes());
+    put(&mut buf, opt + 56, &size.to_le_bytes());
+    put(&mut buf, opt + 60, &(HEADER_SIZE as u32).to_le_bytes());
+    put(&mut buf, opt + 68, &2u16.to_le_bytes());
+    let rva_count = if is_64 { opt + 108 } else { opt + 92 };
+    put(&mut buf, rva_count, &16u32.to_le_bytes());
+
+    buf
+}
+
+fn build_elf(is_64: bool) -> Vec<u8> {
+    let mut buf = vec![0u8; HEADER_SIZE];
+
+    put(&mut buf, 0, b"\x7fELF");
+    buf[4] = if is_64 { 2 } else { 1 };
+    buf[5] = 1;
+    buf[6] = 1;
+
+    // shared object
+    put(&mut buf, 16, &3u16.to_le_bytes());
+    put(&mut buf, 20, &1u32.to_le_bytes());
+    if is_64 {
+        put(&mut buf, 18, &62u16.to_le_bytes());
+        put(&mut buf, 52, &64u16.to_le_bytes());
+        put(&mut buf, 54, &56u16.to_le_bytes());
+        put(&mut buf, 58, &64u16.to_le_bytes());
+    } else {
+        put(&mut buf, 18, &3u16.to_le_bytes());
+        put(&mut buf, 40, &52u16.to_le_bytes());
+        put(&mut buf, 42, &32u16.to_le_bytes());
+        put(&mut buf, 46, &40u16.to_le_bytes());
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_parse() {
+        let base = Address::from(0x40_0000);
+
+        for &arch in [
+            ArchitectureIdent::X86(64, false),
+            ArchitectureIdent::X86(32, true),
+        ]
+        .iter()
+        {
+            let pe = build_header(DummyModuleFormat::Pe, arch, base, 0x10000);
+            let pe = goblin::pe::PE::parse(&pe).unwrap();
+            assert_eq!(pe.is_64, arch == ArchitectureIdent::X86(64, false));
+            assert_eq!(pe.image_base, 0x40_0000);
+
+            let elf = build_header(DummyModuleFormat::Elf, arch, base, 0x10000);
+            let elf = goblin::elf::Elf::parse_header(&elf).unwrap();
+            assert_eq!(elf.e_type, goblin::elf::header::ET_DYN);
+        }
+    }
+}
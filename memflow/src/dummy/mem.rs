@@ -3,7 +3,10 @@ use crate::connector::MappedPhysicalMemory;
 use crate::derive::connector;
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::mem::mem_data::*;
-use crate::mem::{MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::mem::{
+    MappablePhysicalMemory, MemoryMap, PhysicalMemory, PhysicalMemoryMapping,
+    PhysicalMemoryMetadata,
+};
 use crate::plugins::*;
 use crate::types::{size, umem, Address};
 
@@ -71,6 +74,14 @@ impl PhysicalMemory for DummyMemory {
     }
 }
 
+impl MappablePhysicalMemory for DummyMemory {
+    #[inline]
+    fn phys_map(&self, addr: Address) -> Option<&[u8]> {
+        // the buffer is mapped at address zero
+        self.buf.get(addr.to_umem() as usize..)
+    }
+}
+
 pub fn parse_size(args: &Args) -> Result<usize> {
     let (size, size_mul) = {
         let size = args.get("size").unwrap_or("2m");
@@ -1,3 +1,5 @@
+pub mod faulty;
+pub mod image;
 pub mod mem;
 pub mod os;
 pub mod process;
@@ -5,6 +7,8 @@ pub mod process;
 pub(crate) mod offset_pt;
 pub(crate) use offset_pt::OffsetPageTable;
 
+pub use faulty::{Fault, FaultStats, FaultyMemory};
+pub use image::DummyModuleFormat;
 pub use mem::DummyMemory;
 pub use os::DummyOs;
-pub use process::DummyProcessInfo;
+pub use process::{DummyProcessInfo, DummyThreadInfo};
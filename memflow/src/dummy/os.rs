@@ -1,3 +1,4 @@
+use super::image::*;
 use super::mem::*;
 use super::process::*;
 
@@ -19,7 +20,7 @@ use rand_xorshift::XorShiftRng;
 use std::collections::VecDeque;
 use std::convert::TryInto;
 
-use crate::architecture::x86::{x64, X86VirtualTranslate};
+use crate::architecture::x86::{self, x64, X86VirtualTranslate};
 
 use x86_64::{
     structures::paging,
@@ -257,8 +258,16 @@ impl DummyOs {
             .map(|addr| addr.as_u64().into())
     }
 
-    fn internal_alloc_process(&mut self, map_size: usize, test_buf: &[u8]) -> DummyProcessInfo {
-        let (dtb, address) = self.alloc_dtb(map_size, test_buf);
+    fn internal_alloc_process(
+        &mut self,
+        map_size: usize,
+        test_buf: &[u8],
+        arch: ArchitectureIdent,
+    ) -> DummyProcessInfo {
+        let (dtb, address) = match arch {
+            ArchitectureIdent::X86(32, pae) => self.alloc_dtb_x86(map_size, test_buf, pae),
+            _ => self.alloc_dtb(map_size, test_buf),
+        };
 
         self.last_pid += 1;
 
@@ -270,27 +279,47 @@ impl DummyOs {
                 name: "Dummy".into(),
                 path: "/some/dummy".into(),
                 command_line: "/some/dummy --dummyarg".into(),
-                sys_arch: x64::ARCH.ident(),
-                proc_arch: x64::ARCH.ident(),
+                sys_arch: arch,
+                proc_arch: arch,
             },
             dtb,
             map_size,
             modules: vec![],
+            threads: vec![],
         }
     }
 
     pub fn alloc_process(&mut self, map_size: usize, test_buf: &[u8]) -> Pid {
-        let proc = self.internal_alloc_process(map_size, test_buf);
+        self.alloc_process_with_arch(map_size, test_buf, x64::ARCH.ident())
+            .unwrap()
+    }
+
+    /// Allocates a process with an address space of the given architecture.
+    ///
+    /// Supported are x86_64 as well as x86 with 32-bit and PAE paging. The address spaces of
+    /// 32-bit processes are mapped with 4k pages below 2GB.
+    pub fn alloc_process_with_arch(
+        &mut self,
+        map_size: usize,
+        test_buf: &[u8],
+        arch: ArchitectureIdent,
+    ) -> Result<Pid> {
+        match arch {
+            ArchitectureIdent::X86(64, false) | ArchitectureIdent::X86(32, _) => {}
+            _ => return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArchitecture)),
+        }
+
+        let proc = self.internal_alloc_process(map_size, test_buf, arch);
 
         let ret = proc.info.pid;
 
         self.processes.push(proc);
 
-        ret
+        Ok(ret)
     }
 
     pub fn alloc_process_with_module(&mut self, map_size: usize, test_buf: &[u8]) -> Pid {
-        let mut proc = self.internal_alloc_process(map_size, test_buf);
+        let mut proc = self.internal_alloc_process(map_size, test_buf, x64::ARCH.ident());
 
         let ret = proc.info.pid;
 
@@ -301,6 +330,112 @@ impl DummyOs {
         ret
     }
 
+    /// Adds a module with a fake executable header to a process.
+    ///
+    /// Modules are placed one after another at 64k aligned addresses behind the previously
+    /// allocated ones. The header is written at the base of the module, the rest of it is left
+    /// as is.
+    pub fn alloc_module(
+        &mut self,
+        pid: Pid,
+        name: &str,
+        size: usize,
+        format: DummyModuleFormat,
+    ) -> Result<ModuleInfo> {
+        let proc = self
+            .processes
+            .iter_mut()
+            .find(|p| p.info.pid == pid)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound))?;
+
+        let start = proc.info.address;
+        let end = start + proc.map_size;
+        let base = proc
+            .modules
+            .iter()
+            .filter(|m| m.base >= start && m.base < end)
+            .map(|m| m.base + m.size)
+            .max()
+            .unwrap_or(start)
+            .as_mem_aligned(size::kb(64) as umem);
+        let size = std::cmp::max(size, HEADER_SIZE) as umem;
+
+        if base + size > end {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfMemoryRange)
+                .log_warn(format!("no space left for module {}", name)));
+        }
+
+        let arch = proc.info.proc_arch;
+        let module = ModuleInfo {
+            address: Address::from((proc.modules.len() * 1024) as umem),
+            parent_process: proc.info.address,
+            base,
+            size,
+            name: name.into(),
+            path: match format {
+                DummyModuleFormat::Pe => format!("C:\\Windows\\System32\\{}", name),
+                DummyModuleFormat::Elf => format!("/usr/lib/{}", name),
+            }
+            .as_str()
+            .into(),
+            arch,
+        };
+        proc.modules.push(module.clone());
+
+        let translator = x86::new_translator(proc.dtb, arch.into())?;
+        let mut mem = VirtualDma::new(self.mem.forward_mut(), arch, translator);
+        mem.write_raw(base, &build_header(format, arch, base, size))
+            .data_part()?;
+
+        Ok(module)
+    }
+
+    /// Adds threads to a process.
+    ///
+    /// Thread ids are allocated from the same pool as process ids. Every thread gets a stack
+    /// inside of the address space of the process and starts inside one of its modules, if it
+    /// has any.
+    pub fn alloc_threads(&mut self, pid: Pid, count: usize) -> Result<Vec<u32>> {
+        let proc = self
+            .processes
+            .iter_mut()
+            .find(|p| p.info.pid == pid)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound))?;
+
+        let stack_size = size::kb(64) as umem;
+        let slots = std::cmp::max(proc.map_size as umem / stack_size, 1);
+
+        let mut tids = vec![];
+        for _ in 0..count {
+            self.last_pid += 1;
+
+            let start_address = match proc.modules.choose(&mut self.rng) {
+                Some(module) => module.base + self.rng.gen_range(0..std::cmp::max(module.size, 1)),
+                None => proc.info.address,
+            };
+
+            proc.threads.push(DummyThreadInfo {
+                tid: self.last_pid,
+                start_address,
+                stack_base: proc.info.address + self.rng.gen_range(0..slots) * stack_size,
+                stack_size,
+            });
+            tids.push(self.last_pid);
+        }
+
+        Ok(tids)
+    }
+
+    /// Returns the internal state of a process, e.g. to add modules after it was allocated.
+    pub fn process_mut(&mut self, pid: Pid) -> Option<&mut DummyProcessInfo> {
+        self.processes.iter_mut().find(|p| p.info.pid == pid)
+    }
+
+    /// Removes a process from the process list. Its memory is not freed.
+    pub fn free_process(&mut self, pid: Pid) {
+        self.processes.retain(|p| p.info.pid != pid);
+    }
+
     pub fn alloc_dtb(&mut self, map_size: usize, test_buf: &[u8]) -> (Address, Address) {
         let virt_base = (Address::null()
             + self
@@ -400,6 +535,93 @@ impl DummyOs {
         dtb.addr
     }
 
+    /// Builds a 32-bit (or PAE) address space mapping `map_size` bytes with 4k pages.
+    ///
+    /// Returns the dtb and the virtual base address.
+    pub fn alloc_dtb_x86(
+        &mut self,
+        map_size: usize,
+        test_buf: &[u8],
+        pae: bool,
+    ) -> (Address, Address) {
+        let slots = std::cmp::max(0x8000_0000_u64.saturating_sub(map_size as u64) >> 22, 2);
+        let virt_base = Address::from(self.rng.gen_range(1..slots) << 22);
+
+        let dtb = self.alloc_zeroed_pt_page();
+
+        let mut cur_len = 0;
+        while cur_len < map_size {
+            let frame = self.alloc_pt_page().addr;
+            let page_size = size::kb(4);
+
+            if test_buf.len() > cur_len {
+                let end = std::cmp::min(test_buf.len(), cur_len + page_size);
+                self.mem
+                    .phys_write(frame.into(), &test_buf[cur_len..end])
+                    .unwrap();
+            }
+
+            self.map_page_x86(dtb, virt_base + cur_len, frame, pae);
+            cur_len += page_size;
+        }
+
+        (dtb, virt_base)
+    }
+
+    fn map_page_x86(&mut self, dtb: Address, virt_addr: Address, frame: Address, pae: bool) {
+        // (shift, index mask) of every paging level
+        let levels: &[(u32, umem)] = if pae {
+            &[(30, 0x3), (21, 0x1ff), (12, 0x1ff)]
+        } else {
+            &[(22, 0x3ff), (12, 0x3ff)]
+        };
+        let entry_size = if pae { 8 } else { 4 };
+
+        let mut table = dtb;
+        for (i, &(shift, mask)) in levels.iter().enumerate() {
+            let entry_addr = table + ((virt_addr.to_umem() >> shift) & mask) * entry_size;
+            // pdpt entries of pae do not have any access bits
+            let flags = if pae && i == 0 { 0x1 } else { 0x7 };
+
+            if i + 1 == levels.len() {
+                self.write_pte(entry_addr, frame.to_umem() | flags, entry_size);
+                break;
+            }
+
+            let entry = self.read_pte(entry_addr, entry_size);
+            table = if entry & 1 != 0 {
+                Address::from(entry & !0xfff)
+            } else {
+                let page = self.alloc_zeroed_pt_page();
+                self.write_pte(entry_addr, page.to_umem() | flags, entry_size);
+                page
+            };
+        }
+    }
+
+    fn read_pte(&mut self, addr: Address, entry_size: umem) -> umem {
+        let mut buf = [0u8; 8];
+        self.mem
+            .phys_read_into(addr.into(), &mut buf[..entry_size as usize])
+            .unwrap();
+        u64::from_le_bytes(buf) as umem
+    }
+
+    fn write_pte(&mut self, addr: Address, entry: umem, entry_size: umem) {
+        let buf = (entry as u64).to_le_bytes();
+        self.mem
+            .phys_write(addr.into(), &buf[..entry_size as usize])
+            .unwrap();
+    }
+
+    fn alloc_zeroed_pt_page(&mut self) -> Address {
+        let page = self.alloc_pt_page().addr;
+        self.mem
+            .phys_write(page.into(), &[0u8; 0x1000][..])
+            .unwrap();
+        page
+    }
+
     //Given it's the tests, we will have a panic if out of mem
     fn alloc_pt_page(&mut self) -> PageInfo {
         if let Some(page) = self.pt_pages.pop() {
@@ -456,11 +678,12 @@ impl<'a> OsInner<'a> for DummyOs {
             .find(|p| p.info.address == info.address)
             .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidProcessInfo))?
             .clone();
+        let arch = proc.info.sys_arch;
         Ok(DummyProcess {
             mem: VirtualDma::new(
                 self.mem.forward_mut(),
-                x64::ARCH,
-                x64::new_translator(proc.dtb),
+                arch,
+                x86::new_translator(proc.dtb, arch.into())?,
             ),
             proc,
         })
@@ -480,8 +703,9 @@ impl<'a> OsInner<'a> for DummyOs {
             .find(|p| p.info.address == info.address)
             .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidProcessInfo))?
             .clone();
+        let arch = proc.info.sys_arch;
         Ok(DummyProcess {
-            mem: VirtualDma::new(self.mem, x64::ARCH, x64::new_translator(proc.dtb)),
+            mem: VirtualDma::new(self.mem, arch, x86::new_translator(proc.dtb, arch.into())?),
             proc,
         })
     }
@@ -509,6 +733,10 @@ impl<'a> OsInner<'a> for DummyOs {
     }
 }
 
+impl crate::analysis::ProcessSources for DummyOs {}
+
+impl crate::analysis::TimelineSources for DummyOs {}
+
 impl PhysicalMemory for DummyOs {
     #[inline]
     fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
@@ -529,6 +757,11 @@ impl PhysicalMemory for DummyOs {
     fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
         self.mem.set_mem_map(mem_map)
     }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
 }
 
 #[doc(hidden)]
@@ -1,4 +1,4 @@
-use crate::architecture::x86::x64;
+use crate::architecture::x86;
 use crate::error::*;
 use crate::mem::virt_translate::VirtualTranslate3;
 
@@ -12,12 +12,22 @@ use crate::types::{imem, umem, util::GapRemover, Address, PageType};
 use crate::cglue::*;
 use rand::{thread_rng, Rng};
 
+/// Simulated thread of a [`DummyProcessInfo`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DummyThreadInfo {
+    pub tid: u32,
+    pub start_address: Address,
+    pub stack_base: Address,
+    pub stack_size: umem,
+}
+
 #[derive(Clone)]
 pub struct DummyProcessInfo {
     pub info: ProcessInfo,
     pub map_size: usize,
     pub dtb: Address,
     pub modules: Vec<ModuleInfo>,
+    pub threads: Vec<DummyThreadInfo>,
 }
 
 impl DummyProcessInfo {
@@ -36,13 +46,14 @@ impl DummyProcessInfo {
                 )),
                 name: "dummy.so".into(),
                 path: "/".into(),
-                arch: x64::ARCH.ident(),
+                arch: self.info.proc_arch,
             });
         }
     }
 
     pub fn translator(&self) -> impl VirtualTranslate3 {
-        x64::new_translator(self.dtb)
+        x86::new_translator(self.dtb, self.info.sys_arch.into())
+            .expect("dummy processes are always x86")
     }
 }
 
@@ -180,6 +191,8 @@ impl<T: MemoryView> MemoryView for DummyProcess<T> {
 #[cfg(test)]
 mod tests {
     use super::super::*;
+    use crate::architecture::ArchitectureIdent;
+    use crate::mem::MemoryView;
     use crate::os::{OsInner, Process};
     use crate::types::size;
 
@@ -195,4 +208,66 @@ mod tests {
         let module = prc.primary_module();
         assert!(module.is_ok())
     }
+
+    #[test]
+    pub fn x86_32_address_spaces() {
+        let mem = DummyMemory::new(size::mb(32));
+        let mut os = DummyOs::new(mem);
+
+        let buf = (0..size::kb(16)).map(|i| i as u8).collect::<Vec<_>>();
+        let pids = [
+            ArchitectureIdent::X86(64, false),
+            ArchitectureIdent::X86(32, false),
+            ArchitectureIdent::X86(32, true),
+        ]
+        .iter()
+        .map(|&arch| os.alloc_process_with_arch(size::mb(1), &buf, arch).unwrap())
+        .collect::<Vec<_>>();
+
+        let mut dtbs = vec![];
+        for &pid in pids.iter() {
+            let mut prc = os.process_by_pid(pid).unwrap();
+            let base = prc.info().address;
+            let data = prc.read_raw(base, buf.len()).unwrap();
+            assert_eq!(data, buf);
+            dtbs.push(prc.proc.dtb);
+        }
+
+        dtbs.dedup();
+        assert_eq!(dtbs.len(), 3);
+    }
+
+    #[test]
+    pub fn modules_and_threads() {
+        let mem = DummyMemory::new(size::mb(16));
+        let mut os = DummyOs::new(mem);
+
+        let pid = os
+            .alloc_process_with_arch(size::mb(2), &[], ArchitectureIdent::X86(32, true))
+            .unwrap();
+        let exe = os
+            .alloc_module(pid, "dummy.exe", size::kb(256), DummyModuleFormat::Pe)
+            .unwrap();
+        let lib = os
+            .alloc_module(pid, "libdummy.so", size::kb(64), DummyModuleFormat::Elf)
+            .unwrap();
+        let tids = os.alloc_threads(pid, 4).unwrap();
+
+        assert!(lib.base >= exe.base + exe.size);
+
+        let mut prc = os.process_by_pid(pid).unwrap();
+        assert_eq!(prc.primary_module().unwrap().base, exe.base);
+        assert_eq!(prc.read_raw(exe.base, 2).unwrap(), b"MZ");
+        assert_eq!(prc.read_raw(lib.base, 4).unwrap(), b"\x7fELF");
+
+        assert_eq!(
+            prc.proc.threads.iter().map(|t| t.tid).collect::<Vec<_>>(),
+            tids
+        );
+        assert!(prc
+            .proc
+            .threads
+            .iter()
+            .all(|t| t.start_address >= exe.base && t.start_address < lib.base + lib.size));
+    }
 }
@@ -0,0 +1,202 @@
+/*!
+Support for running memflow on embedded DMA hardware.
+
+With `default-features = false, features = ["embedded"]` memflow builds for `no_std` targets
+with nothing but an allocator. Neither threads nor a clock are required, so the core (address
+translation, [`VirtualDma`](crate::mem::VirtualDma), pattern scanning, type parsing) can run on
+the DMA device itself and triage a target, before any data is shipped to a host.
+
+Implementing [`PhysicalMemory`] requires dealing with scatter operations and cglue. Device
+drivers usually only offer plain reads and writes of a buffer, which is exactly what
+[`RawPhysicalMemory`] asks for. Wrapping a driver in [`RawConnector`] turns it into a regular
+[`PhysicalMemory`] object.
+
+On targets without a system allocator a fixed size heap has to be provided through
+`#[global_allocator]`, see `examples/embedded_triage.rs` for a simple bump allocator.
+Virtual memory can also be read without any allocations when all storage is provided by the
+caller, see [`VirtualDma::with_scratch`](crate::mem::VirtualDma::with_scratch) and
+`examples/no_alloc_read.rs`.
+
+# Examples
+
+```
+use memflow::embedded::{RawConnector, SliceMemory};
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::scan::{MemoryScan, Pattern};
+use memflow::types::Address;
+
+let mut buf = [0u8; 0x4000];
+buf[0x1234..0x1238].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+let mut conn = RawConnector::new(SliceMemory::new(&mut buf));
+let pattern = Pattern::parse("de ad ?? ef").unwrap();
+let found = conn
+    .phys_view()
+    .find_pattern(Address::null()..Address::from(0x4000), &pattern);
+
+assert_eq!(found, [Address::from(0x1234)]);
+```
+*/
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::MemOps;
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+/// Minimal connector interface for device drivers.
+///
+/// Unlike [`PhysicalMemory`] this trait has no scatter operations and no requirements on the
+/// ffi layout, so it can be implemented directly on top of a DMA engine.
+pub trait RawPhysicalMemory {
+    /// Reads `out.len()` bytes at the physical address `addr`.
+    fn read_raw(&mut self, addr: Address, out: &mut [u8]) -> Result<()>;
+
+    /// Writes `data` to the physical address `addr`.
+    fn write_raw(&mut self, addr: Address, data: &[u8]) -> Result<()>;
+
+    /// Returns the highest accessible physical address.
+    fn max_address(&self) -> Address;
+
+    /// Returns `true` if the memory can not be written to.
+    fn readonly(&self) -> bool {
+        false
+    }
+}
+
+/// Adapter implementing [`PhysicalMemory`] for [`RawPhysicalMemory`] objects.
+///
+/// Every element of a scatter operation is forwarded as a single raw access.
+#[derive(Clone)]
+pub struct RawConnector<T>(T);
+
+impl<T: RawPhysicalMemory> RawConnector<T> {
+    pub fn new(mem: T) -> Self {
+        Self(mem)
+    }
+
+    /// Returns the wrapped memory object.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+
+    /// Consumes the adapter and returns the wrapped memory object.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: RawPhysicalMemory + Send> PhysicalMemory for RawConnector<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            if self.0.read_raw(addr.address(), &mut *buf).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, buf) in inp {
+            if !self.0.readonly() && self.0.write_raw(addr.address(), &*buf).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        let max_address = self.0.max_address();
+
+        PhysicalMemoryMetadata {
+            max_address,
+            real_size: max_address.to_umem().saturating_add(1),
+            readonly: self.0.readonly(),
+            ideal_batch_size: u32::MAX,
+            max_batch_size: u32::MAX,
+            max_batch_bytes: umem::MAX,
+        }
+    }
+}
+
+/// Physical memory backed by a buffer, e.g. a window into memory mapped by the device.
+pub struct SliceMemory<'a>(&'a mut [u8]);
+
+impl<'a> SliceMemory<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self(buf)
+    }
+
+    fn range(&self, addr: Address, len: usize) -> Result<core::ops::Range<usize>> {
+        let start = addr.to_umem();
+        match start.checked_add(len as umem) {
+            Some(end) if end <= self.0.len() as umem => Ok(start as usize..end as usize),
+            _ => Err(Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds)),
+        }
+    }
+}
+
+impl<'a> RawPhysicalMemory for SliceMemory<'a> {
+    fn read_raw(&mut self, addr: Address, out: &mut [u8]) -> Result<()> {
+        let range = self.range(addr, out.len())?;
+        out.copy_from_slice(&self.0[range]);
+        Ok(())
+    }
+
+    fn write_raw(&mut self, addr: Address, data: &[u8]) -> Result<()> {
+        let range = self.range(addr, data.len())?;
+        self.0[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn max_address(&self) -> Address {
+        Address::from(self.0.len().saturating_sub(1) as umem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+
+    #[test]
+    fn raw_roundtrip() {
+        let mut buf = [0u8; 0x1000];
+        let mut conn = RawConnector::new(SliceMemory::new(&mut buf));
+
+        assert_eq!(conn.metadata().real_size, 0x1000);
+
+        conn.phys_write(Address::from(0x10).into(), &0x1122_3344u32)
+            .unwrap();
+        let value: u32 = conn.phys_view().read(Address::from(0x10)).unwrap();
+        assert_eq!(value, 0x1122_3344);
+
+        // out of bounds accesses are zeroed
+        let mut out = [0xffu8; 8];
+        conn.phys_read_into(Address::from(0xffc).into(), &mut out)
+            .unwrap();
+        assert_eq!(out, [0; 8]);
+    }
+}
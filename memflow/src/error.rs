@@ -70,7 +70,8 @@ impl IntError for Error {
             ErrorOrigin::Other
         };
 
-        let error_kind = if kind > 0 && kind <= ErrorKind::Unknown as i32 + 1 {
+        // new kinds are appended after `Unknown`, `UnableToWriteMemory` is the last one
+        let error_kind = if kind > 0 && kind <= ErrorKind::UnableToWriteMemory as i32 + 1 {
             unsafe { std::mem::transmute(kind as u16 - 1) }
         } else {
             ErrorKind::Unknown
@@ -232,6 +233,10 @@ pub enum ErrorKind {
     SectionNotFound,
 
     Unknown,
+
+    AccessDenied,
+    Cancelled,
+    UnableToWriteMemory,
 }
 
 impl ErrorKind {
@@ -293,6 +298,10 @@ impl ErrorKind {
             ErrorKind::SectionNotFound => "section not found",
 
             ErrorKind::Unknown => "unknown error",
+
+            ErrorKind::AccessDenied => "access outside of the allowed memory ranges",
+            ErrorKind::Cancelled => "operation was cancelled",
+            ErrorKind::UnableToWriteMemory => "unable to write memory",
         }
     }
 }
@@ -495,6 +504,19 @@ mod tests {
         assert_eq!(err.1, ErrorKind::InvalidExeFile);
     }
 
+    #[test]
+    pub fn error_to_from_i32_after_unknown() {
+        let err =
+            Error::from_int_err(Error(ErrorOrigin::Memory, ErrorKind::Cancelled).into_int_err());
+        assert_eq!(err.0, ErrorOrigin::Memory);
+        assert_eq!(err.1, ErrorKind::Cancelled);
+
+        let err = Error::from_int_err(
+            Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteMemory).into_int_err(),
+        );
+        assert_eq!(err.1, ErrorKind::UnableToWriteMemory);
+    }
+
     #[test]
     pub fn result_ok_void_ffi() {
         let r: Result<()> = Ok(());
@@ -0,0 +1,259 @@
+//! WinDbg integration through the EXDI GDB server bridge.
+//!
+//! WinDbg ships `ExdiGdbSrv`, an EXDI driver that attaches to GDB remote stubs. Combined with
+//! [`GdbServer`](super::GdbServer) this allows WinDbg to debug any memflow target, including
+//! DMA accessible machines and snapshots, with full symbol support:
+//!
+//! 1. serve the target, e.g. a [`MemoryTarget`](super::MemoryTarget) over the kernel address
+//!    space, with [`GdbServer::listen`](super::GdbServer::listen),
+//! 2. write the EXDI configuration with [`ExdiConfig`] and register it with WinDbg (the
+//!    `exdiConfigData.xml` file next to `ExdiGdbSrv.dll`),
+//! 3. attach with `windbg -kx exdi:CLSID={29f9906e-9dbe-4d4b-b0fb-6acf7fb6d014},Kd=Guess`.
+//!
+//! WinDbg locates the kernel through the register state of the target. Targets serving
+//! WinDbg should provide registers through [`GdbTarget::registers`](super::GdbTarget::registers),
+//! encoded with [`encode_registers`] in the layout written to the configuration.
+
+use std::prelude::v1::*;
+
+use std::io::Write;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+/// UUID of the memflow EXDI target entry.
+pub const EXDI_TARGET_UUID: &str = "6e234bc8-e8ba-4d36-a6bd-5ea1bf6a4a0e";
+
+/// Register layout of x64 targets, as `(name, size)` in transfer order.
+pub const X64_REGISTERS: &[(&str, usize)] = &[
+    ("rax", 8),
+    ("rbx", 8),
+    ("rcx", 8),
+    ("rdx", 8),
+    ("rsi", 8),
+    ("rdi", 8),
+    ("rbp", 8),
+    ("rsp", 8),
+    ("r8", 8),
+    ("r9", 8),
+    ("r10", 8),
+    ("r11", 8),
+    ("r12", 8),
+    ("r13", 8),
+    ("r14", 8),
+    ("r15", 8),
+    ("rip", 8),
+    ("eflags", 4),
+    ("cs", 4),
+    ("ss", 4),
+    ("ds", 4),
+    ("es", 4),
+    ("fs", 4),
+    ("gs", 4),
+    ("cr0", 8),
+    ("cr2", 8),
+    ("cr3", 8),
+    ("cr4", 8),
+];
+
+/// Register layout of x86 targets, as `(name, size)` in transfer order.
+pub const X86_REGISTERS: &[(&str, usize)] = &[
+    ("eax", 4),
+    ("ecx", 4),
+    ("edx", 4),
+    ("ebx", 4),
+    ("esp", 4),
+    ("ebp", 4),
+    ("esi", 4),
+    ("edi", 4),
+    ("eip", 4),
+    ("eflags", 4),
+    ("cs", 4),
+    ("ss", 4),
+    ("ds", 4),
+    ("es", 4),
+    ("fs", 4),
+    ("gs", 4),
+    ("cr0", 4),
+    ("cr2", 4),
+    ("cr3", 4),
+    ("cr4", 4),
+];
+
+/// Architectures supported by `ExdiGdbSrv`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExdiArch {
+    X86,
+    X64,
+}
+
+impl ExdiArch {
+    /// Returns the register layout used for this architecture.
+    pub fn registers(self) -> &'static [(&'static str, usize)] {
+        match self {
+            ExdiArch::X86 => X86_REGISTERS,
+            ExdiArch::X64 => X64_REGISTERS,
+        }
+    }
+
+    fn names(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            ExdiArch::X86 => ("X86", "ProcessorFamilyX86", "i386"),
+            ExdiArch::X64 => ("X64", "ProcessorFamilyX64", "amd64"),
+        }
+    }
+}
+
+/// Builder of the `exdiConfigData.xml` entry describing a memflow gdb server.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::gdb::exdi::{ExdiArch, ExdiConfig};
+///
+/// let mut out = vec![];
+/// ExdiConfig::new("127.0.0.1:1234", ExdiArch::X64)
+///     .write(&mut out)
+///     .unwrap();
+///
+/// assert!(String::from_utf8(out).unwrap().contains("HostNameAndPort=\"127.0.0.1:1234\""));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExdiConfig {
+    name: String,
+    address: String,
+    arch: ExdiArch,
+    cores: usize,
+    packet_size: usize,
+}
+
+impl ExdiConfig {
+    /// Creates a configuration for a server listening on `address` (`host:port`).
+    pub fn new(address: impl Into<String>, arch: ExdiArch) -> Self {
+        Self {
+            name: "memflow".to_string(),
+            address: address.into(),
+            arch,
+            cores: 1,
+            packet_size: super::MAX_PACKET_SIZE,
+        }
+    }
+
+    /// Sets the name of the target entry, which is selected as current target.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets the number of cores reported to WinDbg.
+    pub fn cores(mut self, cores: usize) -> Self {
+        self.cores = cores;
+        self
+    }
+
+    /// Writes the configuration as xml.
+    pub fn write(&self, out: &mut (impl Write + ?Sized)) -> Result<()> {
+        self.write_inner(out).map_err(|err| {
+            Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile)
+                .log_error(format!("unable to write the exdi config: {}", err))
+        })
+    }
+
+    fn write_inner(&self, out: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+        let (arch, family, feature) = self.arch.names();
+
+        writeln!(out, "<ExdiTargets CurrentTarget=\"{}\">", self.name)?;
+        writeln!(out, "<ExdiTarget Name=\"{}\">", self.name)?;
+        writeln!(
+            out,
+            "<ExdiGdbServerConfigData agentNamePacket=\"\" uuid=\"{}\" displayCommPackets=\"no\" \
+             debuggerSessionByCore=\"no\" enableThrowExceptionOnMemoryErrors=\"yes\" \
+             qSupportedPacket=\"qSupported:xmlRegisters=i386\">",
+            EXDI_TARGET_UUID
+        )?;
+        writeln!(
+            out,
+            "<ExdiGdbServerTargetData targetArchitecture=\"{}\" targetFamily=\"{}\" \
+             numberOfCores=\"{}\" EnableSseContext=\"no\" heuristicScanSize=\"0xfffe\" \
+             targetDescriptionFile=\"\"/>",
+            arch, family, self.cores
+        )?;
+        writeln!(
+            out,
+            "<GdbServerConnectionParameters MultiCoreGdbServerSessions=\"no\" \
+             MaximumGdbServerPacketLength=\"{}\" MaximumConnectAttempts=\"3\" \
+             SendPacketTimeout=\"100\" ReceivePacketTimeout=\"3000\">",
+            self.packet_size
+        )?;
+        writeln!(out, "<Value HostNameAndPort=\"{}\"/>", self.address)?;
+        writeln!(out, "</GdbServerConnectionParameters>")?;
+        writeln!(
+            out,
+            "<ExdiGdbServerMemoryCommands GdbSpecialMemoryCommand=\"no\" PhysicalMemory=\"no\" \
+             SupervisorMemory=\"no\" HypervisorMemory=\"no\" SpecialMemoryRegister=\"no\" \
+             SystemRegistersGdbMonitor=\"no\" SystemRegisterDecoding=\"no\"/>"
+        )?;
+        writeln!(
+            out,
+            "<ExdiGdbServerRegisters Architecture=\"{}\" FeatureNameSupported=\"{}\">",
+            arch, feature
+        )?;
+        for (i, (name, size)) in self.arch.registers().iter().enumerate() {
+            writeln!(
+                out,
+                "<Entry Name=\"{}\" Order=\"{:x}\" Size=\"{}\"/>",
+                name, i, size
+            )?;
+        }
+        writeln!(out, "</ExdiGdbServerRegisters>")?;
+        writeln!(out, "</ExdiGdbServerConfigData>")?;
+        writeln!(out, "</ExdiTarget>")?;
+        writeln!(out, "</ExdiTargets>")
+    }
+}
+
+/// Encodes register values in the layout of `arch`.
+///
+/// Registers `value` returns `None` for are transferred as zeroes.
+pub fn encode_registers(arch: ExdiArch, mut value: impl FnMut(&str) -> Option<u64>) -> Vec<u8> {
+    let mut out = vec![];
+
+    for (name, size) in arch.registers() {
+        let bytes = value(name).unwrap_or_default().to_le_bytes();
+        out.extend_from_slice(&bytes[..*size]);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config() {
+        let mut out = vec![];
+        ExdiConfig::new("localhost:1234", ExdiArch::X64)
+            .name("lab")
+            .write(&mut out)
+            .unwrap();
+
+        let config = String::from_utf8(out).unwrap();
+        assert!(
+            config.starts_with("<ExdiTargets CurrentTarget=\"lab\">\n<ExdiTarget Name=\"lab\">")
+        );
+        assert!(config.contains("<Entry Name=\"cr3\" Order=\"1a\" Size=\"8\"/>"));
+    }
+
+    #[test]
+    fn registers() {
+        let regs = encode_registers(ExdiArch::X86, |name| match name {
+            "eax" => Some(0x1122_3344),
+            "cr3" => Some(0x185000),
+            _ => None,
+        });
+
+        assert_eq!(regs.len(), X86_REGISTERS.len() * 4);
+        assert_eq!(&regs[..4], &[0x44, 0x33, 0x22, 0x11]);
+        assert_eq!(&regs[18 * 4..19 * 4], &[0x00, 0x50, 0x18, 0x00]);
+    }
+}
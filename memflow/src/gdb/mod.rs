@@ -0,0 +1,578 @@
+/*!
+GDB remote serial protocol server.
+
+[`GdbServer`] exposes a [`GdbTarget`] to gdb, lldb, IDA and every other client that speaks the
+[remote serial protocol](https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html).
+This turns any memflow target into a debugger backend for inspecting memory.
+
+The server implements the subset of the protocol needed for memory inspection:
+
+* memory reads and writes (`m`, `M`),
+* the module list of a process via `qXfer:libraries:read`,
+* registers, if the target provides them. Otherwise registers are reported as unavailable.
+
+Targets can not be resumed or stepped. Continue and step requests immediately report the target
+as stopped again.
+
+# Examples
+
+Serving the memory of a process on port 1234:
+
+```no_run
+use memflow::gdb::{GdbServer, ProcessTarget};
+use memflow::os::OsInner;
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+# let pid = os.alloc_process(size::mb(1), &[]);
+
+let process = os.into_process_by_pid(pid).unwrap();
+GdbServer::new(ProcessTarget::new(process))
+    .listen("127.0.0.1:1234")
+    .unwrap();
+```
+
+And connecting to it with `gdb -ex "target remote 127.0.0.1:1234"`.
+*/
+
+pub mod exdi;
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{ModuleInfo, Process};
+use crate::types::{umem, Address};
+
+use log::{info, warn};
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+
+/// Maximum packet size advertised to clients.
+pub const MAX_PACKET_SIZE: usize = 0x4000;
+
+/// Number of register bytes reported as unavailable if the target has no registers.
+const UNAVAILABLE_REGISTER_BYTES: usize = 8;
+
+/// A target that can be served to debugger clients.
+pub trait GdbTarget {
+    fn read_memory(&mut self, addr: Address, out: &mut [u8]) -> Result<()>;
+
+    fn write_memory(&mut self, addr: Address, data: &[u8]) -> Result<()>;
+
+    /// Returns the modules reported to the client as shared libraries.
+    fn libraries(&mut self) -> Result<Vec<ModuleInfo>> {
+        Ok(vec![])
+    }
+
+    /// Returns the register contents in the order of the client's register layout.
+    ///
+    /// Returns `None` if the target has no register access.
+    fn registers(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Serves a process with its module list.
+pub struct ProcessTarget<P> {
+    process: P,
+}
+
+impl<P: Process + MemoryView> ProcessTarget<P> {
+    pub fn new(process: P) -> Self {
+        Self { process }
+    }
+
+    pub fn into_inner(self) -> P {
+        self.process
+    }
+}
+
+impl<P: Process + MemoryView> GdbTarget for ProcessTarget<P> {
+    fn read_memory(&mut self, addr: Address, out: &mut [u8]) -> Result<()> {
+        self.process.read_raw_into(addr, out).data()
+    }
+
+    fn write_memory(&mut self, addr: Address, data: &[u8]) -> Result<()> {
+        self.process.write_raw(addr, data).data()
+    }
+
+    fn libraries(&mut self) -> Result<Vec<ModuleInfo>> {
+        self.process.module_list()
+    }
+}
+
+/// Serves any memory view, e.g. physical memory through `phys_view`.
+pub struct MemoryTarget<M> {
+    mem: M,
+}
+
+impl<M: MemoryView> MemoryTarget<M> {
+    pub fn new(mem: M) -> Self {
+        Self { mem }
+    }
+
+    pub fn into_inner(self) -> M {
+        self.mem
+    }
+}
+
+impl<M: MemoryView> GdbTarget for MemoryTarget<M> {
+    fn read_memory(&mut self, addr: Address, out: &mut [u8]) -> Result<()> {
+        self.mem.read_raw_into(addr, out).data()
+    }
+
+    fn write_memory(&mut self, addr: Address, data: &[u8]) -> Result<()> {
+        self.mem.write_raw(addr, data).data()
+    }
+}
+
+/// Protocol server for a single target.
+pub struct GdbServer<T> {
+    target: T,
+}
+
+impl<T: GdbTarget> GdbServer<T> {
+    pub fn new(target: T) -> Self {
+        Self { target }
+    }
+
+    pub fn target_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    pub fn into_inner(self) -> T {
+        self.target
+    }
+
+    /// Accepts clients on `addr` and serves them one after another.
+    ///
+    /// This function only returns if accepting a client fails.
+    pub fn listen(&mut self, addr: impl ToSocketAddrs) -> Result<()> {
+        let listener = TcpListener::bind(addr).map_err(|err| {
+            Error(ErrorOrigin::Other, ErrorKind::Configuration)
+                .log_error(format!("unable to bind the gdb server: {}", err))
+        })?;
+
+        loop {
+            let (stream, peer) = listener.accept().map_err(|err| {
+                Error(ErrorOrigin::Other, ErrorKind::Configuration)
+                    .log_error(format!("unable to accept a gdb client: {}", err))
+            })?;
+
+            info!("gdb client connected from {}", peer);
+            stream.set_nodelay(true).ok();
+
+            if let Err(err) = self.serve(stream) {
+                warn!("gdb session with {} failed: {}", peer, err);
+            }
+        }
+    }
+
+    /// Serves a single client until it detaches or disconnects.
+    pub fn serve<S: Read + Write>(&mut self, stream: S) -> Result<()> {
+        let mut conn = Connection::new(stream);
+
+        while let Some(packet) = conn.read_packet()? {
+            let data = match packet {
+                Packet::Interrupt => {
+                    conn.write_packet(b"S05")?;
+                    continue;
+                }
+                Packet::Data(data) => data,
+            };
+
+            match self.handle(&data) {
+                Some(response) => conn.write_packet(&response)?,
+                None => return conn.write_packet(b"OK"),
+            }
+
+            if data == b"QStartNoAckMode" {
+                conn.no_ack = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles a single packet, returns `None` once the session ends.
+    fn handle(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        let packet = String::from_utf8_lossy(packet);
+
+        let response = match packet.as_bytes().first() {
+            Some(b'?') => "S05".to_string(),
+            Some(b'D') | Some(b'k') => return None,
+            Some(b'c') | Some(b's') => "S05".to_string(),
+            Some(b'H') => "OK".to_string(),
+            Some(b'T') => "OK".to_string(),
+            Some(b'g') => self.read_registers(),
+            Some(b'p') => "x".repeat(UNAVAILABLE_REGISTER_BYTES * 2),
+            Some(b'G') | Some(b'P') => "E01".to_string(),
+            Some(b'm') => self.read_memory(&packet[1..]),
+            Some(b'M') => self.write_memory(&packet[1..]),
+            Some(b'q') | Some(b'Q') => self.query(&packet),
+            _ => String::new(),
+        };
+
+        Some(response.into_bytes())
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!(
+                "PacketSize={:x};qXfer:libraries:read+;QStartNoAckMode+",
+                MAX_PACKET_SIZE
+            )
+        } else if packet == "QStartNoAckMode" || packet.starts_with("qSymbol") {
+            "OK".to_string()
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else if let Some(range) = packet.strip_prefix("qXfer:libraries:read::") {
+            match parse_range(range) {
+                Some((offset, len)) => self.libraries(offset as usize, len),
+                None => "E01".to_string(),
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    fn read_registers(&mut self) -> String {
+        match self.target.registers() {
+            Some(regs) => hex_encode(&regs),
+            None => "x".repeat(UNAVAILABLE_REGISTER_BYTES * 2),
+        }
+    }
+
+    fn read_memory(&mut self, args: &str) -> String {
+        let (addr, len) = match parse_range(args) {
+            Some(range) => range,
+            None => return "E01".to_string(),
+        };
+
+        let mut buf = vec![0; std::cmp::min(len, MAX_PACKET_SIZE / 2)];
+        match self.target.read_memory(Address::from(addr), &mut buf) {
+            Ok(_) => hex_encode(&buf),
+            Err(_) => "E14".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let mut parts = args.splitn(2, ':');
+
+        let range = parts.next().and_then(parse_range);
+        let data = parts.next().and_then(hex_decode);
+
+        match (range, data) {
+            (Some((addr, len)), Some(data)) if data.len() == len => {
+                match self.target.write_memory(Address::from(addr), &data) {
+                    Ok(_) => "OK".to_string(),
+                    Err(_) => "E14".to_string(),
+                }
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn libraries(&mut self, offset: usize, len: usize) -> String {
+        let mut xml = "<library-list>".to_string();
+
+        for module in self.target.libraries().unwrap_or_default() {
+            xml.push_str(&format!(
+                "<library name=\"{}\"><segment address=\"0x{:x}\"/></library>",
+                xml_escape(module.path.as_ref()),
+                module.base.to_umem()
+            ));
+        }
+
+        xml.push_str("</library-list>");
+
+        // responses are split into chunks, `l` marks the final one.
+        let xml = xml.as_bytes();
+        let start = std::cmp::min(offset, xml.len());
+        let end = std::cmp::min(start + len, xml.len());
+
+        format!(
+            "{}{}",
+            if end == xml.len() { "l" } else { "m" },
+            String::from_utf8_lossy(&xml[start..end])
+        )
+    }
+}
+
+pub(crate) enum Packet {
+    Data(Vec<u8>),
+    Interrupt,
+}
+
+/// Framing of packets on top of a byte stream.
+pub(crate) struct Connection<S> {
+    stream: S,
+    buf: Vec<u8>,
+    pos: usize,
+    pub(crate) no_ack: bool,
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buf: vec![],
+            pos: 0,
+            no_ack: false,
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+        if self.pos == self.buf.len() {
+            self.buf.resize(MAX_PACKET_SIZE, 0);
+            let read = self.stream.read(&mut self.buf).map_err(io_error)?;
+            self.buf.truncate(read);
+            self.pos = 0;
+
+            if read == 0 {
+                return Ok(None);
+            }
+        }
+
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    /// Reads the next packet, returns `None` once the client disconnected.
+    pub(crate) fn read_packet(&mut self) -> Result<Option<Packet>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(0x03) => return Ok(Some(Packet::Interrupt)),
+                Some(b'$') => {}
+                // acknowledgements and noise between packets
+                Some(_) => continue,
+            }
+
+            let mut data = vec![];
+            let mut sum = 0u8;
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => {
+                        sum = sum.wrapping_add(b);
+                        data.push(b);
+                    }
+                }
+            }
+
+            let mut checksum = [0u8; 2];
+            for c in checksum.iter_mut() {
+                *c = match self.read_byte()? {
+                    Some(c) => c,
+                    None => return Ok(None),
+                };
+            }
+
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                == Some(sum);
+
+            if !self.no_ack {
+                self.write_raw(if valid { b"+" } else { b"-" })?;
+            }
+
+            if valid {
+                return Ok(Some(Packet::Data(unescape(&data))));
+            }
+        }
+    }
+
+    pub(crate) fn write_packet(&mut self, data: &[u8]) -> Result<()> {
+        let data = escape(data);
+        let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(format!("#{:02x}", sum).as_bytes());
+
+        self.write_raw(&packet)
+    }
+
+    /// Sends a packet and waits for the response, used by clients.
+    pub(crate) fn request(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.write_packet(data)?;
+
+        loop {
+            match self.read_packet()? {
+                Some(Packet::Data(data)) => return Ok(data),
+                Some(Packet::Interrupt) => continue,
+                None => {
+                    return Err(Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory)
+                        .log_error("the gdb stub closed the connection"))
+                }
+            }
+        }
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> Result<()> {
+        self.stream.write_all(data).map_err(io_error)?;
+        self.stream.flush().map_err(io_error)
+    }
+}
+
+fn io_error(err: std::io::Error) -> Error {
+    Error(ErrorOrigin::Other, ErrorKind::UnableToReadFile)
+        .log_debug(format!("gdb connection failed: {}", err))
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        if let b'#' | b'$' | b'}' | b'*' = b {
+            out.push(b'}');
+            out.push(b ^ 0x20);
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+    while let Some(&b) = iter.next() {
+        if b == b'}' {
+            if let Some(&next) = iter.next() {
+                out.push(next ^ 0x20);
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// Parses `addr,len` with both values in hex.
+fn parse_range(s: &str) -> Option<(umem, usize)> {
+    let mut parts = s.splitn(2, ',');
+    let addr = umem::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((addr, len))
+}
+
+pub(crate) fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn hex_decode(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    use std::io::Cursor;
+
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        format!("${}#{:02x}", data, sum)
+    }
+
+    fn session(target: impl GdbTarget, packets: &[&str]) -> Vec<String> {
+        let input = packets.iter().map(|p| packet(p)).collect::<String>();
+        let mut stream = Duplex {
+            input: Cursor::new(input.into_bytes()),
+            output: vec![],
+        };
+
+        GdbServer::new(target).serve(&mut stream).unwrap();
+
+        String::from_utf8(stream.output)
+            .unwrap()
+            .split('$')
+            .skip(1)
+            .map(|p| p.split('#').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn memory_access() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0xde, 0xad, 0xbe, 0xef]);
+        let base = proc.info().address.to_umem();
+        proc.write(Address::from(base + 0x100), &0x1122u16).unwrap();
+
+        let responses = session(
+            ProcessTarget::new(proc),
+            &[
+                "qSupported:multiprocess+",
+                &format!("m{:x},4", base),
+                &format!("M{:x},2:4142", base + 0x100),
+                &format!("m{:x},2", base + 0x100),
+                "g",
+                "qXfer:libraries:read::0,1000",
+                "D",
+            ],
+        );
+
+        assert!(responses[0].contains("qXfer:libraries:read+"));
+        assert_eq!(responses[1], "deadbeef");
+        assert_eq!(responses[2], "OK");
+        assert_eq!(responses[3], "4142");
+        assert_eq!(responses[4], "xxxxxxxxxxxxxxxx");
+        assert!(responses[5].starts_with("l<library-list>"));
+        assert_eq!(responses[6], "OK");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(unescape(&escape(b"a#b$c}d*")), b"a#b$c}d*");
+        assert_eq!(hex_decode("0aff"), Some(vec![0x0a, 0xff]));
+        assert_eq!(parse_range("1000,10"), Some((0x1000, 0x10)));
+    }
+}
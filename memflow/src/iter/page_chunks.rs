@@ -451,6 +451,13 @@ impl<T: SplitAtIndex, FS: FnMut(Address, &T, Option<&T>) -> bool> Iterator
                 .wrapping_sub(1)
                 .wrapping_add(self.cur_off);
 
+                // The remaining data fits into the current chunk, avoid splitting it.
+                if buf.length() - 1 <= end_len {
+                    let ret = Some((self.cur_address, buf));
+                    self.cur_off = 0;
+                    return ret;
+                }
+
                 let (head, tail) = unsafe { buf.split_inclusive_at_mut(end_len) };
                 let head = head.unwrap();
                 if tail.is_some() && !(self.check_split_fn)(self.cur_address, &head, tail.as_ref())
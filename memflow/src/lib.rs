@@ -49,6 +49,27 @@ pub mod os;
 
 pub mod iter;
 
+pub mod scan;
+
+pub mod analysis;
+
+pub(crate) mod util;
+
+#[cfg(feature = "std")]
+pub mod monitor;
+
+#[cfg(feature = "scripting")]
+pub mod scripting;
+
+#[cfg(feature = "std")]
+pub mod shell;
+
+#[cfg(feature = "std")]
+pub mod gdb;
+
+#[cfg(feature = "embedded")]
+pub mod embedded;
+
 // forward declare
 #[doc(hidden)]
 pub mod derive {
@@ -92,6 +113,7 @@ pub mod prelude {
         pub use crate::plugins::os::*;
         #[cfg(feature = "plugins")]
         pub use crate::plugins::*;
+        pub use crate::scan::*;
         pub use crate::types::*;
     }
     pub use v1::*;
@@ -0,0 +1,417 @@
+//! Per page digests of acquired images.
+//!
+//! A [`PageManifest`] stores the digest of every page written by
+//! [`dump_physical`](super::dump_physical). It can be used to verify that an image was not
+//! altered after the acquisition ([`verify_image`]) or to find the pages that changed on the
+//! live target since the acquisition ([`verify_pages`]).
+//!
+//! The manifest is stored in a simple line based text format:
+//!
+//! ```text
+//! # memflow page manifest
+//! algo = sha256
+//! 0x1000 0x1000 3b8f...
+//! 0x2000 0x1000 9a01...
+//! ```
+//!
+//! Each page line contains the address, the size and the hex digest of the page.
+use std::prelude::v1::*;
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+use super::PAGE_SIZE;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::*;
+use crate::mem::memory_view::{Digest, HashAlgo};
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address, PhysicalAddress};
+
+use cglue::slice::CSliceMut;
+use cglue::tuple::*;
+
+/// Amount of pages read at once by [`verify_pages`].
+const VERIFY_BATCH_SIZE: usize = 256;
+
+/// The digest of a single page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageHash {
+    pub address: Address,
+    pub size: umem,
+    pub digest: Digest,
+}
+
+/// A page whose contents do not match the manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageMismatch {
+    pub address: Address,
+    pub size: umem,
+    pub expected: Digest,
+    /// The digest of the current contents, `None` if the page could not be read.
+    pub actual: Option<Digest>,
+}
+
+impl PageMismatch {
+    /// Returns `true` if the page could not be read at all.
+    pub fn is_unreadable(&self) -> bool {
+        self.actual.is_none()
+    }
+}
+
+/// The digests of all pages of a memory image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageManifest {
+    algo: HashAlgo,
+    pages: Vec<PageHash>,
+}
+
+impl PageManifest {
+    /// Creates an empty manifest using the given hash algorithm.
+    pub fn new(algo: HashAlgo) -> Self {
+        Self {
+            algo,
+            pages: vec![],
+        }
+    }
+
+    /// Returns the hash algorithm of this manifest.
+    pub fn algo(&self) -> HashAlgo {
+        self.algo
+    }
+
+    /// Returns all page digests, sorted by address.
+    pub fn pages(&self) -> &[PageHash] {
+        &self.pages
+    }
+
+    /// Merges the pages of `other` into this manifest.
+    ///
+    /// Pages that are contained in both manifests are taken from `other`. Both manifests have
+    /// to use the same hash algorithm.
+    pub fn merge(&mut self, other: PageManifest) -> Result<()> {
+        if self.algo != other.algo {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+                .log_error("unable to merge page manifests with different hash algorithms"));
+        }
+
+        let mut pages = other.pages;
+        pages.append(&mut self.pages);
+        // the sort is stable, so the pages of `other` come first and survive the dedup
+        pages.sort_by_key(|p| p.address);
+        pages.dedup_by_key(|p| p.address);
+        self.pages = pages;
+
+        Ok(())
+    }
+
+    /// Hashes `data` located at `base`, splitting it up on page boundaries.
+    pub(super) fn push_chunk(&mut self, base: Address, data: &[u8]) {
+        let mut address = base;
+        let mut data = data;
+
+        while !data.is_empty() {
+            let page_offset = (address.to_umem() % PAGE_SIZE as umem) as usize;
+            let (page, rest) = data.split_at(std::cmp::min(data.len(), PAGE_SIZE - page_offset));
+
+            self.pages.push(PageHash {
+                address,
+                size: page.len() as umem,
+                digest: self.hash(page),
+            });
+
+            address += page.len();
+            data = rest;
+        }
+    }
+
+    /// Writes the manifest in its text format.
+    pub fn write(&self, out: &mut (impl Write + ?Sized)) -> Result<()> {
+        self.write_inner(out).map_err(|err| {
+            Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteFile)
+                .log_error(format!("unable to write the page manifest: {}", err))
+        })
+    }
+
+    fn write_inner(&self, out: &mut (impl Write + ?Sized)) -> io::Result<()> {
+        writeln!(out, "# memflow page manifest")?;
+        writeln!(out, "algo = {}", self.algo.name())?;
+        for page in self.pages.iter() {
+            writeln!(
+                out,
+                "0x{:x} 0x{:x} {}",
+                page.address, page.size, page.digest
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Parses a manifest from its text format.
+    pub fn read(input: impl BufRead) -> Result<Self> {
+        let mut manifest: Option<PageManifest> = None;
+
+        for (i, line) in input.lines().enumerate() {
+            let line = line.map_err(|err| {
+                Error(ErrorOrigin::Memory, ErrorKind::UnableToReadFile)
+                    .log_error(format!("unable to read the page manifest: {}", err))
+            })?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                Error(ErrorOrigin::Memory, ErrorKind::UnableToReadFile)
+                    .log_error(format!("invalid page manifest entry in line {}", i + 1))
+            };
+
+            if let Some(algo) = line.strip_prefix("algo") {
+                let algo = algo.trim_start().strip_prefix('=').ok_or_else(invalid)?;
+                let algo = HashAlgo::from_name(algo.trim()).ok_or_else(invalid)?;
+                manifest = Some(PageManifest::new(algo));
+                continue;
+            }
+
+            let manifest = manifest.as_mut().ok_or_else(invalid)?;
+
+            let mut fields = line.split_whitespace();
+            let (address, size, digest) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(address), Some(size), Some(digest)) => (
+                    parse_hex(address).ok_or_else(invalid)?,
+                    parse_hex(size).ok_or_else(invalid)?,
+                    Digest::from_hex(manifest.algo, digest).ok_or_else(invalid)?,
+                ),
+                _ => return Err(invalid()),
+            };
+
+            if size == 0 || size > PAGE_SIZE as umem || fields.next().is_some() {
+                return Err(invalid());
+            }
+
+            manifest.pages.push(PageHash {
+                address: address.into(),
+                size,
+                digest,
+            });
+        }
+
+        let mut manifest = manifest.ok_or_else(|| {
+            Error(ErrorOrigin::Memory, ErrorKind::UnableToReadFile)
+                .log_error("the page manifest does not specify a hash algorithm")
+        })?;
+        manifest.pages.sort_by_key(|p| p.address);
+
+        Ok(manifest)
+    }
+
+    fn hash(&self, data: &[u8]) -> Digest {
+        let mut hasher = self.algo.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    fn check(&self, page: &PageHash, data: Option<&[u8]>, out: &mut Vec<PageMismatch>) {
+        let actual = data.map(|data| self.hash(data));
+        if actual != Some(page.digest) {
+            out.push(PageMismatch {
+                address: page.address,
+                size: page.size,
+                expected: page.digest,
+                actual,
+            });
+        }
+    }
+}
+
+/// Re-reads all pages of the manifest from `mem` and returns the ones that changed.
+pub fn verify_pages<T: PhysicalMemory>(
+    mem: &mut T,
+    manifest: &PageManifest,
+) -> Result<Vec<PageMismatch>> {
+    let mut out = vec![];
+    let mut buf = vec![0u8; PAGE_SIZE * VERIFY_BATCH_SIZE];
+
+    for batch in manifest.pages.chunks(VERIFY_BATCH_SIZE) {
+        let mut failed = vec![false; batch.len()];
+
+        {
+            let iter = batch
+                .iter()
+                .zip(buf.chunks_mut(PAGE_SIZE))
+                .map(|(page, data)| {
+                    (
+                        PhysicalAddress::from(page.address),
+                        CSliceMut::from(&mut data[..page.size as usize]),
+                    )
+                });
+
+            let callback = &mut |CTup2(addr, _): ReadData| {
+                if let Some(i) = batch
+                    .iter()
+                    .position(|p| p.address <= addr && addr < p.address + p.size)
+                {
+                    failed[i] = true;
+                }
+                true
+            };
+
+            MemOps::with(iter, None, Some(&mut callback.into()), |data| {
+                mem.phys_read_raw_iter(data)
+            })?;
+        }
+
+        for ((page, data), &failed) in batch.iter().zip(buf.chunks(PAGE_SIZE)).zip(failed.iter()) {
+            let data = if failed {
+                None
+            } else {
+                Some(&data[..page.size as usize])
+            };
+            manifest.check(page, data, &mut out);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Re-reads all pages of the manifest from a flat image and returns the ones that changed.
+///
+/// Pages that lie beyond the end of the image are reported as unreadable.
+pub fn verify_image<R: Read + Seek>(
+    image: &mut R,
+    manifest: &PageManifest,
+) -> Result<Vec<PageMismatch>> {
+    let mut out = vec![];
+    let mut buf = vec![0u8; PAGE_SIZE];
+
+    for page in manifest.pages.iter() {
+        let data = &mut buf[..page.size as usize];
+
+        let res = image
+            .seek(SeekFrom::Start(page.address.to_umem() as u64))
+            .and_then(|_| image.read_exact(data));
+
+        match res {
+            Ok(_) => manifest.check(page, Some(data), &mut out),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                manifest.check(page, None, &mut out)
+            }
+            Err(err) => {
+                return Err(Error(ErrorOrigin::Memory, ErrorKind::UnableToReadFile)
+                    .log_error(format!("unable to read the memory image: {}", err)))
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn parse_hex(s: &str) -> Option<umem> {
+    umem::from_str_radix(s.strip_prefix("0x")?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{dump_physical, AcquireOptions};
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+    use std::io::Cursor;
+
+    fn dump(mem: &mut DummyMemory) -> (Vec<u8>, PageManifest) {
+        let data = (0..size::kb(64))
+            .map(|i| (i % 253) as u8)
+            .collect::<Vec<_>>();
+        mem.phys_write(Address::null().into(), data.as_slice())
+            .unwrap();
+
+        let mut image = Cursor::new(vec![]);
+        let metadata = dump_physical(
+            mem,
+            &mut image,
+            AcquireOptions::new()
+                .page_hashes(HashAlgo::Sha256)
+                .chunk_size(0x1800),
+        )
+        .unwrap();
+
+        (image.into_inner(), metadata.manifest.unwrap())
+    }
+
+    #[test]
+    fn manifest_covers_pages() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        let (_, manifest) = dump(&mut mem);
+
+        assert_eq!(manifest.pages().len(), 16);
+        assert!(
+            manifest
+                .pages()
+                .iter()
+                .enumerate()
+                .all(|(i, p)| p.address == Address::from(i * PAGE_SIZE)
+                    && p.size == PAGE_SIZE as umem)
+        );
+    }
+
+    #[test]
+    fn verify_modified_image() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        let (mut image, manifest) = dump(&mut mem);
+
+        assert!(verify_image(&mut Cursor::new(&image), &manifest)
+            .unwrap()
+            .is_empty());
+
+        image[0x2345] ^= 0xff;
+        image.truncate(0xf000);
+
+        let mismatches = verify_image(&mut Cursor::new(&image), &manifest).unwrap();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].address, Address::from(0x2000));
+        assert!(!mismatches[0].is_unreadable());
+        assert_eq!(mismatches[1].address, Address::from(0xf000));
+        assert!(mismatches[1].is_unreadable());
+    }
+
+    #[test]
+    fn verify_live_target() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        let (_, manifest) = dump(&mut mem);
+
+        assert!(verify_pages(&mut mem, &manifest).unwrap().is_empty());
+
+        mem.phys_write(Address::from(0x5010).into(), &0xdead_beefu32)
+            .unwrap();
+
+        let mismatches = verify_pages(&mut mem, &manifest).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].address, Address::from(0x5000));
+        assert_eq!(mismatches[0].expected, manifest.pages()[5].digest);
+    }
+
+    #[test]
+    fn manifest_roundtrip() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        let (_, manifest) = dump(&mut mem);
+
+        let mut out = vec![];
+        manifest.write(&mut out).unwrap();
+
+        assert_eq!(PageManifest::read(out.as_slice()).unwrap(), manifest);
+        assert!(PageManifest::read(&b"0x1000 0x1000 00"[..]).is_err());
+    }
+
+    #[test]
+    fn manifest_merge() {
+        let mut first = PageManifest::new(HashAlgo::Crc32);
+        first.push_chunk(Address::null(), &[0u8; PAGE_SIZE * 2]);
+
+        let mut second = PageManifest::new(HashAlgo::Crc32);
+        second.push_chunk(Address::from(PAGE_SIZE), &[1u8; PAGE_SIZE * 2]);
+
+        first.merge(second.clone()).unwrap();
+
+        assert_eq!(first.pages().len(), 3);
+        assert_eq!(&first.pages()[1..], second.pages());
+        assert!(first.merge(PageManifest::new(HashAlgo::Sha256)).is_err());
+    }
+}
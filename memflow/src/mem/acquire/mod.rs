@@ -0,0 +1,603 @@
+//! Acquisition of full physical memory images.
+//!
+//! [`dump_physical`] writes the physical memory of a [`PhysicalMemory`] object into a flat
+//! image, where every byte is stored at the file offset equal to its physical address. Only
+//! the ranges of the memory map are read, so MMIO holes and other unbacked regions are never
+//! touched and stay sparse in the output.
+//!
+//! Next to the image a metadata sidecar can be written. The sidecar is a TOML file that
+//! records the dumped ranges in the same format [`MemoryMap::open`] accepts, together with
+//! the acquisition time, connector information and the unreadable ranges. If the dump was
+//! interrupted the sidecar also contains the address to resume from.
+//!
+//! Optionally a [`PageManifest`] with the digest of every dumped page can be created. The
+//! manifest can later be used to verify an image or a live target with [`verify_image`] and
+//! [`verify_pages`].
+//!
+//! To analyze an image with Volatility 3 the target metadata can be exported with
+//! [`VolatilityConfig`]. Linux targets can be exported as a kdump compatible ELF vmcore for
+//! `crash` and `drgn` with [`write_vmcore`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::acquire::{dump_physical, AcquireOptions};
+//! use memflow::types::size;
+//!
+//! let mut mem = DummyMemory::new(size::mb(2));
+//!
+//! let mut image = std::io::Cursor::new(vec![]);
+//! let mut sidecar = vec![];
+//!
+//! let metadata = dump_physical(
+//!     &mut mem,
+//!     &mut image,
+//!     AcquireOptions::new()
+//!         .connector_info("dummy")
+//!         .sidecar(&mut sidecar)
+//!         .progress(|p| {
+//!             println!("{}/{} bytes", p.bytes_done, p.bytes_total);
+//!             true
+//!         }),
+//! )
+//! .unwrap();
+//!
+//! assert!(metadata.is_complete());
+//! assert_eq!(image.get_ref().len(), size::mb(2));
+//! ```
+use std::prelude::v1::*;
+
+pub mod manifest;
+pub mod vmcore;
+pub mod volatility;
+
+pub use manifest::{verify_image, verify_pages, PageHash, PageManifest, PageMismatch};
+pub use vmcore::{write_vmcore, VmcoreOptions};
+pub use volatility::VolatilityConfig;
+
+use std::io::{Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::mem_data::*;
+use super::{MemoryMap, PhysicalMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::{size, umem, Address, CancellationToken, PhysicalAddress};
+
+use crate::mem::memory_view::HashAlgo;
+
+use cglue::slice::CSliceMut;
+use cglue::tuple::*;
+
+/// Default amount of bytes read and written at once.
+pub const DEFAULT_CHUNK_SIZE: usize = size::mb(4);
+
+/// Size of the individual elements of each scatter read and of the pages in a [`PageManifest`].
+pub const PAGE_SIZE: usize = size::kb(4);
+
+/// Progress information passed to the [`AcquireOptions::progress`] callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcquireProgress {
+    /// The address up to which memory has been dumped.
+    pub address: Address,
+    /// The amount of bytes that have been dumped so far, including resumed ones.
+    pub bytes_done: umem,
+    /// The total amount of bytes that will be dumped.
+    pub bytes_total: umem,
+}
+
+/// Options for [`dump_physical`].
+pub struct AcquireOptions<'a> {
+    mem_map: Option<MemoryMap<(Address, umem)>>,
+    skip: Vec<Range<Address>>,
+    chunk_size: usize,
+    resume_from: Option<Address>,
+    connector: Option<String>,
+    page_hashes: Option<HashAlgo>,
+    sidecar: Option<&'a mut dyn Write>,
+    progress: Option<Box<dyn FnMut(AcquireProgress) -> bool + 'a>>,
+    cancel: Option<CancellationToken>,
+}
+
+impl<'a> Default for AcquireOptions<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> AcquireOptions<'a> {
+    /// Creates the default options.
+    ///
+    /// By default the entire physical address space up to the `max_address` reported by the
+    /// memory object is dumped.
+    pub fn new() -> Self {
+        Self {
+            mem_map: None,
+            skip: vec![],
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            resume_from: None,
+            connector: None,
+            page_hashes: None,
+            sidecar: None,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    /// Only dumps the ranges of the given memory map.
+    ///
+    /// Everything outside of the map (e.g. MMIO holes) is skipped.
+    pub fn mem_map(mut self, mem_map: MemoryMap<(Address, umem)>) -> Self {
+        self.mem_map = Some(mem_map);
+        self
+    }
+
+    /// Excludes the given range from the dump, even if it is part of the memory map.
+    pub fn skip(mut self, range: Range<Address>) -> Self {
+        self.skip.push(range);
+        self
+    }
+
+    /// Sets the amount of bytes that are read and written at once.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Resumes a previously interrupted dump at the given address.
+    ///
+    /// The output has to be the image of the interrupted dump, everything below `address`
+    /// is left untouched. The address to resume from is stored in
+    /// [`AcquireMetadata::next_address`].
+    pub fn resume_from(mut self, address: Address) -> Self {
+        self.resume_from = Some(address);
+        self
+    }
+
+    /// Sets the connector information that is stored in the sidecar.
+    pub fn connector_info(mut self, connector: impl Into<String>) -> Self {
+        self.connector = Some(connector.into());
+        self
+    }
+
+    /// Hashes every dumped page with `algo` and stores the digests in
+    /// [`AcquireMetadata::manifest`].
+    ///
+    /// When resuming a dump only the newly dumped pages are hashed, the manifest of the
+    /// interrupted dump has to be merged with [`PageManifest::merge`].
+    pub fn page_hashes(mut self, algo: HashAlgo) -> Self {
+        self.page_hashes = Some(algo);
+        self
+    }
+
+    /// Writes the metadata sidecar into `sidecar` once the dump is finished or interrupted.
+    pub fn sidecar(mut self, sidecar: &'a mut dyn Write) -> Self {
+        self.sidecar = Some(sidecar);
+        self
+    }
+
+    /// Sets a callback that is invoked after every written chunk.
+    ///
+    /// Returning `false` from the callback interrupts the dump. The returned
+    /// [`AcquireMetadata`] can be used to resume it later on.
+    pub fn progress(mut self, progress: impl FnMut(AcquireProgress) -> bool + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Interrupts the dump after the current chunk once `token` is cancelled.
+    ///
+    /// Like an interruption through [`progress`](Self::progress), this is not an error and the
+    /// dump can be resumed.
+    pub fn cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+/// Metadata describing a physical memory dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcquireMetadata {
+    /// The physical address ranges contained in the image.
+    pub ranges: Vec<Range<Address>>,
+    /// Ranges that could not be read and have been filled with zeroes.
+    pub failed: Vec<Range<Address>>,
+    /// Digests of all dumped pages, if enabled with [`AcquireOptions::page_hashes`].
+    pub manifest: Option<PageManifest>,
+    /// The address to resume from if the dump was interrupted, `None` if it is complete.
+    pub next_address: Option<Address>,
+    /// Connector information as set in [`AcquireOptions::connector_info`].
+    pub connector: Option<String>,
+    /// Seconds since the unix epoch at which the dump was started.
+    pub started: u64,
+    /// Seconds since the unix epoch at which the dump was finished or interrupted.
+    pub finished: u64,
+}
+
+impl AcquireMetadata {
+    /// Returns `true` if all ranges have been dumped.
+    pub fn is_complete(&self) -> bool {
+        self.next_address.is_none()
+    }
+
+    /// Writes the metadata in the sidecar format.
+    ///
+    /// The dumped ranges are written as `[[range]]` tables, so the sidecar can be loaded
+    /// with [`MemoryMap::open`] to access the image with the original memory map.
+    pub fn write_sidecar(&self, out: &mut (impl Write + ?Sized)) -> Result<()> {
+        self.write_sidecar_inner(out).map_err(|err| {
+            Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteFile)
+                .log_error(format!("unable to write the dump sidecar: {}", err))
+        })
+    }
+
+    fn write_sidecar_inner(&self, out: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+        writeln!(out, "# memflow physical memory dump")?;
+        if let Some(connector) = &self.connector {
+            writeln!(out, "connector = {:?}", connector)?;
+        }
+        writeln!(out, "started = {}", self.started)?;
+        writeln!(out, "finished = {}", self.finished)?;
+        writeln!(out, "complete = {}", self.is_complete())?;
+        if let Some(next_address) = self.next_address {
+            writeln!(out, "next_address = 0x{:x}", next_address)?;
+        }
+        if let Some(manifest) = &self.manifest {
+            writeln!(out, "page_hashes = {:?}", manifest.algo().name())?;
+        }
+
+        for (name, ranges) in [("range", &self.ranges), ("failed", &self.failed)].iter() {
+            for range in ranges.iter() {
+                writeln!(out)?;
+                writeln!(out, "[[{}]]", name)?;
+                writeln!(out, "base=0x{:x}", range.start)?;
+                writeln!(
+                    out,
+                    "length=0x{:x}",
+                    range.end.to_umem() - range.start.to_umem()
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Dumps the physical memory of `mem` into `out`.
+///
+/// Every byte is written at the offset of its physical address, ranges that are not dumped
+/// are skipped over. Unreadable parts of the dumped ranges are filled with zeroes and
+/// recorded in [`AcquireMetadata::failed`].
+///
+/// An interrupted dump is not an error, it is reported through
+/// [`AcquireMetadata::next_address`] instead.
+pub fn dump_physical<T: PhysicalMemory, W: Write + Seek>(
+    mem: &mut T,
+    out: &mut W,
+    mut options: AcquireOptions,
+) -> Result<AcquireMetadata> {
+    if options.chunk_size == 0 {
+        return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+            .log_error("chunk size must be greater than 0"));
+    }
+
+    let ranges = dump_ranges(mem, &options);
+    let bytes_total: umem = ranges.iter().map(|r| r.end - r.start).sum();
+
+    let mut metadata = AcquireMetadata {
+        ranges: ranges
+            .iter()
+            .map(|r| Address::from(r.start)..Address::from(r.end))
+            .collect(),
+        failed: vec![],
+        manifest: options.page_hashes.map(PageManifest::new),
+        next_address: None,
+        connector: options.connector.take(),
+        started: unix_time(),
+        finished: 0,
+    };
+
+    let resume_from = options.resume_from.map(Address::to_umem).unwrap_or(0);
+    let mut bytes_done = 0;
+    let mut buf = vec![0u8; options.chunk_size];
+
+    'outer: for range in ranges.iter() {
+        let mut addr = range.start;
+
+        if resume_from >= range.end {
+            bytes_done += range.end - range.start;
+            continue;
+        } else if resume_from > addr {
+            bytes_done += resume_from - addr;
+            addr = resume_from;
+        }
+
+        while addr < range.end {
+            let mut end = std::cmp::min(range.end, addr + buf.len() as umem);
+            // end chunks on page boundaries so that no page is split up in the manifest
+            if end < range.end && end - end % PAGE_SIZE as umem > addr {
+                end -= end % PAGE_SIZE as umem;
+            }
+
+            let len = (end - addr) as usize;
+            let chunk = &mut buf[..len];
+
+            read_chunk(mem, addr, chunk, &mut metadata.failed)?;
+
+            if let Some(manifest) = metadata.manifest.as_mut() {
+                manifest.push_chunk(addr.into(), chunk);
+            }
+
+            out.seek(SeekFrom::Start(addr as u64))
+                .and_then(|_| out.write_all(chunk))
+                .map_err(|err| {
+                    Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteFile)
+                        .log_error(format!("unable to write the memory dump: {}", err))
+                })?;
+
+            addr += len as umem;
+            bytes_done += len as umem;
+
+            let mut proceed = true;
+            if let Some(progress) = options.progress.as_mut() {
+                proceed = progress(AcquireProgress {
+                    address: addr.into(),
+                    bytes_done,
+                    bytes_total,
+                });
+            }
+            if let Some(token) = &options.cancel {
+                proceed &= !token.is_cancelled();
+            }
+
+            if !proceed && bytes_done < bytes_total {
+                metadata.next_address = Some(addr.into());
+                break 'outer;
+            }
+        }
+    }
+
+    out.flush().map_err(|err| {
+        Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteFile)
+            .log_error(format!("unable to write the memory dump: {}", err))
+    })?;
+
+    metadata.finished = unix_time();
+
+    if let Some(sidecar) = options.sidecar {
+        metadata.write_sidecar(sidecar)?;
+    }
+
+    Ok(metadata)
+}
+
+/// Returns the sorted ranges of the memory map with all skipped ranges removed.
+fn dump_ranges<T: PhysicalMemory>(mem: &T, options: &AcquireOptions) -> Vec<Range<umem>> {
+    let mut ranges = match &options.mem_map {
+        Some(mem_map) => mem_map
+            .iter()
+            .map(|m| {
+                let base = m.base().to_umem();
+                base..base + m.output().1
+            })
+            .collect::<Vec<_>>(),
+        None => vec![0..mem.metadata().max_address.to_umem().saturating_add(1)],
+    };
+
+    for skip in options.skip.iter() {
+        let (start, end) = (skip.start.to_umem(), skip.end.to_umem());
+
+        ranges = ranges
+            .into_iter()
+            .flat_map(|r| {
+                let before = r.start..std::cmp::min(r.end, start);
+                let after = std::cmp::max(r.start, end)..r.end;
+                std::iter::once(before).chain(std::iter::once(after))
+            })
+            .filter(|r| r.start < r.end)
+            .collect();
+    }
+
+    ranges.sort_by_key(|r| r.start);
+    ranges
+}
+
+/// Reads a single chunk, zeroing and recording all parts that could not be read.
+pub(crate) fn read_chunk<T: PhysicalMemory>(
+    mem: &mut T,
+    base: umem,
+    chunk: &mut [u8],
+    failed: &mut Vec<Range<Address>>,
+) -> Result<()> {
+    let mut chunk_failed = vec![];
+
+    {
+        let iter = chunk.chunks_mut(PAGE_SIZE).enumerate().map(|(i, data)| {
+            (
+                PhysicalAddress::from(Address::from(base + (i * PAGE_SIZE) as umem)),
+                CSliceMut::from(data),
+            )
+        });
+
+        let callback = &mut |CTup2(addr, mut data): ReadData| {
+            for b in data.iter_mut() {
+                *b = 0;
+            }
+            chunk_failed.push(addr..addr + data.len());
+            true
+        };
+
+        MemOps::with(iter, None, Some(&mut callback.into()), |data| {
+            mem.phys_read_raw_iter(data)
+        })?;
+    }
+
+    chunk_failed.sort_by_key(|r| r.start);
+
+    for range in chunk_failed {
+        match failed.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => failed.push(range),
+        }
+    }
+
+    Ok(())
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use std::io::Cursor;
+
+    fn dummy_mem() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let data = (0..size::mb(1))
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        mem.phys_write(Address::null().into(), data.as_slice())
+            .unwrap();
+        mem
+    }
+
+    #[test]
+    fn dump_skips_holes() {
+        let mut mem = dummy_mem();
+
+        let mut mem_map = MemoryMap::new();
+        mem_map.push_remap(Address::null(), 0x10000, Address::null());
+        mem_map.push_remap(Address::from(0x20000), 0x10000, Address::from(0x20000));
+
+        let mut image = Cursor::new(vec![]);
+        let metadata = dump_physical(
+            &mut mem,
+            &mut image,
+            AcquireOptions::new()
+                .mem_map(mem_map)
+                .skip(Address::from(0x8000)..Address::from(0x9000))
+                .chunk_size(0x3000),
+        )
+        .unwrap();
+
+        assert!(metadata.is_complete());
+        assert_eq!(
+            metadata.ranges,
+            vec![
+                Address::from(0x0)..Address::from(0x8000),
+                Address::from(0x9000)..Address::from(0x10000),
+                Address::from(0x20000)..Address::from(0x30000),
+            ]
+        );
+
+        let image = image.into_inner();
+        assert_eq!(image.len(), 0x30000);
+        for (i, &b) in image.iter().enumerate() {
+            let dumped = metadata
+                .ranges
+                .iter()
+                .any(|r| r.start.to_umem() <= i as umem && (i as umem) < r.end.to_umem());
+            assert_eq!(b, if dumped { (i % 251) as u8 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn dump_records_failures() {
+        let mut mem = dummy_mem();
+
+        let mut mem_map = MemoryMap::new();
+        let base = Address::from(size::mb(1) - 0x1000);
+        mem_map.push_remap(base, 0x3000, base);
+
+        let mut image = Cursor::new(vec![]);
+        let metadata =
+            dump_physical(&mut mem, &mut image, AcquireOptions::new().mem_map(mem_map)).unwrap();
+
+        assert_eq!(
+            metadata.failed,
+            vec![Address::from(size::mb(1))..Address::from(size::mb(1) + 0x2000)]
+        );
+    }
+
+    #[test]
+    fn dump_cancel() {
+        let mut mem = dummy_mem();
+        let token = CancellationToken::new();
+
+        let mut chunks = 0;
+        let mut image = Cursor::new(vec![]);
+        let metadata = dump_physical(
+            &mut mem,
+            &mut image,
+            AcquireOptions::new()
+                .chunk_size(0x10000)
+                .cancel(token.clone())
+                .progress(|_| {
+                    chunks += 1;
+                    if chunks == 2 {
+                        token.cancel();
+                    }
+                    true
+                }),
+        )
+        .unwrap();
+
+        assert_eq!(metadata.next_address, Some(Address::from(0x20000)));
+    }
+
+    #[test]
+    fn dump_resume() {
+        let mut mem = dummy_mem();
+
+        let mut image = Cursor::new(vec![]);
+        let mut sidecar = vec![];
+
+        let mut chunks = 0;
+        let metadata = dump_physical(
+            &mut mem,
+            &mut image,
+            AcquireOptions::new()
+                .chunk_size(0x10000)
+                .sidecar(&mut sidecar)
+                .progress(|_| {
+                    chunks += 1;
+                    chunks < 3
+                }),
+        )
+        .unwrap();
+
+        assert_eq!(metadata.next_address, Some(Address::from(0x30000)));
+        assert!(String::from_utf8(sidecar)
+            .unwrap()
+            .contains("next_address = 0x30000"));
+
+        let mut done = vec![];
+        let metadata = dump_physical(
+            &mut mem,
+            &mut image,
+            AcquireOptions::new()
+                .chunk_size(0x10000)
+                .resume_from(metadata.next_address.unwrap())
+                .progress(|p| {
+                    done.push(p.bytes_done);
+                    true
+                }),
+        )
+        .unwrap();
+
+        assert!(metadata.is_complete());
+        assert_eq!(done.first(), Some(&0x40000));
+        assert_eq!(done.last(), Some(&(size::mb(1) as umem)));
+
+        let image = image.into_inner();
+        assert_eq!(image.len(), size::mb(1));
+        assert!(image.iter().enumerate().all(|(i, &b)| b == (i % 251) as u8));
+    }
+}
@@ -0,0 +1,396 @@
+//! Export of physical memory as a Linux kdump compatible ELF vmcore.
+//!
+//! [`write_vmcore`] writes the physical memory of a target as an ELF64 core file in the
+//! format produced by kexec/kdump (`/proc/vmcore`). Every dumped range becomes a `PT_LOAD`
+//! segment, the metadata is stored in a single `PT_NOTE` segment:
+//!
+//! * a `VMCOREINFO` note, which `crash` and `drgn` use to locate the kernel symbols and to
+//!   undo KASLR. Its contents have to be provided with [`VmcoreOptions::vmcoreinfo`], e.g.
+//!   read from the `vmcoreinfo_note` of the target kernel,
+//! * one `NT_PRSTATUS` note per cpu with its general purpose registers, added with
+//!   [`VmcoreOptions::prstatus`].
+//!
+//! The resulting file can be opened with `crash vmlinux vmcore` or `drgn -c vmcore`.
+
+use std::prelude::v1::*;
+
+use std::io::Write;
+use std::ops::Range;
+
+use super::{read_chunk, unix_time, AcquireMetadata, DEFAULT_CHUNK_SIZE};
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{MemoryMap, PhysicalMemory};
+use crate::types::{umem, Address, CancellationToken};
+
+const ELF_HEADER_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+/// Layout of `struct elf_prstatus` for an architecture.
+struct PrStatusLayout {
+    size: usize,
+    pid_offset: usize,
+    reg_offset: usize,
+    reg_size: usize,
+    reg_count: usize,
+}
+
+fn machine(arch: ArchitectureIdent) -> Result<(u16, PrStatusLayout)> {
+    match arch {
+        ArchitectureIdent::X86(64, _) => Ok((
+            62,
+            PrStatusLayout {
+                size: 336,
+                pid_offset: 32,
+                reg_offset: 112,
+                reg_size: 8,
+                reg_count: 27,
+            },
+        )),
+        ArchitectureIdent::X86(32, _) => Ok((
+            3,
+            PrStatusLayout {
+                size: 144,
+                pid_offset: 24,
+                reg_offset: 72,
+                reg_size: 4,
+                reg_count: 17,
+            },
+        )),
+        ArchitectureIdent::AArch64(_) => Ok((
+            183,
+            PrStatusLayout {
+                size: 392,
+                pid_offset: 32,
+                reg_offset: 112,
+                reg_size: 8,
+                reg_count: 34,
+            },
+        )),
+        _ => Err(Error(ErrorOrigin::Memory, ErrorKind::NotSupported)
+            .log_error(format!("vmcores are not supported for {:?}", arch))),
+    }
+}
+
+/// Options for [`write_vmcore`].
+#[derive(Clone)]
+pub struct VmcoreOptions {
+    arch: ArchitectureIdent,
+    mem_map: Option<MemoryMap<(Address, umem)>>,
+    page_offset: Option<Address>,
+    vmcoreinfo: Option<String>,
+    prstatus: Vec<(u32, Vec<u64>)>,
+    chunk_size: usize,
+    cancel: Option<CancellationToken>,
+}
+
+impl VmcoreOptions {
+    /// Creates the default options for a target of the given architecture.
+    ///
+    /// By default the entire physical address space up to the `max_address` reported by the
+    /// memory object is dumped.
+    pub fn new(arch: ArchitectureIdent) -> Self {
+        Self {
+            arch,
+            mem_map: None,
+            page_offset: None,
+            vmcoreinfo: None,
+            prstatus: vec![],
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            cancel: None,
+        }
+    }
+
+    /// Only dumps the ranges of the given memory map.
+    pub fn mem_map(mut self, mem_map: MemoryMap<(Address, umem)>) -> Self {
+        self.mem_map = Some(mem_map);
+        self
+    }
+
+    /// Sets the virtual base of the direct mapping (`PAGE_OFFSET`).
+    ///
+    /// The virtual address of each segment is set to `page_offset + physical address`, like
+    /// kdump does. Without it the virtual addresses equal the physical ones.
+    pub fn page_offset(mut self, page_offset: Address) -> Self {
+        self.page_offset = Some(page_offset);
+        self
+    }
+
+    /// Sets the contents of the `VMCOREINFO` note (`KEY=value` lines).
+    pub fn vmcoreinfo(mut self, vmcoreinfo: impl Into<String>) -> Self {
+        self.vmcoreinfo = Some(vmcoreinfo.into());
+        self
+    }
+
+    /// Adds the register state of a cpu, `pid` is the id of the task running on it.
+    ///
+    /// `regs` are the general purpose registers in the order of `elf_gregset_t` of the
+    /// architecture, missing registers are stored as zeroes.
+    pub fn prstatus(mut self, pid: u32, regs: &[u64]) -> Self {
+        self.prstatus.push((pid, regs.to_vec()));
+        self
+    }
+
+    /// Sets the amount of bytes that are read and written at once.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Aborts writing with [`ErrorKind::Cancelled`] once `token` is cancelled.
+    ///
+    /// The partially written vmcore is not valid.
+    pub fn cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    fn ranges<T: PhysicalMemory>(&self, mem: &T) -> Vec<Range<umem>> {
+        let mut ranges = match &self.mem_map {
+            Some(mem_map) => mem_map
+                .iter()
+                .map(|m| {
+                    let base = m.base().to_umem();
+                    base..base + m.output().1
+                })
+                .collect::<Vec<_>>(),
+            None => vec![0..mem.metadata().max_address.to_umem().saturating_add(1)],
+        };
+        ranges.sort_by_key(|r| r.start);
+        ranges
+    }
+}
+
+/// Writes the physical memory of `mem` as an ELF vmcore into `out`.
+///
+/// Unreadable parts of the dumped ranges are filled with zeroes and recorded in
+/// [`AcquireMetadata::failed`].
+pub fn write_vmcore<T: PhysicalMemory, W: Write>(
+    mem: &mut T,
+    out: &mut W,
+    options: VmcoreOptions,
+) -> Result<AcquireMetadata> {
+    if options.chunk_size == 0 {
+        return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+            .log_error("chunk size must be greater than 0"));
+    }
+
+    let (machine, layout) = machine(options.arch)?;
+    let ranges = options.ranges(mem);
+
+    let mut metadata = AcquireMetadata {
+        ranges: ranges
+            .iter()
+            .map(|r| Address::from(r.start)..Address::from(r.end))
+            .collect(),
+        failed: vec![],
+        manifest: None,
+        next_address: None,
+        connector: None,
+        started: unix_time(),
+        finished: 0,
+    };
+
+    let notes = build_notes(&options, &layout);
+    let mut header = elf_header(machine, ranges.len() + 1);
+
+    let mut offset = (ELF_HEADER_SIZE + PHDR_SIZE * (ranges.len() + 1)) as u64;
+    push_phdr(&mut header, PT_NOTE, offset, 0, 0, notes.len() as u64);
+    offset += notes.len() as u64;
+
+    let page_offset = options.page_offset.map(Address::to_umem).unwrap_or(0) as u64;
+    for range in ranges.iter() {
+        let size = (range.end - range.start) as u64;
+        let paddr = range.start as u64;
+        push_phdr(
+            &mut header,
+            PT_LOAD,
+            offset,
+            page_offset.wrapping_add(paddr),
+            paddr,
+            size,
+        );
+        offset += size;
+    }
+
+    write_all(out, &header)?;
+    write_all(out, &notes)?;
+
+    let mut buf = vec![0u8; options.chunk_size];
+    for range in ranges.iter() {
+        let mut addr = range.start;
+        while addr < range.end {
+            if let Some(token) = &options.cancel {
+                token.check()?;
+            }
+
+            let len = std::cmp::min(range.end - addr, buf.len() as umem) as usize;
+            let chunk = &mut buf[..len];
+
+            read_chunk(mem, addr, chunk, &mut metadata.failed)?;
+            write_all(out, chunk)?;
+
+            addr += len as umem;
+        }
+    }
+
+    out.flush().map_err(|err| {
+        Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteFile)
+            .log_error(format!("unable to write the vmcore: {}", err))
+    })?;
+
+    metadata.finished = unix_time();
+
+    Ok(metadata)
+}
+
+fn write_all(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    out.write_all(data).map_err(|err| {
+        Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteFile)
+            .log_error(format!("unable to write the vmcore: {}", err))
+    })
+}
+
+fn elf_header(machine: u16, phnum: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(ELF_HEADER_SIZE + PHDR_SIZE * phnum);
+
+    // e_ident: 64 bit, little endian, current version, System V ABI
+    out.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&4u16.to_le_bytes()); // ET_CORE
+    out.extend_from_slice(&machine.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    out.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(phnum as u16).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    out
+}
+
+fn push_phdr(out: &mut Vec<u8>, ty: u32, offset: u64, vaddr: u64, paddr: u64, size: u64) {
+    // PT_LOAD segments are readable, writeable and executable, like in kdump
+    let flags = if ty == PT_LOAD { 7u32 } else { 0 };
+    let align = if ty == PT_LOAD { 0 } else { 4u64 };
+
+    out.extend_from_slice(&ty.to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&offset.to_le_bytes());
+    out.extend_from_slice(&vaddr.to_le_bytes());
+    out.extend_from_slice(&paddr.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes()); // p_filesz
+    out.extend_from_slice(&size.to_le_bytes()); // p_memsz
+    out.extend_from_slice(&align.to_le_bytes());
+}
+
+fn build_notes(options: &VmcoreOptions, layout: &PrStatusLayout) -> Vec<u8> {
+    let mut notes = vec![];
+
+    for (pid, regs) in options.prstatus.iter() {
+        let mut desc = vec![0u8; layout.size];
+        desc[layout.pid_offset..layout.pid_offset + 4].copy_from_slice(&pid.to_le_bytes());
+        for (i, reg) in regs.iter().take(layout.reg_count).enumerate() {
+            let offset = layout.reg_offset + i * layout.reg_size;
+            desc[offset..offset + layout.reg_size]
+                .copy_from_slice(&reg.to_le_bytes()[..layout.reg_size]);
+        }
+        push_note(&mut notes, "CORE", NT_PRSTATUS, &desc);
+    }
+
+    if let Some(vmcoreinfo) = &options.vmcoreinfo {
+        push_note(&mut notes, "VMCOREINFO", 0, vmcoreinfo.as_bytes());
+    }
+
+    notes
+}
+
+fn push_note(out: &mut Vec<u8>, name: &str, ty: u32, desc: &[u8]) {
+    out.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+    out.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    out.extend_from_slice(&ty.to_le_bytes());
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    pad4(out);
+    out.extend_from_slice(desc);
+    pad4(out);
+}
+
+fn pad4(out: &mut Vec<u8>) {
+    while out.len() % 4 != 0 {
+        out.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    fn u64_at(data: &[u8], offset: usize) -> u64 {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&data[offset..offset + 8]);
+        u64::from_le_bytes(buf)
+    }
+
+    #[test]
+    fn vmcore_layout() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(Address::from(0x20000).into(), &[0x42u8; 16])
+            .unwrap();
+
+        let mut mem_map = MemoryMap::new();
+        mem_map.push_remap(Address::null(), 0x10000, Address::null());
+        mem_map.push_remap(Address::from(0x20000), 0x10000, Address::from(0x20000));
+
+        let mut out = vec![];
+        write_vmcore(
+            &mut mem,
+            &mut out,
+            VmcoreOptions::new(ArchitectureIdent::X86(64, false))
+                .mem_map(mem_map)
+                .page_offset(Address::from(0xffff_8880_0000_0000u64))
+                .vmcoreinfo("OSRELEASE=6.1.0\n")
+                .prstatus(1, &[0x1234]),
+        )
+        .unwrap();
+
+        assert_eq!(&out[..4], b"\x7fELF");
+        // e_phnum: one note and two load segments
+        assert_eq!(&out[56..58], &3u16.to_le_bytes());
+
+        let note = ELF_HEADER_SIZE;
+        let note_offset = u64_at(&out, note + 8) as usize;
+        let note_size = u64_at(&out, note + 32) as usize;
+        let notes = &out[note_offset..note_offset + note_size];
+        assert_eq!(&notes[12..17], b"CORE\0");
+        // r15 of the prstatus note
+        assert_eq!(u64_at(notes, 20 + 112), 0x1234);
+        assert!(notes.windows(16).any(|w| w == b"OSRELEASE=6.1.0\n"));
+
+        let load = ELF_HEADER_SIZE + PHDR_SIZE * 2;
+        assert_eq!(u64_at(&out, load + 16), 0xffff_8880_0002_0000);
+        assert_eq!(u64_at(&out, load + 24), 0x20000);
+
+        let offset = u64_at(&out, load + 8) as usize;
+        assert_eq!(&out[offset..offset + 16], &[0x42u8; 16]);
+        assert_eq!(out.len(), offset + 0x10000);
+    }
+
+    #[test]
+    fn unsupported_arch() {
+        let mut mem = DummyMemory::new(size::kb(4));
+        let options = VmcoreOptions::new(ArchitectureIdent::Unknown(4096));
+        assert!(write_vmcore(&mut mem, &mut vec![], options).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Export of target metadata for Volatility 3.
+//!
+//! [`VolatilityConfig`] writes a Volatility 3 configuration file that describes the
+//! translation layer of a target: the paging mode, the directory table base and the kernel
+//! base. Volatility can load the file with `vol -c <config> ...` and skips the scanning
+//! automagic that would otherwise have to rediscover these values from the image.
+//!
+//! The memory layer of the configuration either points to an image written by
+//! [`dump_physical`](super::dump_physical) or to the memflow layer shipped with `memflow-ffi`,
+//! which accesses a connector directly.
+//!
+//! Values Volatility itself has no use for (the KDBG equivalent, the kernel version and the
+//! memory map) are stored under `memflow.*` keys, which Volatility ignores.
+
+use std::prelude::v1::*;
+
+use std::io::Write;
+use std::ops::Range;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::Address;
+
+/// Memory layer class of Volatility for flat images.
+pub const FILE_LAYER_CLASS: &str = "volatility3.framework.layers.physical.FileLayer";
+
+/// Builder of a Volatility 3 configuration file.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::architecture::ArchitectureIdent;
+/// use memflow::mem::acquire::VolatilityConfig;
+/// use memflow::types::Address;
+///
+/// let mut out = vec![];
+///
+/// VolatilityConfig::new(ArchitectureIdent::X86(64, false), Address::from(0x1ad000u64))
+///     .kernel_base(Address::from(0xfffff8025e000000u64))
+///     .kernel_version("10.0.19041")
+///     .image("file:///tmp/memory.raw")
+///     .write(&mut out)
+///     .unwrap();
+///
+/// let config = String::from_utf8(out).unwrap();
+/// assert!(config.contains("\"kernel.layer_name.page_map_offset\": 1757184"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct VolatilityConfig {
+    arch: ArchitectureIdent,
+    dtb: Address,
+    windows: bool,
+    layer_class: Option<String>,
+    kernel_base: Option<Address>,
+    kdbg: Option<Address>,
+    kernel_version: Option<String>,
+    memory_layer: Option<(String, String)>,
+    ranges: Vec<Range<Address>>,
+}
+
+impl VolatilityConfig {
+    /// Creates a configuration for the architecture and directory table base of the kernel.
+    pub fn new(arch: ArchitectureIdent, dtb: Address) -> Self {
+        Self {
+            arch,
+            dtb,
+            windows: false,
+            layer_class: None,
+            kernel_base: None,
+            kdbg: None,
+            kernel_version: None,
+            memory_layer: None,
+            ranges: vec![],
+        }
+    }
+
+    /// Uses the Windows variants of the Intel layers, which are required by the Windows plugins.
+    pub fn windows(mut self, windows: bool) -> Self {
+        self.windows = windows;
+        self
+    }
+
+    /// Overrides the translation layer class derived from the architecture.
+    pub fn layer_class(mut self, class: impl Into<String>) -> Self {
+        self.layer_class = Some(class.into());
+        self
+    }
+
+    /// Sets the virtual base address of the kernel image.
+    pub fn kernel_base(mut self, base: Address) -> Self {
+        self.kernel_base = Some(base);
+        self
+    }
+
+    /// Sets the address of the kernel debugger block or its equivalent on other OSes.
+    pub fn kdbg(mut self, kdbg: Address) -> Self {
+        self.kdbg = Some(kdbg);
+        self
+    }
+
+    /// Sets the version string of the kernel.
+    pub fn kernel_version(mut self, version: impl Into<String>) -> Self {
+        self.kernel_version = Some(version.into());
+        self
+    }
+
+    /// Uses a flat physical memory image at `location` (an url) as memory layer.
+    pub fn image(self, location: impl Into<String>) -> Self {
+        self.memory_layer(FILE_LAYER_CLASS, location)
+    }
+
+    /// Uses a custom memory layer, e.g. the memflow layer of `memflow-ffi`.
+    pub fn memory_layer(mut self, class: impl Into<String>, location: impl Into<String>) -> Self {
+        self.memory_layer = Some((class.into(), location.into()));
+        self
+    }
+
+    /// Records the physical memory map of the target.
+    ///
+    /// For images this should be [`AcquireMetadata::ranges`](super::AcquireMetadata::ranges).
+    pub fn ranges(mut self, ranges: &[Range<Address>]) -> Self {
+        self.ranges = ranges.to_vec();
+        self
+    }
+
+    /// Returns the Volatility translation layer class for this configuration.
+    pub fn translation_layer(&self) -> Result<String> {
+        if let Some(class) = &self.layer_class {
+            return Ok(class.clone());
+        }
+
+        let layer = match self.arch {
+            ArchitectureIdent::X86(64, _) => "Intel32e",
+            ArchitectureIdent::X86(32, true) => "IntelPAE",
+            ArchitectureIdent::X86(32, false) => "Intel",
+            _ => {
+                return Err(Error(ErrorOrigin::Memory, ErrorKind::NotSupported)
+                    .log_error(format!("volatility has no layer for {:?}", self.arch)))
+            }
+        };
+
+        Ok(format!(
+            "volatility3.framework.layers.intel.{}{}",
+            if self.windows { "Windows" } else { "" },
+            layer
+        ))
+    }
+
+    /// Writes the configuration as json.
+    pub fn write(&self, out: &mut (impl Write + ?Sized)) -> Result<()> {
+        let layer = self.translation_layer()?;
+
+        self.write_inner(out, &layer).map_err(|err| {
+            Error(ErrorOrigin::Memory, ErrorKind::UnableToWriteFile)
+                .log_error(format!("unable to write the volatility config: {}", err))
+        })
+    }
+
+    fn write_inner(&self, out: &mut (impl Write + ?Sized), layer: &str) -> std::io::Result<()> {
+        let mut entries = vec![
+            (
+                "kernel.layer_name.class".to_string(),
+                format!("{:?}", layer),
+            ),
+            (
+                "kernel.layer_name.page_map_offset".to_string(),
+                self.dtb.to_umem().to_string(),
+            ),
+        ];
+
+        if let Some(base) = self.kernel_base {
+            entries.push((
+                "kernel.layer_name.kernel_virtual_offset".to_string(),
+                base.to_umem().to_string(),
+            ));
+            entries.push(("kernel.offset".to_string(), base.to_umem().to_string()));
+        }
+
+        if let Some((class, location)) = &self.memory_layer {
+            entries.push((
+                "kernel.layer_name.memory_layer.class".to_string(),
+                format!("{:?}", class),
+            ));
+            entries.push((
+                "kernel.layer_name.memory_layer.location".to_string(),
+                format!("{:?}", location),
+            ));
+            entries.push((
+                "automagic.LayerStacker.single_location".to_string(),
+                format!("{:?}", location),
+            ));
+        }
+
+        if let Some(kdbg) = self.kdbg {
+            entries.push(("memflow.kdbg".to_string(), kdbg.to_umem().to_string()));
+        }
+
+        if let Some(version) = &self.kernel_version {
+            entries.push((
+                "memflow.kernel_version".to_string(),
+                format!("{:?}", version),
+            ));
+        }
+
+        if !self.ranges.is_empty() {
+            let ranges = self
+                .ranges
+                .iter()
+                .map(|r| format!("[{}, {}]", r.start.to_umem(), r.end.to_umem()))
+                .collect::<Vec<_>>();
+            entries.push((
+                "memflow.ranges".to_string(),
+                format!("[{}]", ranges.join(", ")),
+            ));
+        }
+
+        writeln!(out, "{{")?;
+        for (i, (key, value)) in entries.iter().enumerate() {
+            let sep = if i + 1 < entries.len() { "," } else { "" };
+            writeln!(out, "  {:?}: {}{}", key, value, sep)?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_config() {
+        let mut out = vec![];
+
+        VolatilityConfig::new(ArchitectureIdent::X86(32, true), Address::from(0x185000u64))
+            .windows(true)
+            .kdbg(Address::from(0x8273_0c28u64))
+            .memory_layer("memflow_layer.MemflowLayer", "qemu")
+            .ranges(&[Address::null()..Address::from(0x9f000u64)])
+            .write(&mut out)
+            .unwrap();
+
+        let config = String::from_utf8(out).unwrap();
+        assert_eq!(
+            config,
+            "{\n  \
+             \"kernel.layer_name.class\": \"volatility3.framework.layers.intel.WindowsIntelPAE\",\n  \
+             \"kernel.layer_name.page_map_offset\": 1593344,\n  \
+             \"kernel.layer_name.memory_layer.class\": \"memflow_layer.MemflowLayer\",\n  \
+             \"kernel.layer_name.memory_layer.location\": \"qemu\",\n  \
+             \"automagic.LayerStacker.single_location\": \"qemu\",\n  \
+             \"memflow.kdbg\": 2188577832,\n  \
+             \"memflow.ranges\": [[0, 651264]]\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn unsupported_arch() {
+        let config = VolatilityConfig::new(ArchitectureIdent::AArch64(4096), Address::null());
+        assert!(config.write(&mut Vec::<u8>::new()).is_err());
+    }
+}
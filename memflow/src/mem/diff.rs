@@ -0,0 +1,336 @@
+//! Comparison of physical memory.
+//!
+//! [`diff_phys`] reads two physical memory objects side by side, e.g. two snapshots of the same
+//! target, and streams the ranges in which they differ. Memory is compared in granules of a
+//! caller defined size, adjacent differing granules are reported as a single range. Ranges that
+//! can only be read from one of the two objects are reported separately.
+//!
+//! For connectors that are able to service multiple requests at once, [`diff_phys_parallel`]
+//! splits the address space over several threads.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::diff::{diff_phys, DiffKind};
+//! use memflow::mem::PhysicalMemory;
+//! use memflow::types::{size, Address};
+//!
+//! let mut a = DummyMemory::new(size::mb(2));
+//! let mut b = DummyMemory::new(size::mb(2));
+//! b.phys_write(0x1234.into(), &1u8).unwrap();
+//!
+//! let mut diffs = vec![];
+//! diff_phys(&mut a, &mut b, size::kb(4), |diff| {
+//!     diffs.push(diff);
+//!     true
+//! })
+//! .unwrap();
+//!
+//! assert_eq!(diffs.len(), 1);
+//! assert_eq!(diffs[0].range, Address::from(0x1000)..Address::from(0x2000));
+//! assert_eq!(diffs[0].kind, DiffKind::Changed);
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::acquire::{read_chunk, DEFAULT_CHUNK_SIZE};
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address};
+
+use std::ops::Range;
+use std::thread;
+
+/// The way in which a range differs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffKind {
+    /// Both objects hold different data.
+    Changed,
+    /// The range can only be read from the first object.
+    OnlyA,
+    /// The range can only be read from the second object.
+    OnlyB,
+}
+
+/// A range of physical memory that differs between two objects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffRange {
+    pub range: Range<Address>,
+    pub kind: DiffKind,
+}
+
+/// Compares the physical memory of `a` and `b` in steps of `granularity` bytes.
+///
+/// The whole address space up to the larger of both maximum addresses is compared. Differing
+/// ranges are passed to `callback` sorted by address, returning `false` from it stops the
+/// comparison.
+pub fn diff_phys<A: PhysicalMemory, B: PhysicalMemory>(
+    a: &mut A,
+    b: &mut B,
+    granularity: usize,
+    mut callback: impl FnMut(DiffRange) -> bool,
+) -> Result<()> {
+    check_granularity(granularity)?;
+
+    let range = full_range(a, b);
+    let mut merger = Merger::new(&mut callback);
+    diff_range(a, b, range, granularity, &mut |diff| merger.push(diff))?;
+    merger.finish();
+
+    Ok(())
+}
+
+/// Compares the physical memory of `a` and `b` on `threads` threads.
+///
+/// The address space is split into `threads` contiguous parts, the first one is compared on the
+/// calling thread using `a` and `b` directly, all others on spawned threads using clones of
+/// them. The differing ranges are passed to `callback` in the same order as by [`diff_phys`],
+/// once all parts have been compared.
+pub fn diff_phys_parallel<A, B>(
+    a: &mut A,
+    b: &mut B,
+    granularity: usize,
+    threads: usize,
+    mut callback: impl FnMut(DiffRange) -> bool,
+) -> Result<()>
+where
+    A: PhysicalMemory + Clone + 'static,
+    B: PhysicalMemory + Clone + 'static,
+{
+    check_granularity(granularity)?;
+
+    let range = full_range(a, b);
+    let threads = std::cmp::max(threads, 1) as umem;
+    let granules =
+        ((range.end - range.start) as umem + granularity as umem - 1) / granularity as umem;
+    let part_size = std::cmp::max((granules + threads - 1) / threads, 1) * granularity as umem;
+
+    let mut parts = vec![];
+    let mut start = range.start;
+    while start < range.end {
+        let end = std::cmp::min(start + part_size, range.end);
+        parts.push(start..end);
+        start = end;
+    }
+
+    let mut parts = parts.into_iter();
+    let first = parts.next();
+
+    let handles = parts
+        .map(|part| {
+            let (mut a, mut b) = (a.clone(), b.clone());
+            thread::spawn(move || {
+                let mut diffs = vec![];
+                diff_range(&mut a, &mut b, part, granularity, &mut |diff| {
+                    diffs.push(diff);
+                    true
+                })?;
+                Ok(diffs)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut merger = Merger::new(&mut callback);
+
+    if let Some(part) = first {
+        diff_range(a, b, part, granularity, &mut |diff| merger.push(diff))?;
+    }
+
+    for handle in handles {
+        let diffs = handle.join().map_err(|_| {
+            Error(ErrorOrigin::Memory, ErrorKind::Unknown).log_error("memory diff thread panicked")
+        })??;
+
+        if !diffs.into_iter().all(|diff| merger.push(diff)) {
+            break;
+        }
+    }
+
+    merger.finish();
+
+    Ok(())
+}
+
+fn check_granularity(granularity: usize) -> Result<()> {
+    if granularity == 0 {
+        Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+            .log_error("the diff granularity must not be zero"))
+    } else {
+        Ok(())
+    }
+}
+
+fn full_range<A: PhysicalMemory, B: PhysicalMemory>(a: &A, b: &B) -> Range<Address> {
+    let max_address = std::cmp::max(a.metadata().max_address, b.metadata().max_address);
+    Address::null()..max_address + 1
+}
+
+/// Compares `range` chunk by chunk, returns `false` if `out` stopped the comparison.
+fn diff_range<A: PhysicalMemory, B: PhysicalMemory>(
+    a: &mut A,
+    b: &mut B,
+    range: Range<Address>,
+    granularity: usize,
+    out: &mut impl FnMut(DiffRange) -> bool,
+) -> Result<bool> {
+    let chunk_size = std::cmp::max(DEFAULT_CHUNK_SIZE / granularity, 1) * granularity;
+    let mut buf_a = vec![0u8; chunk_size];
+    let mut buf_b = vec![0u8; chunk_size];
+
+    let mut base = range.start;
+    while base < range.end {
+        let len = std::cmp::min(chunk_size as umem, (range.end - base) as umem) as usize;
+
+        let (mut failed_a, mut failed_b) = (vec![], vec![]);
+        read_chunk(a, base.to_umem(), &mut buf_a[..len], &mut failed_a)?;
+        read_chunk(b, base.to_umem(), &mut buf_b[..len], &mut failed_b)?;
+
+        let (mut cursor_a, mut cursor_b) = (0, 0);
+        for (i, (data_a, data_b)) in buf_a[..len]
+            .chunks(granularity)
+            .zip(buf_b[..len].chunks(granularity))
+            .enumerate()
+        {
+            let start = base + i * granularity;
+            let end = start + data_a.len();
+
+            let kind = match (
+                is_readable(&failed_a, &mut cursor_a, start, end),
+                is_readable(&failed_b, &mut cursor_b, start, end),
+            ) {
+                (true, true) if data_a != data_b => DiffKind::Changed,
+                (true, false) => DiffKind::OnlyA,
+                (false, true) => DiffKind::OnlyB,
+                _ => continue,
+            };
+
+            if !out(DiffRange {
+                range: start..end,
+                kind,
+            }) {
+                return Ok(false);
+            }
+        }
+
+        base += len;
+    }
+
+    Ok(true)
+}
+
+/// Checks whether `start..end` does not overlap with any of the sorted `failed` ranges.
+///
+/// `cursor` is advanced past the ranges ending before `start`, so that ascending granules only
+/// walk the list once.
+fn is_readable(
+    failed: &[Range<Address>],
+    cursor: &mut usize,
+    start: Address,
+    end: Address,
+) -> bool {
+    while *cursor < failed.len() && failed[*cursor].end <= start {
+        *cursor += 1;
+    }
+    *cursor >= failed.len() || failed[*cursor].start >= end
+}
+
+/// Merges adjacent ranges of the same kind before passing them on.
+struct Merger<'a, F> {
+    callback: &'a mut F,
+    pending: Option<DiffRange>,
+    stopped: bool,
+}
+
+impl<'a, F: FnMut(DiffRange) -> bool> Merger<'a, F> {
+    fn new(callback: &'a mut F) -> Self {
+        Self {
+            callback,
+            pending: None,
+            stopped: false,
+        }
+    }
+
+    fn push(&mut self, diff: DiffRange) -> bool {
+        if self.stopped {
+            return false;
+        }
+
+        match self.pending.as_mut() {
+            Some(pending) if pending.kind == diff.kind && pending.range.end == diff.range.start => {
+                pending.range.end = diff.range.end;
+                true
+            }
+            _ => {
+                if let Some(pending) = self.pending.replace(diff) {
+                    self.stopped = !(self.callback)(pending);
+                }
+                !self.stopped
+            }
+        }
+    }
+
+    fn finish(mut self) {
+        if self.stopped {
+            return;
+        }
+
+        if let Some(pending) = self.pending.take() {
+            (self.callback)(pending);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    fn collect(parallel: bool, a: &mut DummyMemory, b: &mut DummyMemory) -> Vec<DiffRange> {
+        let mut diffs = vec![];
+        let callback = |diff| {
+            diffs.push(diff);
+            true
+        };
+        if parallel {
+            diff_phys_parallel(a, b, 0x100, 3, callback).unwrap();
+        } else {
+            diff_phys(a, b, 0x100, callback).unwrap();
+        }
+        diffs
+    }
+
+    #[test]
+    fn diff_ranges() {
+        let mut a = DummyMemory::new(size::mb(1));
+        let mut b = DummyMemory::new(size::mb(2));
+
+        // two adjacent granules and a separate one
+        b.phys_write(Address::from(0x10ff).into(), &[1u8; 2])
+            .unwrap();
+        a.phys_write(Address::from(0x8_0000).into(), &[1u8; 1])
+            .unwrap();
+
+        for &parallel in &[false, true] {
+            let diffs = collect(parallel, &mut a, &mut b);
+            assert_eq!(
+                diffs,
+                vec![
+                    DiffRange {
+                        range: Address::from(0x1000)..Address::from(0x1200),
+                        kind: DiffKind::Changed,
+                    },
+                    DiffRange {
+                        range: Address::from(0x8_0000)..Address::from(0x8_0100),
+                        kind: DiffKind::Changed,
+                    },
+                    DiffRange {
+                        range: Address::from(size::mb(1))..Address::from(size::mb(2)),
+                        kind: DiffKind::OnlyB,
+                    },
+                ]
+            );
+        }
+    }
+}
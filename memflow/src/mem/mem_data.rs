@@ -1,5 +1,6 @@
 //! Generic address and buffer association structure.
 
+use crate::error::Result;
 use crate::types::{umem, Address, PageType, PhysicalAddress};
 use cglue::callback::{Callbackable, OpaqueCallback};
 use cglue::iter::CIterator;
@@ -23,6 +24,9 @@ pub type WriteData<'a> = CTup2<Address, CSliceRef<'a, u8>>;
 
 pub type VtopRange = CTup2<Address, umem>;
 
+/// Physical address range, used for prefetch hints.
+pub type PhysicalRange = CTup2<PhysicalAddress, umem>;
+
 pub type MemoryRange = CTup3<Address, umem, PageType>;
 
 pub trait WriteRawIterator<'a>: Iterator<Item = WriteDataRaw<'a>> + 'a {}
@@ -90,6 +94,48 @@ impl<'a: 'c, 'b, 'c, T: 'b, P: 'a> MemOps<'a, 'b, 'c, T, P> {
             out_fail,
         })
     }
+
+    /// Splits `iter` into multiple batches and invokes `func` for each of them.
+    ///
+    /// A batch is closed once it contains `max_elems` elements, or once adding the next element
+    /// would exceed `max_bytes` (as determined by `data_len`). Elements are never split, so a
+    /// single element larger than `max_bytes` is passed in a batch of its own.
+    ///
+    /// The limits are usually obtained via
+    /// [`PhysicalMemoryMetadata::batch_limits`](crate::mem::PhysicalMemoryMetadata::batch_limits).
+    pub fn with_raw_chunked<F: FnMut(MemOps<T, P>) -> Result<()>>(
+        iter: impl Iterator<Item = T>,
+        mut out: Option<&mut OpaqueCallback<'a, P>>,
+        mut out_fail: Option<&mut OpaqueCallback<'a, P>>,
+        (max_elems, max_bytes): (usize, umem),
+        data_len: impl Fn(&T) -> usize,
+        mut func: F,
+    ) -> Result<()> {
+        let mut iter = iter.peekable();
+
+        while iter.peek().is_some() {
+            let mut elems = 0;
+            let mut bytes: umem = 0;
+
+            let mut chunk = core::iter::from_fn(|| {
+                let len = data_len(iter.peek()?) as umem;
+                if elems > 0 && (elems >= max_elems || bytes.saturating_add(len) > max_bytes) {
+                    return None;
+                }
+                elems += 1;
+                bytes = bytes.saturating_add(len);
+                iter.next()
+            });
+
+            func(MemOps {
+                inp: (&mut chunk).into(),
+                out: out.as_deref_mut(),
+                out_fail: out_fail.as_deref_mut(),
+            })?;
+        }
+
+        Ok(())
+    }
 }
 
 impl<'a: 'c, 'b, 'c, A: 'b + Into<Address> + Copy, T: 'b, P: 'a>
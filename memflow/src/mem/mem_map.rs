@@ -105,6 +105,20 @@ impl<M: SplitAtIndex> MemoryMap<M> {
         self.mappings.iter()
     }
 
+    /// Returns the mapping containing `addr`.
+    pub fn lookup(&self, addr: Address) -> Option<&MemoryMapping<M>> {
+        let idx = self
+            .mappings
+            .partition_point(|m| m.base <= addr)
+            .checked_sub(1)?;
+        let mapping = &self.mappings[idx];
+        if addr < mapping.base + mapping.output.borrow().length() {
+            Some(mapping)
+        } else {
+            None
+        }
+    }
+
     /// Maps a linear address range to a hardware address range.
     ///
     /// Output element lengths will both match, so there is no need to do additonal clipping
@@ -0,0 +1,148 @@
+//! Reading of remote arrays and containers.
+//!
+//! The functions in this module back the array helpers of [`MemoryView`]. Element counts are
+//! checked before any memory is allocated, so corrupted or hostile counts read from the target
+//! result in errors instead of huge allocations.
+
+use super::remote_struct::{pod_zeroed, ptr_from_bytes, ptr_size};
+use super::*;
+
+use std::convert::TryInto;
+use std::mem::size_of;
+use std::prelude::v1::*;
+
+/// Returns an error if `count` elements of `T` at `addr` do not fit into the address space.
+fn check_range<T>(addr: Address, count: usize) -> Result<()> {
+    count
+        .checked_mul(size_of::<T>())
+        .and_then(|len| addr.to_umem().checked_add(len as umem))
+        .map(|_| ())
+        .ok_or_else(|| {
+            Error(ErrorOrigin::VirtualMemory, ErrorKind::OutOfBounds)
+                .log_error("remote array exceeds the address space")
+        })
+}
+
+pub(crate) fn read_vec<T: Pod, M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    count: usize,
+) -> PartialResult<Vec<T>> {
+    check_range::<T>(addr, count)?;
+
+    let mut out = (0..count).map(|_| pod_zeroed::<T>()).collect::<Vec<_>>();
+    match mem.read_into(addr, out.as_mut_slice()) {
+        Ok(_) => Ok(out),
+        Err(PartialError::Error(err)) => Err(PartialError::Error(err)),
+        Err(_) => Err(PartialError::PartialVirtualRead(out)),
+    }
+}
+
+pub(crate) fn read_counted_vec<C, T, M>(
+    mem: &mut M,
+    count_addr: Address,
+    data_addr: Address,
+    max: usize,
+) -> PartialResult<Vec<T>>
+where
+    C: Pod + TryInto<usize>,
+    T: Pod,
+    M: MemoryView,
+{
+    let count = mem
+        .read::<C>(count_addr)
+        .data()?
+        .try_into()
+        .ok()
+        .filter(|&count| count <= max)
+        .ok_or_else(|| {
+            Error(ErrorOrigin::VirtualMemory, ErrorKind::OutOfBounds)
+                .log_error("remote array count exceeds the maximum")
+        })?;
+
+    read_vec(mem, data_addr, count)
+}
+
+pub(crate) fn read_ptr_array<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    max: usize,
+) -> PartialResult<Vec<Address>> {
+    let meta = mem.metadata();
+    let ptr_size = ptr_size(&meta);
+    check_range::<u8>(addr, max.saturating_mul(ptr_size))?;
+
+    let mut out = vec![];
+    let mut buf = vec![0; size::kb(4)];
+
+    while out.len() < max {
+        // entries behind the terminator may be unreadable, never read across a page boundary
+        let cur = addr + out.len() * ptr_size;
+        let page_left = (size::kb(4) - (cur.to_umem() % size::kb(4) as umem) as usize) / ptr_size;
+        let n = std::cmp::min(std::cmp::max(page_left, 1), max - out.len());
+
+        let chunk = &mut buf[..n * ptr_size];
+        mem.read_raw_into(cur, chunk).data()?;
+
+        for bytes in chunk.chunks_exact(ptr_size) {
+            let mut raw = [0; 8];
+            raw[..ptr_size].copy_from_slice(bytes);
+
+            let ptr = ptr_from_bytes(&raw, &meta);
+            if ptr.is_null() {
+                return Ok(out);
+            }
+            out.push(ptr);
+        }
+    }
+
+    Err(PartialError::Error(Error(
+        ErrorOrigin::VirtualMemory,
+        ErrorKind::OutOfBounds,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    #[test]
+    fn counted_arrays() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write(base, &3u32).unwrap();
+        proc.write(base + 4usize, &[10u16, 20, 30]).unwrap();
+
+        assert_eq!(
+            proc.read_vec::<u16>(base + 4usize, 3).unwrap(),
+            vec![10, 20, 30]
+        );
+        assert_eq!(
+            proc.read_counted_vec::<u32, u16>(base, base + 4usize, 16)
+                .unwrap(),
+            vec![10, 20, 30]
+        );
+        assert!(proc
+            .read_counted_vec::<u32, u16>(base, base + 4usize, 2)
+            .is_err());
+        assert!(proc.read_vec::<u64>(base, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn null_terminated_ptrs() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // the terminator is the first entry of the next page
+        let array = base + 0xfe8usize;
+        let ptrs = [base + 0x100usize, base + 0x200usize, base + 0x300usize];
+        for (i, ptr) in ptrs.iter().enumerate() {
+            proc.write(array + i * 8, &ptr.to_umem()).unwrap();
+        }
+
+        assert_eq!(proc.read_ptr_array(array, 16).unwrap(), ptrs.to_vec());
+        assert!(proc.read_ptr_array(array, 2).is_err());
+    }
+}
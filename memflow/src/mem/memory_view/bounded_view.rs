@@ -0,0 +1,202 @@
+//! Range restricted memory view.
+use super::*;
+
+use rangemap::RangeSet;
+
+/// Memory view that only allows accesses within a set of whitelisted ranges.
+///
+/// Any read or write operation that touches memory outside of the allowed ranges is rejected
+/// as a whole with an `ErrorKind::AccessDenied` error, before any memory is accessed.
+/// Optionally the view can be made read-only, rejecting all writes.
+///
+/// This is useful for sandboxing analysis code, for example by restricting it to a single
+/// module, and to prevent accidental writes to the wrong region on live targets.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::dummy::DummyOs;
+/// use memflow::error::ErrorKind;
+/// use memflow::mem::{BoundedView, MemoryView};
+/// use memflow::os::Process;
+/// use memflow::types::size;
+///
+/// let proc = DummyOs::quick_process(size::mb(2), &[]);
+/// let base = proc.info().address;
+///
+/// let mut view = BoundedView::new(proc).with_range(base, size::kb(4) as _);
+///
+/// assert!(view.read::<u64>(base).is_ok());
+/// assert!(view.read::<u64>(base + size::kb(4)).is_err());
+/// ```
+#[derive(Clone)]
+pub struct BoundedView<T> {
+    mem: T,
+    ranges: RangeSet<Address>,
+    readonly: bool,
+}
+
+impl<T: MemoryView> BoundedView<T> {
+    /// Creates a new view that does not allow any accesses until ranges are added.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            ranges: RangeSet::new(),
+            readonly: false,
+        }
+    }
+
+    /// Allows accesses to the range `addr..addr + size`.
+    pub fn with_range(mut self, addr: Address, size: umem) -> Self {
+        self.allow_range(addr, size);
+        self
+    }
+
+    /// Rejects all writes.
+    pub fn readonly(mut self) -> Self {
+        self.readonly = true;
+        self
+    }
+
+    /// Allows accesses to the range `addr..addr + size`.
+    pub fn allow_range(&mut self, addr: Address, size: umem) {
+        if size > 0 {
+            self.ranges.insert(addr..addr + size);
+        }
+    }
+
+    /// Returns `true` if the range `addr..addr + len` is completely allowed.
+    pub fn is_allowed(&self, addr: Address, len: usize) -> bool {
+        len == 0 || self.ranges.gaps(&(addr..addr + len)).next().is_none()
+    }
+
+    /// Consumes the view, returning the underlying memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    fn check_all<'a, D, I: Iterator<Item = &'a CTup3<Address, Address, D>>>(
+        &self,
+        mut iter: I,
+        len: impl Fn(&D) -> usize,
+    ) -> Result<()>
+    where
+        D: 'a,
+    {
+        // the second element is caller supplied metadata, only the real address is checked
+        match iter.find(|CTup3(addr, _, data)| !self.is_allowed(*addr, len(data))) {
+            Some(CTup3(addr, _, _)) => Err(Error(ErrorOrigin::Memory, ErrorKind::AccessDenied)
+                .log_warn(format!("denied memory access at {:x}", addr))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: MemoryView> MemoryView for BoundedView<T> {
+    fn read_raw_iter(&mut self, MemOps { inp, out, out_fail }: ReadRawMemOps) -> Result<()> {
+        let ops = inp.collect::<Vec<_>>();
+        self.check_all(ops.iter(), |d| d.len())?;
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(ops.into_iter(), out, out_fail, |data| {
+            mem.read_raw_iter(data)
+        })
+    }
+
+    fn write_raw_iter(&mut self, MemOps { inp, out, out_fail }: WriteRawMemOps) -> Result<()> {
+        if self.readonly {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::ReadOnly));
+        }
+
+        let ops = inp.collect::<Vec<_>>();
+        self.check_all(ops.iter(), |d| d.len())?;
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(ops.into_iter(), out, out_fail, |data| {
+            mem.write_raw_iter(data)
+        })
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        let metadata = self.mem.metadata();
+        MemoryViewMetadata {
+            readonly: metadata.readonly || self.readonly,
+            ..metadata
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    #[test]
+    fn bounded_access() {
+        let proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let mut view = BoundedView::new(proc)
+            .with_range(base, 0x100)
+            .with_range(base + 0x100usize, 0x100);
+
+        assert!(view.write(base + 0x1f8usize, &1u64).is_ok());
+        assert_eq!(view.read::<u64>(base + 0x1f8usize), Ok(1));
+
+        assert_eq!(
+            view.write(base + 0x1fcusize, &1u64),
+            Err(PartialError::Error(Error(
+                ErrorOrigin::Memory,
+                ErrorKind::AccessDenied
+            )))
+        );
+
+        // nothing of the batch is written if a single element is denied
+        let res = view.write_raw_list(&[
+            CTup2(base, [2u8; 8][..].into()),
+            CTup2(base + 0x300usize, [2u8; 8][..].into()),
+        ]);
+        assert!(res.is_err());
+        assert_eq!(view.read::<u64>(base), Ok(0));
+    }
+
+    #[test]
+    fn checks_real_address() {
+        let proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let mut view = BoundedView::new(proc).with_range(base, 0x100);
+
+        let mut read = |addr: Address, meta: Address| {
+            let mut buf = [0u8; 8];
+            let ops = core::iter::once(CTup3(addr, meta, (&mut buf[..]).into()));
+            MemOps::with_raw(ops, None, None, |data| view.read_raw_iter(data))
+        };
+
+        assert!(read(base, base + 0x1000usize).is_ok());
+        assert_eq!(
+            read(base + 0x1000usize, base),
+            Err(Error(ErrorOrigin::Memory, ErrorKind::AccessDenied))
+        );
+    }
+
+    #[test]
+    fn readonly_view() {
+        let proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let mut view = BoundedView::new(proc)
+            .with_range(base, size::kb(4) as umem)
+            .readonly();
+
+        assert!(view.metadata().readonly);
+        assert!(view.read::<u64>(base).is_ok());
+        assert_eq!(
+            view.write(base, &1u64),
+            Err(PartialError::Error(Error(
+                ErrorOrigin::Memory,
+                ErrorKind::ReadOnly
+            )))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Copying of memory between two memory views.
+use super::*;
+
+use core::ops::Range;
+
+/// Default size of the intermediate buffer used by [`copy`].
+pub const COPY_BUFFER_SIZE: usize = size::mb(1);
+
+/// Size of the individual elements of each scatter batch.
+const COPY_ELEMENT_SIZE: usize = size::kb(4);
+
+/// Copies `len` bytes from `src_addr` in `src` to `dst_addr` in `dst`.
+///
+/// The data is streamed through a buffer of [`COPY_BUFFER_SIZE`] bytes. Each buffer is read as
+/// a single scatter batch of page sized elements, and only the successfully read parts are
+/// written to the destination in a single batch again.
+///
+/// If parts of the source could not be read they are skipped and a `PartialVirtualRead` error
+/// is returned after the copy finished. If parts of the destination could not be written a
+/// `PartialVirtualWrite` error is returned.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::dummy::DummyOs;
+/// use memflow::mem::{self, MemoryView};
+/// use memflow::os::Process;
+/// use memflow::types::size;
+///
+/// let mut src = DummyOs::quick_process(size::mb(2), &[1, 2, 3, 4]);
+/// let mut dst = DummyOs::quick_process(size::mb(2), &[]);
+/// let (src_base, dst_base) = (src.info().address, dst.info().address);
+///
+/// mem::copy(&mut src, src_base, &mut dst, dst_base, 4).unwrap();
+///
+/// assert_eq!(dst.read::<[u8; 4]>(dst_base).unwrap(), [1, 2, 3, 4]);
+/// ```
+pub fn copy<S: MemoryView, D: MemoryView>(
+    src: &mut S,
+    src_addr: Address,
+    dst: &mut D,
+    dst_addr: Address,
+    len: umem,
+) -> PartialResult<()> {
+    copy_with_buffer(src, src_addr, dst, dst_addr, len, COPY_BUFFER_SIZE)
+}
+
+/// Copies `len` bytes between two memory views using a buffer of `buffer_size` bytes.
+///
+/// See [`copy`] for details.
+pub fn copy_with_buffer<S: MemoryView, D: MemoryView>(
+    src: &mut S,
+    src_addr: Address,
+    dst: &mut D,
+    dst_addr: Address,
+    len: umem,
+    buffer_size: usize,
+) -> PartialResult<()> {
+    let mut buf = vec![0u8; core::cmp::min(len, core::cmp::max(buffer_size, 1) as umem) as usize];
+
+    let mut partial_read = false;
+    let mut partial_write = false;
+
+    let mut offset: umem = 0;
+    while offset < len {
+        let chunk_len = core::cmp::min(len - offset, buf.len() as umem) as usize;
+        let chunk = &mut buf[..chunk_len];
+        let chunk_src = src_addr + offset;
+
+        let mut failed: Vec<Range<usize>> = vec![];
+
+        {
+            let iter = chunk
+                .chunks_mut(COPY_ELEMENT_SIZE)
+                .enumerate()
+                .map(|(i, data)| CTup2(chunk_src + i * COPY_ELEMENT_SIZE, data.into()));
+
+            let callback = &mut |CTup2(addr, data): ReadData| {
+                let start = (addr - chunk_src) as usize;
+                failed.push(start..start + data.len());
+                true
+            };
+
+            src.read_iter(iter, None, Some(&mut callback.into()))?;
+        }
+
+        if !failed.is_empty() {
+            partial_read = true;
+            failed.sort_by_key(|r| r.start);
+        }
+
+        // collect all successfully read runs
+        let mut writes = vec![];
+        let mut run_start = 0;
+        for fail in failed
+            .iter()
+            .chain(core::iter::once(&(chunk_len..chunk_len)))
+        {
+            if fail.start > run_start {
+                writes.push(CTup2(
+                    dst_addr + offset + run_start,
+                    (&chunk[run_start..fail.start]).into(),
+                ));
+            }
+            run_start = core::cmp::max(run_start, fail.end);
+        }
+
+        match dst.write_raw_list(&writes) {
+            Ok(_) => {}
+            Err(PartialError::Error(e)) => return Err(e.into()),
+            Err(_) => partial_write = true,
+        }
+
+        offset += chunk_len as umem;
+    }
+
+    if partial_read {
+        Err(PartialError::PartialVirtualRead(()))
+    } else if partial_write {
+        Err(PartialError::PartialVirtualWrite(()))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    #[test]
+    fn copy_between_processes() {
+        let data = (0..0x5000u32).map(|i| (i % 255) as u8).collect::<Vec<_>>();
+        let mut src = DummyOs::quick_process(size::mb(2), &data);
+        let mut dst = DummyOs::quick_process(size::mb(2), &[]);
+        let (src_base, dst_base) = (src.info().address, dst.info().address);
+
+        copy_with_buffer(
+            &mut src,
+            src_base + 0x10usize,
+            &mut dst,
+            dst_base,
+            0x4000,
+            0x1800,
+        )
+        .unwrap();
+
+        let out = dst.read_raw(dst_base, 0x4000).unwrap();
+        assert_eq!(out, &data[0x10..0x4010]);
+    }
+
+    #[test]
+    fn copy_skips_unreadable() {
+        let mut src = DummyOs::quick_process(size::mb(2), &[0xff; 0x1000]);
+        let mut dst = DummyOs::quick_process(size::mb(2), &[0xaa; 0x2000]);
+        let (src_base, dst_base) = (src.info().address, dst.info().address);
+
+        let res = copy(&mut src, src_base - 0x1000usize, &mut dst, dst_base, 0x2000);
+        assert_eq!(res, Err(PartialError::PartialVirtualRead(())));
+
+        let out = dst.read_raw(dst_base, 0x2000).unwrap();
+        assert!(out[..0x1000].iter().all(|&b| b == 0xaa));
+        assert!(out[0x1000..].iter().all(|&b| b == 0xff));
+    }
+}
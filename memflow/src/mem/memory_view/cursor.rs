@@ -79,6 +79,7 @@ use crate::types::{umem, Address};
 pub struct MemoryCursor<T> {
     mem: T,
     address: Address,
+    region: Option<(Address, umem)>,
 }
 
 impl<T: MemoryView> MemoryCursor<T> {
@@ -127,6 +128,7 @@ impl<T: MemoryView> MemoryCursor<T> {
         Self {
             mem,
             address: Address::NULL,
+            region: None,
         }
     }
 
@@ -173,7 +175,65 @@ impl<T: MemoryView> MemoryCursor<T> {
     /// let mut cursor = MemoryCursor::at(virt_mem, 0x1000.into());
     /// ```
     pub fn at(mem: T, address: Address) -> Self {
-        Self { mem, address }
+        Self {
+            mem,
+            address,
+            region: None,
+        }
+    }
+
+    /// Creates a new MemoryCursor that is restricted to the region `base..base + size`.
+    ///
+    /// All positions of the cursor are relative to `base`. Reads and writes are truncated
+    /// at the end of the region, and reading at the end of the region returns `0` bytes,
+    /// which allows the cursor to be passed to parsers expecting a regular file.
+    ///
+    /// Cursor initial position is `base`.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use std::io::{Read, Seek, SeekFrom};
+    ///
+    /// use memflow::dummy::DummyOs;
+    /// use memflow::mem::MemoryCursor;
+    /// use memflow::os::Process;
+    /// use memflow::types::size;
+    ///
+    /// let mut proc = DummyOs::quick_process(size::mb(2), &[1, 2, 3, 4]);
+    /// let base = proc.info().address;
+    ///
+    /// let mut cursor = MemoryCursor::region(proc, base, 4);
+    ///
+    /// let mut buf = vec![];
+    /// cursor.read_to_end(&mut buf).unwrap();
+    /// assert_eq!(buf, [1, 2, 3, 4]);
+    ///
+    /// assert_eq!(cursor.seek(SeekFrom::End(-1)).unwrap(), 3);
+    /// ```
+    pub fn region(mem: T, base: Address, size: umem) -> Self {
+        Self {
+            mem,
+            address: base,
+            region: Some((base, size)),
+        }
+    }
+
+    /// Returns the region this cursor is restricted to, if any.
+    pub fn region_bounds(&self) -> Option<(Address, umem)> {
+        self.region
+    }
+
+    /// Returns the amount of bytes that can be accessed at the current address.
+    fn clamp_len(&self, len: usize) -> usize {
+        match self.region {
+            Some((base, size)) => {
+                let end = base.to_umem().saturating_add(size);
+                let remaining = end.saturating_sub(self.address.to_umem());
+                core::cmp::min(len as umem, remaining) as usize
+            }
+            None => len,
+        }
     }
 
     /// Consumes this cursor, returning the underlying [`VirtualMemory`] object.
@@ -318,21 +378,29 @@ impl<T: MemoryView> MemoryCursor<T> {
 
 impl<T: MemoryView> Read for MemoryCursor<T> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.clamp_len(buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
         self.mem
-            .read_raw_into(self.address, buf)
+            .read_raw_into(self.address, &mut buf[..len])
             .map_err(|err| Error::new(ErrorKind::UnexpectedEof, err))?;
-        self.address = (self.address.to_umem() + buf.len() as umem).into();
-        Ok(buf.len())
+        self.address = (self.address.to_umem() + len as umem).into();
+        Ok(len)
     }
 }
 
 impl<T: MemoryView> Write for MemoryCursor<T> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = self.clamp_len(buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
         self.mem
-            .write_raw(self.address, buf)
+            .write_raw(self.address, &buf[..len])
             .map_err(|err| Error::new(ErrorKind::UnexpectedEof, err))?;
-        self.address = (self.address.to_umem() + buf.len() as umem).into();
-        Ok(buf.len())
+        self.address = (self.address.to_umem() + len as umem).into();
+        Ok(len)
     }
 
     fn flush(&mut self) -> Result<()> {
@@ -342,6 +410,25 @@ impl<T: MemoryView> Write for MemoryCursor<T> {
 
 impl<T: MemoryView> Seek for MemoryCursor<T> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        if let Some((base, size)) = self.region {
+            let target_pos = match pos {
+                SeekFrom::Start(offs) => Some(offs as umem),
+                SeekFrom::End(offs) => checked_offset(size, offs),
+                SeekFrom::Current(offs) => {
+                    checked_offset(self.address.to_umem().wrapping_sub(base.to_umem()), offs)
+                }
+            }
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "invalid seek to a negative or overflowing position",
+                )
+            })?;
+
+            self.address = base + target_pos;
+            return Ok(target_pos as u64);
+        }
+
         let target_pos = match pos {
             SeekFrom::Start(offs) => offs,
             // TODO: do we need +1?
@@ -360,6 +447,14 @@ impl<T: MemoryView> Seek for MemoryCursor<T> {
     }
 }
 
+fn checked_offset(pos: umem, offs: i64) -> Option<umem> {
+    if offs >= 0 {
+        pos.checked_add(offs as umem)
+    } else {
+        pos.checked_sub(offs.unsigned_abs() as umem)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -505,4 +600,59 @@ mod tests {
         assert_eq!(cursor.read(&mut read_buf).unwrap(), 4); // read 4 bytes from the 512th byte
         assert_eq!(read_buf, write_buf); // compare buffers
     }
+
+    #[test]
+    fn region_read_eof() {
+        let (virt_mem, virt_base) = dummy_virt_mem();
+        let mut cursor = MemoryCursor::region(virt_mem, virt_base + 0x10usize, 8);
+
+        let write_buf = [0xAu8; 16];
+        assert_eq!(cursor.write(&write_buf).unwrap(), 8); // write is truncated at the region end
+        assert_eq!(cursor.write(&write_buf).unwrap(), 0);
+
+        assert_eq!(cursor.seek(SeekFrom::Start(4)).unwrap(), 4);
+        let mut read_buf = [0u8; 16];
+        assert_eq!(cursor.read(&mut read_buf).unwrap(), 4); // read up to the region end
+        assert_eq!(cursor.read(&mut read_buf).unwrap(), 0); // eof
+
+        let mut all = vec![];
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(cursor.read_to_end(&mut all).unwrap(), 8);
+        assert_eq!(all, [0xAu8; 8]);
+    }
+
+    #[test]
+    fn region_parse() {
+        let (mut virt_mem, virt_base) = dummy_virt_mem();
+        virt_mem.write(virt_base + 0x3cusize, &0x80u32).unwrap();
+        virt_mem
+            .write_raw(virt_base + 0x80usize, b"PE\0\0")
+            .unwrap();
+
+        // follow e_lfanew like a PE parser would
+        let mut cursor = virt_mem.into_cursor_region(virt_base, 0x1000);
+        let mut lfanew = [0u8; 4];
+        cursor.seek(SeekFrom::Start(0x3c)).unwrap();
+        cursor.read_exact(&mut lfanew).unwrap();
+
+        let mut signature = [0u8; 4];
+        cursor
+            .seek(SeekFrom::Start(u32::from_le_bytes(lfanew) as u64))
+            .unwrap();
+        cursor.read_exact(&mut signature).unwrap();
+        assert_eq!(&signature, b"PE\0\0");
+    }
+
+    #[test]
+    fn region_seek() {
+        let (virt_mem, virt_base) = dummy_virt_mem();
+        let mut cursor = MemoryCursor::region(virt_mem, virt_base, 0x100);
+
+        assert_eq!(cursor.seek(SeekFrom::Current(0)).unwrap(), 0);
+        assert_eq!(cursor.seek(SeekFrom::Current(16)).unwrap(), 16);
+        assert_eq!(cursor.seek(SeekFrom::End(-16)).unwrap(), 0x100 - 16);
+        assert_eq!(cursor.address(), virt_base + 0x100usize - 16usize);
+        assert!(cursor.seek(SeekFrom::Current(-0x1000)).is_err());
+        assert_eq!(cursor.seek(SeekFrom::Current(0)).unwrap(), 0x100 - 16);
+    }
 }
@@ -0,0 +1,79 @@
+//! Endian aware reads and writes.
+//!
+//! The functions in this module back the `read_le`/`read_be`/`read_endian` family of
+//! [`MemoryView`]. Values are byte swapped only if the requested byte order differs from the
+//! byte order of the host.
+
+use super::remote_struct::pod_zeroed;
+use super::*;
+
+fn needs_swap(little_endian: bool) -> bool {
+    little_endian != cfg!(target_endian = "little")
+}
+
+fn swapped<T: ByteSwap>(mut value: T, swap: bool) -> T {
+    if swap {
+        value.byte_swap();
+    }
+    value
+}
+
+pub(crate) fn read_endian<T: Pod + ByteSwap, M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    little_endian: bool,
+) -> PartialResult<T> {
+    let swap = needs_swap(little_endian);
+    match mem.read::<T>(addr) {
+        Ok(value) => Ok(swapped(value, swap)),
+        Err(PartialError::PartialVirtualRead(value)) => {
+            Err(PartialError::PartialVirtualRead(swapped(value, swap)))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub(crate) fn write_endian<T: Pod + ByteSwap, M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    data: &T,
+    little_endian: bool,
+) -> PartialResult<()> {
+    if !needs_swap(little_endian) {
+        return mem.write(addr, data);
+    }
+
+    let mut value = pod_zeroed::<T>();
+    value.as_bytes_mut().copy_from_slice(data.as_bytes());
+    value.byte_swap();
+    mem.write(addr, &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    #[test]
+    fn byte_order() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write_be(base, &0x1122_3344u32).unwrap();
+        assert_eq!(
+            proc.read_raw(base, 4).unwrap(),
+            vec![0x11, 0x22, 0x33, 0x44]
+        );
+        assert_eq!(proc.read_be::<u32>(base).unwrap(), 0x1122_3344);
+        assert_eq!(proc.read_le::<u32>(base).unwrap(), 0x4433_2211);
+
+        proc.write_le(base + 4usize, &0x5566u16).unwrap();
+        assert_eq!(proc.read_raw(base + 4usize, 2).unwrap(), vec![0x66, 0x55]);
+
+        // the byte order of the view is used by default
+        let mut be = proc.overlay_endianness(false);
+        assert_eq!(be.read_endian::<u32>(base).unwrap(), 0x1122_3344);
+        be.write_endian(base, &1.5f64).unwrap();
+        assert_eq!(proc.read_be::<f64>(base).unwrap(), 1.5);
+    }
+}
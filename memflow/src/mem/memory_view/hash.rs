@@ -0,0 +1,483 @@
+//! Hashing of remote memory ranges.
+//!
+//! The hashers in this module stream commonly used checksum and digest algorithms over remote
+//! memory, so that it can be hashed without materializing it locally first. CRC-32 is computed
+//! with `crc32fast` and SHA-256 with `sha2`.
+//!
+//! A single digest only tells whether a range changed. [`RangeBaseline`] keeps a digest per page
+//! instead, so that the patched pages of a module can be located by comparing the baseline
+//! against the live memory later on.
+use super::*;
+
+use core::ops::Range;
+use std::convert::TryInto;
+use std::fmt;
+
+/// Amount of bytes read at once while hashing a range.
+pub const HASH_CHUNK_SIZE: usize = size::mb(1);
+
+/// Size of the pages hashed individually by a [`RangeBaseline`].
+pub const BASELINE_PAGE_SIZE: usize = size::kb(4);
+
+/// Hashing algorithm used by [`MemoryView::hash_range`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum HashAlgo {
+    /// CRC-32 (IEEE 802.3)
+    Crc32,
+    /// SHA-256
+    Sha256,
+}
+
+impl HashAlgo {
+    /// Returns the lowercase name of this algorithm.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Crc32 => "crc32",
+            HashAlgo::Sha256 => "sha256",
+        }
+    }
+
+    /// Looks up an algorithm by its name as returned by [`HashAlgo::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "crc32" => Some(HashAlgo::Crc32),
+            "sha256" => Some(HashAlgo::Sha256),
+            _ => None,
+        }
+    }
+
+    /// Creates a new hasher for this algorithm.
+    pub fn hasher(self) -> RangeHasher {
+        match self {
+            HashAlgo::Crc32 => RangeHasher::Crc32(Crc32::new()),
+            HashAlgo::Sha256 => RangeHasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+/// Result of a hash operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Digest {
+    Crc32(u32),
+    Sha256([u8; 32]),
+}
+
+impl Digest {
+    /// Returns the algorithm that produced this digest.
+    pub fn algo(&self) -> HashAlgo {
+        match self {
+            Digest::Crc32(_) => HashAlgo::Crc32,
+            Digest::Sha256(_) => HashAlgo::Sha256,
+        }
+    }
+
+    /// Parses a digest of the given algorithm from its hex representation.
+    pub fn from_hex(algo: HashAlgo, hex: &str) -> Option<Self> {
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        match algo {
+            HashAlgo::Crc32 => Some(Digest::Crc32(u32::from_be_bytes(
+                bytes.as_slice().try_into().ok()?,
+            ))),
+            HashAlgo::Sha256 => Some(Digest::Sha256(bytes.as_slice().try_into().ok()?)),
+        }
+    }
+
+    /// Returns the digest bytes in big endian order.
+    pub fn to_vec(&self) -> Vec<u8> {
+        match self {
+            Digest::Crc32(crc) => crc.to_be_bytes().to_vec(),
+            Digest::Sha256(hash) => hash.to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.to_vec() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Streaming hasher for any of the supported algorithms.
+#[derive(Clone)]
+pub enum RangeHasher {
+    Crc32(Crc32),
+    Sha256(Sha256),
+}
+
+impl RangeHasher {
+    /// Feeds `data` into the hasher.
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            RangeHasher::Crc32(h) => h.update(data),
+            RangeHasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    /// Consumes the hasher and returns the digest.
+    pub fn finalize(self) -> Digest {
+        match self {
+            RangeHasher::Crc32(h) => Digest::Crc32(h.finalize()),
+            RangeHasher::Sha256(h) => Digest::Sha256(h.finalize()),
+        }
+    }
+}
+
+/// Streaming CRC-32 (IEEE) hasher.
+#[derive(Clone, Default)]
+pub struct Crc32 {
+    hasher: crc32fast::Hasher,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self {
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    pub fn finalize(self) -> u32 {
+        self.hasher.finalize()
+    }
+}
+
+/// Streaming SHA-256 hasher.
+#[derive(Clone, Default)]
+pub struct Sha256 {
+    hasher: sha2::Sha256,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self {
+            hasher: sha2::Digest::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(&mut self.hasher, data);
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        sha2::Digest::finalize(self.hasher).into()
+    }
+}
+
+/// Hashes `len` bytes starting at `addr` in chunks of [`HASH_CHUNK_SIZE`] bytes.
+///
+/// This is the implementation of [`MemoryView::hash_range`].
+pub fn hash_range<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    len: umem,
+    algo: HashAlgo,
+) -> PartialResult<Digest> {
+    let mut hasher = algo.hasher();
+    let mut buf = vec![0u8; core::cmp::min(len, HASH_CHUNK_SIZE as umem) as usize];
+    let mut partial = false;
+
+    let mut offset: umem = 0;
+    while offset < len {
+        let chunk = core::cmp::min(len - offset, buf.len() as umem) as usize;
+        match mem.read_raw_into(addr + offset, &mut buf[..chunk]) {
+            Ok(_) => {}
+            Err(PartialError::PartialVirtualRead(_)) => partial = true,
+            Err(e) => return Err(e),
+        }
+        hasher.update(&buf[..chunk]);
+        offset += chunk as umem;
+    }
+
+    let digest = hasher.finalize();
+    if partial {
+        Err(PartialError::PartialVirtualRead(digest))
+    } else {
+        Ok(digest)
+    }
+}
+
+/// Hashes the range at `addr` and compares it against `expected`.
+///
+/// The range is hashed with the algorithm of `expected`. If parts of the range could not be
+/// read, the comparison result is returned in a `PartialVirtualRead` error.
+///
+/// This is the implementation of [`MemoryView::verify_range`].
+pub fn verify_range<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    len: umem,
+    expected: &Digest,
+) -> PartialResult<bool> {
+    hash_range(mem, addr, len, expected.algo()).map_data(|digest| digest == *expected)
+}
+
+/// Hashes every page of the range, unreadable pages have no digest.
+fn page_digests<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    len: umem,
+    algo: HashAlgo,
+) -> Result<Vec<Option<Digest>>> {
+    let hash = |data: &[u8]| {
+        let mut hasher = algo.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    };
+
+    let mut digests = vec![];
+    let mut buf = vec![0u8; core::cmp::min(len, HASH_CHUNK_SIZE as umem) as usize];
+
+    let mut offset: umem = 0;
+    while offset < len {
+        let chunk = core::cmp::min(len - offset, buf.len() as umem) as usize;
+        let buf = &mut buf[..chunk];
+
+        match mem.read_raw_into(addr + offset, buf) {
+            Ok(_) => digests.extend(buf.chunks(BASELINE_PAGE_SIZE).map(|page| Some(hash(page)))),
+            Err(PartialError::Error(err)) => return Err(err),
+            Err(_) => {
+                // find out which of the pages are readable
+                for (i, page) in buf.chunks_mut(BASELINE_PAGE_SIZE).enumerate() {
+                    let page_addr = addr + offset + (i * BASELINE_PAGE_SIZE) as umem;
+                    digests.push(match mem.read_raw_into(page_addr, page) {
+                        Ok(_) => Some(hash(page)),
+                        Err(PartialError::Error(err)) => return Err(err),
+                        Err(_) => None,
+                    });
+                }
+            }
+        }
+
+        offset += chunk as umem;
+    }
+
+    Ok(digests)
+}
+
+/// Per page digests of a memory range, used to detect modifications of the range.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::prelude::v1::*;
+/// use memflow::mem::{HashAlgo, RangeBaseline};
+///
+/// fn patched_pages(proc: &mut (impl Process + MemoryView), module: &ModuleInfo) -> Result<()> {
+///     let baseline = RangeBaseline::module(proc, module, HashAlgo::Crc32)?;
+///
+///     // ... later on
+///     for range in baseline.compare(proc)? {
+///         println!("modified: {:x}-{:x}", range.start, range.end);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RangeBaseline {
+    addr: Address,
+    len: umem,
+    algo: HashAlgo,
+    pages: Vec<Option<Digest>>,
+}
+
+impl RangeBaseline {
+    /// Hashes every page of the `len` bytes at `addr`.
+    ///
+    /// Pages that can not be read are recorded as such, they are reported as modified once
+    /// they become readable.
+    pub fn capture<M: MemoryView>(
+        mem: &mut M,
+        addr: Address,
+        len: umem,
+        algo: HashAlgo,
+    ) -> Result<Self> {
+        Ok(Self {
+            addr,
+            len,
+            algo,
+            pages: page_digests(mem, addr, len, algo)?,
+        })
+    }
+
+    /// Hashes every page of the image of `module`.
+    pub fn module<M: MemoryView>(mem: &mut M, module: &ModuleInfo, algo: HashAlgo) -> Result<Self> {
+        Self::capture(mem, module.base, module.size, algo)
+    }
+
+    /// Returns the address of the range.
+    pub fn address(&self) -> Address {
+        self.addr
+    }
+
+    /// Returns the length of the range in bytes.
+    pub fn len(&self) -> umem {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the algorithm the pages are hashed with.
+    pub fn algo(&self) -> HashAlgo {
+        self.algo
+    }
+
+    /// Returns the digests of all pages, `None` for pages that could not be read.
+    pub fn pages(&self) -> &[Option<Digest>] {
+        &self.pages
+    }
+
+    /// Hashes the range again and returns the ranges of all pages that changed, in order.
+    ///
+    /// Adjacent modified pages are merged into a single range.
+    pub fn compare<M: MemoryView>(&self, mem: &mut M) -> Result<Vec<Range<Address>>> {
+        let current = page_digests(mem, self.addr, self.len, self.algo)?;
+        let end = self.addr + self.len;
+
+        let mut modified: Vec<Range<Address>> = vec![];
+        for (i, _) in self
+            .pages
+            .iter()
+            .zip(current.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+        {
+            let start = self.addr + (i * BASELINE_PAGE_SIZE) as umem;
+            let page_end = std::cmp::min(start + BASELINE_PAGE_SIZE, end);
+            match modified.last_mut() {
+                Some(last) if last.end == start => last.end = page_end,
+                _ => modified.push(start..page_end),
+            }
+        }
+
+        Ok(modified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    fn sha256_hex(data: &[u8]) -> String {
+        let mut h = Sha256::new();
+        h.update(data);
+        Digest::Sha256(h.finalize()).to_string()
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn sha256_streaming() {
+        let data = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut h = Sha256::new();
+        for chunk in data.chunks(7) {
+            h.update(chunk);
+        }
+
+        assert_eq!(Digest::Sha256(h.finalize()).to_string(), sha256_hex(&data));
+    }
+
+    #[test]
+    fn digest_hex_roundtrip() {
+        for digest in [Digest::Crc32(0xcbf4_3926), Digest::Sha256([0xab; 32])].iter() {
+            assert_eq!(
+                Digest::from_hex(digest.algo(), &digest.to_string()),
+                Some(*digest)
+            );
+        }
+        assert_eq!(Digest::from_hex(HashAlgo::Crc32, "cbf439"), None);
+        assert_eq!(Digest::from_hex(HashAlgo::Crc32, "cbf4392g"), None);
+    }
+
+    #[test]
+    fn crc32_vectors() {
+        let mut h = Crc32::new();
+        h.update(b"123456789");
+        assert_eq!(h.finalize(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn hash_remote_range() {
+        let data = (0..0x3000u32).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &data);
+        let base = proc.info().address;
+
+        let mut crc = Crc32::new();
+        crc.update(&data);
+
+        assert_eq!(
+            proc.hash_range(base, data.len() as umem, HashAlgo::Crc32),
+            Ok(Digest::Crc32(crc.finalize()))
+        );
+        assert_eq!(
+            proc.hash_range(base, data.len() as umem, HashAlgo::Sha256)
+                .unwrap()
+                .to_string(),
+            sha256_hex(&data)
+        );
+
+        assert!(matches!(
+            proc.hash_range(base - 0x1000usize, 0x2000, HashAlgo::Crc32),
+            Err(PartialError::PartialVirtualRead(_))
+        ));
+    }
+
+    #[test]
+    fn baseline_detects_patches() {
+        let data = (0..0x5000u32).map(|i| (i * 3) as u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &data);
+        let base = proc.info().address;
+
+        let digest = proc.hash_range(base, 0x5000, HashAlgo::Sha256).unwrap();
+        let baseline = RangeBaseline::capture(&mut proc, base, 0x4800, HashAlgo::Crc32).unwrap();
+        assert_eq!(baseline.pages().len(), 5);
+        assert!(baseline.compare(&mut proc).unwrap().is_empty());
+
+        // patch the second and third page as well as the trailing half page
+        proc.write_raw(base + 0x1ffcusize, &[0xcc; 8]).unwrap();
+        proc.write_raw(base + 0x47f0usize, &[0x90]).unwrap();
+
+        assert_eq!(proc.verify_range(base, 0x5000, &digest), Ok(false));
+        assert_eq!(
+            baseline.compare(&mut proc).unwrap(),
+            vec![
+                base + 0x1000usize..base + 0x3000usize,
+                base + 0x4000usize..base + 0x4800usize
+            ]
+        );
+    }
+}
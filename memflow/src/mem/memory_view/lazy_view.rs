@@ -0,0 +1,304 @@
+//! Lazily paged local copy of a remote memory region.
+use super::*;
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// A locally loaded page of the region.
+struct LoadedPage {
+    data: Box<[u8]>,
+    failed: bool,
+}
+
+/// Lazily paged view of a remote memory region.
+///
+/// The view exposes the region `base..base + len` as a local byte container. Page sized chunks
+/// of the region are read from the underlying memory on first access and are kept locally
+/// afterwards. Multiple missing pages touched by a single access are read in one batch. Only the
+/// pages that were accessed are stored, so the view can span regions much larger than the
+/// memory available locally.
+///
+/// This is useful for algorithms that require random access to a large region (disassemblers,
+/// regex engines, parsers) but typically only touch a small part of it.
+///
+/// Pages that could not be read are zero filled. Use [`LazyView::is_valid`] to check whether
+/// a range was read successfully.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::dummy::DummyOs;
+/// use memflow::mem::LazyView;
+/// use memflow::os::Process;
+/// use memflow::types::size;
+///
+/// let proc = DummyOs::quick_process(size::mb(2), &[0xde, 0xad, 0xbe, 0xef]);
+/// let base = proc.info().address;
+///
+/// let mut view = LazyView::new(proc, base, size::mb(1));
+///
+/// assert_eq!(view.get(1), Some(0xad));
+/// assert_eq!(view.slice(0..4), Some(&[0xde, 0xad, 0xbe, 0xef][..]));
+/// assert_eq!(view.get(size::mb(1)), None);
+/// ```
+pub struct LazyView<T> {
+    mem: T,
+    base: Address,
+    len: usize,
+    page_size: usize,
+    /// Loaded pages, indexed by their page number.
+    pages: BTreeMap<usize, LoadedPage>,
+    /// Contiguous copy of the pages returned by the last access spanning multiple pages.
+    scratch: Vec<u8>,
+}
+
+impl<T: MemoryView> LazyView<T> {
+    /// Creates a new lazy view over `base..base + len` using 4kb pages.
+    pub fn new(mem: T, base: Address, len: usize) -> Self {
+        Self::with_page_size(mem, base, len, size::kb(4))
+    }
+
+    /// Creates a new lazy view over `base..base + len` with a custom page size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` is zero.
+    pub fn with_page_size(mem: T, base: Address, len: usize, page_size: usize) -> Self {
+        assert!(page_size > 0, "page_size must not be zero");
+
+        Self {
+            mem,
+            base,
+            len,
+            page_size,
+            pages: BTreeMap::new(),
+            scratch: vec![],
+        }
+    }
+
+    /// Returns the remote base address of the view.
+    pub fn base(&self) -> Address {
+        self.base
+    }
+
+    /// Returns the size of the view in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the view has a length of zero.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the page size used by this view.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns the byte at offset `idx`, faulting in its page if necessary.
+    ///
+    /// Returns `None` if `idx` is out of bounds.
+    pub fn get(&mut self, idx: usize) -> Option<u8> {
+        self.slice(idx..idx.checked_add(1)?).map(|s| s[0])
+    }
+
+    /// Returns the bytes in `range`, faulting in all missing pages of the range at once.
+    ///
+    /// Ranges within a single page are returned directly, ranges spanning multiple pages are
+    /// copied into a contiguous buffer first.
+    ///
+    /// Returns `None` if the range is out of bounds.
+    pub fn slice(&mut self, range: Range<usize>) -> Option<&[u8]> {
+        if range.start > range.end || range.end > self.len() {
+            return None;
+        }
+
+        if range.is_empty() {
+            return Some(&[]);
+        }
+
+        let pages = self.page_range(range.clone());
+        self.fault_in(pages.clone());
+
+        let offset = pages.start * self.page_size;
+        let range = range.start - offset..range.end - offset;
+
+        if pages.len() == 1 {
+            self.pages.get(&pages.start).map(|page| &page.data[range])
+        } else {
+            self.scratch.clear();
+            for (_, page) in self.pages.range(pages) {
+                self.scratch.extend_from_slice(&page.data);
+            }
+            Some(&self.scratch[range])
+        }
+    }
+
+    /// Returns the entire region, faulting in all missing pages.
+    pub fn bytes(&mut self) -> &[u8] {
+        let len = self.len();
+        self.slice(0..len).unwrap_or_default()
+    }
+
+    /// Returns `true` if all already loaded pages touched by `range` were read successfully.
+    ///
+    /// Pages that were not accessed yet are not taken into account. Ranges that are out of
+    /// bounds are never valid.
+    pub fn is_valid(&self, range: Range<usize>) -> bool {
+        if range.start > range.end || range.end > self.len() {
+            return false;
+        }
+
+        self.page_range(range)
+            .all(|p| self.pages.get(&p).map_or(true, |page| !page.failed))
+    }
+
+    /// Drops all locally cached pages so they are read again on the next access.
+    pub fn invalidate(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Gets a mutable reference to the underlying memory object.
+    ///
+    /// Writes to the memory object are not reflected in already loaded pages,
+    /// see [`LazyView::invalidate`].
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.mem
+    }
+
+    /// Consumes the view, returning the underlying memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    fn page_range(&self, range: Range<usize>) -> Range<usize> {
+        if range.start >= range.end {
+            0..0
+        } else {
+            range.start / self.page_size..(range.end - 1) / self.page_size + 1
+        }
+    }
+
+    fn fault_in(&mut self, pages: Range<usize>) {
+        let len = self.len;
+        let page_size = self.page_size;
+        let loaded = &self.pages;
+
+        let mut missing = pages
+            .filter(|p| !loaded.contains_key(p))
+            .map(|p| {
+                let start = p * page_size;
+                let page_len = core::cmp::min(page_size, len - start);
+                (p, vec![0; page_len].into_boxed_slice())
+            })
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() {
+            return;
+        }
+
+        let base = self.base;
+        let mut failed = vec![];
+
+        let iter = missing
+            .iter_mut()
+            .map(|(p, data)| CTup2(base + *p * page_size, (&mut **data).into()));
+
+        let callback = &mut |CTup2(addr, mut d): ReadData| {
+            failed.push((addr, d.len()));
+            for v in d.iter_mut() {
+                *v = 0;
+            }
+            true
+        };
+
+        let res = self.mem.read_iter(iter, None, Some(&mut callback.into()));
+
+        for (p, data) in missing {
+            let start = base + p * page_size;
+            let end = start + data.len();
+
+            let page_failed = res.is_err()
+                || failed
+                    .iter()
+                    .any(|&(addr, len)| addr < end && addr + len > start);
+
+            self.pages.insert(
+                p,
+                LoadedPage {
+                    data,
+                    failed: page_failed,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    #[test]
+    fn lazy_read_across_pages() {
+        let buf = (0..0x3000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let proc = DummyOs::quick_process(size::mb(2), &buf);
+        let base = proc.info().address;
+
+        let mut view = LazyView::new(proc, base, buf.len());
+
+        assert_eq!(view.len(), 0x3000);
+        assert_eq!(view.get(0x1fff), Some(buf[0x1fff]));
+        assert_eq!(view.slice(0xff0..0x2010), Some(&buf[0xff0..0x2010]));
+        assert_eq!(view.bytes(), buf.as_slice());
+        assert!(view.is_valid(0..0x3000));
+
+        assert_eq!(view.get(0x3000), None);
+        assert_eq!(view.slice(0x2000..0x3001), None);
+    }
+
+    #[test]
+    fn lazy_allocation() {
+        let proc = DummyOs::quick_process(size::mb(2), &[1]);
+        let base = proc.info().address;
+
+        let mut view = LazyView::new(proc, base, size::gb(1));
+        assert_eq!(view.len(), size::gb(1));
+        assert!(view.pages.is_empty());
+
+        assert_eq!(view.get(0), Some(1));
+        assert_eq!(view.get(size::gb(1) - 1), Some(0));
+        assert_eq!(view.pages.len(), 2);
+        assert_eq!(view.pages[&0].data.len(), 0x1000);
+    }
+
+    #[test]
+    fn lazy_failed_pages() {
+        let proc = DummyOs::quick_process(size::mb(2), &[0xff; 0x1000]);
+        let base = proc.info().address;
+
+        let mut view = LazyView::new(proc, base - 0x1000usize, 0x2000);
+
+        assert_eq!(view.slice(0xffe..0x1002), Some(&[0, 0, 0xff, 0xff][..]));
+        assert!(!view.is_valid(0..0x1000));
+        assert!(view.is_valid(0x1000..0x2000));
+        assert!(!view.is_valid(0x1000..0x2001));
+    }
+
+    #[test]
+    fn lazy_invalidate() {
+        let proc = DummyOs::quick_process(size::mb(2), &[1]);
+        let base = proc.info().address;
+
+        let mut view = LazyView::new(proc, base, 0x1000);
+        assert_eq!(view.get(0), Some(1));
+
+        view.get_mut().write(base, &2u8).unwrap();
+        assert_eq!(view.get(0), Some(1));
+
+        view.invalidate();
+        assert_eq!(view.get(0), Some(2));
+    }
+}
@@ -0,0 +1,195 @@
+//! Iteration over intrusive doubly linked lists.
+//!
+//! Kernels link their objects through list entries embedded in the objects themselves, like
+//! `LIST_ENTRY` on Windows or `list_head` on Linux. Both consist of a forward and a backward
+//! pointer, each pointing to the list entry of the neighbouring object. The list starts at a
+//! separate head entry that is not part of any object.
+//!
+//! [`ListIter`] follows such a list from its head and yields the objects containing the list
+//! entries. Lists are read from live systems and may be modified concurrently or be corrupted,
+//! so the iteration stops with an error on null links, cycles that do not lead back to the head
+//! and lists longer than the configured maximum.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//!
+//! #[repr(C)]
+//! #[derive(Clone, Copy, Pod)]
+//! struct Object {
+//!     id: u64,
+//!     links: [u64; 2],
+//! }
+//!
+//! fn object_ids(mem: &mut impl MemoryView, head: Address) -> Result<Vec<u64>> {
+//!     // the list entry is located at offset 8 of the object
+//!     mem.list_iter::<Object>(head, 8)
+//!         .map(|entry| entry.map(|(_, object)| object.id))
+//!         .collect()
+//! }
+//! ```
+
+use super::remote_struct::{ptr_from_bytes, ptr_size};
+use super::*;
+
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+use std::prelude::v1::*;
+
+/// Default maximum number of objects yielded by a [`ListIter`].
+pub const DEFAULT_MAX_LIST_LEN: usize = 0x10000;
+
+/// Iterator over the objects of an intrusive doubly linked list.
+///
+/// Yields the address of every object along with the object read as `T`.
+pub struct ListIter<'a, M, T> {
+    mem: &'a mut M,
+    meta: MemoryViewMetadata,
+    head: Address,
+    link_offset: umem,
+    backwards: bool,
+    max_len: usize,
+    cur: Address,
+    visited: BTreeSet<Address>,
+    done: bool,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<'a, M: MemoryView, T: Pod> ListIter<'a, M, T> {
+    /// Creates an iterator over the list starting at the list head `head`.
+    ///
+    /// `link_offset` is the offset of the list entry inside of the objects.
+    pub fn new(mem: &'a mut M, head: Address, link_offset: usize) -> Self {
+        Self {
+            meta: mem.metadata(),
+            mem,
+            head,
+            link_offset: link_offset as umem,
+            backwards: false,
+            max_len: DEFAULT_MAX_LIST_LEN,
+            cur: head,
+            visited: BTreeSet::new(),
+            done: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Follows the backward links instead of the forward links.
+    pub fn backwards(mut self) -> Self {
+        self.backwards = true;
+        self
+    }
+
+    /// Sets the maximum number of objects, longer lists end with an `OutOfBounds` error.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    fn step(&mut self) -> Result<Option<(Address, T)>> {
+        let ptr_size = ptr_size(&self.meta);
+        let at = if self.backwards {
+            self.cur + ptr_size
+        } else {
+            self.cur
+        };
+
+        let mut raw = [0; 8];
+        self.mem.read_raw_into(at, &mut raw[..ptr_size]).data()?;
+        let link = ptr_from_bytes(&raw, &self.meta);
+
+        if link == self.head {
+            return Ok(None);
+        }
+        if link.is_null() {
+            return Err(Error(ErrorOrigin::Pointer, ErrorKind::UnableToReadMemory)
+                .log_warn(format!("null link in list at {:x}", at)));
+        }
+        if self.visited.len() >= self.max_len {
+            return Err(Error(ErrorOrigin::Pointer, ErrorKind::OutOfBounds)
+                .log_warn("list exceeds the maximum length"));
+        }
+        if !self.visited.insert(link) {
+            return Err(Error(ErrorOrigin::Pointer, ErrorKind::OutOfBounds)
+                .log_warn(format!("list contains a cycle at {:x}", link)));
+        }
+
+        self.cur = link;
+        let object = Address::from(link.to_umem().wrapping_sub(self.link_offset));
+        let value = self.mem.read::<T>(object).data()?;
+
+        Ok(Some((object, value)))
+    }
+}
+
+impl<'a, M: MemoryView, T: Pod> Iterator for ListIter<'a, M, T> {
+    type Item = Result<(Address, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.step() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    #[test]
+    fn walk_list() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // head at base, objects with their id at 0x0 and the list entry at 0x10
+        let head = base;
+        let links = (1..=3)
+            .map(|i| base + i * 0x100usize + 0x10usize)
+            .collect::<Vec<_>>();
+        let chain = std::iter::once(head)
+            .chain(links.iter().copied())
+            .chain(Some(head))
+            .collect::<Vec<_>>();
+
+        for w in chain.windows(3) {
+            proc.write(w[1], &w[2].to_umem()).unwrap();
+            proc.write(w[1] + 8usize, &w[0].to_umem()).unwrap();
+        }
+        proc.write(head, &links[0].to_umem()).unwrap();
+        proc.write(head + 8usize, &links[2].to_umem()).unwrap();
+        for (i, link) in links.iter().enumerate() {
+            proc.write(*link - 0x10usize, &(i as u64)).unwrap();
+        }
+
+        fn ids<M: MemoryView>(iter: ListIter<M, u64>) -> Vec<u64> {
+            iter.map(|e| e.unwrap().1).collect()
+        }
+
+        assert_eq!(ids(proc.list_iter(head, 0x10)), vec![0, 1, 2]);
+        assert_eq!(ids(proc.list_iter(head, 0x10).backwards()), vec![2, 1, 0]);
+        assert!(proc
+            .list_iter::<u64>(head, 0x10)
+            .max_len(2)
+            .any(|e| e.is_err()));
+
+        // the last object links back to the second one
+        proc.write(links[2], &links[1].to_umem()).unwrap();
+        let entries = proc.list_iter::<u64>(head, 0x10).collect::<Vec<_>>();
+        assert_eq!(entries.len(), 4);
+        assert!(entries[3].is_err());
+    }
+}
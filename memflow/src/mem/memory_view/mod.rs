@@ -2,19 +2,46 @@ use super::mem_data::*;
 
 use crate::prelude::v1::{Result, *};
 
+use core::cell::{Cell, RefCell};
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
 use std::mem::MaybeUninit;
 use std::prelude::v1::*;
+use std::ptr::NonNull;
 
 pub mod arch_overlay;
+pub mod array;
 pub mod batcher;
+pub mod bounded_view;
+pub mod copy;
+pub mod endian;
+pub mod hash;
+pub mod lazy_view;
+pub mod list;
+pub mod ptr_chain;
+pub mod read_fields;
 pub mod remap_view;
+pub mod remote_struct;
+pub mod report;
+pub mod scatter;
+pub mod utf16;
+pub mod verified;
 
 #[cfg(feature = "std")]
 pub mod cursor;
 
 pub use arch_overlay::ArchOverlayView;
 pub use batcher::MemoryViewBatcher;
+pub use bounded_view::BoundedView;
+pub use hash::{Digest, HashAlgo, RangeBaseline};
+pub use lazy_view::LazyView;
+pub use list::ListIter;
+pub use ptr_chain::{PtrChain, PtrChainBuilder, PtrChainError, PtrChainStep};
 pub use remap_view::RemapView;
+pub use remote_struct::RemoteStruct;
+pub use report::{AccessFailure, AccessReport};
+pub use scatter::{ScatterHandle, ScatterReadBuilder, ScatterResults};
+pub use utf16::Utf16Mode;
+pub use verified::{CompareExchangeError, ConsistencyError, VerifyError, WriteMismatch};
 
 #[cfg(feature = "std")]
 pub use cursor::MemoryCursor;
@@ -173,10 +200,290 @@ pub trait MemoryView: Send {
         self.read_raw_list(&mut [CTup2(addr, out.into())])
     }
 
+    /// Reads a batch like [`read_raw_list`](Self::read_raw_list) and reports exactly which
+    /// bytes of every element were read.
+    ///
+    /// If some bytes could not be read a `PartialVirtualRead` error containing the reports is
+    /// returned, failed bytes are zeroed out.
+    #[skip_func]
+    fn read_raw_list_report(&mut self, data: &mut [ReadData]) -> PartialResult<Vec<AccessReport>>
+    where
+        Self: Sized,
+    {
+        report::read_raw_list_report(self, data)
+    }
+
+    /// Reads into `out` and reports exactly which bytes were read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::error::PartialError;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn dump(mem: &mut impl MemoryView, addr: Address, out: &mut [u8]) {
+    ///     match mem.read_raw_into_report(addr, out) {
+    ///         Ok(_) => println!("all bytes read"),
+    ///         Err(PartialError::PartialVirtualRead(report)) => {
+    ///             for range in report.failed_ranges() {
+    ///                 println!("unreadable: {:x}..{:x}", range.start, range.end);
+    ///             }
+    ///         }
+    ///         Err(err) => println!("read failed: {}", err),
+    ///     }
+    /// }
+    /// ```
+    #[skip_func]
+    fn read_raw_into_report(&mut self, addr: Address, out: &mut [u8]) -> PartialResult<AccessReport>
+    where
+        Self: Sized,
+    {
+        report::single(self.read_raw_list_report(&mut [CTup2(addr, out.into())]))
+    }
+
+    /// Reads a batch like [`read_raw_list`](Self::read_raw_list), reporting the progress to and
+    /// checking for cancellation through `ctx`.
+    ///
+    /// The token is checked before every element is handed to the backend, the progress is
+    /// reported whenever a part of an element was read or failed. When the operation is cancelled
+    /// an [`ErrorKind::Cancelled`] error is returned and the remaining elements are left untouched.
+    #[skip_func]
+    fn read_raw_list_ctx(
+        &mut self,
+        data: &mut [ReadData],
+        ctx: &mut OpContext,
+    ) -> PartialResult<()> {
+        let total = data.iter().map(|CTup2(_, d)| d.len()).sum();
+        let ctx = RefCell::new(ctx);
+        let done = Cell::new(0);
+        let mut cancelled = None;
+        let mut out = Ok(());
+
+        let progress = |len: usize| {
+            done.set(done.get() + len);
+            ctx.borrow_mut().report(done.get(), total);
+        };
+
+        let success = &mut |CTup2(_, d): ReadData| {
+            progress(d.len());
+            true
+        };
+
+        let fail = &mut |CTup2(_, mut d): ReadData| {
+            out = Err(PartialError::PartialVirtualRead(()));
+
+            for v in d.iter_mut() {
+                *v = 0;
+            }
+
+            progress(d.len());
+            true
+        };
+
+        let iter = data
+            .iter()
+            .map_while(|CTup2(d1, d2)| match ctx.borrow().check() {
+                Ok(_) => Some(CTup3(*d1, *d1, d2.into())),
+                Err(e) => {
+                    cancelled = Some(e);
+                    None
+                }
+            });
+
+        MemOps::with_raw(
+            iter,
+            Some(&mut success.into()),
+            Some(&mut fail.into()),
+            |data| self.read_raw_iter(data),
+        )?;
+
+        match cancelled {
+            Some(e) => Err(PartialError::Error(e)),
+            None => out,
+        }
+    }
+
+    /// Reads into `out` in chunks, reporting the progress to and checking for cancellation
+    /// through `ctx`.
+    ///
+    /// Parts of the buffer that could not be read are zeroed out and cause a partial error, just
+    /// like with [`read_raw_into`](Self::read_raw_into). When the operation is cancelled an
+    /// [`ErrorKind::Cancelled`] error is returned and the rest of the buffer is left untouched.
+    #[skip_func]
+    fn read_raw_into_ctx(
+        &mut self,
+        addr: Address,
+        out: &mut [u8],
+        ctx: &mut OpContext,
+    ) -> PartialResult<()> {
+        let total = out.len();
+        let chunk_size = ctx.chunk_size;
+        let mut res = Ok(());
+
+        for (i, chunk) in out.chunks_mut(chunk_size).enumerate() {
+            ctx.check()?;
+
+            let offset = i * chunk_size;
+            match self.read_raw_into(addr + offset, chunk) {
+                Ok(_) => {}
+                Err(PartialError::Error(e)) => return Err(PartialError::Error(e)),
+                Err(_) => res = Err(PartialError::PartialVirtualRead(())),
+            }
+
+            ctx.report(offset + chunk.len(), total);
+        }
+
+        res
+    }
+
+    /// Reads into a possibly uninitialized buffer.
+    ///
+    /// The buffer is read into directly, only the parts that could not be read are zeroed out.
+    /// On success, and on partial success, the entire buffer is initialized and returned as an
+    /// initialized slice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::mem::MaybeUninit;
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read(mem: &mut impl MemoryView, addr: Address) {
+    ///     let mut buf = [MaybeUninit::uninit(); 16];
+    ///     let data = mem.read_raw_into_uninit(addr, &mut buf).unwrap();
+    ///     println!("{:?}", data);
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let virt_base = proc.info().address;
+    /// # read(&mut proc, virt_base);
+    /// ```
+    #[skip_func]
+    fn read_raw_into_uninit<'b>(
+        &mut self,
+        addr: Address,
+        out: &'b mut [MaybeUninit<u8>],
+    ) -> PartialResult<&'b mut [u8]> {
+        let len = out.len();
+        // Safety: the buffer is only written to by the read, no byte of it is handed out
+        // before every part of it was either read or zeroed below
+        let buf = unsafe { &mut *(out as *mut [MaybeUninit<u8>] as *mut [u8]) };
+
+        // (offset, length) of every part the read reported back
+        let covered = RefCell::new(vec![]);
+        let mut partial = false;
+
+        {
+            let offset = |a: Address| a.to_umem().wrapping_sub(addr.to_umem()) as usize;
+
+            let succeeded = &mut |CTup2(a, d): ReadData| {
+                covered.borrow_mut().push((offset(a), d.len()));
+                true
+            };
+
+            let failed = &mut |CTup2(a, mut d): ReadData| {
+                for v in d.iter_mut() {
+                    *v = 0;
+                }
+                covered.borrow_mut().push((offset(a), d.len()));
+                partial = true;
+                true
+            };
+
+            self.read_iter(
+                Some(CTup2(addr, (&mut *buf).into())).into_iter(),
+                Some(&mut succeeded.into()),
+                Some(&mut failed.into()),
+            )?;
+        }
+
+        // Zero out anything the read did not report on, so the whole buffer is initialized
+        let mut covered = covered.into_inner();
+        covered.sort_unstable();
+        let mut pos = 0;
+        for (off, l) in covered.into_iter().chain(Some((len, 0))) {
+            let off = off.min(len);
+            if off > pos {
+                for v in buf[pos..off].iter_mut() {
+                    *v = 0;
+                }
+                partial = true;
+            }
+            pos = pos.max(off.saturating_add(l).min(len));
+        }
+
+        if partial {
+            Err(PartialError::PartialVirtualRead(buf))
+        } else {
+            Ok(buf)
+        }
+    }
+
     #[skip_func]
     fn read_raw(&mut self, addr: Address, len: usize) -> PartialResult<Vec<u8>> {
-        let mut buf = vec![0u8; len];
-        self.read_raw_into(addr, &mut *buf).map_data(|_| buf)
+        let mut buf = Vec::with_capacity(len);
+        // Safety: the spare capacity of the vector is only handed out as uninitialized memory
+        let uninit = unsafe {
+            std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut MaybeUninit<u8>, len)
+        };
+
+        match self.read_raw_into_uninit(addr, uninit) {
+            Err(PartialError::Error(e)) => Err(PartialError::Error(e)),
+            res => {
+                // Safety: read_raw_into_uninit initialized all `len` bytes
+                unsafe { buf.set_len(len) };
+                match res {
+                    Ok(_) => Ok(buf),
+                    Err(_) => Err(PartialError::PartialVirtualRead(buf)),
+                }
+            }
+        }
+    }
+
+    /// Reads a value of type `T` directly into a heap allocation.
+    ///
+    /// The value is never constructed on the stack, parts that could not be read are zeroed.
+    #[skip_func]
+    fn read_box<T: Pod + Sized>(&mut self, addr: Address) -> PartialResult<Box<T>>
+    where
+        Self: Sized,
+    {
+        let layout = Layout::new::<T>();
+
+        if layout.size() == 0 {
+            // Safety: zero sized values need no allocation and `T` is valid for any bytes
+            return Ok(unsafe { Box::from_raw(NonNull::<T>::dangling().as_ptr()) });
+        }
+
+        // Safety: the layout has a non-zero size
+        let ptr = unsafe { alloc(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+
+        // Safety: the allocation is `layout.size()` bytes long and exclusively owned here
+        let uninit =
+            unsafe { std::slice::from_raw_parts_mut(ptr as *mut MaybeUninit<u8>, layout.size()) };
+
+        match self.read_raw_into_uninit(addr, uninit) {
+            Err(PartialError::Error(e)) => {
+                // Safety: the allocation was made with the same layout and handed out nowhere
+                unsafe { dealloc(ptr, layout) };
+                Err(PartialError::Error(e))
+            }
+            res => {
+                // Safety: every byte was initialized by the read and `T` is valid for any bytes
+                let obj = unsafe { Box::from_raw(ptr as *mut T) };
+                match res {
+                    Ok(_) => Ok(obj),
+                    Err(_) => Err(PartialError::PartialVirtualRead(obj)),
+                }
+            }
+        }
     }
 
     #[skip_func]
@@ -198,7 +505,149 @@ pub trait MemoryView: Send {
         self.read_into(addr, &mut obj).map_data(|_| obj)
     }
 
+    /// Reads a little endian value, swapping bytes on big endian hosts.
+    #[skip_func]
+    fn read_le<T: Pod + ByteSwap>(&mut self, addr: Address) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        endian::read_endian(self, addr, true)
+    }
+
+    /// Reads a big endian value, swapping bytes on little endian hosts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read_magic(mem: &mut impl MemoryView, header: Address) -> u32 {
+    ///     // network byte order
+    ///     mem.read_be::<u32>(header).unwrap()
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[0xfe, 0xed, 0xfa, 0xce]);
+    /// # let virt_base = proc.info().address;
+    /// # assert_eq!(read_magic(&mut proc, virt_base), 0xfeedface);
+    /// ```
+    #[skip_func]
+    fn read_be<T: Pod + ByteSwap>(&mut self, addr: Address) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        endian::read_endian(self, addr, false)
+    }
+
+    /// Reads a value in the byte order of this view, as given by its metadata.
+    ///
+    /// The byte order can be changed with [`overlay_endianness`](Self::overlay_endianness).
+    #[skip_func]
+    fn read_endian<T: Pod + ByteSwap>(&mut self, addr: Address) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        let little_endian = self.metadata().little_endian;
+        endian::read_endian(self, addr, little_endian)
+    }
+
     // TODO: allow cglue to somehow pass MaybeUninit to the IntError
+    /// Reads `count` consecutive elements of `T` into a `Vec`.
+    ///
+    /// Counts that would overflow the address space are rejected before anything is allocated.
+    /// On a partial read the unreadable elements are zeroed and returned in a
+    /// `PartialVirtualRead` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read_table(mem: &mut impl MemoryView, table: Address) -> Vec<u32> {
+    ///     mem.read_vec(table, 16).unwrap()
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let virt_base = proc.info().address;
+    /// # assert_eq!(read_table(&mut proc, virt_base).len(), 16);
+    /// ```
+    #[skip_func]
+    fn read_vec<T: Pod>(&mut self, addr: Address, count: usize) -> PartialResult<Vec<T>>
+    where
+        Self: Sized,
+    {
+        array::read_vec(self, addr, count)
+    }
+
+    /// Reads an array whose element count is stored in the target.
+    ///
+    /// The count is read as a `C` from `count_addr`, the elements are read from `data_addr`.
+    /// Counts larger than `max` result in an `OutOfBounds` error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// // struct { u32 count; u64 entries[]; }
+    /// fn read_entries(mem: &mut impl MemoryView, list: Address) -> Vec<u64> {
+    ///     mem.read_counted_vec::<u32, u64>(list, list + 8usize, 0x1000)
+    ///         .unwrap()
+    /// }
+    /// ```
+    #[skip_func]
+    fn read_counted_vec<C, T>(
+        &mut self,
+        count_addr: Address,
+        data_addr: Address,
+        max: usize,
+    ) -> PartialResult<Vec<T>>
+    where
+        C: Pod + std::convert::TryInto<usize>,
+        T: Pod,
+        Self: Sized,
+    {
+        array::read_counted_vec::<C, T, _>(self, count_addr, data_addr, max)
+    }
+
+    /// Reads a null-terminated array of pointers with up to `max` entries.
+    ///
+    /// The width and byte order of the pointers are taken from the metadata of this view. If no
+    /// null terminator is found within `max` entries this function will return an error.
+    #[skip_func]
+    fn read_ptr_array(&mut self, addr: Address, max: usize) -> PartialResult<Vec<Address>>
+    where
+        Self: Sized,
+    {
+        array::read_ptr_array(self, addr, max)
+    }
+
+    /// Returns an iterator over the objects of an intrusive doubly linked list.
+    ///
+    /// `head` is the address of the list head and `link_offset` the offset of the list entry
+    /// inside of every object. See [`ListIter`] for details.
+    #[skip_func]
+    fn list_iter<T: Pod>(&mut self, head: Address, link_offset: usize) -> ListIter<Self, T>
+    where
+        Self: Sized,
+    {
+        ListIter::new(self, head, link_offset)
+    }
+
+    #[skip_func]
+    fn read_remote<T: RemoteStruct>(&mut self, addr: Address) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        T::read_remote(self, addr)
+    }
+
     #[skip_func]
     fn read_addr32(&mut self, addr: Address) -> PartialResult<Address>
     where
@@ -253,6 +702,37 @@ pub trait MemoryView: Send {
         self.read(ptr.into())
     }
 
+    /// Starts building a pointer chain at `base`.
+    ///
+    /// See [`PtrChain`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read_health(mem: &mut impl MemoryView, base: Address) {
+    ///     match mem.ptr_chain(base).offset(0x18).deref().offset(0x40).read::<f32>() {
+    ///         Ok(hp) => println!("hp: {}", hp),
+    ///         Err(err) => println!("failed at hop {}: {}", err.hop, err),
+    ///     }
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let virt_base = proc.info().address;
+    /// # read_health(&mut proc, virt_base);
+    /// ```
+    #[skip_func]
+    fn ptr_chain(&mut self, base: Address) -> PtrChainBuilder<'_, Self>
+    where
+        Self: Sized,
+    {
+        PtrChainBuilder::new(self, base)
+    }
+
     // Write helpers
 
     /// Write arbitrary amount of data.
@@ -356,6 +836,114 @@ pub trait MemoryView: Send {
         self.write_raw_list(&[CTup2(addr, data.into())])
     }
 
+    /// Writes a batch like [`write_raw_list`](Self::write_raw_list) and reports exactly which
+    /// bytes of every element were written.
+    ///
+    /// If some bytes could not be written a `PartialVirtualWrite` error containing the reports is
+    /// returned.
+    #[skip_func]
+    fn write_raw_list_report(&mut self, data: &[WriteData]) -> PartialResult<Vec<AccessReport>>
+    where
+        Self: Sized,
+    {
+        report::write_raw_list_report(self, data)
+    }
+
+    /// Writes `data` to `addr` and reports exactly which bytes were written.
+    #[skip_func]
+    fn write_raw_report(&mut self, addr: Address, data: &[u8]) -> PartialResult<AccessReport>
+    where
+        Self: Sized,
+    {
+        report::single(self.write_raw_list_report(&[CTup2(addr, data.into())]))
+    }
+
+    /// Writes a batch like [`write_raw_list`](Self::write_raw_list), reporting the progress to
+    /// and checking for cancellation through `ctx`.
+    ///
+    /// The token is checked before every element is handed to the backend. When the operation is
+    /// cancelled an [`ErrorKind::Cancelled`] error is returned, the elements written before stay
+    /// written.
+    #[skip_func]
+    fn write_raw_list_ctx(&mut self, data: &[WriteData], ctx: &mut OpContext) -> PartialResult<()> {
+        let total = data.iter().map(|CTup2(_, d)| d.len()).sum();
+        let ctx = RefCell::new(ctx);
+        let done = Cell::new(0);
+        let mut cancelled = None;
+        let mut out = Ok(());
+
+        let progress = |len: usize| {
+            done.set(done.get() + len);
+            ctx.borrow_mut().report(done.get(), total);
+        };
+
+        let success = &mut |CTup2(_, d): WriteData| {
+            progress(d.len());
+            true
+        };
+
+        let fail = &mut |CTup2(_, d): WriteData| {
+            out = Err(PartialError::PartialVirtualWrite(()));
+            progress(d.len());
+            true
+        };
+
+        let iter = data
+            .iter()
+            .copied()
+            .map_while(|d| match ctx.borrow().check() {
+                Ok(_) => Some(d),
+                Err(e) => {
+                    cancelled = Some(e);
+                    None
+                }
+            });
+
+        MemOps::with_raw(
+            iter,
+            Some(&mut success.into()),
+            Some(&mut fail.into()),
+            |data| self.write_iter(data.inp, data.out, data.out_fail),
+        )?;
+
+        match cancelled {
+            Some(e) => Err(PartialError::Error(e)),
+            None => out,
+        }
+    }
+
+    /// Writes `data` in chunks, reporting the progress to and checking for cancellation through
+    /// `ctx`.
+    ///
+    /// When the operation is cancelled an [`ErrorKind::Cancelled`] error is returned, the chunks
+    /// written before stay written.
+    #[skip_func]
+    fn write_raw_ctx(
+        &mut self,
+        addr: Address,
+        data: &[u8],
+        ctx: &mut OpContext,
+    ) -> PartialResult<()> {
+        let total = data.len();
+        let chunk_size = ctx.chunk_size;
+        let mut res = Ok(());
+
+        for (i, chunk) in data.chunks(chunk_size).enumerate() {
+            ctx.check()?;
+
+            let offset = i * chunk_size;
+            match self.write_raw(addr + offset, chunk) {
+                Ok(_) => {}
+                Err(PartialError::Error(e)) => return Err(PartialError::Error(e)),
+                Err(_) => res = Err(PartialError::PartialVirtualWrite(())),
+            }
+
+            ctx.report(offset + chunk.len(), total);
+        }
+
+        res
+    }
+
     #[skip_func]
     fn write<T: Pod + ?Sized>(&mut self, addr: Address, data: &T) -> PartialResult<()>
     where
@@ -364,6 +952,34 @@ pub trait MemoryView: Send {
         self.write_raw(addr, data.as_bytes())
     }
 
+    /// Writes a value in little endian byte order.
+    #[skip_func]
+    fn write_le<T: Pod + ByteSwap>(&mut self, addr: Address, data: &T) -> PartialResult<()>
+    where
+        Self: Sized,
+    {
+        endian::write_endian(self, addr, data, true)
+    }
+
+    /// Writes a value in big endian byte order.
+    #[skip_func]
+    fn write_be<T: Pod + ByteSwap>(&mut self, addr: Address, data: &T) -> PartialResult<()>
+    where
+        Self: Sized,
+    {
+        endian::write_endian(self, addr, data, false)
+    }
+
+    /// Writes a value in the byte order of this view, as given by its metadata.
+    #[skip_func]
+    fn write_endian<T: Pod + ByteSwap>(&mut self, addr: Address, data: &T) -> PartialResult<()>
+    where
+        Self: Sized,
+    {
+        let little_endian = self.metadata().little_endian;
+        endian::write_endian(self, addr, data, little_endian)
+    }
+
     #[skip_func]
     fn write_ptr<U: PrimitiveAddress, T: Pod + ?Sized>(
         &mut self,
@@ -376,6 +992,192 @@ pub trait MemoryView: Send {
         self.write(ptr.into(), data)
     }
 
+    /// Hashes `len` bytes starting at `addr` without reading the whole range at once.
+    ///
+    /// Bytes that could not be read are hashed as zeroes and a `PartialVirtualRead` error
+    /// containing the digest is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::{HashAlgo, MemoryView};
+    ///
+    /// fn baseline(mem: &mut impl MemoryView, text: Address, len: u64) {
+    ///     let digest = mem.hash_range(text, len as _, HashAlgo::Sha256).unwrap();
+    ///     println!("sha256: {}", digest);
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let virt_base = proc.info().address;
+    /// # baseline(&mut proc, virt_base, 0x1000);
+    /// ```
+    #[skip_func]
+    fn hash_range(&mut self, addr: Address, len: umem, algo: HashAlgo) -> PartialResult<Digest>
+    where
+        Self: Sized,
+    {
+        hash::hash_range(self, addr, len, algo)
+    }
+
+    /// Hashes `len` bytes starting at `addr` and compares the digest against `expected`.
+    ///
+    /// The range is hashed with the algorithm of `expected`. If parts of the range could not be
+    /// read, the result of the comparison is returned in a `PartialVirtualRead` error.
+    ///
+    /// Use [`RangeBaseline`] to find out which pages of a range were modified.
+    #[skip_func]
+    fn verify_range(&mut self, addr: Address, len: umem, expected: &Digest) -> PartialResult<bool>
+    where
+        Self: Sized,
+    {
+        hash::verify_range(self, addr, len, expected)
+    }
+
+    /// Writes `data` to `addr` and verifies the write by reading the memory back.
+    ///
+    /// If the read back data differs from `data`, or parts of it can not be read back, a
+    /// [`VerifyError::Mismatch`] is returned that contains both the written and the read back
+    /// bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn patch(mem: &mut impl MemoryView, addr: Address) {
+    ///     if let Err(err) = mem.write_verified(addr, &0x90u8) {
+    ///         println!("patch failed: {}", err);
+    ///     }
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let virt_base = proc.info().address;
+    /// # patch(&mut proc, virt_base);
+    /// ```
+    #[skip_func]
+    fn write_verified<T: Pod + ?Sized>(
+        &mut self,
+        addr: Address,
+        data: &T,
+    ) -> core::result::Result<(), VerifyError>
+    where
+        Self: Sized,
+    {
+        verified::write_verified(self, addr, data.as_bytes())
+    }
+
+    /// Reads `out` from `addr` until two consecutive reads return the same bytes.
+    ///
+    /// Up to `retries` additional reads are done when the memory keeps changing. Use this for
+    /// structures of a running target that may be torn by concurrent modifications.
+    #[skip_func]
+    fn read_raw_consistent(
+        &mut self,
+        addr: Address,
+        out: &mut [u8],
+        retries: usize,
+    ) -> core::result::Result<(), ConsistencyError>
+    where
+        Self: Sized,
+    {
+        verified::read_consistent(self, addr, out, retries, |a, b| a == b)
+    }
+
+    /// Reads a value until two consecutive reads return the same bytes.
+    ///
+    /// Up to [`DEFAULT_CONSISTENT_RETRIES`](verified::DEFAULT_CONSISTENT_RETRIES) additional reads
+    /// are done. See [`read_raw_consistent`](Self::read_raw_consistent).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read_links(mem: &mut impl MemoryView, entry: Address) -> Option<[u64; 2]> {
+    ///     mem.read_consistent(entry).ok()
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let virt_base = proc.info().address;
+    /// # assert_eq!(read_links(&mut proc, virt_base), Some([0, 0]));
+    /// ```
+    #[skip_func]
+    fn read_consistent<T: Pod + Sized>(
+        &mut self,
+        addr: Address,
+    ) -> core::result::Result<T, ConsistencyError>
+    where
+        Self: Sized,
+    {
+        let mut value = remote_struct::pod_zeroed::<T>();
+        self.read_raw_consistent(
+            addr,
+            value.as_bytes_mut(),
+            verified::DEFAULT_CONSISTENT_RETRIES,
+        )?;
+        Ok(value)
+    }
+
+    /// Reads a value until the keys of two consecutive reads match.
+    ///
+    /// `key` extracts the part of the value that identifies a consistent state, like a sequence
+    /// or generation counter, or the fields that are actually used. Other fields, like
+    /// statistics that change constantly, may differ between the reads. The value of the last
+    /// read is returned.
+    #[skip_func]
+    fn read_consistent_by<T: Pod + Sized, K: PartialEq, F: FnMut(&T) -> K>(
+        &mut self,
+        addr: Address,
+        retries: usize,
+        mut key: F,
+    ) -> core::result::Result<T, ConsistencyError>
+    where
+        Self: Sized,
+    {
+        let mut value = remote_struct::pod_zeroed::<T>();
+        let mut prev = remote_struct::pod_zeroed::<T>();
+        let mut cur = remote_struct::pod_zeroed::<T>();
+
+        verified::read_consistent(self, addr, value.as_bytes_mut(), retries, |a, b| {
+            prev.as_bytes_mut().copy_from_slice(a);
+            cur.as_bytes_mut().copy_from_slice(b);
+            key(&prev) == key(&cur)
+        })?;
+
+        Ok(value)
+    }
+
+    /// Writes `new` to `addr` if the memory currently contains `expected`.
+    ///
+    /// Returns the previous value on success. If the current value does not match `expected`
+    /// nothing is written and [`CompareExchangeError::Current`] is returned.
+    ///
+    /// # Remarks:
+    ///
+    /// The exchange is not atomic, the target may still modify the value in between the
+    /// comparison and the write. The write is verified afterwards however.
+    #[skip_func]
+    fn compare_exchange<T: Pod + Sized>(
+        &mut self,
+        addr: Address,
+        expected: &T,
+        new: &T,
+    ) -> core::result::Result<T, CompareExchangeError<T>>
+    where
+        Self: Sized,
+    {
+        verified::compare_exchange(self, addr, expected, new)
+    }
+
     /// Reads a fixed length string from the target.
     ///
     /// # Remarks:
@@ -444,6 +1246,92 @@ pub trait MemoryView: Send {
         self.read_char_string_n(addr, 4096)
     }
 
+    /// Reads a fixed length UTF-16 string from the target.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - target address to read from
+    /// * `len` - number of UTF-16 code units to read
+    /// * `mode` - handling of invalid UTF-16 sequences
+    ///
+    /// # Remarks:
+    ///
+    /// The string does not have to be null-terminated.
+    /// If a null terminator is found the string is truncated to the terminator.
+    #[skip_func]
+    fn read_utf16_array(
+        &mut self,
+        addr: Address,
+        len: usize,
+        mode: Utf16Mode,
+    ) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        utf16::read_utf16_array(self, addr, len, mode)
+    }
+
+    /// Reads a null-terminated UTF-16 string with a length of up to `n` code units from the target.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - target address to read from
+    /// * `n` - maximum number of UTF-16 code units to read
+    /// * `mode` - handling of invalid UTF-16 sequences
+    ///
+    /// # Remarks:
+    ///
+    /// If no null terminator is found this function will return an error.
+    #[skip_func]
+    fn read_utf16_string_n(
+        &mut self,
+        addr: Address,
+        n: usize,
+        mode: Utf16Mode,
+    ) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        utf16::read_utf16_string_n(self, addr, n, mode)
+    }
+
+    /// Reads a null-terminated UTF-16 string with up to 4096 code units from the target.
+    ///
+    /// Invalid UTF-16 sequences are replaced with `U+FFFD`.
+    #[skip_func]
+    fn read_utf16_string(&mut self, addr: Address) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        self.read_utf16_string_n(addr, utf16::DEFAULT_UTF16_LEN, Utf16Mode::Lossy)
+    }
+
+    /// Reads the string described by the Windows `UNICODE_STRING` structure at `addr`.
+    ///
+    /// The layout of the structure, 8 bytes on 32-bit and 16 bytes on 64-bit targets, is chosen
+    /// based on the architecture of this view. In strict mode a `Length` larger than
+    /// `MaximumLength` or an odd `Length` is rejected as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::{MemoryView, Utf16Mode};
+    ///
+    /// fn image_name(mem: &mut impl MemoryView, ldr_entry: Address) -> String {
+    ///     // BaseDllName of a 64-bit LDR_DATA_TABLE_ENTRY
+    ///     mem.read_unicode_string(ldr_entry + 0x58usize, Utf16Mode::Lossy)
+    ///         .unwrap_or_default()
+    /// }
+    /// ```
+    #[skip_func]
+    fn read_unicode_string(&mut self, addr: Address, mode: Utf16Mode) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        utf16::read_unicode_string(self, addr, mode)
+    }
+
     // TODO: batcher
 
     #[cfg(feature = "std")]
@@ -482,6 +1370,51 @@ pub trait MemoryView: Send {
         MemoryCursor::at(self, address)
     }
 
+    /// Returns a cursor over `base..base + size`, positioned relative to `base`.
+    ///
+    /// The cursor behaves like a file of `size` bytes, which allows passing remote memory to
+    /// parsers expecting `Read + Seek`. See [`MemoryCursor::region`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Seek, SeekFrom};
+    ///
+    /// use memflow::dummy::DummyOs;
+    /// use memflow::mem::MemoryView;
+    /// use memflow::os::Process;
+    /// use memflow::types::size;
+    ///
+    /// let mut proc = DummyOs::quick_process(size::mb(2), b"MZ\x90\x00");
+    /// let base = proc.info().address;
+    ///
+    /// let mut image = proc.cursor_region(base, size::kb(4) as _);
+    /// let mut magic = [0u8; 2];
+    /// image.read_exact(&mut magic).unwrap();
+    /// assert_eq!(&magic, b"MZ");
+    /// assert_eq!(image.seek(SeekFrom::End(0)).unwrap(), 0x1000);
+    /// ```
+    #[cfg(feature = "std")]
+    #[skip_func]
+    fn cursor_region(&mut self, base: Address, size: umem) -> MemoryCursor<Fwd<&mut Self>>
+    where
+        Self: Sized,
+    {
+        MemoryCursor::region(self.forward(), base, size)
+    }
+
+    /// Consumes this view and returns a cursor over `base..base + size`.
+    ///
+    /// See [`MemoryView::cursor_region`].
+    #[cfg(feature = "std")]
+    #[skip_func]
+    fn into_cursor_region(self, base: Address, size: umem) -> MemoryCursor<Self>
+    where
+        Self: Sized,
+    {
+        MemoryCursor::region(self, base, size)
+    }
+
     #[skip_func]
     fn batcher(&mut self) -> MemoryViewBatcher<Self>
     where
@@ -490,6 +1423,15 @@ pub trait MemoryView: Send {
         MemoryViewBatcher::new(self)
     }
 
+    /// Creates a builder that reads values of different types in a single batch.
+    #[skip_func]
+    fn scatter(&mut self) -> ScatterReadBuilder<Self>
+    where
+        Self: Sized,
+    {
+        ScatterReadBuilder::new(self)
+    }
+
     #[skip_func]
     fn into_overlay_arch(self, arch: ArchitectureObj) -> ArchOverlayView<Self>
     where
@@ -526,6 +1468,31 @@ pub trait MemoryView: Send {
         ArchOverlayView::new_parts(self.forward_mut(), arch_bits, little_endian)
     }
 
+    /// Returns a view that reads and writes values with the given byte order by default.
+    ///
+    /// Only the byte order used by [`read_endian`](Self::read_endian) and
+    /// [`write_endian`](Self::write_endian) is changed, the pointer width of this view is kept.
+    #[skip_func]
+    fn into_overlay_endianness(self, little_endian: bool) -> ArchOverlayView<Self>
+    where
+        Self: Sized,
+    {
+        let arch_bits = self.metadata().arch_bits;
+        ArchOverlayView::new_parts(self, arch_bits, little_endian)
+    }
+
+    /// Borrows this view with another default byte order.
+    ///
+    /// See [`into_overlay_endianness`](Self::into_overlay_endianness).
+    #[skip_func]
+    fn overlay_endianness(&mut self, little_endian: bool) -> ArchOverlayView<Fwd<&mut Self>>
+    where
+        Self: Sized,
+    {
+        let arch_bits = self.metadata().arch_bits;
+        ArchOverlayView::new_parts(self.forward_mut(), arch_bits, little_endian)
+    }
+
     #[skip_func]
     fn into_remap_view(self, mem_map: MemoryMap<(Address, umem)>) -> RemapView<Self>
     where
@@ -554,3 +1521,115 @@ pub struct MemoryViewMetadata {
     pub little_endian: bool,
     pub arch_bits: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    #[test]
+    fn read_uninit() {
+        let data = (0..0x2000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &data);
+        let base = proc.info().address;
+
+        let mut buf = vec![MaybeUninit::uninit(); 0x100];
+        assert_eq!(
+            proc.read_raw_into_uninit(base, &mut buf).unwrap(),
+            &data[..0x100]
+        );
+
+        assert_eq!(proc.read_raw(base, 0x2000).unwrap(), data);
+
+        let mut buf = vec![MaybeUninit::uninit(); 0x20];
+        match proc.read_raw_into_uninit(base - 0x10usize, &mut buf) {
+            Err(PartialError::PartialVirtualRead(buf)) => {
+                assert_eq!(&buf[..0x10], &[0; 0x10]);
+                assert_eq!(&buf[0x10..], &data[..0x10]);
+            }
+            _ => panic!("expected a partial read"),
+        }
+    }
+
+    #[test]
+    fn read_boxed() {
+        let data = (0..0x2000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &data);
+        let base = proc.info().address;
+
+        let boxed = proc.read_box::<[u8; 0x2000]>(base).unwrap();
+        assert_eq!(&boxed[..], data.as_slice());
+
+        assert_eq!(*proc.read_box::<u16>(base + 2usize).unwrap(), 0x0302);
+        assert!(proc.read_box::<()>(base).is_ok());
+
+        match proc.read_box::<[u8; 0x20]>(base - 0x10usize) {
+            Err(PartialError::PartialVirtualRead(boxed)) => {
+                assert_eq!(&boxed[..0x10], &[0; 0x10]);
+                assert_eq!(&boxed[0x10..], &data[..0x10]);
+            }
+            _ => panic!("expected a partial read"),
+        }
+    }
+
+    #[test]
+    fn read_cancelled() {
+        let data = vec![0xffu8; 0x4000];
+        let mut proc = DummyOs::quick_process(size::mb(2), &data);
+        let base = proc.info().address;
+
+        let token = CancellationToken::new();
+        let mut reports = vec![];
+        let mut ctx = OpContext::new()
+            .chunk_size(0x1000)
+            .cancel(token.clone())
+            .progress(|done, total| {
+                reports.push((done, total));
+                if done == 0x2000 {
+                    token.cancel();
+                }
+            });
+
+        let mut buf = vec![0u8; 0x4000];
+        let res = proc.read_raw_into_ctx(base, &mut buf, &mut ctx);
+        drop(ctx);
+
+        assert!(matches!(
+            res,
+            Err(PartialError::Error(Error(_, ErrorKind::Cancelled)))
+        ));
+        assert_eq!(reports, vec![(0x1000, 0x4000), (0x2000, 0x4000)]);
+        assert_eq!(&buf[..0x2000], &data[..0x2000]);
+        assert_eq!(&buf[0x2000..], &[0; 0x2000][..]);
+    }
+
+    #[test]
+    fn list_cancelled() {
+        let data = vec![0xffu8; 0x4000];
+        let mut proc = DummyOs::quick_process(size::mb(2), &data);
+        let base = proc.info().address;
+
+        let mut bufs = vec![[0u8; 0x1000]; 4];
+        let mut list = bufs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, buf)| CTup2(base + i * 0x1000, buf.as_mut().into()))
+            .collect::<Vec<_>>();
+
+        let mut last = None;
+        let mut ctx = OpContext::new().progress(|done, total| last = Some((done, total)));
+        assert!(proc.read_raw_list_ctx(&mut list, &mut ctx).is_ok());
+        drop(ctx);
+        assert_eq!(last, Some((0x4000, 0x4000)));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut ctx = OpContext::new().cancel(token);
+        let writes = [CTup2(base, [0u8; 0x10][..].into())];
+        assert!(matches!(
+            proc.write_raw_list_ctx(&writes, &mut ctx),
+            Err(PartialError::Error(Error(_, ErrorKind::Cancelled)))
+        ));
+        assert_eq!(proc.read::<[u8; 0x10]>(base).unwrap(), [0xff; 0x10]);
+    }
+}
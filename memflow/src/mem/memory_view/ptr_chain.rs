@@ -0,0 +1,388 @@
+//! Pointer chain resolution.
+//!
+//! A pointer chain describes a sequence of offsets and dereferences starting at a base address,
+//! as commonly found when following nested structures in a target process.
+//!
+//! Chains can either be resolved one by one via [`MemoryView::ptr_chain`], or in bulk via
+//! [`resolve_chains`], which dereferences all chains in lock-step and batches each hop into a
+//! single read.
+use super::*;
+
+use super::remote_struct::{ptr_from_bytes, ptr_size};
+
+use std::fmt;
+
+/// Single step of a [`PtrChain`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PtrChainStep {
+    /// Adds a (signed) offset to the current address.
+    Offset(imem),
+    /// Reads a pointer (with the pointer width of the memory view) at the current address.
+    Deref,
+}
+
+/// Description of a pointer chain.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::mem::PtrChain;
+/// use memflow::types::Address;
+///
+/// // [[base + 0x18] + 0x40]
+/// let chain = PtrChain::new(Address::from(0x1000))
+///     .offset(0x18)
+///     .deref()
+///     .offset(0x40)
+///     .deref();
+///
+/// assert_eq!(chain.hops(), 2);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PtrChain {
+    base: Address,
+    steps: Vec<PtrChainStep>,
+}
+
+impl PtrChain {
+    /// Creates a new pointer chain starting at `base`.
+    pub fn new(base: Address) -> Self {
+        Self {
+            base,
+            steps: vec![],
+        }
+    }
+
+    /// Appends an offset to the chain.
+    pub fn offset(mut self, offset: imem) -> Self {
+        self.steps.push(PtrChainStep::Offset(offset));
+        self
+    }
+
+    /// Appends a pointer dereference to the chain.
+    pub fn deref(mut self) -> Self {
+        self.steps.push(PtrChainStep::Deref);
+        self
+    }
+
+    /// Returns the base address of the chain.
+    pub fn base(&self) -> Address {
+        self.base
+    }
+
+    /// Returns all steps of the chain.
+    pub fn steps(&self) -> &[PtrChainStep] {
+        &self.steps
+    }
+
+    /// Returns the amount of dereferences in the chain.
+    pub fn hops(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|s| **s == PtrChainStep::Deref)
+            .count()
+    }
+
+    /// Resolves the chain and returns the final address.
+    pub fn resolve<M: MemoryView>(
+        &self,
+        mem: &mut M,
+    ) -> std::result::Result<Address, PtrChainError> {
+        resolve_chains(mem, std::slice::from_ref(self)).remove(0)
+    }
+
+    /// Resolves the chain and reads a value at the final address.
+    ///
+    /// A failed final read is reported with `hop` set to [`PtrChain::hops`].
+    pub fn read<T: Pod + Sized, M: MemoryView>(
+        &self,
+        mem: &mut M,
+    ) -> std::result::Result<T, PtrChainError> {
+        let address = self.resolve(mem)?;
+        mem.read(address).data().map_err(|error| PtrChainError {
+            hop: self.hops(),
+            address,
+            error,
+        })
+    }
+}
+
+/// Error returned when a pointer chain can not be resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PtrChainError {
+    /// Index of the dereference that failed.
+    pub hop: usize,
+    /// Address that was accessed by the failing hop.
+    pub address: Address,
+    /// The underlying error.
+    pub error: Error,
+}
+
+impl fmt::Display for PtrChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pointer chain failed at hop {} ({:x}): {}",
+            self.hop, self.address, self.error
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PtrChainError {}
+
+impl From<PtrChainError> for Error {
+    fn from(err: PtrChainError) -> Self {
+        err.error
+    }
+}
+
+impl<T> From<PtrChainError> for PartialError<T> {
+    fn from(err: PtrChainError) -> Self {
+        PartialError::Error(err.error)
+    }
+}
+
+/// Pointer chain builder bound to a memory view.
+///
+/// This is created by [`MemoryView::ptr_chain`].
+pub struct PtrChainBuilder<'a, M> {
+    mem: &'a mut M,
+    chain: PtrChain,
+}
+
+impl<'a, M: MemoryView> PtrChainBuilder<'a, M> {
+    pub fn new(mem: &'a mut M, base: Address) -> Self {
+        Self {
+            mem,
+            chain: PtrChain::new(base),
+        }
+    }
+
+    /// Appends an offset to the chain.
+    pub fn offset(self, offset: imem) -> Self {
+        Self {
+            chain: self.chain.offset(offset),
+            ..self
+        }
+    }
+
+    /// Appends a pointer dereference to the chain.
+    pub fn deref(self) -> Self {
+        Self {
+            chain: self.chain.deref(),
+            ..self
+        }
+    }
+
+    /// Resolves the chain and returns the final address.
+    pub fn resolve(self) -> std::result::Result<Address, PtrChainError> {
+        self.chain.resolve(self.mem)
+    }
+
+    /// Resolves the chain and reads a value at the final address.
+    pub fn read<T: Pod + Sized>(self) -> std::result::Result<T, PtrChainError> {
+        self.chain.read(self.mem)
+    }
+
+    /// Returns the chain description without resolving it.
+    pub fn into_chain(self) -> PtrChain {
+        self.chain
+    }
+}
+
+struct ChainState {
+    address: Address,
+    step: usize,
+    hop: usize,
+    result: Option<std::result::Result<Address, PtrChainError>>,
+}
+
+/// Resolves multiple pointer chains at once.
+///
+/// All chains are walked in lock-step, so each level of dereferences is performed in a single
+/// batched read. The results are returned in the same order as the input chains.
+///
+/// A chain fails if a pointer could not be read, or if it points to a null address.
+pub fn resolve_chains<M: MemoryView>(
+    mem: &mut M,
+    chains: &[PtrChain],
+) -> Vec<std::result::Result<Address, PtrChainError>> {
+    let meta = mem.metadata();
+    let ptr_size = ptr_size(&meta);
+
+    let mut states = chains
+        .iter()
+        .map(|c| ChainState {
+            address: c.base,
+            step: 0,
+            hop: 0,
+            result: None,
+        })
+        .collect::<Vec<_>>();
+
+    loop {
+        // advance all chains up to their next dereference
+        for (chain, state) in chains.iter().zip(states.iter_mut()) {
+            if state.result.is_some() {
+                continue;
+            }
+
+            while let Some(PtrChainStep::Offset(offset)) = chain.steps.get(state.step) {
+                state.address = state.address.to_umem().wrapping_add(*offset as umem).into();
+                state.step += 1;
+            }
+
+            if state.step >= chain.steps.len() {
+                state.result = Some(Ok(state.address));
+            }
+        }
+
+        let pending = states
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.result.is_none())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        if pending.is_empty() {
+            break;
+        }
+
+        let mut bufs = vec![[0u8; 8]; pending.len()];
+        let mut failed = vec![];
+
+        let res = {
+            let states = &states;
+            let iter = pending
+                .iter()
+                .zip(bufs.iter_mut())
+                .map(|(&i, buf)| CTup2(states[i].address, (&mut buf[..ptr_size]).into()));
+
+            let callback = &mut |CTup2(addr, _): ReadData| {
+                failed.push(addr);
+                true
+            };
+
+            mem.read_iter(iter, None, Some(&mut callback.into()))
+        };
+
+        for (&i, buf) in pending.iter().zip(bufs.iter()) {
+            let state = &mut states[i];
+            let start = state.address;
+            let end = start + ptr_size;
+
+            let err = if let Err(error) = res {
+                Some(error)
+            } else if failed.iter().any(|&a| a >= start && a < end) {
+                Some(Error(ErrorOrigin::Pointer, ErrorKind::UnableToReadMemory))
+            } else {
+                let ptr = ptr_from_bytes(buf, &meta);
+                if ptr.is_null() {
+                    Some(Error(ErrorOrigin::Pointer, ErrorKind::OutOfBounds))
+                } else {
+                    state.address = ptr;
+                    state.step += 1;
+                    state.hop += 1;
+                    None
+                }
+            };
+
+            if let Some(error) = err {
+                state.result = Some(Err(PtrChainError {
+                    hop: state.hop,
+                    address: start,
+                    error,
+                }));
+            }
+        }
+    }
+
+    states.into_iter().map(|s| s.result.unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    #[test]
+    fn resolve_chain() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let a = base + 0x100usize;
+        let b = base + 0x200usize;
+
+        proc.write(base + 0x18usize, &a.to_umem()).unwrap();
+        proc.write(a + 0x40usize, &b.to_umem()).unwrap();
+        proc.write(b + 0x8usize, &1.5f32).unwrap();
+
+        let value = proc
+            .ptr_chain(base)
+            .offset(0x18)
+            .deref()
+            .offset(0x40)
+            .deref()
+            .offset(0x8)
+            .read::<f32>()
+            .unwrap();
+
+        assert_eq!(value, 1.5);
+
+        let addr = proc
+            .ptr_chain(b + 0x10usize)
+            .offset(-0x8)
+            .resolve()
+            .unwrap();
+        assert_eq!(addr, b + 0x8usize);
+    }
+
+    #[test]
+    fn resolve_chain_failed_hop() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let invalid = base + size::gb(1);
+        proc.write(base, &invalid.to_umem()).unwrap();
+
+        let err = proc.ptr_chain(base).deref().deref().resolve().unwrap_err();
+        assert_eq!(err.hop, 1);
+        assert_eq!(err.address, invalid);
+
+        let err = proc
+            .ptr_chain(base + 0x8usize)
+            .deref()
+            .resolve()
+            .unwrap_err();
+        assert_eq!(err.hop, 0);
+        assert_eq!(
+            err.error,
+            Error(ErrorOrigin::Pointer, ErrorKind::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn resolve_many_chains() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        for i in 0..8usize {
+            let target = base + 0x1000usize + i * 8;
+            proc.write(base + i * 8, &target.to_umem()).unwrap();
+        }
+
+        let chains = (0..8)
+            .map(|i| PtrChain::new(base).offset(i * 8).deref().offset(4))
+            .chain(std::iter::once(PtrChain::new(base + 0x2000usize).deref()))
+            .collect::<Vec<_>>();
+
+        let results = resolve_chains(&mut proc, &chains);
+
+        for (i, res) in results.iter().take(8).enumerate() {
+            assert_eq!(*res, Ok(base + 0x1000usize + i * 8 + 4usize));
+        }
+        assert_eq!(results[8].unwrap_err().hop, 0);
+    }
+}
@@ -0,0 +1,174 @@
+//! Batched reads of multiple fields.
+//!
+//! See the [`read_fields`](crate::read_fields) macro.
+
+use super::*;
+
+use super::remote_struct::{ptr_from_bytes, ptr_size};
+
+/// Value that can be read as part of a [`read_fields`](crate::read_fields) batch.
+///
+/// This trait is implemented for all `Pod` types, as well as for [`Address`], which is read with
+/// the pointer width of the underlying [`MemoryView`].
+#[doc(hidden)]
+pub trait ReadField: Sized {
+    type Raw: Pod;
+
+    fn raw_size(meta: &MemoryViewMetadata) -> usize;
+
+    fn from_raw(raw: Self::Raw, meta: &MemoryViewMetadata) -> Self;
+}
+
+impl<T: Pod> ReadField for T {
+    type Raw = T;
+
+    fn raw_size(_: &MemoryViewMetadata) -> usize {
+        core::mem::size_of::<T>()
+    }
+
+    fn from_raw(raw: Self::Raw, _: &MemoryViewMetadata) -> Self {
+        raw
+    }
+}
+
+impl ReadField for Address {
+    type Raw = [u8; 8];
+
+    fn raw_size(meta: &MemoryViewMetadata) -> usize {
+        ptr_size(meta)
+    }
+
+    fn from_raw(raw: Self::Raw, meta: &MemoryViewMetadata) -> Self {
+        ptr_from_bytes(&raw, meta)
+    }
+}
+
+/// Reads multiple fields relative to a base address in a single batch.
+///
+/// The macro collects all fields into one [`MemoryView::read_raw_list`] call and returns a
+/// `PartialResult` of an anonymous struct that contains every field by name.
+/// If only some of the fields could be read a `PartialVirtualRead` error is returned that
+/// contains the structure with the failed fields zeroed out.
+///
+/// Field types have to be `Pod`, or [`Address`], which is read with the pointer width of the
+/// memory view. Types consisting of more than a single token (like `Pointer64<u32>`) have to be
+/// wrapped in parentheses.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::prelude::v1::*;
+///
+/// fn read_player(mem: &mut impl MemoryView, base: Address) {
+///     let player = memflow::read_fields!(*mem, base, {
+///         id: u32 @ 0x10,
+///         hp: f32 @ 0x24,
+///         next: Address @ 0x40,
+///     })
+///     .unwrap();
+///
+///     println!("{} {} {:x}", player.id, player.hp, player.next);
+/// }
+/// # let mut proc = memflow::dummy::DummyOs::quick_process(size::mb(2), &[]);
+/// # let base = proc.info().address;
+/// # read_player(&mut proc, base);
+/// ```
+#[macro_export]
+macro_rules! read_fields {
+    ($view:expr, $base:expr, { $($name:ident : $ty:tt @ $offset:expr),+ $(,)? }) => {{
+        use $crate::mem::memory_view::read_fields::ReadField as __ReadField;
+
+        let __view = &mut $view;
+        let __base: $crate::types::Address = $base;
+        let __meta = $crate::mem::MemoryView::metadata(&*__view);
+
+        $(
+            let mut $name: <$ty as __ReadField>::Raw =
+                $crate::mem::memory_view::remote_struct::pod_zeroed();
+        )+
+
+        let __res = {
+            let mut __data = [$(
+                $crate::cglue::CTup2(
+                    __base + ($offset as $crate::types::umem),
+                    (&mut $crate::dataview::Pod::as_bytes_mut(&mut $name)
+                        [..<$ty as __ReadField>::raw_size(&__meta)])
+                        .into(),
+                )
+            ),+];
+            $crate::mem::MemoryView::read_raw_list(__view, &mut __data)
+        };
+
+        #[allow(non_camel_case_types, dead_code)]
+        struct __ReadFields {
+            $($name: $ty),+
+        }
+
+        let __out = __ReadFields {
+            $($name: <$ty as __ReadField>::from_raw($name, &__meta)),+
+        };
+
+        let mut __partial = false;
+        match $crate::mem::memory_view::remote_struct::merge_partial(__res, &mut __partial) {
+            Err(e) => Err(e.into()),
+            Ok(_) if __partial => Err($crate::error::PartialError::PartialVirtualRead(__out)),
+            Ok(_) => Ok(__out),
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dummy::DummyOs;
+    use crate::error::PartialError;
+    use crate::mem::MemoryView;
+    use crate::os::Process;
+    use crate::types::{size, Address};
+
+    #[test]
+    fn read_fields() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write(base + 0x10usize, &1337u32).unwrap();
+        proc.write(base + 0x24usize, &42.5f32).unwrap();
+        proc.write(base + 0x40usize, &(base + 0x100usize).to_umem())
+            .unwrap();
+
+        let fields = crate::read_fields!(proc, base, {
+            id: u32 @ 0x10,
+            hp: f32 @ 0x24,
+            next: Address @ 0x40,
+            raw: [u8; 2] @ 0x10,
+            wide: (crate::types::Pointer64<u32>) @ 0x40,
+        })
+        .unwrap();
+
+        assert_eq!(fields.id, 1337);
+        assert_eq!(fields.raw, [0x39, 0x05]);
+        assert_eq!(fields.wide.inner, (base + 0x100usize).to_umem());
+        assert_eq!(fields.hp, 42.5);
+        assert_eq!(fields.next, base + 0x100usize);
+    }
+
+    #[test]
+    fn read_fields_partial() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write(base, &0xffu8).unwrap();
+
+        let res = crate::read_fields!(proc, base, {
+            valid: u8 @ 0,
+            invalid: u64 @ size::gb(1),
+        });
+
+        match res {
+            Err(PartialError::PartialVirtualRead(fields)) => {
+                assert_eq!(fields.valid, 0xff);
+                assert_eq!(fields.invalid, 0);
+            }
+            _ => panic!("expected a partial read"),
+        }
+    }
+}
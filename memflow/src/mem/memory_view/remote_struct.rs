@@ -0,0 +1,227 @@
+//! Reading of structures with explicitly placed fields.
+//!
+//! The [`RemoteStruct`] trait is usually implemented with the `RemoteStruct` derive macro.
+//! See the documentation of the derive macro for the supported field attributes.
+//!
+//! The helper functions contained in this module are used by the generated code and are not meant to be
+//! called directly.
+
+use super::*;
+
+use crate::error::{PartialError, PartialResult, Result};
+
+use std::prelude::v1::*;
+
+/// Upper bound for the amount of bytes read for a single `#[utf16]` field.
+pub const MAX_UTF16_LEN: usize = 0x10000;
+
+/// Structure that can be read field by field from a [`MemoryView`].
+///
+/// Unlike `Pod` types the in-memory layout of the implementor does not have to match the
+/// remote layout. Only the annotated fields are read, in a single scatter read.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::prelude::v1::*;
+///
+/// #[derive(RemoteStruct)]
+/// struct Entry {
+///     #[offset(0x0)]
+///     id: u32,
+///     #[offset(0x10)]
+///     #[ptr]
+///     next: Address,
+/// }
+///
+/// fn read_entry(mem: &mut impl MemoryView, addr: Address) {
+///     let entry: Entry = mem.read_remote(addr).unwrap();
+///     println!("id: {} next: {:x}", entry.id, entry.next);
+/// }
+/// # let mut proc = memflow::dummy::DummyOs::quick_process(size::mb(2), &[]);
+/// # let addr = proc.info().address;
+/// # read_entry(&mut proc, addr);
+/// ```
+pub trait RemoteStruct: Sized {
+    /// Reads the structure located at `base`.
+    ///
+    /// If some of the fields could not be read a `PartialVirtualRead` error is returned that
+    /// contains the structure with the failed fields zeroed out.
+    fn read_remote<M: MemoryView>(mem: &mut M, base: Address) -> PartialResult<Self>;
+}
+
+#[doc(hidden)]
+#[allow(clippy::uninit_assumed_init)]
+pub fn pod_zeroed<T: Pod>() -> T {
+    unsafe { MaybeUninit::zeroed().assume_init() }
+}
+
+#[doc(hidden)]
+pub fn ptr_size(meta: &MemoryViewMetadata) -> usize {
+    if meta.arch_bits == 32 {
+        4
+    } else {
+        8
+    }
+}
+
+#[doc(hidden)]
+pub fn ptr_from_bytes(buf: &[u8; 8], meta: &MemoryViewMetadata) -> Address {
+    let mut low = [0u8; 4];
+    low.copy_from_slice(&buf[..4]);
+    match (ptr_size(meta), meta.little_endian) {
+        (4, true) => u32::from_le_bytes(low).into(),
+        (4, false) => u32::from_be_bytes(low).into(),
+        (_, true) => u64::from_le_bytes(*buf).into(),
+        (_, false) => u64::from_be_bytes(*buf).into(),
+    }
+}
+
+#[doc(hidden)]
+pub fn utf16_buf(len: usize) -> Vec<u8> {
+    vec![0; std::cmp::min(len, MAX_UTF16_LEN) & !1]
+}
+
+#[doc(hidden)]
+pub fn utf16_string(buf: &[u8], little_endian: bool) -> String {
+    let end = utf16::terminator(buf).unwrap_or(buf.len());
+    utf16::decode_utf16(&buf[..end], little_endian, Utf16Mode::Lossy).unwrap_or_default()
+}
+
+#[doc(hidden)]
+pub fn read_ptr_field<M: MemoryView>(mem: &mut M, addr: Address) -> PartialResult<Address> {
+    let meta = mem.metadata();
+    let mut raw = [0u8; 8];
+    mem.read_raw_into(addr, &mut raw[..ptr_size(&meta)])
+        .map_data(|_| ptr_from_bytes(&raw, &meta))
+}
+
+#[doc(hidden)]
+pub fn read_utf16_field<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    len: usize,
+) -> PartialResult<String> {
+    let ptr = read_ptr_field(mem, addr).data()?;
+    let mut buf = utf16_buf(if ptr.is_null() { 0 } else { len });
+    if buf.is_empty() {
+        return Ok(String::new());
+    }
+
+    let little_endian = mem.metadata().little_endian;
+    mem.read_raw_into(ptr, &mut buf)
+        .map_data(|_| utf16_string(&buf, little_endian))
+}
+
+#[doc(hidden)]
+pub fn read_list<'a>() -> Vec<ReadData<'a>> {
+    Vec::new()
+}
+
+#[doc(hidden)]
+pub fn merge_partial(res: PartialResult<()>, partial: &mut bool) -> Result<()> {
+    match res {
+        Ok(_) => Ok(()),
+        Err(PartialError::Error(e)) => Err(e),
+        Err(_) => {
+            *partial = true;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::derive::RemoteStruct;
+    use crate::dummy::DummyOs;
+    use crate::mem::{MemoryView, RemoteStruct};
+    use crate::os::Process;
+    use crate::types::{size, Address};
+
+    #[derive(RemoteStruct)]
+    struct TestStruct {
+        #[offset(0x0)]
+        a: u32,
+        #[offset(0x8)]
+        b: u64,
+        #[offset(0x10)]
+        #[ptr]
+        next: Address,
+        #[offset(0x18)]
+        name_len: u16,
+        #[offset(0x20)]
+        #[utf16(name_len)]
+        name: String,
+        local: Option<u8>,
+    }
+
+    #[test]
+    fn read_remote_struct() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let name = "memflow"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        proc.write(base, &0x1234u32).unwrap();
+        proc.write(base + 0x8usize, &0xdead_beefu64).unwrap();
+        proc.write(base + 0x10usize, &(base + 0x40usize).to_umem())
+            .unwrap();
+        proc.write(base + 0x18usize, &(name.len() as u16)).unwrap();
+        proc.write(base + 0x20usize, &(base + 0x100usize).to_umem())
+            .unwrap();
+        proc.write(base + 0x100usize, name.as_slice()).unwrap();
+
+        let s: TestStruct = proc.read_remote(base).unwrap();
+
+        assert_eq!(s.a, 0x1234);
+        assert_eq!(s.b, 0xdead_beef);
+        assert_eq!(s.next, base + 0x40usize);
+        assert_eq!(s.name_len as usize, name.len());
+        assert_eq!(s.name, "memflow");
+        assert_eq!(s.local, None);
+    }
+
+    #[test]
+    fn read_single_fields() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let name = "mf"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        proc.write(base + 0x8usize, &0xdead_beefu64).unwrap();
+        proc.write(base + 0x10usize, &(base + 0x40usize).to_umem())
+            .unwrap();
+        proc.write(base + 0x18usize, &(name.len() as u16)).unwrap();
+        proc.write(base + 0x20usize, &(base + 0x100usize).to_umem())
+            .unwrap();
+        proc.write(base + 0x100usize, name.as_slice()).unwrap();
+
+        assert_eq!(TestStruct::OFFSET_B, 0x8);
+        assert_eq!(TestStruct::OFFSET_NAME, 0x20);
+        assert_eq!(TestStruct::read_b(&mut proc, base).unwrap(), 0xdead_beef);
+        assert_eq!(
+            TestStruct::read_next(&mut proc, base).unwrap(),
+            base + 0x40usize
+        );
+        assert_eq!(TestStruct::read_name(&mut proc, base).unwrap(), "mf");
+    }
+
+    #[test]
+    fn read_remote_struct_null_string() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write(base + 0x18usize, &16u16).unwrap();
+
+        let s: TestStruct = proc.read_remote(base).unwrap();
+
+        assert_eq!(s.next, Address::NULL);
+        assert_eq!(s.name, "");
+    }
+}
@@ -0,0 +1,408 @@
+//! Reads and writes with exact accounting of the accessed bytes.
+//!
+//! The regular batched functions of [`MemoryView`] only tell whether all bytes of a batch could
+//! be accessed. The functions in this module return an [`AccessReport`] for every element of a
+//! batch instead, containing the exact sub-ranges that were read or written successfully.
+//!
+//! Only the bytes reported as accessed by the memory view are considered valid. Every failed
+//! sub-range is reported together with its address and the reason of the failure. Memory views
+//! do not tell why a part of a batch failed, so the reason is derived from the metadata of the
+//! view, unless the whole batch was aborted with an error, in which case the parts that were not
+//! reached carry the kind of that error.
+
+use super::*;
+
+use core::ops::Range;
+use std::prelude::v1::*;
+
+/// Failed sub-range of a single read or write.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessFailure {
+    /// Offsets relative to the address of the access.
+    pub range: Range<usize>,
+    /// Address of the first failed byte.
+    pub address: Address,
+    /// Reason of the failure.
+    pub kind: ErrorKind,
+}
+
+/// Accessed and failed sub-ranges of a single read or write.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessReport {
+    addr: Address,
+    len: usize,
+    /// Sorted and merged offsets relative to `addr`.
+    valid: Vec<Range<usize>>,
+    /// Sorted failures covering everything not in `valid`.
+    failed: Vec<AccessFailure>,
+}
+
+impl AccessReport {
+    /// Creates a report from the accessed `pieces` and the `reported` failures.
+    ///
+    /// Bytes that are neither accessed nor reported as failed are attributed to `unreported`.
+    fn new(
+        addr: Address,
+        len: usize,
+        pieces: Vec<Range<usize>>,
+        mut reported: Vec<(Range<usize>, ErrorKind)>,
+        unreported: impl Fn(Address) -> ErrorKind,
+    ) -> Self {
+        let valid = merge(pieces);
+        reported.sort_unstable_by_key(|(r, _)| r.start);
+
+        let mut failed = vec![];
+        let mut push = |range: Range<usize>, kind: ErrorKind| match failed.last_mut() {
+            Some(AccessFailure {
+                range: last,
+                kind: last_kind,
+                ..
+            }) if last.end == range.start && *last_kind == kind => last.end = range.end,
+            _ => failed.push(AccessFailure {
+                address: addr + range.start,
+                range,
+                kind,
+            }),
+        };
+
+        for gap in gaps(&valid, len) {
+            let mut pos = gap.start;
+            for (r, kind) in reported.iter() {
+                let start = std::cmp::max(r.start, pos);
+                let end = std::cmp::min(r.end, gap.end);
+                if start >= end {
+                    continue;
+                }
+                if start > pos {
+                    push(pos..start, unreported(addr + pos));
+                }
+                push(start..end, *kind);
+                pos = end;
+            }
+            if pos < gap.end {
+                push(pos..gap.end, unreported(addr + pos));
+            }
+        }
+
+        Self {
+            addr,
+            len,
+            valid,
+            failed,
+        }
+    }
+
+    /// Returns the address of the access.
+    pub fn address(&self) -> Address {
+        self.addr
+    }
+
+    /// Returns the length of the access in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if all bytes were accessed successfully.
+    pub fn is_complete(&self) -> bool {
+        self.valid_len() == self.len
+    }
+
+    /// Returns the amount of bytes accessed successfully.
+    pub fn valid_len(&self) -> usize {
+        self.valid.iter().map(|r| r.end - r.start).sum()
+    }
+
+    /// Returns the successfully accessed ranges as offsets relative to the address, in order.
+    pub fn valid_ranges(&self) -> &[Range<usize>] {
+        &self.valid
+    }
+
+    /// Returns the failed ranges as offsets relative to the address, in order.
+    pub fn failed_ranges(&self) -> Vec<Range<usize>> {
+        gaps(&self.valid, self.len)
+    }
+
+    /// Returns the failed sub-ranges together with the reason of every failure, in order.
+    pub fn failures(&self) -> &[AccessFailure] {
+        &self.failed
+    }
+
+    /// Returns `true` if the byte at `offset` was accessed successfully.
+    pub fn is_valid(&self, offset: usize) -> bool {
+        let idx = self.valid.partition_point(|r| r.start <= offset);
+        idx > 0 && offset < self.valid[idx - 1].end
+    }
+}
+
+/// Sorts and merges overlapping or adjacent ranges.
+fn merge(mut pieces: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    pieces.sort_unstable_by_key(|r| r.start);
+
+    let mut merged: Vec<Range<usize>> = vec![];
+    for piece in pieces.into_iter().filter(|r| r.start < r.end) {
+        match merged.last_mut() {
+            Some(last) if last.end >= piece.start => last.end = std::cmp::max(last.end, piece.end),
+            _ => merged.push(piece),
+        }
+    }
+    merged
+}
+
+/// Returns the ranges of `0..len` not covered by the sorted and merged `valid` ranges.
+fn gaps(valid: &[Range<usize>], len: usize) -> Vec<Range<usize>> {
+    let mut gaps = vec![];
+    let mut pos = 0;
+    for r in valid.iter() {
+        if r.start > pos {
+            gaps.push(pos..r.start);
+        }
+        pos = r.end;
+    }
+    if pos < len {
+        gaps.push(pos..len);
+    }
+    gaps
+}
+
+/// Derives the reason of a failed access at `addr` from the metadata of the memory view.
+fn failure_kind(metadata: &MemoryViewMetadata, addr: Address, write: bool) -> ErrorKind {
+    if addr > metadata.max_address {
+        ErrorKind::OutOfMemoryRange
+    } else if write && metadata.readonly {
+        ErrorKind::ReadOnly
+    } else if write {
+        ErrorKind::UnableToWriteMemory
+    } else {
+        ErrorKind::UnableToReadMemory
+    }
+}
+
+/// Splits the accessed and failed pieces, given as offsets into the whole batch, into reports.
+fn into_reports(
+    elems: &[(Address, usize)],
+    starts: &[umem],
+    pieces: Vec<(umem, usize)>,
+    failed: Vec<(umem, usize)>,
+    metadata: MemoryViewMetadata,
+    write: bool,
+    batch_error: Option<Error>,
+) -> Vec<AccessReport> {
+    let elem_of = |offset: umem| {
+        let idx = starts.partition_point(|&start| start <= offset) - 1;
+        (idx, (offset - starts[idx]) as usize)
+    };
+
+    let mut per_elem = vec![vec![]; elems.len()];
+    for (offset, len) in pieces {
+        let (idx, off) = elem_of(offset);
+        per_elem[idx].push(off..off + len);
+    }
+
+    let mut failed_per_elem = vec![vec![]; elems.len()];
+    for (offset, len) in failed {
+        let (idx, off) = elem_of(offset);
+        let kind = failure_kind(&metadata, elems[idx].0 + off, write);
+        failed_per_elem[idx].push((off..off + len, kind));
+    }
+
+    // parts of an aborted batch that were never reached fail with the error of the batch
+    let unreported = |addr| match batch_error {
+        Some(Error(_, kind)) => kind,
+        None => failure_kind(&metadata, addr, write),
+    };
+
+    elems
+        .iter()
+        .zip(per_elem)
+        .zip(failed_per_elem)
+        .map(|((&(addr, len), pieces), failed)| {
+            AccessReport::new(addr, len, pieces, failed, &unreported)
+        })
+        .collect()
+}
+
+fn starts_of(elems: &[(Address, usize)]) -> Vec<umem> {
+    elems
+        .iter()
+        .scan(0, |offset, &(_, len)| {
+            let start = *offset;
+            *offset += len as umem;
+            Some(start)
+        })
+        .collect()
+}
+
+/// Returns the batch error if nothing at all was accessed, so that the error is not hidden
+/// behind a partial result.
+fn batch_result(res: Result<()>, pieces: &[(umem, usize)]) -> Result<Option<Error>> {
+    match res {
+        Ok(_) => Ok(None),
+        Err(err) if pieces.is_empty() => Err(err),
+        Err(err) => Ok(Some(err)),
+    }
+}
+
+pub(crate) fn read_raw_list_report<M: MemoryView>(
+    mem: &mut M,
+    data: &mut [ReadData],
+) -> PartialResult<Vec<AccessReport>> {
+    let metadata = mem.metadata();
+    let elems = data
+        .iter()
+        .map(|CTup2(addr, buf)| (*addr, buf.len()))
+        .collect::<Vec<_>>();
+    let starts = starts_of(&elems);
+
+    let mut pieces = vec![];
+    let mut failed_pieces = vec![];
+    let succeeded = &mut |CTup2(offset, buf): ReadData| {
+        pieces.push((offset.to_umem(), buf.len()));
+        true
+    };
+    let failed = &mut |CTup2(offset, mut buf): ReadData| {
+        // same as read_raw_list, failed data is zeroed out
+        for b in buf.iter_mut() {
+            *b = 0;
+        }
+        failed_pieces.push((offset.to_umem(), buf.len()));
+        true
+    };
+
+    // the meta addresses are offsets into the whole batch
+    let iter = data
+        .iter()
+        .zip(starts.iter())
+        .map(|(CTup2(addr, buf), &start)| CTup3(*addr, Address::from(start), buf.into()));
+
+    let res = MemOps::with_raw(
+        iter,
+        Some(&mut succeeded.into()),
+        Some(&mut failed.into()),
+        |data| mem.read_raw_iter(data),
+    );
+    let batch_error = batch_result(res, &pieces)?;
+
+    let reports = into_reports(
+        &elems,
+        &starts,
+        pieces,
+        failed_pieces,
+        metadata,
+        false,
+        batch_error,
+    );
+    if reports.iter().all(AccessReport::is_complete) {
+        Ok(reports)
+    } else {
+        Err(PartialError::PartialVirtualRead(reports))
+    }
+}
+
+pub(crate) fn write_raw_list_report<M: MemoryView>(
+    mem: &mut M,
+    data: &[WriteData],
+) -> PartialResult<Vec<AccessReport>> {
+    let metadata = mem.metadata();
+    let elems = data
+        .iter()
+        .map(|CTup2(addr, buf)| (*addr, buf.len()))
+        .collect::<Vec<_>>();
+    let starts = starts_of(&elems);
+
+    let mut pieces = vec![];
+    let mut failed_pieces = vec![];
+    let succeeded = &mut |CTup2(offset, buf): WriteData| {
+        pieces.push((offset.to_umem(), buf.len()));
+        true
+    };
+    let failed = &mut |CTup2(offset, buf): WriteData| {
+        failed_pieces.push((offset.to_umem(), buf.len()));
+        true
+    };
+
+    let iter = data
+        .iter()
+        .zip(starts.iter())
+        .map(|(CTup2(addr, buf), &start)| CTup3(*addr, Address::from(start), *buf));
+
+    let res = MemOps::with_raw(
+        iter,
+        Some(&mut succeeded.into()),
+        Some(&mut failed.into()),
+        |data| mem.write_raw_iter(data),
+    );
+    let batch_error = batch_result(res, &pieces)?;
+
+    let reports = into_reports(
+        &elems,
+        &starts,
+        pieces,
+        failed_pieces,
+        metadata,
+        true,
+        batch_error,
+    );
+    if reports.iter().all(AccessReport::is_complete) {
+        Ok(reports)
+    } else {
+        Err(PartialError::PartialVirtualWrite(reports))
+    }
+}
+
+/// Unwraps the report of a single element batch.
+pub(crate) fn single(res: PartialResult<Vec<AccessReport>>) -> PartialResult<AccessReport> {
+    let first = |mut reports: Vec<AccessReport>| reports.swap_remove(0);
+    match res {
+        Ok(reports) => Ok(first(reports)),
+        Err(PartialError::Error(err)) => Err(PartialError::Error(err)),
+        Err(PartialError::PartialVirtualRead(reports)) => {
+            Err(PartialError::PartialVirtualRead(first(reports)))
+        }
+        Err(PartialError::PartialVirtualWrite(reports)) => {
+            Err(PartialError::PartialVirtualWrite(first(reports)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    #[test]
+    fn partial_read_report() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let end = proc.info().address + size::mb(2);
+
+        proc.write_raw(end - 4usize, &[1, 2, 3, 4]).unwrap();
+
+        // the last 8 bytes are behind the end of the mapping
+        let mut buf = [0xffu8; 12];
+        let report = match proc.read_raw_into_report(end - 4usize, &mut buf) {
+            Err(PartialError::PartialVirtualRead(report)) => report,
+            _ => panic!("expected a partial read"),
+        };
+
+        assert!(!report.is_complete());
+        assert_eq!(report.valid_ranges(), &[0..4]);
+        assert_eq!(report.failed_ranges(), vec![4..12]);
+        assert!(report.is_valid(3) && !report.is_valid(4));
+        assert_eq!(
+            report.failures(),
+            &[AccessFailure {
+                range: 4..12,
+                address: end,
+                kind: ErrorKind::UnableToReadMemory,
+            }]
+        );
+        assert_eq!(buf, [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let report = proc.write_raw_report(end - 8usize, &[0; 4]).unwrap();
+        assert!(report.is_complete());
+        assert!(report.failed_ranges().is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! Batched reads of values of different types.
+//!
+//! [`ScatterReadBuilder`] queues reads of arbitrary [`Pod`] types and byte ranges, and executes
+//! all of them with a single call to [`read_raw_iter`](MemoryView::read_raw_iter). Every queued
+//! read returns a typed [`ScatterHandle`] that is used to look up its result afterwards. Reads
+//! that fail do not affect the other reads of the batch.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyOs;
+//! use memflow::mem::MemoryView;
+//! use memflow::os::Process;
+//! use memflow::types::size;
+//!
+//! let mut proc = DummyOs::quick_process(size::mb(2), &[1, 0, 0, 0, 2, 0]);
+//! let base = proc.info().address;
+//!
+//! let mut scatter = proc.scatter();
+//! let first = scatter.read::<u32>(base);
+//! let second = scatter.read::<u16>(base + 4);
+//! let raw = scatter.read_raw(base, 6);
+//! let results = scatter.execute().unwrap();
+//!
+//! assert_eq!(results.get(first), Some(1));
+//! assert_eq!(results.get(second), Some(2));
+//! assert_eq!(results.get_raw(raw), Some(&[1, 0, 0, 0, 2, 0][..]));
+//! ```
+
+use std::prelude::v1::*;
+
+use super::remote_struct::pod_zeroed;
+use super::*;
+use crate::dataview::Pod;
+use crate::types::Address;
+
+use std::marker::PhantomData;
+use std::ops::Range;
+
+/// Reference to the result of a read queued in a [`ScatterReadBuilder`].
+///
+/// Handles are only valid for the results of the builder that created them.
+pub struct ScatterHandle<V: ?Sized> {
+    idx: usize,
+    _marker: PhantomData<fn() -> *const V>,
+}
+
+impl<V: ?Sized> Clone for ScatterHandle<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V: ?Sized> Copy for ScatterHandle<V> {}
+
+/// Collects reads of different types and executes them as a single batch.
+pub struct ScatterReadBuilder<'a, T: MemoryView> {
+    view: &'a mut T,
+    /// Address and location in the result buffer of all reads
+    reads: Vec<(Address, Range<usize>)>,
+    len: usize,
+}
+
+impl<'a, T: MemoryView> ScatterReadBuilder<'a, T> {
+    pub fn new(view: &'a mut T) -> Self {
+        Self {
+            view,
+            reads: vec![],
+            len: 0,
+        }
+    }
+
+    /// Returns the number of queued reads.
+    pub fn len(&self) -> usize {
+        self.reads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty()
+    }
+
+    /// Queues a read of a value of type `V` at `addr`.
+    pub fn read<V: Pod>(&mut self, addr: Address) -> ScatterHandle<V> {
+        self.push(addr, std::mem::size_of::<V>())
+    }
+
+    /// Queues a read of `len` bytes at `addr`.
+    pub fn read_raw(&mut self, addr: Address, len: usize) -> ScatterHandle<[u8]> {
+        self.push(addr, len)
+    }
+
+    fn push<V: ?Sized>(&mut self, addr: Address, len: usize) -> ScatterHandle<V> {
+        self.reads.push((addr, self.len..self.len + len));
+        self.len += len;
+        ScatterHandle {
+            idx: self.reads.len() - 1,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Executes all queued reads in a single batch.
+    ///
+    /// An error is only returned if the batch as a whole failed, failures of individual reads
+    /// are reported through the results.
+    pub fn execute(self) -> Result<ScatterResults> {
+        let Self { view, reads, len } = self;
+
+        let mut buf = vec![0u8; len];
+        let mut failed = vec![false; reads.len()];
+
+        {
+            // the meta addresses hold the offset into the result buffer
+            let mut rest = buf.as_mut_slice();
+            let mut inp = Vec::with_capacity(reads.len());
+            for (addr, range) in reads.iter().filter(|(_, range)| !range.is_empty()) {
+                let (data, tail) = std::mem::take(&mut rest).split_at_mut(range.len());
+                rest = tail;
+                inp.push(CTup3(
+                    *addr,
+                    Address::from(range.start as umem),
+                    CSliceMut::from(data),
+                ));
+            }
+
+            let callback = &mut |CTup2(offset, _): ReadData| {
+                let offset = offset.to_umem() as usize;
+                let idx = reads.partition_point(|(_, range)| range.end <= offset);
+                failed[idx] = true;
+                true
+            };
+
+            MemOps::with_raw(inp.into_iter(), None, Some(&mut callback.into()), |data| {
+                view.read_raw_iter(data)
+            })?;
+        }
+
+        Ok(ScatterResults {
+            buf,
+            reads: reads
+                .into_iter()
+                .zip(failed)
+                .map(|((_, range), failed)| (range, !failed))
+                .collect(),
+        })
+    }
+}
+
+/// The results of an executed [`ScatterReadBuilder`].
+pub struct ScatterResults {
+    buf: Vec<u8>,
+    /// Location in the buffer and success of all reads
+    reads: Vec<(Range<usize>, bool)>,
+}
+
+impl ScatterResults {
+    /// Returns the value read for `handle`, `None` if the read failed.
+    pub fn get<V: Pod>(&self, handle: ScatterHandle<V>) -> Option<V> {
+        let data = self.data(handle.idx)?;
+        let mut value = pod_zeroed::<V>();
+        value.as_bytes_mut().copy_from_slice(data);
+        Some(value)
+    }
+
+    /// Returns the bytes read for `handle`, `None` if the read failed.
+    pub fn get_raw(&self, handle: ScatterHandle<[u8]>) -> Option<&[u8]> {
+        self.data(handle.idx)
+    }
+
+    /// Returns `true` if the read of `handle` succeeded.
+    pub fn succeeded<V: ?Sized>(&self, handle: ScatterHandle<V>) -> bool {
+        self.reads[handle.idx].1
+    }
+
+    /// Returns the number of reads that failed.
+    pub fn failed_count(&self) -> usize {
+        self.reads.iter().filter(|(_, ok)| !ok).count()
+    }
+
+    fn data(&self, idx: usize) -> Option<&[u8]> {
+        let (range, ok) = &self.reads[idx];
+        if *ok {
+            Some(&self.buf[range.clone()])
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    #[test]
+    fn mixed_reads() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0xff; 0x10]);
+        let base = proc.info().address;
+
+        let mut scatter = proc.scatter();
+        let value = scatter.read::<u64>(base + 8);
+        let empty = scatter.read_raw(base, 0);
+        let unmapped = scatter.read::<u32>(Address::null());
+        let tail = scatter.read::<[u8; 2]>(base + 0xe);
+        assert_eq!(scatter.len(), 4);
+
+        let results = scatter.execute().unwrap();
+        assert_eq!(results.get(value), Some(u64::MAX));
+        assert_eq!(results.get_raw(empty), Some(&[][..]));
+        assert!(!results.succeeded(unmapped));
+        assert_eq!(results.get(tail), Some([0xff; 2]));
+        assert_eq!(results.failed_count(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+//! Reading of UTF-16 strings.
+//!
+//! Windows stores nearly all of its strings as UTF-16, either null-terminated or wrapped in a
+//! `UNICODE_STRING` structure. The functions in this module back the UTF-16 helpers of
+//! [`MemoryView`], the byte order of the strings is taken from the metadata of the view.
+
+use super::*;
+
+use std::prelude::v1::*;
+
+/// Maximum number of UTF-16 code units read by [`MemoryView::read_utf16_string`].
+pub const DEFAULT_UTF16_LEN: usize = 4096;
+
+/// Handling of invalid UTF-16 sequences, like unpaired surrogates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Utf16Mode {
+    /// Invalid sequences are replaced with `U+FFFD`.
+    Lossy,
+    /// Invalid sequences result in an `Encoding` error.
+    Strict,
+}
+
+/// Decodes `buf` as UTF-16, a trailing odd byte is ignored.
+pub fn decode_utf16(buf: &[u8], little_endian: bool, mode: Utf16Mode) -> Result<String> {
+    let units = buf.chunks_exact(2).map(|c| {
+        if little_endian {
+            u16::from_le_bytes([c[0], c[1]])
+        } else {
+            u16::from_be_bytes([c[0], c[1]])
+        }
+    });
+
+    match mode {
+        Utf16Mode::Lossy => Ok(std::char::decode_utf16(units)
+            .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+            .collect()),
+        Utf16Mode::Strict => std::char::decode_utf16(units)
+            .collect::<std::result::Result<String, _>>()
+            .map_err(|err| Error(ErrorOrigin::VirtualMemory, ErrorKind::Encoding).log_error(err)),
+    }
+}
+
+/// Returns the byte offset of the first null code unit in `buf`.
+pub(crate) fn terminator(buf: &[u8]) -> Option<usize> {
+    buf.chunks_exact(2)
+        .position(|c| c[0] == 0 && c[1] == 0)
+        .map(|i| i * 2)
+}
+
+pub(crate) fn read_utf16_array<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    len: usize,
+    mode: Utf16Mode,
+) -> PartialResult<String> {
+    let mut buf = vec![0; len * 2];
+    mem.read_raw_into(addr, &mut buf).data_part()?;
+    if let Some(end) = terminator(&buf) {
+        buf.truncate(end);
+    }
+    Ok(decode_utf16(&buf, mem.metadata().little_endian, mode)?)
+}
+
+pub(crate) fn read_utf16_string_n<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    n: usize,
+    mode: Utf16Mode,
+) -> PartialResult<String> {
+    let max = n.saturating_mul(2);
+    let mut buf = vec![0; std::cmp::min(64, max)];
+
+    let mut last = 0;
+
+    loop {
+        mem.read_raw_into(addr + last, &mut buf[last..])
+            .data_part()?;
+        if let Some(end) = terminator(&buf[last..]) {
+            buf.truncate(last + end);
+            return Ok(decode_utf16(&buf, mem.metadata().little_endian, mode)?);
+        }
+        if buf.len() >= max {
+            break;
+        }
+        last = buf.len();
+
+        buf.resize(std::cmp::min(last * 2, max), 0);
+    }
+
+    Err(PartialError::Error(Error(
+        ErrorOrigin::VirtualMemory,
+        ErrorKind::OutOfBounds,
+    )))
+}
+
+pub(crate) fn read_unicode_string<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    mode: Utf16Mode,
+) -> PartialResult<String> {
+    let metadata = mem.metadata();
+    let ptr_size = if metadata.arch_bits == 64 { 8 } else { 4 };
+
+    // Length and MaximumLength, followed by the naturally aligned Buffer pointer
+    let mut header = [0u8; 16];
+    let header = &mut header[..ptr_size * 2];
+    mem.read_raw_into(addr, header).data()?;
+
+    let uint = |bytes: &[u8]| {
+        let fold = |value: u64, &byte: &u8| (value << 8) | u64::from(byte);
+        if metadata.little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        }
+    };
+
+    let len = uint(&header[0..2]) as usize;
+    let max_len = uint(&header[2..4]) as usize;
+    let buffer = Address::from(uint(&header[ptr_size..]) as umem);
+
+    if mode == Utf16Mode::Strict && (len > max_len || len % 2 != 0) {
+        return Err(PartialError::Error(
+            Error(ErrorOrigin::VirtualMemory, ErrorKind::Encoding)
+                .log_error("invalid UNICODE_STRING length"),
+        ));
+    }
+
+    let mut buf = vec![0; len & !1];
+    if !buf.is_empty() {
+        mem.read_raw_into(buffer, &mut buf).data_part()?;
+    }
+    Ok(decode_utf16(&buf, metadata.little_endian, mode)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn unicode_string() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let name = utf16("ntoskrnl.exe");
+        let buffer = base + 0x100usize;
+        proc.write(base, &(name.len() as u16)).unwrap();
+        proc.write(base + 2usize, &(name.len() as u16 + 2)).unwrap();
+        proc.write(base + 8usize, &buffer.to_umem()).unwrap();
+        proc.write_raw(buffer, &name).unwrap();
+
+        assert_eq!(
+            proc.read_unicode_string(base, Utf16Mode::Strict).unwrap(),
+            "ntoskrnl.exe"
+        );
+        assert_eq!(proc.read_utf16_string(buffer).unwrap(), "ntoskrnl.exe");
+        assert_eq!(
+            proc.read_utf16_array(buffer, 4, Utf16Mode::Strict).unwrap(),
+            "ntos"
+        );
+        assert!(proc
+            .read_utf16_string_n(buffer, 4, Utf16Mode::Lossy)
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_surrogate() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        // "a", an unpaired high surrogate, "b"
+        proc.write(base, &[0x61u16, 0xd800, 0x62, 0]).unwrap();
+
+        assert_eq!(
+            proc.read_utf16_string_n(base, 16, Utf16Mode::Lossy)
+                .unwrap(),
+            "a\u{fffd}b"
+        );
+        assert!(proc
+            .read_utf16_string_n(base, 16, Utf16Mode::Strict)
+            .is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Verified writes, compare-and-swap and consistent read helpers.
+//!
+//! Writes through DMA or other external interfaces may be silently dropped, or raced by the
+//! target itself. The helpers in this module read back the written memory and report exactly
+//! which bytes did not end up in memory.
+//!
+//! Reads of a running target race it as well: a structure that is modified while being read
+//! is torn, containing parts of both the old and the new state. [`read_consistent`] reads
+//! memory repeatedly until two consecutive reads agree.
+use super::*;
+
+use core::ops::Range;
+use std::fmt;
+
+/// Mismatch between the written and the read back data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteMismatch {
+    /// Address the data was written to.
+    pub address: Address,
+    /// Data that was written.
+    pub expected: Vec<u8>,
+    /// Data that was read back. Bytes that could not be read are zeroed.
+    pub actual: Vec<u8>,
+    /// Ranges that could not be read back, relative to `address`.
+    ///
+    /// These always count as differing, even if the written bytes were zero.
+    pub unreadable: Vec<Range<usize>>,
+}
+
+impl WriteMismatch {
+    fn differs(&self, offset: usize) -> bool {
+        self.expected[offset] != self.actual[offset]
+            || self.unreadable.iter().any(|r| r.contains(&offset))
+    }
+
+    /// Returns the offset of the first differing byte.
+    pub fn first_offset(&self) -> Option<usize> {
+        (0..self.expected.len()).find(|&i| self.differs(i))
+    }
+
+    /// Returns all ranges of consecutive differing bytes, relative to `address`.
+    pub fn ranges(&self) -> Vec<Range<usize>> {
+        let mut out: Vec<Range<usize>> = vec![];
+
+        for i in (0..self.expected.len()).filter(|&i| self.differs(i)) {
+            match out.last_mut() {
+                Some(r) if r.end == i => r.end += 1,
+                _ => out.push(i..i + 1),
+            }
+        }
+
+        out
+    }
+
+    /// Returns the total amount of differing bytes.
+    pub fn mismatched_bytes(&self) -> usize {
+        self.ranges().iter().map(|r| r.len()).sum()
+    }
+}
+
+impl fmt::Display for WriteMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "write verification at {:x} failed: {} of {} bytes differ",
+            self.address,
+            self.mismatched_bytes(),
+            self.expected.len()
+        )?;
+
+        if let Some(off) = self.first_offset() {
+            write!(f, " (first at +{:x})", off)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Error returned by [`MemoryView::write_verified`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The write or the read back failed.
+    Error(Error),
+    /// The read back data does not match the written data.
+    Mismatch(WriteMismatch),
+}
+
+impl From<Error> for VerifyError {
+    fn from(err: Error) -> Self {
+        VerifyError::Error(err)
+    }
+}
+
+impl From<VerifyError> for Error {
+    fn from(err: VerifyError) -> Self {
+        match err {
+            VerifyError::Error(err) => err,
+            VerifyError::Mismatch(_) => Error(ErrorOrigin::Memory, ErrorKind::PartialData),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::Error(err) => fmt::Display::fmt(err, f),
+            VerifyError::Mismatch(mismatch) => fmt::Display::fmt(mismatch, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerifyError {}
+
+/// Error returned by [`MemoryView::compare_exchange`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompareExchangeError<T> {
+    /// Reading or writing the value failed.
+    Error(Error),
+    /// The current value did not match the expected one, nothing was written.
+    Current(T),
+    /// The value was written but could not be verified afterwards.
+    Mismatch(WriteMismatch),
+}
+
+impl<T> From<Error> for CompareExchangeError<T> {
+    fn from(err: Error) -> Self {
+        CompareExchangeError::Error(err)
+    }
+}
+
+impl<T> From<VerifyError> for CompareExchangeError<T> {
+    fn from(err: VerifyError) -> Self {
+        match err {
+            VerifyError::Error(err) => CompareExchangeError::Error(err),
+            VerifyError::Mismatch(mismatch) => CompareExchangeError::Mismatch(mismatch),
+        }
+    }
+}
+
+/// Default amount of additional reads done by [`MemoryView::read_consistent`].
+pub const DEFAULT_CONSISTENT_RETRIES: usize = 8;
+
+/// Error returned by [`MemoryView::read_consistent`] and its variants.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConsistencyError {
+    /// One of the reads failed.
+    Error(Error),
+    /// No two consecutive reads agreed.
+    Unstable {
+        /// Address of the read.
+        address: Address,
+        /// Amount of reads done.
+        attempts: usize,
+    },
+}
+
+impl From<Error> for ConsistencyError {
+    fn from(err: Error) -> Self {
+        ConsistencyError::Error(err)
+    }
+}
+
+impl From<ConsistencyError> for Error {
+    fn from(err: ConsistencyError) -> Self {
+        match err {
+            ConsistencyError::Error(err) => err,
+            ConsistencyError::Unstable { .. } => Error(ErrorOrigin::Memory, ErrorKind::PartialData),
+        }
+    }
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsistencyError::Error(err) => fmt::Display::fmt(err, f),
+            ConsistencyError::Unstable { address, attempts } => write!(
+                f,
+                "memory at {:x} changed during all of {} reads",
+                address, attempts
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConsistencyError {}
+
+/// Reads `out` from `addr` until two consecutive reads are considered equal by `same`.
+///
+/// After the first two reads up to `retries` additional reads are done, each one compared to
+/// the previous one. Partial reads are treated as errors, `out` contains the last read on
+/// success.
+///
+/// This is the implementation of [`MemoryView::read_raw_consistent`] and
+/// [`MemoryView::read_consistent_by`].
+pub fn read_consistent<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    out: &mut [u8],
+    retries: usize,
+    mut same: impl FnMut(&[u8], &[u8]) -> bool,
+) -> core::result::Result<(), ConsistencyError> {
+    mem.read_raw_into(addr, out).data()?;
+
+    let mut buf = vec![0; out.len()];
+    for _ in 0..=retries {
+        mem.read_raw_into(addr, &mut buf).data()?;
+        let stable = same(out, &buf);
+        out.copy_from_slice(&buf);
+        if stable {
+            return Ok(());
+        }
+    }
+
+    Err(ConsistencyError::Unstable {
+        address: addr,
+        attempts: retries + 2,
+    })
+}
+
+/// Writes `data` to `addr` and verifies it by reading it back.
+///
+/// Bytes that can not be read back fail the verification, regardless of the written data.
+///
+/// This is the implementation of [`MemoryView::write_verified`].
+pub fn write_verified<M: MemoryView>(
+    mem: &mut M,
+    addr: Address,
+    data: &[u8],
+) -> core::result::Result<(), VerifyError> {
+    mem.write_raw(addr, data).data_part()?;
+
+    let mut actual = vec![0; data.len()];
+    let unreadable = match mem.read_raw_into_report(addr, &mut actual) {
+        Ok(_) => vec![],
+        Err(PartialError::Error(err)) => return Err(err.into()),
+        Err(PartialError::PartialVirtualRead(report))
+        | Err(PartialError::PartialVirtualWrite(report)) => report.failed_ranges(),
+    };
+
+    if actual == data && unreadable.is_empty() {
+        Ok(())
+    } else {
+        Err(VerifyError::Mismatch(WriteMismatch {
+            address: addr,
+            expected: data.to_vec(),
+            actual,
+            unreadable,
+        }))
+    }
+}
+
+/// Writes `new` to `addr` if the memory currently contains `expected`.
+///
+/// This is the implementation of [`MemoryView::compare_exchange`].
+pub fn compare_exchange<M: MemoryView, T: Pod + Sized>(
+    mem: &mut M,
+    addr: Address,
+    expected: &T,
+    new: &T,
+) -> core::result::Result<T, CompareExchangeError<T>> {
+    let current: T = mem.read(addr).data()?;
+
+    if current.as_bytes() != expected.as_bytes() {
+        return Err(CompareExchangeError::Current(current));
+    }
+
+    write_verified(mem, addr, new.as_bytes())?;
+
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    #[test]
+    fn verified_write() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write_verified(base, &0x1234_5678u32).unwrap();
+        assert_eq!(proc.read::<u32>(base), Ok(0x1234_5678));
+    }
+
+    #[test]
+    fn verified_write_unmapped() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let err = proc
+            .write_verified(base - 2usize, &[1u8, 2, 3, 4])
+            .unwrap_err();
+
+        match err {
+            VerifyError::Mismatch(mismatch) => {
+                assert_eq!(mismatch.first_offset(), Some(0));
+                assert_eq!(mismatch.ranges(), vec![0..2]);
+                assert_eq!(mismatch.actual, vec![0, 0, 3, 4]);
+            }
+            _ => panic!("expected a mismatch"),
+        }
+    }
+
+    #[test]
+    fn verified_write_zeroes_unmapped() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let err = proc.write_verified(base - 8usize, &[0u8; 8]).unwrap_err();
+
+        match err {
+            VerifyError::Mismatch(mismatch) => {
+                assert_eq!(mismatch.unreadable, vec![0..8]);
+                assert_eq!(mismatch.ranges(), vec![0..8]);
+                assert_eq!(mismatch.first_offset(), Some(0));
+            }
+            _ => panic!("expected a mismatch"),
+        }
+    }
+
+    #[test]
+    fn mismatch_ranges() {
+        let mismatch = WriteMismatch {
+            address: Address::NULL,
+            expected: vec![1, 2, 3, 4, 5, 6],
+            actual: vec![0, 0, 3, 0, 5, 0],
+            unreadable: vec![],
+        };
+
+        assert_eq!(mismatch.ranges(), vec![0..2, 3..4, 5..6]);
+        assert_eq!(mismatch.mismatched_bytes(), 4);
+    }
+
+    #[test]
+    fn compare_exchange_value() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write(base, &1u64).unwrap();
+
+        assert_eq!(proc.compare_exchange(base, &1u64, &2u64), Ok(1));
+        assert_eq!(
+            proc.compare_exchange(base, &1u64, &3u64),
+            Err(CompareExchangeError::Current(2))
+        );
+        assert_eq!(proc.read::<u64>(base), Ok(2));
+    }
+
+    /// Increments the counter at `addr` after every read, like a constantly updated statistic.
+    struct Bumping<M> {
+        mem: M,
+        addr: Address,
+    }
+
+    impl<M: MemoryView> MemoryView for Bumping<M> {
+        fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
+            self.mem.read_raw_iter(data)?;
+            let count: u64 = self.mem.read(self.addr).data()?;
+            self.mem.write(self.addr, &(count + 1)).data()
+        }
+
+        fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
+            self.mem.write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> MemoryViewMetadata {
+            self.mem.metadata()
+        }
+    }
+
+    #[test]
+    fn consistent_reads() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+        proc.write(base + 8usize, &0x1234u64).unwrap();
+
+        assert_eq!(proc.read_consistent::<[u64; 2]>(base), Ok([0, 0x1234]));
+
+        // { counter, value }
+        let mut mem = Bumping {
+            mem: proc,
+            addr: base,
+        };
+        assert_eq!(
+            mem.read_consistent::<[u64; 2]>(base),
+            Err(ConsistencyError::Unstable {
+                address: base,
+                attempts: DEFAULT_CONSISTENT_RETRIES + 2
+            })
+        );
+
+        let value = mem
+            .read_consistent_by(base, 2, |v: &[u64; 2]| v[1])
+            .unwrap();
+        assert_eq!(value[1], 0x1234);
+    }
+}
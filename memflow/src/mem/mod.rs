@@ -7,6 +7,10 @@
 //!
 //! TODO: more documentation
 
+#[cfg(feature = "std")]
+pub mod acquire;
+#[cfg(feature = "std")]
+pub mod diff;
 pub mod mem_data;
 pub mod mem_map;
 pub mod memory_view;
@@ -15,18 +19,33 @@ pub mod virt_mem;
 pub mod virt_translate;
 
 pub use mem_map::{MemoryMap, PhysicalMemoryMapping};
-pub use phys_mem::{CachedPhysicalMemory, PhysicalMemory, PhysicalMemoryMetadata};
+pub use phys_mem::{
+    CacheStats, CachedPhysicalMemory, DeltaTracker, MappablePhysicalMemory, OverlayMemory,
+    PageDelta, PhysicalMemory, PhysicalMemoryEvents, PhysicalMemoryMetadata, SortedPhysicalMemory,
+};
+#[cfg(feature = "std")]
+pub use phys_mem::{
+    PooledPhysicalMemory, ShardedPhysicalMemory, SharedCachedPhysicalMemory, SharedPageCache,
+};
 pub use virt_mem::VirtualDma;
+#[cfg(feature = "std")]
+pub use virt_translate::{SharedCachedVirtualTranslate, SharedTlbCache};
 //#[doc(hidden)]
 //pub use virt_mem_batcher::VirtualMemoryBatcher;
 pub use virt_translate::{
-    CachedVirtualTranslate, DirectTranslate, VirtualTranslate, VirtualTranslate2,
-    VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    CachedVirtualTranslate, DirectTranslate, NestedTranslate, TranslationFailReason,
+    TranslationFailure, TranslationRun, VirtualTranslate, VirtualTranslate2, VirtualTranslate3,
+    VtopFailureCallback, VtopOutputCallback,
 };
 
-pub use memory_view::{MemoryView, MemoryViewMetadata};
+pub use memory_view::{
+    BoundedView, Digest, HashAlgo, LazyView, MemoryView, MemoryViewMetadata, PtrChain,
+    PtrChainError, RangeBaseline, RemoteStruct, Utf16Mode,
+};
 
 #[cfg(feature = "std")]
 pub use memory_view::MemoryCursor;
 
 pub use mem_data::*;
+
+pub use memory_view::copy::copy;
@@ -8,6 +8,12 @@
 //! The cache time is determined by the customizable cache validator.
 //! The cache validator has to implement the [`CacheValidator`](../trait.CacheValidator.html) trait.
 //!
+//! Pages evicted from the cache can optionally be kept in a bigger, slower second level, see
+//! [`CachedPhysicalMemoryBuilder::secondary_cache`].
+//!
+//! Writes are passed through to the connector by default. Optionally they can be buffered and
+//! flushed in coalesced batches instead, see [`CachedPhysicalMemoryBuilder::write_back`].
+//!
 //! To make it easier and quicker to construct and work with caches this module also contains a cache builder.
 //!
 //! More examples can be found in the documentations for each of the structs in this module.
@@ -28,16 +34,30 @@
 //! ```
 
 mod page_cache;
+mod secondary;
+#[cfg(feature = "std")]
+mod shared;
+mod write_back;
 
 use crate::architecture::ArchitectureObj;
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::iter::PageChunks;
+use crate::mem::phys_mem::events::{ModifiedPageCallback, PhysicalMemoryEvents};
+use crate::mem::phys_mem::mappable::MappablePhysicalMemory;
 use crate::mem::{
-    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalReadMemOps,
-    PhysicalWriteMemOps,
+    mem_data::opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata,
+    PhysicalRange, PhysicalReadMemOps, PhysicalWriteMemOps,
 };
+use crate::types::{umem, Address};
+use cglue::callback::OpaqueCallback;
 use cglue::tuple::*;
+pub use page_cache::CacheStats;
 use page_cache::{PageCache, PageValidity};
+pub use secondary::{MemoryPageStore, SecondaryCache};
+#[cfg(feature = "std")]
+pub use shared::{SharedCachedPhysicalMemory, SharedPageCache};
+pub(crate) use write_back::PendingWrites;
+use write_back::WriteBack;
 
 use crate::types::cache::{CacheValidator, DefaultCacheValidator};
 
@@ -45,14 +65,18 @@ use crate::types::{size, PageType};
 
 use bumpalo::Bump;
 
+pub type InvalidationCallback<'a> = OpaqueCallback<'a, CTup2<Address, umem>>;
+
 /// The cache object that can use as a drop-in replacement for any Connector.
 ///
 /// Since this cache implements [`PhysicalMemory`] it can be used as a replacement
 /// in all structs and functions that require a [`PhysicalMemory`] object.
 pub struct CachedPhysicalMemory<'a, T, Q> {
-    mem: T,
+    mem: WriteBack<T>,
     cache: PageCache<'a, Q>,
     arena: Bump,
+    arena_capacity: usize,
+    invalidation_hook: Option<Box<dyn FnMut(InvalidationCallback) + Send + 'a>>,
 }
 
 impl<'a, T, Q> Clone for CachedPhysicalMemory<'a, T, Q>
@@ -60,11 +84,14 @@ where
     T: Clone,
     Q: CacheValidator + Clone,
 {
+    /// The invalidation hook is not cloned.
     fn clone(&self) -> Self {
         Self {
             mem: self.mem.clone(),
             cache: self.cache.clone(),
-            arena: Bump::new(),
+            arena: Bump::with_capacity(self.arena_capacity),
+            arena_capacity: self.arena_capacity,
+            invalidation_hook: None,
         }
     }
 }
@@ -77,13 +104,121 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemory<'a, T, Q> {
     /// For general usage it is advised to just use the [builder](struct.CachedPhysicalMemoryBuilder.html)
     /// to construct the cache.
     pub fn new(mem: T, cache: PageCache<'a, Q>) -> Self {
+        Self::with_arena_capacity(mem, cache, 0)
+    }
+
+    /// Constructs a new cache based on the given `PageCache` and preallocates `arena_capacity`
+    /// bytes of scratch space.
+    ///
+    /// The scratch arena holds temporary per-request state. It is reset, but not freed, between
+    /// requests, so after the first few reads no further allocations happen.
+    pub fn with_arena_capacity(mem: T, cache: PageCache<'a, Q>, arena_capacity: usize) -> Self {
         Self {
-            mem,
+            mem: WriteBack::new(mem),
             cache,
-            arena: Bump::new(),
+            arena: Bump::with_capacity(arena_capacity),
+            arena_capacity,
+            invalidation_hook: None,
+        }
+    }
+
+    /// Invalidates all cached pages overlapping with the `len` bytes at `addr`.
+    ///
+    /// Use this when memory is known to have changed outside of the cache, for example after
+    /// the target wrote to it. Buffered writes of the write-back mode are not affected.
+    pub fn invalidate_range(&mut self, addr: Address, len: usize) {
+        self.cache.invalidate_range(addr, len);
+    }
+
+    /// Installs a hook that reports externally modified physical ranges.
+    ///
+    /// The hook is polled before every read, all ranges passed to the callback are invalidated.
+    /// This allows external components, such as the dirty page log of a hypervisor, to drive
+    /// the invalidation of the cache. A previously installed hook is replaced.
+    ///
+    /// # Examples
+    /// ```
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    /// use memflow::types::Address;
+    /// use memflow::cglue::CTup2;
+    ///
+    /// use std::sync::mpsc::Receiver;
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T, dirty_log: Receiver<(Address, u64)>) {
+    ///     let mut cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     cache.set_invalidation_hook(move |mut out| {
+    ///         dirty_log
+    ///             .try_iter()
+    ///             .for_each(|(addr, len)| {
+    ///                 out.call(CTup2(addr, len));
+    ///             });
+    ///     });
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # build(DummyMemory::new(size::mb(4)), std::sync::mpsc::channel().1);
+    /// ```
+    pub fn set_invalidation_hook(&mut self, hook: impl FnMut(InvalidationCallback) + Send + 'a) {
+        self.invalidation_hook = Some(Box::new(hook));
+    }
+
+    /// Removes the hook installed by `set_invalidation_hook`.
+    pub fn clear_invalidation_hook(&mut self) {
+        self.invalidation_hook = None;
+    }
+
+    fn poll_invalidations(&mut self) {
+        if let Some(hook) = self.invalidation_hook.as_mut() {
+            let cache = &mut self.cache;
+            let callback = &mut |CTup2(addr, len): CTup2<Address, umem>| {
+                cache.invalidate_range(addr, len as usize);
+                true
+            };
+            hook(callback.into());
         }
     }
 
+    /// Returns the hit, miss, eviction and invalidation counters of the cache.
+    ///
+    /// # Examples
+    /// ```
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    ///
+    /// fn tune<T: PhysicalMemory>(mem: T) {
+    ///     let mut cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     // run the workload...
+    ///
+    ///     println!("hit ratio: {}", cache.stats().hit_ratio());
+    ///     cache.reset_stats();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # tune(DummyMemory::new(size::mb(4)));
+    /// ```
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Resets all counters returned by `stats()` to 0.
+    pub fn reset_stats(&mut self) {
+        self.cache.reset_stats()
+    }
+
+    /// Returns the amount of bytes currently allocated by the scratch arena.
+    pub fn arena_allocated_bytes(&self) -> usize {
+        self.arena.allocated_bytes()
+    }
+
     /// Consumes self and returns the containing memory object.
     ///
     /// This function can be useful in case the ownership over the memory object has been given to the cache
@@ -116,7 +251,35 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemory<'a, T, Q> {
     /// # build(mem);
     /// ```
     pub fn into_inner(self) -> T {
-        self.mem
+        self.mem.into_inner()
+    }
+
+    /// Writes all buffered writes to the underlying memory object.
+    ///
+    /// This is a no-op unless write-back mode has been enabled via the builder. Buffered writes
+    /// are also flushed when the cache is dropped or `into_inner` is called, but errors can only
+    /// be observed by calling this function.
+    pub fn flush(&mut self) -> Result<()> {
+        self.mem.flush()
+    }
+}
+
+impl<'a, T: PhysicalMemory + PhysicalMemoryEvents, Q: CacheValidator>
+    CachedPhysicalMemory<'a, T, Q>
+{
+    /// Invalidates all cached pages the underlying memory object reports as modified.
+    ///
+    /// Call this before reading to get up to date data without waiting for the cached pages to
+    /// expire. Returns the number of modified pages.
+    pub fn invalidate_modified(&mut self) -> Result<usize> {
+        let page_size = self.mem.event_page_size();
+        let pages = self.mem.modified_pages_list()?;
+
+        for &page in pages.iter() {
+            self.cache.invalidate_range(page, page_size);
+        }
+
+        Ok(pages.len())
     }
 }
 
@@ -134,14 +297,21 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> PhysicalMemory for CachedPhysical
         //data: PhysicalReadMemOps,
         data: PhysicalReadMemOps,
     ) -> Result<()> {
+        self.poll_invalidations();
+
         self.cache.validator.update_validity();
         self.arena.reset();
-        self.cache.cached_read(&mut self.mem, data, &self.arena)
+        // reads observe the buffered writes, without flushing them
+        self.cache.cached_read(&mut self.mem.patched(), data, &self.arena)
     }
 
     fn phys_write_raw_iter(
         &mut self,
-        MemOps { inp, out, out_fail }: PhysicalWriteMemOps,
+        MemOps {
+            inp,
+            mut out,
+            out_fail,
+        }: PhysicalWriteMemOps,
     ) -> Result<()> {
         self.cache.validator.update_validity();
 
@@ -150,23 +320,46 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> PhysicalMemory for CachedPhysical
 
         let inp = inp.map(move |CTup3(addr, meta_addr, data)| {
             if cache.is_cached_page_type(addr.page_type()) {
-                for (paddr, data_chunk) in data.page_chunks(addr.address(), cache.page_size()) {
-                    let mut cached_page = cache.cached_page_mut(paddr, false);
-                    if let PageValidity::Valid(buf) = &mut cached_page.validity {
-                        // write-back into still valid cache pages
-                        let start = (paddr - cached_page.address) as usize;
-                        buf[start..(start + data_chunk.len())].copy_from_slice(data_chunk.into());
-                    }
+                cache.invalidate_secondary(addr.address(), data.len());
 
-                    cache.put_entry(cached_page);
+                // the same physical page may be cached both as regular and as large entry
+                let page_sizes = core::iter::once(cache.page_size()).chain(cache.large_page_size());
+                for page_size in page_sizes {
+                    for (paddr, data_chunk) in data.page_chunks(addr.address(), page_size) {
+                        let mut cached_page = cache.cached_page_mut(paddr, page_size, false);
+                        if let PageValidity::Valid(buf) = &mut cached_page.validity {
+                            // write-back into still valid cache pages
+                            let start = (paddr - cached_page.address) as usize;
+                            buf[start..(start + data_chunk.len())]
+                                .copy_from_slice(data_chunk.into());
+                        }
+
+                        cache.put_entry(cached_page);
+                    }
                 }
             }
             CTup3(addr, meta_addr, data)
         });
 
-        MemOps::with_raw(inp, out, out_fail, move |data| {
-            mem.phys_write_raw_iter(data)
-        })
+        if !mem.is_enabled() {
+            return MemOps::with_raw(inp, out, out_fail, move |data| {
+                mem.phys_write_raw_iter(data)
+            });
+        }
+
+        // buffered writes are reported as successful, failures are returned by the flush
+        for CTup3(addr, meta_addr, data) in inp {
+            mem.pending.insert(addr.address(), data.into());
+            if !opt_call(out.as_deref_mut(), CTup2(meta_addr, data)) {
+                break;
+            }
+        }
+
+        if mem.pending.bytes() >= mem.max_pending {
+            mem.flush()
+        } else {
+            Ok(())
+        }
     }
 
     #[inline]
@@ -178,6 +371,11 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> PhysicalMemory for CachedPhysical
     fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
         self.mem.set_mem_map(mem_map)
     }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
 }
 
 /// The builder interface for constructing a `CachedPhysicalMemory` object.
@@ -187,6 +385,12 @@ pub struct CachedPhysicalMemoryBuilder<T, Q> {
     page_size: Option<usize>,
     cache_size: usize,
     page_type_mask: PageType,
+    large_page_size: usize,
+    large_cache_size: usize,
+    associativity: usize,
+    secondary: Option<Box<dyn SecondaryCache>>,
+    arena_capacity: usize,
+    write_back: usize,
 }
 
 impl<T: PhysicalMemory> CachedPhysicalMemoryBuilder<T, DefaultCacheValidator> {
@@ -268,25 +472,58 @@ impl<T: PhysicalMemory> CachedPhysicalMemoryBuilder<T, DefaultCacheValidator> {
             page_size: None,
             cache_size: size::mb(2),
             page_type_mask: PageType::PAGE_TABLE | PageType::READ_ONLY,
+            large_page_size: 0,
+            large_cache_size: 0,
+            associativity: 1,
+            secondary: None,
+            arena_capacity: 0,
+            write_back: 0,
         }
     }
 }
 
 impl<T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemoryBuilder<T, Q> {
     /// Builds the `CachedPhysicalMemory` object or returns an error if the page size is not set.
+    ///
+    /// An error is also returned if large pages are enabled with a granularity that is not a
+    /// power of two bigger than the page size.
     pub fn build<'a>(self) -> Result<CachedPhysicalMemory<'a, T, Q>> {
-        Ok(CachedPhysicalMemory::new(
-            self.mem,
-            PageCache::with_page_size(
-                self.page_size.ok_or_else(|| {
-                    Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
-                        .log_error("page_size must be initialized")
-                })?,
-                self.cache_size,
-                self.page_type_mask,
-                self.validator,
-            ),
-        ))
+        let page_size = self.page_size.ok_or_else(|| {
+            Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
+                .log_error("page_size must be initialized")
+        })?;
+
+        if self.large_cache_size > 0
+            && (!self.large_page_size.is_power_of_two() || self.large_page_size <= page_size)
+        {
+            return Err(Error(ErrorOrigin::Cache, ErrorKind::InvalidArgument)
+                .log_error("large page granularity must be a power of two bigger than page_size"));
+        }
+
+        if !self.associativity.is_power_of_two() {
+            return Err(Error(ErrorOrigin::Cache, ErrorKind::InvalidArgument)
+                .log_error("associativity must be a power of two"));
+        }
+
+        let mut page_cache = PageCache::with_large_pages(
+            page_size,
+            self.cache_size,
+            self.large_page_size,
+            self.large_cache_size,
+            self.page_type_mask,
+            self.validator,
+        )
+        .with_associativity(self.associativity);
+
+        if let Some(secondary) = self.secondary {
+            page_cache = page_cache.with_secondary(secondary);
+        }
+
+        let mut cache =
+            CachedPhysicalMemory::with_arena_capacity(self.mem, page_cache, self.arena_capacity);
+        cache.mem.max_pending = self.write_back;
+
+        Ok(cache)
     }
 
     /// Sets a custom validator for the cache.
@@ -327,6 +564,12 @@ impl<T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemoryBuilder<T, Q> {
             page_size: self.page_size,
             cache_size: self.cache_size,
             page_type_mask: self.page_type_mask,
+            large_page_size: self.large_page_size,
+            large_cache_size: self.large_cache_size,
+            associativity: self.associativity,
+            secondary: self.secondary,
+            arena_capacity: self.arena_capacity,
+            write_back: self.write_back,
         }
     }
 
@@ -454,6 +697,234 @@ impl<T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemoryBuilder<T, Q> {
         self.page_type_mask = page_type_mask;
         self
     }
+
+    /// Caches reads from large pages at a coarser granularity.
+    ///
+    /// When the translation reports large pages (e.g. 2MiB or 1GiB pages on x86), reads from
+    /// pages that are at least `granularity` bytes in size are cached in entries of `granularity`
+    /// bytes, instead of being split into many regular sized entries. This greatly reduces the
+    /// amount of entries that have to be validated for workloads that read a lot of memory
+    /// mapped with large pages, such as kernel images.
+    ///
+    /// `cache_size` bytes are allocated for the large entries in addition to the regular cache.
+    /// The `granularity` has to be a power of two bigger than the page size.
+    ///
+    /// By default large pages are cached like regular pages.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::types::size;
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .large_pages(size::kb(64), size::mb(4))
+    ///         .build()
+    ///         .unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # build(mem);
+    /// ```
+    pub fn large_pages(mut self, granularity: usize, cache_size: usize) -> Self {
+        self.large_page_size = granularity;
+        self.large_cache_size = cache_size;
+        self
+    }
+
+    /// Makes the cache set associative.
+    ///
+    /// By default the cache is direct mapped, so two frequently accessed pages that map to the
+    /// same cache entry keep evicting each other. With `ways` > 1 every page can be held by any
+    /// of `ways` entries, and the least recently used one of them is replaced on a miss.
+    ///
+    /// `ways` has to be a power of two, typically 2, 4 or 8. Higher values slightly increase
+    /// the cost of every lookup.
+    ///
+    /// The default setting is 1.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .associativity(4)
+    ///         .build()
+    ///         .unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # build(mem);
+    /// ```
+    pub fn associativity(mut self, ways: usize) -> Self {
+        self.associativity = ways;
+        self
+    }
+
+    /// Adds a second, bigger cache level below the page cache.
+    ///
+    /// Still valid pages that are evicted from the page cache are demoted into `secondary`,
+    /// and promoted back on their next access instead of being reread from the memory object.
+    /// The secondary cache can be a [`MemoryPageStore`], which stores the pages LZ4 compressed
+    /// with the `compressed_cache` feature, or a custom [`SecondaryCache`] implementation, for
+    /// example backed by a file.
+    ///
+    /// Pages in the secondary cache do not expire, they are only dropped on writes and explicit
+    /// invalidations. This makes it most useful for analysing snapshots or paused targets with
+    /// working sets that exceed the page cache by far.
+    ///
+    /// By default no secondary cache is used.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::types::size;
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    /// use memflow::mem::phys_mem::MemoryPageStore;
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .secondary_cache(MemoryPageStore::new(size::mb(256)))
+    ///         .build()
+    ///         .unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # build(mem);
+    /// ```
+    pub fn secondary_cache(mut self, secondary: impl SecondaryCache + 'static) -> Self {
+        self.secondary = Some(Box::new(secondary));
+        self
+    }
+
+    /// Preallocates scratch space for temporary per-request state.
+    ///
+    /// The cache keeps a scratch arena that is reset, but not freed, between requests.
+    /// By default the arena starts out empty and grows on demand. Preallocating it avoids
+    /// the allocations during the first requests, and a sufficiently large arena avoids
+    /// growing it when large batches are issued.
+    ///
+    /// The default setting is 0.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::types::size;
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .arena_capacity(size::kb(64))
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     assert!(cache.arena_allocated_bytes() >= size::kb(64));
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # build(mem);
+    /// ```
+    pub fn arena_capacity(mut self, arena_capacity: usize) -> Self {
+        self.arena_capacity = arena_capacity;
+        self
+    }
+
+    /// Buffers writes and flushes them in batches.
+    ///
+    /// Instead of passing each write through to the underlying memory object, writes are kept
+    /// in a buffer where adjacent and overlapping writes are merged. The buffer is flushed in a
+    /// single batch once `max_pending_bytes` are pending, on an explicit call to `flush()` and when
+    /// the cache is dropped. Reads through the cache observe the pending writes.
+    ///
+    /// This greatly reduces the amount of round trips for connectors with a high per-request
+    /// latency. Note that write errors are only reported by the flush.
+    ///
+    /// The default setting is 0, which disables buffering.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::types::size;
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let mut cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .write_back(size::kb(64))
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     cache.phys_write(0.into(), &0u64).unwrap();
+    ///     cache.flush().unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # build(mem);
+    /// ```
+    pub fn write_back(mut self, max_pending_bytes: usize) -> Self {
+        self.write_back = max_pending_bytes;
+        self
+    }
+}
+
+/// Forwards the notifications of the underlying memory object and invalidates the modified
+/// pages on the way.
+impl<'a, T: PhysicalMemory + PhysicalMemoryEvents, Q: CacheValidator> PhysicalMemoryEvents
+    for CachedPhysicalMemory<'a, T, Q>
+{
+    fn event_page_size(&self) -> usize {
+        self.mem.event_page_size()
+    }
+
+    fn start_tracking(&mut self) -> Result<()> {
+        self.mem.start_tracking()
+    }
+
+    fn stop_tracking(&mut self) -> Result<()> {
+        self.mem.stop_tracking()
+    }
+
+    fn modified_pages(&mut self, mut out: ModifiedPageCallback) -> Result<()> {
+        let page_size = self.mem.event_page_size();
+        let cache = &mut self.cache;
+
+        self.mem.modified_pages(
+            (&mut |page: Address| {
+                cache.invalidate_range(page, page_size);
+                out.call(page)
+            })
+                .into(),
+        )
+    }
+}
+
+/// Hands out views of the underlying memory object, bypassing the cache.
+///
+/// No views are handed out while writes are pending in the write-back buffer, since the views
+/// would not reflect them yet.
+impl<'a, T: MappablePhysicalMemory, Q: CacheValidator> MappablePhysicalMemory
+    for CachedPhysicalMemory<'a, T, Q>
+{
+    fn phys_map(&self, addr: Address) -> Option<&[u8]> {
+        if self.mem.pending.is_empty() {
+            self.mem.phys_map(addr)
+        } else {
+            None
+        }
+    }
 }
 
 #[cfg(feature = "plugins")]
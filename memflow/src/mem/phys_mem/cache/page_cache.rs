@@ -5,9 +5,12 @@ use crate::mem::mem_data::*;
 use crate::mem::phys_mem::*;
 use crate::types::{cache::CacheValidator, umem, Address, PageType, PhysicalAddress};
 
+use super::secondary::SecondaryCache;
+
 use std::alloc::{alloc, alloc_zeroed, dealloc, Layout};
 
 use bumpalo::{collections::Vec as BumpVec, Bump};
+use itertools::Either;
 
 pub enum PageValidity<'a> {
     Invalid,
@@ -27,15 +30,54 @@ impl<'a> CacheEntry<'a> {
     }
 }
 
+/// Counters describing the effectiveness of a page cache.
+///
+/// Hits and misses are counted per cached page that is read, reads of page types that are not
+/// cached are not counted at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads served from a valid cache entry
+    pub hits: usize,
+    /// Reads that had to be passed through to the underlying memory object
+    pub misses: usize,
+    /// Valid entries that were replaced by a different page
+    pub evictions: usize,
+    /// Valid entries that were invalidated, for example because of a write
+    pub invalidations: usize,
+    /// Misses served from the secondary cache
+    pub promotions: usize,
+    /// Evicted entries that were moved into the secondary cache
+    pub demotions: usize,
+}
+
+impl CacheStats {
+    /// Returns the ratio of hits to all counted reads, or 0 if nothing was read yet.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total > 0 {
+            self.hits as f64 / total as f64
+        } else {
+            0.0
+        }
+    }
+}
+
 pub struct PageCache<'a, T> {
     address: Box<[Address]>,
     page_refs: Box<[Option<&'a mut [u8]>]>,
     address_once_validated: Box<[Address]>,
+    last_used: Box<[u64]>,
+    clock: u64,
+    ways: usize,
     page_size: usize,
+    large_page_size: usize,
+    large_entries: usize,
     page_type_mask: PageType,
     pub validator: T,
     cache_ptr: *mut u8,
     cache_layout: Layout,
+    stats: CacheStats,
+    secondary: Option<Box<dyn SecondaryCache + 'a>>,
 }
 
 unsafe impl<'a, T> Send for PageCache<'a, T> {}
@@ -50,62 +92,184 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
         page_size: usize,
         size: usize,
         page_type_mask: PageType,
+        validator: T,
+    ) -> Self {
+        Self::with_large_pages(page_size, size, 0, 0, page_type_mask, validator)
+    }
+
+    /// Constructs a cache with an additional set of `large_page_size` sized entries.
+    ///
+    /// Reads from pages that are at least `large_page_size` bytes in size (as reported by the
+    /// translation) are cached in `large_size` bytes worth of large entries, instead of being
+    /// split into many `page_size` entries. A `large_page_size` that is not bigger than
+    /// `page_size`, or a `large_size` smaller than a single entry disables large entries.
+    pub fn with_large_pages(
+        page_size: usize,
+        size: usize,
+        large_page_size: usize,
+        large_size: usize,
+        page_type_mask: PageType,
         mut validator: T,
     ) -> Self {
         let cache_entries = size / page_size;
 
-        let layout = Layout::from_size_align(cache_entries * page_size, page_size).unwrap();
+        let (large_page_size, large_entries) = if large_page_size > page_size {
+            (large_page_size, large_size / large_page_size)
+        } else {
+            (0, 0)
+        };
+
+        let layout = Layout::from_size_align(
+            cache_entries * page_size + large_entries * large_page_size,
+            page_size,
+        )
+        .unwrap();
 
         let cache_ptr = unsafe { alloc_zeroed(layout) };
 
-        let page_refs = (0..cache_entries)
-            .map(|i| unsafe {
-                std::mem::transmute(std::slice::from_raw_parts_mut(
-                    cache_ptr.add(i * page_size),
-                    page_size,
-                ))
-            })
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
+        let page_refs = unsafe {
+            Self::page_refs(
+                cache_ptr,
+                (page_size, cache_entries),
+                (large_page_size, large_entries),
+            )
+        };
 
-        validator.allocate_slots(cache_entries);
+        let total_entries = cache_entries + large_entries;
+
+        validator.allocate_slots(total_entries);
 
         Self {
-            address: vec![Address::INVALID; cache_entries].into_boxed_slice(),
+            address: vec![Address::INVALID; total_entries].into_boxed_slice(),
             page_refs,
-            address_once_validated: vec![Address::INVALID; cache_entries].into_boxed_slice(),
+            address_once_validated: vec![Address::INVALID; total_entries].into_boxed_slice(),
+            last_used: vec![0; total_entries].into_boxed_slice(),
+            clock: 0,
+            ways: 1,
             page_size,
+            large_page_size,
+            large_entries,
             page_type_mask,
             validator,
             cache_ptr,
             cache_layout: layout,
+            stats: CacheStats::default(),
+            secondary: None,
         }
     }
 
-    fn page_index(&self, addr: Address) -> usize {
-        ((addr.as_page_aligned(self.page_size).to_umem() / self.page_size as umem)
-            % (self.address.len() as umem)) as usize
+    /// Makes the cache `ways`-way set associative.
+    ///
+    /// By default the cache is direct mapped, every page can only be held by a single entry.
+    /// With set associativity each page can be held by any entry of a set of `ways` entries,
+    /// the least recently used entry of the set is replaced on a miss. This avoids thrashing
+    /// when frequently accessed pages map to the same entry.
+    ///
+    /// `ways` has to be a power of two, and is clamped to the number of entries.
+    pub fn with_associativity(mut self, ways: usize) -> Self {
+        debug_assert!(ways.is_power_of_two());
+        self.ways = std::cmp::max(ways, 1);
+        self
     }
 
-    fn take_page(&mut self, addr: Address, skip_validator: bool) -> PageValidity<'a> {
-        let page_index = self.page_index(addr);
+    /// Adds a second cache level below this cache.
+    ///
+    /// Still valid entries are demoted into `secondary` when they are evicted, misses are
+    /// looked up in it before reading from the memory object.
+    pub fn with_secondary(mut self, secondary: Box<dyn SecondaryCache + 'a>) -> Self {
+        self.secondary = Some(secondary);
+        self
+    }
+
+    /// Splits the cache allocation into the regular entries, followed by the large entries.
+    unsafe fn page_refs(
+        cache_ptr: *mut u8,
+        (page_size, entries): (usize, usize),
+        (large_page_size, large_entries): (usize, usize),
+    ) -> Box<[Option<&'a mut [u8]>]> {
+        let large_ptr = cache_ptr.add(entries * page_size);
+
+        (0..entries)
+            .map(|i| (cache_ptr.add(i * page_size), page_size))
+            .chain(
+                (0..large_entries).map(|i| (large_ptr.add(i * large_page_size), large_page_size)),
+            )
+            .map(|(ptr, size)| std::mem::transmute(std::slice::from_raw_parts_mut(ptr, size)))
+            .collect::<Vec<_>>()
+            .into_boxed_slice()
+    }
+
+    /// Returns the first slot and the number of slots of entries with the given size.
+    fn bank(&self, page_size: usize) -> (usize, usize) {
+        let small_entries = self.address.len() - self.large_entries;
+        if page_size == self.page_size {
+            (0, small_entries)
+        } else {
+            debug_assert_eq!(page_size, self.large_page_size);
+            (small_entries, self.large_entries)
+        }
+    }
+
+    /// Returns the slot holding the page at `addr`, or the slot it would be placed in.
+    ///
+    /// Within a set the slot holding or currently validating the page is preferred, followed
+    /// by the least recently used slot.
+    fn page_index(&self, addr: Address, page_size: usize) -> usize {
+        let (offset, entries) = self.bank(page_size);
+        let ways = std::cmp::min(self.ways, entries);
+        let sets = entries / ways;
+
+        let aligned_addr = addr.as_page_aligned(page_size);
+        let set = ((aligned_addr.to_umem() / page_size as umem) % (sets as umem)) as usize;
+        let start = offset + set * ways;
+
+        if ways == 1 {
+            return start;
+        }
+
+        let slots = start..start + ways;
+        slots
+            .clone()
+            .find(|&idx| self.address[idx] == aligned_addr)
+            .or_else(|| {
+                slots
+                    .clone()
+                    .find(|&idx| self.address_once_validated[idx] == aligned_addr)
+            })
+            .or_else(|| slots.min_by_key(|&idx| self.last_used[idx]))
+            .unwrap()
+    }
+
+    fn touch(&mut self, idx: usize) {
+        self.clock += 1;
+        self.last_used[idx] = self.clock;
+    }
+
+    fn take_page(
+        &mut self,
+        addr: Address,
+        page_size: usize,
+        skip_validator: bool,
+    ) -> PageValidity<'a> {
+        let page_index = self.page_index(addr, page_size);
+        let aligned_addr = addr.as_page_aligned(page_size);
 
         let bufopt = std::mem::replace(&mut self.page_refs[page_index], None);
 
         if let Some(buf) = bufopt {
-            if self.address[page_index] == addr.as_page_aligned(self.page_size)
+            if self.address[page_index] == aligned_addr
                 && (skip_validator || self.validator.is_slot_valid(page_index))
             {
+                self.touch(page_index);
                 PageValidity::Valid(buf)
-            } else if self.address_once_validated[page_index]
-                == addr.as_page_aligned(self.page_size)
+            } else if self.address_once_validated[page_index] == aligned_addr
                 || self.address_once_validated[page_index] == Address::INVALID
             {
                 PageValidity::Validatable(buf)
             } else {
                 PageValidity::Invalid
             }
-        } else if self.address_once_validated[page_index] == addr.as_page_aligned(self.page_size) {
+        } else if self.address_once_validated[page_index] == aligned_addr {
             PageValidity::ToBeValidated
         } else {
             PageValidity::Invalid
@@ -113,7 +277,7 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
     }
 
     fn put_page(&mut self, addr: Address, page: &'a mut [u8]) {
-        let page_index = self.page_index(addr);
+        let page_index = self.page_index(addr, page.len());
         debug_assert!(self.page_refs[page_index].is_none());
         self.page_refs[page_index] = Some(page);
     }
@@ -122,16 +286,46 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
         self.page_size
     }
 
+    /// Returns the size of large entries, if they are enabled.
+    pub fn large_page_size(&self) -> Option<usize> {
+        if self.large_entries > 0 {
+            Some(self.large_page_size)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the size of the entries data at the given address is cached in.
+    pub fn entry_size(&self, addr: PhysicalAddress) -> usize {
+        match self.large_page_size() {
+            Some(large_page_size) if addr.page_size() >= large_page_size as umem => large_page_size,
+            _ => self.page_size,
+        }
+    }
+
+    /// Returns the counters accumulated since the cache was created or last reset.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
     pub fn is_cached_page_type(&self, page_type: PageType) -> bool {
         self.page_type_mask.contains(page_type)
     }
 
-    pub fn cached_page_mut(&mut self, addr: Address, skip_validator: bool) -> CacheEntry<'a> {
-        let page_size = self.page_size;
+    pub fn cached_page_mut(
+        &mut self,
+        addr: Address,
+        page_size: usize,
+        skip_validator: bool,
+    ) -> CacheEntry<'a> {
         let aligned_addr = addr.as_page_aligned(page_size);
         CacheEntry {
             address: aligned_addr,
-            validity: self.take_page(addr, skip_validator),
+            validity: self.take_page(addr, page_size, skip_validator),
         }
     }
 
@@ -144,40 +338,113 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
         }
     }
 
-    pub fn mark_page_for_validation(&mut self, addr: Address) {
-        let idx = self.page_index(addr);
-        let aligned_addr = addr.as_page_aligned(self.page_size);
+    pub fn mark_page_for_validation(&mut self, addr: Address, page_size: usize) {
+        let idx = self.page_index(addr, page_size);
+        let aligned_addr = addr.as_page_aligned(page_size);
         self.address_once_validated[idx] = aligned_addr;
+        self.touch(idx);
     }
 
     pub fn cancel_page_validation(&mut self, addr: Address, page_buf: &'a mut [u8]) {
-        let idx = self.page_index(addr);
+        let idx = self.page_index(addr, page_buf.len());
         // We could leave it in previous validity state,
         // but the buffer could have been partially written...
         if self.address_once_validated[idx] == addr {
-            self.invalidate_page_raw(addr);
-            self.put_page(addr, page_buf);
+            self.invalidate_slot(idx);
+            // the page is not found by its address anymore
+            debug_assert!(self.page_refs[idx].is_none());
+            self.page_refs[idx] = Some(page_buf);
         }
     }
 
     pub fn validate_page(&mut self, addr: Address, page_buf: &'a mut [u8]) {
-        let idx = self.page_index(addr);
+        let idx = self.page_index(addr, page_buf.len());
+        if self.address[idx] != addr && self.address[idx] != Address::INVALID {
+            self.stats.evictions += 1;
+        }
         self.address[idx] = addr;
         self.address_once_validated[idx] = Address::INVALID;
         self.validator.validate_slot(idx);
+        self.touch(idx);
         self.put_page(addr, page_buf);
     }
 
-    pub fn invalidate_page_raw(&mut self, addr: Address) {
-        let idx = self.page_index(addr);
+    /// Demotes the valid entry in the slot of `addr` and promotes `addr` in its place.
+    ///
+    /// `buf` is the buffer of the slot. Returns whether `buf` was filled from the secondary
+    /// cache, in which case the caller has to validate it.
+    fn swap_secondary(&mut self, addr: Address, buf: &mut [u8]) -> bool {
+        let idx = self.page_index(addr, buf.len());
+        let evicted = self.address[idx];
+        if evicted == addr {
+            return false;
+        }
+
+        let demote = evicted != Address::INVALID && self.validator.is_slot_valid(idx);
+
+        let secondary = match self.secondary.as_mut() {
+            Some(secondary) => secondary,
+            None => return false,
+        };
+
+        if demote {
+            secondary.put(evicted, buf);
+            self.stats.demotions += 1;
+        }
+
+        if secondary.take(addr, buf) {
+            self.stats.promotions += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drops the pages overlapping with the `size` bytes at `addr` from the secondary cache.
+    pub fn invalidate_secondary(&mut self, addr: Address, size: usize) {
+        if let Some(secondary) = self.secondary.as_mut() {
+            secondary.invalidate_range(addr, size);
+        }
+    }
+
+    pub fn invalidate_page_raw(&mut self, addr: Address, page_size: usize) {
+        let idx = self.page_index(addr, page_size);
+        if self.address[idx] == addr.as_page_aligned(page_size) {
+            self.stats.invalidations += 1;
+        }
+        self.invalidate_slot(idx);
+    }
+
+    fn invalidate_slot(&mut self, idx: usize) {
         self.validator.invalidate_slot(idx);
         self.address[idx] = Address::INVALID;
         self.address_once_validated[idx] = Address::INVALID;
+        self.last_used[idx] = 0;
+    }
+
+    /// Invalidates all entries overlapping with the `size` bytes at `addr`.
+    pub fn invalidate_range(&mut self, addr: Address, size: usize) {
+        self.invalidate_secondary(addr, size);
+
+        let page_sizes = core::iter::once(self.page_size).chain(self.large_page_size());
+
+        for page_size in page_sizes {
+            let mut page = addr.as_page_aligned(page_size);
+            while page < addr + size {
+                self.invalidate_page_raw(page, page_size);
+                page = page + page_size;
+            }
+        }
     }
 
     pub fn invalidate_page(&mut self, addr: Address, page_type: PageType) {
         if self.page_type_mask.contains(page_type) {
-            self.invalidate_page_raw(addr)
+            let size = self.large_page_size().unwrap_or(self.page_size);
+            self.invalidate_secondary(addr.as_page_aligned(size), size);
+            self.invalidate_page_raw(addr, self.page_size);
+            if let Some(large_page_size) = self.large_page_size() {
+                self.invalidate_page_raw(addr, large_page_size);
+            }
         }
     }
 
@@ -207,72 +474,100 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
         }: PhysicalReadMemOps,
         arena: &'b Bump,
     ) -> Result<()> {
-        let page_size = self.page_size;
+        // never keep more pages in flight than there are cache entries
+        let (max_elems, max_bytes) = mem.metadata().batch_limits();
+        let max_elems = std::cmp::min(max_elems, self.address.len());
 
         {
             let mut next = iter.next();
+            let mut batch_bytes: umem = 0;
             let mut clist = BumpVec::new_in(arena);
             let mut wlist = BumpVec::new_in(arena);
             let mut wlistcache = BumpVec::new_in(arena);
 
             while let Some(CTup3(addr, meta_addr, out)) = next {
                 if self.is_cached_page_type(addr.page_type()) {
-                    (meta_addr, out)
-                        .page_chunks(addr.address(), page_size)
-                        .for_each(|(paddr, (meta_addr, chunk))| {
-                            let mut prd = CTup3(
-                                PhysicalAddress::with_page(
-                                    paddr,
-                                    addr.page_type(),
-                                    addr.page_size() as umem,
-                                ),
-                                meta_addr,
-                                chunk,
-                            );
-
-                            let cached_page = self.cached_page_mut(prd.0.address(), false);
-
-                            match cached_page.validity {
-                                PageValidity::Valid(buf) => {
-                                    let aligned_addr = paddr.as_page_aligned(self.page_size);
-                                    let start = paddr - aligned_addr;
-                                    let cached_buf = buf
-                                        .split_at_mut(start as usize)
-                                        .1
-                                        .split_at_mut(prd.2.len())
-                                        .0;
-                                    prd.2.copy_from_slice(cached_buf);
+                    let page_size = self.entry_size(addr);
+                    let page_offset =
+                        (addr.address() - addr.address().as_page_aligned(page_size)) as usize;
+
+                    // Most reads are small and do not cross an entry boundary. Pass those through
+                    // as they are, instead of reconstructing them through the chunk iterator.
+                    let chunks = if page_offset + out.len() <= page_size {
+                        Either::Left(core::iter::once(CTup3(addr, meta_addr, out)))
+                    } else {
+                        Either::Right((meta_addr, out).page_chunks(addr.address(), page_size).map(
+                            |(paddr, (meta_addr, chunk))| {
+                                CTup3(
+                                    PhysicalAddress::with_page(
+                                        paddr,
+                                        addr.page_type(),
+                                        addr.page_size() as umem,
+                                    ),
+                                    meta_addr,
+                                    chunk,
+                                )
+                            },
+                        ))
+                    };
+
+                    chunks.for_each(|mut prd| {
+                        let cached_page = self.cached_page_mut(prd.0.address(), page_size, false);
+
+                        match cached_page.validity {
+                            PageValidity::Valid(buf) => {
+                                self.stats.hits += 1;
+                                let start = (prd.0.address() - cached_page.address) as usize;
+                                prd.2.copy_from_slice(&buf[start..start + prd.2.len()]);
+                                opt_call(cb_out.as_deref_mut(), CTup2(prd.1, prd.2));
+                                self.put_page(cached_page.address, buf);
+                            }
+                            PageValidity::Validatable(buf) => {
+                                self.stats.misses += 1;
+
+                                if self.swap_secondary(cached_page.address, buf) {
+                                    let start = (prd.0.address() - cached_page.address) as usize;
+                                    prd.2.copy_from_slice(&buf[start..start + prd.2.len()]);
                                     opt_call(cb_out.as_deref_mut(), CTup2(prd.1, prd.2));
-                                    self.put_page(cached_page.address, buf);
-                                }
-                                PageValidity::Validatable(buf) => {
-                                    clist.push(prd);
-                                    wlistcache.push(CTup3(
-                                        PhysicalAddress::from(cached_page.address),
-                                        meta_addr,
-                                        buf.into(),
-                                    ));
-                                    self.mark_page_for_validation(cached_page.address);
-                                }
-                                PageValidity::ToBeValidated => {
-                                    clist.push(prd);
-                                }
-                                PageValidity::Invalid => {
-                                    wlist.push(prd);
+                                    self.validate_page(cached_page.address, buf);
+                                    return;
                                 }
+
+                                batch_bytes += buf.len() as umem;
+                                wlistcache.push(CTup3(
+                                    PhysicalAddress::from(cached_page.address),
+                                    prd.1,
+                                    buf.into(),
+                                ));
+                                clist.push(prd);
+                                self.mark_page_for_validation(cached_page.address, page_size);
                             }
-                        });
+                            PageValidity::ToBeValidated => {
+                                self.stats.misses += 1;
+                                clist.push(prd);
+                            }
+                            PageValidity::Invalid => {
+                                self.stats.misses += 1;
+                                batch_bytes += prd.2.len() as umem;
+                                wlist.push(prd);
+                            }
+                        }
+                    });
                 } else {
+                    batch_bytes += out.len() as umem;
                     wlist.push(CTup3(addr, meta_addr, out));
                 }
 
                 next = iter.next();
 
                 if next.is_none()
-                    || wlist.len() >= 64
-                    || wlistcache.len() >= 64
-                    || clist.len() >= 64
+                    || wlist.len() >= max_elems
+                    || wlistcache.len() >= max_elems
+                    || clist.len() >= max_elems
+                    || batch_bytes >= max_bytes
                 {
+                    batch_bytes = 0;
+
                     if !wlist.is_empty() {
                         {
                             let mut drain = wlist.drain(..);
@@ -306,16 +601,16 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                             out_fail: None,
                         })?;
 
-                        wlistcache.into_iter().for_each(|CTup3(addr, _, buf)| {
+                        // drain instead of consuming the list to keep its allocation around
+                        wlistcache.drain(..).for_each(|CTup3(addr, _, buf)| {
                             self.cancel_page_validation(addr.address(), buf.into());
                         });
-
-                        wlistcache = BumpVec::new_in(arena);
                     }
 
                     while let Some(CTup3(addr, meta_addr, mut out)) = clist.pop() {
-                        let cached_page = self.cached_page_mut(addr.address(), false);
-                        let aligned_addr = cached_page.address.as_page_aligned(self.page_size);
+                        let page_size = self.entry_size(addr);
+                        let cached_page = self.cached_page_mut(addr.address(), page_size, false);
+                        let aligned_addr = cached_page.address.as_page_aligned(page_size);
 
                         let start = addr.address() - aligned_addr;
 
@@ -347,34 +642,40 @@ where
         let validator = self.validator.clone();
 
         let cache_entries = self.address.len();
+        let large_entries = self.large_entries;
 
-        let layout = Layout::from_size_align(cache_entries * page_size, page_size).unwrap();
+        let layout = self.cache_layout;
 
         let cache_ptr = unsafe { alloc(layout) };
 
         unsafe {
-            std::ptr::copy_nonoverlapping(self.cache_ptr, cache_ptr, cache_entries * page_size);
+            std::ptr::copy_nonoverlapping(self.cache_ptr, cache_ptr, layout.size());
         };
 
-        let page_refs = (0..cache_entries)
-            .map(|i| unsafe {
-                std::mem::transmute(std::slice::from_raw_parts_mut(
-                    cache_ptr.add(i * page_size),
-                    page_size,
-                ))
-            })
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
+        let page_refs = unsafe {
+            Self::page_refs(
+                cache_ptr,
+                (page_size, cache_entries - large_entries),
+                (self.large_page_size, large_entries),
+            )
+        };
 
         Self {
             address: vec![Address::INVALID; cache_entries].into_boxed_slice(),
             page_refs,
             address_once_validated: vec![Address::INVALID; cache_entries].into_boxed_slice(),
+            last_used: vec![0; cache_entries].into_boxed_slice(),
+            clock: 0,
+            ways: self.ways,
             page_size,
+            large_page_size: self.large_page_size,
+            large_entries,
             page_type_mask,
             validator,
             cache_ptr,
             cache_layout: layout,
+            stats: CacheStats::default(),
+            secondary: None,
         }
     }
 }
@@ -395,6 +696,7 @@ mod tests {
     use crate::dummy::{DummyMemory, DummyOs};
     use crate::mem::{CachedPhysicalMemory, MemoryView, VirtualDma};
     use crate::types::{cache::TimedCacheValidator, size, Address, PhysicalAddress};
+    use cglue::slice::CSliceMut;
 
     use coarsetime::Duration;
     use rand::{thread_rng, Rng};
@@ -840,4 +1142,364 @@ mod tests {
         virt_mem.read_into(virt_base, buf_3.as_mut_slice()).unwrap();
         assert_eq!(buf_2, buf_3);
     }
+
+    #[test]
+    fn large_pages() {
+        let mut mem = DummyMemory::new(size::mb(8));
+        let mem_ptr = &mut mem as *mut DummyMemory;
+
+        let base = Address::from(size::mb(2) as u64);
+        mem.phys_write(base.into(), &[1u8; 0x10000][..]).unwrap();
+
+        let mut mem_cache = CachedPhysicalMemory::builder(mem.forward_mut())
+            .page_size(size::kb(4))
+            .page_type_mask(PageType::READ_ONLY)
+            .large_pages(size::kb(64), size::kb(256))
+            .validator(TimedCacheValidator::new(Duration::from_secs(100)))
+            .build()
+            .unwrap();
+
+        let large = |addr: Address| {
+            PhysicalAddress::with_page(addr, PageType::READ_ONLY, size::mb(2) as umem)
+        };
+        let small = |addr: Address| {
+            PhysicalAddress::with_page(addr, PageType::READ_ONLY, size::kb(4) as umem)
+        };
+
+        let mut buf = [0u8; 8];
+        mem_cache.phys_read_into(large(base), &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 8]);
+
+        // modify a different 4K page within the same large entry behind the cache's back
+        let other = base + size::kb(32);
+        unsafe { mem_ptr.as_mut().unwrap() }
+            .phys_write(other.into(), &[2u8; 8])
+            .unwrap();
+
+        // the whole large entry was cached by the first read
+        mem_cache.phys_read_into(large(other), &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 8]);
+
+        // regular entries are cached independently
+        mem_cache.phys_read_into(small(other), &mut buf).unwrap();
+        assert_eq!(buf, [2u8; 8]);
+
+        // writes are visible through both kinds of entries
+        mem_cache.phys_write(small(other), &[3u8; 8]).unwrap();
+        mem_cache.phys_read_into(large(other), &mut buf).unwrap();
+        assert_eq!(buf, [3u8; 8]);
+        mem_cache.phys_read_into(small(other), &mut buf).unwrap();
+        assert_eq!(buf, [3u8; 8]);
+    }
+
+    #[test]
+    fn large_pages_invalid_granularity() {
+        let mem = DummyMemory::new(size::mb(1));
+        assert!(CachedPhysicalMemory::builder(mem)
+            .page_size(size::kb(4))
+            .large_pages(size::kb(48), size::mb(1))
+            .build()
+            .is_err());
+    }
+
+    struct LimitedMemory {
+        mem: DummyMemory,
+        max_seen: usize,
+        hints: Vec<PhysicalRange>,
+    }
+
+    impl PhysicalMemory for LimitedMemory {
+        fn phys_read_raw_iter(
+            &mut self,
+            MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+        ) -> Result<()> {
+            let data = inp.collect::<Vec<_>>();
+            self.max_seen = std::cmp::max(self.max_seen, data.len());
+            let mem = &mut self.mem;
+            MemOps::with_raw(data.into_iter(), out, out_fail, |data| {
+                mem.phys_read_raw_iter(data)
+            })
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.mem.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            PhysicalMemoryMetadata {
+                max_batch_size: 3,
+                ..self.mem.metadata()
+            }
+        }
+
+        fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+            self.hints.extend_from_slice(ranges);
+        }
+    }
+
+    #[test]
+    fn batch_limits() {
+        let mem = LimitedMemory {
+            mem: DummyMemory::new(size::mb(16)),
+            max_seen: 0,
+            hints: vec![],
+        };
+
+        let cache = PageCache::new(
+            x86::x64::ARCH,
+            size::mb(2),
+            PageType::PAGE_TABLE | PageType::READ_ONLY,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+
+        let mut mem_cache = CachedPhysicalMemory::new(mem, cache);
+
+        let mut bufs = vec![[0u8; 8]; 16];
+        let iter = bufs.iter_mut().enumerate().map(|(i, buf)| {
+            (
+                PhysicalAddress::with_page(
+                    Address::from(i as u64 * 0x1000),
+                    PageType::READ_ONLY,
+                    0x1000,
+                ),
+                CSliceMut::from(&mut buf[..]),
+            )
+        });
+        MemOps::with(iter, None, None, |data| mem_cache.phys_read_raw_iter(data)).unwrap();
+
+        let mut mem = mem_cache.into_inner();
+        assert_eq!(mem.max_seen, 3);
+        mem.max_seen = 0;
+
+        let mut data = vec![0u8; 0x100];
+        mem.phys_view()
+            .read_raw_list(
+                &mut data
+                    .chunks_mut(8)
+                    .enumerate()
+                    .map(|(i, c)| CTup2(Address::from(i as u64 * 0x1000), c.into()))
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap();
+
+        assert_eq!(mem.max_seen, 3);
+    }
+
+    #[test]
+    fn prefetch_hint() {
+        let mut dummy_os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = dummy_os.alloc_dtb(size::mb(1), &[]);
+
+        let mem = LimitedMemory {
+            mem: dummy_os.into_inner(),
+            max_seen: 0,
+            hints: vec![],
+        };
+
+        let cache = PageCache::new(
+            x86::x64::ARCH,
+            size::mb(2),
+            PageType::PAGE_TABLE | PageType::READ_ONLY,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+
+        let mem_cache = CachedPhysicalMemory::new(mem, cache);
+        let mut virt_mem =
+            VirtualDma::new(mem_cache, x86::x64::ARCH, x86::x64::new_translator(dtb));
+
+        virt_mem.prefetch_hint(&[CTup2(virt_base, 0x2000), CTup2(Address::NULL, 0x1000)]);
+
+        let (mem_cache, _) = virt_mem.into_inner();
+        let mem = mem_cache.into_inner();
+
+        // the unmapped range is dropped, the mapped one is forwarded in physical pieces
+        assert!(!mem.hints.is_empty());
+        assert_eq!(
+            mem.hints.iter().map(|CTup2(_, size)| *size).sum::<umem>(),
+            0x2000
+        );
+    }
+
+    #[test]
+    fn invalidate_modified() {
+        use crate::mem::phys_mem::events::tests::TrackedMemory;
+
+        let mut mem = TrackedMemory::new(size::mb(1));
+        let mut backing = mem.mem.clone();
+        mem.start_tracking().unwrap();
+
+        let cache = PageCache::with_page_size(
+            size::kb(4),
+            size::kb(64),
+            PageType::PAGE_TABLE,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+        let mut mem_cache = CachedPhysicalMemory::new(mem, cache);
+
+        let addr = PhysicalAddress::with_page(Address::from(0x2000), PageType::PAGE_TABLE, 0x1000);
+        let mut value = 0u64;
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 0);
+
+        // modified behind the cache, e.g. by the target itself
+        backing.phys_write(addr, &0x1234u64).unwrap();
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 0);
+
+        mem_cache.mem.touch(addr.address());
+        assert_eq!(mem_cache.invalidate_modified().unwrap(), 1);
+
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 0x1234);
+    }
+
+    #[test]
+    fn stats() {
+        let cache = PageCache::with_page_size(
+            size::kb(4),
+            size::kb(8),
+            PageType::PAGE_TABLE,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+        let mut mem_cache = CachedPhysicalMemory::new(DummyMemory::new(size::mb(1)), cache);
+
+        let page = |addr: u64| {
+            PhysicalAddress::with_page(Address::from(addr), PageType::PAGE_TABLE, 0x1000)
+        };
+        let mut value = 0u64;
+
+        mem_cache.phys_read_into(page(0x1000), &mut value).unwrap();
+        mem_cache.phys_read_into(page(0x1008), &mut value).unwrap();
+        // evicts 0x1000, both map to the second of the two entries
+        mem_cache.phys_read_into(page(0x3000), &mut value).unwrap();
+        mem_cache.phys_write(page(0x3000), &value).unwrap();
+        // uncached page types are not counted
+        mem_cache
+            .phys_read_into(Address::from(0x1000).into(), &mut value)
+            .unwrap();
+
+        assert_eq!(
+            mem_cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                evictions: 1,
+                invalidations: 0,
+                promotions: 0,
+                demotions: 0,
+            }
+        );
+        assert_eq!(mem_cache.stats().hit_ratio(), 1.0 / 3.0);
+
+        mem_cache
+            .cache
+            .invalidate_page(Address::from(0x3000), PageType::PAGE_TABLE);
+        assert_eq!(mem_cache.stats().invalidations, 1);
+
+        mem_cache.reset_stats();
+        assert_eq!(mem_cache.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn external_invalidation() {
+        let cache = PageCache::with_page_size(
+            size::kb(4),
+            size::kb(64),
+            PageType::PAGE_TABLE,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+        let mut backing = DummyMemory::new(size::mb(1));
+        let mut mem_cache = CachedPhysicalMemory::new(backing.clone(), cache);
+
+        let addr = PhysicalAddress::with_page(Address::from(0x2000), PageType::PAGE_TABLE, 0x1000);
+        let mut value = 0u64;
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+
+        // the clone shares the underlying buffer
+        backing.phys_write(addr, &1u64).unwrap();
+        mem_cache.invalidate_range(Address::from(0x2004), 1);
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 1);
+
+        let mut dirty = Some(CTup2(addr.address(), 8));
+        mem_cache.set_invalidation_hook(move |mut out| {
+            if let Some(range) = dirty.take() {
+                out.call(range);
+            }
+        });
+
+        backing.phys_write(addr, &2u64).unwrap();
+        mem_cache.phys_read_into(addr, &mut value).unwrap();
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn secondary_cache() {
+        use super::super::secondary::MemoryPageStore;
+
+        // a single entry, so every other page evicts the previous one
+        let cache = PageCache::with_page_size(
+            size::kb(4),
+            size::kb(4),
+            PageType::PAGE_TABLE,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        )
+        .with_secondary(Box::new(MemoryPageStore::new(size::mb(1))));
+        let mut backing = DummyMemory::new(size::mb(1));
+        backing
+            .phys_write(Address::from(0x1000).into(), &1u64)
+            .unwrap();
+        let mut mem_cache = CachedPhysicalMemory::new(backing.clone(), cache);
+
+        let mut read = |addr: u64| {
+            let addr =
+                PhysicalAddress::with_page(Address::from(addr), PageType::PAGE_TABLE, 0x1000);
+            let mut value = 0u64;
+            mem_cache.phys_read_into(addr, &mut value).unwrap();
+            (value, mem_cache.stats())
+        };
+
+        read(0x1000);
+        read(0x2000);
+
+        // the demoted page is promoted back without rereading it
+        backing
+            .phys_write(Address::from(0x1000).into(), &2u64)
+            .unwrap();
+        let (value, stats) = read(0x1000);
+        assert_eq!(value, 1);
+        assert_eq!(stats.promotions, 1);
+        assert_eq!(stats.demotions, 2);
+    }
+
+    #[test]
+    fn set_associative() {
+        // a single set of two entries
+        let cache = PageCache::with_page_size(
+            size::kb(4),
+            size::kb(8),
+            PageType::PAGE_TABLE,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        )
+        .with_associativity(2);
+        let mut mem_cache = CachedPhysicalMemory::new(DummyMemory::new(size::mb(1)), cache);
+
+        let mut read = |addr: u64| {
+            let addr =
+                PhysicalAddress::with_page(Address::from(addr), PageType::PAGE_TABLE, 0x1000);
+            mem_cache.phys_read_into::<u64>(addr, &mut 0).unwrap();
+            mem_cache.stats().hits
+        };
+
+        // pages aliasing in a direct mapped cache do not evict each other
+        read(0x1000);
+        read(0x3000);
+        assert_eq!(read(0x1000), 1);
+        assert_eq!(read(0x3000), 2);
+
+        // replaces the least recently used page
+        read(0x4000);
+        assert_eq!(read(0x3000), 3);
+        assert_eq!(read(0x1000), 3);
+    }
 }
@@ -0,0 +1,252 @@
+//! Second level of the page cache.
+//!
+//! The page cache itself is kept small, so that it mostly stays in the caches of the cpu. Pages
+//! evicted from it can be demoted into a much bigger, but slower [`SecondaryCache`], and are
+//! promoted back on the next access instead of being reread from the connector. The levels are
+//! exclusive, a page is only ever held by one of them.
+//!
+//! Secondary caches do not expire their pages, they are dropped on writes and explicit
+//! invalidations only. They are thus best suited for targets that do not change on their own,
+//! like snapshots or paused systems.
+
+use std::prelude::v1::*;
+
+use crate::types::{umem, Address};
+
+use std::collections::BTreeMap;
+
+/// Storage for pages evicted from the page cache, e.g. in compressed memory or on disk.
+pub trait SecondaryCache: Send {
+    /// Stores a copy of the page at `addr`, replacing a previously stored one.
+    fn put(&mut self, addr: Address, page: &[u8]);
+
+    /// Moves the page at `addr` into `out`, returns `false` if no page of `out.len()` bytes is
+    /// stored at `addr`.
+    fn take(&mut self, addr: Address, out: &mut [u8]) -> bool;
+
+    /// Drops all pages overlapping with the `len` bytes at `addr`.
+    fn invalidate_range(&mut self, addr: Address, len: usize);
+}
+
+enum StoredPage {
+    Zeroes,
+    Raw(Box<[u8]>),
+    #[cfg(feature = "compressed_cache")]
+    Lz4(Box<[u8]>),
+}
+
+impl StoredPage {
+    /// Amount of bytes accounted for the page.
+    fn cost(&self) -> usize {
+        // the key and bookkeeping of a page
+        const OVERHEAD: usize = 32;
+
+        OVERHEAD
+            + match self {
+                StoredPage::Zeroes => 0,
+                StoredPage::Raw(data) => data.len(),
+                #[cfg(feature = "compressed_cache")]
+                StoredPage::Lz4(data) => data.len(),
+            }
+    }
+}
+
+/// In-memory secondary cache with a fixed capacity.
+///
+/// Pages that only contain zeroes, which make up a large part of most memory images, are stored
+/// without their contents. When the capacity is exceeded the oldest pages are dropped first.
+///
+/// With the `compressed_cache` feature pages can also be stored LZ4 compressed, see
+/// [`compressed`](Self::compressed).
+#[derive(Default)]
+pub struct MemoryPageStore {
+    /// Pages by address and size
+    pages: BTreeMap<(Address, usize), (u64, StoredPage)>,
+    /// Keys of the pages by their sequence number, i.e. in insertion order
+    order: BTreeMap<u64, (Address, usize)>,
+    sequence: u64,
+    capacity: usize,
+    used: usize,
+    max_page_size: usize,
+    #[cfg(feature = "compressed_cache")]
+    compress: bool,
+}
+
+impl MemoryPageStore {
+    /// Creates a store holding up to `capacity` bytes of pages.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a store holding up to `capacity` bytes of LZ4 compressed pages.
+    ///
+    /// Pages are decompressed when they are promoted back into the page cache. Kernel code and
+    /// sparsely used pages typically compress very well, so that a multiple of the pages fits
+    /// into the same capacity. Pages that do not compress are stored as they are.
+    #[cfg(feature = "compressed_cache")]
+    pub fn compressed(capacity: usize) -> Self {
+        Self {
+            capacity,
+            compress: true,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the amount of bytes currently used.
+    pub fn used_bytes(&self) -> usize {
+        self.used
+    }
+
+    fn encode(&self, page: &[u8]) -> StoredPage {
+        if page.iter().all(|&b| b == 0) {
+            return StoredPage::Zeroes;
+        }
+
+        #[cfg(feature = "compressed_cache")]
+        if self.compress {
+            let compressed = lz4_flex::block::compress(page);
+            if compressed.len() < page.len() {
+                return StoredPage::Lz4(compressed.into_boxed_slice());
+            }
+        }
+
+        StoredPage::Raw(page.into())
+    }
+
+    fn remove(&mut self, key: (Address, usize)) -> Option<StoredPage> {
+        let (sequence, data) = self.pages.remove(&key)?;
+        self.order.remove(&sequence);
+        self.used -= data.cost();
+        Some(data)
+    }
+}
+
+impl SecondaryCache for MemoryPageStore {
+    fn put(&mut self, addr: Address, page: &[u8]) {
+        let key = (addr, page.len());
+        self.remove(key);
+
+        let data = self.encode(page);
+
+        let cost = data.cost();
+        if cost > self.capacity {
+            return;
+        }
+
+        while self.used + cost > self.capacity {
+            let oldest = match self.order.values().next() {
+                Some(&key) => key,
+                None => break,
+            };
+            self.remove(oldest);
+        }
+
+        self.sequence += 1;
+        self.used += cost;
+        self.max_page_size = std::cmp::max(self.max_page_size, page.len());
+        self.pages.insert(key, (self.sequence, data));
+        self.order.insert(self.sequence, key);
+    }
+
+    fn take(&mut self, addr: Address, out: &mut [u8]) -> bool {
+        match self.remove((addr, out.len())) {
+            Some(StoredPage::Zeroes) => out.iter_mut().for_each(|b| *b = 0),
+            Some(StoredPage::Raw(data)) => out.copy_from_slice(&data),
+            #[cfg(feature = "compressed_cache")]
+            Some(StoredPage::Lz4(data)) => {
+                let expected = out.len();
+                return lz4_flex::block::decompress_into(&data, out).ok() == Some(expected);
+            }
+            None => return false,
+        }
+        true
+    }
+
+    fn invalidate_range(&mut self, addr: Address, len: usize) {
+        let start = Address::from(addr.to_umem().saturating_sub(self.max_page_size as umem));
+        let end = addr + len;
+
+        let overlapping = self
+            .pages
+            .range((start, 0)..(end, 0))
+            .map(|(&key, _)| key)
+            .filter(|&(page, size)| page + size > addr)
+            .collect::<Vec<_>>();
+
+        for key in overlapping {
+            self.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_page_store() {
+        let mut store = MemoryPageStore::new(100);
+        store.put(Address::from(0x1000), &[1; 16]);
+        store.put(Address::from(0x2000), &[0; 16]);
+        assert_eq!(store.used_bytes(), 32 + 16 + 32);
+
+        // evicts the oldest page
+        store.put(Address::from(0x3000), &[3; 16]);
+        let mut buf = [0xffu8; 16];
+        assert!(!store.take(Address::from(0x1000), &mut buf));
+        assert!(store.take(Address::from(0x2000), &mut buf));
+        assert_eq!(buf, [0; 16]);
+
+        // pages are moved out
+        assert!(!store.take(Address::from(0x2000), &mut buf));
+
+        store.invalidate_range(Address::from(0x300f), 1);
+        assert!(!store.take(Address::from(0x3000), &mut buf));
+        assert_eq!(store.used_bytes(), 0);
+    }
+
+    #[test]
+    fn promote_demote_order() {
+        let mut store = MemoryPageStore::new(100);
+        let mut buf = [0u8; 16];
+
+        for _ in 0..1000 {
+            store.put(Address::from(0x1000), &[1; 16]);
+            assert!(store.take(Address::from(0x1000), &mut buf));
+        }
+        assert!(store.order.is_empty());
+
+        for i in 0..1000 {
+            store.put(Address::from(0x1000u64 * (i % 4)), &[1; 16]);
+        }
+        assert_eq!(store.order.len(), store.pages.len());
+        assert!(store.order.len() * 48 <= store.capacity);
+    }
+
+    #[cfg(feature = "compressed_cache")]
+    #[test]
+    fn compressed_page_store() {
+        let page = (0..0x1000).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+
+        let mut store = MemoryPageStore::compressed(0x1000);
+        for i in 0..4 {
+            store.put(Address::from(i * 0x1000), &page);
+        }
+        assert!(store.used_bytes() < 0x1000);
+
+        let mut buf = vec![0; 0x1000];
+        assert!(store.take(Address::from(0x3000), &mut buf));
+        assert_eq!(buf, page);
+
+        // promoting and demoting compressed pages does not grow the eviction order
+        for _ in 0..1000 {
+            store.put(Address::from(0x3000), &page);
+            assert!(store.take(Address::from(0x3000), &mut buf));
+        }
+        assert_eq!(store.order.len(), 3);
+        assert_eq!(store.order.len(), store.pages.len());
+    }
+}
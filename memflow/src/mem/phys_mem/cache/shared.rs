@@ -0,0 +1,612 @@
+//! Page cache that can be shared between multiple threads.
+//!
+//! [`CachedPhysicalMemory`](super::CachedPhysicalMemory) owns its cache, so every thread that
+//! accesses the same target has to clone the whole cache and fill it up separately. For read
+//! mostly workloads with many threads this wastes both memory and connector bandwidth.
+//!
+//! [`SharedPageCache`] is a direct mapped page cache where every slot is synchronized on its
+//! own through a sequence counter. Readers never block, they optimistically copy the data out
+//! and retry through the underlying connector in case the slot was modified concurrently.
+//! Writers only ever contend on a single slot.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::{MemoryView, PhysicalMemory, SharedCachedPhysicalMemory, SharedPageCache};
+//! use memflow::types::{size, PageType};
+//!
+//! let mem = DummyMemory::new(size::mb(4));
+//!
+//! let cache = Arc::new(SharedPageCache::new(
+//!     size::kb(4),
+//!     size::mb(1),
+//!     PageType::PAGE_TABLE | PageType::READ_ONLY,
+//!     std::time::Duration::from_millis(1000).into(),
+//! ));
+//!
+//! let handles = (0..4)
+//!     .map(|_| {
+//!         let mut mem = SharedCachedPhysicalMemory::new(mem.clone(), cache.clone());
+//!         thread::spawn(move || {
+//!             let value: u64 = mem.phys_view().read(0x1000.into()).unwrap();
+//!             value
+//!         })
+//!     })
+//!     .collect::<Vec<_>>();
+//!
+//! for handle in handles {
+//!     handle.join().unwrap();
+//! }
+//! ```
+
+use crate::error::Result;
+use crate::iter::PageChunks;
+use crate::mem::mem_data::*;
+use crate::mem::phys_mem::events::{ModifiedPageCallback, PhysicalMemoryEvents};
+use crate::mem::{
+    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalRange,
+    PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address, PageType, PhysicalAddress};
+use cglue::tuple::*;
+
+use coarsetime::{Duration, Instant};
+
+use std::convert::TryFrom;
+use std::prelude::v1::*;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A single cache entry.
+///
+/// `seq` is odd while the entry is being modified. `frame` holds the page frame number plus one,
+/// or 0 for empty entries. `stamp` holds the time the entry was filled at.
+struct Slot {
+    seq: AtomicU64,
+    frame: AtomicU64,
+    stamp: AtomicU64,
+    data: Box<[AtomicU64]>,
+}
+
+impl Slot {
+    fn new(words: usize) -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            frame: AtomicU64::new(0),
+            stamp: AtomicU64::new(0),
+            data: (0..words).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn try_lock(&self) -> Option<u64> {
+        let seq = self.seq.load(Ordering::Relaxed);
+        if seq & 1 != 0 {
+            return None;
+        }
+        self.seq
+            .compare_exchange(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        // keep the data stores from being reordered before the sequence update
+        fence(Ordering::Release);
+        Some(seq)
+    }
+
+    fn lock(&self) -> u64 {
+        loop {
+            if let Some(seq) = self.try_lock() {
+                return seq;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    fn unlock(&self, seq: u64) {
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+
+    fn copy_out(&self, start: usize, out: &mut [u8]) {
+        let mut done = 0;
+        while done < out.len() {
+            let pos = start + done;
+            let off = pos % 8;
+            let len = std::cmp::min(8 - off, out.len() - done);
+            let bytes = self.data[pos / 8].load(Ordering::Relaxed).to_ne_bytes();
+            out[done..done + len].copy_from_slice(&bytes[off..off + len]);
+            done += len;
+        }
+    }
+
+    fn copy_in(&self, start: usize, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() {
+            let pos = start + done;
+            let off = pos % 8;
+            let len = std::cmp::min(8 - off, data.len() - done);
+            let word = &self.data[pos / 8];
+            let mut bytes = if len == 8 {
+                [0; 8]
+            } else {
+                word.load(Ordering::Relaxed).to_ne_bytes()
+            };
+            bytes[off..off + len].copy_from_slice(&data[done..done + len]);
+            word.store(u64::from_ne_bytes(bytes), Ordering::Relaxed);
+            done += len;
+        }
+    }
+}
+
+/// A page cache that can be accessed by multiple threads at once.
+///
+/// The cache is usually wrapped in an [`Arc`] and handed to multiple
+/// [`SharedCachedPhysicalMemory`] objects, one for every thread.
+pub struct SharedPageCache {
+    slots: Box<[Slot]>,
+    page_size: usize,
+    page_type_mask: PageType,
+    valid_ticks: u64,
+}
+
+impl SharedPageCache {
+    /// Constructs a new cache holding `size` bytes worth of `page_size` sized pages.
+    ///
+    /// Pages matching `page_type_mask` are cached and stay valid for `valid_time`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` is not a multiple of 8, or if `size` is smaller than `page_size`.
+    pub fn new(
+        page_size: usize,
+        size: usize,
+        page_type_mask: PageType,
+        valid_time: Duration,
+    ) -> Self {
+        assert!(page_size > 0 && page_size % 8 == 0);
+
+        let entries = size / page_size;
+        assert!(entries > 0);
+
+        Self {
+            slots: (0..entries).map(|_| Slot::new(page_size / 8)).collect(),
+            page_size,
+            page_type_mask,
+            valid_ticks: valid_time.as_ticks(),
+        }
+    }
+
+    /// Returns the page size of this cache.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns true if pages of the given type are being cached.
+    pub fn is_cached_page_type(&self, page_type: PageType) -> bool {
+        self.page_type_mask.contains(page_type)
+    }
+
+    /// Invalidates all entries of the cache.
+    pub fn invalidate_all(&self) {
+        for slot in self.slots.iter() {
+            let seq = slot.lock();
+            slot.frame.store(0, Ordering::Relaxed);
+            slot.unlock(seq);
+        }
+    }
+
+    /// Invalidates all entries overlapping with the `size` bytes at `addr`.
+    pub fn invalidate_range(&self, addr: Address, size: usize) {
+        let mut page = addr.as_page_aligned(self.page_size);
+        while page < addr + size {
+            if let Some((tag, slot)) = self.slot(page) {
+                if slot.frame.load(Ordering::Relaxed) == tag {
+                    let seq = slot.lock();
+                    if slot.frame.load(Ordering::Relaxed) == tag {
+                        slot.frame.store(0, Ordering::Relaxed);
+                    }
+                    slot.unlock(seq);
+                }
+            }
+            page = page + self.page_size;
+        }
+    }
+
+    #[allow(clippy::useless_conversion)]
+    fn slot(&self, addr: Address) -> Option<(u64, &Slot)> {
+        let frame = u64::try_from(addr.to_umem() / self.page_size as umem).ok()?;
+        let slot = &self.slots[(frame % self.slots.len() as u64) as usize];
+        Some((frame + 1, slot))
+    }
+
+    /// Copies cached data into `out`. `out` must not cross a page boundary.
+    ///
+    /// Returns false if the page is not cached, expired or was modified during the copy.
+    fn read(&self, addr: Address, out: &mut [u8], now: u64) -> bool {
+        let (tag, slot) = match self.slot(addr) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        let seq = slot.seq.load(Ordering::Acquire);
+        if seq & 1 != 0
+            || slot.frame.load(Ordering::Relaxed) != tag
+            || now.saturating_sub(slot.stamp.load(Ordering::Relaxed)) > self.valid_ticks
+        {
+            return false;
+        }
+
+        let start = (addr - addr.as_page_aligned(self.page_size)) as usize;
+        slot.copy_out(start, out);
+
+        fence(Ordering::Acquire);
+        slot.seq.load(Ordering::Relaxed) == seq
+    }
+
+    /// Stores a freshly read page. The page is skipped if the entry is being modified.
+    fn fill(&self, page: Address, data: &[u8], now: u64) {
+        debug_assert_eq!(data.len(), self.page_size);
+
+        if let Some((tag, slot)) = self.slot(page) {
+            if let Some(seq) = slot.try_lock() {
+                slot.frame.store(tag, Ordering::Relaxed);
+                slot.stamp.store(now, Ordering::Relaxed);
+                slot.copy_in(0, data);
+                slot.unlock(seq);
+            }
+        }
+    }
+
+    /// Updates a cached page with written data. `data` must not cross a page boundary.
+    fn write_back(&self, addr: Address, data: &[u8]) {
+        if let Some((tag, slot)) = self.slot(addr) {
+            if slot.frame.load(Ordering::Relaxed) != tag {
+                return;
+            }
+
+            let seq = slot.lock();
+            if slot.frame.load(Ordering::Relaxed) == tag {
+                let start = (addr - addr.as_page_aligned(self.page_size)) as usize;
+                slot.copy_in(start, data);
+            }
+            slot.unlock(seq);
+        }
+    }
+}
+
+/// A physical memory wrapper that caches pages in a [`SharedPageCache`].
+///
+/// Cloning this object clones the underlying memory object, but keeps referencing the same
+/// cache. This allows multiple threads to share their cached pages.
+#[derive(Clone)]
+pub struct SharedCachedPhysicalMemory<T> {
+    mem: T,
+    cache: Arc<SharedPageCache>,
+}
+
+impl<T: PhysicalMemory> SharedCachedPhysicalMemory<T> {
+    /// Constructs a new wrapper around `mem`, using the given cache.
+    pub fn new(mem: T, cache: Arc<SharedPageCache>) -> Self {
+        Self { mem, cache }
+    }
+
+    /// Returns the cache used by this object.
+    pub fn cache(&self) -> &Arc<SharedPageCache> {
+        &self.cache
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: PhysicalMemory> PhysicalMemory for SharedCachedPhysicalMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let cache = &*self.cache;
+        let mem = &mut self.mem;
+
+        let page_size = cache.page_size;
+        let now = Instant::now().as_ticks();
+        let limits = mem.metadata().batch_limits();
+
+        let mut uncached = vec![];
+        let mut misses = vec![];
+
+        for CTup3(addr, meta_addr, buf) in inp {
+            if !cache.is_cached_page_type(addr.page_type()) {
+                uncached.push(CTup3(addr, meta_addr, buf));
+                continue;
+            }
+
+            for (paddr, (meta_addr, mut chunk)) in
+                (meta_addr, buf).page_chunks(addr.address(), page_size)
+            {
+                if cache.read(paddr, &mut chunk, now) {
+                    opt_call(out.as_deref_mut(), CTup2(meta_addr, chunk));
+                } else {
+                    misses.push(CTup3(
+                        PhysicalAddress::with_page(paddr, addr.page_type(), addr.page_size()),
+                        meta_addr,
+                        chunk,
+                    ));
+                }
+            }
+        }
+
+        if !uncached.is_empty() {
+            MemOps::with_raw_chunked(
+                uncached.into_iter(),
+                out.as_deref_mut(),
+                out_fail.as_deref_mut(),
+                limits,
+                |CTup3(_, _, buf)| buf.len(),
+                |data| mem.phys_read_raw_iter(data),
+            )?;
+        }
+
+        if misses.is_empty() {
+            return Ok(());
+        }
+
+        // read every missing page once, as a whole
+        let mut pages = misses
+            .iter()
+            .map(|CTup3(addr, _, _)| addr.address().as_page_aligned(page_size))
+            .collect::<Vec<_>>();
+        pages.sort_unstable();
+        pages.dedup();
+
+        let mut scratch = vec![0u8; pages.len() * page_size];
+        let mut valid = vec![false; pages.len()];
+
+        {
+            let iter = pages
+                .iter()
+                .zip(scratch.chunks_mut(page_size))
+                .map(|(&page, buf)| CTup3(PhysicalAddress::from(page), page, buf.into()));
+
+            let callback = &mut |CTup2(page, buf): ReadData| {
+                if buf.len() == page_size {
+                    if let Ok(idx) = pages.binary_search(&page) {
+                        valid[idx] = true;
+                    }
+                }
+                true
+            };
+
+            let mut callback = callback.into();
+
+            MemOps::with_raw_chunked(
+                iter,
+                Some(&mut callback),
+                None,
+                limits,
+                |CTup3(_, _, buf)| buf.len(),
+                |data| mem.phys_read_raw_iter(data),
+            )?;
+        }
+
+        for ((&page, data), &is_valid) in pages
+            .iter()
+            .zip(scratch.chunks(page_size))
+            .zip(valid.iter())
+        {
+            if is_valid {
+                cache.fill(page, data, now);
+            }
+        }
+
+        let mut failed = vec![];
+
+        for CTup3(addr, meta_addr, mut chunk) in misses {
+            let page = addr.address().as_page_aligned(page_size);
+            match pages.binary_search(&page) {
+                Ok(idx) if valid[idx] => {
+                    let start = idx * page_size + (addr.address() - page) as usize;
+                    chunk.copy_from_slice(&scratch[start..start + chunk.len()]);
+                    opt_call(out.as_deref_mut(), CTup2(meta_addr, chunk));
+                }
+                _ => failed.push(CTup3(addr, meta_addr, chunk)),
+            }
+        }
+
+        // pages that could not be read as a whole are retried without the cache,
+        // so that partially readable pages still report as much data as possible
+        if !failed.is_empty() {
+            MemOps::with_raw_chunked(
+                failed.into_iter(),
+                out,
+                out_fail,
+                limits,
+                |CTup3(_, _, buf)| buf.len(),
+                |data| mem.phys_read_raw_iter(data),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let cache = &*self.cache;
+        let mem = &mut self.mem;
+
+        let inp = inp.map(move |CTup3(addr, meta_addr, data)| {
+            if cache.is_cached_page_type(addr.page_type()) {
+                for (paddr, data_chunk) in data.page_chunks(addr.address(), cache.page_size) {
+                    // write-back into cached pages
+                    cache.write_back(paddr, data_chunk.into());
+                }
+            }
+            CTup3(addr, meta_addr, data)
+        });
+
+        MemOps::with_raw(inp, out, out_fail, move |data| {
+            mem.phys_write_raw_iter(data)
+        })
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
+}
+
+impl<T: PhysicalMemory + PhysicalMemoryEvents> SharedCachedPhysicalMemory<T> {
+    /// Invalidates all cached pages the underlying memory object reports as modified.
+    ///
+    /// The pages are invalidated for all users of the cache. Returns the number of modified
+    /// pages.
+    pub fn invalidate_modified(&mut self) -> Result<usize> {
+        let page_size = self.mem.event_page_size();
+        let pages = self.mem.modified_pages_list()?;
+
+        for &page in pages.iter() {
+            self.cache.invalidate_range(page, page_size);
+        }
+
+        Ok(pages.len())
+    }
+}
+
+/// Forwards the notifications of the underlying memory object and invalidates the modified
+/// pages on the way.
+impl<T: PhysicalMemory + PhysicalMemoryEvents> PhysicalMemoryEvents
+    for SharedCachedPhysicalMemory<T>
+{
+    fn event_page_size(&self) -> usize {
+        self.mem.event_page_size()
+    }
+
+    fn start_tracking(&mut self) -> Result<()> {
+        self.mem.start_tracking()
+    }
+
+    fn stop_tracking(&mut self) -> Result<()> {
+        self.mem.stop_tracking()
+    }
+
+    fn modified_pages(&mut self, mut out: ModifiedPageCallback) -> Result<()> {
+        let page_size = self.mem.event_page_size();
+        let cache = &*self.cache;
+
+        self.mem.modified_pages(
+            (&mut |page: Address| {
+                cache.invalidate_range(page, page_size);
+                out.call(page)
+            })
+                .into(),
+        )
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue::cglue_impl_group!(
+    SharedCachedPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    use std::thread;
+
+    fn page(addr: u64) -> PhysicalAddress {
+        PhysicalAddress::with_page(addr.into(), PageType::READ_ONLY, size::kb(4) as umem)
+    }
+
+    fn new_cache() -> Arc<SharedPageCache> {
+        Arc::new(SharedPageCache::new(
+            size::kb(4),
+            size::kb(64),
+            PageType::PAGE_TABLE | PageType::READ_ONLY,
+            Duration::from_secs(100),
+        ))
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let data = (0..size::mb(1)).map(|i| (i / 7) as u8).collect::<Vec<_>>();
+        mem.phys_write(page(0), data.as_slice()).unwrap();
+
+        let cache = new_cache();
+
+        let handles = (0..4)
+            .map(|t| {
+                let mut mem = SharedCachedPhysicalMemory::new(mem.clone(), cache.clone());
+                let data = data.clone();
+                thread::spawn(move || {
+                    for i in 0..256u64 {
+                        let addr = ((i * 0x1f3 + t * 0x35) % 0x20000) & !0xf;
+                        let mut buf = [0u8; 0x1100];
+                        mem.phys_read_into(page(addr), &mut buf[..]).unwrap();
+                        assert_eq!(&buf[..], &data[addr as usize..addr as usize + buf.len()]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn write_back() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(page(0x2000), &[1u8; 16]).unwrap();
+
+        let cache = new_cache();
+        let mut mem_a = SharedCachedPhysicalMemory::new(mem.clone(), cache.clone());
+        let mut mem_b = SharedCachedPhysicalMemory::new(mem.clone(), cache);
+
+        let mut buf = [0u8; 16];
+        mem_a.phys_read_into(page(0x2000), &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 16]);
+
+        // modifications behind the cache's back are not visible to either of the handles
+        mem.phys_write(page(0x2000), &[2u8; 16]).unwrap();
+        mem_b.phys_read_into(page(0x2000), &mut buf).unwrap();
+        assert_eq!(buf, [1u8; 16]);
+
+        // writes through one of the handles are
+        mem_b.phys_write(page(0x2004), &[3u8; 5]).unwrap();
+        mem_a.phys_read_into(page(0x2000), &mut buf).unwrap();
+        assert_eq!(&buf[..4], &[1u8; 4]);
+        assert_eq!(&buf[4..9], &[3u8; 5]);
+        assert_eq!(&buf[9..], &[1u8; 7]);
+
+        mem_a.cache().invalidate_all();
+        mem_a.phys_read_into(page(0x2000), &mut buf).unwrap();
+        assert_eq!(&buf[..4], &[2u8; 4]);
+        assert_eq!(&buf[4..9], &[3u8; 5]);
+        assert_eq!(&buf[9..], &[2u8; 7]);
+    }
+}
@@ -0,0 +1,374 @@
+//! Write-back buffering of physical writes.
+//!
+//! Pending writes are kept sorted by address and adjacent or overlapping writes are merged, so
+//! that a flush issues as few and as large writes as possible.
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::*;
+use crate::mem::{opt_call, PhysicalMemory, PhysicalMemoryMetadata};
+use crate::types::{umem, Address};
+use cglue::slice::CSliceRef;
+use cglue::tuple::*;
+
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+
+#[derive(Clone, Default)]
+pub(crate) struct PendingWrites {
+    writes: BTreeMap<Address, Vec<u8>>,
+    bytes: usize,
+}
+
+impl PendingWrites {
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Number of bytes that would be written by a flush.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.writes.clear();
+        self.bytes = 0;
+    }
+
+    pub fn insert(&mut self, addr: Address, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let mut start = addr;
+        let mut end = addr + data.len();
+
+        // collect all writes touching the range, they never overlap each other
+        let touching = self
+            .writes
+            .range(..=end)
+            .rev()
+            .take_while(|&(&other, buf)| other + buf.len() >= start)
+            .map(|(&other, _)| other)
+            .collect::<Vec<_>>();
+
+        let merged = touching
+            .into_iter()
+            .rev()
+            .map(|other| (other, self.writes.remove(&other).unwrap()))
+            .collect::<Vec<_>>();
+
+        for (other, buf) in merged.iter() {
+            start = std::cmp::min(start, *other);
+            end = std::cmp::max(end, *other + buf.len());
+        }
+
+        let mut buf = vec![0; (end - start) as usize];
+        for (other, old) in merged.iter() {
+            let offset = (*other - start) as usize;
+            buf[offset..offset + old.len()].copy_from_slice(old);
+            self.bytes -= old.len();
+        }
+
+        let offset = (addr - start) as usize;
+        buf[offset..offset + data.len()].copy_from_slice(data);
+
+        self.bytes += buf.len();
+        self.writes.insert(start, buf);
+    }
+
+    /// Copies the pending data overlapping with `buf` at `addr` into it.
+    ///
+    /// Returns the amount of bytes that were copied.
+    pub fn patch(&self, addr: Address, buf: &mut [u8]) -> usize {
+        let end = addr + buf.len();
+        let mut patched = 0;
+
+        // the writes do not overlap, so they end in the same order as they start
+        for (&other, data) in self
+            .writes
+            .range(..end)
+            .rev()
+            .take_while(|&(&other, data)| other + data.len() > addr)
+        {
+            let start = std::cmp::max(addr, other);
+            let stop = std::cmp::min(end, other + data.len());
+            let len = (stop - start) as usize;
+
+            let src = (start - other) as usize;
+            let dst = (start - addr) as usize;
+            buf[dst..dst + len].copy_from_slice(&data[src..src + len]);
+            patched += len;
+        }
+
+        patched
+    }
+
+    /// Writes all pending data to `mem` in a single batch.
+    ///
+    /// The pending writes are dropped even if some of them fail.
+    pub fn flush<T: PhysicalMemory>(&mut self, mem: &mut T) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let writes = std::mem::take(&mut self.writes);
+        self.bytes = 0;
+
+        let mut failed = false;
+        let fail = &mut |_: WriteData| {
+            failed = true;
+            true
+        };
+
+        MemOps::with(
+            writes
+                .iter()
+                .map(|(&addr, buf)| (addr.into(), CSliceRef::from(buf.as_slice()))),
+            None,
+            Some(&mut fail.into()),
+            |data| mem.phys_write_raw_iter(data),
+        )?;
+
+        if failed {
+            Err(Error(ErrorOrigin::Cache, ErrorKind::PartialData)
+                .log_error("unable to flush all pending writes"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Memory object with write-back buffering.
+///
+/// The buffer is flushed when the object is dropped. Buffering is disabled if `max_pending` is 0.
+pub(crate) struct WriteBack<T> {
+    mem: Option<T>,
+    pub pending: PendingWrites,
+    pub max_pending: usize,
+    flush: fn(&mut T, &mut PendingWrites) -> Result<()>,
+}
+
+impl<T: PhysicalMemory> WriteBack<T> {
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem: Some(mem),
+            pending: PendingWrites::default(),
+            max_pending: 0,
+            flush: |mem, pending| pending.flush(mem),
+        }
+    }
+}
+
+impl<T> WriteBack<T> {
+    pub fn is_enabled(&self) -> bool {
+        self.max_pending > 0
+    }
+
+    /// Returns the memory object, patching the pending writes into all data read from it.
+    pub fn patched(&mut self) -> Patched<T> {
+        Patched {
+            mem: self.mem.as_mut().unwrap(),
+            pending: &self.pending,
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        match self.mem.as_mut() {
+            Some(mem) => (self.flush)(mem, &mut self.pending),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes the pending writes and returns the memory object.
+    pub fn into_inner(mut self) -> T {
+        self.flush().ok();
+        self.mem.take().unwrap()
+    }
+}
+
+impl<T: Clone> Clone for WriteBack<T> {
+    /// Pending writes are not cloned, they are only flushed by the original object.
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            pending: PendingWrites::default(),
+            max_pending: self.max_pending,
+            flush: self.flush,
+        }
+    }
+}
+
+impl<T> Deref for WriteBack<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.mem.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for WriteBack<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.mem.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for WriteBack<T> {
+    fn drop(&mut self) {
+        self.flush().ok();
+    }
+}
+
+/// Memory object whose reads observe the pending writes.
+///
+/// Reads through the cache go through this, so that neither the caller nor the cached pages miss
+/// writes that have not been flushed yet.
+pub(crate) struct Patched<'a, T> {
+    mem: &'a mut T,
+    pending: &'a PendingWrites,
+}
+
+impl<'a, T: PhysicalMemory> PhysicalMemory for Patched<'a, T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        if self.pending.is_empty() {
+            return self.mem.phys_read_raw_iter(MemOps { inp, out, out_fail });
+        }
+
+        // replace the meta addresses with offsets, so that partial results can be mapped back
+        // onto the physical address: (physical address, meta address, offset)
+        let mut elems = vec![];
+        let mut offset: umem = 0;
+        let inputs = inp
+            .map(|CTup3(addr, meta_addr, buf)| {
+                elems.push((addr.address(), meta_addr, offset));
+                let data = CTup3(addr, Address::from(offset), buf);
+                offset += data.2.len() as umem;
+                data
+            })
+            .collect::<Vec<_>>();
+
+        let locate = |offset: Address| {
+            let offset = offset.to_umem();
+            let idx = elems.partition_point(|&(_, _, start)| start <= offset) - 1;
+            let (addr, meta_addr, start) = elems[idx];
+            (addr + (offset - start), meta_addr + (offset - start))
+        };
+
+        let pending = self.pending;
+        let succeeded = &mut |CTup2(offset, mut buf): ReadData| {
+            let (addr, meta_addr) = locate(offset);
+            pending.patch(addr, &mut buf);
+            opt_call(out.as_deref_mut(), CTup2(meta_addr, buf))
+        };
+        let failed = &mut |CTup2(offset, buf): ReadData| {
+            let (_, meta_addr) = locate(offset);
+            opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+        };
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(
+            inputs.into_iter(),
+            Some(&mut succeeded.into()),
+            Some(&mut failed.into()),
+            |data| mem.phys_read_raw_iter(data),
+        )
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::cglue::ForwardMut;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{CachedPhysicalMemory, MemoryView};
+    use crate::types::size;
+
+    #[test]
+    fn coalesce_writes() {
+        let mut pending = PendingWrites::default();
+        pending.insert(Address::from(0x1000), &[1; 8]);
+        pending.insert(Address::from(0x1010), &[2; 8]);
+        assert_eq!(pending.writes.len(), 2);
+
+        // bridges the gap and overlaps both neighbours
+        pending.insert(Address::from(0x1004), &[3; 16]);
+        assert_eq!(pending.writes.len(), 1);
+        assert_eq!(pending.bytes(), 0x18);
+
+        // adjacent writes are merged as well
+        pending.insert(Address::from(0x1018), &[4; 8]);
+        assert_eq!(pending.writes.len(), 1);
+
+        let mut buf = [0u8; 0x10];
+        assert_eq!(pending.patch(Address::from(0x0ff8), &mut buf), 8);
+        assert_eq!(buf, [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 3, 3, 3, 3]);
+
+        let mut mem = DummyMemory::new(size::mb(1));
+        pending.flush(&mut mem).unwrap();
+        assert!(pending.is_empty());
+
+        let mut buf = [0u8; 0x20];
+        mem.phys_read_into(Address::from(0x1000).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf[..4], [1; 4]);
+        assert_eq!(buf[4..0x14], [3; 16]);
+        assert_eq!(buf[0x14..0x18], [2; 4]);
+        assert_eq!(buf[0x18..], [4; 8]);
+    }
+
+    #[test]
+    fn buffered_until_flush() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut cache = CachedPhysicalMemory::builder(mem.forward_mut())
+            .arch(x64::ARCH)
+            .write_back(size::kb(4))
+            .build()
+            .unwrap();
+
+        cache
+            .phys_write(Address::from(0x1000).into(), &1u64)
+            .unwrap();
+        assert_eq!(cache.mem.phys_view().read::<u64>(0x1000.into()).unwrap(), 0);
+
+        cache.flush().unwrap();
+        assert_eq!(cache.mem.phys_view().read::<u64>(0x1000.into()).unwrap(), 1);
+
+        // reads through the cache observe pending writes without flushing them
+        cache
+            .phys_write(Address::from(0x2000).into(), &2u64)
+            .unwrap();
+        assert_eq!(cache.phys_view().read::<u64>(0x2000.into()).unwrap(), 2);
+        assert_eq!(cache.mem.phys_view().read::<u64>(0x2000.into()).unwrap(), 0);
+
+        cache
+            .phys_write(Address::from(0x2ffe).into(), &0x0403u16)
+            .unwrap();
+        assert_eq!(
+            cache.phys_view().read::<u32>(0x2ffc.into()).unwrap(),
+            0x0403_0000
+        );
+
+        cache
+            .phys_write(Address::from(0x3000).into(), &3u64)
+            .unwrap();
+        std::mem::drop(cache);
+        assert_eq!(mem.phys_view().read::<u64>(0x3000.into()).unwrap(), 3);
+    }
+}
@@ -0,0 +1,441 @@
+//! Tracking of modified pages.
+//!
+//! [`DeltaTracker`] hashes every page the first time it is read through the tracker. Later, the
+//! tracked pages can be compared against the target again, to find the ones that changed
+//! ([`changed_pages`](DeltaTracker::changed_pages)), or their new contents can be captured as a
+//! [`PageDelta`] ([`take_delta`](DeltaTracker::take_delta)). Taking a delta moves the baseline to
+//! the captured contents, so periodically taken deltas form a chain of differential snapshots.
+//! Since changes are detected by rereading, this works through any connector.
+//!
+//! Pages that were never read can be added explicitly with
+//! [`track_range`](DeltaTracker::track_range).
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::{DeltaTracker, PhysicalMemory};
+//! use memflow::types::{size, Address};
+//!
+//! let mut mem = DummyMemory::new(size::mb(1));
+//! let mut tracker = DeltaTracker::new(mem.clone());
+//! tracker.track_range(Address::null(), size::kb(64) as _).unwrap();
+//!
+//! // the target changes behind the back of the tracker
+//! mem.phys_write(0x2000.into(), &0xdeadu32).unwrap();
+//!
+//! assert_eq!(tracker.changed_pages().unwrap(), vec![Address::from(0x2000)]);
+//!
+//! let delta = tracker.take_delta().unwrap();
+//! assert_eq!(delta.pages.len(), 1);
+//! assert!(tracker.changed_pages().unwrap().is_empty());
+//! ```
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::*;
+use crate::mem::memory_view::{Digest, HashAlgo};
+use crate::mem::{MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::types::{size, umem, Address, PhysicalAddress};
+
+use cglue::slice::CSliceMut;
+use cglue::tuple::*;
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::prelude::v1::*;
+
+/// Granularity at which changes are tracked.
+pub const DELTA_PAGE_SIZE: usize = size::kb(4);
+
+/// Amount of pages reread at once.
+const BATCH_SIZE: usize = 256;
+
+/// Magic bytes at the start of an encoded [`PageDelta`].
+const DELTA_MAGIC: [u8; 8] = *b"MFDELTA\0";
+
+/// Length stored for pages that could not be read.
+const UNREADABLE: u32 = u32::MAX;
+
+/// A page that changed since the previous delta.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeltaPage {
+    pub address: Address,
+    /// The new contents, `None` if the page can not be read anymore.
+    pub data: Option<Vec<u8>>,
+}
+
+/// The pages that changed between two points in time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PageDelta {
+    /// The changed pages, sorted by address.
+    pub pages: Vec<DeltaPage>,
+}
+
+impl PageDelta {
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Encodes the delta into its binary format.
+    ///
+    /// ```text
+    /// magic "MFDELTA\0" | page count: u64 | pages: address: u64 | length: u32 | data
+    /// ```
+    ///
+    /// All integers are little endian, unreadable pages are stored with a length of `u32::MAX`
+    /// and no data.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = DELTA_MAGIC.to_vec();
+        out.extend_from_slice(&(self.pages.len() as u64).to_le_bytes());
+
+        for page in self.pages.iter() {
+            out.extend_from_slice(&(page.address.to_umem() as u64).to_le_bytes());
+            match &page.data {
+                Some(data) => {
+                    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    out.extend_from_slice(data);
+                }
+                None => out.extend_from_slice(&UNREADABLE.to_le_bytes()),
+            }
+        }
+
+        out
+    }
+
+    /// Decodes a delta previously encoded with [`encode`](Self::encode).
+    pub fn decode(mut data: &[u8]) -> Result<Self> {
+        Self::decode_inner(&mut data).ok_or_else(|| {
+            Error(ErrorOrigin::Memory, ErrorKind::Encoding).log_error("invalid page delta")
+        })
+    }
+
+    fn decode_inner(data: &mut &[u8]) -> Option<Self> {
+        if take(data, DELTA_MAGIC.len())? != DELTA_MAGIC {
+            return None;
+        }
+
+        let count = u64::from_le_bytes(take(data, 8)?.try_into().ok()?);
+
+        let mut pages = vec![];
+        for _ in 0..count {
+            let address = u64::from_le_bytes(take(data, 8)?.try_into().ok()?);
+            let len = u32::from_le_bytes(take(data, 4)?.try_into().ok()?);
+            let data = match len {
+                UNREADABLE => None,
+                len => Some(take(data, len as usize)?.to_vec()),
+            };
+            pages.push(DeltaPage {
+                address: Address::from(address),
+                data,
+            });
+        }
+
+        Some(Self { pages })
+    }
+
+    /// Writes the contents of all readable pages to `mem`.
+    ///
+    /// Applying the deltas of a tracker in order onto a full image of the target brings the
+    /// image up to date.
+    pub fn apply<T: PhysicalMemory>(&self, mem: &mut T) -> Result<()> {
+        for page in self.pages.iter() {
+            if let Some(data) = &page.data {
+                mem.phys_write(page.address.into(), data.as_slice())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let cur: &'a [u8] = *data;
+    if cur.len() < len {
+        return None;
+    }
+    let (head, rest) = cur.split_at(len);
+    *data = rest;
+    Some(head)
+}
+
+/// Physical memory wrapper detecting changes of the pages read through it.
+#[derive(Clone)]
+pub struct DeltaTracker<T> {
+    mem: T,
+    algo: HashAlgo,
+    /// Digest of the baseline contents of every tracked page
+    pages: BTreeMap<Address, Digest>,
+}
+
+impl<T: PhysicalMemory> DeltaTracker<T> {
+    /// Creates a new tracker comparing pages by their CRC-32.
+    pub fn new(mem: T) -> Self {
+        Self::with_algo(mem, HashAlgo::Crc32)
+    }
+
+    /// Creates a new tracker comparing pages with the given hash algorithm.
+    pub fn with_algo(mem: T, algo: HashAlgo) -> Self {
+        Self {
+            mem,
+            algo,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of tracked pages.
+    pub fn tracked_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Starts tracking all pages overlapping with the `len` bytes at `addr`.
+    ///
+    /// Pages that are already tracked keep their baseline, unreadable pages are skipped.
+    pub fn track_range(&mut self, addr: Address, len: umem) -> Result<()> {
+        let mut untracked = vec![];
+        untracked_pages(&self.pages, addr, len, &mut untracked);
+        self.track_pages(untracked)
+    }
+
+    /// Stops tracking all pages.
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Rereads all tracked pages and returns the ones that changed since the baseline.
+    ///
+    /// Pages that can not be read anymore are reported as changed as well.
+    pub fn changed_pages(&mut self) -> Result<Vec<Address>> {
+        let addrs = self.pages.keys().copied().collect::<Vec<_>>();
+        let (pages, algo) = (&self.pages, self.algo);
+
+        let mut changed = vec![];
+        read_pages(&mut self.mem, &addrs, |addr, data| {
+            if data.map(|data| hash(algo, data)).as_ref() != pages.get(&addr) {
+                changed.push(addr);
+            }
+        })?;
+
+        Ok(changed)
+    }
+
+    /// Rereads all tracked pages and returns the contents of the changed ones.
+    ///
+    /// The returned contents become the new baseline. Pages that can not be read anymore are
+    /// no longer tracked.
+    pub fn take_delta(&mut self) -> Result<PageDelta> {
+        let addrs = self.pages.keys().copied().collect::<Vec<_>>();
+        let (pages, algo) = (&mut self.pages, self.algo);
+
+        let mut delta = PageDelta::default();
+        read_pages(&mut self.mem, &addrs, |addr, data| match data {
+            Some(data) => {
+                let digest = hash(algo, data);
+                if pages.insert(addr, digest) != Some(digest) {
+                    delta.pages.push(DeltaPage {
+                        address: addr,
+                        data: Some(data.to_vec()),
+                    });
+                }
+            }
+            None => {
+                pages.remove(&addr);
+                delta.pages.push(DeltaPage {
+                    address: addr,
+                    data: None,
+                });
+            }
+        })?;
+
+        Ok(delta)
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.mem
+    }
+
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    fn track_pages(&mut self, mut addrs: Vec<Address>) -> Result<()> {
+        addrs.sort_unstable();
+        addrs.dedup();
+
+        let (pages, algo) = (&mut self.pages, self.algo);
+        read_pages(&mut self.mem, &addrs, |addr, data| {
+            if let Some(data) = data {
+                pages.insert(addr, hash(algo, data));
+            }
+        })
+    }
+}
+
+fn hash(algo: HashAlgo, data: &[u8]) -> Digest {
+    let mut hasher = algo.hasher();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Collects the base addresses of all untracked pages overlapping with the given range.
+fn untracked_pages(
+    pages: &BTreeMap<Address, Digest>,
+    addr: Address,
+    len: umem,
+    out: &mut Vec<Address>,
+) {
+    if len == 0 {
+        return;
+    }
+
+    let end = addr + (len - 1);
+    let mut page = addr.as_page_aligned(DELTA_PAGE_SIZE);
+    while page <= end {
+        if !pages.contains_key(&page) {
+            out.push(page);
+        }
+        page += DELTA_PAGE_SIZE;
+    }
+}
+
+/// Reads the given pages in batches and calls `f` with the contents of every page, or `None`
+/// if the page could not be read.
+fn read_pages<T: PhysicalMemory>(
+    mem: &mut T,
+    pages: &[Address],
+    mut f: impl FnMut(Address, Option<&[u8]>),
+) -> Result<()> {
+    let mut buf = vec![0u8; DELTA_PAGE_SIZE * BATCH_SIZE];
+
+    for batch in pages.chunks(BATCH_SIZE) {
+        let mut failed = vec![false; batch.len()];
+
+        {
+            let iter = batch
+                .iter()
+                .zip(buf.chunks_mut(DELTA_PAGE_SIZE))
+                .map(|(&page, data)| (PhysicalAddress::from(page), CSliceMut::from(data)));
+
+            let callback = &mut |CTup2(addr, _): ReadData| {
+                if let Some(i) = batch
+                    .iter()
+                    .position(|&p| p <= addr && addr < p + DELTA_PAGE_SIZE)
+                {
+                    failed[i] = true;
+                }
+                true
+            };
+
+            MemOps::with(iter, None, Some(&mut callback.into()), |data| {
+                mem.phys_read_raw_iter(data)
+            })?;
+        }
+
+        for ((&page, data), &failed) in batch
+            .iter()
+            .zip(buf.chunks(DELTA_PAGE_SIZE))
+            .zip(failed.iter())
+        {
+            f(page, if failed { None } else { Some(data) });
+        }
+    }
+
+    Ok(())
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for DeltaTracker<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let (mem, pages) = (&mut self.mem, &self.pages);
+
+        let mut untracked = vec![];
+        let inp = inp.map(|data: PhysicalReadData| {
+            untracked_pages(
+                pages,
+                data.0.address(),
+                data.2.len() as umem,
+                &mut untracked,
+            );
+            data
+        });
+
+        MemOps::with_raw(inp, out, out_fail, |data| mem.phys_read_raw_iter(data))?;
+
+        // hashing the pages on their own keeps the read path free of partial page handling
+        self.track_pages(untracked)
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue::cglue_impl_group!(
+    DeltaTracker<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+
+    #[test]
+    fn tracks_read_pages() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut tracker = DeltaTracker::new(mem.clone());
+
+        let mut buf = [0u8; 0x10];
+        tracker
+            .phys_read_into(Address::from(0x1000).into(), &mut buf)
+            .unwrap();
+        // crosses a page boundary
+        tracker
+            .phys_read_into(Address::from(0x2ff8).into(), &mut buf)
+            .unwrap();
+        assert_eq!(tracker.tracked_pages(), 3);
+
+        mem.phys_write(Address::from(0x3004).into(), &[1u8; 4])
+            .unwrap();
+        // never read through the tracker
+        mem.phys_write(Address::from(0x5000).into(), &[1u8; 4])
+            .unwrap();
+        assert_eq!(
+            tracker.changed_pages().unwrap(),
+            vec![Address::from(0x3000)]
+        );
+
+        let delta = tracker.take_delta().unwrap();
+        assert_eq!(delta.pages.len(), 1);
+        assert_eq!(delta.pages[0].data.as_ref().unwrap()[4..8], [1; 4]);
+        assert!(tracker.take_delta().unwrap().is_empty());
+
+        let decoded = PageDelta::decode(&delta.encode()).unwrap();
+        assert_eq!(decoded, delta);
+
+        let mut image = DummyMemory::new(size::mb(1));
+        decoded.apply(&mut image).unwrap();
+        image
+            .phys_read_into(Address::from(0x3000).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf[4..8], [1; 4]);
+    }
+}
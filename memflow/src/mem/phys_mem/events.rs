@@ -0,0 +1,146 @@
+//! Hardware assisted change notifications.
+//!
+//! Detecting modifications by comparing snapshots requires rereading all of the watched memory.
+//! Some transports are able to track modifications on their own, for example through dirty
+//! logging of the second level page tables of a hypervisor, or by snooping the writes on the bus
+//! of an FPGA. Connectors backed by such a transport implement [`PhysicalMemoryEvents`] in
+//! addition to [`PhysicalMemory`](super::PhysicalMemory). The trait is not part of the
+//! `ConnectorInstance` plugin interface, it is only available on statically linked connectors.
+//!
+//! The notifications are consumed by the caches, which invalidate modified pages instead of
+//! waiting for them to expire (see [`CachedPhysicalMemory::invalidate_modified`](super::CachedPhysicalMemory::invalidate_modified)),
+//! and by [`Monitor::page_events`](crate::monitor::Monitor::page_events).
+
+use crate::cglue::*;
+use crate::error::Result;
+use crate::types::Address;
+
+use std::prelude::v1::*;
+
+pub type ModifiedPageCallback<'a> = OpaqueCallback<'a, Address>;
+
+/// Page modification notifications of a connector.
+///
+/// Modifications are reported in pages of [`event_page_size`](Self::event_page_size) bytes,
+/// after tracking was enabled with [`start_tracking`](Self::start_tracking). Each modified page
+/// is reported once per call to [`modified_pages`](Self::modified_pages), no matter how often
+/// it was written to in between.
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+#[cglue_forward]
+pub trait PhysicalMemoryEvents: Send {
+    /// Returns the granularity at which modifications are tracked.
+    fn event_page_size(&self) -> usize;
+
+    /// Starts tracking modifications, previously recorded modifications are discarded.
+    fn start_tracking(&mut self) -> Result<()>;
+
+    /// Stops tracking modifications.
+    fn stop_tracking(&mut self) -> Result<()>;
+
+    /// Reports the base address of every page modified since the last call.
+    fn modified_pages(&mut self, out: ModifiedPageCallback) -> Result<()>;
+
+    /// Returns the base addresses of all pages modified since the last call.
+    #[skip_func]
+    fn modified_pages_list(&mut self) -> Result<Vec<Address>>
+    where
+        Self: Sized,
+    {
+        let mut pages = vec![];
+        self.modified_pages((&mut pages).into())?;
+        Ok(pages)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::mem_data::*;
+    use crate::mem::{PhysicalMemory, PhysicalMemoryMetadata};
+    use crate::types::size;
+
+    /// Dummy memory that records the pages written through it.
+    #[derive(Clone)]
+    pub struct TrackedMemory {
+        pub mem: DummyMemory,
+        pub tracking: bool,
+        pub modified: Vec<Address>,
+    }
+
+    impl TrackedMemory {
+        pub fn new(size: usize) -> Self {
+            Self {
+                mem: DummyMemory::new(size),
+                tracking: false,
+                modified: vec![],
+            }
+        }
+
+        /// Marks a page as modified without going through the memory object, like a write
+        /// of the target would.
+        pub fn touch(&mut self, addr: Address) {
+            if self.tracking {
+                let page = addr.as_page_aligned(size::kb(4));
+                if !self.modified.contains(&page) {
+                    self.modified.push(page);
+                }
+            }
+        }
+    }
+
+    impl PhysicalMemory for TrackedMemory {
+        fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+            self.mem.phys_read_raw_iter(data)
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.mem.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            self.mem.metadata()
+        }
+    }
+
+    impl PhysicalMemoryEvents for TrackedMemory {
+        fn event_page_size(&self) -> usize {
+            size::kb(4)
+        }
+
+        fn start_tracking(&mut self) -> Result<()> {
+            self.tracking = true;
+            self.modified.clear();
+            Ok(())
+        }
+
+        fn stop_tracking(&mut self) -> Result<()> {
+            self.tracking = false;
+            Ok(())
+        }
+
+        fn modified_pages(&mut self, mut out: ModifiedPageCallback) -> Result<()> {
+            self.modified.drain(..).for_each(|page| {
+                out.call(page);
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn modified_pages_list() {
+        let mut mem = TrackedMemory::new(size::mb(1));
+        mem.touch(Address::from(0x1234));
+        mem.start_tracking().unwrap();
+        mem.touch(Address::from(0x2010));
+        mem.touch(Address::from(0x2ff0));
+        mem.touch(Address::from(0x5000));
+
+        assert_eq!(
+            mem.modified_pages_list().unwrap(),
+            vec![Address::from(0x2000), Address::from(0x5000)]
+        );
+        assert!(mem.modified_pages_list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+//! Zero-copy access to physical memory.
+//!
+//! Connectors backed by memory of the local process, like memory mapped files or shared memory
+//! regions, can hand out views of their memory instead of copying it into the buffers of every
+//! read. Those connectors implement [`MappablePhysicalMemory`] in addition to
+//! [`PhysicalMemory`].
+//!
+//! Views can be borrowed straight from the connector, from a
+//! [`CachedPhysicalMemory`](super::CachedPhysicalMemory) wrapping it, or for virtual addresses
+//! through [`VirtualDma::virt_read_mapped`](crate::mem::VirtualDma::virt_read_mapped).
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::{MappablePhysicalMemory, PhysicalMemory};
+//! use memflow::types::size;
+//!
+//! let mut mem = DummyMemory::new(size::mb(1));
+//! mem.phys_write(0x1000.into(), &[1u8, 2, 3, 4]).unwrap();
+//!
+//! let view = mem.phys_map_range(0x1000.into(), 4).unwrap();
+//! assert_eq!(view, [1, 2, 3, 4]);
+//! ```
+
+use super::PhysicalMemory;
+use crate::types::Address;
+
+/// Direct access to the memory backing a physical memory object.
+pub trait MappablePhysicalMemory: PhysicalMemory {
+    /// Returns the memory directly backing the physical address `addr`.
+    ///
+    /// The view starts at `addr` and extends up to the end of the contiguous region containing it.
+    /// `None` is returned if `addr` is not backed by directly accessible memory.
+    fn phys_map(&self, addr: Address) -> Option<&[u8]>;
+
+    /// Returns the `len` bytes at `addr`, if they are backed by a single contiguous region.
+    fn phys_map_range(&self, addr: Address, len: usize) -> Option<&[u8]> {
+        self.phys_map(addr).and_then(|data| data.get(..len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::{CachedPhysicalMemory, VirtualDma};
+    use crate::types::size;
+
+    #[test]
+    fn mapped_views() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(Address::from(0x1000).into(), &[1u8; 4])
+            .unwrap();
+
+        assert_eq!(mem.phys_map(Address::from(0xf_fff0)).unwrap().len(), 0x10);
+        assert!(mem.phys_map(Address::from(size::mb(1))).is_none());
+        assert!(mem.phys_map_range(Address::from(0xf_fff0), 0x11).is_none());
+
+        let mut cache = CachedPhysicalMemory::builder(mem)
+            .arch(x64::ARCH)
+            .write_back(size::kb(4))
+            .build()
+            .unwrap();
+        assert_eq!(
+            cache.phys_map_range(Address::from(0x1000), 4).unwrap(),
+            [1; 4]
+        );
+
+        // pending writes are not visible in the mapping yet
+        cache
+            .phys_write(Address::from(0x1000).into(), &[2u8; 4])
+            .unwrap();
+        assert!(cache.phys_map(Address::from(0x1000)).is_none());
+        cache.flush().unwrap();
+        assert_eq!(
+            cache.phys_map_range(Address::from(0x1000), 4).unwrap(),
+            [2; 4]
+        );
+    }
+
+    #[test]
+    fn virt_read_mapped() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[3u8; 0x2000]);
+        let mut virt_mem = VirtualDma::new(os.into_inner(), x64::ARCH, x64::new_translator(dtb));
+
+        let mut read = 0;
+        virt_mem
+            .virt_read_mapped(virt_base + 0x800, 0x1000, |addr, data| {
+                assert_eq!(addr, virt_base + 0x800 + read);
+                assert!(data.iter().all(|&b| b == 3));
+                read += data.len();
+            })
+            .unwrap();
+        assert_eq!(read, 0x1000);
+    }
+}
@@ -11,8 +11,26 @@ use std::prelude::v1::*;
 use crate::mem::memory_view::*;
 
 pub mod cache;
+pub mod delta;
+pub mod events;
+pub mod mappable;
+pub mod overlay;
+#[cfg(feature = "std")]
+pub mod pooled;
+#[cfg(feature = "std")]
+pub mod sharded;
+pub mod sorted;
 
 pub use cache::*;
+pub use delta::{DeltaTracker, PageDelta};
+pub use events::{ModifiedPageCallback, PhysicalMemoryEvents};
+pub use mappable::MappablePhysicalMemory;
+pub use overlay::OverlayMemory;
+#[cfg(feature = "std")]
+pub use pooled::PooledPhysicalMemory;
+#[cfg(feature = "std")]
+pub use sharded::ShardedPhysicalMemory;
+pub use sorted::SortedPhysicalMemory;
 
 // TODO:
 // - check endianess here and return an error
@@ -100,7 +118,9 @@ pub use cache::*;
 ///             max_address: (self.mem.len() - 1).into(),
 ///             real_size: self.mem.len() as umem,
 ///             readonly: false,
-///             ideal_batch_size: u32::MAX
+///             ideal_batch_size: u32::MAX,
+///             max_batch_size: u32::MAX,
+///             max_batch_bytes: umem::MAX,
 ///         }
 ///     }
 /// }
@@ -159,6 +179,17 @@ pub trait PhysicalMemory: Send {
     #[inline]
     fn set_mem_map(&mut self, _mem_map: &[PhysicalMemoryMapping]) {}
 
+    /// Hints the connector that the given ranges are likely going to be read soon.
+    ///
+    /// Connectors with high access latency (e.g. network based ones) can use this to start
+    /// fetching the data in the background, while the caller is still busy processing previous
+    /// results. The hint does not guarantee anything, the ranges still have to be read
+    /// normally afterwards.
+    ///
+    /// By default this is a no-op.
+    #[inline]
+    fn prefetch_hint(&mut self, _ranges: &[PhysicalRange]) {}
+
     #[skip_func]
     fn phys_read_into<T: Pod + ?Sized>(&mut self, addr: PhysicalAddress, out: &mut T) -> Result<()>
     where
@@ -217,21 +248,31 @@ pub struct PhysicalMemoryView<T> {
 
 impl<T: PhysicalMemory> MemoryView for PhysicalMemoryView<T> {
     fn read_raw_iter(&mut self, MemOps { inp, out, out_fail }: ReadRawMemOps) -> Result<()> {
-        let inp = &mut inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
-        let inp = inp.into();
-
-        let data = MemOps { inp, out, out_fail };
+        let inp = inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
+        let limits = self.mem.metadata().batch_limits();
 
-        self.mem.phys_read_raw_iter(data)
+        MemOps::with_raw_chunked(
+            inp,
+            out,
+            out_fail,
+            limits,
+            |CTup3(_, _, data)| data.len(),
+            |data| self.mem.phys_read_raw_iter(data),
+        )
     }
 
     fn write_raw_iter(&mut self, MemOps { inp, out, out_fail }: WriteRawMemOps) -> Result<()> {
-        let inp = &mut inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
-        let inp = inp.into();
-
-        let data = MemOps { inp, out, out_fail };
+        let inp = inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
+        let limits = self.mem.metadata().batch_limits();
 
-        self.mem.phys_write_raw_iter(data)
+        MemOps::with_raw_chunked(
+            inp,
+            out,
+            out_fail,
+            limits,
+            |CTup3(_, _, data)| data.len(),
+            |data| self.mem.phys_write_raw_iter(data),
+        )
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
@@ -267,4 +308,23 @@ pub struct PhysicalMemoryMetadata {
     pub real_size: umem,
     pub readonly: bool,
     pub ideal_batch_size: u32,
+    /// Maximum number of elements the connector accepts in a single batch.
+    ///
+    /// `u32::MAX` means there is no limit.
+    pub max_batch_size: u32,
+    /// Maximum number of bytes the connector accepts in a single batch.
+    ///
+    /// `umem::MAX` means there is no limit. Single elements are never split to honor this limit.
+    pub max_batch_bytes: umem,
+}
+
+impl PhysicalMemoryMetadata {
+    /// Returns the number of elements and bytes after which a batch should be split up.
+    ///
+    /// The element count is the smaller one of `ideal_batch_size` and `max_batch_size`,
+    /// but at least 1.
+    pub fn batch_limits(&self) -> (usize, umem) {
+        let elems = std::cmp::max(std::cmp::min(self.ideal_batch_size, self.max_batch_size), 1);
+        (elems as usize, std::cmp::max(self.max_batch_bytes, 1))
+    }
 }
@@ -0,0 +1,229 @@
+//! Copy-on-write overlay over a physical memory object.
+//!
+//! [`OverlayMemory`] records all writes in a local overlay instead of passing them on, and
+//! serves reads from the overlay where it has data. The underlying memory object is never
+//! written to, unless the overlay is explicitly [committed](OverlayMemory::commit). This allows
+//! patching experiments against live targets, and makes read-only snapshots writeable.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::{MemoryView, OverlayMemory, PhysicalMemory};
+//! use memflow::cglue::ForwardMut;
+//! use memflow::types::size;
+//!
+//! let mut mem = DummyMemory::new(size::mb(4));
+//! mem.phys_write(0x1000.into(), &1u32).unwrap();
+//!
+//! let mut overlay = OverlayMemory::new(mem.forward_mut());
+//! overlay.phys_write(0x1000.into(), &2u32).unwrap();
+//!
+//! let value: u32 = overlay.phys_view().read(0x1000.into()).unwrap();
+//! assert_eq!(value, 2);
+//!
+//! // the underlying memory is left untouched
+//! std::mem::drop(overlay);
+//! let value: u32 = mem.phys_view().read(0x1000.into()).unwrap();
+//! assert_eq!(value, 1);
+//! ```
+
+use crate::error::Result;
+use crate::mem::mem_data::*;
+use crate::mem::phys_mem::cache::PendingWrites;
+use crate::mem::{MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::types::{umem, Address};
+
+use cglue::tuple::*;
+
+use core::cell::RefCell;
+use std::prelude::v1::*;
+
+/// Physical memory wrapper keeping all writes in a local overlay.
+#[derive(Clone)]
+pub struct OverlayMemory<T> {
+    mem: T,
+    overlay: PendingWrites,
+}
+
+impl<T: PhysicalMemory> OverlayMemory<T> {
+    /// Wraps a physical memory object with an empty overlay.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            overlay: PendingWrites::default(),
+        }
+    }
+
+    /// Returns the amount of bytes held by the overlay.
+    pub fn overlay_bytes(&self) -> usize {
+        self.overlay.bytes()
+    }
+
+    /// Drops all writes recorded so far.
+    pub fn discard(&mut self) {
+        self.overlay.clear();
+    }
+
+    /// Writes the overlay to the underlying memory object and clears it.
+    ///
+    /// The overlay is cleared even if some of the writes fail.
+    pub fn commit(&mut self) -> Result<()> {
+        self.overlay.flush(&mut self.mem)
+    }
+
+    /// Consumes self and returns the containing memory object, discarding the overlay.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for OverlayMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let Self { mem, overlay } = self;
+
+        if overlay.is_empty() {
+            return MemOps::with_raw(inp, out, out_fail, |data| mem.phys_read_raw_iter(data));
+        }
+
+        // replace the meta addresses with offsets, so that partial results can be mapped back
+        // onto the physical address: (physical address, meta address, offset)
+        let mut elems = vec![];
+        let mut offset: umem = 0;
+        let inputs = inp
+            .map(|CTup3(addr, meta_addr, buf)| {
+                elems.push((addr.address(), meta_addr, offset));
+                let data = CTup3(addr, Address::from(offset), buf);
+                offset += data.2.len() as umem;
+                data
+            })
+            .collect::<Vec<_>>();
+
+        let locate = |offset: Address| {
+            let offset = offset.to_umem();
+            let idx = elems.partition_point(|&(_, _, start)| start <= offset) - 1;
+            let (addr, meta_addr, start) = elems[idx];
+            (addr + (offset - start), meta_addr + (offset - start))
+        };
+
+        let out = RefCell::new(out);
+        let out_fail = RefCell::new(out_fail);
+
+        let succeeded = &mut |CTup2(offset, mut buf): ReadData| {
+            let (addr, meta_addr) = locate(offset);
+            overlay.patch(addr, &mut buf);
+            opt_call(out.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf))
+        };
+        let failed = &mut |CTup2(offset, mut buf): ReadData| {
+            let (addr, meta_addr) = locate(offset);
+            // memory missing from the underlying object may have been written to
+            if overlay.patch(addr, &mut buf) == buf.len() {
+                opt_call(out.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf))
+            } else {
+                opt_call(out_fail.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf))
+            }
+        };
+
+        MemOps::with_raw(
+            inputs.into_iter(),
+            Some(&mut succeeded.into()),
+            Some(&mut failed.into()),
+            |data| mem.phys_read_raw_iter(data),
+        )
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, mut out, .. }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, data) in inp {
+            self.overlay.insert(addr.address(), data.into());
+            if !opt_call(out.as_deref_mut(), CTup2(meta_addr, data)) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            readonly: false,
+            ..self.mem.metadata()
+        }
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue::cglue_impl_group!(
+    OverlayMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cglue::ForwardMut;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn overlay_reads_and_commit() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(Address::from(0x1000).into(), &[1u8; 8])
+            .unwrap();
+
+        let mut overlay = OverlayMemory::new(mem.forward_mut());
+        overlay
+            .phys_write(Address::from(0x1002).into(), &[2u8; 2])
+            .unwrap();
+        // outside of the underlying memory
+        overlay
+            .phys_write(Address::from(size::mb(2)).into(), &[3u8; 4])
+            .unwrap();
+
+        let mut buf = [0u8; 8];
+        overlay
+            .phys_read_into(Address::from(0x1000).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, [1, 1, 2, 2, 1, 1, 1, 1]);
+
+        let mut data = [0u8; 4];
+        let mut unmapped = [0u8; 4];
+        overlay
+            .phys_view()
+            .read_raw_list(&mut [
+                CTup2(Address::from(size::mb(2)), data.as_mut().into()),
+                CTup2(Address::from(size::mb(2) + 2), unmapped.as_mut().into()),
+            ])
+            .unwrap_err();
+        assert_eq!(data, [3; 4]);
+
+        overlay.discard();
+        overlay
+            .phys_write(Address::from(0x1000).into(), &[4u8; 1])
+            .unwrap();
+        overlay.commit().unwrap();
+        assert_eq!(overlay.overlay_bytes(), 0);
+
+        std::mem::drop(overlay);
+        mem.phys_read_into(Address::from(0x1000).into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, [4, 1, 1, 1, 1, 1, 1, 1]);
+    }
+}
@@ -0,0 +1,385 @@
+//! Parallel reads over a pool of connector instances.
+//!
+//! Many connectors are able to service several requests at once: PCIe DMA devices have multiple
+//! in-flight queues, network connectors hide their latency best with multiple outstanding
+//! requests. [`PooledPhysicalMemory`] owns a number of cloned connector instances, each of them
+//! driven by its own worker thread, and splits large read batches over all of them.
+//!
+//! Writes are always issued on the calling thread in their original order.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::{MemoryView, PhysicalMemory, PooledPhysicalMemory};
+//! use memflow::types::size;
+//!
+//! let mut mem = PooledPhysicalMemory::new(DummyMemory::new(size::mb(4)), 4);
+//!
+//! mem.phys_write(0x1000.into(), &0xdeadbeefu32).unwrap();
+//!
+//! let value: u32 = mem.phys_view().read(0x1000.into()).unwrap();
+//! assert_eq!(value, 0xdeadbeef);
+//! ```
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::mem_data::*;
+use crate::mem::{MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::types::{umem, Address, PhysicalAddress};
+
+use cglue::slice::CSliceMut;
+use cglue::tuple::*;
+
+use std::prelude::v1::*;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Default minimum number of elements in a batch before it is split over the pool.
+pub const DEFAULT_MIN_BATCH: usize = 64;
+
+/// Physical memory wrapper that splits read batches over multiple connector instances.
+///
+/// The read batch is split into contiguous parts, one per instance. The first part is read on
+/// the calling thread, all others on the worker threads. Batches with less than
+/// [`min_batch`](Self::min_batch) elements are read on the calling thread only.
+///
+/// Memory maps set on this object are forwarded to all instances.
+pub struct PooledPhysicalMemory<T> {
+    mem: T,
+    pool: Pool,
+    min_batch: usize,
+}
+
+impl<T: PhysicalMemory + Clone + Send + 'static> PooledPhysicalMemory<T> {
+    /// Wraps a physical memory object and spreads reads over `threads` instances of it.
+    ///
+    /// `mem` is read from the calling thread, `threads - 1` clones of it are moved onto worker
+    /// threads.
+    pub fn new(mem: T, threads: usize) -> Self {
+        let workers = (1..threads).map(|_| Worker::spawn(mem.clone())).collect();
+
+        Self {
+            mem,
+            pool: Pool { workers },
+            min_batch: DEFAULT_MIN_BATCH,
+        }
+    }
+}
+
+impl<T> PooledPhysicalMemory<T> {
+    /// Sets the minimum number of elements in a batch before it is split over the pool.
+    ///
+    /// Splitting small batches costs more in synchronization than is gained by reading them in
+    /// parallel.
+    pub fn min_batch(mut self, min_batch: usize) -> Self {
+        self.min_batch = min_batch;
+        self
+    }
+
+    /// Returns the number of connector instances reads are split over.
+    pub fn threads(&self) -> usize {
+        self.pool.workers.len() + 1
+    }
+
+    /// Consumes self, stops the worker threads and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+impl<T: PhysicalMemory + Clone + Send + 'static> Clone for PooledPhysicalMemory<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.mem.clone(), self.threads()).min_batch(self.min_batch)
+    }
+}
+
+/// A single element of a read batch, sent to another thread.
+///
+/// The buffer is borrowed from the batch, which outlives the read since the calling thread
+/// waits for all parts to complete.
+struct RawRead {
+    addr: PhysicalAddress,
+    offset: umem,
+    ptr: *mut u8,
+    len: usize,
+}
+
+struct Job(Vec<RawRead>);
+
+unsafe impl Send for Job {}
+
+/// Reported (offset, length, success) triples of a read part.
+type Pieces = Vec<(umem, usize, bool)>;
+
+enum Request {
+    Read(Job),
+    SetMemMap(Vec<PhysicalMemoryMapping>),
+}
+
+struct Worker {
+    requests: Sender<Request>,
+    results: Receiver<Result<Pieces>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn spawn<T: PhysicalMemory + Send + 'static>(mut mem: T) -> Self {
+        let (requests, rx) = channel();
+        let (tx, results) = channel();
+
+        let handle = thread::spawn(move || {
+            for request in rx {
+                match request {
+                    Request::Read(job) => {
+                        if tx.send(read_part(&mut mem, &job.0)).is_err() {
+                            break;
+                        }
+                    }
+                    Request::SetMemMap(mem_map) => mem.set_mem_map(&mem_map),
+                }
+            }
+        });
+
+        Self {
+            requests,
+            results,
+            handle: Some(handle),
+        }
+    }
+}
+
+struct Pool {
+    workers: Vec<Worker>,
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // closing the request channels stops the workers
+        let handles = self
+            .workers
+            .drain(..)
+            .filter_map(|mut worker| worker.handle.take())
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().ok();
+        }
+    }
+}
+
+/// Workers that are currently reading a part of the batch.
+///
+/// The buffers of the batch have to outlive the reads, so the parts are waited for even if the
+/// calling thread unwinds.
+struct InFlight<'a>(Vec<&'a Worker>);
+
+impl InFlight<'_> {
+    fn wait(&mut self) -> Vec<Result<Pieces>> {
+        self.0
+            .drain(..)
+            .map(|worker| match worker.results.recv() {
+                Ok(result) => result,
+                Err(_) => Err(worker_failed()),
+            })
+            .collect()
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.wait();
+    }
+}
+
+fn worker_failed() -> Error {
+    Error(ErrorOrigin::PhysicalMemory, ErrorKind::Unknown)
+        .log_error("connector pool worker thread exited")
+}
+
+fn read_part<T: PhysicalMemory>(mem: &mut T, part: &[RawRead]) -> Result<Pieces> {
+    let inp = part.iter().map(|read| {
+        let buf = unsafe { std::slice::from_raw_parts_mut(read.ptr, read.len) };
+        CTup3(read.addr, Address::from(read.offset), CSliceMut::from(buf))
+    });
+
+    let mut succeeded = vec![];
+    let mut failed = vec![];
+
+    let succeeded_cb = &mut |CTup2(offset, buf): ReadData| {
+        succeeded.push((offset.to_umem(), buf.len(), true));
+        true
+    };
+    let failed_cb = &mut |CTup2(offset, buf): ReadData| {
+        failed.push((offset.to_umem(), buf.len(), false));
+        true
+    };
+
+    MemOps::with_raw(
+        inp,
+        Some(&mut succeeded_cb.into()),
+        Some(&mut failed_cb.into()),
+        |data| mem.phys_read_raw_iter(data),
+    )?;
+
+    succeeded.append(&mut failed);
+    Ok(succeeded)
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for PooledPhysicalMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let Self {
+            mem,
+            pool,
+            min_batch,
+        } = self;
+
+        let mut elems = inp.collect::<Vec<_>>();
+
+        if pool.workers.is_empty() || elems.len() < std::cmp::max(*min_batch, 2) {
+            return MemOps::with_raw(elems.into_iter(), out, out_fail, |data| {
+                mem.phys_read_raw_iter(data)
+            });
+        }
+
+        // the meta addresses are replaced with offsets into the whole batch, so that the reported
+        // pieces can be mapped back: (meta address, buffer, offset)
+        let mut locations = Vec::with_capacity(elems.len());
+        let mut reads = Vec::with_capacity(elems.len());
+        let mut offset: umem = 0;
+
+        for CTup3(addr, meta_addr, buf) in elems.iter_mut() {
+            locations.push((*meta_addr, buf.as_mut_ptr(), offset));
+            reads.push(RawRead {
+                addr: *addr,
+                offset,
+                ptr: buf.as_mut_ptr(),
+                len: buf.len(),
+            });
+            offset += buf.len() as umem;
+        }
+
+        let threads = pool.workers.len() + 1;
+        let part_size = (reads.len() + threads - 1) / threads;
+
+        let mut parts = vec![];
+        while reads.len() > part_size {
+            let tail = reads.split_off(part_size);
+            parts.push(std::mem::replace(&mut reads, tail));
+        }
+        parts.push(reads);
+
+        let mut parts = parts.into_iter();
+        let first = parts.next().unwrap_or_default();
+
+        let mut results = vec![];
+        let mut in_flight = InFlight(vec![]);
+        for (worker, part) in pool.workers.iter().zip(parts) {
+            match worker.requests.send(Request::Read(Job(part))) {
+                Ok(_) => in_flight.0.push(worker),
+                Err(_) => results.push(Err(worker_failed())),
+            }
+        }
+
+        results.push(read_part(mem, &first));
+        results.append(&mut in_flight.wait());
+
+        let mut pieces = vec![];
+        for result in results {
+            pieces.append(&mut result?);
+        }
+
+        for (offset, len, ok) in pieces {
+            let idx = locations.partition_point(|&(_, _, start)| start <= offset) - 1;
+            let (meta_addr, ptr, start) = locations[idx];
+            let off = (offset - start) as usize;
+            let buf = unsafe { std::slice::from_raw_parts_mut(ptr.add(off), len) };
+
+            let cb = if ok {
+                out.as_deref_mut()
+            } else {
+                out_fail.as_deref_mut()
+            };
+            opt_call(cb, CTup2(meta_addr + off, buf.into()));
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map);
+        for worker in self.pool.workers.iter() {
+            worker
+                .requests
+                .send(Request::SetMemMap(mem_map.to_vec()))
+                .ok();
+        }
+    }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue::cglue_impl_group!(
+    PooledPhysicalMemory<T: PhysicalMemory + Clone + Send + 'static>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn pooled_reads() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let buf = (0..size::mb(1))
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        mem.phys_write(Address::NULL.into(), buf.as_slice())
+            .unwrap();
+
+        let mut mem = PooledPhysicalMemory::new(mem, 4).min_batch(0);
+        assert_eq!(mem.threads(), 4);
+
+        let mut bufs = vec![[0u8; 8]; 257];
+        let mut data = bufs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, b)| CTup2(Address::from(i as umem * 0x1000), b[..].into()))
+            .collect::<Vec<_>>();
+        // the last element is out of bounds
+        let res = mem.phys_view().read_raw_list(&mut data);
+        assert!(res.is_err());
+
+        for (i, b) in bufs.iter().enumerate().take(256) {
+            let base = i * 0x1000;
+            let expected = (base..base + 8)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<_>>();
+            assert_eq!(&b[..], expected.as_slice());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Concurrent access to physical memory from multiple threads.
+//!
+//! [`PhysicalMemory`] requires `&mut self` for every access, so threads working on the same
+//! target, e.g. scanners walking different processes, are serialized around a single
+//! connector, or have to lock it for the whole duration of their work.
+//!
+//! [`ShardedPhysicalMemory`] holds a number of connector instances, each behind its own lock.
+//! Every access is handed to a currently unused instance, so up to as many threads as there are
+//! instances access the target at the same time. Accesses are possible through `&self` by
+//! [`with_shard`](ShardedPhysicalMemory::with_shard), and through cheap clones of the object,
+//! which implement [`PhysicalMemory`] and share the same instances.
+//!
+//! The connector instances have to access the same target. Connectors that can not be cloned
+//! can be opened multiple times and passed to [`ShardedPhysicalMemory::from_shards`].
+//!
+//! # Examples
+//!
+//! ```
+//! use std::thread;
+//!
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::{PhysicalMemory, ShardedPhysicalMemory};
+//! use memflow::types::size;
+//!
+//! let mem = ShardedPhysicalMemory::new(DummyMemory::new(size::mb(4)), 4);
+//!
+//! let handles = (0..8u64)
+//!     .map(|i| {
+//!         let mut mem = mem.clone();
+//!         thread::spawn(move || {
+//!             let mut buf = [0u8; 8];
+//!             mem.phys_read_into((i * 0x1000).into(), &mut buf).unwrap();
+//!             buf
+//!         })
+//!     })
+//!     .collect::<Vec<_>>();
+//!
+//! for handle in handles {
+//!     handle.join().unwrap();
+//! }
+//! ```
+
+use crate::error::Result;
+use crate::mem::{
+    PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalRange,
+    PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+
+use std::prelude::v1::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+struct Shards<T> {
+    shards: Box<[Mutex<T>]>,
+    next: AtomicUsize,
+}
+
+/// Physical memory wrapper spreading concurrent accesses over multiple connector instances.
+///
+/// Cloning this object keeps referencing the same instances.
+pub struct ShardedPhysicalMemory<T> {
+    inner: Arc<Shards<T>>,
+}
+
+impl<T: PhysicalMemory + Clone> ShardedPhysicalMemory<T> {
+    /// Wraps a physical memory object and spreads accesses over `shards` instances of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn new(mem: T, shards: usize) -> Self {
+        assert!(shards > 0);
+
+        let mut instances = (1..shards).map(|_| mem.clone()).collect::<Vec<_>>();
+        instances.push(mem);
+        Self::from_shards(instances)
+    }
+}
+
+impl<T: PhysicalMemory> ShardedPhysicalMemory<T> {
+    /// Spreads accesses over the given instances, all of them accessing the same target.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is empty.
+    pub fn from_shards(shards: Vec<T>) -> Self {
+        assert!(!shards.is_empty());
+
+        Self {
+            inner: Arc::new(Shards {
+                shards: shards.into_iter().map(Mutex::new).collect(),
+                next: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the number of connector instances.
+    pub fn shard_count(&self) -> usize {
+        self.inner.shards.len()
+    }
+
+    /// Calls `f` with an instance that is not used by any other thread.
+    ///
+    /// Instances are handed out in turns, if all of them are in use this blocks until the next
+    /// one in turn is released.
+    pub fn with_shard<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.lock_shard())
+    }
+
+    fn lock_shard(&self) -> MutexGuard<T> {
+        let shards = &self.inner.shards;
+        let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+
+        (0..shards.len())
+            .map(|i| &shards[(start + i) % shards.len()])
+            .find_map(|shard| shard.try_lock().ok())
+            .unwrap_or_else(|| Self::lock(&shards[start % shards.len()]))
+    }
+
+    fn lock(shard: &Mutex<T>) -> MutexGuard<T> {
+        // connectors stay usable even if a user panicked while holding the lock
+        shard.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<T> Clone for ShardedPhysicalMemory<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for ShardedPhysicalMemory<T> {
+    #[inline]
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.with_shard(|mem| mem.phys_read_raw_iter(data))
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.with_shard(|mem| mem.phys_write_raw_iter(data))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.with_shard(|mem| mem.metadata())
+    }
+
+    /// Sets the memory map of all instances.
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        for shard in self.inner.shards.iter() {
+            Self::lock(shard).set_mem_map(mem_map);
+        }
+    }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.with_shard(|mem| mem.prefetch_hint(ranges))
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue::cglue_impl_group!(
+    ShardedPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::{size, Address};
+
+    use std::sync::Barrier;
+    use std::thread;
+
+    #[test]
+    fn concurrent_reads() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let data = (0..size::mb(1))
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        mem.phys_write(Address::NULL.into(), data.as_slice())
+            .unwrap();
+
+        let mem = ShardedPhysicalMemory::new(mem, 4);
+        assert_eq!(mem.shard_count(), 4);
+
+        // all threads hold an instance at the same time
+        let barrier = Arc::new(Barrier::new(4));
+        let handles = (0..4)
+            .map(|t| {
+                let mem = mem.clone();
+                let barrier = barrier.clone();
+                let data = data.clone();
+                thread::spawn(move || {
+                    mem.with_shard(|shard| {
+                        barrier.wait();
+                        let addr = t * 0x4000 + 3;
+                        let mut buf = [0u8; 0x2000];
+                        shard
+                            .phys_read_into(Address::from(addr as u64).into(), &mut buf[..])
+                            .unwrap();
+                        assert_eq!(&buf[..], &data[addr..addr + buf.len()]);
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut mem = mem;
+        let mut buf = [0u8; 4];
+        mem.phys_read_into(Address::from(0x1000u64).into(), &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], &data[0x1000..0x1004]);
+    }
+}
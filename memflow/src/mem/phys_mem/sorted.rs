@@ -0,0 +1,509 @@
+//! Sorting and merging of physical scatter reads.
+//!
+//! Many connectors perform best when they are accessed sequentially. FPGA based devices are able to
+//! stream larger contiguous reads much faster than many small ones, file backed connectors benefit
+//! from locality, and so on. [`SortedPhysicalMemory`] is a thin wrapper that sorts every read batch
+//! by physical address and merges the elements that are adjacent (or close to each other) into a
+//! single larger read before passing it on to the connector.
+//!
+//! Workloads that issue many tiny reads, like reading hundreds of 4 byte fields scattered over a
+//! few pages, can additionally merge all elements that fall into the same page with
+//! [`merge_pages`](SortedPhysicalMemory::merge_pages). The connector then only sees one request
+//! per touched page, the results are sliced back out into the original buffers.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::{PhysicalMemory, SortedPhysicalMemory, MemoryView};
+//! use memflow::types::size;
+//!
+//! let mut mem = SortedPhysicalMemory::new(DummyMemory::new(size::mb(4)))
+//!     .max_gap(0x10);
+//!
+//! mem.phys_write(0x1000.into(), &0xdeadbeefu32).unwrap();
+//!
+//! let value: u32 = mem.phys_view().read(0x1000.into()).unwrap();
+//! assert_eq!(value, 0xdeadbeef);
+//! ```
+
+use crate::error::Result;
+use crate::iter::SplitAtIndex;
+use crate::mem::mem_data::*;
+use crate::mem::{MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::types::{size, umem, Address, PhysicalAddress};
+
+use cglue::slice::CSliceMut;
+use cglue::tuple::*;
+
+use std::ops::Range;
+use std::prelude::v1::*;
+
+/// Default upper bound for the size of a merged read.
+pub const DEFAULT_MAX_RUN: usize = size::kb(64);
+
+/// Physical memory wrapper that sorts read batches by address and merges adjacent elements.
+///
+/// Only reads are reordered. Writes are passed through unchanged, since reordering overlapping
+/// writes would change their result.
+///
+/// Merged elements are read into an internal scratch buffer and copied out afterwards. The scratch
+/// buffer is kept around between requests.
+#[derive(Clone)]
+pub struct SortedPhysicalMemory<T> {
+    mem: T,
+    max_gap: umem,
+    max_run: usize,
+    page_size: Option<umem>,
+    scratch: Vec<u8>,
+}
+
+struct Run {
+    addr: PhysicalAddress,
+    len: usize,
+    elems: Range<usize>,
+    meta: umem,
+    scratch: usize,
+}
+
+impl<T: PhysicalMemory> SortedPhysicalMemory<T> {
+    /// Wraps a physical memory object.
+    ///
+    /// By default only elements that are directly adjacent (or overlapping) are merged,
+    /// and merged reads are limited to [`DEFAULT_MAX_RUN`] bytes.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            max_gap: 0,
+            max_run: DEFAULT_MAX_RUN,
+            page_size: None,
+            scratch: vec![],
+        }
+    }
+
+    /// Sets the maximum amount of bytes between two elements that will still be merged.
+    ///
+    /// The bytes in between are read as well and then discarded. For connectors with
+    /// a high per-request overhead this can be a net win.
+    pub fn max_gap(mut self, max_gap: umem) -> Self {
+        self.max_gap = max_gap;
+        self
+    }
+
+    /// Sets the maximum size of a merged read.
+    ///
+    /// Single elements larger than this are still passed through as they are.
+    pub fn max_run(mut self, max_run: usize) -> Self {
+        self.max_run = max_run;
+        self
+    }
+
+    /// Merges all elements that start within the same `page_size` sized page as the end of the
+    /// previous element, no matter how large the gap between them is.
+    ///
+    /// Merged reads are still limited by [`max_run`](Self::max_run).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` is not a non-zero power of two.
+    pub fn merge_pages(mut self, page_size: usize) -> Self {
+        assert!(
+            page_size.is_power_of_two(),
+            "page_size must be a non-zero power of two"
+        );
+        self.page_size = Some(page_size as umem);
+        self
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+fn build_runs(
+    elems: &[PhysicalReadData],
+    max_gap: umem,
+    max_run: usize,
+    page_size: Option<umem>,
+) -> Vec<Run> {
+    let mut runs: Vec<Run> = vec![];
+    let mut meta = 0;
+
+    for (i, CTup3(addr, _, buf)) in elems.iter().enumerate() {
+        let start = addr.address();
+        let end = start + buf.len();
+
+        if let Some(run) = runs.last_mut() {
+            let run_start = run.addr.address();
+            let run_end = run_start + run.len;
+
+            let close = start.to_umem() <= run_end.to_umem().saturating_add(max_gap)
+                || page_size.map_or(false, |page_size| {
+                    start.to_umem() / page_size == run_end.to_umem().saturating_sub(1) / page_size
+                });
+
+            if run.addr.page_type() == addr.page_type()
+                && run.addr.page_size() == addr.page_size()
+                && close
+                && (end.to_umem() - run_start.to_umem()) as usize <= max_run
+            {
+                if end > run_end {
+                    let new_len = (end.to_umem() - run_start.to_umem()) as usize;
+                    meta += (new_len - run.len) as umem;
+                    run.len = new_len;
+                }
+                run.elems.end = i + 1;
+                continue;
+            }
+        }
+
+        runs.push(Run {
+            addr: *addr,
+            len: buf.len(),
+            elems: i..i + 1,
+            meta,
+            scratch: 0,
+        });
+        meta += buf.len() as umem;
+    }
+
+    // only merged runs are read through the scratch buffer
+    let mut scratch = 0;
+    for run in runs.iter_mut().filter(|r| r.elems.len() > 1) {
+        run.scratch = scratch;
+        scratch += run.len;
+    }
+
+    runs
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for SortedPhysicalMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let Self {
+            mem,
+            max_gap,
+            max_run,
+            page_size,
+            scratch,
+        } = self;
+
+        let mut elems = inp.collect::<Vec<_>>();
+        elems.sort_by_key(|CTup3(addr, _, _)| addr.address());
+
+        let runs = build_runs(&elems, *max_gap, *max_run, *page_size);
+
+        let scratch_len = runs
+            .iter()
+            .filter(|r| r.elems.len() > 1)
+            .map(|r| r.len)
+            .sum();
+        scratch.clear();
+        scratch.resize(scratch_len, 0);
+
+        // (meta offset, length) pairs reported by the connector
+        let mut succeeded = vec![];
+        let mut failed = vec![];
+
+        {
+            let mut inputs = Vec::with_capacity(runs.len());
+            let mut rest_elems = &mut elems[..];
+            let mut rest_scratch = &mut scratch[..];
+
+            for run in runs.iter() {
+                let (cur, tail) = rest_elems.split_at_mut(run.elems.len());
+                rest_elems = tail;
+
+                let buf: CSliceMut<u8> = if cur.len() == 1 {
+                    (&mut cur[0].2).into()
+                } else {
+                    let (buf, tail) = std::mem::take(&mut rest_scratch).split_at_mut(run.len);
+                    rest_scratch = tail;
+                    buf.into()
+                };
+
+                inputs.push(CTup3(run.addr, Address::from(run.meta), buf));
+            }
+
+            let succeeded_cb = &mut |CTup2(meta, buf): ReadData| {
+                succeeded.push((meta.to_umem(), buf.len()));
+                true
+            };
+            let failed_cb = &mut |CTup2(meta, buf): ReadData| {
+                failed.push((meta.to_umem(), buf.len()));
+                true
+            };
+
+            MemOps::with_raw(
+                inputs.into_iter(),
+                Some(&mut succeeded_cb.into()),
+                Some(&mut failed_cb.into()),
+                |data| mem.phys_read_raw_iter(data),
+            )?;
+        }
+
+        // map the reported ranges back onto the original elements:
+        // (element index, offset in element, length, success)
+        let mut pieces = vec![];
+
+        let reported = succeeded
+            .into_iter()
+            .map(|(meta, len)| (meta, len, true))
+            .chain(failed.into_iter().map(|(meta, len)| (meta, len, false)));
+
+        for (meta, len, ok) in reported {
+            let idx = runs.partition_point(|r| r.meta <= meta);
+            if idx == 0 {
+                continue;
+            }
+            let run = &runs[idx - 1];
+
+            let start = run.addr.address() + (meta - run.meta);
+            let end = start + len;
+
+            for i in run.elems.clone() {
+                let elem_start = elems[i].0.address();
+                let elem_end = elem_start + elems[i].2.len();
+
+                let s = std::cmp::max(start, elem_start);
+                let e = std::cmp::min(end, elem_end);
+
+                if s >= e {
+                    continue;
+                }
+
+                let off = (s.to_umem() - elem_start.to_umem()) as usize;
+                let n = (e.to_umem() - s.to_umem()) as usize;
+
+                if ok && run.elems.len() > 1 {
+                    let scratch_off =
+                        run.scratch + (s.to_umem() - run.addr.address().to_umem()) as usize;
+                    elems[i].2[off..off + n]
+                        .copy_from_slice(&scratch[scratch_off..scratch_off + n]);
+                }
+
+                pieces.push((i, off, n, ok));
+            }
+        }
+
+        pieces.sort_unstable_by_key(|&(i, off, _, _)| (i, off));
+        let mut pieces = pieces.into_iter().peekable();
+
+        for (i, CTup3(_, meta, buf)) in elems.into_iter().enumerate() {
+            let mut buf = Some(buf);
+            let mut pos = 0;
+
+            while let Some(&(pi, off, n, ok)) = pieces.peek() {
+                if pi > i {
+                    break;
+                }
+                pieces.next();
+
+                // skip pieces of earlier elements and already reported bytes
+                if pi < i || off + n <= pos {
+                    continue;
+                }
+
+                let off_start = std::cmp::max(off, pos);
+                let rest = match buf
+                    .take()
+                    .map(|b| SplitAtIndex::split_at(b, (off_start - pos) as umem))
+                {
+                    Some((_, Some(rest))) => rest,
+                    _ => continue,
+                };
+                let (piece, tail) = SplitAtIndex::split_at(rest, (off + n - off_start) as umem);
+                buf = tail;
+                pos = off + n;
+
+                if let Some(piece) = piece {
+                    let cb = if ok {
+                        out.as_deref_mut()
+                    } else {
+                        out_fail.as_deref_mut()
+                    };
+                    opt_call(cb, CTup2(meta + off_start, piece));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+
+    #[inline]
+    fn prefetch_hint(&mut self, ranges: &[PhysicalRange]) {
+        self.mem.prefetch_hint(ranges)
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue::cglue_impl_group!(
+    SortedPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+
+    struct CountingMemory {
+        mem: DummyMemory,
+        elems: usize,
+    }
+
+    impl PhysicalMemory for CountingMemory {
+        fn phys_read_raw_iter(
+            &mut self,
+            MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+        ) -> Result<()> {
+            let data = inp.collect::<Vec<_>>();
+            self.elems += data.len();
+            let mem = &mut self.mem;
+            MemOps::with_raw(data.into_iter(), out, out_fail, |data| {
+                mem.phys_read_raw_iter(data)
+            })
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.mem.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            self.mem.metadata()
+        }
+    }
+
+    fn counting(size: usize) -> CountingMemory {
+        let mut mem = DummyMemory::new(size);
+        let buf = (0..size).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        mem.phys_write(Address::NULL.into(), buf.as_slice())
+            .unwrap();
+        CountingMemory { mem, elems: 0 }
+    }
+
+    #[test]
+    fn merge_adjacent() {
+        let mut mem = SortedPhysicalMemory::new(counting(size::mb(1)));
+
+        let mut bufs = vec![[0u8; 8]; 16];
+        // reverse order, every element directly follows the next one
+        let mut data = bufs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, b)| CTup2(Address::from(0x1000 + (15 - i as u64) * 8), b[..].into()))
+            .collect::<Vec<_>>();
+        mem.phys_view().read_raw_list(&mut data).unwrap();
+
+        for (i, b) in bufs.iter().enumerate() {
+            let base = 0x1000 + (15 - i) * 8;
+            let expected = (base..base + 8)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<_>>();
+            assert_eq!(&b[..], expected.as_slice());
+        }
+
+        assert_eq!(mem.into_inner().elems, 1);
+    }
+
+    #[test]
+    fn merge_gaps_and_overlaps() {
+        let mut mem = SortedPhysicalMemory::new(counting(size::mb(1))).max_gap(0x20);
+
+        let mut a = [0u8; 16];
+        let mut b = [0u8; 16];
+        let mut c = [0u8; 4];
+        let mut d = [0u8; 4];
+
+        let mut data = vec![
+            CTup2(Address::from(0x2010u64), (&mut a[..]).into()),
+            CTup2(Address::from(0x2000u64), (&mut b[..]).into()),
+            CTup2(Address::from(0x2030u64), (&mut c[..]).into()),
+            CTup2(Address::from(0x8000u64), (&mut d[..]).into()),
+        ];
+        mem.phys_view().read_raw_list(&mut data).unwrap();
+
+        let expected = |base: usize, len| {
+            (base..base + len)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(&a[..], expected(0x2010, 16).as_slice());
+        assert_eq!(&b[..], expected(0x2000, 16).as_slice());
+        assert_eq!(&c[..], expected(0x2030, 4).as_slice());
+        assert_eq!(&d[..], expected(0x8000, 4).as_slice());
+
+        assert_eq!(mem.into_inner().elems, 2);
+    }
+
+    #[test]
+    fn merge_pages() {
+        let mut mem = SortedPhysicalMemory::new(counting(size::mb(1))).merge_pages(size::kb(4));
+
+        let addrs = [0x3ff0u64, 0x3000, 0x3800, 0x4100, 0x4f00];
+        let mut bufs = vec![[0u8; 4]; addrs.len()];
+        let mut data = addrs
+            .iter()
+            .zip(bufs.iter_mut())
+            .map(|(&addr, b)| CTup2(Address::from(addr), b[..].into()))
+            .collect::<Vec<_>>();
+        mem.phys_view().read_raw_list(&mut data).unwrap();
+
+        for (&addr, b) in addrs.iter().zip(bufs.iter()) {
+            let expected = (addr..addr + 4)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<_>>();
+            assert_eq!(&b[..], expected.as_slice());
+        }
+
+        // one request per touched page
+        assert_eq!(mem.into_inner().elems, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_pages_zero() {
+        SortedPhysicalMemory::new(counting(size::kb(8))).merge_pages(0);
+    }
+
+    #[test]
+    fn merge_failed_tail() {
+        let mut mem = SortedPhysicalMemory::new(counting(size::kb(8)));
+
+        let mut a = [0xffu8; 8];
+        let mut b = [0xffu8; 8];
+        let mut data = vec![
+            CTup2(Address::from(0x1ff8u64), (&mut a[..]).into()),
+            CTup2(Address::from(0x2000u64), (&mut b[..]).into()),
+        ];
+        let res = mem.phys_view().read_raw_list(&mut data);
+
+        assert!(res.is_err());
+        assert_eq!(a[0], (0x1ff8 % 251) as u8);
+        assert_eq!(b, [0; 8]);
+    }
+}
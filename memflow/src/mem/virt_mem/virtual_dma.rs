@@ -2,32 +2,38 @@ use std::prelude::v1::*;
 
 use crate::architecture::{ArchitectureObj, Endianess};
 use crate::error::{Error, Result, *};
+use crate::iter::PageChunks;
 use crate::mem::memory_view::*;
 use crate::mem::{
     mem_data::*,
     virt_translate::{
-        DirectTranslate, VirtualTranslate, VirtualTranslate2, VirtualTranslate3,
-        VirtualTranslation, VirtualTranslationCallback, VirtualTranslationFail,
-        VirtualTranslationFailCallback,
+        DirectTranslate, TranslationFailure, TranslationFailureCallback, VirtualTranslate,
+        VirtualTranslate2, VirtualTranslate3, VirtualTranslation, VirtualTranslationCallback,
+        VirtualTranslationFail, VirtualTranslationFailCallback,
     },
-    MemoryView, PhysicalMemory, PhysicalMemoryMetadata,
+    MappablePhysicalMemory, MemoryView, PhysicalMemory, PhysicalMemoryMetadata,
 };
 use crate::types::{umem, Address, PhysicalAddress};
 use cglue::tuple::*;
 
 use bumpalo::{collections::Vec as BumpVec, Bump};
 use cglue::callback::FromExtend;
+use fixed_slice_vec::FixedSliceVec;
+use std::mem::MaybeUninit;
 
 /// The VirtualDma struct provides a default implementation to access virtual memory
 /// from user provided [`PhysicalMemory`] and [`VirtualTranslate2`] objects.
 ///
 /// This struct implements [`MemoryView`] and allows the user to access the virtual memory of a process.
-pub struct VirtualDma<T, V, D> {
+///
+/// Translated addresses are collected in a growable arena by default. A `VirtualDma` object constructed
+/// with [`VirtualDma::with_scratch`] keeps them in a caller provided buffer instead.
+pub struct VirtualDma<T, V, D, S = Bump> {
     phys_mem: T,
     vat: V,
     proc_arch: ArchitectureObj,
     translator: D,
-    arena: Bump,
+    arena: S,
 }
 
 impl<T: PhysicalMemory, D: VirtualTranslate3> VirtualDma<T, DirectTranslate, D> {
@@ -119,7 +125,37 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualDma<T
             arena: Bump::new(),
         }
     }
+}
 
+impl<'s, T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3>
+    VirtualDma<T, V, D, &'s mut [MaybeUninit<u8>]>
+{
+    /// Constructs a `VirtualDma` object that keeps translated addresses in `scratch`.
+    ///
+    /// Memory operations are translated and performed in batches that fit into the scratch buffer,
+    /// the object itself never allocates. Together with a translator that does not allocate either
+    /// (see [`DirectTranslate::with_buffer`]), virtual memory can be accessed without an allocator.
+    ///
+    /// The buffer has to be large enough to hold at least a single translated page, otherwise all
+    /// operations fail with [`ErrorKind::InvalidMemorySize`].
+    pub fn with_scratch(
+        phys_mem: T,
+        arch: impl Into<ArchitectureObj>,
+        translator: D,
+        vat: V,
+        scratch: &'s mut [MaybeUninit<u8>],
+    ) -> Self {
+        Self {
+            phys_mem,
+            vat,
+            proc_arch: arch.into(),
+            translator,
+            arena: scratch,
+        }
+    }
+}
+
+impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3, S> VirtualDma<T, V, D, S> {
     /// Returns the architecture of the system. The system architecture is used for virtual to physical translations.
     pub fn sys_arch(&self) -> ArchitectureObj {
         self.translator.arch()
@@ -168,6 +204,99 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualDma<T
     pub fn vat(&mut self) -> &mut V {
         &mut self.vat
     }
+
+    fn view_metadata(&self) -> MemoryViewMetadata {
+        let PhysicalMemoryMetadata {
+            max_address,
+            real_size,
+            readonly,
+            ..
+        } = self.phys_mem.metadata();
+
+        MemoryViewMetadata {
+            max_address,
+            real_size,
+            readonly,
+            little_endian: self.proc_arch.endianess() == Endianess::LittleEndian,
+            arch_bits: self.proc_arch.bits(),
+        }
+    }
+
+    /// Translates the given virtual ranges and passes them on as prefetch hint to the
+    /// underlying physical memory.
+    ///
+    /// Ranges that fail to translate are skipped. See [`PhysicalMemory::prefetch_hint`]
+    /// for details.
+    pub fn prefetch_hint(&mut self, ranges: &[VtopRange]) {
+        let mut phys_ranges = vec![];
+
+        self.vat.virt_to_phys_iter(
+            &mut self.phys_mem,
+            &self.translator,
+            ranges
+                .iter()
+                .map(|&CTup2(address, size)| CTup3(address, address, size)),
+            &mut (&mut |CTup3(addr, _, size): CTup3<PhysicalAddress, Address, umem>| {
+                phys_ranges.push(CTup2(addr, size));
+                true
+            })
+                .into(),
+            &mut (&mut |_: (Error, CTup3<Address, Address, umem>)| true).into(),
+        );
+
+        self.phys_mem.prefetch_hint(&phys_ranges);
+    }
+}
+
+impl<T: MappablePhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3, S>
+    VirtualDma<T, V, D, S>
+{
+    /// Visits the `len` bytes at the virtual address `addr` without copying them.
+    ///
+    /// `out` is called in order with the virtual address and a view of the physical memory of
+    /// every physically contiguous piece of the range. Pieces that can not be translated or are
+    /// not directly mapped are skipped, and a `PartialVirtualRead` error is returned after all
+    /// other pieces were visited.
+    pub fn virt_read_mapped(
+        &mut self,
+        addr: Address,
+        len: umem,
+        mut out: impl FnMut(Address, &[u8]),
+    ) -> PartialResult<()> {
+        let mut pieces = vec![];
+        let mut partial = false;
+
+        self.vat.virt_to_phys_iter(
+            &mut self.phys_mem,
+            &self.translator,
+            Some(CTup3(addr, addr, len)).into_iter(),
+            &mut (&mut |CTup3(phys, virt, size): CTup3<PhysicalAddress, Address, umem>| {
+                pieces.push((virt, phys.address(), size));
+                true
+            })
+                .into(),
+            &mut (&mut |_: (Error, CTup3<Address, Address, umem>)| {
+                partial = true;
+                true
+            })
+                .into(),
+        );
+
+        pieces.sort_by_key(|&(virt, _, _)| virt);
+
+        for (virt, phys, size) in pieces {
+            match self.phys_mem.phys_map_range(phys, size as usize) {
+                Some(data) => out(virt, data),
+                None => partial = true,
+            }
+        }
+
+        if partial {
+            Err(PartialError::PartialVirtualRead(()))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl<T, V, D> Clone for VirtualDma<T, V, D>
@@ -213,9 +342,17 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
                 .into(),
         );
 
-        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
-            self.phys_mem.phys_read_raw_iter(data)
-        })
+        let limits = self.phys_mem.metadata().batch_limits();
+        let phys_mem = &mut self.phys_mem;
+
+        MemOps::with_raw_chunked(
+            translation.into_iter(),
+            out,
+            out_fail,
+            limits,
+            |CTup3(_, _, buf)| buf.len(),
+            |data| phys_mem.phys_read_raw_iter(data),
+        )
     }
 
     fn write_raw_iter(
@@ -240,31 +377,145 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
                 .into(),
         );
 
-        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
-            self.phys_mem.phys_write_raw_iter(data)
-        })
+        let limits = self.phys_mem.metadata().batch_limits();
+        let phys_mem = &mut self.phys_mem;
+
+        MemOps::with_raw_chunked(
+            translation.into_iter(),
+            out,
+            out_fail,
+            limits,
+            |CTup3(_, _, buf)| buf.len(),
+            |data| phys_mem.phys_write_raw_iter(data),
+        )
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
-        let PhysicalMemoryMetadata {
-            max_address,
-            real_size,
-            readonly,
-            ..
-        } = self.phys_mem.metadata();
+        self.view_metadata()
+    }
+}
 
-        MemoryViewMetadata {
-            max_address,
-            real_size,
-            readonly,
-            little_endian: self.proc_arch.endianess() == Endianess::LittleEndian,
-            arch_bits: self.proc_arch.bits(),
+#[allow(clippy::needless_option_as_deref)]
+impl<'s, T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
+    for VirtualDma<T, V, D, &'s mut [MaybeUninit<u8>]>
+{
+    fn read_raw_iter<'a>(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+    ) -> Result<()> {
+        let page_size = self.translator.arch().page_size();
+        let limits = self.phys_mem.metadata().batch_limits();
+
+        // Every page sized piece translates into at most one physical piece,
+        // so a batch never holds more translations than its number of inputs
+        let mut inp = inp
+            .flat_map(move |CTup3(a, m, b)| {
+                CTup3(a, m, b).page_chunks(a, page_size).map(|(_, d)| d)
+            })
+            .peekable();
+
+        while inp.peek().is_some() {
+            let mut translation = FixedSliceVec::from_uninit_bytes(&mut *self.arena);
+            if translation.capacity() == 0 {
+                return Err(Error(
+                    ErrorOrigin::VirtualMemory,
+                    ErrorKind::InvalidMemorySize,
+                ));
+            }
+
+            let batch = (&mut inp).take(translation.capacity());
+
+            self.vat.virt_to_phys_iter(
+                &mut self.phys_mem,
+                &self.translator,
+                batch,
+                &mut (&mut |data: PhysicalReadData| translation.try_push(data).is_ok()).into(),
+                &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+                })
+                    .into(),
+            );
+
+            let phys_mem = &mut self.phys_mem;
+
+            MemOps::with_raw_chunked(
+                core::iter::from_fn(|| translation.pop()),
+                out.as_deref_mut(),
+                out_fail.as_deref_mut(),
+                limits,
+                |CTup3(_, _, buf)| buf.len(),
+                |data| phys_mem.phys_read_raw_iter(data),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: WriteRawMemOps,
+    ) -> Result<()> {
+        let page_size = self.translator.arch().page_size();
+        let limits = self.phys_mem.metadata().batch_limits();
+
+        let mut inp = inp
+            .flat_map(move |CTup3(a, m, b)| {
+                CTup3(a, m, b).page_chunks(a, page_size).map(|(_, d)| d)
+            })
+            .peekable();
+
+        while inp.peek().is_some() {
+            let mut translation = FixedSliceVec::from_uninit_bytes(&mut *self.arena);
+            if translation.capacity() == 0 {
+                return Err(Error(
+                    ErrorOrigin::VirtualMemory,
+                    ErrorKind::InvalidMemorySize,
+                ));
+            }
+
+            let batch = (&mut inp).take(translation.capacity());
+
+            self.vat.virt_to_phys_iter(
+                &mut self.phys_mem,
+                &self.translator,
+                batch,
+                &mut (&mut |data: PhysicalWriteData| translation.try_push(data).is_ok()).into(),
+                &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+                })
+                    .into(),
+            );
+
+            let phys_mem = &mut self.phys_mem;
+
+            MemOps::with_raw_chunked(
+                core::iter::from_fn(|| translation.pop()),
+                out.as_deref_mut(),
+                out_fail.as_deref_mut(),
+                limits,
+                |CTup3(_, _, buf)| buf.len(),
+                |data| phys_mem.phys_write_raw_iter(data),
+            )?;
         }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        self.view_metadata()
     }
 }
 
-impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualTranslate
-    for VirtualDma<T, V, D>
+impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3, S: Send> VirtualTranslate
+    for VirtualDma<T, V, D, S>
 {
     fn virt_to_phys_list(
         &mut self,
@@ -292,4 +543,29 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualTrans
                 .into(),
         )
     }
+
+    fn virt_to_phys_list_diag(
+        &mut self,
+        addrs: &[VtopRange],
+        out: VirtualTranslationCallback,
+        mut out_fail: TranslationFailureCallback,
+    ) {
+        let mut failed: Vec<VirtualTranslationFail> = vec![];
+        self.virt_to_phys_list(addrs, out, (&mut failed).into());
+
+        for VirtualTranslationFail { from, size } in failed {
+            let failure = match self.translator.diagnose(&mut self.phys_mem, from) {
+                Some(failure) => TranslationFailure {
+                    from,
+                    size,
+                    ..failure
+                },
+                // the mapping changed in the meantime
+                None => TranslationFailure::unknown(from, size),
+            };
+            if !out_fail.call(failure) {
+                break;
+            }
+        }
+    }
 }
@@ -1,5 +1,7 @@
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 
+#[cfg(feature = "std")]
+mod shared;
 mod tlb_cache;
 
 use crate::architecture::ArchitectureObj;
@@ -9,6 +11,8 @@ use crate::mem::PhysicalMemory;
 use crate::types::cache::{CacheValidator, DefaultCacheValidator};
 use crate::types::{umem, Address};
 use cglue::tuple::*;
+#[cfg(feature = "std")]
+pub use shared::{SharedCachedVirtualTranslate, SharedTlbCache};
 use tlb_cache::TlbCache;
 
 use super::{VirtualTranslate3, VtopFailureCallback, VtopOutputCallback};
@@ -154,87 +158,160 @@ impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2 for CachedVirtua
         D: VirtualTranslate3,
         VI: Iterator<Item = CTup3<Address, Address, B>>,
     {
-        self.tlb.validator.update_validity();
         self.arena.reset();
 
-        let tlb = &mut self.tlb;
-        let vat = &mut self.vat;
-        let mut uncached_out = BumpVec::new_in(&self.arena);
-        let mut uncached_out_fail = BumpVec::new_in(&self.arena);
-        let mut uncached_in = BumpVec::new_in(&self.arena);
-
-        let mut hitc = 0;
-        let mut misc = 0;
-
-        let arch = self.arch;
-        let mut addrs = addrs
-            .filter_map(|CTup3(addr, meta_addr, buf)| {
-                if tlb.is_read_too_long(arch, buf.length() as umem) {
-                    uncached_in.push(CTup3(addr, meta_addr, buf));
-                    None
-                } else {
-                    Some((addr, meta_addr, buf))
-                }
-            })
-            .flat_map(|(addr, meta_addr, buf)| {
-                (meta_addr, buf).page_chunks_by(addr, arch.page_size(), |addr, (_, split), _| {
-                    tlb.try_entry(translator, addr + split.length(), arch)
-                        .is_some()
-                        || tlb.try_entry(translator, addr, arch).is_some()
-                })
-            })
-            .filter_map(|(addr, (meta_addr, buf))| {
-                if let Some(entry) = tlb.try_entry(translator, addr, arch) {
-                    hitc += 1;
-                    debug_assert!(buf.length() <= arch.page_size() as umem);
-                    // TODO: handle case
-                    let _ = match entry {
-                        Ok(entry) => out.call(CTup3(entry.phys_addr, meta_addr, buf)),
-                        Err(error) => out_fail.call((error, CTup3(addr, meta_addr, buf))),
-                    };
-                    None
-                } else {
-                    misc += core::cmp::max(1, buf.length() / arch.page_size() as umem);
-                    Some(CTup3(addr, meta_addr, (addr, buf)))
-                }
-            })
-            .peekable();
-
-        if addrs.peek().is_some() {
-            vat.virt_to_phys_iter(
-                phys_mem,
-                translator,
-                addrs,
-                &mut uncached_out.from_extend(),
-                &mut uncached_out_fail.from_extend(),
-            );
-        }
+        let (hitc, misc) = translate_cached(
+            &mut self.tlb,
+            &mut self.vat,
+            self.arch,
+            &self.arena,
+            phys_mem,
+            translator,
+            addrs,
+            out,
+            out_fail,
+        );
 
-        let mut uncached_iter = uncached_in.into_iter().peekable();
+        self.hitc += hitc;
+        self.misc += misc;
+    }
 
-        if uncached_iter.peek().is_some() {
-            vat.virt_to_phys_iter(phys_mem, translator, uncached_iter, out, out_fail);
-        }
+    fn invalidate_translations(&mut self) {
+        self.tlb.invalidate_all();
+    }
+}
+
+/// Access to the tlb of a cached translator.
+pub(crate) trait TlbAccess {
+    type Validator: CacheValidator;
+
+    fn with_tlb<R>(&mut self, f: impl FnOnce(&mut TlbCache<Self::Validator>) -> R) -> R;
+}
+
+impl<Q: CacheValidator> TlbAccess for TlbCache<Q> {
+    type Validator = Q;
+
+    fn with_tlb<R>(&mut self, f: impl FnOnce(&mut TlbCache<Q>) -> R) -> R {
+        f(self)
+    }
+}
 
-        out.extend(
-            uncached_out
-                .into_iter()
-                .map(|CTup3(paddr, meta_addr, (addr, buf))| {
-                    tlb.cache_entry(translator, addr, paddr, arch);
-                    CTup3(paddr, meta_addr, buf)
+/// Translates `addrs` through the tlb, only the missing translations are passed on to `vat`.
+///
+/// The tlb is accessed twice, once to look up cached translations and once to store the new
+/// ones, but never while `vat` is walking the page tables. Returns the number of hits and misses.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn translate_cached<A, V, T, B, D, VI>(
+    tlb: &mut A,
+    vat: &mut V,
+    arch: ArchitectureObj,
+    arena: &Bump,
+    phys_mem: &mut T,
+    translator: &D,
+    addrs: VI,
+    out: &mut VtopOutputCallback<B>,
+    out_fail: &mut VtopFailureCallback<B>,
+) -> (umem, umem)
+where
+    A: TlbAccess,
+    V: VirtualTranslate2,
+    T: PhysicalMemory + ?Sized,
+    B: SplitAtIndex,
+    D: VirtualTranslate3,
+    VI: Iterator<Item = CTup3<Address, Address, B>>,
+{
+    let mut hits = BumpVec::new_in(arena);
+    let mut uncached_in = BumpVec::new_in(arena);
+    let mut uncached_out = BumpVec::new_in(arena);
+    let mut uncached_out_fail = BumpVec::new_in(arena);
+
+    let mut hitc = 0;
+    let mut misc = 0;
+
+    let misses = tlb.with_tlb(|tlb| {
+        tlb.validator.update_validity();
+
+        let mut misses = BumpVec::new_in(arena);
+        misses.extend(
+            addrs
+                .filter_map(|CTup3(addr, meta_addr, buf)| {
+                    if tlb.is_read_too_long(arch, buf.length() as umem) {
+                        uncached_in.push(CTup3(addr, meta_addr, buf));
+                        None
+                    } else {
+                        Some((addr, meta_addr, buf))
+                    }
+                })
+                .flat_map(|(addr, meta_addr, buf)| {
+                    (meta_addr, buf).page_chunks_by(
+                        addr,
+                        arch.page_size(),
+                        |addr, (_, split), _| {
+                            tlb.try_entry(translator, addr + split.length(), arch)
+                                .is_some()
+                                || tlb.try_entry(translator, addr, arch).is_some()
+                        },
+                    )
+                })
+                .filter_map(|(addr, (meta_addr, buf))| {
+                    if let Some(entry) = tlb.try_entry(translator, addr, arch) {
+                        hitc += 1;
+                        debug_assert!(buf.length() <= arch.page_size() as umem);
+                        hits.push((entry, CTup3(addr, meta_addr, buf)));
+                        None
+                    } else {
+                        misc += core::cmp::max(1, buf.length() / arch.page_size() as umem);
+                        Some(CTup3(addr, meta_addr, (addr, buf)))
+                    }
                 }),
         );
+        misses
+    });
+
+    for (entry, CTup3(addr, meta_addr, buf)) in hits {
+        // TODO: handle case
+        let _ = match entry {
+            Ok(entry) => out.call(CTup3(entry.phys_addr, meta_addr, buf)),
+            Err(error) => out_fail.call((error, CTup3(addr, meta_addr, buf))),
+        };
+    }
 
-        out_fail.extend(uncached_out_fail.into_iter().map(
-            |(err, CTup3(vaddr, meta_addr, (_, buf)))| {
-                tlb.cache_invalid_if_uncached(translator, vaddr, buf.length() as umem, arch);
-                (err, CTup3(vaddr, meta_addr, buf))
-            },
-        ));
+    if !misses.is_empty() {
+        vat.virt_to_phys_iter(
+            phys_mem,
+            translator,
+            misses.into_iter(),
+            &mut uncached_out.from_extend(),
+            &mut uncached_out_fail.from_extend(),
+        );
+    }
 
-        self.hitc += hitc;
-        self.misc += misc;
+    if !uncached_in.is_empty() {
+        vat.virt_to_phys_iter(phys_mem, translator, uncached_in.into_iter(), out, out_fail);
     }
+
+    tlb.with_tlb(|tlb| {
+        for CTup3(paddr, _, (addr, _)) in uncached_out.iter() {
+            tlb.cache_entry(translator, *addr, *paddr, arch);
+        }
+        for (_, CTup3(vaddr, _, (_, buf))) in uncached_out_fail.iter() {
+            tlb.cache_invalid_if_uncached(translator, *vaddr, buf.length() as umem, arch);
+        }
+    });
+
+    out.extend(
+        uncached_out
+            .into_iter()
+            .map(|CTup3(paddr, meta_addr, (_, buf))| CTup3(paddr, meta_addr, buf)),
+    );
+
+    out_fail.extend(
+        uncached_out_fail
+            .into_iter()
+            .map(|(err, CTup3(vaddr, meta_addr, (_, buf)))| (err, CTup3(vaddr, meta_addr, buf))),
+    );
+
+    (hitc, misc)
 }
 
 pub struct CachedVirtualTranslateBuilder<V, Q> {
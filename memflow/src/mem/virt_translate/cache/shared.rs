@@ -0,0 +1,235 @@
+//! Translation cache that can be shared between multiple translators.
+//!
+//! [`CachedVirtualTranslate`](super::CachedVirtualTranslate) owns its tlb. A tool holding
+//! handles to many processes ends up with one cold tlb per process, and every clone of a
+//! process handle starts out with a copy that is not updated afterwards.
+//!
+//! [`SharedTlbCache`] is a tlb behind a lock that is handed to multiple
+//! [`SharedCachedVirtualTranslate`] objects. Entries are keyed by the page table id of the
+//! translator (the DTB on x86), so address spaces of different processes can live in the same
+//! cache side by side. The lock is only taken to look up and store entries, page table walks of
+//! the cache misses happen without holding it.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use memflow::architecture::x86::x64;
+//! use memflow::dummy::{DummyMemory, DummyOs};
+//! use memflow::mem::{
+//!     DirectTranslate, MemoryView, SharedCachedVirtualTranslate, SharedTlbCache, VirtualDma,
+//! };
+//! use memflow::types::{cache::DefaultCacheValidator, size};
+//!
+//! let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+//! let processes = (0..4)
+//!     .map(|_| os.alloc_dtb(size::mb(1), &[]))
+//!     .collect::<Vec<_>>();
+//! let mem = os.into_inner();
+//!
+//! let tlb = Arc::new(SharedTlbCache::new(2048, DefaultCacheValidator::default()));
+//!
+//! for (dtb, virt_base) in processes {
+//!     let vat = SharedCachedVirtualTranslate::new(DirectTranslate::new(), tlb.clone(), x64::ARCH);
+//!     let mut view = VirtualDma::with_vat(mem.clone(), x64::ARCH, x64::new_translator(dtb), vat);
+//!     let _: u64 = view.read(virt_base).unwrap();
+//! }
+//! ```
+
+use std::prelude::v1::*;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{translate_cached, TlbAccess, TlbCache};
+use crate::architecture::ArchitectureObj;
+use crate::iter::SplitAtIndex;
+use crate::mem::virt_translate::{
+    VirtualTranslate2, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+};
+use crate::mem::PhysicalMemory;
+use crate::types::{cache::CacheValidator, umem, Address};
+use cglue::tuple::*;
+
+use bumpalo::Bump;
+
+/// A tlb that can be used by multiple translators at once.
+///
+/// The cache is usually wrapped in an [`Arc`] and handed to multiple
+/// [`SharedCachedVirtualTranslate`] objects, one for every address space.
+pub struct SharedTlbCache<Q> {
+    tlb: Mutex<TlbCache<Q>>,
+}
+
+impl<Q: CacheValidator> SharedTlbCache<Q> {
+    /// Constructs a new cache with `entries` slots, shared by all address spaces.
+    pub fn new(entries: usize, validator: Q) -> Self {
+        Self {
+            tlb: Mutex::new(TlbCache::new(entries, validator)),
+        }
+    }
+
+    /// Drops all cached translations of all address spaces.
+    pub fn invalidate_all(&self) {
+        self.lock().invalidate_all();
+    }
+
+    fn lock(&self) -> MutexGuard<TlbCache<Q>> {
+        // the tlb is consistent even if a user panicked while holding the lock
+        self.tlb.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<Q: CacheValidator> TlbAccess for &SharedTlbCache<Q> {
+    type Validator = Q;
+
+    fn with_tlb<R>(&mut self, f: impl FnOnce(&mut TlbCache<Q>) -> R) -> R {
+        f(&mut self.lock())
+    }
+}
+
+/// A translator caching its translations in a [`SharedTlbCache`].
+///
+/// Cloning this object keeps referencing the same cache.
+pub struct SharedCachedVirtualTranslate<V, Q> {
+    vat: V,
+    tlb: Arc<SharedTlbCache<Q>>,
+    arch: ArchitectureObj,
+    arena: Bump,
+    pub hitc: umem,
+    pub misc: umem,
+}
+
+impl<V: VirtualTranslate2, Q: CacheValidator> SharedCachedVirtualTranslate<V, Q> {
+    /// Constructs a new translator around `vat`, using the given cache.
+    pub fn new(vat: V, tlb: Arc<SharedTlbCache<Q>>, arch: impl Into<ArchitectureObj>) -> Self {
+        Self {
+            vat,
+            tlb,
+            arch: arch.into(),
+            arena: Bump::new(),
+            hitc: 0,
+            misc: 0,
+        }
+    }
+
+    /// Returns the cache used by this object.
+    pub fn tlb(&self) -> &Arc<SharedTlbCache<Q>> {
+        &self.tlb
+    }
+}
+
+impl<V: VirtualTranslate2 + Clone, Q: CacheValidator> Clone for SharedCachedVirtualTranslate<V, Q> {
+    fn clone(&self) -> Self {
+        Self {
+            vat: self.vat.clone(),
+            tlb: self.tlb.clone(),
+            arch: self.arch,
+            arena: Bump::new(),
+            hitc: self.hitc,
+            misc: self.misc,
+        }
+    }
+}
+
+impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2
+    for SharedCachedVirtualTranslate<V, Q>
+{
+    fn virt_to_phys_iter<T, B, D, VI>(
+        &mut self,
+        phys_mem: &mut T,
+        translator: &D,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+    ) where
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        D: VirtualTranslate3,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    {
+        self.arena.reset();
+
+        let (hitc, misc) = translate_cached(
+            &mut &*self.tlb,
+            &mut self.vat,
+            self.arch,
+            &self.arena,
+            phys_mem,
+            translator,
+            addrs,
+            out,
+            out_fail,
+        );
+
+        self.hitc += hitc;
+        self.misc += misc;
+    }
+
+    /// Drops the cached translations of all users of the cache.
+    fn invalidate_translations(&mut self) {
+        self.tlb.invalidate_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::error::PartialResultExt;
+    use crate::mem::{DirectTranslate, MemoryView, VirtualDma};
+    use crate::types::cache::timed_validator::TimedCacheValidator;
+    use crate::types::size;
+
+    use coarsetime::Duration;
+
+    #[test]
+    fn shared_between_address_spaces() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let virt_base = Address::from(0x0001_0000_0000u64);
+        let dtbs = [
+            os.alloc_dtb_const_base(virt_base, size::mb(1), &[1u8; 16]),
+            os.alloc_dtb_const_base(virt_base, size::mb(1), &[2u8; 16]),
+        ];
+        let mut mem = os.into_inner();
+
+        let tlb = Arc::new(SharedTlbCache::new(
+            2048,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        ));
+        let view = |mem: &DummyMemory, dtb| {
+            let vat =
+                SharedCachedVirtualTranslate::new(DirectTranslate::new(), tlb.clone(), x64::ARCH);
+            VirtualDma::with_vat(mem.clone(), x64::ARCH, x64::new_translator(dtb), vat)
+        };
+
+        let mut buf = [0u8; 16];
+        for (i, &dtb) in dtbs.iter().enumerate() {
+            view(&mem, dtb)
+                .read_raw_into(virt_base, &mut buf)
+                .data()
+                .unwrap();
+            assert_eq!(buf, [i as u8 + 1; 16]);
+        }
+
+        // destroy the page tables, fresh views have to use the translations of the first ones
+        for &dtb in dtbs.iter() {
+            mem.phys_write(dtb.into(), vec![0u8; size::kb(4)].as_slice())
+                .unwrap();
+        }
+
+        for (i, &dtb) in dtbs.iter().enumerate() {
+            view(&mem, dtb)
+                .read_raw_into(virt_base, &mut buf)
+                .data()
+                .unwrap();
+            assert_eq!(buf, [i as u8 + 1; 16]);
+        }
+
+        tlb.invalidate_all();
+        assert!(view(&mem, dtbs[0])
+            .read_raw_into(virt_base, &mut buf)
+            .data()
+            .is_err());
+    }
+}
@@ -53,9 +53,21 @@ impl<T: CacheValidator> TlbCache<T> {
         }
     }
 
+    /// Drops all cached entries.
+    pub fn invalidate_all(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = CachedEntry::INVALID;
+        }
+    }
+
+    /// Returns the slot of a page.
+    ///
+    /// The page table id is mixed in, so that the same pages of different address spaces do not
+    /// evict each other when the cache is shared.
     #[inline]
-    fn get_cache_index(&self, page_addr: Address, page_size: usize) -> usize {
-        ((page_addr.to_umem() / page_size as umem) % (self.entries.len() as umem)) as usize
+    fn get_cache_index(&self, pt_index: umem, page_addr: Address, page_size: usize) -> usize {
+        let frame = page_addr.to_umem() / page_size as umem;
+        ((frame ^ pt_index.wrapping_mul(0x9e37_79b9)) % (self.entries.len() as umem)) as usize
     }
 
     #[inline]
@@ -73,7 +85,7 @@ impl<T: CacheValidator> TlbCache<T> {
         let pt_index = translator.translation_table_id(addr);
         let page_size = arch.page_size();
         let page_address = addr.as_page_aligned(page_size);
-        let idx = self.get_cache_index(page_address, page_size);
+        let idx = self.get_cache_index(pt_index, page_address, page_size);
         let entry = self.entries[idx];
         if entry.pt_index == pt_index
             && entry.virt_page == page_address
@@ -109,7 +121,7 @@ impl<T: CacheValidator> TlbCache<T> {
     ) {
         let pt_index = translator.translation_table_id(in_addr);
         let page_size = arch.page_size();
-        let idx = self.get_cache_index(in_addr.as_page_aligned(page_size), page_size);
+        let idx = self.get_cache_index(pt_index, in_addr.as_page_aligned(page_size), page_size);
         self.entries[idx] = CachedEntry {
             pt_index,
             virt_page: in_addr.as_page_aligned(page_size),
@@ -136,7 +148,7 @@ impl<T: CacheValidator> TlbCache<T> {
             .take(self.entries.len())
         {
             let cur_page = Address::from(i);
-            let idx = self.get_cache_index(cur_page, page_size);
+            let idx = self.get_cache_index(pt_index, cur_page, page_size);
 
             let entry = &mut self.entries[idx];
             if entry.pt_index == !0
@@ -3,14 +3,19 @@ use crate::iter::SplitAtIndex;
 use crate::mem::PhysicalMemory;
 use crate::types::{size, Address};
 use cglue::tuple::*;
+use std::mem::MaybeUninit;
 use std::prelude::v1::*;
 
 /*
 The `DirectTranslate` struct provides a default implementation for `VirtualTranslate2` for physical memory.
+
+The scratch buffer used during translation is either owned by the struct, or borrowed from the caller
+(see `DirectTranslate::with_buffer`). Translating through a borrowed buffer does not touch the heap,
+which makes it usable in environments without an allocator.
 */
 #[derive(Debug, Default)]
-pub struct DirectTranslate {
-    tmp_buf: Box<[std::mem::MaybeUninit<u8>]>,
+pub struct DirectTranslate<S = Box<[MaybeUninit<u8>]>> {
+    tmp_buf: S,
 }
 
 impl DirectTranslate {
@@ -20,18 +25,28 @@ impl DirectTranslate {
 
     pub fn with_capacity(size: usize) -> Self {
         Self {
-            tmp_buf: vec![std::mem::MaybeUninit::new(0); size].into_boxed_slice(),
+            tmp_buf: vec![MaybeUninit::new(0); size].into_boxed_slice(),
         }
     }
 }
 
+impl<'a> DirectTranslate<&'a mut [MaybeUninit<u8>]> {
+    /// Creates a translator that uses `buf` as its scratch space.
+    ///
+    /// No allocations are performed during translation. The size of the buffer determines how many
+    /// addresses are kept in flight at once, too small buffers degrade translation performance.
+    pub fn with_buffer(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self { tmp_buf: buf }
+    }
+}
+
 impl Clone for DirectTranslate {
     fn clone(&self) -> Self {
         Self::with_capacity(self.tmp_buf.len())
     }
 }
 
-impl VirtualTranslate2 for DirectTranslate {
+impl<S: AsMut<[MaybeUninit<u8>]> + Send> VirtualTranslate2 for DirectTranslate<S> {
     fn virt_to_phys_iter<T, B, D, VI>(
         &mut self,
         phys_mem: &mut T,
@@ -45,6 +60,6 @@ impl VirtualTranslate2 for DirectTranslate {
         D: VirtualTranslate3,
         VI: Iterator<Item = CTup3<Address, Address, B>>,
     {
-        translator.virt_to_phys_iter(phys_mem, addrs, out, out_fail, &mut self.tmp_buf)
+        translator.virt_to_phys_iter(phys_mem, addrs, out, out_fail, self.tmp_buf.as_mut())
     }
 }
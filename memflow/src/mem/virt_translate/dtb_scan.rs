@@ -0,0 +1,263 @@
+//! Scanning of physical memory for page table roots.
+//!
+//! OS layers usually locate the directory table base (the value of `CR3` on x86) of the kernel
+//! through OS specific structures. When these can not be found, e.g. in corrupted dumps or on
+//! unknown operating systems, [`scan`] looks for pages that are plausible roots of page tables
+//! instead:
+//!
+//! - Windows maps the page tables of every address space into the address space itself. Its
+//!   roots reference themselves, in the kernel half on x64 and at `0xc030_0000` on 32-bit x86.
+//! - Every address space maps the kernel into its upper half, so roots without kernel mappings
+//!   are ignored.
+//! - The tables referenced by the kernel half of a root have to look like page tables as well.
+//!
+//! Candidates are ranked by how well they match these heuristics. The results are guesses,
+//! callers should verify them (for example by translating a well known kernel address) before
+//! relying on them.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::dummy::DummyMemory;
+//! use memflow::mem::virt_translate::dtb_scan;
+//! use memflow::types::size;
+//!
+//! let mut mem = DummyMemory::new(size::mb(4));
+//! let candidates = dtb_scan::scan(&mut mem).unwrap();
+//! assert!(candidates.is_empty());
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::architecture::detect::{u32_at, u64_at, x64_page_table, x86_page_directory};
+use crate::architecture::ArchitectureIdent;
+use crate::error::Result;
+use crate::mem::PhysicalMemory;
+use crate::types::{size, umem, Address};
+
+const PAGE_SIZE: usize = size::kb(4);
+const CHUNK_SIZE: usize = size::mb(2);
+
+/// Maximum number of referenced tables that are verified per candidate.
+const MAX_CHECKED_TABLES: usize = 16;
+
+const SELF_REFERENCE_SCORE: u32 = 8;
+const KERNEL_ENTRY_SCORE: u32 = 1;
+const VALID_TABLE_SCORE: u32 = 2;
+
+/// A plausible page table root found by [`scan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DtbCandidate {
+    /// Architecture the page tables were found for.
+    pub arch: ArchitectureIdent,
+    /// Physical address of the root table.
+    pub dtb: Address,
+    /// Score of the candidate, higher is more likely.
+    pub score: u32,
+    /// Whether the root table references itself.
+    pub self_ref: bool,
+    /// Number of present entries in the kernel half of the root table.
+    pub kernel_entries: usize,
+    /// Number of tables referenced by the kernel half that look like page tables.
+    pub valid_tables: usize,
+}
+
+/// Scans the physical memory for page table roots and returns them, the most likely first.
+///
+/// 4-level x64 and non-PAE 32-bit x86 page tables are supported. Pages that can not be read
+/// are skipped. Candidates whose referenced tables do not look like page tables are dropped.
+pub fn scan<T: PhysicalMemory>(mem: &mut T) -> Result<Vec<DtbCandidate>> {
+    let max_address = mem.metadata().max_address;
+    let mut found = vec![];
+    let mut buf = vec![0u8; CHUNK_SIZE];
+
+    let mut base = Address::null();
+    while base <= max_address {
+        let len = std::cmp::min(CHUNK_SIZE as umem, (max_address - base) as umem + 1) as usize;
+        let chunk = &mut buf[..len];
+
+        if mem.phys_read_into(base.into(), chunk).is_ok() {
+            for (i, page) in chunk.chunks_exact(PAGE_SIZE).enumerate() {
+                found.extend(scan_page(page, base + i * PAGE_SIZE, max_address));
+            }
+        }
+
+        base += CHUNK_SIZE;
+    }
+
+    let mut page = vec![0u8; PAGE_SIZE];
+    let mut candidates = found
+        .into_iter()
+        .filter_map(|root| root.verify(mem, &mut page, max_address))
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| b.score.cmp(&a.score).then(a.dtb.cmp(&b.dtb)));
+    Ok(candidates)
+}
+
+/// A root table found during the scan, before its referenced tables are verified.
+struct Root {
+    arch: ArchitectureIdent,
+    dtb: Address,
+    self_ref: bool,
+    kernel_entries: usize,
+    /// Tables referenced by the kernel half, excluding the root itself
+    tables: Vec<Address>,
+}
+
+impl Root {
+    fn verify<T: PhysicalMemory>(
+        self,
+        mem: &mut T,
+        page: &mut [u8],
+        max_address: Address,
+    ) -> Option<DtbCandidate> {
+        let pte_size = if self.arch == ArchitectureIdent::X86(64, false) {
+            8
+        } else {
+            4
+        };
+
+        let valid_tables = self
+            .tables
+            .iter()
+            .filter(|&&table| {
+                mem.phys_read_into(table.into(), &mut *page).is_ok()
+                    && is_page_table(page, pte_size, max_address)
+            })
+            .count();
+
+        // self-referencing roots are trusted even if the kernel half only maps the root itself
+        if valid_tables == 0 && !(self.self_ref && self.tables.is_empty()) {
+            return None;
+        }
+
+        let self_ref_score = if self.self_ref {
+            SELF_REFERENCE_SCORE
+        } else {
+            0
+        };
+
+        Some(DtbCandidate {
+            arch: self.arch,
+            dtb: self.dtb,
+            score: self_ref_score
+                + self.kernel_entries as u32 * KERNEL_ENTRY_SCORE
+                + valid_tables as u32 * VALID_TABLE_SCORE,
+            self_ref: self.self_ref,
+            kernel_entries: self.kernel_entries,
+            valid_tables,
+        })
+    }
+}
+
+fn scan_page(page: &[u8], addr: Address, max_address: Address) -> Vec<Root> {
+    let mut roots = vec![];
+
+    if let Some(self_ref) = x64_page_table(page, addr, max_address) {
+        let entries = (256..512)
+            .map(|idx| u64_at(page, idx))
+            .filter(|entry| entry & 1 != 0)
+            .map(|entry| Address::from(entry & 0x000f_ffff_ffff_f000))
+            .collect::<Vec<_>>();
+
+        roots.push(Root {
+            arch: ArchitectureIdent::X86(64, false),
+            dtb: addr,
+            self_ref,
+            kernel_entries: entries.len(),
+            tables: kernel_tables(entries, addr),
+        });
+    }
+
+    if x86_page_directory(page, addr, max_address) {
+        // 4mb pages in the kernel half do not reference any tables
+        let entries = (512..1024)
+            .map(|idx| u32_at(page, idx))
+            .filter(|entry| entry & 1 != 0)
+            .collect::<Vec<_>>();
+        let tables = entries
+            .iter()
+            .filter(|&entry| entry & (1 << 7) == 0)
+            .map(|entry| Address::from(entry & !0xfff))
+            .collect();
+
+        roots.push(Root {
+            arch: ArchitectureIdent::X86(32, false),
+            dtb: addr,
+            self_ref: true,
+            kernel_entries: entries.len(),
+            tables: kernel_tables(tables, addr),
+        });
+    }
+
+    roots
+}
+
+fn kernel_tables(mut tables: Vec<Address>, root: Address) -> Vec<Address> {
+    tables.retain(|&table| table != root);
+    tables.sort_unstable();
+    tables.dedup();
+    tables.truncate(MAX_CHECKED_TABLES);
+    tables
+}
+
+/// Checks whether `page` has present entries, all of them pointing into physical memory.
+fn is_page_table(page: &[u8], pte_size: usize, max_address: Address) -> bool {
+    let mut present = 0;
+
+    for idx in 0..PAGE_SIZE / pte_size {
+        let entry = if pte_size == 8 {
+            u64_at(page, idx)
+        } else {
+            u32_at(page, idx) as u64
+        };
+
+        if entry & 1 != 0 {
+            if Address::from(entry & 0x000f_ffff_ffff_f000) > max_address {
+                return false;
+            }
+            present += 1;
+        }
+    }
+
+    present > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+
+    fn write_u64(mem: &mut DummyMemory, addr: u64, value: u64) {
+        mem.phys_write(Address::from(addr).into(), &value.to_le_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn ranks_candidates() {
+        let mut mem = DummyMemory::new(size::mb(4));
+
+        // a windows style pml4 at 0x1000 and a linux style one at 0x3000, sharing a pdpt
+        write_u64(&mut mem, 0x1000 + 256 * 8, 0x5003);
+        write_u64(&mut mem, 0x1000 + 0x1ed * 8, 0x1063);
+        write_u64(&mut mem, 0x3000 + 256 * 8, 0x5003);
+        write_u64(&mut mem, 0x5000, 0x6003);
+
+        // the kernel half references an empty page
+        write_u64(&mut mem, 0x2000 + 256 * 8, 0x7003);
+
+        let candidates = scan(&mut mem).unwrap();
+        let dtbs = candidates.iter().map(|c| c.dtb).collect::<Vec<_>>();
+        assert_eq!(dtbs, vec![Address::from(0x1000), Address::from(0x3000)]);
+
+        assert!(candidates[0].self_ref);
+        assert_eq!(candidates[0].kernel_entries, 2);
+        assert_eq!(candidates[0].valid_tables, 1);
+        assert_eq!(
+            candidates[0].score,
+            SELF_REFERENCE_SCORE + 2 * KERNEL_ENTRY_SCORE + VALID_TABLE_SCORE
+        );
+        assert!(!candidates[1].self_ref);
+    }
+}
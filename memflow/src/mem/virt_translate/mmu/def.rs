@@ -43,6 +43,10 @@ pub struct ArchMmuDef {
     pub nx_bit: fn(Address, bool) -> bool,
     /// function for checking a bit in PTE to see if the PTE points to a large page.
     pub large_page_bit: fn(Address) -> bool,
+    /// function converting a raw PTE into the layout expected by the page walk, with the physical
+    /// address bits in place. This is the identity on architectures that do not shift the frame
+    /// number inside the entry.
+    pub pte_fixup: fn(Address) -> Address,
 }
 
 impl ArchMmuDef {
@@ -72,10 +76,10 @@ impl ArchMmuDef {
             + if step == self.virtual_address_splits.len() - 1 {
                 0
             } else {
-                self.pte_size.to_le().trailing_zeros() as u8
+                self.pte_size.trailing_zeros() as u8
             };
         let mask = Address::bit_mask(min..max);
-        pte_addr.to_umem() & umem::from_le(mask.to_umem())
+        pte_addr.to_umem() & mask.to_umem()
     }
 
     pub(crate) const fn virt_addr_bit_range(&self, step: usize) -> (u8, u8) {
@@ -67,7 +67,7 @@ impl ArchMmuSpec {
                 + if i == def.virtual_address_splits.len() - 1 {
                     0
                 } else {
-                    def.pte_size.to_le().trailing_zeros() as u8
+                    def.pte_size.trailing_zeros() as u8
                 };
             let mask = Address::bit_mask_u8(min..max);
             pte_addr_masks[i] = mask.to_umem();
@@ -103,7 +103,7 @@ impl ArchMmuSpec {
     }
 
     pub fn pte_addr_mask(&self, pte_addr: Address, step: usize) -> umem {
-        pte_addr.to_umem() & umem::from_le(self.pte_addr_masks[step])
+        pte_addr.to_umem() & self.pte_addr_masks[step]
     }
 
     /// Filter out the input virtual address range to be in bounds
@@ -215,14 +215,12 @@ impl ArchMmuSpec {
     }
 
     pub fn virt_addr_to_pte_offset(&self, virt_addr: Address, step: usize) -> umem {
-        umem::from_le(
-            (virt_addr.to_umem().to_le() >> self.virt_addr_bit_ranges[step].0)
-                & self.virt_addr_masks[step],
-        ) * self.def.pte_size as umem
+        ((virt_addr.to_umem() >> self.virt_addr_bit_ranges[step].0) & self.virt_addr_masks[step])
+            * self.def.pte_size as umem
     }
 
     pub fn virt_addr_to_page_offset(&self, virt_addr: Address, step: usize) -> umem {
-        virt_addr.to_umem() & umem::from_le(self.virt_addr_page_masks[step])
+        virt_addr.to_umem() & self.virt_addr_page_masks[step]
     }
 
     /// Get the page size of a specific step without checking if such page could exist
@@ -564,7 +562,7 @@ impl ArchMmuSpec {
 
         // Move the read value into the chunk
         for (ref mut chunk, CTup3(_, _, buf)) in chunks.iter_mut().zip(pt_read.iter()) {
-            let pt_addr = buf_to_addr(&*buf);
+            let pt_addr = (self.def.pte_fixup)(buf_to_addr(&*buf));
             chunk.pt_addr = pt_addr;
             // We assume the flags may either always inherit or never inherit.
             // Thus, if there is a more insane architecture, that has it mixed,
@@ -713,3 +711,114 @@ impl ArchMmuSpec {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::{mem, size};
+
+    use std::mem::MaybeUninit;
+
+    // x86 layouts with big-endian page table entries
+    static BE64: ArchMmuSpec = ArchMmuDef {
+        virtual_address_splits: &[9, 9, 9, 9, 12],
+        valid_final_page_steps: &[2, 3, 4],
+        address_space_bits: 52,
+        endianess: Endianess::BigEndian,
+        addr_size: 8,
+        pte_size: 8,
+        present_bit: |a| a.bit_at(0),
+        writeable_bit: |a, pb| pb || a.bit_at(1),
+        nx_bit: |a, pb| pb || a.bit_at(63),
+        large_page_bit: |a| a.bit_at(7),
+        pte_fixup: |a| a,
+    }
+    .into_spec();
+
+    static BE32: ArchMmuSpec = ArchMmuDef {
+        virtual_address_splits: &[10, 10, 12],
+        valid_final_page_steps: &[1, 2],
+        address_space_bits: 32,
+        endianess: Endianess::BigEndian,
+        addr_size: 4,
+        pte_size: 4,
+        present_bit: |a| a.bit_at(0),
+        writeable_bit: |a, pb| pb || a.bit_at(1),
+        nx_bit: |_, _| false,
+        large_page_bit: |a| a.bit_at(7),
+        pte_fixup: |a| a,
+    }
+    .into_spec();
+
+    fn translate(spec: &ArchMmuSpec, mem: &mut DummyMemory, addr: u64) -> Option<PhysicalAddress> {
+        let mut buf = vec![MaybeUninit::new(0); size::kb(64)];
+        let mut ret = None;
+        let out = &mut |CTup3(page, _, _): CTup3<PhysicalAddress, Address, umem>| {
+            ret = Some(page);
+            true
+        };
+        let fail = &mut |_: (Error, CTup3<Address, Address, umem>)| true;
+
+        let addr = Address::from(addr);
+        spec.virt_to_phys_iter(
+            mem,
+            Address::from(0x1000),
+            std::iter::once(CTup3(addr, addr, 1)),
+            &mut out.into(),
+            &mut fail.into(),
+            &mut buf,
+        );
+
+        ret
+    }
+
+    #[test]
+    fn big_endian_64() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        for &(pte_addr, pte) in [
+            (0x1000u64, 0x2003u64),
+            (0x2000, 0x3003),
+            (0x3000, 0x4003),
+            (0x4008, 0x5003),
+            (0x3008, 0x20_0083),
+        ]
+        .iter()
+        {
+            mem.phys_write(Address::from(pte_addr).into(), &pte.to_be_bytes())
+                .unwrap();
+        }
+
+        let page = translate(&BE64, &mut mem, 0x1234).unwrap();
+        assert_eq!(page.address(), Address::from(0x5234));
+        assert_eq!(page.page_size(), mem::kb(4));
+
+        let page = translate(&BE64, &mut mem, 0x21_2345).unwrap();
+        assert_eq!(page.address(), Address::from(0x21_2345));
+        assert_eq!(page.page_size(), mem::mb(2));
+
+        assert!(translate(&BE64, &mut mem, 0x2000).is_none());
+    }
+
+    #[test]
+    fn big_endian_32() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        for &(pte_addr, pte) in [
+            (0x1000u64, 0x2003u32),
+            (0x2004, 0x5003),
+            (0x1004, 0x40_0083),
+        ]
+        .iter()
+        {
+            mem.phys_write(Address::from(pte_addr).into(), &pte.to_be_bytes())
+                .unwrap();
+        }
+
+        let page = translate(&BE32, &mut mem, 0x1234).unwrap();
+        assert_eq!(page.address(), Address::from(0x5234));
+
+        let page = translate(&BE32, &mut mem, 0x40_5678).unwrap();
+        assert_eq!(page.address(), Address::from(0x40_5678));
+        assert_eq!(page.page_size(), mem::mb(4));
+    }
+}
@@ -3,6 +3,7 @@ use std::prelude::v1::*;
 use super::{MemoryRange, MemoryRangeCallback, VtopRange};
 
 use std::cmp::*;
+use std::ops::Range;
 
 use cglue::prelude::v1::*;
 use itertools::Itertools;
@@ -21,6 +22,11 @@ pub mod cache;
 
 pub use cache::*;
 
+pub mod nested;
+pub use nested::NestedTranslate;
+
+pub mod dtb_scan;
+
 //use crate::error::{Error, Result};
 //use crate::iter::SplitAtIndex;
 //use crate::mem::{MemData, PhysicalMemory};
@@ -36,7 +42,7 @@ mod tests;
 use crate::error::{Result, *};
 
 use crate::mem::PhysicalMemory;
-use crate::types::{imem, umem, Address, Page, PhysicalAddress};
+use crate::types::{imem, umem, Address, Page, PageType, PhysicalAddress};
 
 #[cglue_trait]
 #[int_result]
@@ -143,6 +149,50 @@ pub trait VirtualTranslate: Send {
         out
     }
 
+    /// Translates a list of virtual address ranges, reporting the reasons of failed translations.
+    ///
+    /// The reason reported for a failed range is the one of its first page. Implementations
+    /// without diagnostics report every failure with an [`Unknown`](TranslationFailReason::Unknown)
+    /// reason.
+    ///
+    /// This function is not part of the plugin interface, plugin instances always report
+    /// [`Unknown`](TranslationFailReason::Unknown) reasons.
+    #[skip_func]
+    fn virt_to_phys_list_diag(
+        &mut self,
+        addrs: &[VtopRange],
+        out: VirtualTranslationCallback,
+        out_fail: TranslationFailureCallback,
+    ) {
+        let mut out_fail = out_fail;
+        self.virt_to_phys_list(
+            addrs,
+            out,
+            (&mut |VirtualTranslationFail { from, size }| {
+                out_fail.call(TranslationFailure::unknown(from, size))
+            })
+                .into(),
+        )
+    }
+
+    /// Returns why `address` can not be translated, `None` if it can.
+    #[skip_func]
+    fn virt_translation_failure(&mut self, address: Address) -> Option<TranslationFailure> {
+        let mut failure = None;
+
+        self.virt_to_phys_list_diag(
+            &[CTup2(address, 1)],
+            (&mut |_: VirtualTranslation| false).into(),
+            (&mut |fail| {
+                failure = Some(fail);
+                false
+            })
+                .into(),
+        );
+
+        failure
+    }
+
     fn virt_page_info(&mut self, addr: Address) -> Result<Page> {
         let paddr = self.virt_to_phys(addr)?;
         Ok(paddr.containing_page())
@@ -160,6 +210,39 @@ pub trait VirtualTranslate: Send {
         out
     }
 
+    /// Translates the virtual `range` into runs of contiguous mappings in a single pass.
+    ///
+    /// Neighbouring translations are merged into one run if both their virtual and physical
+    /// addresses are contiguous and they share the same page type and page size. Unmapped parts
+    /// of the range do not produce any runs.
+    #[skip_func]
+    fn translate_map(&mut self, range: Range<Address>) -> Vec<TranslationRun> {
+        let mut translations: Vec<VirtualTranslation> = vec![];
+        self.virt_to_phys_range(range.start, range.end, (&mut translations).into());
+
+        translations.sort_unstable();
+        translations.dedup();
+
+        translations
+            .into_iter()
+            .map(TranslationRun::from)
+            .coalesce(|a, b| {
+                if b.virt == a.virt + a.len
+                    && b.phys == a.phys + a.len
+                    && a.page_type == b.page_type
+                    && a.page_size == b.page_size
+                {
+                    Ok(TranslationRun {
+                        len: a.len + b.len,
+                        ..a
+                    })
+                } else {
+                    Err((a, b))
+                }
+            })
+            .collect()
+    }
+
     // page map helpers
     fn virt_translation_map(&mut self, out: VirtualTranslationCallback) {
         self.virt_translation_map_range(Address::null(), Address::invalid(), out)
@@ -265,6 +348,36 @@ impl PartialEq for VirtualTranslation {
     }
 }
 
+/// Contiguous virtual memory mapped to contiguous physical memory with uniform pages.
+///
+/// Returned by [`VirtualTranslate::translate_map`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TranslationRun {
+    /// Virtual start address of the run.
+    pub virt: Address,
+    /// Physical start address of the run.
+    pub phys: Address,
+    /// Length of the run in bytes.
+    pub len: umem,
+    /// Type of the pages mapping the run.
+    pub page_type: PageType,
+    /// Size of the pages mapping the run.
+    pub page_size: umem,
+}
+
+impl From<VirtualTranslation> for TranslationRun {
+    fn from(translation: VirtualTranslation) -> Self {
+        Self {
+            virt: translation.in_virtual,
+            phys: translation.out_physical.address(),
+            len: translation.size,
+            page_type: translation.out_physical.page_type(),
+            page_size: translation.out_physical.page_size(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
@@ -274,6 +387,77 @@ pub struct VirtualTranslationFail {
     pub size: umem,
 }
 
+/// Reason of a failed virtual address translation.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum TranslationFailReason {
+    /// The translator does not provide any details.
+    Unknown,
+    /// The address lies outside of the virtual address space, e.g. it is not canonical on x64.
+    OutOfAddressSpace,
+    /// The entry is not present.
+    NotPresent,
+    /// The entry has reserved bits set.
+    ReservedBits,
+    /// The entry does not allow accesses from user mode.
+    UserSupervisor,
+    /// The table containing the entry could not be read.
+    Unreadable,
+}
+
+/// Failed translation of a virtual address range, along with the reason of the failure.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct TranslationFailure {
+    pub from: Address,
+    pub size: umem,
+    pub reason: TranslationFailReason,
+    /// Paging level of the entry causing the failure, 1 being the lowest level.
+    ///
+    /// Zero if the failure is not caused by an entry.
+    pub level: u8,
+    /// Raw value of the entry causing the failure.
+    pub entry: u64,
+}
+
+impl TranslationFailure {
+    /// Creates a failure without any details.
+    pub fn unknown(from: Address, size: umem) -> Self {
+        Self {
+            from,
+            size,
+            reason: TranslationFailReason::Unknown,
+            level: 0,
+            entry: 0,
+        }
+    }
+}
+
+impl core::fmt::Display for TranslationFailure {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let reason = match self.reason {
+            TranslationFailReason::Unknown => "translation failed",
+            TranslationFailReason::OutOfAddressSpace => "outside of the address space",
+            TranslationFailReason::NotPresent => "entry not present",
+            TranslationFailReason::ReservedBits => "reserved bits set",
+            TranslationFailReason::UserSupervisor => "supervisor only entry",
+            TranslationFailReason::Unreadable => "page table unreadable",
+        };
+
+        write!(f, "{:x}: {}", self.from, reason)?;
+        if self.level != 0 {
+            write!(f, " at level {} (entry {:#x})", self.level, self.entry)?;
+        }
+        Ok(())
+    }
+}
+
+pub type TranslationFailureCallback<'a> = OpaqueCallback<'a, TranslationFailure>;
+
 pub trait VirtualTranslate2
 where
     Self: Send,
@@ -426,6 +610,12 @@ where
         );
         output.map(Ok).unwrap_or_else(|| Err(output_err.unwrap()))
     }
+
+    /// Drops all cached translations.
+    ///
+    /// Translators without a cache do nothing. This has to be called after the page tables of
+    /// the target were modified by memflow itself, cached translations would go stale otherwise.
+    fn invalidate_translations(&mut self) {}
 }
 
 // forward impls
@@ -450,6 +640,11 @@ where
     {
         (**self).virt_to_phys_iter(phys_mem, translator, addrs, out, out_fail)
     }
+
+    #[inline]
+    fn invalidate_translations(&mut self) {
+        (**self).invalidate_translations()
+    }
 }
 
 /// Translates virtual memory to physical using internal translation base (usually a process' dtb)
@@ -538,6 +733,20 @@ pub trait VirtualTranslate3: Clone + Copy + Send {
         tmp_buf: &mut [std::mem::MaybeUninit<u8>],
     );
 
+    /// Returns why `address` can not be translated, `None` if it can.
+    ///
+    /// The default implementation only determines whether the translation fails, without
+    /// giving a reason.
+    fn diagnose<T: PhysicalMemory>(
+        &self,
+        mem: &mut T,
+        address: Address,
+    ) -> Option<TranslationFailure> {
+        self.virt_to_phys(mem, address)
+            .err()
+            .map(|_| TranslationFailure::unknown(address, 1))
+    }
+
     fn translation_table_id(&self, address: Address) -> umem;
 
     fn arch(&self) -> ArchitectureObj;
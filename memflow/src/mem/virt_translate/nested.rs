@@ -0,0 +1,322 @@
+/*!
+Two-stage address translation.
+
+Virtual machines translate addresses twice: the guest page tables translate guest virtual
+addresses to guest physical ones, and the second stage (EPT on Intel, NPT on AMD) translates guest
+physical addresses to host physical ones. The guest page tables themselves are stored in guest
+physical memory, so every page table access of the guest walk goes through the second stage as
+well.
+
+[`NestedTranslate`] composes two translators this way, which allows reading guest virtual memory
+straight from a host memory image:
+
+```
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::{size, Address};
+use memflow::architecture::x86::{ept, x64};
+use memflow::mem::{NestedTranslate, VirtualDma};
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+# let (ept_pml4, _) = os.alloc_dtb(size::kb(64), &[]);
+# let (guest_cr3, _) = os.alloc_dtb(size::kb(64), &[]);
+# let mem = os.into_inner();
+// guest_cr3 is a guest physical address, translated through the second stage
+let translator = NestedTranslate::new(x64::new_translator(guest_cr3), ept::new_translator(ept_pml4));
+let guest_mem = VirtualDma::new(mem, x64::ARCH, translator);
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::{VirtualTranslate3, VtopFailureCallback, VtopOutputCallback};
+use crate::architecture::ArchitectureObj;
+use crate::error::{Error, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::mem_data::*;
+use crate::mem::{PhysicalMemory, PhysicalMemoryMetadata};
+use crate::types::{umem, Address, PageType, PhysicalAddress};
+
+use cglue::callback::FromExtend;
+use cglue::tuple::*;
+
+use std::mem::MaybeUninit;
+
+/// Translator composing a guest translator with a second stage translator.
+///
+/// The guest translator walks the guest page tables in guest physical memory. The second stage
+/// translator maps guest physical memory to host physical memory. The resulting pages are only
+/// writeable if both stages allow writes, and executable if both allow execution.
+#[derive(Clone, Copy)]
+pub struct NestedTranslate<G, H> {
+    guest: G,
+    host: H,
+}
+
+impl<G: VirtualTranslate3, H: VirtualTranslate3> NestedTranslate<G, H> {
+    pub fn new(guest: G, host: H) -> Self {
+        Self { guest, host }
+    }
+
+    /// Returns the guest virtual to guest physical translator.
+    pub fn guest(&self) -> &G {
+        &self.guest
+    }
+
+    /// Returns the guest physical to host physical translator.
+    pub fn host(&self) -> &H {
+        &self.host
+    }
+}
+
+impl<G: VirtualTranslate3, H: VirtualTranslate3> VirtualTranslate3 for NestedTranslate<G, H> {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        tmp_buf: &mut [MaybeUninit<u8>],
+    ) {
+        // The meta address slot carries the guest virtual address through both stages, so that
+        // failures of the second stage can still be reported on it.
+        let mut guest_phys = vec![];
+
+        {
+            let (guest_buf, host_buf) = tmp_buf.split_at_mut(tmp_buf.len() / 2);
+            let mut guest_mem = SecondStage {
+                mem: &mut *mem,
+                host: self.host,
+                tmp_buf: host_buf,
+            };
+
+            let guest_out = &mut |CTup3(page, addr, mut data): StagedOutput<B>| {
+                data.page = page;
+                guest_phys.push(CTup3(page.address(), addr, data));
+                true
+            };
+            let guest_fail = &mut |(err, CTup3(addr, _, data)): StagedFailure<B>| {
+                out_fail.call((err, CTup3(addr, data.meta, data.buf)))
+            };
+
+            self.guest.virt_to_phys_iter(
+                &mut guest_mem,
+                addrs.map(|CTup3(addr, meta, buf)| {
+                    let page = PhysicalAddress::INVALID;
+                    CTup3(addr, addr, Staged { page, meta, buf })
+                }),
+                &mut guest_out.into(),
+                &mut guest_fail.into(),
+                guest_buf,
+            );
+        }
+
+        let host_out = &mut |CTup3(page, _, data): StagedOutput<B>| {
+            out.call(CTup3(combine_pages(data.page, page), data.meta, data.buf))
+        };
+        let host_fail = &mut |(err, CTup3(_, addr, data)): StagedFailure<B>| {
+            out_fail.call((err, CTup3(addr, data.meta, data.buf)))
+        };
+
+        self.host.virt_to_phys_iter(
+            mem,
+            guest_phys.into_iter(),
+            &mut host_out.into(),
+            &mut host_fail.into(),
+            tmp_buf,
+        );
+    }
+
+    fn translation_table_id(&self, address: Address) -> umem {
+        // guests of different virtual machines may use the same page table addresses
+        self.guest.translation_table_id(address)
+            ^ self
+                .host
+                .translation_table_id(Address::null())
+                .rotate_left(umem::BITS / 2)
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        self.guest.arch()
+    }
+}
+
+fn combine_pages(guest: PhysicalAddress, host: PhysicalAddress) -> PhysicalAddress {
+    let (guest_type, host_type) = (guest.page_type(), host.page_type());
+
+    PhysicalAddress::with_page(
+        host.address(),
+        PageType::default()
+            .write(
+                guest_type.contains(PageType::WRITEABLE) && host_type.contains(PageType::WRITEABLE),
+            )
+            .noexec(guest_type.contains(PageType::NOEXEC) || host_type.contains(PageType::NOEXEC)),
+        std::cmp::min(guest.page_size(), host.page_size()),
+    )
+}
+
+type StagedOutput<B> = CTup3<PhysicalAddress, Address, Staged<B>>;
+type StagedFailure<B> = (Error, CTup3<Address, Address, Staged<B>>);
+
+/// Data passed through both stages, along with the original meta address and the guest page.
+struct Staged<B> {
+    page: PhysicalAddress,
+    meta: Address,
+    buf: B,
+}
+
+impl<B: SplitAtIndex> Staged<B> {
+    fn split_with(
+        page: PhysicalAddress,
+        meta: Address,
+        (left, right): (Option<B>, Option<B>),
+    ) -> (Option<Self>, Option<Self>) {
+        let right_meta = meta + left.as_ref().map(B::length).unwrap_or(0);
+        (
+            left.map(|buf| Self { page, meta, buf }),
+            right.map(|buf| Self {
+                page,
+                meta: right_meta,
+                buf,
+            }),
+        )
+    }
+}
+
+impl<B: SplitAtIndex> SplitAtIndex for Staged<B> {
+    fn split_at(self, idx: umem) -> (Option<Self>, Option<Self>) {
+        Self::split_with(self.page, self.meta, self.buf.split_at(idx))
+    }
+
+    unsafe fn split_at_mut(&mut self, idx: umem) -> (Option<Self>, Option<Self>) {
+        Self::split_with(self.page, self.meta, self.buf.split_at_mut(idx))
+    }
+
+    fn length(&self) -> umem {
+        self.buf.length()
+    }
+
+    fn size_hint(&self) -> usize {
+        self.buf.size_hint()
+    }
+}
+
+/// Guest physical memory, translated through the second stage.
+struct SecondStage<'a, T: ?Sized, H> {
+    mem: &'a mut T,
+    host: H,
+    tmp_buf: &'a mut [MaybeUninit<u8>],
+}
+
+impl<'a, T: PhysicalMemory + ?Sized, H: VirtualTranslate3> PhysicalMemory
+    for SecondStage<'a, T, H>
+{
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let mut translation = Vec::with_capacity(inp.size_hint().0);
+
+        self.host.virt_to_phys_iter(
+            self.mem,
+            inp.map(|CTup3(addr, meta, buf)| CTup3(addr.address(), meta, buf)),
+            &mut translation.from_extend(),
+            &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+            })
+                .into(),
+            self.tmp_buf,
+        );
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let mut translation = Vec::with_capacity(inp.size_hint().0);
+
+        self.host.virt_to_phys_iter(
+            self.mem,
+            inp.map(|CTup3(addr, meta, buf)| CTup3(addr.address(), meta, buf)),
+            &mut translation.from_extend(),
+            &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+            })
+                .into(),
+            self.tmp_buf,
+        );
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
+            mem.phys_write_raw_iter(data)
+        })
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::cglue::ForwardMut;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::{MemoryView, VirtualDma};
+    use crate::types::{mem, size};
+
+    #[test]
+    fn guest_virtual_reads() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let gpa = Address::from(0x1000_0000);
+        let host = x64::new_translator(os.alloc_dtb_const_base(gpa, size::mb(1), &[]));
+
+        // guest virtual 0x1000 -> gpa + 0x4000, 0x2000 -> unmapped in the second stage
+        {
+            let mut guest_phys = VirtualDma::new(os.forward_mut(), x64::ARCH, host);
+            let entries: [(umem, umem, umem); 5] = [
+                (0x0, 0, 0x1000),
+                (0x1000, 0, 0x2000),
+                (0x2000, 0, 0x3000),
+                (0x3000, 1, 0x4000),
+                (0x3000, 2, mem::mb(2)),
+            ];
+            for &(table, idx, next) in entries.iter() {
+                let pte = (gpa + next).to_umem() | 0b11;
+                guest_phys.write(gpa + table + idx * 8, &pte).unwrap();
+            }
+            guest_phys.write_raw(gpa + 0x4000, b"nested").unwrap();
+        }
+
+        let nested = NestedTranslate::new(x64::new_translator(gpa), host);
+
+        let page = nested.virt_to_phys(&mut os, Address::from(0x1000)).unwrap();
+        let expected = host.virt_to_phys(&mut os, gpa + 0x4000).unwrap();
+        assert_eq!(page.address(), expected.address());
+        assert_eq!(page.page_size(), mem::kb(4));
+        assert!(nested.virt_to_phys(&mut os, Address::from(0x2000)).is_err());
+
+        let mut guest_virt = VirtualDma::new(os.forward_mut(), x64::ARCH, nested);
+        assert_eq!(
+            guest_virt.read_raw(Address::from(0x1000), 6).unwrap(),
+            b"nested"
+        );
+    }
+}
@@ -5,9 +5,11 @@ use crate::mem::{
     DirectTranslate, MemoryView, PhysicalMemory, VirtualDma, VirtualTranslate, VirtualTranslate2,
     VirtualTranslate3,
 };
-use crate::types::{mem, size, PageType};
+use crate::types::{mem, size, umem, PageType};
 use cglue::tuple::*;
 
+use std::mem::MaybeUninit;
+
 #[test]
 fn test_vtop() {
     let dummy_mem = DummyMemory::new(size::mb(32));
@@ -98,6 +100,109 @@ fn test_virt_page_map() {
     assert_eq!(page_map[0].1, mem::mb(2));
 }
 
+#[test]
+fn test_translate_map() {
+    let dummy_mem = DummyMemory::new(size::mb(16));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let (dtb, virt_base) = dummy_os.alloc_dtb(size::mb(2), &[]);
+    let translator = x64::new_translator(dtb);
+    let arch = x64::ARCH;
+    let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), arch, translator);
+
+    // the range extends past the end of the mapping
+    let runs = virt_mem.translate_map(virt_base..virt_base + size::mb(4));
+
+    assert_eq!(runs[0].virt, virt_base);
+    assert_eq!(runs.iter().map(|r| r.len).sum::<umem>(), mem::mb(2));
+
+    for run in runs.iter() {
+        let paddr = VirtualTranslate::virt_to_phys(&mut virt_mem, run.virt).unwrap();
+        assert_eq!(paddr.address(), run.phys);
+        assert_eq!(paddr.page_size(), run.page_size);
+    }
+
+    for w in runs.windows(2) {
+        assert!(w[0].virt + w[0].len <= w[1].virt);
+        assert!(w[0].virt + w[0].len != w[1].virt || w[0].phys + w[0].len != w[1].phys);
+    }
+}
+
+#[test]
+fn test_vtop_borrowed_buffer() {
+    let dummy_mem = DummyMemory::new(size::mb(16));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let mut buf = vec![0u8; size::kb(64)];
+    for (i, item) in buf.iter_mut().enumerate() {
+        *item = i as u8;
+    }
+    let (dtb, virt_base) = dummy_os.alloc_dtb(buf.len(), &buf);
+    let translator = x64::new_translator(dtb);
+
+    let mut tmp_buf = [MaybeUninit::new(0); size::kb(32)];
+    let mut vat = DirectTranslate::with_buffer(&mut tmp_buf);
+
+    for i in (0..buf.len()).step_by(0x1000) {
+        let virt_base = virt_base + i;
+        let vtop = vat
+            .virt_to_phys(dummy_os.as_mut(), &translator, virt_base)
+            .ok()
+            .map(|paddr| paddr.address());
+        assert_eq!(vtop, dummy_os.vtop(dtb, virt_base));
+    }
+
+    let mut virt_mem = VirtualDma::with_vat(dummy_os.forward_mut(), x64::ARCH, translator, vat);
+
+    let mut out = vec![0u8; buf.len()];
+    virt_mem.read_into(virt_base, &mut out[..]).unwrap();
+    assert_eq!(buf, out);
+}
+
+#[test]
+fn test_virt_rw_scratch() {
+    let dummy_mem = DummyMemory::new(size::mb(16));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let mut buf = vec![0u8; size::kb(64)];
+    for (i, item) in buf.iter_mut().enumerate() {
+        *item = i as u8;
+    }
+    let (dtb, virt_base) = dummy_os.alloc_dtb(buf.len(), &buf);
+    let translator = x64::new_translator(dtb);
+
+    let mut tmp_buf = [MaybeUninit::new(0); size::kb(32)];
+    let vat = DirectTranslate::with_buffer(&mut tmp_buf);
+
+    // only fits a few translations, the reads are split into multiple batches
+    let mut scratch = [MaybeUninit::new(0); 256];
+    let mut virt_mem = VirtualDma::with_scratch(
+        dummy_os.forward_mut(),
+        x64::ARCH,
+        translator,
+        vat,
+        &mut scratch,
+    );
+
+    let mut out = vec![0u8; buf.len()];
+    virt_mem
+        .read_into(virt_base + 0x10usize, &mut out[..0x8000])
+        .unwrap();
+    assert_eq!(&buf[0x10..0x8010], &out[..0x8000]);
+
+    let input = vec![0xau8; buf.len()];
+    virt_mem.write(virt_base, &input[..]).unwrap();
+    virt_mem.read_into(virt_base, &mut out[..]).unwrap();
+    assert_eq!(input, out);
+
+    let mut scratch = [MaybeUninit::new(0); 1];
+    let mut virt_mem = VirtualDma::with_scratch(
+        dummy_os.forward_mut(),
+        x64::ARCH,
+        translator,
+        DirectTranslate::new(),
+        &mut scratch,
+    );
+    assert!(virt_mem.read_into(virt_base, &mut out[..]).is_err());
+}
+
 #[test]
 fn test_virt_read_small() {
     let dummy_mem = DummyMemory::new(size::mb(2));
@@ -0,0 +1,447 @@
+/*!
+Change monitoring on top of the OS abstraction.
+
+A [`Monitor`] periodically polls an OS and reports what changed since the last poll:
+
+* processes that were started or have exited,
+* modules that were loaded into or unloaded from watched processes,
+* watched memory regions whose contents were modified,
+* physical pages reported as modified by the connector, if it implements
+  [`PhysicalMemoryEvents`].
+
+The first poll only records the initial state and does not produce events. Monitors can be
+polled manually with [`Monitor::poll`], or moved to a background thread with
+[`Monitor::spawn`], which delivers the events over a channel.
+
+Since every change is detected by comparing snapshots, changes that are reverted between two
+polls are not reported. The poll interval is a tradeoff between the load put on the target
+(and the connector) and the likelihood of missing short lived changes.
+
+# Examples
+
+```
+use memflow::dummy::{DummyMemory, DummyOs};
+use memflow::monitor::{Monitor, MonitorEvent};
+use memflow::types::size;
+
+use std::time::Duration;
+
+let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+os.alloc_process(size::mb(1), &[]);
+
+let mut monitor = Monitor::new(os).interval(Duration::from_millis(10));
+monitor.poll().unwrap();
+
+monitor.os_mut().alloc_process(size::mb(1), &[]);
+
+let handle = monitor.spawn();
+let event = handle.events().recv().unwrap();
+assert!(matches!(event, MonitorEvent::ProcessStarted(_)));
+handle.stop();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, PartialResultExt, Result};
+use crate::mem::{MemoryView, PhysicalMemoryEvents};
+use crate::os::{ModuleInfo, Os, OsInner, Pid, Process, ProcessInfo};
+use crate::types::Address;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The default interval between two polls of a spawned monitor.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change reported by a [`Monitor`].
+#[derive(Clone, Debug)]
+pub enum MonitorEvent {
+    ProcessStarted(ProcessInfo),
+    ProcessExited(ProcessInfo),
+    ModuleLoaded {
+        pid: Pid,
+        module: ModuleInfo,
+    },
+    ModuleUnloaded {
+        pid: Pid,
+        module: ModuleInfo,
+    },
+    /// A watched region was modified.
+    ///
+    /// `address` and `data` cover the bytes from the first to the last modified byte.
+    RegionModified {
+        pid: Pid,
+        address: Address,
+        data: Vec<u8>,
+    },
+    /// Physical pages were modified, as reported by the source set with
+    /// [`Monitor::page_events`].
+    PagesModified(Vec<Address>),
+    /// Polling failed, a spawned monitor keeps polling afterwards.
+    PollFailed(Error),
+}
+
+struct WatchedRegion {
+    pid: Pid,
+    address: Address,
+    size: usize,
+    last: Option<Vec<u8>>,
+}
+
+/// Polls an OS for changes.
+pub struct Monitor<T> {
+    os: T,
+    interval: Duration,
+    track_processes: bool,
+    processes: Option<BTreeMap<Address, ProcessInfo>>,
+    modules: BTreeMap<Pid, Option<BTreeMap<Address, ModuleInfo>>>,
+    regions: Vec<WatchedRegion>,
+    page_events: Option<(Box<dyn PhysicalMemoryEvents>, bool)>,
+}
+
+impl<T: Os> Monitor<T> {
+    /// Creates a new monitor which tracks processes.
+    pub fn new(os: T) -> Self {
+        Self {
+            os,
+            interval: DEFAULT_INTERVAL,
+            track_processes: true,
+            processes: None,
+            modules: BTreeMap::new(),
+            regions: vec![],
+            page_events: None,
+        }
+    }
+
+    /// Sets the interval between two polls of a spawned monitor.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Enables or disables tracking of started and exited processes.
+    pub fn processes(mut self, track: bool) -> Self {
+        self.track_processes = track;
+        self
+    }
+
+    /// Tracks loaded and unloaded modules of a process.
+    pub fn watch_modules(mut self, pid: Pid) -> Self {
+        self.modules.insert(pid, None);
+        self
+    }
+
+    /// Tracks modifications of `size` bytes at `address` in a process.
+    pub fn watch_region(mut self, pid: Pid, address: Address, size: usize) -> Self {
+        self.regions.push(WatchedRegion {
+            pid,
+            address,
+            size,
+            last: None,
+        });
+        self
+    }
+
+    /// Reports the physical pages `events` notifies about.
+    ///
+    /// Tracking is started on the first poll. The source is usually the connector of the OS,
+    /// or a cache wrapping it, in which case the modified pages are also invalidated.
+    pub fn page_events(mut self, events: impl PhysicalMemoryEvents + 'static) -> Self {
+        self.page_events = Some((Box::new(events), false));
+        self
+    }
+
+    /// Returns the monitored OS.
+    pub fn os_mut(&mut self) -> &mut T {
+        &mut self.os
+    }
+
+    /// Consumes the monitor and returns the monitored OS.
+    pub fn into_inner(self) -> T {
+        self.os
+    }
+
+    /// Compares the current state of the OS against the last poll.
+    ///
+    /// Processes that can not be opened are skipped, their modules and regions are compared
+    /// again once they can be opened.
+    pub fn poll(&mut self) -> Result<Vec<MonitorEvent>> {
+        let mut events = vec![];
+
+        if self.track_processes {
+            self.poll_processes(&mut events)?;
+        }
+
+        if let Some((source, started)) = self.page_events.as_mut() {
+            if *started {
+                let mut pages = vec![];
+                source.modified_pages((&mut pages).into())?;
+                if !pages.is_empty() {
+                    events.push(MonitorEvent::PagesModified(pages));
+                }
+            } else {
+                source.start_tracking()?;
+                *started = true;
+            }
+        }
+
+        for (&pid, last) in self.modules.iter_mut() {
+            let modules = match self
+                .os
+                .process_by_pid(pid)
+                .and_then(|mut proc| proc.module_list())
+            {
+                Ok(modules) => modules
+                    .into_iter()
+                    .map(|m| (m.base, m))
+                    .collect::<BTreeMap<_, _>>(),
+                Err(_) => continue,
+            };
+
+            if let Some(last) = last {
+                diff(last, &modules, |state, module| {
+                    events.push(if state {
+                        MonitorEvent::ModuleLoaded { pid, module }
+                    } else {
+                        MonitorEvent::ModuleUnloaded { pid, module }
+                    })
+                });
+            }
+
+            *last = Some(modules);
+        }
+
+        for region in self.regions.iter_mut() {
+            let mut data = vec![0u8; region.size];
+            let read = self
+                .os
+                .process_by_pid(region.pid)
+                .and_then(|mut proc| proc.read_raw_into(region.address, &mut data).data_part());
+            if read.is_err() {
+                continue;
+            }
+
+            if let Some(last) = &region.last {
+                let first = last.iter().zip(data.iter()).position(|(a, b)| a != b);
+                let end = last.iter().zip(data.iter()).rposition(|(a, b)| a != b);
+
+                if let (Some(first), Some(end)) = (first, end) {
+                    events.push(MonitorEvent::RegionModified {
+                        pid: region.pid,
+                        address: region.address + first,
+                        data: data[first..=end].to_vec(),
+                    });
+                }
+            }
+
+            region.last = Some(data);
+        }
+
+        Ok(events)
+    }
+
+    fn poll_processes(&mut self, events: &mut Vec<MonitorEvent>) -> Result<()> {
+        let processes = self
+            .os
+            .process_info_list()?
+            .into_iter()
+            .map(|p| (p.address, p))
+            .collect::<BTreeMap<_, _>>();
+
+        if let Some(last) = &self.processes {
+            diff(last, &processes, |started, info| {
+                events.push(if started {
+                    MonitorEvent::ProcessStarted(info)
+                } else {
+                    MonitorEvent::ProcessExited(info)
+                })
+            });
+        }
+
+        self.processes = Some(processes);
+
+        Ok(())
+    }
+}
+
+impl<T: Os + Send + 'static> Monitor<T> {
+    /// Moves the monitor to a background thread that polls in the configured interval.
+    ///
+    /// The thread stops once [`MonitorHandle::stop`] is called or the handle is dropped.
+    pub fn spawn(mut self) -> MonitorHandle<T> {
+        let (sender, events) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let events = match self.poll() {
+                        Ok(events) => events,
+                        Err(err) => vec![MonitorEvent::PollFailed(err)],
+                    };
+
+                    if events.into_iter().any(|e| sender.send(e).is_err()) {
+                        break;
+                    }
+
+                    thread::sleep(self.interval);
+                }
+
+                self
+            })
+        };
+
+        MonitorHandle {
+            events,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Handle to a monitor running in a background thread.
+pub struct MonitorHandle<T> {
+    events: Receiver<MonitorEvent>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Monitor<T>>>,
+}
+
+impl<T> MonitorHandle<T> {
+    /// Returns the receiving end of the event channel.
+    pub fn events(&self) -> &Receiver<MonitorEvent> {
+        &self.events
+    }
+
+    /// Stops the monitor and returns it.
+    ///
+    /// Returns `None` if the monitor thread panicked.
+    pub fn stop(mut self) -> Option<Monitor<T>> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.take().and_then(|t| t.join().ok())
+    }
+}
+
+impl<T> Drop for MonitorHandle<T> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Calls `f` with `true` for every entry added to `new` and with `false` for every entry removed.
+fn diff<K: Ord, V: Clone>(old: &BTreeMap<K, V>, new: &BTreeMap<K, V>, mut f: impl FnMut(bool, V)) {
+    for (key, value) in new.iter() {
+        if !old.contains_key(key) {
+            f(true, value.clone());
+        }
+    }
+
+    for (key, value) in old.iter() {
+        if !new.contains_key(key) {
+            f(false, value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    #[test]
+    fn reports_changes() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(32)));
+        let pid = os.alloc_process(size::mb(1), &[]);
+        let exiting = os.alloc_process(size::mb(1), &[]);
+        let base = os.process_info_by_pid(pid).unwrap().address;
+
+        let mut monitor =
+            Monitor::new(os)
+                .watch_modules(pid)
+                .watch_region(pid, base + 0x100usize, 0x100);
+
+        assert!(monitor.poll().unwrap().is_empty());
+
+        let os = monitor.os_mut();
+        let started = os.alloc_process(size::mb(1), &[]);
+        os.free_process(exiting);
+        os.process_mut(pid).unwrap().add_modules(1, 0x1000);
+        os.process_by_pid(pid)
+            .unwrap()
+            .write(base + 0x110usize, &[1u8, 2, 3])
+            .unwrap();
+
+        let events = monitor.poll().unwrap();
+        assert_eq!(events.len(), 4);
+
+        assert!(matches!(&events[0], MonitorEvent::ProcessStarted(p) if p.pid == started));
+        assert!(matches!(&events[1], MonitorEvent::ProcessExited(p) if p.pid == exiting));
+        assert!(matches!(&events[2], MonitorEvent::ModuleLoaded { pid: p, .. } if *p == pid));
+        match &events[3] {
+            MonitorEvent::RegionModified { address, data, .. } => {
+                assert_eq!(*address, base + 0x110usize);
+                assert_eq!(data.as_slice(), &[1, 2, 3]);
+            }
+            e => panic!("unexpected event {:?}", e),
+        }
+
+        assert!(monitor.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_page_events() {
+        use crate::mem::phys_mem::events::tests::TrackedMemory;
+        use std::sync::Mutex;
+
+        // shares the tracked memory between the monitor and the test
+        #[derive(Clone)]
+        struct Shared(Arc<Mutex<TrackedMemory>>);
+
+        impl PhysicalMemoryEvents for Shared {
+            fn event_page_size(&self) -> usize {
+                self.0.lock().unwrap().event_page_size()
+            }
+
+            fn start_tracking(&mut self) -> Result<()> {
+                self.0.lock().unwrap().start_tracking()
+            }
+
+            fn stop_tracking(&mut self) -> Result<()> {
+                self.0.lock().unwrap().stop_tracking()
+            }
+
+            fn modified_pages(
+                &mut self,
+                out: crate::mem::phys_mem::ModifiedPageCallback,
+            ) -> Result<()> {
+                self.0.lock().unwrap().modified_pages(out)
+            }
+        }
+
+        let tracked = Shared(Arc::new(Mutex::new(TrackedMemory::new(size::mb(1)))));
+        let os = DummyOs::new(DummyMemory::new(size::mb(16)));
+
+        let mut monitor = Monitor::new(os)
+            .processes(false)
+            .page_events(tracked.clone());
+
+        assert!(monitor.poll().unwrap().is_empty());
+
+        tracked.0.lock().unwrap().touch(Address::from(0x3008));
+
+        match monitor.poll().unwrap().as_slice() {
+            [MonitorEvent::PagesModified(pages)] => {
+                assert_eq!(pages, &[Address::from(0x3000)])
+            }
+            e => panic!("unexpected events {:?}", e),
+        }
+
+        assert!(monitor.poll().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,645 @@
+//! Processes loaded from user-mode minidumps.
+//!
+//! Minidumps (`.dmp` files written by `MiniDumpWriteDump`, Windows Error Reporting, crash
+//! reporters like Crashpad and Breakpad, or debuggers) capture the state of a single process:
+//! its module list, its threads and a selection of its memory ranges.
+//!
+//! [`MinidumpProcess`] exposes such a dump as a [`Process`]. The memory captured by the dump is
+//! available through its [`MemoryView`] implementation, reads outside of the captured ranges
+//! fail. Modules and the process information are taken from the module list, the threads from
+//! the thread list of the dump.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::os::{MinidumpProcess, Process};
+//!
+//! let mut process = MinidumpProcess::open("crash.dmp").unwrap();
+//! println!("{} ({})", process.info().name, process.info().pid);
+//!
+//! for module in process.module_list().unwrap() {
+//!     println!("{:x} {}", module.base, module.name);
+//! }
+//!
+//! for thread in process.threads() {
+//!     println!("thread {} with teb at {:x}", thread.tid, thread.teb);
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureIdent;
+use crate::connector::{CloneFile, FileIoMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{mem_data::*, memory_view::*, MemoryMap, PhysicalMemory};
+use crate::os::process::*;
+use crate::os::*;
+use crate::types::{imem, size, umem, util::GapRemover, Address, PageType};
+
+use crate::cglue::*;
+
+use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Signature at the start of every minidump.
+pub const MINIDUMP_SIGNATURE: [u8; 4] = *b"MDMP";
+
+const HEADER_SIZE: usize = 0x20;
+const DIRECTORY_ENTRY_SIZE: usize = 0xc;
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const SYSTEM_INFO_STREAM: u32 = 7;
+const MEMORY64_LIST_STREAM: u32 = 9;
+const MISC_INFO_STREAM: u32 = 15;
+
+const THREAD_SIZE: usize = 0x30;
+const MODULE_SIZE: usize = 0x6c;
+const MEMORY_DESCRIPTOR_SIZE: usize = 0x10;
+
+const MISC1_PROCESS_ID: u32 = 1;
+
+/// Maximum size of a single stream that is read into memory.
+const MAX_STREAM_SIZE: u32 = size::mb(64) as u32;
+/// Maximum length of a module name in bytes.
+const MAX_NAME_SIZE: u32 = size::kb(64) as u32;
+
+/// A thread captured in a minidump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinidumpThread {
+    pub tid: u32,
+    pub suspend_count: u32,
+    pub priority_class: u32,
+    pub priority: u32,
+    /// Address of the thread environment block.
+    pub teb: Address,
+    /// Start of the captured stack memory.
+    pub stack_start: Address,
+    /// Size of the captured stack memory.
+    pub stack_size: umem,
+}
+
+/// A process captured in a user-mode minidump.
+pub struct MinidumpProcess<T> {
+    mem: FileIoMemory<T>,
+    info: ProcessInfo,
+    modules: Vec<ModuleInfo>,
+    threads: Vec<MinidumpThread>,
+    ranges: Vec<(Address, umem)>,
+}
+
+impl<T: Read + Seek + Write + Send> MinidumpProcess<T> {
+    /// Loads the minidump stored in `reader`.
+    pub fn new(mut reader: T) -> Result<Self> {
+        let mut header = [0u8; HEADER_SIZE];
+        read_exact_at(&mut reader, 0, &mut header)?;
+
+        if header[..4] != MINIDUMP_SIGNATURE {
+            return Err(invalid_dump("the file is not a minidump"));
+        }
+
+        let stream_count = u32_at(&header, 0x8)?;
+        let directory = read_at(
+            &mut reader,
+            u32_at(&header, 0xc)?,
+            stream_count.saturating_mul(DIRECTORY_ENTRY_SIZE as u32),
+        )?;
+
+        let mut arch = ArchitectureIdent::X86(64, false);
+        let mut pid = 0;
+        let mut modules = vec![];
+        let mut threads = vec![];
+        let mut ranges = vec![];
+
+        for entry in directory.chunks_exact(DIRECTORY_ENTRY_SIZE) {
+            let stream_type = u32_at(entry, 0x0)?;
+            let stream = (u32_at(entry, 0x8)?, u32_at(entry, 0x4)?);
+
+            match stream_type {
+                SYSTEM_INFO_STREAM => arch = read_arch(&mut reader, stream)?,
+                MISC_INFO_STREAM => pid = read_pid(&mut reader, stream)?.unwrap_or(pid),
+                MODULE_LIST_STREAM => modules = read_modules(&mut reader, stream)?,
+                THREAD_LIST_STREAM => threads = read_threads(&mut reader, stream)?,
+                MEMORY_LIST_STREAM => ranges.extend(read_memory_list(&mut reader, stream)?),
+                MEMORY64_LIST_STREAM => ranges.extend(read_memory64_list(&mut reader, stream)?),
+                _ => {}
+            }
+        }
+
+        for module in modules.iter_mut() {
+            module.arch = arch;
+        }
+
+        let (name, path) = modules
+            .first()
+            .map(|m| (m.name.clone(), m.path.clone()))
+            .unwrap_or_else(|| ("".into(), "".into()));
+
+        let info = ProcessInfo {
+            address: Address::NULL,
+            pid,
+            state: ProcessState::Unknown,
+            name,
+            path,
+            command_line: "".into(),
+            sys_arch: arch,
+            proc_arch: arch,
+        };
+
+        // (virtual address, file offset, size), overlapping ranges are dropped
+        ranges.sort_unstable_by_key(|&(addr, _, _)| addr);
+        let mut mem_map = MemoryMap::new();
+        let mut mapped = vec![];
+        let mut end = Address::NULL;
+        for (addr, file_off, size) in ranges {
+            if size > 0 && (mapped.is_empty() || addr >= end) {
+                mem_map.push_remap(addr, size, file_off);
+                mapped.push((addr, size));
+                end = addr + size;
+            }
+        }
+
+        Ok(Self {
+            mem: FileIoMemory::try_with_reader(reader, mem_map)?,
+            info,
+            modules,
+            threads,
+            ranges: mapped,
+        })
+    }
+
+    /// Returns the threads captured in the dump.
+    pub fn threads(&self) -> &[MinidumpThread] {
+        &self.threads
+    }
+
+    /// Returns the captured memory ranges as (address, size) pairs, sorted by address.
+    pub fn memory_ranges(&self) -> &[(Address, umem)] {
+        &self.ranges
+    }
+}
+
+impl MinidumpProcess<CloneFile> {
+    /// Opens a minidump file.
+    pub fn open<P: AsRef<::std::path::Path>>(path: P) -> Result<Self> {
+        let file = ::std::fs::File::open(path).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to open the minidump: {}", err))
+        })?;
+        Self::new(file.into())
+    }
+}
+
+fn invalid_dump(msg: &str) -> Error {
+    Error(ErrorOrigin::OsLayer, ErrorKind::Encoding).log_error(msg)
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> Result<u16> {
+    buf.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_dump("truncated minidump stream"))
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> Result<u32> {
+    buf.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_dump("truncated minidump stream"))
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> Result<u64> {
+    buf.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid_dump("truncated minidump stream"))
+}
+
+fn read_exact_at<R: Read + Seek>(reader: &mut R, rva: u64, out: &mut [u8]) -> Result<()> {
+    reader
+        .seek(SeekFrom::Start(rva))
+        .and_then(|_| reader.read_exact(out))
+        .map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile)
+                .log_error(format!("unable to read the minidump: {}", err))
+        })
+}
+
+fn read_at<R: Read + Seek>(reader: &mut R, rva: u32, size: u32) -> Result<Vec<u8>> {
+    if size > MAX_STREAM_SIZE {
+        return Err(invalid_dump("minidump stream exceeds the maximum size"));
+    }
+
+    let mut buf = vec![0u8; size as usize];
+    read_exact_at(reader, rva as u64, &mut buf)?;
+    Ok(buf)
+}
+
+/// Reads a list stream, returning the bytes of its `count` entries of `entry_size` bytes.
+fn read_list<R: Read + Seek>(
+    reader: &mut R,
+    (rva, size): (u32, u32),
+    header_size: usize,
+    entry_size: usize,
+) -> Result<(Vec<u8>, usize)> {
+    let stream = read_at(reader, rva, size)?;
+    let count = if header_size >= 8 {
+        u64_at(&stream, 0)? as usize
+    } else {
+        u32_at(&stream, 0)? as usize
+    };
+
+    // the count may be followed by padding
+    let entries = stream.len().saturating_sub(header_size) / entry_size;
+    if count > entries {
+        return Err(invalid_dump("minidump list exceeds its stream"));
+    }
+
+    Ok((stream, count))
+}
+
+fn read_arch<R: Read + Seek>(reader: &mut R, (rva, size): (u32, u32)) -> Result<ArchitectureIdent> {
+    let stream = read_at(reader, rva, size)?;
+    match u16_at(&stream, 0)? {
+        0 => Ok(ArchitectureIdent::X86(32, false)),
+        9 => Ok(ArchitectureIdent::X86(64, false)),
+        12 => Ok(ArchitectureIdent::AArch64(size::kb(4))),
+        arch => Err(
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArchitecture).log_error(format!(
+                "unsupported minidump processor architecture {}",
+                arch
+            )),
+        ),
+    }
+}
+
+fn read_pid<R: Read + Seek>(reader: &mut R, (rva, size): (u32, u32)) -> Result<Option<Pid>> {
+    let stream = read_at(reader, rva, size)?;
+    if u32_at(&stream, 0x4)? & MISC1_PROCESS_ID != 0 {
+        u32_at(&stream, 0x8).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_string<R: Read + Seek>(reader: &mut R, rva: u32) -> Result<String> {
+    let len = u32_at(&read_at(reader, rva, 4)?, 0)?;
+    if len > MAX_NAME_SIZE {
+        return Err(invalid_dump("minidump string exceeds the maximum size"));
+    }
+
+    let utf16 = read_at(reader, rva + 4, len)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    Ok(String::from_utf16_lossy(&utf16))
+}
+
+fn read_modules<R: Read + Seek>(reader: &mut R, stream: (u32, u32)) -> Result<Vec<ModuleInfo>> {
+    let (list, count) = read_list(reader, stream, 4, MODULE_SIZE)?;
+
+    (0..count)
+        .map(|i| {
+            let module = &list[4 + i * MODULE_SIZE..4 + (i + 1) * MODULE_SIZE];
+            let base = Address::from(u64_at(module, 0x0)?);
+            let path = read_string(reader, u32_at(module, 0x14)?)?;
+            let name = path.rsplit(&['\\', '/'][..]).next().unwrap_or_default();
+
+            Ok(ModuleInfo {
+                // modules are identified by their base address
+                address: base,
+                parent_process: Address::NULL,
+                base,
+                size: u32_at(module, 0x8)? as umem,
+                name: name.into(),
+                path: path.as_str().into(),
+                arch: ArchitectureIdent::X86(64, false),
+            })
+        })
+        .collect()
+}
+
+fn read_threads<R: Read + Seek>(reader: &mut R, stream: (u32, u32)) -> Result<Vec<MinidumpThread>> {
+    let (list, count) = read_list(reader, stream, 4, THREAD_SIZE)?;
+
+    (0..count)
+        .map(|i| {
+            let thread = &list[4 + i * THREAD_SIZE..4 + (i + 1) * THREAD_SIZE];
+            Ok(MinidumpThread {
+                tid: u32_at(thread, 0x0)?,
+                suspend_count: u32_at(thread, 0x4)?,
+                priority_class: u32_at(thread, 0x8)?,
+                priority: u32_at(thread, 0xc)?,
+                teb: u64_at(thread, 0x10)?.into(),
+                stack_start: u64_at(thread, 0x18)?.into(),
+                stack_size: u32_at(thread, 0x20)? as umem,
+            })
+        })
+        .collect()
+}
+
+/// Reads the ranges of a memory list as (address, file offset, size).
+fn read_memory_list<R: Read + Seek>(
+    reader: &mut R,
+    stream: (u32, u32),
+) -> Result<Vec<(Address, Address, umem)>> {
+    let (list, count) = read_list(reader, stream, 4, MEMORY_DESCRIPTOR_SIZE)?;
+
+    (0..count)
+        .map(|i| {
+            let desc = 4 + i * MEMORY_DESCRIPTOR_SIZE;
+            Ok((
+                u64_at(&list, desc)?.into(),
+                (u32_at(&list, desc + 0xc)? as umem).into(),
+                u32_at(&list, desc + 0x8)? as umem,
+            ))
+        })
+        .collect()
+}
+
+/// Reads the ranges of a 64-bit memory list, their data is stored back to back.
+fn read_memory64_list<R: Read + Seek>(
+    reader: &mut R,
+    stream: (u32, u32),
+) -> Result<Vec<(Address, Address, umem)>> {
+    let (list, count) = read_list(reader, stream, 0x10, MEMORY_DESCRIPTOR_SIZE)?;
+    let mut file_off = u64_at(&list, 0x8)? as umem;
+
+    (0..count)
+        .map(|i| {
+            let desc = 0x10 + i * MEMORY_DESCRIPTOR_SIZE;
+            let size = u64_at(&list, desc + 0x8)? as umem;
+            let range = (u64_at(&list, desc)?.into(), file_off.into(), size);
+            file_off = file_off.saturating_add(size);
+            Ok(range)
+        })
+        .collect()
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(
+    MinidumpProcess<T: Read + Seek + Write + Send>,
+    crate::plugins::ProcessInstance,
+    {}
+);
+
+impl<T: Read + Seek + Write + Send> Process for MinidumpProcess<T> {
+    /// Minidumps do not record whether the process is still alive.
+    fn state(&mut self) -> ProcessState {
+        ProcessState::Unknown
+    }
+
+    fn module_address_list_callback(
+        &mut self,
+        target_arch: Option<&ArchitectureIdent>,
+        callback: ModuleAddressCallback,
+    ) -> Result<()> {
+        self.modules
+            .iter()
+            .filter(|m| target_arch.is_none() || Some(&m.arch) == target_arch)
+            .map(|m| ModuleAddressInfo {
+                address: m.address,
+                arch: m.arch,
+            })
+            .feed_into(callback);
+        Ok(())
+    }
+
+    fn module_by_address(
+        &mut self,
+        address: Address,
+        architecture: ArchitectureIdent,
+    ) -> Result<ModuleInfo> {
+        self.modules
+            .iter()
+            .find(|m| m.address == address && m.arch == architecture)
+            .cloned()
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    /// The main executable is the first module of the module list.
+    fn primary_module_address(&mut self) -> Result<Address> {
+        self.modules
+            .first()
+            .map(|m| m.address)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn module_import_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ImportCallback,
+    ) -> Result<()> {
+        crate::os::util::module_import_list_callback(self, info, callback)
+    }
+
+    fn module_export_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ExportCallback,
+    ) -> Result<()> {
+        crate::os::util::module_export_list_callback(self, info, callback)
+    }
+
+    fn module_section_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: SectionCallback,
+    ) -> Result<()> {
+        crate::os::util::module_section_list_callback(self, info, callback)
+    }
+
+    fn info(&self) -> &ProcessInfo {
+        &self.info
+    }
+
+    /// Returns the captured memory ranges.
+    fn mapped_mem_range(
+        &mut self,
+        gap_size: imem,
+        start: Address,
+        end: Address,
+        out: MemoryRangeCallback,
+    ) {
+        GapRemover::new(out, gap_size, start, end).extend(
+            self.ranges
+                .iter()
+                .map(|&(addr, size)| CTup3(addr, size, PageType::UNKNOWN)),
+        )
+    }
+}
+
+impl<T: Read + Seek + Write + Send> MemoryView for MinidumpProcess<T> {
+    fn read_raw_iter(&mut self, MemOps { inp, out, out_fail }: ReadRawMemOps) -> Result<()> {
+        let inp = inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
+        let limits = self.mem.metadata().batch_limits();
+
+        MemOps::with_raw_chunked(
+            inp,
+            out,
+            out_fail,
+            limits,
+            |CTup3(_, _, data)| data.len(),
+            |data| self.mem.phys_read_raw_iter(data),
+        )
+    }
+
+    fn write_raw_iter(&mut self, _data: WriteRawMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::ReadOnly)
+            .log_warn("minidumps can not be written to"))
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        let meta = self.mem.metadata();
+        MemoryViewMetadata {
+            max_address: meta.max_address,
+            real_size: meta.real_size,
+            readonly: true,
+            arch_bits: self.info.proc_arch.into_obj().bits(),
+            little_endian: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    /// Assembles a minidump, the header is written by `finish`.
+    struct Builder {
+        data: Vec<u8>,
+        streams: Vec<(u32, u32, u32)>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            Self {
+                data: vec![0; HEADER_SIZE],
+                streams: vec![],
+            }
+        }
+
+        fn append(&mut self, bytes: &[u8]) -> u32 {
+            let rva = self.data.len() as u32;
+            self.data.extend_from_slice(bytes);
+            rva
+        }
+
+        fn stream(&mut self, stream_type: u32, bytes: &[u8]) {
+            let rva = self.append(bytes);
+            self.streams.push((stream_type, bytes.len() as u32, rva));
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            let directory = self.data.len() as u32;
+            let count = self.streams.len() as u32;
+            for &(stream_type, size, rva) in self.streams.iter() {
+                for value in [stream_type, size, rva].iter() {
+                    self.data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+
+            self.data[..4].copy_from_slice(&MINIDUMP_SIGNATURE);
+            self.data[8..12].copy_from_slice(&count.to_le_bytes());
+            self.data[12..16].copy_from_slice(&directory.to_le_bytes());
+            self.data
+        }
+    }
+
+    fn minidump() -> Vec<u8> {
+        let mut dump = Builder::new();
+
+        let mut system_info = vec![0u8; 0x38];
+        system_info[..2].copy_from_slice(&9u16.to_le_bytes());
+        dump.stream(SYSTEM_INFO_STREAM, &system_info);
+
+        let mut misc_info = vec![0u8; 0x18];
+        misc_info[..4].copy_from_slice(&0x18u32.to_le_bytes());
+        misc_info[4..8].copy_from_slice(&MISC1_PROCESS_ID.to_le_bytes());
+        misc_info[8..12].copy_from_slice(&1234u32.to_le_bytes());
+        dump.stream(MISC_INFO_STREAM, &misc_info);
+
+        let path = "C:\\app\\app.exe"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let mut string = (path.len() as u32).to_le_bytes().to_vec();
+        string.extend_from_slice(&path);
+        let name_rva = dump.append(&string);
+
+        let mut modules = 1u32.to_le_bytes().to_vec();
+        let mut module = vec![0u8; MODULE_SIZE];
+        module[..8].copy_from_slice(&0x1_4000_0000u64.to_le_bytes());
+        module[8..12].copy_from_slice(&0x2000u32.to_le_bytes());
+        module[0x14..0x18].copy_from_slice(&name_rva.to_le_bytes());
+        modules.extend_from_slice(&module);
+        dump.stream(MODULE_LIST_STREAM, &modules);
+
+        let stack_rva = dump.append(&[0x11; 0x100]);
+        let mut threads = 1u32.to_le_bytes().to_vec();
+        let mut thread = vec![0u8; THREAD_SIZE];
+        thread[..4].copy_from_slice(&42u32.to_le_bytes());
+        thread[0x10..0x18].copy_from_slice(&0x7ff0_0000u64.to_le_bytes());
+        thread[0x18..0x20].copy_from_slice(&0x5_0000u64.to_le_bytes());
+        thread[0x20..0x24].copy_from_slice(&0x100u32.to_le_bytes());
+        thread[0x24..0x28].copy_from_slice(&stack_rva.to_le_bytes());
+        threads.extend_from_slice(&thread);
+        dump.stream(THREAD_LIST_STREAM, &threads);
+
+        let mut memory = 1u32.to_le_bytes().to_vec();
+        memory.extend_from_slice(&0x5_0000u64.to_le_bytes());
+        memory.extend_from_slice(&0x100u32.to_le_bytes());
+        memory.extend_from_slice(&stack_rva.to_le_bytes());
+        dump.stream(MEMORY_LIST_STREAM, &memory);
+
+        let image_rva = dump.append(&[0x22; 0x2000]) as u64;
+        let mut memory64 = 1u64.to_le_bytes().to_vec();
+        memory64.extend_from_slice(&image_rva.to_le_bytes());
+        memory64.extend_from_slice(&0x1_4000_0000u64.to_le_bytes());
+        memory64.extend_from_slice(&0x2000u64.to_le_bytes());
+        dump.stream(MEMORY64_LIST_STREAM, &memory64);
+
+        dump.finish()
+    }
+
+    #[test]
+    fn load_minidump() {
+        let mut process = MinidumpProcess::new(Cursor::new(minidump())).unwrap();
+
+        assert_eq!(process.info().pid, 1234);
+        assert_eq!(process.info().name.as_ref(), "app.exe");
+        assert_eq!(process.info().path.as_ref(), "C:\\app\\app.exe");
+        assert_eq!(process.info().proc_arch, ArchitectureIdent::X86(64, false));
+
+        let primary = process.primary_module().unwrap();
+        assert_eq!(primary.base, Address::from(0x1_4000_0000u64));
+        assert_eq!(primary.size, 0x2000);
+
+        let thread = process.threads()[0].clone();
+        assert_eq!(thread.tid, 42);
+        assert_eq!(thread.teb, Address::from(0x7ff0_0000u64));
+        assert_eq!(thread.stack_size, 0x100);
+
+        assert_eq!(
+            process.memory_ranges(),
+            &[
+                (Address::from(0x5_0000u64), 0x100),
+                (Address::from(0x1_4000_0000u64), 0x2000)
+            ]
+        );
+        assert_eq!(process.mapped_mem_vec(0).len(), 2);
+
+        assert_eq!(
+            process.read::<u32>(thread.stack_start).unwrap(),
+            0x1111_1111
+        );
+        assert_eq!(
+            process
+                .read::<u64>(Address::from(0x1_4000_1ff8u64))
+                .unwrap(),
+            0x2222_2222_2222_2222
+        );
+        assert!(process.read::<u64>(Address::from(0x5_00fcu64)).is_err());
+        assert!(process.write(primary.base, &0u32).is_err());
+    }
+}
@@ -16,7 +16,11 @@
 //! flags, and other things concerned with individual modules.
 
 pub mod keyboard;
+#[cfg(feature = "std")]
+pub mod minidump;
 pub mod module;
+#[cfg(feature = "std")]
+pub mod parallel;
 pub mod process;
 pub mod root;
 pub mod util;
@@ -28,6 +32,9 @@ pub use module::{
     ModuleAddressInfo, ModuleInfo, ModuleInfoCallback, SectionCallback, SectionInfo,
 };
 
+#[cfg(feature = "std")]
+pub use minidump::{MinidumpProcess, MinidumpThread};
+
 pub use process::{Pid, Process, ProcessInfo, ProcessInfoCallback, ProcessState};
 
 pub use root::{Os, OsInfo, OsInner};
@@ -0,0 +1,202 @@
+//! Parallel process enumeration.
+//!
+//! Walking the process list and gathering information for every process is latency bound on
+//! most transports. The functions in this module distribute the per-process work over multiple
+//! cloned OS handles, each running on its own thread. This is most useful for connectors that
+//! are able to service multiple requests at once (e.g. multi-channel DMA devices).
+//!
+//! The serial functions of [`OsInner`] remain the default, the parallel variants are opt-in.
+
+use super::{ModuleInfo, Os, OsInner, Process, ProcessInfo};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+use std::prelude::v1::*;
+use std::thread;
+
+/// Information gathered for a single process by [`process_inventory_parallel`].
+#[derive(Clone, Debug)]
+pub struct ProcessInventory {
+    /// General process information.
+    pub info: ProcessInfo,
+    /// All modules of the process.
+    pub modules: Result<Vec<ModuleInfo>>,
+    /// The primary module of the process.
+    pub primary_module: Result<ModuleInfo>,
+}
+
+/// Retrieves a process list, gathering per-process information on `threads` OS handles.
+///
+/// The process addresses are walked on `os`, the remaining handles are cloned from it.
+/// Processes that only returned partial data are skipped, and the list is truncated at the first
+/// process that failed to be read, matching the behaviour of [`OsInner::process_info_list`].
+///
+/// # Examples
+///
+/// ```
+/// use memflow::dummy::{DummyMemory, DummyOs};
+/// use memflow::os::parallel::process_info_list_parallel;
+/// use memflow::types::size;
+///
+/// let mut os = DummyOs::new(DummyMemory::new(size::mb(64)));
+/// for _ in 0..4 {
+///     os.alloc_process(size::mb(1), &[]);
+/// }
+///
+/// let list = process_info_list_parallel(&mut os, 2).unwrap();
+/// assert_eq!(list.len(), 4);
+/// ```
+pub fn process_info_list_parallel<T: Os + Clone + 'static>(
+    os: &mut T,
+    threads: usize,
+) -> Result<Vec<ProcessInfo>> {
+    let addresses = os.process_address_list()?;
+
+    let results = run_parallel(os, addresses, threads, |os, address| {
+        os.process_info_by_address(address)
+    })?;
+
+    Ok(results
+        .into_iter()
+        .filter(|r| !matches!(r, Err(Error(_, ErrorKind::PartialData))))
+        .take_while(Result::is_ok)
+        .map(Result::unwrap)
+        .collect())
+}
+
+/// Retrieves a process list together with the module list and primary module of every process.
+///
+/// The work is distributed the same way as in [`process_info_list_parallel`]. Errors while
+/// gathering modules do not abort the enumeration, they are stored in the returned
+/// [`ProcessInventory`] instead.
+pub fn process_inventory_parallel<T: Os + Clone + 'static>(
+    os: &mut T,
+    threads: usize,
+) -> Result<Vec<ProcessInventory>> {
+    let infos = process_info_list_parallel(os, threads)?;
+
+    let results = run_parallel(os, infos, threads, |os, info| {
+        let mut process = os.process_by_info(info.clone())?;
+        let modules = process.module_list();
+        let primary_module = process.primary_module();
+        Ok(ProcessInventory {
+            info,
+            modules,
+            primary_module,
+        })
+    })?;
+
+    Ok(results.into_iter().filter_map(Result::ok).collect())
+}
+
+/// Splits `items` into `threads` contiguous parts and maps every item with `func`.
+///
+/// The first part is processed on the calling thread using `os` directly, while all other
+/// parts are processed on spawned threads using clones of `os`. The results are returned in
+/// the same order as the input.
+///
+/// If any of the spawned threads panicked an error is returned instead of the partial results.
+fn run_parallel<T, I, O, F>(
+    os: &mut T,
+    items: Vec<I>,
+    threads: usize,
+    func: F,
+) -> Result<Vec<Result<O>>>
+where
+    T: Os + Clone + 'static,
+    I: Send + 'static,
+    O: Send + 'static,
+    F: Fn(&mut T, I) -> Result<O> + Clone + Send + 'static,
+{
+    let threads = std::cmp::max(threads, 1);
+    let chunk_size = std::cmp::max((items.len() + threads - 1) / threads, 1);
+
+    let mut items = items.into_iter();
+    let first = items.by_ref().take(chunk_size).collect::<Vec<_>>();
+
+    let mut handles = vec![];
+    loop {
+        let chunk = items.by_ref().take(chunk_size).collect::<Vec<_>>();
+        if chunk.is_empty() {
+            break;
+        }
+
+        let mut os = os.clone();
+        let func = func.clone();
+        handles.push(thread::spawn(move || {
+            chunk
+                .into_iter()
+                .map(|item| func(&mut os, item))
+                .collect::<Vec<_>>()
+        }));
+    }
+
+    let mut results = first
+        .into_iter()
+        .map(|item| func(os, item))
+        .collect::<Vec<_>>();
+
+    // join every thread before bailing out so no worker outlives the call
+    let mut panicked = false;
+    for handle in handles {
+        match handle.join() {
+            Ok(res) => results.extend(res),
+            Err(_) => panicked = true,
+        }
+    }
+
+    if panicked {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::Unknown)
+            .log_error("process enumeration thread panicked"))
+    } else {
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    #[test]
+    fn parallel_matches_serial() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(32)));
+        for _ in 0..7 {
+            os.alloc_process_with_module(size::mb(1), &[]);
+        }
+
+        let serial = os.process_info_list().unwrap();
+
+        for &threads in &[1, 3, 16] {
+            let parallel = process_info_list_parallel(&mut os, threads).unwrap();
+            assert_eq!(
+                serial.iter().map(|p| p.pid).collect::<Vec<_>>(),
+                parallel.iter().map(|p| p.pid).collect::<Vec<_>>()
+            );
+        }
+
+        let inventory = process_inventory_parallel(&mut os, 4).unwrap();
+        assert_eq!(inventory.len(), serial.len());
+        for (inv, info) in inventory.iter().zip(serial.iter()) {
+            assert_eq!(inv.info.pid, info.pid);
+            assert_eq!(inv.modules.as_ref().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn worker_panic_is_reported() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(4)));
+
+        let results = run_parallel(&mut os, (0..4).collect(), 2, |_, i: usize| {
+            if i == 3 {
+                panic!("worker failure");
+            }
+            Ok(i)
+        });
+        assert_eq!(
+            results.unwrap_err(),
+            Error(ErrorOrigin::OsLayer, ErrorKind::Unknown)
+        );
+    }
+}
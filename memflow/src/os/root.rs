@@ -73,6 +73,24 @@ pub trait OsInner<'a>: Send {
         Ok(ret)
     }
 
+    /// Retrieves a process list, stopping early once `token` is cancelled
+    ///
+    /// Returns [`ErrorKind::Cancelled`] if the enumeration was cancelled.
+    #[skip_func]
+    fn process_info_list_cancellable(
+        &mut self,
+        token: &CancellationToken,
+    ) -> Result<Vec<ProcessInfo>> {
+        let mut ret = vec![];
+        let mut callback = token.guard(|info: ProcessInfo| {
+            ret.push(info);
+            true
+        });
+        self.process_info_list_callback((&mut callback).into())?;
+        token.check()?;
+        Ok(ret)
+    }
+
     /// Find process information by its internal address
     fn process_info_by_address(&mut self, address: Address) -> Result<ProcessInfo>;
 
@@ -216,13 +216,21 @@ pub fn module_import_list_callback(
 pub fn module_export_list_callback(
     mem: &mut impl MemoryView,
     info: &ModuleInfo,
-    mut callback: ExportCallback,
+    callback: ExportCallback,
 ) -> Result<()> {
     let mut module_image = aligned_alloc(info.size as usize);
     let module_image = module_image.as_bytes_mut();
 
     mem.read_raw_into(info.base, module_image).data_part()?;
 
+    image_export_list_callback(module_image, callback)
+}
+
+/// Parses the exports of a module image that is already available locally.
+///
+/// Unlike [`module_export_list_callback`] this also works for images in their on-disk layout.
+/// The image should be aligned to 8 bytes, as some of the parsers require aligned data.
+pub fn image_export_list_callback(module_image: &[u8], mut callback: ExportCallback) -> Result<()> {
     fn export_call(iter: impl Iterator<Item = (umem, ReprCString)>, callback: &mut ExportCallback) {
         iter.take_while(|(offset, name)| {
             callback.call(ExportInfo {
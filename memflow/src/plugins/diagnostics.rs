@@ -0,0 +1,194 @@
+/*!
+Diagnostics of the plugin discovery.
+
+The [`Inventory`](super::Inventory) silently skips every file it can not load, which leaves
+users with nothing but a "plugin not found" error. Instead, every skipped candidate is recorded
+as a [`PluginDiagnostic`] that applications can present to the user:
+
+```
+use memflow::plugins::Inventory;
+
+let inventory = Inventory::scan();
+
+for diag in inventory.diagnostics_for("qemu") {
+    println!("{}", diag);
+}
+```
+*/
+
+use crate::error::{Error, ErrorKind, ErrorOrigin};
+
+use std::fmt;
+use std::path::PathBuf;
+use std::prelude::v1::*;
+
+/// Reason why a candidate file or plugin was not added to the inventory.
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file could not be read or is not a valid library for this platform.
+    InvalidFile(ErrorKind),
+    /// The library was built for a different architecture than the running one.
+    WrongArchitecture {
+        found: String,
+        expected: &'static str,
+    },
+    /// The library does not export any memflow plugin.
+    MissingExport,
+    /// The system loader refused to load the library.
+    UnableToLoad(String),
+    /// The plugin was built for a different version of the plugin api.
+    VersionMismatch { required: i32, found: i32 },
+    /// The layouts of the plugin interface do not match the ones of this memflow build.
+    InvalidAbi,
+    /// A plugin with the same name was added before.
+    AlreadyLoaded,
+}
+
+impl SkipReason {
+    /// Returns the [`ErrorKind`] corresponding to this reason.
+    pub fn error_kind(&self) -> ErrorKind {
+        match self {
+            SkipReason::InvalidFile(kind) => *kind,
+            SkipReason::WrongArchitecture { .. } => ErrorKind::InvalidArchitecture,
+            SkipReason::MissingExport => ErrorKind::MemflowExportsNotFound,
+            SkipReason::UnableToLoad(_) => ErrorKind::UnableToLoadLibrary,
+            SkipReason::VersionMismatch { .. } => ErrorKind::VersionMismatch,
+            SkipReason::InvalidAbi => ErrorKind::InvalidAbi,
+            SkipReason::AlreadyLoaded => ErrorKind::AlreadyExists,
+        }
+    }
+}
+
+impl From<SkipReason> for Error {
+    fn from(reason: SkipReason) -> Self {
+        Error(ErrorOrigin::Inventory, reason.error_kind())
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::InvalidFile(kind) => write!(f, "not a valid library ({})", kind.to_str()),
+            SkipReason::WrongArchitecture { found, expected } => write!(
+                f,
+                "built for {}, but {} is required. install a build for this machine",
+                found, expected
+            ),
+            SkipReason::MissingExport => write!(f, "does not contain any memflow plugin"),
+            SkipReason::UnableToLoad(err) => write!(
+                f,
+                "could not be loaded ({}), check that its dependencies are installed",
+                err
+            ),
+            SkipReason::VersionMismatch { required, found } => write!(
+                f,
+                "was built for plugin version {}, but version {} is required. rebuild the plugin against this version of memflow",
+                found, required
+            ),
+            SkipReason::InvalidAbi => write!(
+                f,
+                "has an incompatible abi. rebuild the plugin against this version of memflow"
+            ),
+            SkipReason::AlreadyLoaded => write!(
+                f,
+                "was skipped because a plugin with the same name was found earlier"
+            ),
+        }
+    }
+}
+
+/// A candidate that was skipped while scanning for plugins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PluginDiagnostic {
+    /// Path of the candidate file.
+    pub path: PathBuf,
+    /// Type of the plugin (`connector` or `os`), if the file contained a plugin export.
+    pub plugin_type: Option<&'static str>,
+    /// Name of the plugin, if the file contained a plugin export.
+    pub name: Option<String>,
+    pub reason: SkipReason,
+}
+
+impl PluginDiagnostic {
+    pub(crate) fn file(path: impl Into<PathBuf>, reason: SkipReason) -> Self {
+        Self {
+            path: path.into(),
+            plugin_type: None,
+            name: None,
+            reason,
+        }
+    }
+
+    pub(crate) fn plugin(
+        path: impl Into<PathBuf>,
+        plugin_type: &'static str,
+        name: impl Into<String>,
+        reason: SkipReason,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            plugin_type: Some(plugin_type),
+            name: Some(name.into()),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for PluginDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.plugin_type, &self.name) {
+            (Some(ty), Some(name)) => write!(
+                f,
+                "{} plugin '{}' in {:?} {}",
+                ty, name, self.path, self.reason
+            ),
+            _ => write!(f, "{:?} {}", self.path, self.reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::Inventory;
+
+    #[test]
+    fn invalid_file() {
+        let dir = std::env::temp_dir().join("memflow_plugin_diagnostics");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("libmemflow_broken.so");
+        std::fs::write(&path, b"not a library").unwrap();
+
+        let inventory = Inventory::scan_path(&dir).unwrap();
+        let diags = inventory.diagnostics_for("broken").collect::<Vec<_>>();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(
+            diags,
+            vec![&PluginDiagnostic::file(
+                path,
+                SkipReason::InvalidFile(ErrorKind::InvalidExeFile)
+            )]
+        );
+    }
+
+    #[test]
+    fn actionable_message() {
+        let diag = PluginDiagnostic::plugin(
+            "/usr/lib/memflow/libmemflow_qemu.so",
+            "connector",
+            "qemu",
+            SkipReason::VersionMismatch {
+                required: -8,
+                found: -7,
+            },
+        );
+
+        assert_eq!(diag.reason.error_kind(), ErrorKind::VersionMismatch);
+        assert_eq!(
+            diag.to_string(),
+            "connector plugin 'qemu' in \"/usr/lib/memflow/libmemflow_qemu.so\" was built for plugin version -7, but version -8 is required. rebuild the plugin against this version of memflow"
+        );
+    }
+}
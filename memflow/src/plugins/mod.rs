@@ -35,6 +35,12 @@ pub type OsInputArg = <LoadableOs as Loadable>::InputArg;
 pub mod logger;
 pub use logger::*; // TODO: restrict
 
+pub mod diagnostics;
+pub use diagnostics::{PluginDiagnostic, SkipReason};
+
+#[cfg(any(feature = "testsuite", test))]
+pub mod testsuite;
+
 pub(crate) mod util;
 pub use util::create_bare;
 
@@ -50,7 +56,7 @@ use libloading::Library;
 use once_cell::sync::OnceCell;
 
 /// Exported memflow plugins version
-pub const MEMFLOW_PLUGIN_VERSION: i32 = -8;
+pub const MEMFLOW_PLUGIN_VERSION: i32 = -9;
 
 /// Help and Target callbacks
 pub type HelpCallback<'a> = OpaqueCallback<'a, ReprCString>;
@@ -172,15 +178,24 @@ pub trait Loadable: Sized {
         library: &CArc<LibContext>,
         export: &str,
     ) -> Result<LibInstance<Self>> {
+        Self::load_with_reason(path, library, export).map_err(Error::from)
+    }
+
+    /// Same as [`load`](Self::load), but returns the reason why the plugin could not be loaded.
+    fn load_with_reason(
+        path: impl AsRef<Path>,
+        library: &CArc<LibContext>,
+        export: &str,
+    ) -> std::result::Result<LibInstance<Self>, SkipReason> {
         // find os descriptor
         let descriptor = unsafe {
             library
                 .as_ref()
                 // TODO: support loading without arc
-                .ok_or(Error(ErrorOrigin::Inventory, ErrorKind::Uninitialized))?
+                .ok_or(SkipReason::InvalidFile(ErrorKind::Uninitialized))?
                 .lib
                 .get::<*mut PluginDescriptor<Self>>(format!("{}\0", export).as_bytes())
-                .map_err(|_| Error(ErrorOrigin::Inventory, ErrorKind::MemflowExportsNotFound))?
+                .map_err(|_| SkipReason::MissingExport)?
                 .read()
         };
 
@@ -190,7 +205,10 @@ pub trait Loadable: Sized {
                 "{} has a different version. version {} required, found {}.",
                 export, MEMFLOW_PLUGIN_VERSION, descriptor.plugin_version
             );
-            Err(Error(ErrorOrigin::Inventory, ErrorKind::VersionMismatch))
+            Err(SkipReason::VersionMismatch {
+                required: MEMFLOW_PLUGIN_VERSION,
+                found: descriptor.plugin_version,
+            })
         } else if VerifyLayout::check::<Self::CInputArg>(Some(descriptor.input_layout))
             .and(VerifyLayout::check::<Self::Instance>(Some(
                 descriptor.output_layout,
@@ -204,7 +222,7 @@ pub trait Loadable: Sized {
             })
         } else {
             warn!("{} has invalid ABI.", export);
-            Err(Error(ErrorOrigin::Inventory, ErrorKind::InvalidAbi))
+            Err(SkipReason::InvalidAbi)
         }
     }
 
@@ -223,12 +241,23 @@ pub trait Loadable: Sized {
     ///
     /// It is adviced to use a provided proc macro to define a valid library.
     fn load_all(path: impl AsRef<Path>) -> Result<Vec<LibInstance<Self>>> {
+        Self::load_all_with_diagnostics(path, &mut vec![]).map_err(Error::from)
+    }
+
+    /// Same as [`load_all`](Self::load_all), but returns the reason why the library was skipped.
+    ///
+    /// Plugins of the library that fail to load are recorded in `diagnostics`.
+    ///
+    /// # Safety
+    ///
+    /// See [`load_all`](Self::load_all).
+    fn load_all_with_diagnostics(
+        path: impl AsRef<Path>,
+        diagnostics: &mut Vec<PluginDiagnostic>,
+    ) -> std::result::Result<Vec<LibInstance<Self>>, SkipReason> {
         let exports = util::find_export_by_prefix(path.as_ref(), Self::export_prefix())?;
         if exports.is_empty() {
-            return Err(Error(
-                ErrorOrigin::Inventory,
-                ErrorKind::MemflowExportsNotFound,
-            ));
+            return Err(SkipReason::MissingExport);
         }
 
         // load library
@@ -240,14 +269,28 @@ pub trait Loadable: Sized {
                     path.as_ref(),
                     err
                 );
-                Error(ErrorOrigin::Inventory, ErrorKind::UnableToLoadLibrary)
+                SkipReason::UnableToLoad(err.to_string())
             })
             .map(LibContext::from)
             .map(CArc::from)?;
 
         Ok(exports
             .into_iter()
-            .filter_map(|e| Self::load(path.as_ref(), &library, &e).ok())
+            .filter_map(
+                |e| match Self::load_with_reason(path.as_ref(), &library, &e) {
+                    Ok(lib) => Some(lib),
+                    Err(reason) => {
+                        let name = e[Self::export_prefix().len()..].to_lowercase();
+                        diagnostics.push(PluginDiagnostic::plugin(
+                            path.as_ref(),
+                            Self::plugin_type(),
+                            name,
+                            reason,
+                        ));
+                        None
+                    }
+                },
+            )
             .collect())
     }
 
@@ -285,6 +328,49 @@ pub trait Loadable: Sized {
         Ok(())
     }
 
+    /// Same as [`load_append`](Self::load_append), but records every skipped plugin in
+    /// `diagnostics` and returns the number of added plugins.
+    ///
+    /// Duplicates are skipped instead of aborting the load of the remaining plugins.
+    ///
+    /// # Safety
+    ///
+    /// See [`load_append`](Self::load_append).
+    fn load_append_with_diagnostics(
+        path: impl AsRef<Path>,
+        out: &mut Vec<LibInstance<Self>>,
+        diagnostics: &mut Vec<PluginDiagnostic>,
+    ) -> std::result::Result<usize, SkipReason> {
+        let libs = Self::load_all_with_diagnostics(path.as_ref(), diagnostics)?;
+        let mut added = 0;
+        for lib in libs.into_iter() {
+            if !lib.loader.exists(out) {
+                info!(
+                    "adding plugin '{}/{}': {:?}",
+                    Self::plugin_type(),
+                    lib.loader.ident(),
+                    path.as_ref()
+                );
+                out.push(lib);
+                added += 1;
+            } else {
+                debug!(
+                    "skipping library '{}' because it was added already: {:?}",
+                    lib.loader.ident(),
+                    path.as_ref()
+                );
+                diagnostics.push(PluginDiagnostic::plugin(
+                    path.as_ref(),
+                    Self::plugin_type(),
+                    lib.loader.ident(),
+                    SkipReason::AlreadyLoaded,
+                ));
+            }
+        }
+
+        Ok(added)
+    }
+
     /// Retrieves the help text for this plugin
     fn help(&self) -> Result<String>;
 
@@ -347,6 +433,7 @@ pub trait Loadable: Sized {
 pub struct Inventory {
     connectors: Vec<LibInstance<connector::LoadableConnector>>,
     os_layers: Vec<LibInstance<os::LoadableOs>>,
+    diagnostics: Vec<PluginDiagnostic>,
 }
 
 impl Inventory {
@@ -369,6 +456,7 @@ impl Inventory {
         let mut ret = Self {
             connectors: vec![],
             os_layers: vec![],
+            diagnostics: vec![],
         };
         ret.add_dir(dir)?;
         Ok(ret)
@@ -432,6 +520,7 @@ impl Inventory {
         let mut ret = Self {
             connectors: vec![],
             os_layers: vec![],
+            diagnostics: vec![],
         };
 
         for mut path in path_iter {
@@ -499,11 +588,58 @@ impl Inventory {
     /// Same as previous functions - compiler can not guarantee the safety of
     /// third party library implementations.
     pub fn load(&mut self, path: PathBuf) -> &mut Self {
-        Loadable::load_append(&path, &mut self.connectors).ok();
-        Loadable::load_append(&path, &mut self.os_layers).ok();
+        let mut diagnostics = vec![];
+        let connectors =
+            Loadable::load_append_with_diagnostics(&path, &mut self.connectors, &mut diagnostics);
+        let os_layers =
+            Loadable::load_append_with_diagnostics(&path, &mut self.os_layers, &mut diagnostics);
+
+        // a library only has to contain one type of plugin
+        match (connectors, os_layers) {
+            (Err(a), Err(b)) if a == b => diagnostics.push(PluginDiagnostic::file(&path, a)),
+            (Err(a), Err(b)) => {
+                diagnostics.push(PluginDiagnostic::file(&path, a));
+                diagnostics.push(PluginDiagnostic::file(&path, b));
+            }
+            (Err(SkipReason::MissingExport), Ok(_)) | (Ok(_), Err(SkipReason::MissingExport)) => {}
+            (Err(reason), Ok(_)) | (Ok(_), Err(reason)) => {
+                diagnostics.push(PluginDiagnostic::file(&path, reason))
+            }
+            (Ok(_), Ok(_)) => {}
+        }
+
+        self.diagnostics.append(&mut diagnostics);
         self
     }
 
+    /// Returns all candidates that were skipped while scanning for plugins.
+    ///
+    /// See [`diagnostics`] for details.
+    pub fn diagnostics(&self) -> &[PluginDiagnostic] {
+        &self.diagnostics
+    }
+
+    /// Returns the reasons why plugins with the given name were skipped.
+    ///
+    /// Candidates that could not be attributed to a specific plugin (e.g. libraries built for
+    /// another architecture) are included if their file name contains `name`.
+    pub fn diagnostics_for<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a PluginDiagnostic> + 'a {
+        self.diagnostics.iter().filter(move |d| match &d.name {
+            Some(n) => n == name,
+            None => {
+                d.reason != SkipReason::MissingExport
+                    && d.path
+                        .file_name()
+                        .and_then(|f| f.to_str())
+                        .map(|f| f.contains(name))
+                        .unwrap_or_default()
+            }
+        })
+    }
+
     /// Returns the names of all currently available connectors that can be used.
     pub fn available_connectors(&self) -> Vec<String> {
         self.connectors
@@ -671,7 +807,7 @@ impl Inventory {
         input: ConnectorInputArg,
         args: Option<&ConnectorArgs>,
     ) -> Result<ConnectorInstanceArcBox<'static>> {
-        Self::create_internal(&self.connectors, name, input, args)
+        self.create_internal(&self.connectors, name, input, args)
     }
 
     /// Create OS instance
@@ -714,10 +850,11 @@ impl Inventory {
         input: OsInputArg,
         args: Option<&OsArgs>,
     ) -> Result<OsInstanceArcBox<'static>> {
-        Self::create_internal(&self.os_layers, name, input, args)
+        self.create_internal(&self.os_layers, name, input, args)
     }
 
     fn create_internal<T: Loadable>(
+        &self,
         libs: &[LibInstance<T>],
         name: &str,
         input: T::InputArg,
@@ -736,6 +873,9 @@ impl Inventory {
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
+                for diag in self.diagnostics_for(name) {
+                    error!("{}", diag);
+                }
                 Error(ErrorOrigin::Inventory, ErrorKind::PluginNotFound)
             })?;
 
@@ -0,0 +1,626 @@
+/*!
+Conformance tests for connectors.
+
+Connector authors can validate their [`PhysicalMemory`] implementation with a single call:
+
+```
+use memflow::dummy::DummyMemory;
+use memflow::plugins::testsuite::TestSuite;
+use memflow::types::size;
+
+let mut mem = DummyMemory::new(size::mb(4));
+TestSuite::new().writes(true).run(&mut mem).assert_ok();
+```
+
+Loaded plugins can be tested the same way, since [`ConnectorInstanceArcBox`](super::ConnectorInstanceArcBox)
+implements [`PhysicalMemory`] as well.
+
+The suite consists of the following checks:
+
+* `metadata`: the metadata is consistent in itself and between clones.
+* `scatter`: randomized batches of reads report every requested byte exactly once, either as
+  read or as failed, and return the same data as reading each element on its own.
+* `edges`: reads crossing or beyond `max_address`, page crossing reads and empty reads.
+* `roundtrip`: randomized writes can be read back. Only run if enabled with
+  [`TestSuite::writes`], the original memory contents are restored afterwards.
+* `clones`: clones used concurrently from multiple threads return the same data.
+* `mem_map`: reads still behave after a memory map was set. This check runs last, on a clone of
+  the connector.
+
+The checks assume memory does not change while they are running, so live targets should be
+paused. The randomized checks are seeded, a failing run can be reproduced with
+[`TestSuite::seed`].
+*/
+
+use crate::cglue::*;
+use crate::mem::mem_data::*;
+use crate::mem::{PhysicalMemory, PhysicalMemoryMapping};
+use crate::types::{size, umem, Address, PhysicalAddress};
+
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use std::fmt;
+use std::prelude::v1::*;
+
+/// Result of a single byte, `None` if it could not be read.
+type Bytes = Vec<Option<u8>>;
+
+/// Outcome of a single check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckResult {
+    Passed,
+    Skipped(String),
+    Failed(String),
+}
+
+/// Outcome of a check of the suite.
+#[derive(Clone, Debug)]
+pub struct CheckOutcome {
+    pub name: &'static str,
+    pub result: CheckResult,
+}
+
+/// Outcomes of all checks of a [`TestSuite`] run.
+#[derive(Clone, Debug, Default)]
+pub struct ConformanceReport {
+    pub checks: Vec<CheckOutcome>,
+}
+
+impl ConformanceReport {
+    /// Returns `true` if no check failed.
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns all failed checks.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckOutcome> {
+        self.checks
+            .iter()
+            .filter(|c| matches!(c.result, CheckResult::Failed(_)))
+    }
+
+    /// Panics with the full report if any check failed.
+    pub fn assert_ok(&self) {
+        if !self.is_ok() {
+            panic!("connector conformance checks failed:\n{}", self);
+        }
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in self.checks.iter() {
+            match &check.result {
+                CheckResult::Passed => writeln!(f, "{}: passed", check.name)?,
+                CheckResult::Skipped(reason) => {
+                    writeln!(f, "{}: skipped ({})", check.name, reason)?
+                }
+                CheckResult::Failed(reason) => writeln!(f, "{}: FAILED: {}", check.name, reason)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+type CheckFn<T> = fn(&TestSuite, &mut T, &mut XorShiftRng) -> CheckResult;
+
+/// Configurable battery of connector conformance checks.
+#[derive(Clone, Debug)]
+pub struct TestSuite {
+    seed: u64,
+    iterations: usize,
+    batch_size: usize,
+    max_len: usize,
+    threads: usize,
+    writes: bool,
+}
+
+impl Default for TestSuite {
+    fn default() -> Self {
+        Self {
+            seed: 0x6d656d666c6f77,
+            iterations: 32,
+            batch_size: 64,
+            max_len: size::kb(8),
+            threads: 4,
+            writes: false,
+        }
+    }
+}
+
+impl TestSuite {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the seed of the randomized checks.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the number of random batches issued by each randomized check.
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Sets the maximum number of elements in a random batch.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = std::cmp::max(batch_size, 1);
+        self
+    }
+
+    /// Sets the maximum length of a single random read or write.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = std::cmp::max(max_len, 1);
+        self
+    }
+
+    /// Sets the number of threads used by the `clones` check.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Enables the `roundtrip` check, which writes to the memory of the target.
+    pub fn writes(mut self, writes: bool) -> Self {
+        self.writes = writes;
+        self
+    }
+
+    /// Runs all checks against `mem`.
+    pub fn run<T: PhysicalMemory + Clone + Send + 'static>(
+        &self,
+        mem: &mut T,
+    ) -> ConformanceReport {
+        let checks: &[(&'static str, CheckFn<T>)] = &[
+            ("metadata", check_metadata),
+            ("scatter", check_scatter),
+            ("edges", check_edges),
+            ("roundtrip", check_roundtrip),
+            ("clones", check_clones),
+            ("mem_map", check_mem_map),
+        ];
+
+        let mut rng = XorShiftRng::seed_from_u64(self.seed);
+
+        ConformanceReport {
+            checks: checks
+                .iter()
+                .map(|&(name, check)| CheckOutcome {
+                    name,
+                    result: check(self, mem, &mut rng),
+                })
+                .collect(),
+        }
+    }
+
+    /// Generates a random batch of ranges below `max_address`.
+    fn random_batch(
+        &self,
+        rng: &mut XorShiftRng,
+        mem: &impl PhysicalMemory,
+    ) -> Vec<(Address, usize)> {
+        let metadata = mem.metadata();
+        let max_address = metadata.max_address.to_umem();
+        let max_batch = std::cmp::min(self.batch_size, metadata.max_batch_size as usize);
+        let max_len = std::cmp::min(
+            self.max_len as umem,
+            metadata.max_batch_bytes / max_batch as umem,
+        ) as usize;
+
+        (0..rng.gen_range(1..=max_batch))
+            .map(|_| {
+                let addr = rng.gen_range(0..=max_address);
+                let len = rng.gen_range(1..=std::cmp::max(max_len, 1)) as umem;
+                let len = std::cmp::min(len, (max_address - addr).saturating_add(1));
+                (Address::from(addr), len as usize)
+            })
+            .collect()
+    }
+}
+
+/// Runs all checks with the default configuration.
+pub fn run_conformance<T: PhysicalMemory + Clone + Send + 'static>(
+    mem: &mut T,
+) -> ConformanceReport {
+    TestSuite::default().run(mem)
+}
+
+macro_rules! ensure {
+    ($cond:expr, $($arg:tt)*) => {
+        if !$cond {
+            return CheckResult::Failed(format!($($arg)*));
+        }
+    };
+}
+
+macro_rules! tri {
+    ($res:expr) => {
+        match $res {
+            Ok(v) => v,
+            Err(e) => return CheckResult::Failed(e),
+        }
+    };
+}
+
+/// Every element is tagged with its own meta address range, so the reported pieces can be
+/// attributed even if the physical ranges overlap.
+fn meta_base(idx: usize) -> umem {
+    (idx as umem) << (umem::BITS / 2)
+}
+
+/// Reads all ranges in a single batch and verifies that every byte was reported exactly once.
+fn scatter_read(
+    mem: &mut impl PhysicalMemory,
+    reqs: &[(Address, usize)],
+) -> Result<Vec<Bytes>, String> {
+    let mut bufs = reqs
+        .iter()
+        .map(|&(_, len)| vec![0u8; len])
+        .collect::<Vec<_>>();
+    let mut read = vec![];
+    let mut failed = vec![];
+
+    {
+        let mut out = |CTup2(meta, data): ReadData| {
+            read.push((meta.to_umem(), data.len()));
+            true
+        };
+        let mut out_fail = |CTup2(meta, data): ReadData| {
+            failed.push((meta.to_umem(), data.len()));
+            true
+        };
+        let iter = reqs
+            .iter()
+            .zip(bufs.iter_mut())
+            .enumerate()
+            .map(|(i, (&(addr, _), buf))| {
+                CTup3(
+                    PhysicalAddress::from(addr),
+                    Address::from(meta_base(i)),
+                    CSliceMut::from(&mut buf[..]),
+                )
+            });
+
+        MemOps::with_raw(
+            iter,
+            Some(&mut (&mut out).into()),
+            Some(&mut (&mut out_fail).into()),
+            |data| mem.phys_read_raw_iter(data),
+        )
+        .map_err(|err| format!("batch of {} reads failed: {}", reqs.len(), err))?;
+    }
+
+    let mut pieces = read
+        .into_iter()
+        .map(|(meta, len)| (meta, len, true))
+        .chain(failed.into_iter().map(|(meta, len)| (meta, len, false)))
+        .filter(|&(_, len, _)| len > 0)
+        .collect::<Vec<_>>();
+    pieces.sort_unstable();
+
+    let mut ret = vec![];
+    let mut pieces = pieces.into_iter().peekable();
+
+    for (i, (&(addr, len), buf)) in reqs.iter().zip(bufs.into_iter()).enumerate() {
+        let base = meta_base(i);
+        let mut bytes = vec![None; len];
+        let mut covered = 0;
+
+        while let Some(&(meta, plen, ok)) = pieces.peek() {
+            if meta >= base + len as umem {
+                break;
+            }
+            pieces.next();
+
+            if meta != base + covered as umem || covered + plen > len {
+                return Err(format!(
+                    "read of {:x}+{:x} reported bytes {:x}..{:x} out of order or twice, expected offset {:x}",
+                    addr,
+                    len,
+                    meta - base,
+                    meta - base + plen as umem,
+                    covered
+                ));
+            }
+
+            if ok {
+                for (b, v) in bytes[covered..covered + plen]
+                    .iter_mut()
+                    .zip(&buf[covered..])
+                {
+                    *b = Some(*v);
+                }
+            }
+            covered += plen;
+        }
+
+        if covered != len {
+            return Err(format!(
+                "read of {:x}+{:x} only reported {:x} bytes",
+                addr, len, covered
+            ));
+        }
+
+        ret.push(bytes);
+    }
+
+    if let Some((meta, _, _)) = pieces.next() {
+        return Err(format!(
+            "a piece at unknown meta address {:x} was reported",
+            meta
+        ));
+    }
+
+    Ok(ret)
+}
+
+/// Compares two reads of the same range in the bytes both of them were able to read.
+fn compare(addr: Address, a: &[Option<u8>], b: &[Option<u8>]) -> Result<(), String> {
+    match a
+        .iter()
+        .zip(b.iter())
+        .position(|(a, b)| matches!((a, b), (Some(a), Some(b)) if a != b))
+    {
+        Some(pos) => Err(format!(
+            "inconsistent data at {:x}: {:x?} != {:x?}",
+            addr + pos,
+            a[pos],
+            b[pos]
+        )),
+        None => Ok(()),
+    }
+}
+
+fn check_metadata<T: PhysicalMemory + Clone>(
+    _: &TestSuite,
+    mem: &mut T,
+    _: &mut XorShiftRng,
+) -> CheckResult {
+    let metadata = mem.metadata();
+
+    ensure!(
+        metadata.real_size <= metadata.max_address.to_umem().saturating_add(1),
+        "real_size {:x} is larger than the address space ending at {:x}",
+        metadata.real_size,
+        metadata.max_address
+    );
+    ensure!(
+        metadata.ideal_batch_size > 0
+            && metadata.max_batch_size > 0
+            && metadata.max_batch_bytes > 0,
+        "batch limits have to be at least 1: {:?}",
+        metadata
+    );
+    ensure!(
+        metadata.ideal_batch_size <= metadata.max_batch_size,
+        "ideal_batch_size {} exceeds max_batch_size {}",
+        metadata.ideal_batch_size,
+        metadata.max_batch_size
+    );
+
+    let clone = mem.clone().metadata();
+    ensure!(
+        clone.max_address == metadata.max_address
+            && clone.real_size == metadata.real_size
+            && clone.readonly == metadata.readonly,
+        "metadata of a clone differs: {:?} != {:?}",
+        clone,
+        metadata
+    );
+
+    CheckResult::Passed
+}
+
+fn check_scatter<T: PhysicalMemory>(
+    suite: &TestSuite,
+    mem: &mut T,
+    rng: &mut XorShiftRng,
+) -> CheckResult {
+    for _ in 0..suite.iterations {
+        let reqs = suite.random_batch(rng, mem);
+        let batch = tri!(scatter_read(mem, &reqs));
+
+        for (&req, bytes) in reqs.iter().zip(batch.iter()) {
+            let single = tri!(scatter_read(mem, &[req]));
+            tri!(compare(req.0, bytes, &single[0]));
+        }
+    }
+
+    CheckResult::Passed
+}
+
+fn check_edges<T: PhysicalMemory>(_: &TestSuite, mem: &mut T, _: &mut XorShiftRng) -> CheckResult {
+    let max_address = mem.metadata().max_address;
+    let page = size::kb(4);
+
+    // bytes beyond max_address must never be reported as read
+    if max_address.to_umem() >= 8 && max_address.to_umem() < umem::MAX - 8 {
+        let crossing = tri!(scatter_read(mem, &[(max_address - 7usize, 16)]));
+        ensure!(
+            crossing[0][8..].iter().all(Option::is_none),
+            "bytes beyond max_address {:x} were reported as read",
+            max_address
+        );
+
+        let beyond = tri!(scatter_read(mem, &[(max_address + 1usize, 16)]));
+        ensure!(
+            beyond[0].iter().all(Option::is_none),
+            "read beyond max_address {:x} was reported as read",
+            max_address
+        );
+    }
+
+    // page crossing reads equal the reads of both halves
+    if max_address.to_umem() >= 2 * page as umem {
+        let addr = Address::from(page as umem - 3);
+        let whole = tri!(scatter_read(mem, &[(addr, 6)]));
+        let halves = tri!(scatter_read(mem, &[(addr, 3), (addr + 3usize, 3)]));
+        let halves = halves.concat();
+        tri!(compare(addr, &whole[0], &halves));
+    }
+
+    // empty reads must not break the batch
+    let empty = tri!(scatter_read(
+        mem,
+        &[(Address::null(), 0), (Address::null(), 1)]
+    ));
+    ensure!(empty[1].len() == 1, "read following an empty read was lost");
+
+    CheckResult::Passed
+}
+
+fn check_roundtrip<T: PhysicalMemory>(
+    suite: &TestSuite,
+    mem: &mut T,
+    rng: &mut XorShiftRng,
+) -> CheckResult {
+    if !suite.writes {
+        return CheckResult::Skipped("writes are disabled".into());
+    }
+    if mem.metadata().readonly {
+        return CheckResult::Skipped("connector is readonly".into());
+    }
+
+    for _ in 0..suite.iterations {
+        let (addr, len) = suite.random_batch(rng, mem)[0];
+
+        let original = tri!(scatter_read(mem, &[(addr, len)])).remove(0);
+        if original.iter().any(Option::is_none) {
+            continue;
+        }
+        let original = original.into_iter().flatten().collect::<Vec<_>>();
+
+        let data = (0..len).map(|_| rng.gen()).collect::<Vec<u8>>();
+        let write = mem.phys_write(addr.into(), &data[..]);
+        let written = scatter_read(mem, &[(addr, len)]);
+        let restore = mem.phys_write(addr.into(), &original[..]);
+
+        tri!(write.map_err(|err| format!("write at {:x} failed: {}", addr, err)));
+        tri!(restore.map_err(|err| format!("restoring {:x} failed: {}", addr, err)));
+        let written = tri!(written).remove(0);
+
+        ensure!(
+            written.iter().zip(data.iter()).all(|(a, b)| *a == Some(*b)),
+            "data written to {:x}+{:x} could not be read back",
+            addr,
+            len
+        );
+    }
+
+    CheckResult::Passed
+}
+
+fn check_clones<T: PhysicalMemory + Clone + Send + 'static>(
+    suite: &TestSuite,
+    mem: &mut T,
+    rng: &mut XorShiftRng,
+) -> CheckResult {
+    if suite.threads == 0 {
+        return CheckResult::Skipped("no threads configured".into());
+    }
+
+    let batches = (0..suite.iterations)
+        .map(|_| suite.random_batch(rng, mem))
+        .collect::<Vec<_>>();
+
+    let expected = tri!(batches
+        .iter()
+        .map(|reqs| scatter_read(mem, reqs))
+        .collect::<Result<Vec<_>, _>>());
+
+    let handles = (0..suite.threads)
+        .map(|_| {
+            let mut clone = mem.clone();
+            let batches = batches.clone();
+            std::thread::spawn(move || {
+                batches
+                    .iter()
+                    .map(|reqs| scatter_read(&mut clone, reqs))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        let results = match handle.join() {
+            Ok(results) => tri!(results),
+            Err(_) => return CheckResult::Failed("a clone panicked".into()),
+        };
+
+        for ((reqs, expected), results) in batches.iter().zip(expected.iter()).zip(results.iter()) {
+            for ((&(addr, _), a), b) in reqs.iter().zip(expected.iter()).zip(results.iter()) {
+                tri!(compare(addr, a, b));
+            }
+        }
+    }
+
+    CheckResult::Passed
+}
+
+fn check_mem_map<T: PhysicalMemory + Clone>(
+    suite: &TestSuite,
+    mem: &mut T,
+    rng: &mut XorShiftRng,
+) -> CheckResult {
+    let max_address = mem.metadata().max_address.to_umem();
+    if max_address < 2 * size::kb(4) as umem {
+        return CheckResult::Skipped("address space too small".into());
+    }
+
+    let batches = (0..suite.iterations)
+        .map(|_| suite.random_batch(rng, mem))
+        .collect::<Vec<_>>();
+    let expected = tri!(batches
+        .iter()
+        .map(|reqs| scatter_read(mem, reqs))
+        .collect::<Result<Vec<_>, _>>());
+
+    // identity map the lower half of the address space
+    let half = (max_address / 2 + 1) & !(size::kb(4) as umem - 1);
+    let mut clone = mem.clone();
+    clone.set_mem_map(&[PhysicalMemoryMapping {
+        base: Address::null(),
+        size: half,
+        real_base: Address::null(),
+    }]);
+
+    for (reqs, expected) in batches.iter().zip(expected.iter()) {
+        let results = tri!(scatter_read(&mut clone, reqs));
+        for ((&(addr, len), a), b) in reqs.iter().zip(expected.iter()).zip(results.iter()) {
+            // only the mapped part has to stay the same
+            let mapped = std::cmp::min(len as umem, half.saturating_sub(addr.to_umem())) as usize;
+            tri!(compare(addr, &a[..mapped], &b[..mapped]));
+        }
+    }
+
+    CheckResult::Passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, Fault, FaultyMemory};
+
+    #[test]
+    fn dummy_conforms() {
+        let mut mem = DummyMemory::new(size::mb(2));
+        let report = TestSuite::new().writes(true).iterations(8).run(&mut mem);
+        report.assert_ok();
+        assert_eq!(report.checks.len(), 6);
+    }
+
+    #[test]
+    fn detects_torn_writes() {
+        let mem = DummyMemory::new(size::mb(2));
+        let mut mem =
+            FaultyMemory::with_seed(mem, 5).fault_everywhere(Fault::new().torn_write(1.0));
+
+        let report = TestSuite::new().writes(true).iterations(8).run(&mut mem);
+        let failures = report.failures().map(|c| c.name).collect::<Vec<_>>();
+        assert_eq!(failures, vec!["roundtrip"]);
+    }
+}
@@ -1,22 +1,66 @@
 use crate::cglue::result::into_int_out_result;
 use crate::error::{Error, ErrorKind, ErrorOrigin};
 
-use super::{LibArc, PluginLogger};
+use super::{LibArc, PluginLogger, SkipReason};
 
 use std::mem::MaybeUninit;
 use std::path::Path;
 
+/// Name of the architecture memflow was built for.
+pub const TARGET_ARCH: &str = std::env::consts::ARCH;
+
+fn read_file(path: &Path) -> Result<Vec<u8>, SkipReason> {
+    std::fs::read(path).map_err(|err| {
+        Error(ErrorOrigin::Inventory, ErrorKind::UnableToReadFile).log_trace(err);
+        SkipReason::InvalidFile(ErrorKind::UnableToReadFile)
+    })
+}
+
+fn invalid_exe(err: impl std::fmt::Display) -> SkipReason {
+    Error(ErrorOrigin::Inventory, ErrorKind::InvalidExeFile).log_trace(err);
+    SkipReason::InvalidFile(ErrorKind::InvalidExeFile)
+}
+
+fn check_arch<T: PartialEq + Copy>(
+    found: T,
+    expected: Option<T>,
+    name: impl FnOnce(T) -> String,
+) -> Result<(), SkipReason> {
+    match expected {
+        Some(expected) if expected != found => Err(SkipReason::WrongArchitecture {
+            found: name(found),
+            expected: TARGET_ARCH,
+        }),
+        _ => Ok(()),
+    }
+}
+
 #[cfg(not(any(target_os = "windows", target_os = "macos")))]
 pub fn find_export_by_prefix(
     path: impl AsRef<Path>,
     prefix: &str,
-) -> crate::error::Result<Vec<String>> {
+) -> Result<Vec<String>, SkipReason> {
+    use goblin::elf::header::*;
     use goblin::elf::Elf;
 
-    let buffer = std::fs::read(path.as_ref())
-        .map_err(|err| Error(ErrorOrigin::Inventory, ErrorKind::UnableToReadFile).log_trace(err))?;
-    let elf = Elf::parse(buffer.as_slice())
-        .map_err(|err| Error(ErrorOrigin::Inventory, ErrorKind::InvalidExeFile).log_trace(err))?;
+    const MACHINE: Option<u16> = if cfg!(target_arch = "x86_64") {
+        Some(EM_X86_64)
+    } else if cfg!(target_arch = "x86") {
+        Some(EM_386)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(EM_AARCH64)
+    } else if cfg!(target_arch = "arm") {
+        Some(EM_ARM)
+    } else {
+        None
+    };
+
+    let buffer = read_file(path.as_ref())?;
+    let elf = Elf::parse(buffer.as_slice()).map_err(invalid_exe)?;
+    check_arch(elf.header.e_machine, MACHINE, |m| {
+        machine_to_str(m).to_string()
+    })?;
+
     Ok(elf
         .syms
         .iter()
@@ -37,13 +81,26 @@ pub fn find_export_by_prefix(
 pub fn find_export_by_prefix(
     path: impl AsRef<Path>,
     prefix: &str,
-) -> crate::error::Result<Vec<String>> {
+) -> Result<Vec<String>, SkipReason> {
+    use goblin::pe::header::*;
     use goblin::pe::PE;
 
-    let buffer = std::fs::read(path.as_ref())
-        .map_err(|err| Error(ErrorOrigin::Inventory, ErrorKind::UnableToReadFile).log_trace(err))?;
-    let pe = PE::parse(buffer.as_slice())
-        .map_err(|err| Error(ErrorOrigin::Inventory, ErrorKind::InvalidExeFile).log_trace(err))?;
+    const MACHINE: Option<u16> = if cfg!(target_arch = "x86_64") {
+        Some(COFF_MACHINE_X86_64)
+    } else if cfg!(target_arch = "x86") {
+        Some(COFF_MACHINE_X86)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(COFF_MACHINE_ARM64)
+    } else {
+        None
+    };
+
+    let buffer = read_file(path.as_ref())?;
+    let pe = PE::parse(buffer.as_slice()).map_err(invalid_exe)?;
+    check_arch(pe.header.coff_header.machine, MACHINE, |m| {
+        format!("machine type {:#x}", m)
+    })?;
+
     Ok(pe
         .exports
         .iter()
@@ -62,32 +119,48 @@ pub fn find_export_by_prefix(
 pub fn find_export_by_prefix(
     path: impl AsRef<Path>,
     prefix: &str,
-) -> crate::error::Result<Vec<String>> {
+) -> Result<Vec<String>, SkipReason> {
+    use goblin::mach::constants::cputype::*;
     use goblin::mach::Mach;
 
-    let buffer = std::fs::read(path.as_ref())
-        .map_err(|err| Error(ErrorOrigin::Inventory, ErrorKind::UnableToReadFile).log_trace(err))?;
-    let mach = Mach::parse(buffer.as_slice())
-        .map_err(|err| Error(ErrorOrigin::Inventory, ErrorKind::InvalidExeFile).log_trace(err))?;
+    const CPU_TYPE: Option<u32> = if cfg!(target_arch = "x86_64") {
+        Some(CPU_TYPE_X86_64)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(CPU_TYPE_ARM64)
+    } else {
+        None
+    };
+
+    let buffer = read_file(path.as_ref())?;
+    let mach = Mach::parse(buffer.as_slice()).map_err(invalid_exe)?;
     let macho = match mach {
         Mach::Binary(mach) => mach,
-        Mach::Fat(mach) => (0..mach.narches)
-            .filter_map(|i| mach.get(i).ok())
-            .next()
-            .ok_or_else(|| {
-                Error(ErrorOrigin::Inventory, ErrorKind::InvalidExeFile)
-                    .log_trace("failed to find valid MachO header!")
-            })?,
+        // prefer the slice matching the running architecture
+        Mach::Fat(mach) => {
+            let slices = (0..mach.narches)
+                .filter_map(|i| mach.get(i).ok())
+                .collect::<Vec<_>>();
+            let pos = slices
+                .iter()
+                .position(|m| Some(m.header.cputype) == CPU_TYPE)
+                .unwrap_or(0);
+            slices
+                .into_iter()
+                .nth(pos)
+                .ok_or_else(|| invalid_exe("failed to find valid MachO header!"))?
+        }
     };
+    check_arch(macho.header.cputype, CPU_TYPE, |c| {
+        get_arch_name_from_types(c, macho.header.cpusubtype)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("cpu type {:#x}", c))
+    })?;
 
     // macho symbols are prefixed with `_` in the object file.
     let macho_prefix = "_".to_owned() + prefix;
     Ok(macho
         .symbols
-        .ok_or_else(|| {
-            Error(ErrorOrigin::Inventory, ErrorKind::InvalidExeFile)
-                .log_trace("failed to parse MachO symbols!")
-        })?
+        .ok_or_else(|| invalid_exe("failed to parse MachO symbols!"))?
         .iter()
         .filter_map(|s| s.ok())
         .filter_map(|(name, _)| {
@@ -0,0 +1,25 @@
+//! Backends used for matching pattern sets against scanned memory.
+use super::MultiPattern;
+
+use std::prelude::v1::*;
+
+/// Matches a [`MultiPattern`] against chunks of memory.
+///
+/// Backends only ever see memory that was already read, the chunking and handling of
+/// unreadable regions is done by [`MemoryScan`](super::MemoryScan).
+pub trait ScanBackend {
+    /// Returns all `(pattern index, offset)` matches in `data`, sorted by offset.
+    ///
+    /// This has to return the same results as [`MultiPattern::find_iter`].
+    fn find_patterns(&mut self, data: &[u8], patterns: &MultiPattern) -> Vec<(usize, usize)>;
+}
+
+/// The default backend, matching patterns on the cpu.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuBackend;
+
+impl ScanBackend for CpuBackend {
+    fn find_patterns(&mut self, data: &[u8], patterns: &MultiPattern) -> Vec<(usize, usize)> {
+        patterns.find_iter(data)
+    }
+}
@@ -0,0 +1,235 @@
+//! Discovery of pointer chains.
+//!
+//! [`ChainSearch`] looks for pointer chains that start at a static location inside of a module
+//! and lead to a target address, like a field of a heap allocated object. Such chains stay valid
+//! across restarts of the target, while the address of the object does not.
+//!
+//! The search walks backwards from the target using a [`PointerMap`]: every pointer pointing
+//! slightly below the current address is a candidate for the previous hop, until a pointer
+//! stored inside of a module is reached.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::prelude::v1::*;
+//! use memflow::scan::ChainSearch;
+//!
+//! fn find(proc: &mut (impl Process + MemoryView), target: Address) -> Result<()> {
+//!     for found in ChainSearch::new().max_depth(3).find_in_process(proc, target)? {
+//!         println!("{}: {:?}", found.module, found.chain.steps());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use super::PointerMap;
+use crate::error::Result;
+use crate::mem::{MemoryView, PtrChain};
+use crate::os::{ModuleInfo, Process};
+use crate::types::{imem, umem, Address};
+
+use std::collections::BTreeSet;
+use std::prelude::v1::*;
+
+/// A pointer chain from a module to the searched address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FoundChain {
+    /// Name of the module the chain starts in.
+    pub module: String,
+    /// The chain, starting at the base address of the module.
+    pub chain: PtrChain,
+}
+
+/// Configuration of a pointer chain search.
+#[derive(Clone, Debug)]
+pub struct ChainSearch {
+    max_depth: usize,
+    max_offset: umem,
+    max_results: usize,
+    max_nodes: usize,
+}
+
+impl Default for ChainSearch {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_offset: 0x400,
+            max_results: 100,
+            max_nodes: 100_000,
+        }
+    }
+}
+
+impl ChainSearch {
+    /// Creates a search with a depth of 4 and offsets of up to 0x400 bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum amount of dereferences in a chain.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the maximum offset added to a pointer in every hop.
+    pub fn max_offset(mut self, max_offset: umem) -> Self {
+        self.max_offset = max_offset;
+        self
+    }
+
+    /// Sets the maximum number of chains returned.
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.max_results = max_results;
+        self
+    }
+
+    /// Sets the maximum number of addresses followed per hop.
+    ///
+    /// Limits the memory used by searches in densely linked address spaces.
+    pub fn max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    /// Builds the pointer map of a process and searches chains to `target` in it.
+    pub fn find_in_process<P: Process + MemoryView>(
+        &self,
+        proc: &mut P,
+        target: Address,
+    ) -> Result<Vec<FoundChain>> {
+        let map = PointerMap::build(proc)?;
+        let modules = proc.module_list()?;
+        Ok(self.find(&map, &modules, target))
+    }
+
+    /// Searches chains from any of the `modules` to `target` using the pointers of `map`.
+    ///
+    /// Chains are ranked by their amount of dereferences first and the sum of their offsets
+    /// second, the best chains come first.
+    pub fn find(
+        &self,
+        map: &PointerMap,
+        modules: &[ModuleInfo],
+        target: Address,
+    ) -> Vec<FoundChain> {
+        let mut modules = modules.iter().collect::<Vec<_>>();
+        modules.sort_by_key(|m| m.base);
+
+        let module_of = |addr: Address| {
+            let idx = modules.partition_point(|m| m.base <= addr);
+            idx.checked_sub(1)
+                .map(|idx| modules[idx])
+                .filter(|m| ((addr - m.base) as umem) < m.size)
+        };
+
+        let mut results = vec![];
+        let mut visited = BTreeSet::new();
+        visited.insert(target);
+
+        // addresses reached in the current hop, along with the offsets leading to the target
+        let mut frontier = vec![(target, vec![])];
+
+        for _ in 0..self.max_depth {
+            let mut next = vec![];
+
+            for (addr, offsets) in frontier {
+                let start = Address::from(addr.to_umem().saturating_sub(self.max_offset));
+
+                for (source, value) in map.pointers_into(start..addr + 1usize) {
+                    let mut offsets = offsets.clone();
+                    offsets.insert(0, (addr - value) as umem);
+
+                    if let Some(module) = module_of(source) {
+                        results.push(build_chain(module, source, &offsets));
+                    } else if next.len() < self.max_nodes && visited.insert(source) {
+                        next.push((source, offsets));
+                    }
+                }
+            }
+
+            // deeper chains always rank lower
+            if results.len() >= self.max_results || next.is_empty() {
+                break;
+            }
+
+            frontier = next;
+        }
+
+        results.sort_by_key(|(score, found)| (*score, found.module.clone()));
+        results
+            .into_iter()
+            .take(self.max_results)
+            .map(|(_, found)| found)
+            .collect()
+    }
+}
+
+/// Builds the chain from the static pointer at `source`, returns it with its rank.
+fn build_chain(
+    module: &ModuleInfo,
+    source: Address,
+    offsets: &[umem],
+) -> ((usize, umem), FoundChain) {
+    let mut chain = PtrChain::new(module.base)
+        .offset((source - module.base) as imem)
+        .deref();
+
+    for (i, &offset) in offsets.iter().enumerate() {
+        if offset != 0 {
+            chain = chain.offset(offset as imem);
+        }
+        if i + 1 < offsets.len() {
+            chain = chain.deref();
+        }
+    }
+
+    let score = (offsets.len(), offsets.iter().sum());
+    let found = FoundChain {
+        module: module.name.as_ref().to_string(),
+        chain,
+    };
+
+    (score, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyModuleFormat, DummyOs};
+    use crate::os::Os;
+    use crate::types::size;
+
+    #[test]
+    fn find_static_chain() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let pid = os.alloc_process(size::mb(2), &[]);
+        let module = os
+            .alloc_module(pid, "game.exe", size::kb(64), DummyModuleFormat::Pe)
+            .unwrap();
+
+        let mut proc = os.process_by_pid(pid).unwrap();
+        let heap = proc.info().address + size::mb(1);
+        let (a, b) = (heap, heap + 0x8000usize);
+
+        proc.write(module.base + 0x2000usize, &a.to_umem()).unwrap();
+        proc.write(a + 0x18usize, &b.to_umem()).unwrap();
+
+        let target = b + 0x40usize;
+        let found = ChainSearch::new()
+            .find_in_process(&mut proc, target)
+            .unwrap();
+
+        assert_eq!(found[0].module, "game.exe");
+        assert_eq!(
+            found[0].chain,
+            PtrChain::new(module.base)
+                .offset(0x2000)
+                .deref()
+                .offset(0x18)
+                .deref()
+                .offset(0x40)
+        );
+        assert_eq!(found[0].chain.resolve(&mut proc).unwrap(), target);
+    }
+}
@@ -0,0 +1,196 @@
+//! Chunked traversal of memory ranges.
+use crate::cglue::CTup2;
+use crate::error::Result;
+use crate::mem::{MemoryView, ReadData};
+use crate::types::{size, umem, Address, CancellationToken};
+
+use core::ops::Range;
+use std::prelude::v1::*;
+
+/// Default amount of bytes read at once while scanning.
+pub const DEFAULT_CHUNK_SIZE: usize = size::mb(1);
+
+/// Walks over all readable bytes of `range` using [`DEFAULT_CHUNK_SIZE`] sized reads.
+///
+/// See [`scan_chunks_with_size`] for details.
+pub fn scan_chunks<M, F>(mem: &mut M, range: Range<Address>, overlap: usize, callback: F)
+where
+    M: MemoryView,
+    F: FnMut(Address, &[u8], usize),
+{
+    scan_chunks_with_size(mem, range, overlap, DEFAULT_CHUNK_SIZE, callback)
+}
+
+/// Walks over all readable bytes of `range` in chunks of `chunk_size` bytes.
+///
+/// The callback is invoked with the address and the contents of each contiguous readable run
+/// within a chunk. Regions that could not be read are not passed to the callback.
+///
+/// If a run directly continues the previous one, up to `overlap` bytes of the previous run are
+/// prepended to it. The third argument passed to the callback is the amount of those prepended
+/// bytes, so matches ending within them can be discarded as they were already reported.
+pub fn scan_chunks_with_size<M, F>(
+    mem: &mut M,
+    range: Range<Address>,
+    overlap: usize,
+    chunk_size: usize,
+    callback: F,
+) where
+    M: MemoryView,
+    F: FnMut(Address, &[u8], usize),
+{
+    // without a token the walk can not fail
+    scan_chunks_inner(mem, range, overlap, chunk_size, None, callback).ok();
+}
+
+/// Same as [`scan_chunks_with_size`], but stops before the next chunk once `token` is
+/// cancelled.
+///
+/// Returns [`ErrorKind::Cancelled`](crate::error::ErrorKind::Cancelled) if the walk was
+/// cancelled before reaching the end of `range`.
+pub fn scan_chunks_cancellable<M, F>(
+    mem: &mut M,
+    range: Range<Address>,
+    overlap: usize,
+    chunk_size: usize,
+    token: &CancellationToken,
+    callback: F,
+) -> Result<()>
+where
+    M: MemoryView,
+    F: FnMut(Address, &[u8], usize),
+{
+    scan_chunks_inner(mem, range, overlap, chunk_size, Some(token), callback)
+}
+
+fn scan_chunks_inner<M, F>(
+    mem: &mut M,
+    range: Range<Address>,
+    overlap: usize,
+    chunk_size: usize,
+    token: Option<&CancellationToken>,
+    mut callback: F,
+) -> Result<()>
+where
+    M: MemoryView,
+    F: FnMut(Address, &[u8], usize),
+{
+    let chunk_size = core::cmp::max(chunk_size, 1);
+    let mut buf = vec![0u8; overlap + chunk_size];
+
+    // amount of valid bytes carried over from the previous chunk
+    let mut carry = 0;
+    let mut cur = range.start;
+
+    while cur < range.end {
+        if let Some(token) = token {
+            token.check()?;
+        }
+
+        let len = core::cmp::min(chunk_size as umem, range.end.to_umem() - cur.to_umem()) as usize;
+
+        let mut failed = vec![];
+
+        let res = {
+            let data = &mut buf[carry..carry + len];
+            let fail_cb = &mut |CTup2(addr, d): ReadData| {
+                let start = (addr.to_umem() - cur.to_umem()) as usize + carry;
+                failed.push(start..start + d.len());
+                true
+            };
+            mem.read_iter(
+                std::iter::once(CTup2(cur, data.into())),
+                None,
+                Some(&mut fail_cb.into()),
+            )
+        };
+
+        if res.is_err() {
+            failed = vec![carry..carry + len];
+        }
+
+        failed.sort_by_key(|r| r.start);
+
+        let end = carry + len;
+
+        // walk all valid runs in the buffer
+        let mut run_start = 0;
+        let mut last_run = 0..0;
+        for fail in failed.iter().chain(std::iter::once(&(end..end))) {
+            if fail.start > run_start {
+                let new_from = carry.saturating_sub(run_start);
+                callback(
+                    cur - carry + run_start,
+                    &buf[run_start..fail.start],
+                    new_from,
+                );
+                last_run = run_start..fail.start;
+            }
+            run_start = core::cmp::max(run_start, fail.end);
+        }
+
+        // carry over the tail of the buffer if it was valid
+        carry = if last_run.end == end {
+            core::cmp::min(overlap, last_run.len())
+        } else {
+            0
+        };
+        buf.copy_within(end - carry..end, 0);
+
+        cur = cur + len;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+
+    #[test]
+    fn chunks_overlap() {
+        let buf = (0..0x3000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let base = proc.info().address;
+
+        let mut runs = vec![];
+        scan_chunks_with_size(
+            &mut proc,
+            base..base + 0x3000usize,
+            4,
+            0x1000,
+            |addr, data, new_from| {
+                assert_eq!(data, &buf[(addr - base) as usize..][..data.len()]);
+                runs.push((addr, data.len(), new_from));
+            },
+        );
+
+        assert_eq!(
+            runs,
+            vec![
+                (base, 0x1000, 0),
+                (base + 0xffcusize, 0x1004, 4),
+                (base + 0x1ffcusize, 0x1004, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn chunks_skip_failed() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let mut runs = vec![];
+        scan_chunks_with_size(
+            &mut proc,
+            base - 0x1000usize..base + 0x1000usize,
+            4,
+            0x1800,
+            |addr, data, new_from| runs.push((addr, data.len(), new_from)),
+        );
+
+        assert_eq!(runs, vec![(base, 0x800, 0), (base + 0x7fcusize, 0x804, 4)]);
+    }
+}
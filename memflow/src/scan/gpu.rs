@@ -0,0 +1,377 @@
+//! Pattern matching on the gpu.
+//!
+//! The [`GpuBackend`] uploads every scanned chunk together with the pattern set to the gpu and
+//! checks all offsets of the chunk in parallel. This pays off when scanning large amounts of
+//! memory (e.g. snapshots) against large signature databases, for small pattern sets the
+//! [`CpuBackend`](super::CpuBackend) is usually faster.
+//!
+//! This module is only available with the `gpu_scan` feature enabled.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::prelude::v1::*;
+//! use memflow::scan::{GpuBackend, MemoryScan, MultiPattern, Pattern};
+//!
+//! fn find(mem: &mut impl MemoryView, start: Address) -> Result<()> {
+//!     let mut backend = GpuBackend::new()?;
+//!
+//!     let patterns = MultiPattern::new(vec![
+//!         Pattern::parse("48 8b 05 ?? ?? ?? ??")?,
+//!         Pattern::literal(b"memflow"),
+//!     ]);
+//!
+//!     for m in mem.find_patterns_with(start..start + size::gb(1), &patterns, &mut backend) {
+//!         println!("pattern {} found at {:x}", m.pattern, m.address);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use super::{MultiPattern, ScanBackend};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+use std::prelude::v1::*;
+
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: usize = 256;
+
+/// Maximum amount of workgroups per dispatch, as guaranteed by the default limits.
+const MAX_WORKGROUPS: usize = 65535;
+
+/// Every invocation checks all patterns at a single offset of the chunk.
+///
+/// Pattern bytes are stored as `value | (is_masked << 8)`, patterns themselves as
+/// `(first byte, length)` pairs.
+const SHADER: &str = r#"
+struct Params {
+    data_len: u32;
+    pattern_count: u32;
+    out_capacity: u32;
+    base_offset: u32;
+};
+
+struct Words {
+    words: array<u32>;
+};
+
+struct Matches {
+    count: atomic<u32>;
+    entries: array<u32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var<storage, read> data: Words;
+[[group(0), binding(2)]] var<storage, read> patterns: Words;
+[[group(0), binding(3)]] var<storage, read> pattern_bytes: Words;
+[[group(0), binding(4)]] var<storage, read_write> matches: Matches;
+
+fn data_byte(i: u32) -> u32 {
+    return (data.words[i / 4u] >> ((i % 4u) * 8u)) & 0xffu;
+}
+
+[[stage(compute), workgroup_size(256)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let offset = params.base_offset + id.x;
+    if (offset >= params.data_len) {
+        return;
+    }
+
+    var p: u32 = 0u;
+    loop {
+        if (p >= params.pattern_count) {
+            break;
+        }
+
+        let start = patterns.words[p * 2u];
+        let len = patterns.words[p * 2u + 1u];
+
+        if (offset + len <= params.data_len) {
+            var matched: bool = true;
+            var i: u32 = 0u;
+            loop {
+                if (i >= len) {
+                    break;
+                }
+                let b = pattern_bytes.words[start + i];
+                if ((b & 0x100u) != 0u && (b & 0xffu) != data_byte(offset + i)) {
+                    matched = false;
+                    break;
+                }
+                i = i + 1u;
+            }
+
+            if (matched) {
+                let slot = atomicAdd(&matches.count, 1u);
+                if (slot < params.out_capacity) {
+                    matches.entries[slot * 2u] = p;
+                    matches.entries[slot * 2u + 1u] = offset;
+                }
+            }
+        }
+
+        p = p + 1u;
+    }
+}
+"#;
+
+/// Scan backend that matches patterns on the gpu.
+///
+/// Chunks producing more matches than fit into the result buffer are transparently matched
+/// on the cpu instead, as are chunks for which the gpu could not be used.
+pub struct GpuBackend {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuBackend {
+    /// Initializes the backend on the most powerful available adapter.
+    ///
+    /// Returns an error if no suitable adapter could be found.
+    pub fn new() -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or_else(|| {
+            Error(ErrorOrigin::Other, ErrorKind::NotSupported).log_error("no gpu adapter found")
+        })?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("memflow scan"),
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None,
+        ))
+        .map_err(|err| {
+            Error(ErrorOrigin::Other, ErrorKind::NotSupported)
+                .log_error(format!("unable to open gpu device: {}", err))
+        })?;
+
+        let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("memflow scan"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("memflow scan"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    fn find_gpu(&mut self, data: &[u8], patterns: &MultiPattern) -> Option<Vec<(usize, usize)>> {
+        // offsets are passed as 32 bit integers
+        if data.len() > u32::MAX as usize / 2 {
+            return None;
+        }
+
+        let mut table = vec![];
+        let mut bytes = vec![];
+        for idx in 0..patterns.len() {
+            let pattern = patterns.pattern(idx);
+            table.push(bytes.len() as u32);
+            table.push(pattern.len() as u32);
+            bytes.extend(pattern.masked_bytes().map(|b| match b {
+                Some(b) => b as u32 | 0x100,
+                None => 0,
+            }));
+        }
+        // empty bindings are not allowed
+        bytes.push(0);
+
+        let out_capacity = std::cmp::max(data.len() / 16, 4096);
+
+        let data_buf = self.storage(&pad_words(data), wgpu::BufferUsages::STORAGE);
+        let table_buf = self.storage(&words_to_bytes(&table), wgpu::BufferUsages::STORAGE);
+        let bytes_buf = self.storage(&words_to_bytes(&bytes), wgpu::BufferUsages::STORAGE);
+        let out_size = (4 + out_capacity * 8) as wgpu::BufferAddress;
+        let out_buf = self.storage(
+            &vec![0u8; out_size as usize],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let staging_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("memflow scan staging"),
+            size: out_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        // keep every dispatch within the workgroup count limits
+        let per_dispatch = WORKGROUP_SIZE * MAX_WORKGROUPS;
+        let mut param_bufs = vec![];
+        for base_offset in (0..data.len()).step_by(per_dispatch) {
+            let params = [
+                data.len() as u32,
+                patterns.len() as u32,
+                out_capacity as u32,
+                base_offset as u32,
+            ];
+            let params_buf = self.storage(&words_to_bytes(&params), wgpu::BufferUsages::UNIFORM);
+
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: data_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: table_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: bytes_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: out_buf.as_entire_binding(),
+                    },
+                ],
+            });
+
+            {
+                let mut pass =
+                    encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                let len = std::cmp::min(per_dispatch, data.len() - base_offset);
+                pass.dispatch(((len + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE) as u32, 1, 1);
+            }
+
+            param_bufs.push(params_buf);
+        }
+
+        encoder.copy_buffer_to_buffer(&out_buf, 0, &staging_buf, 0, out_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging_buf.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        if let Err(err) = pollster::block_on(mapping) {
+            log::warn!("unable to read back gpu scan results: {}", err);
+            return None;
+        }
+
+        let out = {
+            let raw = slice.get_mapped_range();
+            let word = |i: usize| {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(&raw[i * 4..i * 4 + 4]);
+                u32::from_le_bytes(bytes) as usize
+            };
+
+            let count = word(0);
+            if count > out_capacity {
+                None
+            } else {
+                let mut out = (0..count)
+                    .map(|i| (word(1 + i * 2), word(2 + i * 2)))
+                    .collect::<Vec<_>>();
+                out.sort_by_key(|&(idx, off)| (off, idx));
+                Some(out)
+            }
+        };
+
+        staging_buf.unmap();
+
+        out
+    }
+
+    fn storage(&self, contents: &[u8], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents,
+                usage,
+            })
+    }
+}
+
+impl ScanBackend for GpuBackend {
+    fn find_patterns(&mut self, data: &[u8], patterns: &MultiPattern) -> Vec<(usize, usize)> {
+        if data.is_empty() || patterns.is_empty() {
+            return vec![];
+        }
+
+        // the shader skips empty patterns, leave their semantics to the cpu matcher
+        if (0..patterns.len()).any(|idx| patterns.pattern(idx).is_empty()) {
+            return patterns.find_iter(data);
+        }
+
+        self.find_gpu(data, patterns).unwrap_or_else(|| {
+            log::warn!("gpu scan failed, falling back to the cpu");
+            patterns.find_iter(data)
+        })
+    }
+}
+
+/// Converts the data into little endian words, padding it with zeroes.
+fn pad_words(data: &[u8]) -> Vec<u8> {
+    let mut out = data.to_vec();
+    out.resize((data.len() + 3) / 4 * 4, 0);
+    out
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(words.len() * 4);
+    for w in words {
+        out.extend_from_slice(&w.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::Pattern;
+
+    #[test]
+    fn gpu_matches_cpu() {
+        let mut backend = match GpuBackend::new() {
+            Ok(backend) => backend,
+            // nothing to test on machines without a gpu
+            Err(_) => return,
+        };
+
+        let data = (0..0x10000u32)
+            .map(|i| (i.wrapping_mul(0x9e37_79b9) >> 24) as u8)
+            .collect::<Vec<_>>();
+
+        let patterns = MultiPattern::new(vec![
+            Pattern::literal(&data[0x100..0x104]),
+            Pattern::from_masked(&[Some(data[0x2000]), None, Some(data[0x2002])]),
+            Pattern::literal(&data[0xfffe..]),
+        ]);
+
+        assert_eq!(
+            backend.find_patterns(&data, &patterns),
+            patterns.find_iter(&data)
+        );
+    }
+}
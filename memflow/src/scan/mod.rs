@@ -0,0 +1,353 @@
+/*!
+Scanning of memory for byte patterns.
+
+The scanning functions in this module operate on any [`MemoryView`] and read the requested
+range in large chunks. Chunks overlap by the maximum match length so that matches crossing a
+chunk boundary are found, and regions that could not be read are skipped.
+
+For processes, [`MemoryScan::find_patterns_mapped`] scans only the mapped regions of the address
+space, which makes scanning a full 64-bit address space feasible.
+
+# Examples
+
+```
+use memflow::prelude::v1::*;
+use memflow::scan::{MemoryScan, Pattern};
+
+fn find(mem: &mut impl MemoryView, start: Address) {
+    let pattern = Pattern::parse("de ad ?? ef").unwrap();
+    for addr in mem.find_pattern(start..start + size::kb(64), &pattern) {
+        println!("found at {:x}", addr);
+    }
+}
+# use memflow::dummy::DummyOs;
+# let mut proc = DummyOs::quick_process(size::mb(2), &[0xde, 0xad, 0xbe, 0xef]);
+# let base = proc.info().address;
+# find(&mut proc, base);
+```
+*/
+
+pub mod backend;
+pub mod chains;
+pub mod chunks;
+#[cfg(feature = "gpu_scan")]
+pub mod gpu;
+pub mod multi;
+pub mod pattern;
+pub mod pointers;
+pub mod session;
+#[cfg(feature = "yara_scan")]
+pub mod yara;
+
+pub use backend::{CpuBackend, ScanBackend};
+pub use chains::{ChainSearch, FoundChain};
+pub use chunks::{scan_chunks, scan_chunks_cancellable};
+#[cfg(feature = "gpu_scan")]
+pub use gpu::GpuBackend;
+pub use multi::MultiPattern;
+pub use pattern::Pattern;
+pub use pointers::PointerMap;
+pub use session::{ScanFilter, ScanSession, ScanValue, ValueType};
+
+use crate::cglue::CTup3;
+use crate::error::Result;
+use crate::mem::MemoryView;
+use crate::os::Process;
+use crate::types::{umem, Address, CancellationToken};
+
+use core::ops::Range;
+use std::prelude::v1::*;
+
+/// Single match found by a scan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ScanMatch {
+    /// Address the match starts at.
+    pub address: Address,
+    /// Index of the pattern that matched.
+    pub pattern: usize,
+    /// Length of the match in bytes.
+    pub len: usize,
+}
+
+/// Scanning extension for all [`MemoryView`] objects.
+pub trait MemoryScan: MemoryView + Sized {
+    /// Returns the addresses of all occurrences of `pattern` inside of `range`.
+    fn find_pattern(&mut self, range: Range<Address>, pattern: &Pattern) -> Vec<Address> {
+        let mut out = vec![];
+
+        if pattern.is_empty() {
+            return out;
+        }
+
+        scan_chunks(self, range, pattern.len() - 1, |base, data, new_from| {
+            out.extend(
+                pattern
+                    .find_iter(data)
+                    .filter(|&off| off + pattern.len() > new_from)
+                    .map(|off| base + off),
+            );
+        });
+
+        out
+    }
+
+    /// Same as [`find_pattern`](Self::find_pattern), but aborts once `token` is cancelled.
+    fn find_pattern_cancellable(
+        &mut self,
+        range: Range<Address>,
+        pattern: &Pattern,
+        token: &CancellationToken,
+    ) -> Result<Vec<Address>> {
+        let mut out = vec![];
+
+        if pattern.is_empty() {
+            return Ok(out);
+        }
+
+        scan_chunks_cancellable(
+            self,
+            range,
+            pattern.len() - 1,
+            chunks::DEFAULT_CHUNK_SIZE,
+            token,
+            |base, data, new_from| {
+                out.extend(
+                    pattern
+                        .find_iter(data)
+                        .filter(|&off| off + pattern.len() > new_from)
+                        .map(|off| base + off),
+                );
+            },
+        )?;
+
+        Ok(out)
+    }
+
+    /// Locates all patterns of the set inside of `range` in a single pass over memory.
+    ///
+    /// Matches are returned sorted by address.
+    fn find_patterns(&mut self, range: Range<Address>, patterns: &MultiPattern) -> Vec<ScanMatch> {
+        self.find_patterns_with(range, patterns, &mut CpuBackend)
+    }
+
+    /// Locates all patterns of the set inside of `range`, matching memory with `backend`.
+    ///
+    /// Matches are returned sorted by address.
+    fn find_patterns_with<B: ScanBackend>(
+        &mut self,
+        range: Range<Address>,
+        patterns: &MultiPattern,
+        backend: &mut B,
+    ) -> Vec<ScanMatch> {
+        let mut out = vec![];
+
+        if patterns.max_len() == 0 {
+            return out;
+        }
+
+        scan_chunks(
+            self,
+            range,
+            patterns.max_len() - 1,
+            |base, data, new_from| {
+                out.extend(
+                    backend
+                        .find_patterns(data, patterns)
+                        .into_iter()
+                        .filter(|&(idx, off)| off + patterns.pattern(idx).len() > new_from)
+                        .map(|(idx, off)| ScanMatch {
+                            address: base + off,
+                            pattern: idx,
+                            len: patterns.pattern(idx).len(),
+                        }),
+                );
+            },
+        );
+
+        out
+    }
+
+    /// Locates all patterns of the set inside of each of the `ranges`.
+    ///
+    /// Only matches starting at a multiple of `alignment` are returned, an alignment of 0 or 1
+    /// accepts all addresses. Matches are returned sorted by address if the ranges are sorted.
+    fn find_patterns_in(
+        &mut self,
+        ranges: impl IntoIterator<Item = Range<Address>>,
+        patterns: &MultiPattern,
+        alignment: usize,
+    ) -> Vec<ScanMatch> {
+        let alignment = core::cmp::max(alignment, 1) as umem;
+
+        ranges
+            .into_iter()
+            .flat_map(|range| self.find_patterns(range, patterns))
+            .filter(|m| m.address.to_umem() % alignment == 0)
+            .collect()
+    }
+
+    /// Locates all patterns of the set inside of the mapped memory of the process within
+    /// `range`.
+    ///
+    /// Unmapped parts of `range` are never read, see [`find_patterns_in`](Self::find_patterns_in)
+    /// for the meaning of `alignment`.
+    fn find_patterns_mapped(
+        &mut self,
+        range: Range<Address>,
+        patterns: &MultiPattern,
+        alignment: usize,
+    ) -> Vec<ScanMatch>
+    where
+        Self: Process,
+    {
+        let ranges = self
+            .mapped_mem_range_vec(0, range.start, range.end)
+            .into_iter()
+            .map(|CTup3(addr, size, _)| addr..addr + size)
+            .collect::<Vec<_>>();
+
+        self.find_patterns_in(ranges, patterns, alignment)
+    }
+
+    /// Returns all matches of the regular expression inside of `range`.
+    ///
+    /// `max_len` is the longest match that has to be detected across chunk boundaries.
+    /// Matches longer than the scan chunk size may be missed.
+    #[cfg(feature = "regex")]
+    fn find_regex(
+        &mut self,
+        range: Range<Address>,
+        regex: &::regex::bytes::Regex,
+        max_len: usize,
+    ) -> Vec<ScanMatch> {
+        let mut out = vec![];
+
+        scan_chunks(
+            self,
+            range,
+            max_len.saturating_sub(1),
+            |base, data, new_from| {
+                out.extend(
+                    regex
+                        .find_iter(data)
+                        .filter(|m| m.end() > new_from && !m.as_bytes().is_empty())
+                        .map(|m| ScanMatch {
+                            address: base + m.start(),
+                            pattern: 0,
+                            len: m.end() - m.start(),
+                        }),
+                );
+            },
+        );
+
+        out
+    }
+}
+
+impl<T: MemoryView> MemoryScan for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    #[test]
+    fn find_across_chunks() {
+        let mut proc = DummyOs::quick_process(size::mb(4), &[]);
+        let base = proc.info().address;
+
+        let at = base + chunks::DEFAULT_CHUNK_SIZE - 2usize;
+        proc.write(at, &[0xdeu8, 0xad, 0xbe, 0xef]).unwrap();
+        proc.write(base + 0x100usize, &[0xdeu8, 0xad, 0x00, 0xef])
+            .unwrap();
+
+        let pattern = Pattern::parse("de ad ? ef").unwrap();
+        let found = proc.find_pattern(base..base + size::mb(3), &pattern);
+
+        assert_eq!(found, vec![base + 0x100usize, at]);
+    }
+
+    #[test]
+    fn find_multiple_patterns() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write(base + 0x10usize, b"memflow").unwrap();
+        proc.write(base + 0x40usize, &[0x48u8, 0x8b, 0x05, 0x11, 0x22])
+            .unwrap();
+        proc.write(base + 0x80usize, b"flow").unwrap();
+
+        let patterns = MultiPattern::new(vec![
+            Pattern::literal(b"memflow"),
+            Pattern::parse("48 8b 05 ?? 22").unwrap(),
+            Pattern::literal(b"flow"),
+        ]);
+
+        let found = proc.find_patterns(base..base + size::kb(4), &patterns);
+
+        assert_eq!(
+            found
+                .iter()
+                .map(|m| (m.address, m.pattern))
+                .collect::<Vec<_>>(),
+            vec![
+                (base + 0x10usize, 0),
+                (base + 0x13usize, 2),
+                (base + 0x40usize, 1),
+                (base + 0x80usize, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_mapped_aligned() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        proc.write(base + 0x11usize, b"memflow").unwrap();
+        proc.write(base + 0x20usize, b"memflow").unwrap();
+
+        let patterns = MultiPattern::new(vec![Pattern::literal(b"memflow")]);
+        let found = proc.find_patterns_mapped(Address::null()..Address::invalid(), &patterns, 0x10);
+
+        assert_eq!(
+            found.iter().map(|m| m.address).collect::<Vec<_>>(),
+            vec![base + 0x20usize]
+        );
+    }
+
+    #[test]
+    fn cancel_scan() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0xff; 4]);
+        let base = proc.info().address;
+        let pattern = Pattern::literal(&[0xff]);
+
+        let token = CancellationToken::new();
+        let found = proc
+            .find_pattern_cancellable(base..base + size::kb(4), &pattern, &token)
+            .unwrap();
+        assert_eq!(found.len(), 4);
+
+        token.cancel();
+        let err = proc
+            .find_pattern_cancellable(base..base + size::kb(4), &pattern, &token)
+            .unwrap_err();
+        assert_eq!(err.1, crate::error::ErrorKind::Cancelled);
+    }
+
+    #[test]
+    fn skip_unreadable() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[0xff; 4]);
+        let base = proc.info().address;
+
+        // the region in front of the base address is not mapped
+        let found = proc.find_pattern(
+            base - size::mb(1)..base + size::kb(4),
+            &Pattern::literal(&[0, 0, 0xff]),
+        );
+
+        assert!(found.is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+//! Multi-pattern search using an Aho-Corasick automaton.
+use super::Pattern;
+
+use std::prelude::v1::*;
+
+const NONE: u32 = u32::MAX;
+
+/// Set of patterns that are searched in a single pass.
+///
+/// Internally the longest literal part (anchor) of every pattern is inserted into an
+/// Aho-Corasick automaton. Every anchor hit is then verified against the full pattern,
+/// so patterns containing wildcards are supported as well.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::scan::{MultiPattern, Pattern};
+///
+/// let patterns = MultiPattern::new(vec![
+///     Pattern::literal(b"he"),
+///     Pattern::parse("73 ?? 65").unwrap(), // "s?e"
+/// ]);
+///
+/// assert_eq!(patterns.find_iter(b"ushers"), vec![(1, 1), (0, 2)]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MultiPattern {
+    patterns: Vec<Pattern>,
+    anchors: Vec<(usize, usize)>,
+    unanchored: Vec<usize>,
+    transitions: Vec<[u32; 256]>,
+    outputs: Vec<Vec<u32>>,
+    max_len: usize,
+}
+
+impl MultiPattern {
+    /// Builds the automaton for the given patterns.
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        let mut transitions = vec![[NONE; 256]];
+        let mut outputs = vec![vec![]];
+        let mut anchors = vec![];
+        let mut unanchored = vec![];
+
+        for (idx, pattern) in patterns.iter().enumerate() {
+            let (offset, anchor) = pattern.anchor();
+            anchors.push((offset, anchor.len()));
+
+            if anchor.is_empty() {
+                if !pattern.is_empty() {
+                    unanchored.push(idx);
+                }
+                continue;
+            }
+
+            let mut state = 0;
+            for &b in anchor {
+                let next = transitions[state][b as usize];
+                state = if next == NONE {
+                    transitions.push([NONE; 256]);
+                    outputs.push(vec![]);
+                    let new = (transitions.len() - 1) as u32;
+                    transitions[state][b as usize] = new;
+                    new as usize
+                } else {
+                    next as usize
+                };
+            }
+            outputs[state].push(idx as u32);
+        }
+
+        // compute failure links in breadth first order and turn the trie into a full automaton
+        let mut fail = vec![0u32; transitions.len()];
+        let mut queue = std::collections::VecDeque::new();
+
+        for b in 0..256 {
+            match transitions[0][b] {
+                NONE => transitions[0][b] = 0,
+                next => queue.push_back(next as usize),
+            }
+        }
+
+        while let Some(state) = queue.pop_front() {
+            for b in 0..256 {
+                let next = transitions[state][b];
+                if next == NONE {
+                    transitions[state][b] = transitions[fail[state] as usize][b];
+                } else {
+                    let f = transitions[fail[state] as usize][b];
+                    fail[next as usize] = f;
+                    let inherited = outputs[f as usize].clone();
+                    outputs[next as usize].extend(inherited);
+                    queue.push_back(next as usize);
+                }
+            }
+        }
+
+        let max_len = patterns.iter().map(Pattern::len).max().unwrap_or(0);
+
+        Self {
+            patterns,
+            anchors,
+            unanchored,
+            transitions,
+            outputs,
+            max_len,
+        }
+    }
+
+    /// Returns the amount of patterns in the set.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Returns `true` if the set contains no patterns.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Returns the pattern with the given index.
+    pub fn pattern(&self, idx: usize) -> &Pattern {
+        &self.patterns[idx]
+    }
+
+    /// Returns the length of the longest pattern.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Returns all `(pattern index, offset)` matches in `data`, sorted by offset.
+    pub fn find_iter(&self, data: &[u8]) -> Vec<(usize, usize)> {
+        let mut out = vec![];
+
+        let mut state = 0;
+        for (i, &b) in data.iter().enumerate() {
+            state = self.transitions[state][b as usize] as usize;
+
+            for &idx in &self.outputs[state] {
+                let idx = idx as usize;
+                let (anchor_off, anchor_len) = self.anchors[idx];
+
+                let start = match (i + 1).checked_sub(anchor_len + anchor_off) {
+                    Some(start) => start,
+                    None => continue,
+                };
+
+                if self.patterns[idx].matches(&data[start..]) {
+                    out.push((idx, start));
+                }
+            }
+        }
+
+        for &idx in &self.unanchored {
+            out.extend(self.patterns[idx].find_iter(data).map(|off| (idx, off)));
+        }
+
+        out.sort_by_key(|&(idx, off)| (off, idx));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aho_corasick_classic() {
+        let patterns = MultiPattern::new(vec![
+            Pattern::literal(b"he"),
+            Pattern::literal(b"she"),
+            Pattern::literal(b"his"),
+            Pattern::literal(b"hers"),
+        ]);
+
+        assert_eq!(
+            patterns.find_iter(b"ahishers"),
+            vec![(2, 1), (1, 3), (0, 4), (3, 4)]
+        );
+    }
+
+    #[test]
+    fn wildcard_patterns() {
+        let patterns = MultiPattern::new(vec![
+            Pattern::parse("01 ?? 03").unwrap(),
+            Pattern::parse("?? 02").unwrap(),
+            Pattern::parse("??").unwrap(),
+        ]);
+
+        assert_eq!(
+            patterns.find_iter(&[0x01, 0x02, 0x03]),
+            vec![(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)]
+        );
+    }
+}
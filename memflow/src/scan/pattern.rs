@@ -0,0 +1,171 @@
+//! Byte patterns with wildcards.
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+use core::str::FromStr;
+use std::prelude::v1::*;
+
+/// Byte pattern with optional wildcards.
+///
+/// Patterns can be parsed from the commonly used hex notation, where each byte is written as
+/// two hex digits and wildcards are written as `?` or `??`.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::scan::Pattern;
+///
+/// let pattern = Pattern::parse("48 8B 05 ?? ?? ?? ??").unwrap();
+/// assert_eq!(pattern.len(), 7);
+/// assert!(pattern.matches(&[0x48, 0x8b, 0x05, 1, 2, 3, 4]));
+/// assert!(!pattern.matches(&[0x48, 0x8b, 0x06, 1, 2, 3, 4]));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    mask: Vec<bool>,
+}
+
+impl Pattern {
+    /// Creates a pattern that matches `bytes` exactly.
+    pub fn literal(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+            mask: vec![true; bytes.len()],
+        }
+    }
+
+    /// Creates a pattern from a list of optional bytes, where `None` is a wildcard.
+    pub fn from_masked(bytes: &[Option<u8>]) -> Self {
+        Self {
+            bytes: bytes.iter().map(|b| b.unwrap_or(0)).collect(),
+            mask: bytes.iter().map(Option::is_some).collect(),
+        }
+    }
+
+    /// Parses a pattern in hex notation, e.g. `"48 8B ?? 05"`.
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let mut bytes = vec![];
+
+        for token in pattern.split_whitespace() {
+            if token.chars().all(|c| c == '?') && token.len() <= 2 {
+                bytes.push(None);
+            } else if token.len() == 2 {
+                let byte = u8::from_str_radix(token, 16).map_err(|_| {
+                    Error(ErrorOrigin::Other, ErrorKind::InvalidArgument)
+                        .log_error(format!("invalid pattern byte: {}", token))
+                })?;
+                bytes.push(Some(byte));
+            } else {
+                return Err(Error(ErrorOrigin::Other, ErrorKind::InvalidArgument)
+                    .log_error(format!("invalid pattern byte: {}", token)));
+            }
+        }
+
+        Ok(Self::from_masked(&bytes))
+    }
+
+    /// Returns the length of the pattern in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns `true` if the pattern has a length of zero.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns the pattern as a list of optional bytes, where `None` is a wildcard.
+    pub fn masked_bytes(&self) -> impl Iterator<Item = Option<u8>> + '_ {
+        self.bytes
+            .iter()
+            .zip(self.mask.iter())
+            .map(|(&b, &m)| if m { Some(b) } else { None })
+    }
+
+    /// Returns `true` if `data` starts with this pattern.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.len()
+            && self
+                .bytes
+                .iter()
+                .zip(self.mask.iter())
+                .zip(data.iter())
+                .all(|((b, &m), d)| !m || b == d)
+    }
+
+    /// Returns an iterator over the offsets of all (possibly overlapping) matches in `data`.
+    pub fn find_iter<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let (anchor_off, anchor) = self.anchor();
+        let first = anchor.first().copied();
+
+        (0..(data.len() + 1).saturating_sub(self.len()))
+            .filter(move |&i| match first {
+                Some(b) => data[i + anchor_off] == b,
+                None => true,
+            })
+            .filter(move |&i| self.matches(&data[i..]))
+    }
+
+    /// Returns the longest run of non-wildcard bytes and its offset within the pattern.
+    pub fn anchor(&self) -> (usize, &[u8]) {
+        let mut best = (0, 0..0);
+        let mut start = None;
+
+        for (i, &m) in self.mask.iter().chain(std::iter::once(&false)).enumerate() {
+            match (m, start) {
+                (true, None) => start = Some(i),
+                (false, Some(s)) => {
+                    if i - s > best.1.len() {
+                        best = (s, s..i);
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        (best.0, &self.bytes[best.1])
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pattern() {
+        let pattern: Pattern = "de ? AD ??".parse().unwrap();
+        assert_eq!(
+            pattern,
+            Pattern::from_masked(&[Some(0xde), None, Some(0xad), None])
+        );
+
+        assert!(Pattern::parse("de a").is_err());
+        assert!(Pattern::parse("zz").is_err());
+        assert!(Pattern::parse("???").is_err());
+    }
+
+    #[test]
+    fn find_overlapping() {
+        let pattern = Pattern::parse("aa ? aa").unwrap();
+        let data = [0xaa, 0x00, 0xaa, 0x11, 0xaa, 0xaa];
+        assert_eq!(pattern.find_iter(&data).collect::<Vec<_>>(), vec![0, 2]);
+    }
+
+    #[test]
+    fn pattern_anchor() {
+        let pattern = Pattern::parse("01 ?? 02 03 04 ?? 05").unwrap();
+        assert_eq!(pattern.anchor(), (2, &[2, 3, 4][..]));
+
+        let pattern = Pattern::parse("?? ??").unwrap();
+        assert_eq!(pattern.anchor(), (0, &[][..]));
+    }
+}
@@ -0,0 +1,229 @@
+//! Reverse pointer maps.
+//!
+//! A [`PointerMap`] holds every pointer sized value of an address space that points into one of
+//! its mapped regions, indexed by the address it points to. This answers "who points to
+//! address X" without touching memory again, which is the basis for finding pointer chains to
+//! dynamically allocated objects.
+//!
+//! Memory is read in large chunks over the mapped regions only. Regions that changed can be
+//! rescanned individually with [`PointerMap::update_region`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::scan::PointerMap;
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let base = proc.info().address;
+//!
+//! let target = base + 0x800usize;
+//! proc.write(base + 0x40usize, &target.to_umem()).unwrap();
+//!
+//! let map = PointerMap::build(&mut proc).unwrap();
+//! assert_eq!(map.pointers_to(target).collect::<Vec<_>>(), vec![base + 0x40usize]);
+//! ```
+
+use super::chunks::scan_chunks;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::os::Process;
+use crate::types::{umem, Address};
+
+use core::ops::Range;
+use std::prelude::v1::*;
+
+/// Reverse map of the pointers inside of a set of memory regions.
+pub struct PointerMap {
+    /// Sorted, non-overlapping regions pointers are searched in and may point into.
+    regions: Vec<Range<Address>>,
+    /// (target, source) pairs sorted by target.
+    entries: Vec<(Address, Address)>,
+    ptr_size: usize,
+    little_endian: bool,
+}
+
+impl PointerMap {
+    /// Builds the pointer map of the mapped memory of a process.
+    ///
+    /// The pointer size and byte order are taken from the metadata of the process memory view.
+    pub fn build<P: Process + MemoryView>(proc: &mut P) -> Result<Self> {
+        let regions = proc
+            .mapped_mem_vec(0)
+            .into_iter()
+            .map(|r| r.0..r.0 + r.1)
+            .collect();
+
+        let metadata = proc.metadata();
+        Self::build_in(
+            proc,
+            regions,
+            metadata.arch_bits as usize / 8,
+            metadata.little_endian,
+        )
+    }
+
+    /// Builds the pointer map of `regions` of `mem`.
+    ///
+    /// Only naturally aligned values of `ptr_size` bytes are considered, `ptr_size` has to be
+    /// 4 or 8.
+    pub fn build_in<M: MemoryView>(
+        mem: &mut M,
+        mut regions: Vec<Range<Address>>,
+        ptr_size: usize,
+        little_endian: bool,
+    ) -> Result<Self> {
+        if ptr_size != 4 && ptr_size != 8 {
+            return Err(Error(ErrorOrigin::Other, ErrorKind::InvalidArgument)
+                .log_error("pointer maps only support 4 and 8 byte pointers"));
+        }
+
+        regions.retain(|r| r.start < r.end);
+        regions.sort_by_key(|r| r.start);
+
+        let mut map = Self {
+            regions,
+            entries: vec![],
+            ptr_size,
+            little_endian,
+        };
+
+        for idx in 0..map.regions.len() {
+            let range = map.regions[idx].clone();
+            map.scan_range(mem, range);
+        }
+        map.entries.sort_unstable();
+
+        Ok(map)
+    }
+
+    /// Returns the number of pointers in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the addresses of all pointers pointing to exactly `target`.
+    pub fn pointers_to(&self, target: Address) -> impl Iterator<Item = Address> + '_ {
+        self.pointers_into(target..target + 1usize)
+            .map(|(source, _)| source)
+    }
+
+    /// Returns all pointers pointing into `range` as (source, target) pairs, sorted by target.
+    ///
+    /// Searching for pointers into the range `x - max_offset..x + 1` yields all pointers to
+    /// objects that contain `x` within their first `max_offset` bytes.
+    pub fn pointers_into(
+        &self,
+        range: Range<Address>,
+    ) -> impl Iterator<Item = (Address, Address)> + '_ {
+        let start = self.entries.partition_point(|&(t, _)| t < range.start);
+        self.entries[start..]
+            .iter()
+            .take_while(move |&&(t, _)| t < range.end)
+            .map(|&(target, source)| (source, target))
+    }
+
+    /// Rescans `range` of `mem`, replacing all pointers that are stored inside of it.
+    ///
+    /// Only the parts of `range` that overlap with the regions of the map are scanned, pointers
+    /// into regions that were not part of the map are not found.
+    pub fn update_region<M: MemoryView>(&mut self, mem: &mut M, range: Range<Address>) {
+        self.entries
+            .retain(|&(_, source)| source < range.start || source >= range.end);
+
+        let overlapping = self
+            .regions
+            .iter()
+            .filter(|r| r.start < range.end && r.end > range.start)
+            .map(|r| std::cmp::max(r.start, range.start)..std::cmp::min(r.end, range.end))
+            .collect::<Vec<_>>();
+
+        for range in overlapping {
+            self.scan_range(mem, range);
+        }
+        self.entries.sort_unstable();
+    }
+
+    fn scan_range<M: MemoryView>(&mut self, mem: &mut M, range: Range<Address>) {
+        let Self {
+            regions,
+            entries,
+            ptr_size,
+            little_endian,
+        } = self;
+        let (ptr_size, little_endian) = (*ptr_size, *little_endian);
+
+        scan_chunks(mem, range, ptr_size - 1, |base, data, new_from| {
+            // first offset at which an aligned pointer starts
+            let misalign = (base.to_umem() % ptr_size as umem) as usize;
+            let first = (ptr_size - misalign) % ptr_size;
+
+            for off in (first..data.len().saturating_sub(ptr_size - 1)).step_by(ptr_size) {
+                if off + ptr_size <= new_from {
+                    continue;
+                }
+
+                let target = read_ptr(&data[off..off + ptr_size], little_endian);
+                if is_mapped(regions, target) {
+                    entries.push((target, base + off));
+                }
+            }
+        });
+    }
+}
+
+fn read_ptr(bytes: &[u8], little_endian: bool) -> Address {
+    let fold = |value: u64, &byte: &u8| (value << 8) | u64::from(byte);
+    let value = if little_endian {
+        bytes.iter().rev().fold(0, fold)
+    } else {
+        bytes.iter().fold(0, fold)
+    };
+    Address::from(value)
+}
+
+fn is_mapped(regions: &[Range<Address>], addr: Address) -> bool {
+    let idx = regions.partition_point(|r| r.start <= addr);
+    idx > 0 && addr < regions[idx - 1].end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    #[test]
+    fn reverse_lookup() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        let obj = base + 0x1000usize;
+        proc.write(base + 0x10usize, &obj.to_umem()).unwrap();
+        proc.write(base + 0x18usize, &(obj + 8usize).to_umem())
+            .unwrap();
+        // unaligned and unmapped values are no pointers
+        proc.write(base + 0x21usize, &obj.to_umem()).unwrap();
+        proc.write(base + 0x30usize, &0x10u64).unwrap();
+
+        let mut map = PointerMap::build(&mut proc).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            map.pointers_into(obj..obj + 0x10usize).collect::<Vec<_>>(),
+            vec![(base + 0x10usize, obj), (base + 0x18usize, obj + 8usize)]
+        );
+
+        proc.write(base + 0x10usize, &0u64).unwrap();
+        proc.write(base + 0x808usize, &obj.to_umem()).unwrap();
+        map.update_region(&mut proc, base..base + 0x1000usize);
+
+        assert_eq!(
+            map.pointers_to(obj).collect::<Vec<_>>(),
+            vec![base + 0x808usize]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! Iterative value scanning.
+//!
+//! A [`ScanSession`] implements the workflow known from tools like Cheat Engine: an initial scan
+//! locates all occurrences of a value, subsequent rescans narrow the candidates down by
+//! comparing their current value with the one seen in the previous scan.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::scan::{ScanFilter, ScanSession, ScanValue, ValueType};
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let base = proc.info().address;
+//! # proc.write(base + 0x40usize, &100i32).unwrap();
+//!
+//! let mut session = ScanSession::new(ValueType::I32);
+//! session
+//!     .first_scan_process(&mut proc, &ScanValue::I32(100))
+//!     .unwrap();
+//!
+//! // the value was increased by the target in the meantime
+//! # proc.write(base + 0x40usize, &105i32).unwrap();
+//! session
+//!     .rescan(&mut proc, &ScanFilter::IncreasedBy(ScanValue::I32(5)))
+//!     .unwrap();
+//!
+//! assert_eq!(session.addresses().collect::<Vec<_>>(), vec![base + 0x40usize]);
+//! ```
+
+use super::{MemoryScan, MultiPattern, Pattern};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::os::Process;
+use crate::types::Address;
+
+use core::cmp::Ordering;
+use core::convert::TryInto;
+use core::ops::Range;
+use std::prelude::v1::*;
+
+/// Number of candidates read back in a single batch during rescans.
+const RESCAN_BATCH: usize = 0x1000;
+
+/// Type of the values a [`ScanSession`] looks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    I32,
+    F32,
+    F64,
+    /// Byte strings of the given length.
+    Bytes(usize),
+}
+
+impl ValueType {
+    /// Returns the size of a value in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            ValueType::I32 | ValueType::F32 => 4,
+            ValueType::F64 => 8,
+            ValueType::Bytes(len) => len,
+        }
+    }
+
+    /// Returns the alignment candidates are expected at by default.
+    pub fn alignment(self) -> usize {
+        match self {
+            ValueType::Bytes(_) => 1,
+            _ => self.size(),
+        }
+    }
+}
+
+/// A typed value to scan for or to compare with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScanValue {
+    I32(i32),
+    F32(f32),
+    F64(f64),
+    Bytes(Vec<u8>),
+}
+
+impl ScanValue {
+    /// Returns the type of the value.
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            ScanValue::I32(_) => ValueType::I32,
+            ScanValue::F32(_) => ValueType::F32,
+            ScanValue::F64(_) => ValueType::F64,
+            ScanValue::Bytes(bytes) => ValueType::Bytes(bytes.len()),
+        }
+    }
+
+    /// Returns the in-memory representation of the value.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ScanValue::I32(v) => v.to_ne_bytes().to_vec(),
+            ScanValue::F32(v) => v.to_ne_bytes().to_vec(),
+            ScanValue::F64(v) => v.to_ne_bytes().to_vec(),
+            ScanValue::Bytes(bytes) => bytes.clone(),
+        }
+    }
+
+    /// Decodes a value of type `ty` from its in-memory representation.
+    pub fn from_bytes(ty: ValueType, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ty.size() {
+            return None;
+        }
+
+        Some(match ty {
+            ValueType::I32 => ScanValue::I32(i32::from_ne_bytes(bytes.try_into().ok()?)),
+            ValueType::F32 => ScanValue::F32(f32::from_ne_bytes(bytes.try_into().ok()?)),
+            ValueType::F64 => ScanValue::F64(f64::from_ne_bytes(bytes.try_into().ok()?)),
+            ValueType::Bytes(_) => ScanValue::Bytes(bytes.to_vec()),
+        })
+    }
+}
+
+/// Predicate applied to the candidates of a [`ScanSession`] during a rescan.
+///
+/// The comparisons against the previous value refer to the value seen in the last scan.
+/// Floating point differences are compared with a tolerance of one unit of precision.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScanFilter {
+    /// The value equals the given one.
+    Exact(ScanValue),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    /// The value was increased by exactly the given amount.
+    IncreasedBy(ScanValue),
+    /// The value was decreased by exactly the given amount.
+    DecreasedBy(ScanValue),
+}
+
+impl ScanFilter {
+    fn validate(&self, ty: ValueType) -> Result<()> {
+        let valid = match self {
+            ScanFilter::Exact(value) => value.value_type() == ty,
+            ScanFilter::Changed | ScanFilter::Unchanged => true,
+            ScanFilter::Increased | ScanFilter::Decreased => !matches!(ty, ValueType::Bytes(_)),
+            ScanFilter::IncreasedBy(value) | ScanFilter::DecreasedBy(value) => {
+                value.value_type() == ty && !matches!(ty, ValueType::Bytes(_))
+            }
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(Error(ErrorOrigin::Other, ErrorKind::InvalidArgument)
+                .log_error("scan filter does not match the value type of the session"))
+        }
+    }
+
+    fn matches(&self, ty: ValueType, old: &[u8], new: &[u8]) -> bool {
+        let decode = |bytes: &[u8]| ScanValue::from_bytes(ty, bytes);
+
+        match (self, decode(old), decode(new)) {
+            (ScanFilter::Exact(value), _, _) => value.to_bytes() == new,
+            (ScanFilter::Changed, _, _) => old != new,
+            (ScanFilter::Unchanged, _, _) => old == new,
+            (ScanFilter::Increased, Some(old), Some(new)) => {
+                compare(&old, &new) == Some(Ordering::Greater)
+            }
+            (ScanFilter::Decreased, Some(old), Some(new)) => {
+                compare(&old, &new) == Some(Ordering::Less)
+            }
+            (ScanFilter::IncreasedBy(by), Some(old), Some(new)) => offset_by(&old, &new, by, 1),
+            (ScanFilter::DecreasedBy(by), Some(old), Some(new)) => offset_by(&old, &new, by, -1),
+            _ => false,
+        }
+    }
+}
+
+/// Compares `new` to `old`.
+fn compare(old: &ScanValue, new: &ScanValue) -> Option<Ordering> {
+    match (old, new) {
+        (ScanValue::I32(old), ScanValue::I32(new)) => new.partial_cmp(old),
+        (ScanValue::F32(old), ScanValue::F32(new)) => new.partial_cmp(old),
+        (ScanValue::F64(old), ScanValue::F64(new)) => new.partial_cmp(old),
+        _ => None,
+    }
+}
+
+/// Checks whether `new` equals `old` moved by `by` into the direction of `sign`.
+fn offset_by(old: &ScanValue, new: &ScanValue, by: &ScanValue, sign: i32) -> bool {
+    match (old, new, by) {
+        (ScanValue::I32(old), ScanValue::I32(new), ScanValue::I32(by)) => {
+            new.wrapping_sub(*old) == by.wrapping_mul(sign)
+        }
+        (ScanValue::F32(old), ScanValue::F32(new), ScanValue::F32(by)) => {
+            let tolerance = f32::EPSILON * new.abs().max(old.abs()).max(1.0);
+            ((new - old) - by * sign as f32).abs() <= tolerance
+        }
+        (ScanValue::F64(old), ScanValue::F64(new), ScanValue::F64(by)) => {
+            let tolerance = f64::EPSILON * new.abs().max(old.abs()).max(1.0);
+            ((new - old) - by * sign as f64).abs() <= tolerance
+        }
+        _ => false,
+    }
+}
+
+/// Candidate addresses and their last seen values of an iterative value scan.
+///
+/// Candidates are stored as a sorted list of addresses and a single buffer holding all of their
+/// values back to back.
+pub struct ScanSession {
+    ty: ValueType,
+    alignment: usize,
+    addresses: Vec<Address>,
+    values: Vec<u8>,
+}
+
+impl ScanSession {
+    /// Creates an empty session scanning for values of type `ty`.
+    ///
+    /// Candidates are only searched at the natural alignment of the type by default.
+    pub fn new(ty: ValueType) -> Self {
+        Self {
+            ty,
+            alignment: ty.alignment(),
+            addresses: vec![],
+            values: vec![],
+        }
+    }
+
+    /// Sets the alignment candidates are searched at by the first scan.
+    pub fn alignment(mut self, alignment: usize) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Returns the type of the scanned values.
+    pub fn value_type(&self) -> ValueType {
+        self.ty
+    }
+
+    /// Returns the number of candidates.
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Returns the addresses of all candidates.
+    pub fn addresses(&self) -> impl Iterator<Item = Address> + '_ {
+        self.addresses.iter().copied()
+    }
+
+    /// Returns all candidates along with their value seen in the last scan.
+    pub fn candidates(&self) -> impl Iterator<Item = (Address, ScanValue)> + '_ {
+        let ty = self.ty;
+        self.addresses
+            .iter()
+            .zip(self.values.chunks(core::cmp::max(ty.size(), 1)))
+            .filter_map(move |(&addr, value)| Some((addr, ScanValue::from_bytes(ty, value)?)))
+    }
+
+    /// Replaces all candidates with the occurrences of `value` inside of `ranges`.
+    ///
+    /// Returns the number of candidates found.
+    pub fn first_scan<M: MemoryView>(
+        &mut self,
+        mem: &mut M,
+        ranges: impl IntoIterator<Item = Range<Address>>,
+        value: &ScanValue,
+    ) -> Result<usize> {
+        ScanFilter::Exact(value.clone()).validate(self.ty)?;
+
+        let bytes = value.to_bytes();
+        if bytes.is_empty() {
+            return Err(Error(ErrorOrigin::Other, ErrorKind::InvalidArgument)
+                .log_error("can not scan for an empty value"));
+        }
+
+        let patterns = MultiPattern::new(vec![Pattern::literal(&bytes)]);
+        self.addresses = mem
+            .find_patterns_in(ranges, &patterns, self.alignment)
+            .into_iter()
+            .map(|m| m.address)
+            .collect();
+        self.addresses.sort_unstable();
+        self.addresses.dedup();
+
+        self.values = bytes.repeat(self.addresses.len());
+
+        Ok(self.len())
+    }
+
+    /// Replaces all candidates with the occurrences of `value` in the mapped memory of `proc`.
+    pub fn first_scan_process<P: MemoryView + Process>(
+        &mut self,
+        proc: &mut P,
+        value: &ScanValue,
+    ) -> Result<usize> {
+        let ranges = proc
+            .mapped_mem_vec(0)
+            .into_iter()
+            .map(|r| r.0..r.0 + r.1)
+            .collect::<Vec<_>>();
+
+        self.first_scan(proc, ranges, value)
+    }
+
+    /// Reads the current values of all candidates and keeps the ones matching `filter`.
+    ///
+    /// Candidates that can no longer be read are dropped. Returns the number of remaining
+    /// candidates.
+    pub fn rescan<M: MemoryView>(&mut self, mem: &mut M, filter: &ScanFilter) -> Result<usize> {
+        filter.validate(self.ty)?;
+
+        if self.is_empty() {
+            return Ok(0);
+        }
+
+        let size = self.ty.size();
+        let mut addresses = Vec::with_capacity(self.addresses.len());
+        let mut values = Vec::with_capacity(self.values.len());
+
+        for (batch, old_values) in self
+            .addresses
+            .chunks(RESCAN_BATCH)
+            .zip(self.values.chunks(RESCAN_BATCH * size))
+        {
+            let mut scatter = mem.scatter();
+            let handles = batch
+                .iter()
+                .map(|&addr| scatter.read_raw(addr, size))
+                .collect::<Vec<_>>();
+            let results = scatter.execute()?;
+
+            for ((&addr, handle), old) in batch.iter().zip(handles).zip(old_values.chunks(size)) {
+                if let Some(new) = results.get_raw(handle) {
+                    if filter.matches(self.ty, old, new) {
+                        addresses.push(addr);
+                        values.extend_from_slice(new);
+                    }
+                }
+            }
+        }
+
+        self.addresses = addresses;
+        self.values = values;
+
+        Ok(self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    #[test]
+    fn refine_candidates() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+
+        for off in [0x10usize, 0x20, 0x30].iter() {
+            proc.write(base + *off, &1.5f32).unwrap();
+        }
+        // unaligned occurrences are ignored
+        proc.write(base + 0x42usize, &1.5f32).unwrap();
+
+        let mut session = ScanSession::new(ValueType::F32);
+        let range = base..base + size::kb(4);
+        assert_eq!(
+            session
+                .first_scan(&mut proc, Some(range), &ScanValue::F32(1.5))
+                .unwrap(),
+            3
+        );
+
+        proc.write(base + 0x10usize, &2.0f32).unwrap();
+        proc.write(base + 0x20usize, &1.0f32).unwrap();
+
+        assert_eq!(session.rescan(&mut proc, &ScanFilter::Changed).unwrap(), 2);
+
+        proc.write(base + 0x10usize, &2.5f32).unwrap();
+        proc.write(base + 0x20usize, &0.5f32).unwrap();
+
+        assert_eq!(
+            session
+                .rescan(&mut proc, &ScanFilter::IncreasedBy(ScanValue::F32(0.5)))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            session.candidates().collect::<Vec<_>>(),
+            vec![(base + 0x10usize, ScanValue::F32(2.5))]
+        );
+
+        assert!(session
+            .rescan(&mut proc, &ScanFilter::Exact(ScanValue::I32(1)))
+            .is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Scanning memory with YARA rules.
+//!
+//! The [`YaraScanner`] runs compiled YARA rules directly over the memory of a process or over
+//! physical memory, without dumping it to disk first. Memory is scanned in overlapping chunks,
+//! string matches crossing a chunk boundary are found as long as they are shorter than the
+//! configured [`overlap`](YaraScanner::overlap). Conditions are evaluated per chunk, so rules
+//! depending on the layout of a whole file (e.g. `filesize` or module headers) are not suited
+//! for memory scanning.
+//!
+//! This module is only available with the `yara_scan` feature enabled.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use memflow::prelude::v1::*;
+//! use memflow::scan::yara::YaraScanner;
+//!
+//! fn scan(proc: &mut (impl Process + MemoryView)) -> Result<()> {
+//!     let rules = ::yara::Compiler::new()
+//!         .and_then(|c| c.add_rules_str(r#"rule memflow { strings: $a = "memflow" condition: $a }"#))
+//!         .and_then(|c| c.compile_rules())
+//!         .unwrap();
+//!
+//!     for m in YaraScanner::new(&rules).scan_process(proc)? {
+//!         println!("{} {} at {:x} in {:?}", m.rule, m.string, m.address, m.module);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use super::chunks::{scan_chunks_with_size, DEFAULT_CHUNK_SIZE};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{MemoryView, PhysicalMemory};
+use crate::os::Process;
+use crate::types::{size, umem, Address};
+
+use core::ops::Range;
+use std::prelude::v1::*;
+
+use ::yara::Rules;
+
+/// Default amount of bytes shared by two consecutive chunks.
+pub const DEFAULT_OVERLAP: usize = size::kb(4);
+
+/// A single string match of a YARA rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct YaraMatch {
+    /// Identifier of the rule.
+    pub rule: String,
+    /// Namespace of the rule.
+    pub namespace: String,
+    /// Identifier of the matching string, e.g. `$a`.
+    pub string: String,
+    /// Address of the match.
+    pub address: Address,
+    /// Length of the match in bytes.
+    pub len: usize,
+    /// Name of the module containing the match, only set for process scans.
+    pub module: Option<String>,
+}
+
+/// Runs YARA rules over memory.
+pub struct YaraScanner<'a> {
+    rules: &'a Rules,
+    chunk_size: usize,
+    overlap: usize,
+    timeout: i32,
+}
+
+impl<'a> YaraScanner<'a> {
+    /// Creates a scanner for a set of compiled rules.
+    pub fn new(rules: &'a Rules) -> Self {
+        Self {
+            rules,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            overlap: DEFAULT_OVERLAP,
+            timeout: 0,
+        }
+    }
+
+    /// Sets the amount of bytes passed to YARA at once.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets the maximum length of matches that are found across chunk boundaries.
+    pub fn overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Sets the timeout for scanning a single chunk in seconds, 0 disables the timeout.
+    pub fn timeout(mut self, timeout: i32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Scans all `ranges` of `mem`.
+    ///
+    /// Matches are returned sorted by address, the owning module is not resolved.
+    pub fn scan_view<M: MemoryView>(
+        &self,
+        mem: &mut M,
+        ranges: impl IntoIterator<Item = Range<Address>>,
+    ) -> Result<Vec<YaraMatch>> {
+        let mut out = vec![];
+        let mut error = None;
+
+        for range in ranges {
+            scan_chunks_with_size(
+                mem,
+                range,
+                self.overlap,
+                self.chunk_size,
+                |base, data, new_from| {
+                    if error.is_some() {
+                        return;
+                    }
+
+                    match self.rules.scan_mem(data, self.timeout) {
+                        Ok(rules) => {
+                            for rule in rules {
+                                for string in rule.strings.iter() {
+                                    out.extend(
+                                        string
+                                            .matches
+                                            .iter()
+                                            .filter(|m| m.offset + m.length > new_from)
+                                            .map(|m| YaraMatch {
+                                                rule: rule.identifier.to_string(),
+                                                namespace: rule.namespace.to_string(),
+                                                string: string.identifier.to_string(),
+                                                address: base + m.offset,
+                                                len: m.length,
+                                                module: None,
+                                            }),
+                                    );
+                                }
+                            }
+                        }
+                        Err(err) => error = Some(err),
+                    }
+                },
+            );
+
+            if let Some(err) = error.take() {
+                return Err(Error(ErrorOrigin::Other, ErrorKind::Unknown).log_error(err));
+            }
+        }
+
+        out.sort_by_key(|m| m.address);
+        Ok(out)
+    }
+
+    /// Scans the mapped memory of a process and resolves the modules containing the matches.
+    pub fn scan_process<P: Process + MemoryView>(&self, proc: &mut P) -> Result<Vec<YaraMatch>> {
+        let ranges = proc
+            .mapped_mem_vec(0)
+            .into_iter()
+            .map(|r| r.0..r.0 + r.1)
+            .collect::<Vec<_>>();
+
+        let mut out = self.scan_view(proc, ranges)?;
+
+        // not all os layers are able to list modules
+        let mut modules = proc.module_list().unwrap_or_default();
+        modules.sort_by_key(|m| m.base);
+
+        for m in out.iter_mut() {
+            let idx = modules.partition_point(|module| module.base <= m.address);
+            m.module = idx
+                .checked_sub(1)
+                .map(|idx| &modules[idx])
+                .filter(|module| ((m.address - module.base) as umem) < module.size)
+                .map(|module| module.name.as_ref().to_string());
+        }
+
+        Ok(out)
+    }
+
+    /// Scans the whole physical address space of `mem`.
+    pub fn scan_phys<T: PhysicalMemory>(&self, mem: &mut T) -> Result<Vec<YaraMatch>> {
+        let end = mem.metadata().max_address + 1usize;
+        self.scan_view(&mut mem.phys_view(), Some(Address::null()..end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+
+    #[test]
+    fn scan_process() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let base = proc.info().address;
+        proc.write(base + 0x1ffeusize, b"memflow").unwrap();
+
+        let rules = ::yara::Compiler::new()
+            .and_then(|c| c.add_rules_str(r#"rule mf { strings: $a = "memflow" condition: $a }"#))
+            .and_then(|c| c.compile_rules())
+            .unwrap();
+
+        let found = YaraScanner::new(&rules)
+            .chunk_size(0x1000)
+            .scan_process(&mut proc)
+            .unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].rule, "mf");
+        assert_eq!(found[0].string, "$a");
+        assert_eq!(found[0].address, base + 0x1ffeusize);
+    }
+}
@@ -0,0 +1,460 @@
+/*!
+Scripting of analysis tasks with [rhai](https://rhai.rs).
+
+A [`ScriptEngine`] exposes the plugin inventory, OS layers, processes, connectors and the
+pattern scanner to rhai scripts. This allows shipping small analysis scripts that run against
+any memflow target without recompiling Rust.
+
+The engine registers bindings for the plugin instance types by default. Statically linked types
+(e.g. a specific OS layer) can be registered with [`ScriptEngine::register_os`] and its
+siblings.
+
+All addresses and sizes are plain integers on the script side, byte buffers are blobs.
+
+# Bindings
+
+* `inventory.connectors()`, `inventory.os_layers()` - names of the available plugins.
+* `inventory.connector(name, args)` - creates a connector.
+* `inventory.os(name, args)`, `inventory.os(name, args, connector)` - creates an OS layer.
+* OS: `process_list()`, `process_by_name(name)`, `process_by_pid(pid)`, `module_list()`.
+* Process: `info`, `module_list()`, `module_by_name(name)` and all memory view functions.
+* Connector: `max_address`, `phys_view()`, which returns a memory view of physical memory.
+* Memory view: `read(addr, len)`, `read_u8/u16/u32/u64(addr)`, `read_string(addr, max_len)`,
+  `write(addr, blob)`, `write_u8/u16/u32/u64(addr, value)`,
+  `scan(addr, len, pattern)` returning the addresses of all matches.
+* Process info: `pid`, `name`, `path`, `command_line`, `address`.
+* Module info: `name`, `path`, `base`, `size`, `address`.
+
+# Examples
+
+```no_run
+use memflow::plugins::Inventory;
+use memflow::scripting::ScriptEngine;
+
+let mut engine = ScriptEngine::new().with_inventory(Inventory::scan());
+
+engine
+    .run(
+        r#"
+        let os = inventory.os("win32", "", inventory.connector("qemu", ""));
+        for p in os.process_list() {
+            print(p.pid + " " + p.name);
+        }
+        "#,
+    )
+    .unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::phys_mem::PhysicalMemoryView;
+use crate::mem::{MemoryView, PhysicalMemory};
+use crate::os::{ModuleInfo, Os, OsInner, Process, ProcessInfo};
+use crate::plugins::{ConnectorArgs, ConnectorInstanceArcBox, Inventory, OsArgs, OsInstanceArcBox};
+use crate::scan::{MemoryScan, Pattern};
+use crate::types::Address;
+
+use rhai::{Array, Blob, Dynamic, Engine, EvalAltResult, Scope, INT};
+
+use std::path::Path;
+use std::rc::Rc;
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// Plugin inventory as seen by scripts.
+#[derive(Clone)]
+pub struct ScriptInventory(Rc<Inventory>);
+
+/// A rhai engine with memflow bindings.
+pub struct ScriptEngine {
+    engine: Engine,
+    scope: Scope<'static>,
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptEngine {
+    /// Creates an engine with bindings for the plugin instance types.
+    pub fn new() -> Self {
+        let mut ret = Self {
+            engine: Engine::new(),
+            scope: Scope::new(),
+        };
+
+        ret.register_info();
+        ret.register_inventory();
+        ret.register_os::<OsInstanceArcBox<'static>>("Os");
+        ret.register_connector::<ConnectorInstanceArcBox<'static>>("Connector");
+
+        ret
+    }
+
+    /// Makes `inventory` available to scripts.
+    pub fn with_inventory(mut self, inventory: Inventory) -> Self {
+        self.scope
+            .push_constant("inventory", ScriptInventory(Rc::new(inventory)));
+        self
+    }
+
+    /// Makes an OS layer available to scripts as `os`.
+    ///
+    /// The type has to be registered with [`register_os`](Self::register_os) first, unless it
+    /// is a plugin instance.
+    pub fn with_os<T: Os + Clone + 'static>(mut self, os: T) -> Self {
+        self.scope.push("os", os);
+        self
+    }
+
+    /// Makes a connector available to scripts as `connector`.
+    pub fn with_connector<T: PhysicalMemory + Clone + 'static>(mut self, connector: T) -> Self {
+        self.scope.push("connector", connector);
+        self
+    }
+
+    /// Returns the underlying engine, e.g. to register custom functions.
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// Returns the scope scripts are run in.
+    ///
+    /// Variables defined at the top level of a script are kept in the scope between runs.
+    pub fn scope_mut(&mut self) -> &mut Scope<'static> {
+        &mut self.scope
+    }
+
+    /// Registers an OS type and the type of its processes.
+    pub fn register_os<T: Os + Clone + 'static>(&mut self, name: &str) -> &mut Self {
+        self.engine
+            .register_type_with_name::<T>(name)
+            .register_fn("process_list", |os: &mut T| -> ScriptResult<Array> {
+                Ok(to_script(os.process_info_list())?
+                    .into_iter()
+                    .map(Dynamic::from)
+                    .collect())
+            })
+            .register_fn(
+                "process_by_name",
+                |os: &mut T,
+                 name: &str|
+                 -> ScriptResult<<T as OsInner<'static>>::IntoProcessType> {
+                    to_script(<T as OsInner<'static>>::into_process_by_name(
+                        os.clone(),
+                        name,
+                    ))
+                },
+            )
+            .register_fn(
+                "process_by_pid",
+                |os: &mut T, pid: INT| -> ScriptResult<<T as OsInner<'static>>::IntoProcessType> {
+                    to_script(<T as OsInner<'static>>::into_process_by_pid(
+                        os.clone(),
+                        pid as _,
+                    ))
+                },
+            )
+            .register_fn("module_list", |os: &mut T| -> ScriptResult<Array> {
+                Ok(to_script(os.module_list())?
+                    .into_iter()
+                    .map(Dynamic::from)
+                    .collect())
+            });
+
+        self.register_process::<<T as OsInner<'static>>::IntoProcessType>(&format!(
+            "{}Process",
+            name
+        ))
+    }
+
+    /// Registers a process type.
+    pub fn register_process<T: Process + MemoryView + Clone + 'static>(
+        &mut self,
+        name: &str,
+    ) -> &mut Self {
+        self.engine
+            .register_type_with_name::<T>(name)
+            .register_get("info", |proc: &mut T| proc.info().clone())
+            .register_fn("module_list", |proc: &mut T| -> ScriptResult<Array> {
+                Ok(to_script(proc.module_list())?
+                    .into_iter()
+                    .map(Dynamic::from)
+                    .collect())
+            })
+            .register_fn(
+                "module_by_name",
+                |proc: &mut T, name: &str| -> ScriptResult<ModuleInfo> {
+                    to_script(proc.module_by_name(name))
+                },
+            );
+
+        self.register_memory_view::<T>()
+    }
+
+    /// Registers a connector type.
+    pub fn register_connector<T: PhysicalMemory + Clone + 'static>(
+        &mut self,
+        name: &str,
+    ) -> &mut Self {
+        self.engine
+            .register_type_with_name::<T>(name)
+            .register_get("max_address", |conn: &mut T| {
+                address_to_int(conn.metadata().max_address)
+            })
+            .register_type_with_name::<PhysicalMemoryView<T>>(&format!("{}View", name))
+            .register_fn("phys_view", |conn: &mut T| conn.clone().into_phys_view());
+
+        self.register_memory_view::<PhysicalMemoryView<T>>()
+    }
+
+    /// Registers the memory view functions for a type.
+    ///
+    /// The type itself has to be registered separately.
+    pub fn register_memory_view<T: MemoryView + Clone + 'static>(&mut self) -> &mut Self {
+        self.engine
+            .register_fn(
+                "read",
+                |mem: &mut T, addr: INT, len: INT| -> ScriptResult<Blob> {
+                    let mut buf = vec![0; len as usize];
+                    to_script(
+                        mem.read_raw_into(int_to_address(addr), &mut buf)
+                            .data_part(),
+                    )?;
+                    Ok(buf)
+                },
+            )
+            .register_fn("read_u8", |mem: &mut T, addr: INT| -> ScriptResult<INT> {
+                Ok(to_script(mem.read::<u8>(int_to_address(addr)).data_part())? as INT)
+            })
+            .register_fn("read_u16", |mem: &mut T, addr: INT| -> ScriptResult<INT> {
+                Ok(to_script(mem.read::<u16>(int_to_address(addr)).data_part())? as INT)
+            })
+            .register_fn("read_u32", |mem: &mut T, addr: INT| -> ScriptResult<INT> {
+                Ok(to_script(mem.read::<u32>(int_to_address(addr)).data_part())? as INT)
+            })
+            .register_fn("read_u64", |mem: &mut T, addr: INT| -> ScriptResult<INT> {
+                Ok(to_script(mem.read::<u64>(int_to_address(addr)).data_part())? as INT)
+            })
+            .register_fn(
+                "read_string",
+                |mem: &mut T, addr: INT, max_len: INT| -> ScriptResult<String> {
+                    to_script(
+                        mem.read_char_string_n(int_to_address(addr), max_len as usize)
+                            .data_part(),
+                    )
+                },
+            )
+            .register_fn(
+                "write",
+                |mem: &mut T, addr: INT, data: Blob| -> ScriptResult<()> {
+                    to_script(mem.write_raw(int_to_address(addr), &data).data_part())
+                },
+            )
+            .register_fn(
+                "write_u8",
+                |mem: &mut T, addr: INT, value: INT| -> ScriptResult<()> {
+                    to_script(mem.write(int_to_address(addr), &(value as u8)).data_part())
+                },
+            )
+            .register_fn(
+                "write_u16",
+                |mem: &mut T, addr: INT, value: INT| -> ScriptResult<()> {
+                    to_script(mem.write(int_to_address(addr), &(value as u16)).data_part())
+                },
+            )
+            .register_fn(
+                "write_u32",
+                |mem: &mut T, addr: INT, value: INT| -> ScriptResult<()> {
+                    to_script(mem.write(int_to_address(addr), &(value as u32)).data_part())
+                },
+            )
+            .register_fn(
+                "write_u64",
+                |mem: &mut T, addr: INT, value: INT| -> ScriptResult<()> {
+                    to_script(mem.write(int_to_address(addr), &(value as u64)).data_part())
+                },
+            )
+            .register_fn(
+                "scan",
+                |mem: &mut T, addr: INT, len: INT, pattern: &str| -> ScriptResult<Array> {
+                    let pattern = to_script(Pattern::parse(pattern))?;
+                    let start = int_to_address(addr);
+                    Ok(mem
+                        .find_pattern(start..start + len as usize, &pattern)
+                        .into_iter()
+                        .map(|a| Dynamic::from(address_to_int(a)))
+                        .collect())
+                },
+            );
+
+        self
+    }
+
+    /// Runs a script.
+    pub fn run(&mut self, script: &str) -> Result<()> {
+        self.engine
+            .run_with_scope(&mut self.scope, script)
+            .map_err(script_error)
+    }
+
+    /// Runs a script and returns the value of its last expression.
+    pub fn eval(&mut self, script: &str) -> Result<Dynamic> {
+        self.engine
+            .eval_with_scope::<Dynamic>(&mut self.scope, script)
+            .map_err(script_error)
+    }
+
+    /// Runs a script file.
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let script = std::fs::read_to_string(path.as_ref()).map_err(|err| {
+            Error(ErrorOrigin::Other, ErrorKind::UnableToReadFile).log_error(format!(
+                "unable to read script {}: {}",
+                path.as_ref().display(),
+                err
+            ))
+        })?;
+
+        self.run(&script)
+    }
+
+    fn register_info(&mut self) {
+        self.engine
+            .register_type_with_name::<ProcessInfo>("ProcessInfo")
+            .register_get("pid", |info: &mut ProcessInfo| info.pid as INT)
+            .register_get("name", |info: &mut ProcessInfo| {
+                info.name.as_ref().to_string()
+            })
+            .register_get("path", |info: &mut ProcessInfo| {
+                info.path.as_ref().to_string()
+            })
+            .register_get("command_line", |info: &mut ProcessInfo| {
+                info.command_line.as_ref().to_string()
+            })
+            .register_get("address", |info: &mut ProcessInfo| {
+                address_to_int(info.address)
+            })
+            .register_type_with_name::<ModuleInfo>("ModuleInfo")
+            .register_get("name", |info: &mut ModuleInfo| {
+                info.name.as_ref().to_string()
+            })
+            .register_get("path", |info: &mut ModuleInfo| {
+                info.path.as_ref().to_string()
+            })
+            .register_get("base", |info: &mut ModuleInfo| address_to_int(info.base))
+            .register_get("size", |info: &mut ModuleInfo| info.size as INT)
+            .register_get("address", |info: &mut ModuleInfo| {
+                address_to_int(info.address)
+            });
+    }
+
+    fn register_inventory(&mut self) {
+        self.engine
+            .register_type_with_name::<ScriptInventory>("Inventory")
+            .register_fn("connectors", |inv: &mut ScriptInventory| -> Array {
+                inv.0
+                    .available_connectors()
+                    .into_iter()
+                    .map(Dynamic::from)
+                    .collect()
+            })
+            .register_fn("os_layers", |inv: &mut ScriptInventory| -> Array {
+                inv.0
+                    .available_os()
+                    .into_iter()
+                    .map(Dynamic::from)
+                    .collect()
+            })
+            .register_fn(
+                "connector",
+                |inv: &mut ScriptInventory,
+                 name: &str,
+                 args: &str|
+                 -> ScriptResult<ConnectorInstanceArcBox<'static>> {
+                    let args = to_script(args.parse::<ConnectorArgs>())?;
+                    to_script(inv.0.create_connector(name, None, Some(&args)))
+                },
+            )
+            .register_fn(
+                "os",
+                |inv: &mut ScriptInventory,
+                 name: &str,
+                 args: &str|
+                 -> ScriptResult<OsInstanceArcBox<'static>> {
+                    let args = to_script(args.parse::<OsArgs>())?;
+                    to_script(inv.0.create_os(name, None, Some(&args)))
+                },
+            )
+            .register_fn(
+                "os",
+                |inv: &mut ScriptInventory,
+                 name: &str,
+                 args: &str,
+                 connector: ConnectorInstanceArcBox<'static>|
+                 -> ScriptResult<OsInstanceArcBox<'static>> {
+                    let args = to_script(args.parse::<OsArgs>())?;
+                    to_script(inv.0.create_os(name, Some(connector), Some(&args)))
+                },
+            );
+    }
+}
+
+fn int_to_address(addr: INT) -> Address {
+    Address::from(addr as u64)
+}
+
+fn address_to_int(addr: Address) -> INT {
+    addr.to_umem() as INT
+}
+
+fn to_script<T>(res: Result<T>) -> ScriptResult<T> {
+    res.map_err(|err| err.to_string().into())
+}
+
+fn script_error(err: Box<EvalAltResult>) -> Error {
+    Error(ErrorOrigin::Other, ErrorKind::Unknown).log_error(format!("script failed: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    #[test]
+    fn process_bindings() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(8)));
+        let pid = os.alloc_process(size::mb(1), &[0xde, 0xad, 0xbe, 0xef]);
+
+        let mut engine = ScriptEngine::new();
+        engine.register_os::<DummyOs>("DummyOs");
+        let mut engine = engine.with_os(os);
+        engine.scope_mut().push("pid", pid as INT);
+
+        let ret = engine
+            .eval(
+                r#"
+                let proc = os.process_by_pid(pid);
+                let base = proc.info.address;
+                proc.write_u32(base + 0x100, 0x1234);
+                [proc.scan(base, 0x1000, "de ad ?? ef").len(), proc.read_u32(base + 0x100)]
+                "#,
+            )
+            .unwrap()
+            .cast::<Array>();
+
+        assert_eq!(ret[0].as_int().unwrap(), 1);
+        assert_eq!(ret[1].as_int().unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn script_errors() {
+        let mut engine = ScriptEngine::new();
+        assert!(engine.run("let x = ").is_err());
+        assert!(engine.run("os.process_list()").is_err());
+    }
+}
@@ -0,0 +1,449 @@
+/*!
+Building blocks of interactive consoles.
+
+[`Command`] parses a single line of input and [`Shell`] executes commands against an OS,
+writing human readable output. Downstream CLIs and GUIs only have to provide the input loop,
+which keeps the console consistent across frontends.
+
+# Commands
+
+* `attach <pid|name>` - attaches to a process, all memory commands operate on it.
+* `detach` - detaches from the current process.
+* `ps` - lists all processes.
+* `modules` - lists the modules of the attached process.
+* `maps` - lists the mapped memory ranges of the attached process.
+* `rd <addr> [len]` - prints a hexdump of memory.
+* `wr <addr> <bytes>` - writes hex encoded bytes, e.g. `wr 1000 de ad be ef`.
+* `scan <addr> <len> <pattern>` - scans for a pattern, e.g. `scan 1000 0x2000 de ad ?? ef`.
+* `dump <addr> <len> <file>` - writes memory to a file.
+* `help` - lists all commands.
+
+Addresses are always hexadecimal, lengths are decimal unless prefixed with `0x`.
+
+# Examples
+
+```
+use memflow::dummy::{DummyMemory, DummyOs};
+use memflow::shell::Shell;
+use memflow::types::size;
+
+let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+let pid = os.alloc_process(size::mb(1), &[]);
+
+let mut shell = Shell::new(os);
+let mut out = vec![];
+
+shell.run_line(&format!("attach {}", pid), &mut out).unwrap();
+shell.run_line("maps", &mut out).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{Os, OsInner, Pid, Process};
+use crate::scan::{MemoryScan, Pattern};
+use crate::types::{imem, umem, Address};
+
+use std::io::Write;
+use std::str::FromStr;
+
+/// Number of bytes `rd` prints when no length is given.
+pub const DEFAULT_READ_LEN: usize = 0x40;
+
+/// Gap size used to merge ranges printed by `maps`.
+pub const MAPS_GAP_SIZE: imem = 0x1000;
+
+/// Process to attach to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttachTarget {
+    Pid(Pid),
+    Name(String),
+}
+
+/// A single parsed console command.
+#[derive(Clone, Debug)]
+pub enum Command {
+    Attach(AttachTarget),
+    Detach,
+    Ps,
+    Modules,
+    Maps,
+    Read {
+        address: Address,
+        len: usize,
+    },
+    Write {
+        address: Address,
+        data: Vec<u8>,
+    },
+    Scan {
+        address: Address,
+        len: usize,
+        pattern: Pattern,
+    },
+    Dump {
+        address: Address,
+        len: usize,
+        path: String,
+    },
+    Help,
+}
+
+impl Command {
+    /// Parses a line of input.
+    ///
+    /// Returns `None` for empty lines.
+    pub fn parse(line: &str) -> Result<Option<Self>> {
+        let mut args = line.split_whitespace();
+
+        let cmd = match args.next() {
+            Some(cmd) => cmd,
+            None => return Ok(None),
+        };
+
+        let args = args.collect::<Vec<_>>();
+
+        let cmd = match (cmd, args.as_slice()) {
+            ("attach", [target]) => Command::Attach(match target.parse::<Pid>() {
+                Ok(pid) => AttachTarget::Pid(pid),
+                Err(_) => AttachTarget::Name(target.to_string()),
+            }),
+            ("detach", []) => Command::Detach,
+            ("ps", []) => Command::Ps,
+            ("modules", []) => Command::Modules,
+            ("maps", []) => Command::Maps,
+            ("rd", [address]) => Command::Read {
+                address: parse_address(address)?,
+                len: DEFAULT_READ_LEN,
+            },
+            ("rd", [address, len]) => Command::Read {
+                address: parse_address(address)?,
+                len: parse_len(len)?,
+            },
+            ("wr", [address, data @ ..]) if !data.is_empty() => Command::Write {
+                address: parse_address(address)?,
+                data: parse_bytes(&data.concat())?,
+            },
+            ("scan", [address, len, pattern @ ..]) if !pattern.is_empty() => Command::Scan {
+                address: parse_address(address)?,
+                len: parse_len(len)?,
+                pattern: Pattern::parse(&pattern.join(" "))?,
+            },
+            ("dump", [address, len, path]) => Command::Dump {
+                address: parse_address(address)?,
+                len: parse_len(len)?,
+                path: path.to_string(),
+            },
+            ("help", []) => Command::Help,
+            _ => {
+                return Err(Error(ErrorOrigin::Args, ErrorKind::InvalidArgument)
+                    .log_error(format!("invalid command: {}", line.trim())))
+            }
+        };
+
+        Ok(Some(cmd))
+    }
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Command::parse(s)?.ok_or_else(|| {
+            Error(ErrorOrigin::Args, ErrorKind::InvalidArgument).log_error("empty command")
+        })
+    }
+}
+
+/// Executes console commands against an OS.
+pub struct Shell<T: Os> {
+    os: T,
+    process: Option<<T as OsInner<'static>>::IntoProcessType>,
+}
+
+impl<T: Os + Clone> Shell<T> {
+    pub fn new(os: T) -> Self {
+        Self { os, process: None }
+    }
+
+    pub fn os_mut(&mut self) -> &mut T {
+        &mut self.os
+    }
+
+    /// Returns the attached process.
+    pub fn process_mut(&mut self) -> Option<&mut <T as OsInner<'static>>::IntoProcessType> {
+        self.process.as_mut()
+    }
+
+    /// Parses and executes a line of input, empty lines are ignored.
+    pub fn run_line(&mut self, line: &str, out: &mut (impl Write + ?Sized)) -> Result<()> {
+        match Command::parse(line)? {
+            Some(cmd) => self.execute(&cmd, out),
+            None => Ok(()),
+        }
+    }
+
+    /// Executes a command and writes its output to `out`.
+    pub fn execute(&mut self, cmd: &Command, out: &mut (impl Write + ?Sized)) -> Result<()> {
+        match cmd {
+            Command::Attach(target) => {
+                let os = self.os.clone();
+                let process = match target {
+                    AttachTarget::Pid(pid) => {
+                        <T as OsInner<'static>>::into_process_by_pid(os, *pid)?
+                    }
+                    AttachTarget::Name(name) => {
+                        <T as OsInner<'static>>::into_process_by_name(os, name)?
+                    }
+                };
+
+                let info = process.info();
+                write_out(
+                    out,
+                    format_args!("attached to {} ({})\n", info.name, info.pid),
+                )?;
+
+                self.process = Some(process);
+            }
+            Command::Detach => self.process = None,
+            Command::Ps => {
+                for info in self.os.process_info_list()? {
+                    write_out(
+                        out,
+                        format_args!(
+                            "{:>8} {:>16x} {:?} {}\n",
+                            info.pid,
+                            info.address.to_umem(),
+                            info.proc_arch,
+                            info.name
+                        ),
+                    )?;
+                }
+            }
+            Command::Modules => {
+                for module in self.attached()?.module_list()? {
+                    write_out(
+                        out,
+                        format_args!(
+                            "{:>16x} {:>10x} {}\n",
+                            module.base.to_umem(),
+                            module.size,
+                            module.name
+                        ),
+                    )?;
+                }
+            }
+            Command::Maps => {
+                for range in self.attached()?.mapped_mem_vec(MAPS_GAP_SIZE) {
+                    write_out(
+                        out,
+                        format_args!(
+                            "{:>16x} {:>16x} {:?}\n",
+                            range.0.to_umem(),
+                            (range.0 + range.1).to_umem(),
+                            range.2
+                        ),
+                    )?;
+                }
+            }
+            Command::Read { address, len } => {
+                let mut buf = vec![0; *len];
+                self.attached()?
+                    .read_raw_into(*address, &mut buf)
+                    .data_part()?;
+
+                for (i, line) in buf.chunks(16).enumerate() {
+                    write_out(
+                        out,
+                        format_args!("{}\n", hexdump_line(*address + i * 16, line)),
+                    )?;
+                }
+            }
+            Command::Write { address, data } => {
+                self.attached()?.write_raw(*address, data).data_part()?;
+                write_out(
+                    out,
+                    format_args!("wrote {} bytes at {:x}\n", data.len(), address),
+                )?;
+            }
+            Command::Scan {
+                address,
+                len,
+                pattern,
+            } => {
+                let matches = self
+                    .attached()?
+                    .find_pattern(*address..*address + *len, pattern);
+
+                for addr in matches.iter() {
+                    write_out(out, format_args!("{:x}\n", addr))?;
+                }
+                write_out(out, format_args!("{} matches\n", matches.len()))?;
+            }
+            Command::Dump { address, len, path } => {
+                let mut buf = vec![0; *len];
+                self.attached()?
+                    .read_raw_into(*address, &mut buf)
+                    .data_part()?;
+
+                std::fs::write(path, &buf).map_err(|err| {
+                    Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile)
+                        .log_error(format!("unable to write {}: {}", path, err))
+                })?;
+                write_out(
+                    out,
+                    format_args!("dumped {} bytes to {}\n", buf.len(), path),
+                )?;
+            }
+            Command::Help => write_out(out, format_args!("{}", HELP))?,
+        }
+
+        Ok(())
+    }
+
+    fn attached(&mut self) -> Result<&mut <T as OsInner<'static>>::IntoProcessType> {
+        self.process.as_mut().ok_or_else(|| {
+            Error(ErrorOrigin::Other, ErrorKind::ProcessNotFound).log_error("no process attached")
+        })
+    }
+}
+
+const HELP: &str = "\
+attach <pid|name>            attach to a process
+detach                       detach from the process
+ps                           list processes
+modules                      list modules of the process
+maps                         list mapped memory of the process
+rd <addr> [len]              hexdump memory
+wr <addr> <bytes>            write hex encoded bytes
+scan <addr> <len> <pattern>  scan memory for a pattern
+dump <addr> <len> <file>     write memory to a file
+";
+
+fn write_out(out: &mut (impl Write + ?Sized), args: std::fmt::Arguments) -> Result<()> {
+    out.write_fmt(args).map_err(|err| {
+        Error(ErrorOrigin::Other, ErrorKind::UnableToWriteFile)
+            .log_error(format!("unable to write output: {}", err))
+    })
+}
+
+fn hexdump_line(address: Address, data: &[u8]) -> String {
+    let hex = data
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let ascii = data
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect::<String>();
+
+    format!("{:>16x}  {:<47}  {}", address.to_umem(), hex, ascii)
+}
+
+fn parse_address(s: &str) -> Result<Address> {
+    let s = s.trim_start_matches("0x");
+    umem::from_str_radix(s, 16).map(Address::from).map_err(|_| {
+        Error(ErrorOrigin::Args, ErrorKind::InvalidArgument)
+            .log_error(format!("invalid address: {}", s))
+    })
+}
+
+fn parse_len(s: &str) -> Result<usize> {
+    let len = if let Some(hex) = s.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+
+    len.map_err(|_| {
+        Error(ErrorOrigin::Args, ErrorKind::InvalidArgument)
+            .log_error(format!("invalid length: {}", s))
+    })
+}
+
+fn parse_bytes(s: &str) -> Result<Vec<u8>> {
+    let err = || {
+        Error(ErrorOrigin::Args, ErrorKind::InvalidArgument)
+            .log_error(format!("invalid hex bytes: {}", s))
+    };
+
+    if s.len() % 2 != 0 {
+        return Err(err());
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(err)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    #[test]
+    fn parse_commands() {
+        assert!(Command::parse("   ").unwrap().is_none());
+        assert!(matches!(
+            "attach explorer.exe".parse::<Command>().unwrap(),
+            Command::Attach(AttachTarget::Name(name)) if name == "explorer.exe"
+        ));
+        assert!(matches!(
+            "wr 0x1000 dead be ef".parse::<Command>().unwrap(),
+            Command::Write { address, data } if address == Address::from(0x1000u64) && data == [0xde, 0xad, 0xbe, 0xef]
+        ));
+        assert!(matches!(
+            "rd 1000 0x20".parse::<Command>().unwrap(),
+            Command::Read { len: 0x20, .. }
+        ));
+        assert!("rd".parse::<Command>().is_err());
+        assert!("wr 1000 abc".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn execute_commands() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let pid = os.alloc_process(size::mb(1), &[]);
+        let base = os.process_info_by_pid(pid).unwrap().address;
+
+        let mut shell = Shell::new(os);
+        let mut out = vec![];
+
+        assert!(shell.run_line("rd 1000", &mut out).is_err());
+
+        shell
+            .run_line(&format!("attach {}", pid), &mut out)
+            .unwrap();
+        shell
+            .run_line(&format!("wr {:x} 41424344", base + 0x10usize), &mut out)
+            .unwrap();
+        shell
+            .run_line(&format!("scan {:x} 0x1000 41 ?? 43", base), &mut out)
+            .unwrap();
+
+        out.clear();
+        shell
+            .run_line(&format!("rd {:x} 32", base), &mut out)
+            .unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("41 42 43 44"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Validators are used when working with caches and determine for how long
+//! a specific cache entry stays valid.
+//!
+//! This validator limits the amount of valid slots instead of their age. Only the most recently
+//! used slots stay valid, validating a slot beyond the limit invalidates the least recently used
+//! one. This suits workloads where the access pattern matters more than how stale the cached
+//! data may become, e.g. when the target is paused.
+//!
+//! The default implementation keeps 256 slots valid.
+
+use std::prelude::v1::*;
+
+use super::CacheValidator;
+use core::cell::Cell;
+
+/// Validator for limiting the amount of valid slots based on least-recent-use ordering
+#[derive(Clone)]
+pub struct LruCacheValidator {
+    /// Time of the last use of every slot, 0 for invalid slots
+    last_used: Vec<Cell<u64>>,
+    clock: Cell<u64>,
+    valid_count: usize,
+    max_valid: usize,
+}
+
+/// Creates a validator keeping 256 slots valid.
+impl Default for LruCacheValidator {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl LruCacheValidator {
+    /// Creates a new LruCacheValidator keeping at most `max_valid` slots valid.
+    ///
+    /// Checking the validity of a slot counts as a use.
+    ///
+    /// # Examples:
+    /// ```
+    /// use memflow::types::cache::{CacheValidator, LruCacheValidator};
+    ///
+    /// let mut validator = LruCacheValidator::new(2);
+    ///
+    /// validator.allocate_slots(3);
+    ///
+    /// validator.validate_slot(0);
+    /// validator.validate_slot(1);
+    /// assert!(validator.is_slot_valid(0));
+    ///
+    /// // slot 1 is the least recently used one at this point
+    /// validator.validate_slot(2);
+    /// assert!(validator.is_slot_valid(0));
+    /// assert!(!validator.is_slot_valid(1));
+    /// assert!(validator.is_slot_valid(2));
+    /// ```
+    pub fn new(max_valid: usize) -> Self {
+        Self {
+            last_used: vec![],
+            clock: Cell::new(0),
+            valid_count: 0,
+            max_valid,
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        let time = self.clock.get() + 1;
+        self.clock.set(time);
+        time
+    }
+
+    fn evict_least_recent(&mut self) {
+        let lru = self
+            .last_used
+            .iter()
+            .enumerate()
+            .filter(|(_, time)| time.get() != 0)
+            .min_by_key(|(_, time)| time.get())
+            .map(|(slot_id, _)| slot_id);
+
+        if let Some(slot_id) = lru {
+            self.invalidate_slot(slot_id);
+        }
+    }
+}
+
+impl CacheValidator for LruCacheValidator {
+    #[inline]
+    fn allocate_slots(&mut self, slot_count: usize) {
+        self.last_used.clear();
+        self.last_used.resize(slot_count, Cell::new(0));
+        self.valid_count = 0;
+    }
+
+    #[inline]
+    fn update_validity(&mut self) {}
+
+    #[inline]
+    fn is_slot_valid(&self, slot_id: usize) -> bool {
+        let slot = &self.last_used[slot_id];
+        if slot.get() != 0 {
+            slot.set(self.tick());
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    fn validate_slot(&mut self, slot_id: usize) {
+        if self.last_used[slot_id].get() == 0 {
+            if self.valid_count >= self.max_valid {
+                self.evict_least_recent();
+            }
+            self.valid_count += 1;
+        }
+        let time = self.tick();
+        self.last_used[slot_id].set(time);
+    }
+
+    #[inline]
+    fn invalidate_slot(&mut self, slot_id: usize) {
+        if self.last_used[slot_id].get() != 0 {
+            self.last_used[slot_id].set(0);
+            self.valid_count -= 1;
+        }
+    }
+}
@@ -2,6 +2,7 @@
 pub mod timed_validator;
 
 pub mod count_validator;
+pub mod lru_validator;
 
 #[cfg(feature = "std")]
 #[doc(hidden)]
@@ -10,6 +11,9 @@ pub use timed_validator::*;
 #[doc(hidden)]
 pub use count_validator::*;
 
+#[doc(hidden)]
+pub use lru_validator::*;
+
 #[cfg(feature = "std")]
 pub type DefaultCacheValidator = TimedCacheValidator;
 #[cfg(not(feature = "std"))]
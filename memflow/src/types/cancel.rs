@@ -0,0 +1,267 @@
+//! Cooperative cancellation of long running operations.
+//!
+//! Scatter reads, scans, dumps and process enumeration over slow connectors can easily take
+//! minutes. A [`CancellationToken`] is handed to such an operation and checked between the
+//! individual steps, so that another thread (e.g. the ui thread of a gui) can abort it by calling
+//! [`CancellationToken::cancel`]. With the `std` feature a token can also carry a deadline after
+//! which it counts as cancelled.
+//!
+//! The operations accepting a token stop at the next step after cancellation and return
+//! [`ErrorKind::Cancelled`], unless they have their own way of reporting interruptions, like
+//! [`dump_physical`](crate::mem::acquire::dump_physical).
+//!
+//! Any iterator of scatter operations can be made cancellable with
+//! [`CancellationToken::guard_iter`], any callback with [`CancellationToken::guard`]:
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::types::CancellationToken;
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let base = proc.info().address;
+//!
+//! let token = CancellationToken::new();
+//! token.cancel();
+//!
+//! let mut bufs = vec![[0u8; 8]; 16];
+//! let reads = bufs
+//!     .iter_mut()
+//!     .enumerate()
+//!     .map(|(i, buf)| CTup2(base + i * 8, buf.as_mut().into()));
+//!
+//! // no reads are issued once the token is cancelled
+//! proc.read_iter(token.guard_iter(reads), None, None).unwrap();
+//! assert!(token.check().is_err());
+//! ```
+//!
+//! Large reads and writes can be split into chunks with an [`OpContext`], which combines a token
+//! with a progress callback, see
+//! [`MemoryView::read_raw_into_ctx`](crate::mem::MemoryView::read_raw_into_ctx). Scatter batches
+//! check the token of a context before every element with
+//! [`MemoryView::read_raw_list_ctx`](crate::mem::MemoryView::read_raw_list_ctx) and
+//! [`MemoryView::write_raw_list_ctx`](crate::mem::MemoryView::write_raw_list_ctx):
+//!
+//! ```
+//! use memflow::prelude::v1::*;
+//! use memflow::types::OpContext;
+//! # use memflow::dummy::DummyOs;
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let base = proc.info().address;
+//!
+//! let mut done = 0;
+//! let mut ctx = OpContext::new()
+//!     .chunk_size(size::kb(4))
+//!     .progress(|processed, _total| done = processed);
+//!
+//! let mut buf = vec![0u8; size::kb(64)];
+//! proc.read_raw_into_ctx(base, &mut buf, &mut ctx).unwrap();
+//! drop(ctx);
+//! assert_eq!(done, size::kb(64));
+//! ```
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::size;
+
+use std::prelude::v1::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Shared cancellation flag with an optional deadline.
+///
+/// Clones of a token share the flag, cancelling one of them cancels all.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    #[cfg(feature = "std")]
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is only cancelled through [`cancel`](Self::cancel).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token that is cancelled once `deadline` has passed.
+    #[cfg(feature = "std")]
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            cancelled: Default::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Creates a token that is cancelled after `timeout`.
+    #[cfg(feature = "std")]
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Returns the deadline of the token, if any.
+    #[cfg(feature = "std")]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Cancels the token and all of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the token was cancelled or its deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        #[cfg(feature = "std")]
+        {
+            if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+                return true;
+            }
+        }
+
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns an [`ErrorKind::Cancelled`] error if the token was cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error(ErrorOrigin::Other, ErrorKind::Cancelled))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wraps an iterator so that it ends as soon as the token is cancelled.
+    pub fn guard_iter<I: Iterator>(&self, iter: I) -> Cancellable<'_, I> {
+        Cancellable { iter, token: self }
+    }
+
+    /// Wraps a callback so that it stops the enumeration as soon as the token is cancelled.
+    pub fn guard<'a, T>(
+        &'a self,
+        mut callback: impl FnMut(T) -> bool + 'a,
+    ) -> impl FnMut(T) -> bool + 'a {
+        move |item| !self.is_cancelled() && callback(item)
+    }
+}
+
+/// Iterator returned by [`CancellationToken::guard_iter`].
+pub struct Cancellable<'a, I> {
+    iter: I,
+    token: &'a CancellationToken,
+}
+
+impl<'a, I: Iterator> Iterator for Cancellable<'a, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.token.is_cancelled() {
+            None
+        } else {
+            self.iter.next()
+        }
+    }
+}
+
+/// Default number of bytes processed between two checks of an [`OpContext`].
+pub const DEFAULT_OP_CHUNK_SIZE: usize = size::mb(1);
+
+/// Progress reporting and cancellation of a large read or write.
+///
+/// Operations taking a context split their work into chunks of
+/// [`chunk_size`](Self::chunk_size) bytes. Before every chunk the cancellation token is checked,
+/// after every chunk the progress callback is invoked with the number of bytes processed so far
+/// and the total number of bytes.
+pub struct OpContext<'a> {
+    token: Option<CancellationToken>,
+    progress: Option<Box<dyn FnMut(usize, usize) + 'a>>,
+    pub(crate) chunk_size: usize,
+}
+
+impl<'a> OpContext<'a> {
+    /// Creates a context without token or progress callback.
+    pub fn new() -> Self {
+        Self {
+            token: None,
+            progress: None,
+            chunk_size: DEFAULT_OP_CHUNK_SIZE,
+        }
+    }
+
+    /// Aborts the operation once `token` is cancelled.
+    pub fn cancel(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Sets the callback receiving the number of processed and total bytes.
+    pub fn progress(mut self, callback: impl FnMut(usize, usize) + 'a) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the number of bytes processed between two checks.
+    ///
+    /// Smaller chunks react faster to cancellation at the cost of more individual requests.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = std::cmp::max(chunk_size, 1);
+        self
+    }
+
+    /// Returns an [`ErrorKind::Cancelled`] error if the token of the context was cancelled.
+    pub fn check(&self) -> Result<()> {
+        self.token.as_ref().map_or(Ok(()), CancellationToken::check)
+    }
+
+    /// Reports that `done` out of `total` bytes have been processed.
+    pub fn report(&mut self, done: usize, total: usize) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress(done, total);
+        }
+    }
+}
+
+impl Default for OpContext<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(token.check().is_ok());
+
+        let mut iter = token.guard_iter(0..10);
+        assert_eq!(iter.next(), Some(0));
+
+        clone.cancel();
+        assert_eq!(iter.next(), None);
+        assert_eq!(token.check().unwrap_err().1, ErrorKind::Cancelled);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn deadline() {
+        let token = CancellationToken::with_timeout(Duration::from_secs(0));
+        assert!(token.is_cancelled());
+
+        let token = CancellationToken::with_timeout(Duration::from_secs(3600));
+        let mut seen = vec![];
+        let mut callback = token.guard(|i: u32| {
+            seen.push(i);
+            true
+        });
+        assert!(callback(1));
+        token.cancel();
+        assert!(!callback(2));
+        assert_eq!(seen, vec![1]);
+    }
+}
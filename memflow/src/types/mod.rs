@@ -27,4 +27,7 @@ pub use byte_swap::ByteSwap;
 pub mod cache;
 pub use cache::{CacheValidator, DefaultCacheValidator};
 
+pub mod cancel;
+pub use cancel::{CancellationToken, OpContext};
+
 pub mod util;
@@ -4,7 +4,7 @@ Pointer abstraction.
 
 use crate::cglue::ReprCString;
 use crate::dataview::Pod;
-use crate::error::{PartialError, PartialResult};
+use crate::error::{PartialError, PartialResult, PartialResultExt};
 use crate::mem::MemoryView;
 use crate::types::{imem, umem, Address, ByteSwap, PrimitiveAddress};
 
@@ -98,6 +98,9 @@ unsafe impl<U: Pod, T: ?Sized + 'static> Pod for Pointer<U, T> {}
 impl<U: PrimitiveAddress, T: ?Sized> Pointer<U, T> {
     const PHANTOM_DATA: PhantomData<fn() -> T> = PhantomData;
 
+    /// Width of the pointer in the target memory in bytes.
+    pub const WIDTH: usize = size_of::<U>();
+
     /// Returns a pointer64 with a value of zero.
     ///
     /// # Examples
@@ -170,6 +173,78 @@ impl<U: PrimitiveAddress, T: ?Sized> Pointer<U, T> {
     pub fn address(&self) -> Address {
         Address::from(self.inner)
     }
+
+    /// Returns `true` if the pointer has the width of pointers of the architecture of `mem`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::dummy::DummyOs;
+    /// use memflow::types::{size, Pointer32, Pointer64};
+    ///
+    /// let proc = DummyOs::quick_process(size::mb(2), &[]);
+    ///
+    /// assert!(Pointer64::<()>::matches_arch(&proc));
+    /// assert!(!Pointer32::<()>::matches_arch(&proc));
+    /// ```
+    #[inline]
+    pub fn matches_arch<M: MemoryView>(mem: &M) -> bool {
+        mem.metadata().arch_bits as usize == Self::WIDTH * 8
+    }
+
+    /// Changes the type of the pointee, keeping the address.
+    #[inline]
+    pub fn cast<V: ?Sized>(self) -> Pointer<U, V> {
+        Pointer {
+            inner: self.inner,
+            phantom_data: Pointer::<U, V>::PHANTOM_DATA,
+        }
+    }
+}
+
+impl<U: PrimitiveAddress + Pod, T: ?Sized> Pointer<U, T> {
+    /// Follows a chain of pointers with the width of this pointer.
+    ///
+    /// Starting at the address of this pointer, for every offset a pointer is read at the
+    /// current address and the offset is added to it: `[[self] + offsets[0]] + offsets[1]`...
+    /// The final address is not dereferenced and is returned as a pointer to `V`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::dummy::DummyOs;
+    /// use memflow::mem::MemoryView;
+    /// use memflow::os::Process;
+    /// use memflow::types::{size, Pointer64};
+    ///
+    /// let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// let base = proc.info().address.to_umem();
+    ///
+    /// proc.write((base + 0x10).into(), &(base + 0x100)).unwrap();
+    /// proc.write((base + 0x108).into(), &(base + 0x200)).unwrap();
+    /// proc.write((base + 0x220).into(), &1234u32).unwrap();
+    ///
+    /// let value = Pointer64::<()>::from(base + 0x10)
+    ///     .read_chain::<u32, _>(&mut proc, &[0x8, 0x20])
+    ///     .unwrap();
+    /// assert_eq!(value.read(&mut proc).unwrap(), 1234);
+    /// ```
+    pub fn read_chain<V: ?Sized, M: MemoryView>(
+        self,
+        mem: &mut M,
+        offsets: &[imem],
+    ) -> PartialResult<Pointer<U, V>> {
+        let mut inner = self.inner;
+        for &offset in offsets {
+            let ptr: U = mem.read(Address::from(inner)).data()?;
+            inner = ptr.wrapping_add(U::from_imem(offset));
+        }
+
+        Ok(Pointer {
+            inner,
+            phantom_data: Pointer::<U, V>::PHANTOM_DATA,
+        })
+    }
 }
 
 impl<U: PrimitiveAddress, T: Sized> Pointer<U, T> {
@@ -536,4 +611,30 @@ mod tests {
         assert_eq!(ptr2.offset_from(ptr1), 4);
         assert_eq!(ptr1.offset_from(ptr2), -4);
     }
+
+    #[test]
+    fn read_chain32() {
+        use crate::architecture::ArchitectureIdent;
+        use crate::dummy::{DummyMemory, DummyOs};
+        use crate::os::{Os, Process};
+        use crate::types::size;
+
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let pid = os
+            .alloc_process_with_arch(size::mb(2), &[], ArchitectureIdent::X86(32, false))
+            .unwrap();
+        let mut proc = os.process_by_pid(pid).unwrap();
+        assert!(Pointer32::<()>::matches_arch(&proc));
+        assert!(!Pointer64::<()>::matches_arch(&proc));
+
+        let base = proc.info().address.to_umem() as u32;
+        proc.write((base + 0x10).into(), &(base + 0x400)).unwrap();
+        proc.write((base + 0x3f0).into(), &(base + 0x800)).unwrap();
+
+        // offsets may point below the address read
+        let found = Pointer32::<()>::from(base + 0x10)
+            .read_chain::<u32, _>(&mut proc, &[-0x10, 0x4])
+            .unwrap();
+        assert_eq!(found, Pointer32::from(base + 0x804));
+    }
 }
@@ -0,0 +1,344 @@
+//! Minimal json support for the text based protocols and exports of the crate.
+//!
+//! Values are parsed into a [`Json`] tree, numbers are kept as text. The parser only accepts
+//! strict json and limits the nesting depth of its input.
+
+use std::prelude::v1::*;
+
+/// Maximum nesting depth of arrays and objects.
+const MAX_DEPTH: usize = 128;
+
+/// Escapes `s` for use within a json string.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A parsed json value.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a single json value, surrounding whitespace is ignored.
+    pub fn parse(input: &str) -> Option<Self> {
+        let mut parser = Parser {
+            data: input.as_bytes(),
+            pos: 0,
+        };
+
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos == parser.data.len() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn take(&mut self, key: &str) -> Option<Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .position(|(k, _)| k == key)
+                .map(|i| fields.swap_remove(i).1),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(
+            self.peek(),
+            Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r')
+        ) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        self.skip_whitespace();
+
+        match self.peek()? {
+            b'{' | b'[' if depth >= MAX_DEPTH => None,
+            b'{' => {
+                self.pos += 1;
+                let mut fields = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Some(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.bump()? != b':' {
+                        return None;
+                    }
+                    fields.push((key, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.bump()? {
+                        b',' => continue,
+                        b'}' => return Some(Json::Object(fields)),
+                        _ => return None,
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut values = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Some(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.bump()? {
+                        b',' => continue,
+                        b']' => return Some(Json::Array(values)),
+                        _ => return None,
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::String),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'n' => self.literal("null", Json::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Option<Json> {
+        if self.data[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Skips a run of digits, returns `false` if there was none.
+    fn digits(&mut self) -> bool {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek()? {
+            b'0' => self.pos += 1,
+            b'1'..=b'9' => {
+                self.digits();
+            }
+            _ => return None,
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !self.digits() {
+                return None;
+            }
+        }
+        if matches!(self.peek(), Some(b'e') | Some(b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+') | Some(b'-')) {
+                self.pos += 1;
+            }
+            if !self.digits() {
+                return None;
+            }
+        }
+
+        let number = std::str::from_utf8(&self.data[start..self.pos]).ok()?;
+        Some(Json::Number(number.to_string()))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.bump()? != b'"' {
+            return None;
+        }
+
+        let mut out = vec![];
+        loop {
+            match self.bump()? {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let c = match self.bump()? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\x08',
+                        b'f' => '\x0c',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                // control characters have to be escaped
+                c if c < 0x20 => return None,
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Parses the digits of a `\u` escape, including the second half of a surrogate pair.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            // lone low surrogates are rejected by `from_u32`
+            return char::from_u32(high);
+        }
+
+        if self.bump()? != b'\\' || self.bump()? != b'u' {
+            return None;
+        }
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.data.get(self.pos..self.pos + 4)?;
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        self.pos += 4;
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_json() {
+        let json =
+            Json::parse(r#" {"a": [1, -2.5e3, true, null], "b": "x\"ä😀\n", "c": "ä😀\/"} "#)
+                .unwrap();
+
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number("1".into()),
+                Json::Number("-2.5e3".into()),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(
+            json.get("b").and_then(Json::as_str),
+            Some("x\"\u{e4}\u{1f600}\n")
+        );
+        assert_eq!(
+            json.get("c").and_then(Json::as_str),
+            Some("\u{e4}\u{1f600}/")
+        );
+
+        assert_eq!(Json::parse(r#"{"a": 1"#), None);
+        assert_eq!(Json::parse(r#"{"a": 1} x"#), None);
+    }
+
+    #[test]
+    fn reject_invalid() {
+        for input in [
+            r#""\x""#,
+            r#""\u+123""#,
+            r#""\udc00""#,
+            r#""\ud83dA""#,
+            "\"\u{1}\"",
+            "+1",
+            "01",
+            "1.",
+            "--1",
+        ]
+        .iter()
+        {
+            assert_eq!(Json::parse(input), None, "{}", input);
+        }
+
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_some());
+        assert_eq!(Json::parse(&nested(MAX_DEPTH + 1)), None);
+        assert_eq!(Json::parse(&"[".repeat(100_000)), None);
+    }
+
+    #[test]
+    fn escape_roundtrip() {
+        let s = "a\"b\\c\n\r\t\u{1}\u{7f}ä😀";
+        let escaped = format!("\"{}\"", escape(s));
+        assert!(escaped.bytes().all(|b| b >= 0x20 && b != 0x7f));
+        assert_eq!(Json::parse(&escaped), Some(Json::String(s.into())));
+    }
+}
@@ -0,0 +1,4 @@
+//! Helpers shared between the modules of the crate.
+
+#[cfg(feature = "std")]
+pub(crate) mod json;
@@ -12,6 +12,11 @@ extern crate rlibc;
 
 use log::*;
 
+use memflow::embedded::{RawConnector, SliceMemory};
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::scan::{MemoryScan, Pattern};
+use memflow::types::Address;
+
 use uefi::{Handle, Status};
 
 #[entry]
@@ -22,5 +27,20 @@ fn efi_main(_handle: Handle, mut st: SystemTable<Boot>) -> Status {
 
     let _bt = st.boot_services();
 
-    Status::SUCCESS
+    let mut buf = vec![0u8; 0x10000];
+    buf[0x2000..0x2004].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+    let mut conn = RawConnector::new(SliceMemory::new(&mut buf));
+    let pattern = Pattern::parse("de ad ?? ef").unwrap();
+    let found = conn
+        .phys_view()
+        .find_pattern(Address::null()..Address::from(0x10000), &pattern);
+
+    info!("found pattern at {:?}", found);
+
+    if found.len() == 1 {
+        Status::SUCCESS
+    } else {
+        Status::ABORTED
+    }
 }